@@ -0,0 +1,89 @@
+//! Compare the size and parse time of JSON and MessagePack messages
+//!
+//! Run with `cargo bench --features msgpack --bench codec`.
+
+use mcpr::{
+    schema::{
+        json_rpc::{JSONRPCMessage, JSONRPCPayload, JSONRPCRequest, JSONRPCResponse, RequestId},
+        server::{CallToolResult, ToolResultContent},
+        TextContent,
+    },
+    transport::codec::Codec,
+};
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+const ITERATIONS: u32 = 20_000;
+
+fn tool_call(id: i64) -> JSONRPCMessage {
+    JSONRPCMessage::Request(JSONRPCRequest::new(
+        RequestId::Number(id),
+        "tools/call".to_string(),
+        Some(serde_json::json!({
+            "name": "search",
+            "arguments": { "query": "model context protocol", "limit": 10, "exact": false },
+        })),
+    ))
+}
+
+fn tool_result(id: i64) -> JSONRPCMessage {
+    let result = CallToolResult {
+        content: (0..10)
+            .map(|i| {
+                ToolResultContent::Text(TextContent {
+                    r#type: "text".to_string(),
+                    text: format!("Result {}: a short snippet of matching text", i),
+                    annotations: None,
+                })
+            })
+            .collect(),
+        structured_content: Some(serde_json::json!({
+            "total": 10,
+            "scores": [0.98, 0.91, 0.87, 0.8, 0.77, 0.7, 0.64, 0.6, 0.51, 0.5],
+        })),
+        is_error: Some(false),
+    };
+    JSONRPCMessage::Response(JSONRPCResponse::new(
+        RequestId::Number(id),
+        serde_json::to_value(result).unwrap(),
+    ))
+}
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn compare(name: &str, payload: &JSONRPCPayload) {
+    println!("{}", name);
+    for codec in [Codec::Json, Codec::MessagePack] {
+        let bytes = codec.encode(payload).unwrap();
+        let encode = time(|| {
+            black_box(codec.encode(black_box(payload)).unwrap());
+        });
+        let decode = time(|| {
+            black_box(codec.decode::<JSONRPCPayload>(black_box(&bytes)).unwrap());
+        });
+        println!(
+            "  {:<12} {:>6} bytes  encode {:>9?}  decode {:>9?}",
+            format!("{:?}", codec),
+            bytes.len(),
+            encode,
+            decode
+        );
+    }
+}
+
+fn main() {
+    compare("tools/call request", &JSONRPCPayload::Single(tool_call(1)));
+    compare("tools/call result", &JSONRPCPayload::Single(tool_result(1)));
+    compare(
+        "batch of 10 results",
+        &JSONRPCPayload::Batch((0..10).map(tool_result).collect()),
+    );
+}
@@ -0,0 +1,92 @@
+//! Compare parsing frames with serde_json, with simd-json on a copy of each
+//! frame, and with a `FrameParser` reusing its buffers
+//!
+//! The copy costs simd-json most of its lead on small frames, and types it
+//! cannot decode, such as `RawMessage`, are parsed twice.
+//!
+//! Run with `cargo bench --bench parse --features simd-json`.
+
+use mcpr::{
+    schema::{
+        json_rpc::{JSONRPCMessage, JSONRPCResponse, RawMessage, RequestId},
+        server::{CallToolResult, ToolResultContent},
+        TextContent,
+    },
+    transport::framed::FrameParser,
+};
+use serde::de::DeserializeOwned;
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+const ITERATIONS: u32 = 5_000;
+
+fn tool_result(id: i64, snippets: usize) -> String {
+    let result = CallToolResult {
+        content: (0..snippets)
+            .map(|i| {
+                ToolResultContent::Text(TextContent {
+                    r#type: "text".to_string(),
+                    text: format!("Result {}: a short snippet of matching text", i),
+                    annotations: None,
+                })
+            })
+            .collect(),
+        structured_content: Some(serde_json::json!({
+            "total": snippets,
+            "scores": (0..snippets).map(|i| 1.0 / (i + 1) as f64).collect::<Vec<_>>(),
+        })),
+        is_error: Some(false),
+    };
+    let message = JSONRPCMessage::Response(JSONRPCResponse::new(
+        RequestId::Number(id),
+        serde_json::to_value(result).unwrap(),
+    ));
+    serde_json::to_string(&message).unwrap()
+}
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+/// Parse the way transports did: simd-json on a new copy, then serde_json
+fn parse_copied<T: DeserializeOwned>(frame: &str) -> T {
+    let mut bytes = frame.as_bytes().to_vec();
+    match simd_json::serde::from_slice(&mut bytes) {
+        Ok(message) => message,
+        Err(_) => serde_json::from_str(frame).unwrap(),
+    }
+}
+
+fn compare<T: DeserializeOwned>(name: &str, frame: &str) {
+    let serde = time(|| {
+        black_box(serde_json::from_str::<T>(black_box(frame)).unwrap());
+    });
+    let copied = time(|| {
+        black_box(parse_copied::<T>(black_box(frame)));
+    });
+    let mut parser = FrameParser::new();
+    let reused = time(|| {
+        black_box(parser.parse::<T>(black_box(frame)).unwrap());
+    });
+    println!(
+        "  {:<12} serde_json {:>9?}  simd copied {:>9?}  frame parser {:>9?}",
+        name, serde, copied, reused
+    );
+}
+
+fn main() {
+    for snippets in [1, 10, 100, 1000] {
+        let frame = tool_result(1, snippets);
+        println!("tools/call result of {} bytes", frame.len());
+        compare::<serde_json::Value>("value", &frame);
+        compare::<JSONRPCResponse>("response", &frame);
+        compare::<JSONRPCMessage>("message", &frame);
+        compare::<RawMessage>("raw message", &frame);
+    }
+}
@@ -0,0 +1,102 @@
+//! Compare writing and relaying messages through strings and values with
+//! serializing into reused buffers and passing raw JSON through
+//!
+//! Run with `cargo bench --bench pipeline`.
+
+use bytes::BytesMut;
+use mcpr::{
+    schema::{
+        json_rpc::{JSONRPCMessage, JSONRPCPayload, JSONRPCResponse, RawPayload, RequestId},
+        server::{CallToolResult, ToolResultContent},
+        TextContent,
+    },
+    transport::framed::{ContentLength, Framer, NewlineDelimited},
+};
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+const ITERATIONS: u32 = 20_000;
+
+fn tool_result(id: i64, snippets: usize) -> JSONRPCPayload {
+    let result = CallToolResult {
+        content: (0..snippets)
+            .map(|i| {
+                ToolResultContent::Text(TextContent {
+                    r#type: "text".to_string(),
+                    text: format!("Result {}: a short snippet of matching text", i),
+                    annotations: None,
+                })
+            })
+            .collect(),
+        structured_content: Some(serde_json::json!({
+            "total": snippets,
+            "scores": (0..snippets).map(|i| 1.0 / (i + 1) as f64).collect::<Vec<_>>(),
+        })),
+        is_error: Some(false),
+    };
+    JSONRPCPayload::Single(JSONRPCMessage::Response(JSONRPCResponse::new(
+        RequestId::Number(id),
+        serde_json::to_value(result).unwrap(),
+    )))
+}
+
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+/// Frame a message the way transports did: into a string, then a new frame
+fn encode_copied<F: Framer>(framer: &F, payload: &JSONRPCPayload) -> Vec<u8> {
+    let json = serde_json::to_string(payload).unwrap();
+    let mut frame = Vec::with_capacity(json.len() + 32);
+    framer.encode(&json, &mut frame);
+    frame
+}
+
+fn compare_encode<F: Framer>(framer: F, name: &str, payload: &JSONRPCPayload) {
+    let copied = time(|| {
+        black_box(encode_copied(&framer, black_box(payload)));
+    });
+    let mut buffer = BytesMut::new();
+    let reused = time(|| {
+        buffer.clear();
+        black_box(framer.encode_json(black_box(payload), &mut buffer).unwrap());
+    });
+    println!(
+        "  encode {:<18} copied {:>9?}  reused buffer {:>9?}",
+        name, copied, reused
+    );
+}
+
+fn compare_relay(payload: &JSONRPCPayload) {
+    let json = serde_json::to_string(payload).unwrap();
+    let parsed = time(|| {
+        let payload: JSONRPCPayload = serde_json::from_str(black_box(&json)).unwrap();
+        black_box(serde_json::to_string(&payload).unwrap());
+    });
+    let raw = time(|| {
+        let payload: RawPayload = serde_json::from_str(black_box(&json)).unwrap();
+        black_box(serde_json::to_string(&payload).unwrap());
+    });
+    println!(
+        "  relay  {:<18} parsed {:>9?}  raw           {:>9?}",
+        format!("{} bytes", json.len()),
+        parsed,
+        raw
+    );
+}
+
+fn main() {
+    for snippets in [1, 10, 100] {
+        println!("tools/call result with {} snippets", snippets);
+        let payload = tool_result(1, snippets);
+        compare_encode(NewlineDelimited, "newline-delimited", &payload);
+        compare_encode(ContentLength, "content-length", &payload);
+        compare_relay(&payload);
+    }
+}
@@ -23,18 +23,10 @@ async fn main() -> Result<(), MCPError> {
     let init_result = client.initialize().await?;
 
     // Print server info
-    if let Some(server_info) = init_result.get("serverInfo") {
-        if let (Some(name), Some(version), Some(protocol_version)) = (
-            server_info.get("name").and_then(|v| v.as_str()),
-            server_info.get("version").and_then(|v| v.as_str()),
-            init_result.get("protocolVersion").and_then(|v| v.as_str()),
-        ) {
-            info!(
-                "Connected to server: {} v{} (protocol {})",
-                name, version, protocol_version
-            );
-        }
-    }
+    info!(
+        "Connected to server: {} v{} (protocol {})",
+        init_result.server_info.name, init_result.server_info.version, init_result.protocol_version
+    );
 
     // Make concurrent requests
     let num_requests = 10;
@@ -36,7 +36,10 @@ async fn main() -> Result<(), MCPError> {
                 .collect::<HashMap<_, _>>(),
             ),
             required: Some(vec!["message".to_string()]),
+            keywords: serde_json::Map::new(),
         },
+        output_schema: None,
+        annotations: None,
     };
 
     // Create a hello tool
@@ -57,7 +60,10 @@ async fn main() -> Result<(), MCPError> {
                 .collect::<HashMap<_, _>>(),
             ),
             required: Some(vec!["name".to_string()]),
+            keywords: serde_json::Map::new(),
         },
+        output_schema: None,
+        annotations: None,
     };
 
     // Configure the server
@@ -234,7 +234,10 @@ impl GitHubTool for ReadmeQueryTool {
                 r#type: "object".to_string(),
                 properties: Some(properties),
                 required: Some(required),
+                keywords: serde_json::Map::new(),
             },
+            output_schema: None,
+            annotations: None,
         }
     }
 
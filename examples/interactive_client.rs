@@ -1,12 +1,15 @@
 use log::{error, info};
-use mcpr::{client::Client, error::MCPError, transport::stdio::StdioTransport};
+use mcpr::{
+    client::Client,
+    error::MCPError,
+    schema::server::{CallToolResult, RenderOptions},
+    transport::stdio::StdioTransport,
+};
 use serde_json::Value;
-use std::{io::Write, process::Stdio};
+use std::io::Write;
 use tokio::{
     io::{AsyncBufReadExt, BufReader},
-    process::Command,
     sync::mpsc,
-    time::sleep,
 };
 
 #[tokio::main]
@@ -33,26 +36,8 @@ async fn main() -> Result<(), MCPError> {
             eprintln!("Error reading from stdin: {}", e);
         }
     }
-    // Start the server process
-    let mut server_process = Command::new(server_cmd)
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .map_err(|e| MCPError::Transport(format!("Failed to start server: {}", e)))?;
-
-    sleep(tokio::time::Duration::from_millis(500)).await;
-
-    let server_stdin = server_process.stdin.take().ok_or(MCPError::Transport(
-        "Failed to connect to new process stdin".to_string(),
-    ))?;
-    let server_stdout = server_process.stdout.take().ok_or(MCPError::Transport(
-        "Failed to connect to new process stdout".to_string(),
-    ))?;
-
-    // Create a transport
-    let transport =
-        StdioTransport::with_reader_and_writer(Box::new(server_stdout), Box::new(server_stdin));
+    // Start the server process; the transport owns it and stops it on shutdown
+    let transport = StdioTransport::spawn(&server_cmd, std::iter::empty::<&str>())?;
 
     // Create a client
     let mut client = Client::new(transport);
@@ -61,21 +46,13 @@ async fn main() -> Result<(), MCPError> {
     info!("Initializing client...");
     let init_result = client.initialize().await?;
 
-    info!("Connection established {}", init_result);
+    info!("Connection established {:?}", init_result);
 
     // Get server information
-    if let Some(server_info) = init_result.get("serverInfo") {
-        if let (Some(name), Some(version), Some(protocol_version)) = (
-            server_info.get("name").and_then(|v| v.as_str()),
-            server_info.get("version").and_then(|v| v.as_str()),
-            init_result.get("protocolVersion").and_then(|v| v.as_str()),
-        ) {
-            info!(
-                "Connected to server: {} v{} (protocol {})",
-                name, version, protocol_version
-            );
-        }
-    }
+    info!(
+        "Connected to server: {} v{} (protocol {})",
+        init_result.server_info.name, init_result.server_info.version, init_result.protocol_version
+    );
 
     // Retrieve available tools
     let tools_result = client.list_tools::<Value>().await?;
@@ -121,7 +98,7 @@ async fn main() -> Result<(), MCPError> {
             match reader.read_line(&mut buffer).await {
                 Ok(0) => break, // EOF
                 Ok(_) => {
-                    if let Err(_) = input_tx.send(buffer.trim().to_string()).await {
+                    if input_tx.send(buffer.trim().to_string()).await.is_err() {
                         break;
                     } else {
                         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
@@ -169,9 +146,15 @@ async fn main() -> Result<(), MCPError> {
             "Calling tool: {} with parameters: {}",
             tool_name, params_str
         );
-        match client.call_tool::<_, Value>(tool_name, &params).await {
+        match client
+            .call_tool::<_, CallToolResult>(tool_name, &params)
+            .await
+        {
             Ok(result) => {
-                println!("Result: {}", serde_json::to_string_pretty(&result).unwrap());
+                println!(
+                    "{}",
+                    result.render_cli(&RenderOptions::new().with_color(true))
+                );
             }
             Err(e) => {
                 error!("Error calling tool: {}", e);
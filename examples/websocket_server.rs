@@ -37,7 +37,10 @@ async fn main() -> Result<(), MCPError> {
                 .collect::<HashMap<_, _>>(),
             ),
             required: Some(vec!["message".to_string()]),
+            keywords: serde_json::Map::new(),
         },
+        output_schema: None,
+        annotations: None,
     };
 
     // Configure the server
@@ -0,0 +1,405 @@
+//! Procedural macros for defining MCP tools with mcpr
+//!
+//! Use them through mcpr's `macros` feature, which re-exports them:
+//!
+//! - `#[derive(ToolInput)]` derives the JSON Schema of a tool's arguments
+//!   from a struct, with doc comments as property descriptions
+//! - `#[mcp_tool]` turns an async fn into a tool handler with its definition,
+//!   ready for `Server::add_tool`
+//!
+//! ```rust,ignore
+//! use mcpr::{error::MCPError, mcp_tool, ToolInput};
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize, ToolInput)]
+//! struct EchoArgs {
+//!     /// The text to echo back
+//!     text: String,
+//! }
+//!
+//! /// Echo the text back
+//! #[mcp_tool(name = "echo")]
+//! async fn echo(args: EchoArgs) -> Result<String, MCPError> {
+//!     Ok(args.text)
+//! }
+//!
+//! server.add_tool(EchoTool)?;
+//! ```
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, spanned::Spanned, Attribute, Data, DeriveInput, Error, Expr, ExprLit,
+    Fields, FnArg, ItemFn, Lit, LitBool, LitStr, Meta, Token, Type,
+};
+
+/// Derive `SchemaType` and `ToolInput` for a struct with named fields
+///
+/// Each field becomes a property with its doc comment as description.
+/// Fields are required unless their type is an `Option` or they have
+/// `#[serde(default)]`; `#[serde(rename = "...")]`, `#[serde(skip)]` and
+/// `#[serde(rename_all = "camelCase")]` on the struct are honored.
+#[proc_macro_derive(ToolInput)]
+pub fn derive_tool_input(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    tool_input(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Turn an async fn into a tool
+///
+/// The fn takes the tool's arguments, a type implementing `ToolInput` and
+/// `Deserialize`, optionally followed by a `ToolContext`, and returns a
+/// `Result` of something `Serialize` with `MCPError`. A unit struct named
+/// after the fn in PascalCase with a `Tool` suffix is generated, which
+/// implements `ToolHandler` and `ToolDefinition`.
+///
+/// Takes `name`, which defaults to the fn's name, and `description`, which
+/// defaults to the fn's doc comment. The tool annotations are set with
+/// `title = "..."` and the hints `read_only`, `destructive`, `idempotent`
+/// and `open_world`, which are `true` when given alone, or take a bool as in
+/// `destructive = false`.
+#[proc_macro_attribute]
+pub fn mcp_tool(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut name = None;
+    let mut description = None;
+    let mut hints = Hints::default();
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            name = Some(meta.value()?.parse::<LitStr>()?);
+            Ok(())
+        } else if meta.path.is_ident("description") {
+            description = Some(meta.value()?.parse::<LitStr>()?);
+            Ok(())
+        } else if meta.path.is_ident("title") {
+            hints.title = Some(meta.value()?.parse::<LitStr>()?);
+            Ok(())
+        } else if let Some(hint) = hints.flag(&meta.path) {
+            *hint = Some(match meta.input.peek(Token![=]) {
+                true => meta.value()?.parse::<LitBool>()?.value,
+                false => true,
+            });
+            Ok(())
+        } else {
+            Err(meta.error(
+                "expected `name`, `description`, `title`, `read_only`, `destructive`, \
+                 `idempotent` or `open_world`",
+            ))
+        }
+    });
+    parse_macro_input!(attr with parser);
+    let function = parse_macro_input!(item as ItemFn);
+
+    tool(function, name, description, hints)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Tool annotations given to `#[mcp_tool]`
+#[derive(Default)]
+struct Hints {
+    title: Option<LitStr>,
+    read_only: Option<bool>,
+    destructive: Option<bool>,
+    idempotent: Option<bool>,
+    open_world: Option<bool>,
+}
+
+impl Hints {
+    /// The hint a flag sets
+    fn flag(&mut self, path: &syn::Path) -> Option<&mut Option<bool>> {
+        let ident = path.get_ident()?.to_string();
+        match ident.as_str() {
+            "read_only" => Some(&mut self.read_only),
+            "destructive" => Some(&mut self.destructive),
+            "idempotent" => Some(&mut self.idempotent),
+            "open_world" => Some(&mut self.open_world),
+            _ => None,
+        }
+    }
+
+    /// The `Option<ToolAnnotations>` expression of the hints
+    fn annotations(&self) -> TokenStream2 {
+        fn option<T: quote::ToTokens>(value: &Option<T>, map: TokenStream2) -> TokenStream2 {
+            match value {
+                Some(value) => quote!(::std::option::Option::Some(#value #map)),
+                None => quote!(::std::option::Option::None),
+            }
+        }
+        let Hints {
+            title,
+            read_only,
+            destructive,
+            idempotent,
+            open_world,
+        } = self;
+        if title.is_none()
+            && read_only.is_none()
+            && destructive.is_none()
+            && idempotent.is_none()
+            && open_world.is_none()
+        {
+            return quote!(::std::option::Option::None);
+        }
+        let title = option(title, quote!(.to_string()));
+        let read_only = option(read_only, quote!());
+        let destructive = option(destructive, quote!());
+        let idempotent = option(idempotent, quote!());
+        let open_world = option(open_world, quote!());
+        quote! {
+            ::std::option::Option::Some(::mcpr::schema::common::ToolAnnotations {
+                title: #title,
+                read_only_hint: #read_only,
+                destructive_hint: #destructive,
+                idempotent_hint: #idempotent,
+                open_world_hint: #open_world,
+            })
+        }
+    }
+}
+
+fn tool(
+    function: ItemFn,
+    name: Option<LitStr>,
+    description: Option<LitStr>,
+    hints: Hints,
+) -> syn::Result<TokenStream2> {
+    let signature = &function.sig;
+    if signature.asyncness.is_none() {
+        return Err(Error::new(
+            signature.span(),
+            "#[mcp_tool] needs an async fn",
+        ));
+    }
+
+    let inputs: Vec<&Type> = signature
+        .inputs
+        .iter()
+        .map(|input| match input {
+            FnArg::Typed(input) => Ok(&*input.ty),
+            FnArg::Receiver(receiver) => Err(Error::new(
+                receiver.span(),
+                "#[mcp_tool] does not support methods",
+            )),
+        })
+        .collect::<syn::Result<_>>()?;
+    let (arguments, call) = match inputs.as_slice() {
+        [arguments] => (arguments, quote!((arguments))),
+        [arguments, _context] => (arguments, quote!((arguments, context))),
+        _ => {
+            return Err(Error::new(
+                signature.inputs.span(),
+                "#[mcp_tool] fn takes the tool arguments and optionally a ToolContext",
+            ))
+        }
+    };
+
+    let ident = &signature.ident;
+    let vis = &function.vis;
+    let name = name.unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+    let description = match description
+        .map(|d| d.value())
+        .or_else(|| doc(&function.attrs))
+    {
+        Some(description) => quote!(::std::option::Option::Some(#description.to_string())),
+        None => quote!(::std::option::Option::None),
+    };
+    let annotations = hints.annotations();
+    let handler = format_ident!("{}Tool", pascal_case(&ident.to_string()));
+    let handler_doc = format!("The `{}` tool, handled by [`{}`]", name.value(), ident);
+
+    Ok(quote! {
+        #function
+
+        #[doc = #handler_doc]
+        #[derive(Debug, Clone, Copy, Default)]
+        #vis struct #handler;
+
+        impl ::mcpr::server::ToolDefinition for #handler {
+            fn definition() -> ::mcpr::Tool {
+                ::mcpr::Tool {
+                    name: #name.to_string(),
+                    description: #description,
+                    input_schema: <#arguments as ::mcpr::schema::input::ToolInput>::input_schema(),
+                    output_schema: None,
+                    annotations: #annotations,
+                }
+            }
+        }
+
+        #[::mcpr::__private::async_trait]
+        impl ::mcpr::server::ToolHandler for #handler {
+            async fn call(
+                &self,
+                arguments: ::mcpr::__private::serde_json::Value,
+                context: ::mcpr::server::ToolContext,
+            ) -> ::std::result::Result<::mcpr::__private::serde_json::Value, ::mcpr::error::MCPError> {
+                let _ = &context;
+                let arguments: #arguments = ::mcpr::__private::serde_json::from_value(arguments)?;
+                let output = #ident #call.await?;
+                ::std::result::Result::Ok(::mcpr::__private::serde_json::to_value(output)?)
+            }
+        }
+    })
+}
+
+fn tool_input(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new(
+            input.span(),
+            "ToolInput can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(Error::new(
+            input.span(),
+            "ToolInput needs a struct with named fields",
+        ));
+    };
+    let camel_case = serde_value(&input.attrs, "rename_all")?.as_deref() == Some("camelCase");
+
+    let mut properties = Vec::new();
+    for field in &fields.named {
+        if has_serde_flag(&field.attrs, "skip")? {
+            continue;
+        }
+        let ident = field.ident.as_ref().expect("named field");
+        let ident = ident.to_string();
+        let ident = ident.strip_prefix("r#").unwrap_or(&ident);
+        let property = match serde_value(&field.attrs, "rename")? {
+            Some(rename) => rename,
+            None if camel_case => camel_case_of(ident),
+            None => ident.to_string(),
+        };
+        let ty = &field.ty;
+        let describe = doc(&field.attrs).map(|description| {
+            quote! {
+                if let ::std::option::Option::Some(schema) = schema.as_object_mut() {
+                    schema.insert("description".to_string(), #description.into());
+                }
+            }
+        });
+        let defaulted = has_serde_flag(&field.attrs, "default")?;
+
+        properties.push(quote! {
+            #[allow(unused_mut)]
+            let mut schema = <#ty as ::mcpr::schema::input::SchemaType>::json_schema();
+            #describe
+            properties.insert(#property.to_string(), schema);
+            if !#defaulted && !<#ty as ::mcpr::schema::input::SchemaType>::optional() {
+                required.push(#property.to_string());
+            }
+        });
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::mcpr::schema::input::ToolInput for #ident #ty_generics #where_clause {
+            fn input_schema() -> ::mcpr::schema::common::ToolInputSchema {
+                let mut properties = ::std::collections::HashMap::new();
+                let mut required = ::std::vec::Vec::new();
+                #(#properties)*
+                ::mcpr::schema::common::ToolInputSchema {
+                    r#type: "object".to_string(),
+                    properties: ::std::option::Option::Some(properties),
+                    required: ::std::option::Option::Some(required),
+                    keywords: ::serde_json::Map::new(),
+                }
+            }
+        }
+
+        impl #impl_generics ::mcpr::schema::input::SchemaType for #ident #ty_generics #where_clause {
+            fn json_schema() -> ::mcpr::__private::serde_json::Value {
+                ::mcpr::__private::serde_json::to_value(
+                    <Self as ::mcpr::schema::input::ToolInput>::input_schema(),
+                )
+                .expect("a tool input schema is valid JSON")
+            }
+        }
+    })
+}
+
+/// The doc comment of an item, with lines joined by spaces
+fn doc(attrs: &[Attribute]) -> Option<String> {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(meta) => match &meta.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(line),
+                    ..
+                }) => Some(line.value().trim().to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .filter(|line| !line.is_empty())
+        .collect();
+    (!lines.is_empty()).then(|| lines.join(" "))
+}
+
+/// The value of `#[serde(key = "value")]`, if present
+fn serde_value(attrs: &[Attribute], key: &str) -> syn::Result<Option<String>> {
+    let mut found = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident(key) && meta.input.peek(syn::Token![=]) {
+                found = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                skip_meta(meta)
+            }
+        })?;
+    }
+    Ok(found)
+}
+
+/// Whether `#[serde(flag)]` or `#[serde(flag = "...")]` is present
+fn has_serde_flag(attrs: &[Attribute], flag: &str) -> syn::Result<bool> {
+    let mut found = false;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident(flag) {
+                found = true;
+            }
+            skip_meta(meta)
+        })?;
+    }
+    Ok(found)
+}
+
+/// Consume the value or nested list of a serde attribute entry
+fn skip_meta(meta: syn::meta::ParseNestedMeta) -> syn::Result<()> {
+    if meta.input.peek(syn::Token![=]) {
+        meta.value()?.parse::<Expr>()?;
+    } else if meta.input.peek(syn::token::Paren) {
+        meta.parse_nested_meta(skip_meta)?;
+    }
+    Ok(())
+}
+
+fn pascal_case(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+fn camel_case_of(name: &str) -> String {
+    let pascal = pascal_case(name);
+    let mut chars = pascal.chars();
+    chars
+        .next()
+        .map(|first| first.to_lowercase().chain(chars).collect())
+        .unwrap_or_default()
+}
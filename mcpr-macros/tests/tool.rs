@@ -0,0 +1,66 @@
+use mcpr::{
+    error::MCPError,
+    mcp_tool,
+    schema::input::ToolInput,
+    server::{ToolContext, ToolDefinition, ToolHandler},
+    ToolInput,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Deserialize, ToolInput)]
+#[serde(rename_all = "camelCase")]
+struct SearchArgs {
+    /// What to look for
+    query: String,
+    max_results: Option<u32>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(skip)]
+    #[allow(dead_code)]
+    cache: (),
+}
+
+/// Search the index
+#[mcp_tool(name = "search", read_only, open_world = false)]
+async fn search(args: SearchArgs) -> Result<Vec<String>, MCPError> {
+    let count = args.max_results.unwrap_or(2) as usize;
+    Ok(std::iter::repeat_n(args.query, count)
+        .chain(args.tags)
+        .collect())
+}
+
+#[tokio::test]
+async fn test_mcp_tool() {
+    let schema = SearchArgs::input_schema();
+    assert_eq!(schema.required, Some(vec!["query".to_string()]));
+    let properties = schema.properties.unwrap();
+    assert_eq!(
+        properties["query"],
+        json!({ "type": "string", "description": "What to look for" })
+    );
+    assert_eq!(properties["maxResults"], json!({ "type": "integer" }));
+    assert!(properties.contains_key("tags") && !properties.contains_key("cache"));
+
+    let definition = SearchTool::definition();
+    assert_eq!(definition.name, "search");
+    assert_eq!(definition.description.as_deref(), Some("Search the index"));
+    let annotations = definition.annotations.unwrap();
+    assert!(annotations.is_read_only() && !annotations.is_destructive());
+    assert_eq!(annotations.open_world_hint, Some(false));
+
+    let output = SearchTool
+        .call(
+            json!({ "query": "mcp", "maxResults": 3 }),
+            ToolContext::default(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(output, json!(["mcp", "mcp", "mcp"]));
+
+    // Arguments that do not match the struct are an error
+    assert!(SearchTool
+        .call(json!({ "maxResults": 3 }), ToolContext::default())
+        .await
+        .is_err());
+}
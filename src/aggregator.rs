@@ -0,0 +1,557 @@
+//! One MCP endpoint in front of several servers
+//!
+//! An [`Aggregator`] connects to upstream servers as a client and serves a
+//! downstream client as if they were one server: `tools/list`,
+//! `resources/list` and `prompts/list` merge what the upstreams offer, and
+//! calls, reads and prompt requests are routed to the upstream that offers
+//! the tool, resource or prompt. List changes announced by an upstream are
+//! forwarded downstream.
+//!
+//! Tools and prompts of different upstreams may share a name. With
+//! [`Aggregator::with_prefixes`], names are prefixed with the upstream's name,
+//! as `weather__forecast`; otherwise the first upstream offering a name
+//! wins. Resource URIs are never renamed.
+//!
+//! ```rust,no_run
+//! use mcpr::{
+//!     aggregator::Aggregator,
+//!     client::Client,
+//!     transport::{stdio::StdioTransport, websocket::WebSocketTransport},
+//! };
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), mcpr::error::MCPError> {
+//!     let weather = Client::new(StdioTransport::spawn("weather-server", [] as [&str; 0])?);
+//!     let search = Client::new(WebSocketTransport::new("ws://search.internal:8080"));
+//!     let aggregator = Aggregator::new()
+//!         .with_upstream("weather", weather)
+//!         .with_upstream("search", search)
+//!         .with_prefixes(true);
+//!     aggregator.serve(StdioTransport::new()).await
+//! }
+//! ```
+//!
+//! Upstream clients read what their server sends while they wait for a
+//! response, so an upstream's list change reaches the downstream client the
+//! next time the aggregator talks to that upstream.
+
+use crate::{
+    client::Client,
+    constants::{LATEST_PROTOCOL_VERSION, SUPPORTED_PROTOCOL_VERSIONS},
+    error::MCPError,
+    schema::json_rpc::{
+        error_codes, JSONRPCError, JSONRPCErrorObject, JSONRPCMessage, JSONRPCNotification,
+        JSONRPCPayload, JSONRPCRequest, JSONRPCResponse,
+    },
+    transport::Transport,
+};
+use async_trait::async_trait;
+use futures::future::join_all;
+use log::{debug, error, info, warn};
+use serde_json::{Map, Value};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::mpsc;
+
+/// Separator between an upstream's name and a tool or prompt name
+pub const PREFIX_SEPARATOR: &str = "__";
+
+/// Notifications of upstreams that are forwarded downstream
+const FORWARDED_NOTIFICATIONS: &[&str] = &[
+    "notifications/tools/list_changed",
+    "notifications/resources/list_changed",
+    "notifications/prompts/list_changed",
+    "notifications/message",
+];
+
+/// A client of an upstream server, whatever its transport
+#[async_trait]
+trait Upstream: Send + Sync {
+    /// Initialize the session, returning the server's result
+    async fn connect(&mut self) -> Result<Value, MCPError>;
+    /// Send a request and return its result
+    async fn forward(&self, method: &str, params: Option<Value>) -> Result<Value, MCPError>;
+    /// Shut down the session
+    async fn disconnect(&self) -> Result<(), MCPError>;
+}
+
+#[async_trait]
+impl<T: Transport + Clone + Send + Sync + 'static> Upstream for Client<T> {
+    async fn connect(&mut self) -> Result<Value, MCPError> {
+        self.initialize_raw().await
+    }
+
+    async fn forward(&self, method: &str, params: Option<Value>) -> Result<Value, MCPError> {
+        self.clone().request(method, params).await
+    }
+
+    async fn disconnect(&self) -> Result<(), MCPError> {
+        self.clone().shutdown().await
+    }
+}
+
+/// Where requests for a tool, prompt or resource go
+#[derive(Default)]
+struct Routes {
+    /// Upstream index and upstream name of each tool, by advertised name
+    tools: HashMap<String, (usize, String)>,
+    /// Upstream index and upstream name of each prompt, by advertised name
+    prompts: HashMap<String, (usize, String)>,
+    /// Upstream index of each resource, by URI
+    resources: HashMap<String, usize>,
+}
+
+/// Serves several upstream servers as one, see the [module docs](self)
+pub struct Aggregator {
+    name: String,
+    version: String,
+    upstreams: Vec<(String, Box<dyn Upstream>)>,
+    prefixes: bool,
+    routes: Arc<Mutex<Routes>>,
+    /// Notifications of the upstreams, waiting to be forwarded
+    notifications: (
+        mpsc::UnboundedSender<JSONRPCNotification>,
+        mpsc::UnboundedReceiver<JSONRPCNotification>,
+    ),
+}
+
+impl Aggregator {
+    /// An aggregator without upstreams
+    pub fn new() -> Self {
+        Self {
+            name: "mcpr-aggregator".to_string(),
+            version: crate::VERSION.to_string(),
+            upstreams: Vec::new(),
+            prefixes: false,
+            routes: Arc::new(Mutex::new(Routes::default())),
+            notifications: mpsc::unbounded_channel(),
+        }
+    }
+
+    /// Set the name and version the aggregator gives downstream
+    pub fn with_server_info(mut self, name: &str, version: &str) -> Self {
+        self.name = name.to_string();
+        self.version = version.to_string();
+        self
+    }
+
+    /// Add an upstream server, known by `name`, through a client not yet initialized
+    pub fn with_upstream<T>(mut self, name: &str, mut client: Client<T>) -> Self
+    where
+        T: Transport + Clone + Send + Sync + 'static,
+    {
+        for method in FORWARDED_NOTIFICATIONS {
+            let notifications = self.notifications.0.clone();
+            client.on_notification(method, move |notification| {
+                let _ = notifications.send(notification.clone());
+            });
+        }
+        self.upstreams.push((name.to_string(), Box::new(client)));
+        self
+    }
+
+    /// Prefix tool and prompt names with the name of their upstream
+    pub fn with_prefixes(mut self, enabled: bool) -> Self {
+        self.prefixes = enabled;
+        self
+    }
+
+    /// Initialize the upstreams, then serve a client on `downstream` until it leaves
+    ///
+    /// The upstreams are shut down once the downstream client shuts down or
+    /// disconnects. Requests are handled concurrently.
+    pub async fn serve<D>(mut self, mut downstream: D) -> Result<(), MCPError>
+    where
+        D: Transport + Clone + 'static,
+    {
+        let results = join_all(self.upstreams.iter_mut().map(|(_, u)| u.connect())).await;
+        let mut capabilities = Map::new();
+        for ((name, _), result) in self.upstreams.iter().zip(results) {
+            let result = result.map_err(|e| {
+                MCPError::Protocol(format!("Upstream '{}' failed to initialize: {}", name, e))
+            })?;
+            info!("Upstream '{}' initialized", name);
+            for capability in ["tools", "resources", "prompts", "logging"] {
+                if result
+                    .pointer(&format!("/capabilities/{}", capability))
+                    .is_some()
+                {
+                    let merged = match capability {
+                        "logging" => serde_json::json!({}),
+                        _ => serde_json::json!({ "listChanged": true }),
+                    };
+                    capabilities.insert(capability.to_string(), merged);
+                }
+            }
+        }
+        let aggregator = Arc::new(AggregatorState {
+            server_info: serde_json::json!({ "name": self.name, "version": self.version }),
+            capabilities: Value::Object(capabilities),
+            upstreams: std::mem::take(&mut self.upstreams),
+            prefixes: self.prefixes,
+            routes: self.routes.clone(),
+        });
+
+        downstream.start().await?;
+        let mut sender = downstream.clone();
+        loop {
+            let payload = tokio::select! {
+                payload = downstream.receive::<JSONRPCPayload>() => payload,
+                Some(notification) = self.notifications.1.recv() => {
+                    debug!("Forwarding upstream notification '{}'", notification.method);
+                    sender.send(&JSONRPCMessage::Notification(notification)).await?;
+                    continue;
+                }
+            };
+            let payload = match payload {
+                Ok(payload) => payload,
+                Err(MCPError::Serialization(e)) => {
+                    warn!("Skipping unparseable message: {}", e);
+                    continue;
+                }
+                Err(e) => {
+                    info!("Downstream client disconnected: {}", e);
+                    break;
+                }
+            };
+
+            let mut shutdown = false;
+            for message in payload.into_messages() {
+                let JSONRPCMessage::Request(request) = message else {
+                    continue;
+                };
+                if request.method == "shutdown" {
+                    let response = JSONRPCResponse::new(request.id, serde_json::json!({}));
+                    sender.send(&JSONRPCMessage::Response(response)).await?;
+                    shutdown = true;
+                    continue;
+                }
+                let aggregator = aggregator.clone();
+                let mut sender = sender.clone();
+                tokio::spawn(async move {
+                    let response = aggregator.handle(request).await;
+                    if let Err(e) = sender.send(&response).await {
+                        error!("Error sending response: {}", e);
+                    }
+                });
+            }
+            if shutdown {
+                break;
+            }
+        }
+
+        for (name, upstream) in &aggregator.upstreams {
+            if let Err(e) = upstream.disconnect().await {
+                warn!("Upstream '{}' did not shut down cleanly: {}", name, e);
+            }
+        }
+        downstream.close().await
+    }
+}
+
+impl Default for Aggregator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What request handlers share while serving
+struct AggregatorState {
+    server_info: Value,
+    capabilities: Value,
+    upstreams: Vec<(String, Box<dyn Upstream>)>,
+    prefixes: bool,
+    routes: Arc<Mutex<Routes>>,
+}
+
+impl AggregatorState {
+    /// Answer a request of the downstream client
+    async fn handle(&self, request: JSONRPCRequest) -> JSONRPCMessage {
+        let id = request.id.clone();
+        match self.dispatch(request).await {
+            Ok(result) => JSONRPCMessage::Response(JSONRPCResponse::new(id, result)),
+            Err(MCPError::Rpc {
+                code,
+                message,
+                data,
+            }) => JSONRPCMessage::Error(JSONRPCError::new(
+                id,
+                JSONRPCErrorObject {
+                    code,
+                    message,
+                    data,
+                },
+            )),
+            Err(e) => JSONRPCMessage::Error(JSONRPCError::new_with_details(
+                id,
+                error_codes::INTERNAL_ERROR,
+                e.to_string(),
+                None,
+            )),
+        }
+    }
+
+    async fn dispatch(&self, request: JSONRPCRequest) -> Result<Value, MCPError> {
+        let params = request.params;
+        match request.method.as_str() {
+            "initialize" => {
+                let requested = params
+                    .as_ref()
+                    .and_then(|p| p.get("protocolVersion"))
+                    .and_then(Value::as_str)
+                    .filter(|version| SUPPORTED_PROTOCOL_VERSIONS.contains(version))
+                    .unwrap_or(LATEST_PROTOCOL_VERSION);
+                Ok(serde_json::json!({
+                    "protocolVersion": requested,
+                    "capabilities": self.capabilities,
+                    "serverInfo": self.server_info,
+                }))
+            }
+            "ping" => Ok(serde_json::json!({})),
+            "tools/list" => {
+                let tools = self.list_named("tools/list", "tools").await?;
+                Ok(serde_json::json!({ "tools": tools }))
+            }
+            "prompts/list" => {
+                let prompts = self.list_named("prompts/list", "prompts").await?;
+                Ok(serde_json::json!({ "prompts": prompts }))
+            }
+            "resources/list" => {
+                let mut resources = Vec::new();
+                let mut routes = HashMap::new();
+                for (index, items) in self.list_all("resources/list", "resources").await? {
+                    for resource in items {
+                        if let Some(uri) = resource.get("uri").and_then(Value::as_str) {
+                            routes.entry(uri.to_string()).or_insert(index);
+                        }
+                        resources.push(resource);
+                    }
+                }
+                self.routes.lock().unwrap().resources = routes;
+                Ok(serde_json::json!({ "resources": resources }))
+            }
+            "resources/templates/list" => {
+                let templates = self
+                    .list_all("resources/templates/list", "resourceTemplates")
+                    .await?
+                    .into_iter()
+                    .flat_map(|(_, items)| items)
+                    .collect::<Vec<_>>();
+                Ok(serde_json::json!({ "resourceTemplates": templates }))
+            }
+            "tools/call" => self.route_named(params, "tools/call", true).await,
+            "prompts/get" => self.route_named(params, "prompts/get", false).await,
+            "resources/read" => {
+                let uri = params
+                    .as_ref()
+                    .and_then(|p| p.get("uri"))
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| invalid_params("Missing uri"))?
+                    .to_string();
+                let known = self.routes.lock().unwrap().resources.get(&uri).copied();
+                if let Some(index) = known {
+                    return self.upstreams[index]
+                        .1
+                        .forward("resources/read", params)
+                        .await;
+                }
+                // Resources from templates are not listed, so ask every upstream
+                let mut last_error = None;
+                for (_, upstream) in &self.upstreams {
+                    match upstream.forward("resources/read", params.clone()).await {
+                        Ok(result) => return Ok(result),
+                        Err(e) => last_error = Some(e),
+                    }
+                }
+                Err(last_error.unwrap_or_else(|| invalid_params("Unknown resource")))
+            }
+            method => Err(MCPError::Rpc {
+                code: error_codes::METHOD_NOT_FOUND,
+                message: format!("Method not found: {}", method),
+                data: None,
+            }),
+        }
+    }
+
+    /// Every page of a list from every upstream, by upstream index
+    async fn list_all(
+        &self,
+        method: &str,
+        field: &str,
+    ) -> Result<Vec<(usize, Vec<Value>)>, MCPError> {
+        let lists = self.upstreams.iter().map(|(name, upstream)| async move {
+            let mut items = Vec::new();
+            let mut cursor: Option<Value> = None;
+            loop {
+                let params = cursor.take().map(|c| serde_json::json!({ "cursor": c }));
+                let result = upstream.forward(method, params).await.map_err(|e| {
+                    MCPError::Protocol(format!("Upstream '{}' failed {}: {}", name, method, e))
+                })?;
+                if let Some(Value::Array(page)) = result.get(field) {
+                    items.extend(page.iter().cloned());
+                }
+                match result.get("nextCursor") {
+                    Some(next) if !next.is_null() => cursor = Some(next.clone()),
+                    _ => return Ok::<_, MCPError>(items),
+                }
+            }
+        });
+        join_all(lists)
+            .await
+            .into_iter()
+            .enumerate()
+            .map(|(index, items)| items.map(|items| (index, items)))
+            .collect()
+    }
+
+    /// Merge the tools or prompts of the upstreams, renaming them if prefixed
+    async fn list_named(&self, method: &str, field: &str) -> Result<Vec<Value>, MCPError> {
+        let mut merged = Vec::new();
+        let mut routes = HashMap::new();
+        for (index, items) in self.list_all(method, field).await? {
+            let upstream = &self.upstreams[index].0;
+            for mut item in items {
+                let Some(name) = item.get("name").and_then(Value::as_str).map(str::to_string)
+                else {
+                    continue;
+                };
+                let advertised = if self.prefixes {
+                    format!("{}{}{}", upstream, PREFIX_SEPARATOR, name)
+                } else {
+                    name.clone()
+                };
+                if routes.contains_key(&advertised) {
+                    warn!(
+                        "Hiding '{}' of upstream '{}', another upstream has one by that name",
+                        name, upstream
+                    );
+                    continue;
+                }
+                item["name"] = Value::String(advertised.clone());
+                routes.insert(advertised, (index, name));
+                merged.push(item);
+            }
+        }
+
+        let mut all_routes = self.routes.lock().unwrap();
+        match field {
+            "tools" => all_routes.tools = routes,
+            _ => all_routes.prompts = routes,
+        }
+        Ok(merged)
+    }
+
+    /// Forward a request for a tool or prompt to its upstream, by its original name
+    async fn route_named(
+        &self,
+        params: Option<Value>,
+        method: &str,
+        tool: bool,
+    ) -> Result<Value, MCPError> {
+        let mut params = params.ok_or_else(|| invalid_params("Missing params"))?;
+        let name = params
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or_else(|| invalid_params("Missing name"))?
+            .to_string();
+
+        let lookup = |routes: &Routes| {
+            let routes = if tool { &routes.tools } else { &routes.prompts };
+            routes.get(&name).cloned()
+        };
+        let mut route = lookup(&self.routes.lock().unwrap());
+        if route.is_none() {
+            // The client may not have listed them yet
+            let (list, field) = if tool {
+                ("tools/list", "tools")
+            } else {
+                ("prompts/list", "prompts")
+            };
+            self.list_named(list, field).await?;
+            route = lookup(&self.routes.lock().unwrap());
+        }
+        let Some((index, original)) = route else {
+            return Err(invalid_params(&format!("Unknown name: {}", name)));
+        };
+
+        params["name"] = Value::String(original);
+        self.upstreams[index].1.forward(method, Some(params)).await
+    }
+}
+
+fn invalid_params(message: &str) -> MCPError {
+    MCPError::Rpc {
+        code: error_codes::INVALID_PARAMS,
+        message: message.to_string(),
+        data: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        schema::common::{Tool, ToolInputSchema},
+        server::{Server, ServerConfig},
+        transport::in_memory::InMemoryTransport,
+    };
+
+    /// A client of a server with an `echo` tool answering with `tag`
+    fn upstream(tag: &'static str) -> Client<InMemoryTransport> {
+        let tool = Tool {
+            name: "echo".to_string(),
+            description: None,
+            input_schema: ToolInputSchema {
+                r#type: "object".to_string(),
+                properties: None,
+                required: None,
+                keywords: serde_json::Map::new(),
+            },
+            output_schema: None,
+            annotations: None,
+        };
+        let mut server = Server::new(ServerConfig::new().with_tool(tool));
+        server
+            .register_tool_handler("echo", move |_| async move { Ok(Value::from(tag)) })
+            .unwrap();
+        let (client_end, server_end) = InMemoryTransport::pair();
+        tokio::spawn(async move { server.serve(server_end).await });
+        Client::new(client_end)
+    }
+
+    #[tokio::test]
+    async fn test_aggregator() -> Result<(), MCPError> {
+        let aggregator = Aggregator::new()
+            .with_upstream("a", upstream("from a"))
+            .with_upstream("b", upstream("from b"))
+            .with_prefixes(true);
+        let (client_end, downstream) = InMemoryTransport::pair();
+        let serving = tokio::spawn(aggregator.serve(downstream));
+
+        let mut client = Client::new(client_end);
+        let result = client.initialize().await?;
+        assert_eq!(result.server_info.name, "mcpr-aggregator");
+        assert!(result.capabilities.tools.is_some());
+
+        let tools: Vec<String> = client
+            .list_all_tools()
+            .await?
+            .into_iter()
+            .map(|tool| tool.name)
+            .collect();
+        assert_eq!(tools, ["a__echo", "b__echo"]);
+
+        let result: Value = client.call_tool("b__echo", &Value::Null).await?;
+        assert_eq!(result["content"][0]["text"], "\"from b\"");
+        let error = client
+            .call_tool::<_, Value>("echo", &Value::Null)
+            .await
+            .unwrap_err();
+        assert!(error.is_invalid_params());
+
+        client.shutdown().await?;
+        serving.await.unwrap()
+    }
+}
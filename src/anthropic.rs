@@ -0,0 +1,239 @@
+//! Answer sampling requests with Anthropic's Messages API
+//!
+//! Available with the `anthropic` feature. [`AnthropicSampler`] implements
+//! [`SamplingHandler`], so a host can satisfy the `sampling/createMessage`
+//! requests of servers with a hosted model:
+//!
+//! ```rust,ignore
+//! use mcpr::{anthropic::AnthropicSampler, sampling::{ModelProfile, ModelSelector}};
+//!
+//! let sampler = AnthropicSampler::from_env()?.with_models(ModelSelector::new([
+//!     ModelProfile::new("claude-3-5-sonnet-latest").with_scores(0.5, 0.5, 0.9),
+//!     ModelProfile::new("claude-3-5-haiku-latest").with_scores(0.9, 0.9, 0.6),
+//! ]));
+//! let client = Client::new(transport).with_sampling_handler(sampler);
+//! ```
+
+use crate::{
+    client::SamplingHandler,
+    error::MCPError,
+    sampling::{post_json, ModelProfile, ModelSelector},
+    schema::{
+        common::{Role, TextContent},
+        server::{
+            CreateMessageParams, CreateMessageResult, KnownStopReason, MessageContent, StopReason,
+        },
+    },
+};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+/// Where [`AnthropicSampler`] sends requests unless told otherwise
+pub const DEFAULT_ANTHROPIC_BASE_URL: &str = "https://api.anthropic.com/v1";
+
+/// The API version sent in the `anthropic-version` header
+pub const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Answers sampling requests with Anthropic's Messages API
+///
+/// The model is chosen from the server's preferences with a
+/// [`ModelSelector`], by default between `claude-3-5-sonnet-latest` and
+/// `claude-3-5-haiku-latest`. The system prompt, temperature, token limit,
+/// stop sequences and metadata are passed on; including context from
+/// servers is left to the host.
+#[derive(Clone)]
+pub struct AnthropicSampler {
+    http: reqwest::Client,
+    api_key: String,
+    base_url: String,
+    models: ModelSelector,
+}
+
+impl AnthropicSampler {
+    /// A sampler authenticating with `api_key`
+    pub fn new(api_key: &str) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            api_key: api_key.to_string(),
+            base_url: DEFAULT_ANTHROPIC_BASE_URL.to_string(),
+            models: ModelSelector::new([
+                ModelProfile::new("claude-3-5-sonnet-latest").with_scores(0.4, 0.5, 0.9),
+                ModelProfile::new("claude-3-5-haiku-latest").with_scores(0.9, 0.9, 0.6),
+            ]),
+        }
+    }
+
+    /// A sampler with the API key in the `ANTHROPIC_API_KEY` environment variable
+    pub fn from_env() -> Result<Self, MCPError> {
+        let api_key = std::env::var("ANTHROPIC_API_KEY")
+            .map_err(|_| MCPError::Unauthorized("ANTHROPIC_API_KEY is not set".to_string()))?;
+        Ok(Self::new(&api_key))
+    }
+
+    /// Send requests to `base_url` instead, e.g. a proxy
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Choose among these models instead of the default ones
+    pub fn with_models(mut self, models: ModelSelector) -> Self {
+        self.models = models;
+        self
+    }
+
+    /// The Messages API request for a sampling request
+    fn request_body(&self, params: &CreateMessageParams) -> Result<Value, MCPError> {
+        let model = self
+            .models
+            .select(params.model_preferences.as_ref())
+            .ok_or_else(|| MCPError::Protocol("No model to sample with".to_string()))?;
+
+        let messages: Vec<Value> = params
+            .messages
+            .iter()
+            .map(|message| {
+                let content = match &message.content {
+                    MessageContent::Text(text) => json!({ "type": "text", "text": text.text }),
+                    MessageContent::Image(image) => json!({
+                        "type": "image",
+                        "source": {
+                            "type": "base64",
+                            "media_type": image.mime_type,
+                            "data": image.data,
+                        },
+                    }),
+                };
+                json!({ "role": message.role.as_str(), "content": [content] })
+            })
+            .collect();
+
+        let mut body = json!({
+            "model": model,
+            "messages": messages,
+            "max_tokens": params.max_tokens,
+        });
+        if let Some(system_prompt) = &params.system_prompt {
+            body["system"] = json!(system_prompt);
+        }
+        if let Some(temperature) = params.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        if let Some(stop_sequences) = &params.stop_sequences {
+            body["stop_sequences"] = json!(stop_sequences);
+        }
+        if let Some(metadata) = &params.metadata {
+            body["metadata"] = metadata.clone();
+        }
+        Ok(body)
+    }
+}
+
+#[async_trait]
+impl SamplingHandler for AnthropicSampler {
+    async fn create_message(
+        &self,
+        params: CreateMessageParams,
+    ) -> Result<CreateMessageResult, MCPError> {
+        let body = self.request_body(&params)?;
+        let request = self
+            .http
+            .post(format!("{}/messages", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION);
+        parse_message(post_json("Anthropic", request, &body).await?)
+    }
+}
+
+/// Read a Messages API response as a sampling result, joining its text blocks
+fn parse_message(response: Value) -> Result<CreateMessageResult, MCPError> {
+    let Some(blocks) = response["content"].as_array() else {
+        return Err(MCPError::Protocol(format!(
+            "Anthropic response without content: {}",
+            response
+        )));
+    };
+    let text: String = blocks
+        .iter()
+        .filter(|block| block["type"] == "text")
+        .filter_map(|block| block["text"].as_str())
+        .collect();
+    let stop_reason = response["stop_reason"].as_str().map(|reason| match reason {
+        "end_turn" => StopReason::Known(KnownStopReason::EndTurn),
+        "stop_sequence" => StopReason::Known(KnownStopReason::StopSequence),
+        "max_tokens" => StopReason::Known(KnownStopReason::MaxTokens),
+        other => StopReason::Custom(other.to_string()),
+    });
+    Ok(CreateMessageResult {
+        role: Role::Assistant,
+        content: MessageContent::Text(TextContent {
+            r#type: "text".to_string(),
+            text,
+            annotations: None,
+        }),
+        model: response["model"].as_str().unwrap_or_default().to_string(),
+        stop_reason,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::server::{ModelPreferences, SamplingMessage};
+
+    #[test]
+    fn test_anthropic_sampler() {
+        let params = CreateMessageParams {
+            messages: vec![SamplingMessage {
+                role: Role::User,
+                content: MessageContent::Text(TextContent {
+                    r#type: "text".to_string(),
+                    text: "Summarize the file".to_string(),
+                    annotations: None,
+                }),
+            }],
+            model_preferences: Some(ModelPreferences {
+                hints: None,
+                cost_priority: Some(1.0),
+                speed_priority: None,
+                intelligence_priority: Some(0.2),
+            }),
+            system_prompt: Some("Be brief".to_string()),
+            include_context: None,
+            temperature: None,
+            max_tokens: 200,
+            stop_sequences: Some(vec!["END".to_string()]),
+            metadata: Some(json!({ "user_id": "u1" })),
+        };
+        let body = AnthropicSampler::new("key").request_body(&params).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "model": "claude-3-5-haiku-latest",
+                "messages": [{
+                    "role": "user",
+                    "content": [{ "type": "text", "text": "Summarize the file" }],
+                }],
+                "max_tokens": 200,
+                "system": "Be brief",
+                "stop_sequences": ["END"],
+                "metadata": { "user_id": "u1" },
+            })
+        );
+
+        let result = parse_message(json!({
+            "model": "claude-3-5-haiku-20241022",
+            "content": [{ "type": "text", "text": "It is " }, { "type": "text", "text": "short." }],
+            "stop_reason": "stop_sequence",
+        }))
+        .unwrap();
+        assert_eq!(result.model, "claude-3-5-haiku-20241022");
+        assert_eq!(
+            result.stop_reason,
+            Some(StopReason::Known(KnownStopReason::StopSequence))
+        );
+        assert!(
+            matches!(result.content, MessageContent::Text(text) if text.text == "It is short.")
+        );
+    }
+}
@@ -0,0 +1,386 @@
+//! An audit trail of tool calls, for hosts that must account for them
+//!
+//! An [`Auditor`] hands an [`AuditRecord`] of every tool call to an
+//! [`AuditSink`]: which tool was called with which arguments, by or on whom,
+//! how long it took and how it ended. Install one on a client with
+//! [`Client::with_audit`](crate::client::Client::with_audit) and on a server
+//! with [`ServerConfig::with_audit`](crate::server::ServerConfig::with_audit):
+//!
+//! ```rust,no_run
+//! use mcpr::audit::{ArgumentPolicy, Auditor, JsonLinesAuditSink};
+//!
+//! let file = std::fs::File::create("tool-calls.jsonl")?;
+//! let auditor = Auditor::new(JsonLinesAuditSink::new(file))
+//!     .with_arguments(ArgumentPolicy::Redact(vec!["password".to_string()]));
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! Arguments are recorded as a SHA-256 hash by default, so records show
+//! which calls had the same arguments without keeping what they were. With
+//! [`Auditor::with_redactor`], secrets are masked before the policy applies.
+
+use crate::{clock::Instant, redact::Redactor, schema::json_rpc::RequestId};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt::Write as _,
+    io::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Which side of the connection recorded a call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditSide {
+    /// The client, which sent the call
+    Client,
+    /// The server, which ran the tool
+    Server,
+}
+
+/// How a tool call ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditOutcome {
+    /// The tool ran and succeeded
+    Success,
+    /// The tool ran and reported an error in its result
+    ToolError,
+    /// The call was refused before the tool ran, for this reason
+    Denied(String),
+    /// The call failed with this error
+    Error(String),
+    /// The call was cancelled or abandoned before it ended
+    Cancelled,
+}
+
+/// How the arguments of a call appear in its record
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ArgumentPolicy {
+    /// A SHA-256 hash of the arguments, as `sha256:<hex>`
+    #[default]
+    Hash,
+    /// The arguments, with the values of these fields replaced, at any depth
+    Redact(Vec<String>),
+    /// The arguments as they are
+    Full,
+    /// No trace of the arguments
+    Omit,
+}
+
+/// The placeholder for redacted argument values
+pub const REDACTED: &str = "[REDACTED]";
+
+/// One tool call, as recorded
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditRecord {
+    pub side: AuditSide,
+    /// Name of the tool called
+    pub tool: String,
+    /// The arguments, as the [`ArgumentPolicy`] allows; `None` if omitted
+    pub arguments: Option<Value>,
+    /// Id of the server's session the call was made in, on the server
+    pub session: Option<u64>,
+    /// Name the other side gave on initialization: the client's on the
+    /// server, the server's on the client
+    pub peer: Option<String>,
+    /// Id of the `tools/call` request, on the server
+    pub request_id: Option<RequestId>,
+    /// When the call started
+    pub started_at: SystemTime,
+    /// How long the call took
+    pub duration: Duration,
+    pub outcome: AuditOutcome,
+}
+
+impl AuditRecord {
+    /// The record as a JSON object, as [`JsonLinesAuditSink`] writes it
+    pub fn to_json(&self) -> Value {
+        let (outcome, reason) = match &self.outcome {
+            AuditOutcome::Success => ("success", None),
+            AuditOutcome::ToolError => ("tool_error", None),
+            AuditOutcome::Denied(reason) => ("denied", Some(reason)),
+            AuditOutcome::Error(error) => ("error", Some(error)),
+            AuditOutcome::Cancelled => ("cancelled", None),
+        };
+        let started_at = self
+            .started_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let mut record = json!({
+            "side": match self.side {
+                AuditSide::Client => "client",
+                AuditSide::Server => "server",
+            },
+            "tool": self.tool,
+            "startedAt": started_at,
+            "durationMs": self.duration.as_secs_f64() * 1000.0,
+            "outcome": outcome,
+        });
+        let optional = [
+            ("arguments", self.arguments.clone()),
+            ("session", self.session.map(Value::from)),
+            ("peer", self.peer.clone().map(Value::from)),
+            ("requestId", self.request_id.as_ref().map(|id| json!(id))),
+            ("reason", reason.cloned().map(Value::from)),
+        ];
+        for (name, value) in optional {
+            if let Some(value) = value {
+                record[name] = value;
+            }
+        }
+        record
+    }
+}
+
+/// Receives the records of tool calls
+///
+/// Called as each call ends, so implementations should not block for long.
+pub trait AuditSink: Send + Sync + 'static {
+    /// Keep a record
+    fn record(&self, record: &AuditRecord);
+}
+
+impl<S: AuditSink> AuditSink for Arc<S> {
+    fn record(&self, record: &AuditRecord) {
+        (**self).record(record)
+    }
+}
+
+/// Keeps records in memory, for tests and inspection
+#[derive(Debug, Default)]
+pub struct InMemoryAuditSink {
+    records: Mutex<Vec<AuditRecord>>,
+}
+
+impl InMemoryAuditSink {
+    /// Start without records
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The records kept so far, oldest first
+    pub fn records(&self) -> Vec<AuditRecord> {
+        self.records.lock().unwrap().clone()
+    }
+}
+
+impl AuditSink for InMemoryAuditSink {
+    fn record(&self, record: &AuditRecord) {
+        self.records.lock().unwrap().push(record.clone());
+    }
+}
+
+/// Writes each record as a line of JSON, see [`AuditRecord::to_json`]
+pub struct JsonLinesAuditSink<W: Write + Send + 'static> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send + 'static> JsonLinesAuditSink<W> {
+    /// Write records to `writer`, flushing after each
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+}
+
+impl<W: Write + Send + 'static> AuditSink for JsonLinesAuditSink<W> {
+    fn record(&self, record: &AuditRecord) {
+        let mut writer = self.writer.lock().unwrap();
+        let written = writeln!(writer, "{}", record.to_json()).and_then(|()| writer.flush());
+        if let Err(e) = written {
+            log::error!("Failed to write audit record: {}", e);
+        }
+    }
+}
+
+/// Records tool calls to a sink, see the [module docs](self)
+#[derive(Clone)]
+pub struct Auditor {
+    sink: Arc<dyn AuditSink>,
+    arguments: ArgumentPolicy,
+    redactor: Option<Redactor>,
+}
+
+impl Auditor {
+    /// Record to `sink`, with the arguments hashed
+    pub fn new(sink: impl AuditSink) -> Self {
+        Self {
+            sink: Arc::new(sink),
+            arguments: ArgumentPolicy::default(),
+            redactor: None,
+        }
+    }
+
+    /// Set how arguments are recorded
+    pub fn with_arguments(mut self, policy: ArgumentPolicy) -> Self {
+        self.arguments = policy;
+        self
+    }
+
+    /// Mask secrets in the arguments with `redactor` before recording them
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    /// Start recording a call; it is recorded as cancelled unless finished
+    pub(crate) fn start(&self, side: AuditSide, tool: &str, arguments: &Value) -> AuditCall {
+        AuditCall {
+            auditor: self.clone(),
+            record: Some(AuditRecord {
+                side,
+                tool: tool.to_string(),
+                arguments: self.audited_arguments(tool, arguments),
+                session: None,
+                peer: None,
+                request_id: None,
+                started_at: now(),
+                duration: Duration::ZERO,
+                outcome: AuditOutcome::Cancelled,
+            }),
+            started: Instant::now(),
+        }
+    }
+
+    fn audited_arguments(&self, tool: &str, arguments: &Value) -> Option<Value> {
+        let redacted;
+        let arguments = match &self.redactor {
+            Some(redactor) => {
+                redacted = redactor.redact_arguments(tool, arguments);
+                &redacted
+            }
+            None => arguments,
+        };
+        match &self.arguments {
+            ArgumentPolicy::Hash => {
+                let digest = Sha256::digest(arguments.to_string().as_bytes());
+                let mut hash = String::from("sha256:");
+                for byte in digest {
+                    let _ = write!(hash, "{:02x}", byte);
+                }
+                Some(Value::String(hash))
+            }
+            ArgumentPolicy::Redact(fields) => Some(redact(arguments, fields)),
+            ArgumentPolicy::Full => Some(arguments.clone()),
+            ArgumentPolicy::Omit => None,
+        }
+    }
+}
+
+/// A call being recorded, handed to the sink when finished or dropped
+pub(crate) struct AuditCall {
+    auditor: Auditor,
+    record: Option<AuditRecord>,
+    started: Instant,
+}
+
+impl AuditCall {
+    /// Set the session and the other side's name
+    pub(crate) fn with_peer(mut self, session: Option<u64>, peer: Option<String>) -> Self {
+        if let Some(record) = &mut self.record {
+            record.session = session;
+            record.peer = peer;
+        }
+        self
+    }
+
+    /// Set the id of the request that carries the call; only the server knows it
+    #[cfg_attr(not(feature = "runtime-tokio"), allow(dead_code))]
+    pub(crate) fn with_request_id(mut self, request_id: RequestId) -> Self {
+        if let Some(record) = &mut self.record {
+            record.request_id = Some(request_id);
+        }
+        self
+    }
+
+    /// Record the call with how it ended
+    pub(crate) fn finish(mut self, outcome: AuditOutcome) {
+        self.send(outcome);
+    }
+
+    fn send(&mut self, outcome: AuditOutcome) {
+        if let Some(mut record) = self.record.take() {
+            record.duration = self.started.elapsed();
+            record.outcome = outcome;
+            self.auditor.sink.record(&record);
+        }
+    }
+}
+
+impl Drop for AuditCall {
+    fn drop(&mut self) {
+        self.send(AuditOutcome::Cancelled);
+    }
+}
+
+/// Replace the values of `fields` in `value`, at any depth
+fn redact(value: &Value, fields: &[String]) -> Value {
+    match value {
+        Value::Object(object) => Value::Object(
+            object
+                .iter()
+                .map(|(key, value)| {
+                    let value = if fields.contains(key) {
+                        Value::String(REDACTED.to_string())
+                    } else {
+                        redact(value, fields)
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => {
+            Value::Array(items.iter().map(|item| redact(item, fields)).collect())
+        }
+        value => value.clone(),
+    }
+}
+
+/// The current time; `SystemTime::now` is not available in the browser
+fn now() -> SystemTime {
+    #[cfg(target_arch = "wasm32")]
+    {
+        UNIX_EPOCH + Duration::from_millis(js_sys::Date::now() as u64)
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        SystemTime::now()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auditor() {
+        let sink = Arc::new(InMemoryAuditSink::new());
+        let arguments = json!({ "user": "ann", "auth": { "password": "hunter2" } });
+
+        let auditor = Auditor::new(sink.clone());
+        auditor
+            .start(AuditSide::Server, "login", &arguments)
+            .with_peer(Some(3), Some("desktop".to_string()))
+            .finish(AuditOutcome::Success);
+        let redacting =
+            auditor.with_arguments(ArgumentPolicy::Redact(vec!["password".to_string()]));
+        drop(redacting.start(AuditSide::Client, "login", &arguments));
+
+        let records = sink.records();
+        let hash = records[0]
+            .arguments
+            .as_ref()
+            .and_then(Value::as_str)
+            .unwrap();
+        assert!(hash.starts_with("sha256:") && hash.len() == 7 + 64);
+        assert_eq!(records[0].session, Some(3));
+        assert_eq!(records[0].to_json()["outcome"], "success");
+        assert_eq!(
+            records[1].arguments,
+            Some(json!({ "user": "ann", "auth": { "password": REDACTED } }))
+        );
+        assert_eq!(records[1].outcome, AuditOutcome::Cancelled);
+    }
+}
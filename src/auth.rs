@@ -0,0 +1,598 @@
+//! Authorization for the HTTP transports
+//!
+//! [`SseTransport`](crate::transport::sse::SseTransport) and
+//! [`StreamableHttpTransport`](crate::transport::streamable_http::StreamableHttpTransport)
+//! send the token of a [`TokenProvider`] as `Authorization: Bearer` with
+//! every request, and when the server answers 401 ask the provider for a new
+//! token and retry once. [`StaticToken`] sends a fixed token. [`OAuthClient`]
+//! runs the OAuth 2.1 authorization code flow with PKCE that the MCP
+//! specification requires of remote servers: it discovers the authorization
+//! server's endpoints, registers itself if it has no client id, builds the
+//! URL to send the user to, exchanges the code the redirect brings back for
+//! tokens and refreshes them as they expire.
+//!
+//! ```rust,no_run
+//! use mcpr::{auth::OAuthClient, transport::streamable_http::StreamableHttpTransport};
+//!
+//! # async fn run() -> Result<(), mcpr::error::MCPError> {
+//! let oauth = OAuthClient::new("https://example.com", "http://localhost:8400/callback")?
+//!     .with_scopes(&["tools"]);
+//! let request = oauth.authorization_request().await?;
+//! println!("Open {}", request.url);
+//!
+//! // Once the browser is redirected to http://localhost:8400/callback?code=...&state=...
+//! # let (code, state) = (String::new(), String::new());
+//! oauth.exchange_code(&request, &code, &state).await?;
+//! let transport =
+//!     StreamableHttpTransport::new("https://example.com/mcp").with_token_provider(oauth);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{constants::LATEST_PROTOCOL_VERSION, error::MCPError};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use log::{debug, info};
+use rand::{distributions::Alphanumeric, Rng};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::Instant;
+use url::Url;
+
+/// Tokens are refreshed this long before they expire
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+/// Supplies the bearer tokens HTTP transports authorize their requests with
+#[async_trait]
+pub trait TokenProvider: Send + Sync + 'static {
+    /// The access token to send
+    async fn token(&self) -> Result<String, MCPError>;
+
+    /// Get a new token after the server rejected the current one
+    ///
+    /// Returns whether there is a new token to retry with; by default there
+    /// is none.
+    async fn refresh(&self) -> Result<bool, MCPError> {
+        Ok(false)
+    }
+}
+
+/// A fixed bearer token
+#[derive(Debug, Clone)]
+pub struct StaticToken(String);
+
+impl StaticToken {
+    /// Send `token` with every request
+    pub fn new(token: impl Into<String>) -> Self {
+        Self(token.into())
+    }
+}
+
+#[async_trait]
+impl TokenProvider for StaticToken {
+    async fn token(&self) -> Result<String, MCPError> {
+        Ok(self.0.clone())
+    }
+}
+
+/// Add the provider's token to `headers`, if there is a provider
+pub(crate) async fn authorize(
+    provider: Option<&Arc<dyn TokenProvider>>,
+    headers: &mut HeaderMap,
+) -> Result<(), MCPError> {
+    if let Some(provider) = provider {
+        let token = provider.token().await?;
+        let value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|e| MCPError::Unauthorized(format!("Invalid token: {}", e)))?;
+        headers.insert(AUTHORIZATION, value);
+    }
+    Ok(())
+}
+
+/// After a 401, whether the provider has a new token to retry with
+pub(crate) async fn reauthorize(
+    provider: Option<&Arc<dyn TokenProvider>>,
+) -> Result<bool, MCPError> {
+    match provider {
+        Some(provider) => provider.refresh().await,
+        None => Ok(false),
+    }
+}
+
+/// Endpoints of an authorization server (RFC 8414)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthorizationServerMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registration_endpoint: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scopes_supported: Vec<String>,
+}
+
+/// Tokens from a token endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenSet {
+    pub access_token: String,
+    #[serde(default = "bearer")]
+    pub token_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    /// Lifetime of the access token, in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_in: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+}
+
+fn bearer() -> String {
+    "Bearer".to_string()
+}
+
+/// An authorization the user has to grant, see [`OAuthClient::authorization_request`]
+#[derive(Debug, Clone)]
+pub struct AuthorizationRequest {
+    /// URL to open in the user's browser
+    pub url: String,
+    /// Value the redirect must bring back unchanged
+    pub state: String,
+    /// PKCE secret sent with the code; only its hash is in the URL
+    pub code_verifier: String,
+}
+
+#[derive(Default)]
+struct OAuthState {
+    metadata: Option<AuthorizationServerMetadata>,
+    client_id: Option<String>,
+    /// Tokens, with when they were issued
+    tokens: Option<(TokenSet, Instant)>,
+}
+
+/// OAuth 2.1 client for the authorization code flow with PKCE
+///
+/// Clones share the client registration and the tokens.
+#[derive(Clone)]
+pub struct OAuthClient {
+    /// Base URL of the MCP server, whose origin hosts the authorization server
+    server_url: Url,
+    redirect_uri: String,
+    client_name: String,
+    scopes: Vec<String>,
+    http: reqwest::Client,
+    /// Locked only between awaits, so the setters never wait on a request
+    state: Arc<Mutex<OAuthState>>,
+}
+
+impl OAuthClient {
+    /// Authorize with the MCP server at `server_url`, which redirects the
+    /// user back to `redirect_uri` once they granted access
+    pub fn new(server_url: &str, redirect_uri: &str) -> Result<Self, MCPError> {
+        let server_url = Url::parse(server_url).map_err(|e| {
+            MCPError::Transport(format!("Invalid server URL '{}': {}", server_url, e))
+        })?;
+        Ok(Self {
+            server_url,
+            redirect_uri: redirect_uri.to_string(),
+            client_name: "mcpr".to_string(),
+            scopes: Vec::new(),
+            http: reqwest::Client::new(),
+            state: Arc::new(Mutex::new(OAuthState::default())),
+        })
+    }
+
+    /// Use a client id registered beforehand instead of registering dynamically
+    pub fn with_client_id(self, client_id: &str) -> Self {
+        self.state.lock().unwrap().client_id = Some(client_id.to_string());
+        self
+    }
+
+    /// Set the name the client registers under
+    pub fn with_client_name(mut self, client_name: &str) -> Self {
+        self.client_name = client_name.to_string();
+        self
+    }
+
+    /// Request these scopes
+    pub fn with_scopes(mut self, scopes: &[&str]) -> Self {
+        self.scopes = scopes.iter().map(|scope| scope.to_string()).collect();
+        self
+    }
+
+    /// Start with tokens saved from an earlier session
+    pub fn with_tokens(self, tokens: TokenSet) -> Self {
+        self.state.lock().unwrap().tokens = Some((tokens, Instant::now()));
+        self
+    }
+
+    /// The current tokens, for saving them
+    pub async fn tokens(&self) -> Option<TokenSet> {
+        let state = self.state.lock().unwrap();
+        state.tokens.as_ref().map(|(tokens, _)| tokens.clone())
+    }
+
+    /// Discover the authorization server's endpoints
+    ///
+    /// Reads its metadata from `/.well-known/oauth-authorization-server`,
+    /// falling back to `/authorize`, `/token` and `/register` on the MCP
+    /// server's origin when there is none, as the specification allows.
+    pub async fn discover(&self) -> Result<AuthorizationServerMetadata, MCPError> {
+        if let Some(metadata) = &self.state.lock().unwrap().metadata {
+            return Ok(metadata.clone());
+        }
+
+        let endpoint = |path: &str| self.server_url.join(path).map(String::from);
+        let url = endpoint("/.well-known/oauth-authorization-server")
+            .map_err(|e| MCPError::Transport(format!("Invalid metadata URL: {}", e)))?;
+        let response = self
+            .http
+            .get(&url)
+            .header("MCP-Protocol-Version", LATEST_PROTOCOL_VERSION)
+            .send()
+            .await
+            .map_err(|e| MCPError::Transport(format!("Failed to fetch {}: {}", url, e)))?;
+        let metadata = if response.status().is_success() {
+            response.json().await.map_err(|e| {
+                MCPError::Protocol(format!("Invalid authorization server metadata: {}", e))
+            })?
+        } else if response.status() == reqwest::StatusCode::NOT_FOUND {
+            debug!("No authorization server metadata, using the default endpoints");
+            let invalid = |e: url::ParseError| MCPError::Transport(e.to_string());
+            AuthorizationServerMetadata {
+                issuer: None,
+                authorization_endpoint: endpoint("/authorize").map_err(invalid)?,
+                token_endpoint: endpoint("/token").map_err(invalid)?,
+                registration_endpoint: Some(endpoint("/register").map_err(invalid)?),
+                scopes_supported: Vec::new(),
+            }
+        } else {
+            return Err(MCPError::Transport(format!(
+                "Failed to fetch {}: HTTP {}",
+                url,
+                response.status()
+            )));
+        };
+
+        self.state.lock().unwrap().metadata = Some(metadata.clone());
+        Ok(metadata)
+    }
+
+    /// The client id, registering the client dynamically (RFC 7591) if there is none
+    pub async fn register(&self) -> Result<String, MCPError> {
+        if let Some(client_id) = &self.state.lock().unwrap().client_id {
+            return Ok(client_id.clone());
+        }
+
+        let metadata = self.discover().await?;
+        let endpoint = metadata.registration_endpoint.ok_or_else(|| {
+            MCPError::Unauthorized(
+                "The authorization server does not support dynamic client registration; \
+                 set a client id"
+                    .to_string(),
+            )
+        })?;
+        let registration = serde_json::json!({
+            "client_name": self.client_name,
+            "redirect_uris": [self.redirect_uri],
+            "grant_types": ["authorization_code", "refresh_token"],
+            "response_types": ["code"],
+            "token_endpoint_auth_method": "none",
+        });
+        let response = self
+            .http
+            .post(&endpoint)
+            .json(&registration)
+            .send()
+            .await
+            .map_err(|e| MCPError::Transport(format!("Failed to register client: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(MCPError::Unauthorized(format!(
+                "Client registration failed: HTTP {}",
+                response.status()
+            )));
+        }
+
+        #[derive(Deserialize)]
+        struct Registered {
+            client_id: String,
+        }
+        let registered: Registered = response
+            .json()
+            .await
+            .map_err(|e| MCPError::Protocol(format!("Invalid registration response: {}", e)))?;
+        info!("Registered OAuth client {}", registered.client_id);
+        self.state.lock().unwrap().client_id = Some(registered.client_id.clone());
+        Ok(registered.client_id)
+    }
+
+    /// Build the URL to send the user to, with a fresh PKCE challenge and state
+    pub async fn authorization_request(&self) -> Result<AuthorizationRequest, MCPError> {
+        let metadata = self.discover().await?;
+        let client_id = self.register().await?;
+
+        let code_verifier = random_string(64);
+        let state = random_string(32);
+        let mut url = Url::parse(&metadata.authorization_endpoint).map_err(|e| {
+            MCPError::Protocol(format!(
+                "Invalid authorization endpoint '{}': {}",
+                metadata.authorization_endpoint, e
+            ))
+        })?;
+        {
+            let mut query = url.query_pairs_mut();
+            query
+                .append_pair("response_type", "code")
+                .append_pair("client_id", &client_id)
+                .append_pair("redirect_uri", &self.redirect_uri)
+                .append_pair("code_challenge", &code_challenge(&code_verifier))
+                .append_pair("code_challenge_method", "S256")
+                .append_pair("state", &state);
+            if !self.scopes.is_empty() {
+                query.append_pair("scope", &self.scopes.join(" "));
+            }
+        }
+
+        Ok(AuthorizationRequest {
+            url: url.into(),
+            state,
+            code_verifier,
+        })
+    }
+
+    /// Exchange the code and state the redirect brought back for tokens
+    pub async fn exchange_code(
+        &self,
+        request: &AuthorizationRequest,
+        code: &str,
+        state: &str,
+    ) -> Result<TokenSet, MCPError> {
+        if state != request.state {
+            return Err(MCPError::Unauthorized(
+                "State of the redirect does not match the request".to_string(),
+            ));
+        }
+        let client_id = self.register().await?;
+        self.request_tokens(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.redirect_uri),
+            ("client_id", &client_id),
+            ("code_verifier", &request.code_verifier),
+        ])
+        .await
+    }
+
+    /// Get a new access token with the refresh token
+    pub async fn refresh_tokens(&self) -> Result<TokenSet, MCPError> {
+        let refresh_token = self
+            .tokens()
+            .await
+            .and_then(|tokens| tokens.refresh_token)
+            .ok_or_else(|| MCPError::Unauthorized("No refresh token".to_string()))?;
+        let client_id = self.register().await?;
+        self.request_tokens(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", &refresh_token),
+            ("client_id", &client_id),
+        ])
+        .await
+    }
+
+    /// Post a token request and keep the tokens it returns
+    async fn request_tokens(&self, form: &[(&str, &str)]) -> Result<TokenSet, MCPError> {
+        let metadata = self.discover().await?;
+        let response = self
+            .http
+            .post(&metadata.token_endpoint)
+            .form(form)
+            .send()
+            .await
+            .map_err(|e| MCPError::Transport(format!("Token request failed: {}", e)))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(MCPError::Unauthorized(format!(
+                "Token request failed: HTTP {} {}",
+                status, body
+            )));
+        }
+        let mut tokens: TokenSet = response
+            .json()
+            .await
+            .map_err(|e| MCPError::Protocol(format!("Invalid token response: {}", e)))?;
+
+        let mut state = self.state.lock().unwrap();
+        // A refresh may not return a new refresh token; the old one stays valid
+        if tokens.refresh_token.is_none() {
+            tokens.refresh_token = state
+                .tokens
+                .as_ref()
+                .and_then(|(tokens, _)| tokens.refresh_token.clone());
+        }
+        state.tokens = Some((tokens.clone(), Instant::now()));
+        Ok(tokens)
+    }
+}
+
+#[async_trait]
+impl TokenProvider for OAuthClient {
+    async fn token(&self) -> Result<String, MCPError> {
+        let (expired, can_refresh) = match &self.state.lock().unwrap().tokens {
+            Some((tokens, issued)) => {
+                let expired = tokens.expires_in.is_some_and(|expires_in| {
+                    issued.elapsed() + EXPIRY_MARGIN >= Duration::from_secs(expires_in)
+                });
+                if !expired {
+                    return Ok(tokens.access_token.clone());
+                }
+                (expired, tokens.refresh_token.is_some())
+            }
+            None => (false, false),
+        };
+        if expired && can_refresh {
+            return Ok(self.refresh_tokens().await?.access_token);
+        }
+        Err(MCPError::Unauthorized(
+            "No valid access token; complete the authorization flow first".to_string(),
+        ))
+    }
+
+    async fn refresh(&self) -> Result<bool, MCPError> {
+        let can_refresh = self
+            .tokens()
+            .await
+            .is_some_and(|tokens| tokens.refresh_token.is_some());
+        if !can_refresh {
+            return Ok(false);
+        }
+        self.refresh_tokens().await?;
+        Ok(true)
+    }
+}
+
+/// Random string of letters and digits, which PKCE and OAuth state allow
+fn random_string(length: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+/// The S256 PKCE challenge for a verifier
+fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::test_http::read_request;
+    use crate::transport::{streamable_http::StreamableHttpTransport, Transport};
+    use serde_json::Value;
+    use std::collections::HashMap;
+    use tokio::io::AsyncWriteExt;
+
+    fn form(body: &str) -> HashMap<String, String> {
+        url::form_urlencoded::parse(body.as_bytes())
+            .into_owned()
+            .collect()
+    }
+
+    #[test]
+    fn test_code_challenge() {
+        // Example from RFC 7636, appendix B
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oauth_flow() -> Result<(), MCPError> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+
+        let server = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let (head, body) = read_request(&mut socket).await;
+                let line = head.lines().next().unwrap().to_string();
+                let fields = form(&body);
+                let (status, reply) = if line.starts_with("GET /.well-known") {
+                    ("404 Not Found", String::new())
+                } else if line.starts_with("POST /register") {
+                    ("201 Created", r#"{"client_id":"c-1"}"#.to_string())
+                } else if line.starts_with("POST /token") {
+                    let token = match fields["grant_type"].as_str() {
+                        "authorization_code" => {
+                            r#"{"access_token":"a-1","refresh_token":"r-1","expires_in":3600}"#
+                        }
+                        _ => r#"{"access_token":"a-2","expires_in":3600}"#,
+                    };
+                    ("200 OK", token.to_string())
+                } else if head.contains("Bearer a-1") {
+                    ("401 Unauthorized", String::new())
+                } else {
+                    let message: Value = serde_json::from_str(&body).unwrap();
+                    let result =
+                        serde_json::json!({ "jsonrpc": "2.0", "id": message["id"], "result": {} });
+                    ("200 OK", result.to_string())
+                };
+                requests.push((head, body));
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    status,
+                    reply.len(),
+                    reply
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+                if line.starts_with("POST /mcp") && status == "200 OK" {
+                    return requests;
+                }
+            }
+        });
+
+        let oauth = OAuthClient::new(&format!("{}/mcp", base), "http://localhost/callback")?
+            .with_scopes(&["tools"]);
+        let request = oauth.authorization_request().await?;
+        let url = Url::parse(&request.url).unwrap();
+        let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(url.path(), "/authorize");
+        assert_eq!(query["client_id"], "c-1");
+        assert_eq!(query["code_challenge_method"], "S256");
+        assert_eq!(
+            query["code_challenge"],
+            code_challenge(&request.code_verifier)
+        );
+        assert_eq!(query["state"], request.state);
+        assert_eq!(query["scope"], "tools");
+
+        assert!(matches!(
+            oauth.exchange_code(&request, "code-1", "forged").await,
+            Err(MCPError::Unauthorized(_))
+        ));
+        let tokens = oauth
+            .exchange_code(&request, "code-1", &request.state)
+            .await?;
+        assert_eq!(tokens.access_token, "a-1");
+
+        // The server rejects a-1, so the transport refreshes and retries
+        let mut transport = StreamableHttpTransport::new(&format!("{}/mcp", base))
+            .with_token_provider(oauth.clone());
+        transport
+            .send(&serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": "ping" }))
+            .await?;
+        let response: Value = transport.receive().await?;
+        assert_eq!(response["id"], 1);
+        let tokens = oauth.tokens().await.unwrap();
+        assert_eq!(
+            (
+                tokens.access_token.as_str(),
+                tokens.refresh_token.as_deref()
+            ),
+            ("a-2", Some("r-1"))
+        );
+
+        let requests = server.await.unwrap();
+        let exchange = form(&requests[2].1);
+        assert_eq!(exchange["code"], "code-1");
+        assert_eq!(exchange["code_verifier"], request.code_verifier);
+        let registration: Value = serde_json::from_str(&requests[1].1).unwrap();
+        assert_eq!(
+            registration["redirect_uris"][0],
+            "http://localhost/callback"
+        );
+        assert!(requests[3].0.contains("Bearer a-1"));
+        assert_eq!(form(&requests[4].1)["refresh_token"], "r-1");
+        assert!(requests[5].0.contains("Bearer a-2"));
+        Ok(())
+    }
+}
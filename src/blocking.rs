@@ -0,0 +1,135 @@
+//! Synchronous client, for programs that do not use async
+//!
+//! [`Client`] wraps the async [`client::Client`](crate::client::Client) and
+//! runs it on a runtime of its own, like `reqwest::blocking`. Calls block the
+//! current thread until they complete, so it must not be used from within an
+//! async runtime.
+//!
+//! ```rust,no_run
+//! use mcpr::{blocking::Client, transport::stdio::StdioTransport};
+//! use serde_json::Value;
+//!
+//! fn main() -> Result<(), mcpr::error::MCPError> {
+//!     let mut client = Client::new(StdioTransport::new())?;
+//!     client.initialize()?;
+//!     let result: Value = client.call_tool("echo", &serde_json::json!({ "message": "hi" }))?;
+//!     println!("{}", result);
+//!     client.shutdown()
+//! }
+//! ```
+
+use crate::{
+    client,
+    error::MCPError,
+    schema::{common::Tool, server::InitializeResult},
+    transport::Transport,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::runtime::Runtime;
+
+/// A client whose methods block until the server answers
+pub struct Client<T: Transport + Send + Sync> {
+    inner: client::Client<T>,
+    runtime: Runtime,
+}
+
+impl<T: Transport + Send + Sync> Client<T> {
+    /// Create a client with the given transport
+    ///
+    /// Fails if the runtime cannot be started.
+    pub fn new(transport: T) -> Result<Self, MCPError> {
+        Self::from_async(client::Client::new(transport))
+    }
+
+    /// Wrap an async client, for settings only it has builders for
+    pub fn from_async(inner: client::Client<T>) -> Result<Self, MCPError> {
+        // A worker thread keeps transports reading between calls
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .map_err(|e| MCPError::Transport(format!("Failed to start runtime: {}", e)))?;
+        Ok(Self { inner, runtime })
+    }
+
+    /// Initialize the client
+    pub fn initialize(&mut self) -> Result<InitializeResult, MCPError> {
+        self.runtime.block_on(self.inner.initialize())
+    }
+
+    /// List the tools on the server, walking all pages
+    pub fn list_tools(&mut self) -> Result<Vec<Tool>, MCPError> {
+        self.runtime.block_on(self.inner.list_all_tools())
+    }
+
+    /// Call a tool on the server
+    pub fn call_tool<P, R>(&mut self, tool_name: &str, params: &P) -> Result<R, MCPError>
+    where
+        P: Serialize + Send + Sync,
+        R: DeserializeOwned + Send + Sync,
+    {
+        self.runtime
+            .block_on(self.inner.call_tool(tool_name, params))
+    }
+
+    /// Check that the server is responsive
+    pub fn ping(&mut self) -> Result<(), MCPError> {
+        self.runtime.block_on(self.inner.ping())
+    }
+
+    /// Shutdown the client
+    pub fn shutdown(&mut self) -> Result<(), MCPError> {
+        self.runtime.block_on(self.inner.shutdown())
+    }
+
+    /// Run any method of the async client to completion
+    ///
+    /// ```rust,ignore
+    /// let prompts = client.block_on(|client| client.list_prompts())?;
+    /// ```
+    pub fn block_on<'a, F, Fut>(&'a mut self, f: F) -> Fut::Output
+    where
+        F: FnOnce(&'a mut client::Client<T>) -> Fut,
+        Fut: std::future::Future + 'a,
+    {
+        self.runtime.block_on(f(&mut self.inner))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::common::ToolInputSchema;
+    use crate::schema::server::CallToolResult;
+    use crate::testing::MockServer;
+
+    #[test]
+    fn test_blocking_client() -> Result<(), MCPError> {
+        let tool = Tool {
+            name: "echo".to_string(),
+            description: None,
+            input_schema: ToolInputSchema {
+                r#type: "object".to_string(),
+                properties: None,
+                required: None,
+                keywords: serde_json::Map::new(),
+            },
+            output_schema: None,
+            annotations: None,
+        };
+        let result = CallToolResult {
+            content: Vec::new(),
+            structured_content: Some(serde_json::json!({ "ok": true })),
+            is_error: None,
+        };
+        let server = MockServer::new().with_tool(tool.clone(), result.clone());
+
+        let mut client = Client::new(server.transport())?;
+        client.initialize()?;
+        assert_eq!(client.list_tools()?, vec![tool]);
+        let called: CallToolResult = client.call_tool("echo", &())?;
+        assert_eq!(called, result);
+        client.block_on(|client| client.ping())?;
+        client.shutdown()
+    }
+}
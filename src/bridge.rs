@@ -0,0 +1,242 @@
+//! Serve an MCP server over another transport
+//!
+//! A [`Bridge`] connects as a client to a server on one transport (upstream)
+//! and serves the same capabilities on another (downstream), such as a local
+//! stdio server behind a WebSocket endpoint. The upstream session is initialized
+//! once by the bridge, and its result is the answer to the downstream
+//! client's `initialize`; every other request, notification and response,
+//! including progress and requests the server sends the client, is
+//! forwarded both ways with [`relay`](crate::proxy::relay). A bridge serves
+//! one downstream client, since the upstream session is its own.
+//!
+//! ```rust,no_run
+//! use mcpr::{
+//!     bridge::Bridge,
+//!     transport::{stdio::StdioTransport, websocket::WebSocketTransport},
+//! };
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), mcpr::error::MCPError> {
+//!     let upstream = StdioTransport::spawn("my-mcp-server", ["--quiet"])?;
+//!     let mut bridge = Bridge::new(upstream);
+//!     bridge.connect().await?;
+//!     bridge.serve(WebSocketTransport::new_server("ws://127.0.0.1:8080")).await
+//! }
+//! ```
+
+use crate::{
+    constants::LATEST_PROTOCOL_VERSION,
+    error::MCPError,
+    proxy::{relay_started, Direction, FrameAction, RelayHooks},
+    schema::{
+        client::{ClientCapabilities, InitializeParams},
+        common::Implementation,
+        json_rpc::{
+            JSONRPCMessage, JSONRPCNotification, JSONRPCPayload, JSONRPCRequest, JSONRPCResponse,
+            RequestId,
+        },
+    },
+    transport::Transport,
+};
+use log::{debug, info};
+use serde_json::Value;
+
+/// Id of the bridge's own `initialize` request, distinct from the client's ids
+const INITIALIZE_ID: &str = "mcpr-bridge-initialize";
+
+/// Serves an upstream server's capabilities downstream, see the [module docs](self)
+pub struct Bridge<U: Transport + Clone + 'static> {
+    upstream: U,
+    client_info: Implementation,
+    capabilities: ClientCapabilities,
+    /// The upstream server's `initialize` result, once connected
+    initialize_result: Option<Value>,
+}
+
+impl<U: Transport + Clone + 'static> Bridge<U> {
+    /// Bridge the server on `upstream`
+    pub fn new(upstream: U) -> Self {
+        Self {
+            upstream,
+            client_info: Implementation {
+                name: "mcpr-bridge".to_string(),
+                version: crate::VERSION.to_string(),
+            },
+            capabilities: ClientCapabilities::default(),
+            initialize_result: None,
+        }
+    }
+
+    /// Set the name and version the bridge gives the upstream server
+    pub fn with_client_info(mut self, name: &str, version: &str) -> Self {
+        self.client_info = Implementation {
+            name: name.to_string(),
+            version: version.to_string(),
+        };
+        self
+    }
+
+    /// Advertise capabilities upstream on behalf of the downstream clients
+    ///
+    /// None by default. Advertise sampling or roots when the clients served
+    /// downstream can answer the server's requests for them.
+    pub fn with_capabilities(mut self, capabilities: ClientCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Start the upstream transport and initialize the session
+    ///
+    /// Returns the server's `initialize` result, which downstream clients get.
+    pub async fn connect(&mut self) -> Result<&Value, MCPError> {
+        if self.initialize_result.is_none() {
+            self.upstream.start().await?;
+            let result = self.initialize().await?;
+            self.initialize_result = Some(result);
+        }
+        Ok(self.initialize_result.as_ref().unwrap())
+    }
+
+    /// The upstream server's `initialize` result, once connected
+    pub fn initialize_result(&self) -> Option<&Value> {
+        self.initialize_result.as_ref()
+    }
+
+    /// Serve a client on `downstream` until either side disconnects
+    ///
+    /// Connects upstream first if [`Bridge::connect`] was not called.
+    pub async fn serve<D>(mut self, mut downstream: D) -> Result<(), MCPError>
+    where
+        D: Transport + Clone + 'static,
+    {
+        let result = self.connect().await?.clone();
+        downstream.start().await?;
+
+        // The downstream client's handshake is answered here, since the
+        // upstream session is initialized already
+        let hooks = RelayHooks::new().on_frame(move |direction, message| match message {
+            JSONRPCMessage::Request(request)
+                if direction == Direction::ToServer && request.method == "initialize" =>
+            {
+                debug!(
+                    "Answering initialize {:?} for the upstream server",
+                    request.id
+                );
+                FrameAction::Reply(JSONRPCMessage::Response(JSONRPCResponse::new(
+                    request.id,
+                    result.clone(),
+                )))
+            }
+            JSONRPCMessage::Notification(notification)
+                if direction == Direction::ToServer
+                    && notification.method == "notifications/initialized" =>
+            {
+                FrameAction::Drop
+            }
+            message => FrameAction::Forward(message),
+        });
+        relay_started(downstream, self.upstream, hooks).await
+    }
+
+    /// Perform the initialize handshake with the upstream server
+    async fn initialize(&mut self) -> Result<Value, MCPError> {
+        let params = InitializeParams {
+            protocol_version: LATEST_PROTOCOL_VERSION.to_string(),
+            capabilities: self.capabilities.clone(),
+            client_info: self.client_info.clone(),
+        };
+        let id = RequestId::String(INITIALIZE_ID.to_string());
+        let request = JSONRPCRequest::new(
+            id.clone(),
+            "initialize".to_string(),
+            Some(serde_json::to_value(params)?),
+        );
+        self.upstream
+            .send(&JSONRPCMessage::Request(request))
+            .await?;
+
+        // Whatever the server sends before answering has no client to go to
+        let result = loop {
+            let payload: JSONRPCPayload = self.upstream.receive().await?;
+            let response = payload
+                .into_messages()
+                .into_iter()
+                .find(|message| message.id() == Some(&id));
+            match response {
+                Some(JSONRPCMessage::Response(response)) => break response.result,
+                Some(JSONRPCMessage::Error(err)) => return Err(err.error.into()),
+                _ => debug!("Skipping a message received before the initialize result"),
+            }
+        };
+
+        let initialized = JSONRPCNotification::new("notifications/initialized".to_string(), None);
+        self.upstream
+            .send(&JSONRPCMessage::Notification(initialized))
+            .await?;
+        info!(
+            "Bridge connected to {}",
+            result
+                .pointer("/serverInfo/name")
+                .and_then(Value::as_str)
+                .unwrap_or("the upstream server")
+        );
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::Client,
+        schema::common::{Tool, ToolInputSchema},
+        server::{Server, ServerConfig},
+        transport::in_memory::InMemoryTransport,
+    };
+
+    #[tokio::test]
+    async fn test_bridge() -> Result<(), MCPError> {
+        let tool = Tool {
+            name: "echo".to_string(),
+            description: None,
+            input_schema: ToolInputSchema {
+                r#type: "object".to_string(),
+                properties: None,
+                required: None,
+                keywords: serde_json::Map::new(),
+            },
+            output_schema: None,
+            annotations: None,
+        };
+        let mut server = Server::new(ServerConfig::new().with_name("upstream").with_tool(tool));
+        server.register_tool_handler("echo", |params| async move { Ok(params) })?;
+        let sessions = server.clone();
+
+        let (upstream, server_end) = InMemoryTransport::pair();
+        tokio::spawn(async move { server.serve(server_end).await });
+        let mut bridge = Bridge::new(upstream);
+        assert_eq!(
+            bridge.connect().await?.pointer("/serverInfo/name"),
+            Some(&"upstream".into())
+        );
+
+        let (client_end, downstream) = InMemoryTransport::pair();
+        tokio::spawn(bridge.serve(downstream));
+        let mut client = Client::new(client_end);
+        let result = client.initialize().await?;
+        assert_eq!(result.server_info.name, "upstream");
+
+        let response: Value = client
+            .call_tool("echo", &serde_json::json!({ "x": 1 }))
+            .await?;
+        assert!(response["content"][0]["text"]
+            .as_str()
+            .unwrap()
+            .contains("\"x\": 1"));
+
+        // The server only ever saw the bridge's handshake
+        let session = &sessions.sessions().await[0];
+        assert_eq!(session.client_info.as_ref().unwrap().name, "mcpr-bridge");
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+//! Answering repeated read-only requests from memory
+//!
+//! A [`ResponseCache`] given to [`Client::with_response_cache`] keeps the
+//! answers to `tools/list`, `prompts/list`, `resources/list` and
+//! `resources/templates/list`, keyed by method and params, until the server
+//! sends the matching `list_changed` notification. Reads of resources the
+//! cache is told are immutable, such as versioned URIs, are kept too, until
+//! the server reports the resource updated. Every answer also expires after
+//! the cache's time to live, and can be dropped explicitly:
+//!
+//! ```rust,ignore
+//! let cache = ResponseCache::new(Duration::from_secs(300))
+//!     .with_immutable_uris(|uri| uri.contains("@sha256:"));
+//! let mut client = Client::new(transport).with_response_cache(cache.clone());
+//! let tools: ListToolsResult = client.list_tools().await?; // sent
+//! let tools: ListToolsResult = client.list_tools().await?; // answered by the cache
+//! cache.invalidate("tools/list");
+//! ```
+//!
+//! The client only reads notifications while it waits for a response, so a
+//! `list_changed` sent while every request is answered from the cache is
+//! noticed with the next request that goes to the server, or once the
+//! answer expires.
+//!
+//! [`Client::with_response_cache`]: crate::client::Client::with_response_cache
+
+use crate::{clock::Instant, schema::json_rpc::JSONRPCMessage};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// List methods whose answers are kept, with the notification invalidating them
+const LIST_METHODS: &[(&str, &str)] = &[
+    ("tools/list", "notifications/tools/list_changed"),
+    ("prompts/list", "notifications/prompts/list_changed"),
+    ("resources/list", "notifications/resources/list_changed"),
+    (
+        "resources/templates/list",
+        "notifications/resources/list_changed",
+    ),
+];
+
+/// Whether the resource at a URI never changes
+type UriPredicate = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// A cached answer
+struct Entry {
+    method: String,
+    /// The resource read, for `resources/read`
+    uri: Option<String>,
+    response: JSONRPCMessage,
+    stored: Instant,
+}
+
+/// Answers to read-only requests, see the [module docs](self)
+///
+/// Clones share the cached answers, so a clone kept by the caller can
+/// invalidate what the client caches.
+#[derive(Clone)]
+pub struct ResponseCache {
+    ttl: Duration,
+    immutable_uri: Option<UriPredicate>,
+    entries: Arc<Mutex<HashMap<String, Entry>>>,
+}
+
+impl fmt::Debug for ResponseCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseCache")
+            .field("ttl", &self.ttl)
+            .field("entries", &self.len())
+            .finish()
+    }
+}
+
+impl ResponseCache {
+    /// Keep list answers for at most `ttl`, and no resource reads
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            immutable_uri: None,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Also keep reads of the resources whose URI `immutable` accepts
+    pub fn with_immutable_uris<F>(mut self, immutable: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.immutable_uri = Some(Arc::new(immutable));
+        self
+    }
+
+    /// Drop the answers to `method`, such as `"tools/list"`
+    pub fn invalidate(&self, method: &str) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|_, entry| entry.method != method);
+    }
+
+    /// Drop the reads of the resource at `uri`
+    pub fn invalidate_uri(&self, uri: &str) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|_, entry| entry.uri.as_deref() != Some(uri));
+    }
+
+    /// Drop every answer
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// The number of answers kept, including expired ones not yet dropped
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Whether no answer is kept
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The key a request is cached under, if its answer may be kept
+    pub(crate) fn key(&self, method: &str, params: Option<&Value>) -> Option<String> {
+        let cacheable = match method {
+            "resources/read" => params
+                .and_then(|params| params.get("uri"))
+                .and_then(Value::as_str)
+                .zip(self.immutable_uri.as_ref())
+                .is_some_and(|(uri, immutable)| immutable(uri)),
+            method => LIST_METHODS.iter().any(|(list, _)| *list == method),
+        };
+        cacheable.then(|| format!("{} {}", method, params.unwrap_or(&Value::Null)))
+    }
+
+    /// The answer kept under `key`, unless it expired by `now`
+    pub(crate) fn get(&self, key: &str, now: Instant) -> Option<JSONRPCMessage> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get(key)?;
+        if now.saturating_duration_since(entry.stored) >= self.ttl {
+            entries.remove(key);
+            return None;
+        }
+        Some(entry.response.clone())
+    }
+
+    /// Keep a successful answer under `key`
+    pub(crate) fn insert(
+        &self,
+        key: String,
+        method: &str,
+        params: Option<&Value>,
+        response: &JSONRPCMessage,
+        now: Instant,
+    ) {
+        if !matches!(response, JSONRPCMessage::Response(_)) {
+            return;
+        }
+        let uri = params
+            .and_then(|params| params.get("uri"))
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        let entry = Entry {
+            method: method.to_string(),
+            uri,
+            response: response.clone(),
+            stored: now,
+        };
+        self.entries.lock().unwrap().insert(key, entry);
+    }
+
+    /// Drop the answers a notification from the server makes stale
+    pub(crate) fn notified(&self, method: &str, params: &Value) {
+        if method == "notifications/resources/updated" {
+            if let Some(uri) = params.get("uri").and_then(Value::as_str) {
+                self.invalidate_uri(uri);
+            }
+            return;
+        }
+        for (list, _) in LIST_METHODS
+            .iter()
+            .filter(|(_, notification)| *notification == method)
+        {
+            self.invalidate(list);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::json_rpc::{JSONRPCResponse, RequestId};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_response_cache() {
+        let cache = ResponseCache::new(Duration::from_secs(60))
+            .with_immutable_uris(|uri| uri.starts_with("pkg://v1/"));
+        let response = JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(1),
+            json!({ "tools": [] }),
+        ));
+        let now = Instant::now();
+
+        // Lists are cached until they change, immutable reads until updated
+        let key = cache.key("tools/list", None).unwrap();
+        cache.insert(key.clone(), "tools/list", None, &response, now);
+        assert!(cache.get(&key, now).is_some());
+        cache.notified("notifications/tools/list_changed", &Value::Null);
+        assert!(cache.get(&key, now).is_none());
+
+        let read = json!({ "uri": "pkg://v1/readme" });
+        let key = cache.key("resources/read", Some(&read)).unwrap();
+        cache.insert(key.clone(), "resources/read", Some(&read), &response, now);
+        assert!(cache.get(&key, now).is_some());
+        assert!(cache.get(&key, now + Duration::from_secs(60)).is_none());
+        assert!(cache.is_empty());
+
+        // Mutable reads and other methods are not cached
+        assert!(cache
+            .key("resources/read", Some(&json!({ "uri": "file:///a" })))
+            .is_none());
+        assert!(cache.key("tools/call", None).is_none());
+    }
+}
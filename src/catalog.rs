@@ -0,0 +1,582 @@
+//! Compare the tools a server offers across versions
+//!
+//! A [`ToolCatalog`] holds a server's tools by name. [`ToolCatalog::diff`]
+//! lists what changed between two catalogs, and flags changes that break
+//! callers of the old tools, such as a removed tool or a new required
+//! argument:
+//!
+//! ```rust,ignore
+//! use mcpr::catalog::ToolCatalog;
+//!
+//! let old = ToolCatalog::from(cached_tools);
+//! let new = ToolCatalog::from(client.list_tools::<ListToolsResult>().await?);
+//! let diff = ToolCatalog::diff(&old, &new);
+//! if diff.is_breaking() {
+//!     println!("{}", diff);
+//! }
+//! ```
+//!
+//! A [`LiveCatalog`], from [`Client::tool_catalog`] and its siblings for
+//! prompts and resources, keeps a server's list up to date instead: it is
+//! fetched on first use, and fetched again whenever the server sends a
+//! `list_changed` notification, with the changes published as
+//! [`CatalogEvent`]s.
+
+use crate::{
+    client::Client,
+    error::MCPError,
+    schema::{
+        client::ListToolsResult,
+        common::{Prompt, Resource, Tool},
+    },
+    transport::Transport,
+};
+use futures::{stream, Stream};
+use log::{debug, warn};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::{broadcast, Mutex as TokioMutex, Notify};
+
+/// The tools a server offers, by name
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ToolCatalog {
+    tools: BTreeMap<String, Tool>,
+}
+
+impl ToolCatalog {
+    /// Create a catalog; a tool listed twice keeps its last definition
+    pub fn new(tools: impl IntoIterator<Item = Tool>) -> Self {
+        Self {
+            tools: tools
+                .into_iter()
+                .map(|tool| (tool.name.clone(), tool))
+                .collect(),
+        }
+    }
+
+    /// Look up a tool by name
+    pub fn get(&self, name: &str) -> Option<&Tool> {
+        self.tools.get(name)
+    }
+
+    /// The tools, ordered by name
+    pub fn tools(&self) -> impl Iterator<Item = &Tool> {
+        self.tools.values()
+    }
+
+    /// Number of tools
+    pub fn len(&self) -> usize {
+        self.tools.len()
+    }
+
+    /// Whether the catalog has no tools
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// List the differences between an old and a new catalog
+    pub fn diff(old: &ToolCatalog, new: &ToolCatalog) -> CatalogDiff {
+        let mut diff = CatalogDiff::default();
+
+        for (name, old_tool) in &old.tools {
+            match new.tools.get(name) {
+                None => diff.removed.push(old_tool.clone()),
+                Some(new_tool) if new_tool != old_tool => {
+                    diff.changed.push(ToolChange::new(old_tool, new_tool))
+                }
+                Some(_) => {}
+            }
+        }
+        diff.added = new
+            .tools
+            .iter()
+            .filter(|(name, _)| !old.tools.contains_key(*name))
+            .map(|(_, tool)| tool.clone())
+            .collect();
+
+        diff
+    }
+}
+
+impl From<Vec<Tool>> for ToolCatalog {
+    fn from(tools: Vec<Tool>) -> Self {
+        Self::new(tools)
+    }
+}
+
+impl From<ListToolsResult> for ToolCatalog {
+    fn from(result: ListToolsResult) -> Self {
+        Self::new(result.tools)
+    }
+}
+
+/// Differences between two tool catalogs, with tools ordered by name
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CatalogDiff {
+    /// Tools only in the new catalog
+    pub added: Vec<Tool>,
+    /// Tools only in the old catalog
+    pub removed: Vec<Tool>,
+    /// Tools in both catalogs whose definition changed
+    pub changed: Vec<ToolChange>,
+}
+
+impl CatalogDiff {
+    /// Whether the catalogs are the same
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Whether a call that worked with the old catalog may fail with the new one
+    pub fn is_breaking(&self) -> bool {
+        !self.removed.is_empty() || self.changed.iter().any(ToolChange::is_breaking)
+    }
+}
+
+impl fmt::Display for CatalogDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for tool in &self.added {
+            writeln!(f, "+ {}", tool.name)?;
+        }
+        for tool in &self.removed {
+            writeln!(f, "- {} (breaking)", tool.name)?;
+        }
+        for change in &self.changed {
+            writeln!(f, "~ {}", change.name)?;
+            if change.description_changed {
+                writeln!(f, "    description changed")?;
+            }
+            for incompatibility in &change.incompatibilities {
+                writeln!(f, "    {} (breaking)", incompatibility)?;
+            }
+            if change.schema_changed && change.incompatibilities.is_empty() {
+                writeln!(f, "    input schema changed")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a tool's definition changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolChange {
+    /// Name of the tool
+    pub name: String,
+    /// The old definition
+    pub old: Tool,
+    /// The new definition
+    pub new: Tool,
+    /// Whether the description changed
+    pub description_changed: bool,
+    /// Whether the input schema changed
+    pub schema_changed: bool,
+    /// Schema changes that can make old calls fail, described for people
+    pub incompatibilities: Vec<String>,
+}
+
+impl ToolChange {
+    fn new(old: &Tool, new: &Tool) -> Self {
+        Self {
+            name: new.name.clone(),
+            old: old.clone(),
+            new: new.clone(),
+            description_changed: old.description != new.description,
+            schema_changed: old.input_schema != new.input_schema,
+            incompatibilities: incompatibilities(old, new),
+        }
+    }
+
+    /// Whether a call that worked with the old definition may fail with the new one
+    pub fn is_breaking(&self) -> bool {
+        !self.incompatibilities.is_empty()
+    }
+}
+
+/// Find the input schema changes that can make calls valid for `old` invalid for `new`
+///
+/// Arguments that are removed, newly required or change type are reported;
+/// new optional arguments and description changes are not.
+fn incompatibilities(old: &Tool, new: &Tool) -> Vec<String> {
+    let old_schema = &old.input_schema;
+    let new_schema = &new.input_schema;
+    let old_properties = old_schema.properties.clone().unwrap_or_default();
+    let new_properties = new_schema.properties.clone().unwrap_or_default();
+    let old_required: HashSet<&String> = old_schema.required.iter().flatten().collect();
+
+    let mut found = Vec::new();
+    if old_schema.r#type != new_schema.r#type {
+        found.push(format!(
+            "input type changed from '{}' to '{}'",
+            old_schema.r#type, new_schema.r#type
+        ));
+    }
+
+    let mut names: Vec<&String> = old_properties.keys().collect();
+    names.sort();
+    for name in names {
+        match new_properties.get(name) {
+            None => found.push(format!("argument '{}' removed", name)),
+            Some(new_property) => {
+                let old_type = old_properties[name].get("type");
+                let new_type = new_property.get("type");
+                if old_type != new_type {
+                    found.push(format!(
+                        "argument '{}' changed type from {} to {}",
+                        name,
+                        old_type.unwrap_or(&Value::Null),
+                        new_type.unwrap_or(&Value::Null)
+                    ));
+                }
+            }
+        }
+    }
+
+    for name in new_schema.required.iter().flatten() {
+        if !old_required.contains(name) {
+            found.push(format!("argument '{}' is now required", name));
+        }
+    }
+    found
+}
+
+/// Something a server lists, kept up to date by a [`LiveCatalog`]
+pub trait CatalogEntry: Clone + PartialEq + DeserializeOwned + Send + Sync + 'static {
+    /// Method listing the entries
+    const LIST_METHOD: &'static str;
+    /// Field of the list result holding the entries
+    const LIST_FIELD: &'static str;
+    /// Notification the server sends when the list changed
+    const LIST_CHANGED: &'static str;
+
+    /// What the entry is looked up by
+    fn key(&self) -> &str;
+}
+
+impl CatalogEntry for Tool {
+    const LIST_METHOD: &'static str = "tools/list";
+    const LIST_FIELD: &'static str = "tools";
+    const LIST_CHANGED: &'static str = "notifications/tools/list_changed";
+
+    fn key(&self) -> &str {
+        &self.name
+    }
+}
+
+impl CatalogEntry for Prompt {
+    const LIST_METHOD: &'static str = "prompts/list";
+    const LIST_FIELD: &'static str = "prompts";
+    const LIST_CHANGED: &'static str = "notifications/prompts/list_changed";
+
+    fn key(&self) -> &str {
+        &self.name
+    }
+}
+
+impl CatalogEntry for Resource {
+    const LIST_METHOD: &'static str = "resources/list";
+    const LIST_FIELD: &'static str = "resources";
+    const LIST_CHANGED: &'static str = "notifications/resources/list_changed";
+
+    fn key(&self) -> &str {
+        &self.uri
+    }
+}
+
+/// A change to the entries of a [`LiveCatalog`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum CatalogEvent<E> {
+    /// The server added an entry
+    Added(E),
+    /// The server removed an entry
+    Removed(E),
+    /// The server changed an entry
+    Changed {
+        /// The previous definition
+        old: E,
+        /// The new definition
+        new: E,
+    },
+}
+
+/// What the clones of a [`LiveCatalog`] share
+struct SharedCatalog<T: Transport + Send + Sync, E> {
+    client: TokioMutex<Client<T>>,
+    /// Entries by key, or `None` until fetched and after a failed refresh
+    entries: Mutex<Option<BTreeMap<String, E>>>,
+    events: broadcast::Sender<CatalogEvent<E>>,
+}
+
+/// A server's tools, prompts or resources, refreshed when the server changes them
+///
+/// Entries are fetched on first use. When the server sends the entries'
+/// `list_changed` notification, they are fetched again in the background,
+/// and the differences published to [`LiveCatalog::changes`]. Like other
+/// notifications, it is read while a request is in flight on the client or
+/// one of its clones. Clones share the entries.
+pub struct LiveCatalog<T: Transport + Send + Sync, E> {
+    shared: Arc<SharedCatalog<T, E>>,
+}
+
+impl<T: Transport + Send + Sync, E> Clone for LiveCatalog<T, E> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T, E> LiveCatalog<T, E>
+where
+    T: Transport + Clone + Send + Sync + 'static,
+    E: CatalogEntry,
+{
+    /// Keep the entries of the server `client` is connected to
+    pub fn new(client: &Client<T>) -> Self {
+        let mut client = client.clone();
+        let changed = Arc::new(Notify::new());
+        let notify = changed.clone();
+        client.on_notification(E::LIST_CHANGED, move |_| notify.notify_one());
+
+        let catalog = Self {
+            shared: Arc::new(SharedCatalog {
+                client: TokioMutex::new(client),
+                entries: Mutex::new(None),
+                events: broadcast::channel(64).0,
+            }),
+        };
+        let weak = Arc::downgrade(&catalog.shared);
+        crate::rt::spawn(async move {
+            loop {
+                changed.notified().await;
+                let Some(shared) = weak.upgrade() else {
+                    return;
+                };
+                debug!("{} changed, refreshing", E::LIST_METHOD);
+                let catalog = Self { shared };
+                if let Err(e) = catalog.refresh().await {
+                    warn!("Failed to refresh {}: {}", E::LIST_METHOD, e);
+                    catalog.shared.entries.lock().unwrap().take();
+                }
+            }
+        });
+        catalog
+    }
+
+    /// Look up an entry: a tool or prompt by name, a resource by URI
+    pub async fn get(&self, key: &str) -> Result<Option<E>, MCPError> {
+        self.load().await?;
+        Ok(self
+            .shared
+            .entries
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|entries| entries.get(key).cloned()))
+    }
+
+    /// All entries, ordered by key
+    pub async fn entries(&self) -> Result<Vec<E>, MCPError> {
+        self.load().await?;
+        Ok(self
+            .shared
+            .entries
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|entries| entries.values().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    /// Changes to the entries from now on, found when they are refreshed
+    ///
+    /// The first fetch publishes nothing. The stream ends when the catalog
+    /// and its clones are dropped.
+    pub fn changes(&self) -> impl Stream<Item = CatalogEvent<E>> + Send + 'static {
+        stream::unfold(self.shared.events.subscribe(), |mut events| async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => return Some((event, events)),
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("Missed {} catalog changes", missed)
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+
+    /// Fetch the entries from the server now, publishing what changed
+    pub async fn refresh(&self) -> Result<(), MCPError> {
+        let fetched = self.fetch().await?;
+        let previous = self.shared.entries.lock().unwrap().replace(fetched.clone());
+        let Some(previous) = previous else {
+            return Ok(());
+        };
+
+        for (key, old) in &previous {
+            match fetched.get(key) {
+                None => self.publish(CatalogEvent::Removed(old.clone())),
+                Some(new) if new != old => self.publish(CatalogEvent::Changed {
+                    old: old.clone(),
+                    new: new.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+        for (key, new) in &fetched {
+            if !previous.contains_key(key) {
+                self.publish(CatalogEvent::Added(new.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fetch the entries unless they are known
+    async fn load(&self) -> Result<(), MCPError> {
+        if self.shared.entries.lock().unwrap().is_none() {
+            self.refresh().await?;
+        }
+        Ok(())
+    }
+
+    /// Fetch every page of the entries
+    async fn fetch(&self) -> Result<BTreeMap<String, E>, MCPError> {
+        let mut client = self.shared.client.lock().await;
+        let mut entries = BTreeMap::new();
+        let mut cursor: Option<Value> = None;
+        loop {
+            let params = cursor.map(|cursor| serde_json::json!({ "cursor": cursor }));
+            let mut page: Value = client.request(E::LIST_METHOD, params).await?;
+            let items: Vec<E> = serde_json::from_value(page[E::LIST_FIELD].take())?;
+            entries.extend(items.into_iter().map(|e| (e.key().to_string(), e)));
+            match page.get_mut("nextCursor").map(Value::take) {
+                Some(next) if !next.is_null() => cursor = Some(next),
+                _ => return Ok(entries),
+            }
+        }
+    }
+
+    fn publish(&self, event: CatalogEvent<E>) {
+        // Nobody may be listening
+        let _ = self.shared.events.send(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::common::ToolInputSchema;
+    use std::collections::HashMap;
+
+    fn tool(name: &str, description: &str, properties: Value, required: &[&str]) -> Tool {
+        Tool {
+            name: name.to_string(),
+            description: Some(description.to_string()),
+            input_schema: ToolInputSchema {
+                r#type: "object".to_string(),
+                properties: serde_json::from_value::<HashMap<String, Value>>(properties).ok(),
+                required: Some(required.iter().map(|s| s.to_string()).collect()),
+                keywords: serde_json::Map::new(),
+            },
+            output_schema: None,
+            annotations: None,
+        }
+    }
+
+    #[test]
+    fn test_catalog_diff() {
+        let old = ToolCatalog::new(vec![
+            tool("echo", "Echo", serde_json::json!({}), &[]),
+            tool(
+                "search",
+                "Search",
+                serde_json::json!({ "query": { "type": "string" }, "limit": { "type": "integer" } }),
+                &["query"],
+            ),
+            tool("legacy", "Old", serde_json::json!({}), &[]),
+        ]);
+        let new = ToolCatalog::new(vec![
+            tool("echo", "Echo back", serde_json::json!({}), &[]),
+            tool(
+                "search",
+                "Search",
+                serde_json::json!({ "query": { "type": "array" }, "page": { "type": "integer" } }),
+                &["query", "page"],
+            ),
+            tool("summarize", "Summarize", serde_json::json!({}), &[]),
+        ]);
+
+        assert!(ToolCatalog::diff(&old, &old).is_empty());
+
+        let diff = ToolCatalog::diff(&old, &new);
+        assert_eq!(diff.added[0].name, "summarize");
+        assert_eq!(diff.removed[0].name, "legacy");
+        assert!(diff.is_breaking());
+
+        // A new description alone does not break callers
+        let echo = &diff.changed[0];
+        assert_eq!(echo.name, "echo");
+        assert!(echo.description_changed && !echo.schema_changed && !echo.is_breaking());
+
+        let search = &diff.changed[1];
+        assert_eq!(
+            search.incompatibilities,
+            vec![
+                "argument 'limit' removed",
+                "argument 'query' changed type from \"string\" to \"array\"",
+                "argument 'page' is now required",
+            ]
+        );
+        assert!(diff.to_string().contains("- legacy (breaking)"));
+    }
+
+    #[tokio::test]
+    async fn test_live_catalog() -> Result<(), MCPError> {
+        use crate::{
+            schema::client::{ReadResourceParams, ResourceContent},
+            server::{Server, ServerConfig},
+            transport::in_memory::InMemoryTransport,
+        };
+        use futures::StreamExt;
+
+        let resource = |uri: &str| Resource {
+            uri: uri.to_string(),
+            name: uri.to_string(),
+            description: None,
+            mime_type: None,
+            size: None,
+            annotations: None,
+        };
+        let unused = |_: ReadResourceParams| async {
+            Err::<ResourceContent, _>(MCPError::Protocol("unused".to_string()))
+        };
+        let mut server = Server::new(ServerConfig::new());
+        let resources = server.resources_handle();
+        resources.add(resource("memo://1"), unused).await?;
+        let (client_end, server_end) = InMemoryTransport::pair();
+        tokio::spawn(async move { server.serve(server_end).await });
+        let mut client = Client::new(client_end);
+        client.initialize().await?;
+
+        let catalog = client.resource_catalog();
+        assert!(catalog.get("memo://1").await?.is_some());
+        assert!(catalog.get("memo://2").await?.is_none());
+
+        // The notification is read during the next request, then the
+        // catalog refreshes in the background
+        let mut changes = Box::pin(catalog.changes());
+        resources.add(resource("memo://2"), unused).await?;
+        client.ping().await?;
+        let event = tokio::time::timeout(std::time::Duration::from_secs(5), changes.next())
+            .await
+            .unwrap();
+        assert_eq!(event, Some(CatalogEvent::Added(resource("memo://2"))));
+        assert_eq!(catalog.entries().await?.len(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+//! Line input with Tab completion and history for the shell
+//!
+//! On a unix terminal, input is read a key at a time so that Tab completes
+//! and the arrow keys walk the history. Elsewhere, and when stdin is not a
+//! terminal, whole lines are read as they are.
+
+use std::io::{self, BufRead, Read, Write};
+
+const TAB: u8 = b'\t';
+const ENTER: u8 = b'\r';
+const NEWLINE: u8 = b'\n';
+const ESCAPE: u8 = 0x1b;
+const BACKSPACE: u8 = 0x7f;
+const CTRL_H: u8 = 0x08;
+const CTRL_D: u8 = 0x04;
+
+/// Reads lines, remembering them for the history
+pub(crate) struct LineEditor {
+    history: Vec<String>,
+}
+
+impl LineEditor {
+    pub(crate) fn new() -> Self {
+        Self {
+            history: Vec::new(),
+        }
+    }
+
+    /// Read a line after showing `prompt`, or `None` at end of input
+    ///
+    /// `complete` returns the completions of the last word of a line.
+    pub(crate) fn read_line(
+        &mut self,
+        prompt: &str,
+        complete: impl Fn(&str) -> Vec<String>,
+    ) -> io::Result<Option<String>> {
+        let mut stdout = io::stdout();
+        write!(stdout, "{}", prompt)?;
+        stdout.flush()?;
+
+        #[cfg(unix)]
+        if let Some(_raw) = raw::RawMode::enable() {
+            let line = self.edit(prompt, complete)?;
+            writeln!(stdout)?;
+            if let Some(line) = &line {
+                if !line.trim().is_empty() && self.history.last() != Some(line) {
+                    self.history.push(line.clone());
+                }
+            }
+            return Ok(line);
+        }
+
+        let _ = complete;
+        let mut line = String::new();
+        if io::stdin().lock().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+    }
+
+    /// Edit a line key by key, with the terminal in raw mode
+    #[cfg(unix)]
+    fn edit(
+        &mut self,
+        prompt: &str,
+        complete: impl Fn(&str) -> Vec<String>,
+    ) -> io::Result<Option<String>> {
+        let mut stdin = io::stdin().lock();
+        let mut stdout = io::stdout();
+        let mut line = String::new();
+        let mut pending = Vec::new();
+        let mut position = self.history.len();
+
+        loop {
+            let mut byte = [0u8];
+            if stdin.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            match byte[0] {
+                ENTER | NEWLINE => return Ok(Some(line)),
+                CTRL_D if line.is_empty() => return Ok(None),
+                BACKSPACE | CTRL_H => {
+                    line.pop();
+                }
+                TAB => {
+                    let candidates = complete(&line);
+                    let partial =
+                        line.len() - line.trim_end_matches(|c: char| !c.is_whitespace()).len();
+                    let prefix = common_prefix(&candidates);
+                    if candidates.len() == 1 {
+                        line.push_str(&candidates[0][partial..]);
+                        line.push(' ');
+                    } else if prefix.len() > partial {
+                        line.push_str(&prefix[partial..]);
+                    } else if !candidates.is_empty() {
+                        write!(stdout, "\r\n{}\r\n", candidates.join("  "))?;
+                    }
+                }
+                ESCAPE => {
+                    let mut sequence = [0u8; 2];
+                    stdin.read_exact(&mut sequence)?;
+                    match sequence {
+                        [b'[', b'A'] if position > 0 => position -= 1,
+                        [b'[', b'B'] if position < self.history.len() => position += 1,
+                        _ => continue,
+                    }
+                    line = self.history.get(position).cloned().unwrap_or_default();
+                }
+                byte if byte >= 0x20 => {
+                    pending.push(byte);
+                    match std::str::from_utf8(&pending) {
+                        Ok(text) => {
+                            line.push_str(text);
+                            pending.clear();
+                        }
+                        Err(e) if e.error_len().is_some() => pending.clear(),
+                        Err(_) => continue,
+                    }
+                }
+                _ => continue,
+            }
+            write!(stdout, "\r\x1b[K{}{}", prompt, line)?;
+            stdout.flush()?;
+        }
+    }
+}
+
+/// The longest prefix shared by all `candidates`
+fn common_prefix(candidates: &[String]) -> &str {
+    let Some(first) = candidates.first() else {
+        return "";
+    };
+    let mut len = first.len();
+    for candidate in &candidates[1..] {
+        len = first
+            .char_indices()
+            .zip(candidate.chars())
+            .take_while(|((_, a), b)| a == b)
+            .map(|((i, a), _)| i + a.len_utf8())
+            .last()
+            .unwrap_or(0)
+            .min(len);
+    }
+    &first[..len]
+}
+
+#[cfg(unix)]
+mod raw {
+    /// Keeps the terminal in non-canonical mode without echo until dropped
+    pub(super) struct RawMode(libc::termios);
+
+    impl RawMode {
+        /// Switch stdin to raw mode, if it is a terminal
+        pub(super) fn enable() -> Option<Self> {
+            // SAFETY: the termios structs are plain data filled in by the
+            // calls, which only read and write the given pointers
+            unsafe {
+                if libc::isatty(libc::STDIN_FILENO) != 1 {
+                    return None;
+                }
+                let mut termios: libc::termios = std::mem::zeroed();
+                if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 {
+                    return None;
+                }
+                let original = termios;
+                termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+                termios.c_cc[libc::VMIN] = 1;
+                termios.c_cc[libc::VTIME] = 0;
+                if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) != 0 {
+                    return None;
+                }
+                Some(Self(original))
+            }
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            // SAFETY: restores the settings read in `enable`
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.0);
+            }
+        }
+    }
+}
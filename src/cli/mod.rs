@@ -3,6 +3,9 @@
 // This module will contain CLI functionality for generating server and client stubs
 // and for running MCP servers and clients.
 
+mod line_editor;
+pub mod repl;
+
 /// CLI command definitions
 pub mod commands {
     // To be implemented
@@ -0,0 +1,349 @@
+//! Interactive shell for exploring the tools and resources of a server
+//!
+//! A [`Repl`] reads commands such as `tools call echo {"message": "hi"}`
+//! from the terminal, runs them with a [`Client`] and pretty-prints the
+//! results. Tool names and commands complete with Tab.
+
+use crate::{
+    client::Client,
+    error::MCPError,
+    schema::{
+        client::ListToolsResult,
+        server::{CallToolResult, RenderOptions},
+    },
+    transport::Transport,
+};
+use serde_json::Value;
+use std::io::Write;
+
+use super::line_editor::LineEditor;
+
+const HELP: &str = "\
+Commands:
+  tools list                    List the tools of the server
+  tools call <name> [json]      Call a tool, with its arguments as a JSON object
+  resources list                List the resources of the server
+  resources read <uri>          Read a resource
+  help                          Show this help
+  quit                          Leave the shell";
+
+/// A command of the shell
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// `tools list`
+    ListTools,
+    /// `tools call <name> [json]`
+    CallTool {
+        /// Name of the tool
+        name: String,
+        /// Arguments of the call, an empty object when none are given
+        arguments: Value,
+    },
+    /// `resources list`
+    ListResources,
+    /// `resources read <uri>`
+    ReadResource {
+        /// URI of the resource
+        uri: String,
+    },
+    /// `help`
+    Help,
+    /// `quit` or `exit`
+    Quit,
+}
+
+impl Command {
+    /// Parse a line, or return `None` for a blank one
+    ///
+    /// Errors are messages for the user, such as a usage line.
+    pub fn parse(line: &str) -> Result<Option<Self>, String> {
+        let line = line.trim();
+        let (word, rest) = split_word(line);
+        let (sub, rest) = split_word(rest);
+        let command = match (word, sub) {
+            ("", _) => return Ok(None),
+            ("help" | "?", _) => Command::Help,
+            ("quit" | "exit", _) => Command::Quit,
+            ("tools", "list") => Command::ListTools,
+            ("tools", "call") => {
+                let (name, arguments) = split_word(rest);
+                if name.is_empty() {
+                    return Err("Usage: tools call <name> [json]".to_string());
+                }
+                let arguments = if arguments.is_empty() {
+                    Value::Object(Default::default())
+                } else {
+                    serde_json::from_str(arguments)
+                        .map_err(|e| format!("Invalid JSON arguments: {}", e))?
+                };
+                Command::CallTool {
+                    name: name.to_string(),
+                    arguments,
+                }
+            }
+            ("resources", "list") => Command::ListResources,
+            ("resources", "read") if !rest.is_empty() => Command::ReadResource {
+                uri: rest.to_string(),
+            },
+            ("resources", "read") => return Err("Usage: resources read <uri>".to_string()),
+            _ => return Err(format!("Unknown command '{}', try 'help'", line)),
+        };
+        Ok(Some(command))
+    }
+}
+
+/// Split off the first whitespace-separated word
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    match text.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim_start()),
+        None => (text, ""),
+    }
+}
+
+/// Completions of the last word of `line`, given the tool names of the server
+///
+/// Commands complete at the start of the line, and tool names after
+/// `tools call`.
+pub fn complete(line: &str, tool_names: &[String]) -> Vec<String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (done, partial) = if line.is_empty() || line.ends_with(char::is_whitespace) {
+        (&words[..], "")
+    } else {
+        (&words[..words.len() - 1], words[words.len() - 1])
+    };
+
+    let candidates: Vec<&str> = match done {
+        [] => vec!["tools", "resources", "help", "quit"],
+        ["tools"] => vec!["list", "call"],
+        ["resources"] => vec!["list", "read"],
+        ["tools", "call"] => tool_names.iter().map(String::as_str).collect(),
+        _ => Vec::new(),
+    };
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(partial))
+        .map(str::to_string)
+        .collect()
+}
+
+/// An interactive shell over a connected client
+pub struct Repl<T: Transport> {
+    client: Client<T>,
+    tool_names: Vec<String>,
+    options: RenderOptions,
+}
+
+impl<T: Transport + Send + Sync> Repl<T> {
+    /// Initialize `client` and fetch the tool names for completion
+    pub async fn connect(mut client: Client<T>) -> Result<Self, MCPError> {
+        client.initialize().await?;
+        let mut repl = Self {
+            client,
+            tool_names: Vec::new(),
+            options: RenderOptions::new(),
+        };
+        repl.refresh_tools().await?;
+        Ok(repl)
+    }
+
+    /// Render results with these options
+    pub fn with_render_options(mut self, options: RenderOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Names of the tools of the server, as of the last `tools list`
+    pub fn tool_names(&self) -> &[String] {
+        &self.tool_names
+    }
+
+    async fn refresh_tools(&mut self) -> Result<ListToolsResult, MCPError> {
+        let tools: ListToolsResult = self.client.list_tools().await?;
+        self.tool_names = tools.tools.iter().map(|tool| tool.name.clone()).collect();
+        Ok(tools)
+    }
+
+    /// Run a command, writing its output to `out`
+    ///
+    /// Returns `false` once the shell should stop.
+    pub async fn execute(
+        &mut self,
+        command: Command,
+        out: &mut impl Write,
+    ) -> Result<bool, MCPError> {
+        match command {
+            Command::ListTools => {
+                let tools = self.refresh_tools().await?;
+                let width = self.tool_names.iter().map(String::len).max().unwrap_or(0);
+                for tool in &tools.tools {
+                    let description = tool.description.as_deref().unwrap_or("");
+                    writeln!(out, "{:width$}  {}", tool.name, description).map_err(io_error)?;
+                }
+            }
+            Command::CallTool { name, arguments } => {
+                let result: CallToolResult = self.client.call_tool(&name, &arguments).await?;
+                writeln!(out, "{}", result.render_cli(&self.options)).map_err(io_error)?;
+            }
+            Command::ListResources => {
+                let resources = self.client.list_resources().await?;
+                let width = resources.iter().map(|r| r.uri.len()).max().unwrap_or(0);
+                for resource in &resources {
+                    writeln!(out, "{:width$}  {}", resource.uri, resource.name)
+                        .map_err(io_error)?;
+                }
+            }
+            Command::ReadResource { uri } => {
+                let result = self.client.read_resource(&uri, None).await?;
+                for contents in &result.contents {
+                    writeln!(out, "{}", contents.to_transcript()).map_err(io_error)?;
+                }
+            }
+            Command::Help => writeln!(out, "{}", HELP).map_err(io_error)?,
+            Command::Quit => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Read and run commands from the terminal until `quit` or end of input,
+    /// then shut the client down
+    ///
+    /// Failed commands are reported and the shell carries on.
+    pub async fn run(mut self) -> Result<(), MCPError> {
+        let mut editor = Some(LineEditor::new());
+        loop {
+            let mut line_editor = editor.take().unwrap_or_else(LineEditor::new);
+            let tool_names = self.tool_names.clone();
+            let (line_editor, line) = tokio::task::spawn_blocking(move || {
+                let line = line_editor.read_line("mcp> ", |line| complete(line, &tool_names));
+                (line_editor, line)
+            })
+            .await
+            .map_err(|e| MCPError::Transport(format!("Failed to read input: {}", e)))?;
+            editor = Some(line_editor);
+
+            let line = match line.map_err(io_error)? {
+                Some(line) => line,
+                None => break,
+            };
+            let command = match Command::parse(&line) {
+                Ok(Some(command)) => command,
+                Ok(None) => continue,
+                Err(message) => {
+                    eprintln!("{}", message);
+                    continue;
+                }
+            };
+
+            let mut stdout = std::io::stdout();
+            match self.execute(command, &mut stdout).await {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => eprintln!("Error: {}", e),
+            }
+        }
+        self.shutdown().await
+    }
+
+    /// Shut the client down
+    pub async fn shutdown(mut self) -> Result<(), MCPError> {
+        self.client.shutdown().await
+    }
+}
+
+fn io_error(e: std::io::Error) -> MCPError {
+    MCPError::Transport(format!("Failed to write output: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        schema::{
+            client::ResourceContent,
+            common::{Resource, TextContent, TextResourceContents, Tool, ToolInputSchema},
+            server::ToolResultContent,
+        },
+        testing::MockServer,
+    };
+
+    #[test]
+    fn test_parse_and_complete() {
+        assert_eq!(Command::parse("  "), Ok(None));
+        assert_eq!(
+            Command::parse(r#"tools call echo {"message": "hi there"}"#),
+            Ok(Some(Command::CallTool {
+                name: "echo".to_string(),
+                arguments: serde_json::json!({ "message": "hi there" }),
+            }))
+        );
+        assert!(Command::parse("tools call").is_err());
+        assert!(Command::parse("tools call echo {").is_err());
+
+        let names = vec!["echo".to_string(), "eval".to_string(), "list".to_string()];
+        assert_eq!(complete("", &names), ["tools", "resources", "help", "quit"]);
+        assert_eq!(complete("to", &names), ["tools"]);
+        assert_eq!(complete("tools c", &names), ["call"]);
+        assert_eq!(complete("tools call e", &names), ["echo", "eval"]);
+        assert!(complete("tools call echo {", &names).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_execute() -> Result<(), MCPError> {
+        let tool = Tool {
+            name: "echo".to_string(),
+            description: Some("Echo a message".to_string()),
+            input_schema: ToolInputSchema {
+                r#type: "object".to_string(),
+                properties: None,
+                required: None,
+                keywords: serde_json::Map::new(),
+            },
+            output_schema: None,
+            annotations: None,
+        };
+        let result = CallToolResult {
+            content: vec![ToolResultContent::Text(TextContent {
+                r#type: "text".to_string(),
+                text: "hi".to_string(),
+                annotations: None,
+            })],
+            structured_content: None,
+            is_error: None,
+        };
+        let resource = Resource {
+            uri: "file:///notes.txt".to_string(),
+            name: "notes".to_string(),
+            description: None,
+            mime_type: None,
+            size: None,
+            annotations: None,
+        };
+        let contents = ResourceContent::Text(TextResourceContents {
+            uri: "file:///notes.txt".to_string(),
+            mime_type: None,
+            text: "remember the milk".to_string(),
+        });
+        let server = MockServer::new()
+            .with_tool(tool, result)
+            .with_resource(resource, contents);
+        let mut repl = Repl::connect(Client::new(server.transport())).await?;
+        assert_eq!(repl.tool_names(), ["echo"]);
+
+        let mut out = Vec::new();
+        for line in [
+            "tools list",
+            "tools call echo",
+            "resources read file:///notes.txt",
+        ] {
+            let command = Command::parse(line).unwrap().unwrap();
+            assert!(repl.execute(command, &mut out).await?);
+        }
+        assert!(!repl.execute(Command::Quit, &mut out).await?);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "echo  Echo a message\nhi\n[resource file:///notes.txt]\nremember the milk\n"
+        );
+        Ok(())
+    }
+}
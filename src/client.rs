@@ -44,607 +44,7798 @@
 //! - Timeouts for operations
 //! - Concurrent tool calls
 //! - Simplified session execution
+//! - Reconnection with jittered exponential backoff
+//! - One-call discovery of everything a server offers
+//! - Waiting until a server's tools are ready, instead of sleeping
+//! - A builder for tool call arguments assembled at runtime
+//! - Diagnostics for outstanding requests, with a warning for slow ones
+//! - Automatic retries for rate-limited tool calls
+//! - An end-of-session summary from [`Client::close`]
+//! - Handlers for requests initiated by the server, with progress reporting
+//! - Cancellation of requests with a reason, and a configurable policy for
+//!   late responses to cancelled requests
+//! - Chunked uploads of tool arguments too large for the server's message limit
+//! - Coalescing of identical concurrent read-only requests (single-flight)
+//! - Cached answers to list requests and reads of immutable resources
+//! - Cached answers to the server's `roots/list` requests, from static or computed roots
+//! - Typed resource updates, applied to the cached resource list
+//! - Validation of structured tool results against the tool's `outputSchema`
+//! - Validation of tool arguments against the tool's `inputSchema` before sending
+//! - Renegotiation of the session, with a fresh connection if the server requires one
+//! - Batches of requests sent in one frame, with [`Client::batch`]
+//! - Keep-alive pings measuring latency and detecting an unresponsive server
+//! - Tool, prompt and resource catalogs refreshed on `list_changed`
+//! - A stream of lifecycle events, with [`Client::events`], for connection indicators
+//!
+//! The client handles server-initiated requests and notifications while it
+//! waits for the response to one of its own requests, and while idle too
+//! once initialized, over transports whose clones share their connection.
 
 use crate::{
-    constants::LATEST_PROTOCOL_VERSION,
-    error::MCPError,
-    schema::json_rpc::{JSONRPCMessage, JSONRPCRequest, RequestId},
-    transport::Transport,
+    audit::{AuditOutcome, AuditSide, Auditor},
+    cache::ResponseCache,
+    catalog::LiveCatalog,
+    clock::{self, Clock, Instant, SystemClock},
+    constants::{
+        ACCEPTED_CONTENT_TYPES_CAPABILITY, CHUNKED_UPLOAD_CAPABILITY, JSONRPC_VERSION,
+        LATEST_PROTOCOL_VERSION, SUPPORTED_PROTOCOL_VERSIONS,
+    },
+    error::{ErrorKind, MCPError},
+    schema::{
+        client::{
+            ArgumentInfo, CancelledParams, CompleteParams, GetPromptParams, GetPromptResult,
+            ListPromptsResult, ListResourceTemplatesResult, ListResourcesResult, ListToolsResult,
+            ProgressParams, ReadResourceMeta, ReadResourceParams, ReadResourceResult, Reference,
+            ResourceContent, SetLevelParams, UploadChunkParams, UploadMeta,
+        },
+        common::{
+            Cursor, Implementation, LoggingLevel, ProgressToken, Prompt, Resource,
+            ResourceTemplate, Root, Tool, ToolAnnotations,
+        },
+        json_rpc::{
+            error_codes, merge_meta, meta_of, EmptyResult, JSONRPCError, JSONRPCMessage,
+            JSONRPCNotification, JSONRPCPayload, JSONRPCRequest, JSONRPCResponse, Meta, RequestId,
+            RequestMeta,
+        },
+        parse::ParseMode,
+        server::{
+            CallToolResult, CompleteResult, CreateMessageParams, CreateMessageResult, ElicitAction,
+            ElicitParams, ElicitResult, InitializeResult, LoggingMessageParams,
+            ResourceUpdatedParams, ServerCapabilities, ToolResultContent,
+        },
+        validation,
+    },
+    trace::RequestSpan,
+    transport::{Traffic, Transport},
 };
 use async_trait::async_trait;
-use futures::future::join_all;
+use futures::{future::BoxFuture, stream, Stream, TryStreamExt};
+use log::{debug, info, warn};
+use rand::Rng;
 use serde::{de::DeserializeOwned, Serialize};
-use serde_json::Value;
-use std::time::Duration;
-use tokio::time::timeout;
+use serde_json::{Map, Value};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    future::Future,
+    pin::Pin,
+    process::ExitStatus,
+    sync::{
+        atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::sync::{broadcast, mpsc, oneshot, watch, Mutex as TokioMutex, Notify};
 
-/// High-level MCP client
-pub struct Client<T: Transport + Send + Sync> {
-    transport: T,
-    next_request_id: i64,
-    timeout_duration: Option<Duration>,
+/// Connection state of a client, observable through [`Client::subscribe_state`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum ConnectionState {
+    /// The client has not connected yet, or the last connection attempt failed
+    Disconnected,
+    /// The transport is starting and the initialize handshake is in progress
+    Connecting,
+    /// The session is initialized and ready for requests
+    Connected,
+    /// The connection was lost and the given reconnect attempt is in progress
+    Reconnecting { attempt: u32 },
+    /// The client was shut down or gave up reconnecting
+    Closed,
+    /// The server stopped answering keep-alive pings
+    ///
+    /// Requests waiting for a response fail with
+    /// [`MCPError::ConnectionClosed`]; with a reconnect policy, the next one
+    /// to fail reconnects. See [`Client::spawn_keep_alive`].
+    Unresponsive,
 }
 
-impl<T: Transport + Send + Sync> Client<T> {
-    /// Create a new MCP client with the given transport
-    pub fn new(transport: T) -> Self {
+/// Backoff policy used when reconnecting to a server
+///
+/// The delay before attempt `n` (starting at 1) is
+/// `initial_delay * multiplier^(n - 1)`, capped at `max_delay`, and then
+/// randomly spread by `jitter` so that many clients reconnecting to the same
+/// server do not retry in lockstep.
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// Delay before the first reconnect attempt
+    pub initial_delay: Duration,
+    /// Factor by which the delay grows after each failed attempt
+    pub multiplier: f64,
+    /// Upper bound for the delay between attempts
+    pub max_delay: Duration,
+    /// Fraction of the delay (0.0 to 1.0) that is randomized
+    pub jitter: f64,
+    /// Total time after which reconnecting gives up, if any
+    pub max_elapsed: Option<Duration>,
+}
+
+impl ConnectionState {
+    /// Whether requests waiting for a response can no longer get one
+    fn is_lost(&self) -> bool {
+        matches!(
+            self,
+            ConnectionState::Closed | ConnectionState::Unresponsive
+        )
+    }
+}
+
+impl ReconnectPolicy {
+    /// Create a reconnect policy with the default settings
+    pub fn new() -> Self {
         Self {
-            transport,
-            next_request_id: 1,
-            timeout_duration: None,
+            initial_delay: Duration::from_millis(500),
+            multiplier: 2.0,
+            max_delay: Duration::from_secs(30),
+            jitter: 0.2,
+            max_elapsed: Some(Duration::from_secs(300)),
         }
     }
 
-    /// Set a timeout for operations
-    pub fn with_timeout(mut self, duration: Duration) -> Self {
-        self.timeout_duration = Some(duration);
+    /// Set the delay before the first reconnect attempt
+    pub fn with_initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
         self
     }
 
-    /// Check if the client is connected to the server
-    pub fn is_connected(&self) -> bool {
-        // For now, we don't have a direct way to check this in the Transport trait
-        // This is an estimation based on our internal state
-        self.next_request_id > 1 // If we've sent at least one request, we consider ourselves connected
+    /// Set the factor by which the delay grows after each attempt
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
     }
 
-    /// Initialize the client
-    pub async fn initialize(&mut self) -> Result<Value, MCPError> {
-        // Start the transport
-        self.transport.start().await?;
+    /// Set the upper bound for the delay between attempts
+    pub fn with_max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
 
-        // Send initialization request
-        let initialize_request = JSONRPCRequest::new(
-            self.next_request_id(),
-            "initialize".to_string(),
-            Some(serde_json::json!({
-                "protocol_version": LATEST_PROTOCOL_VERSION
-            })),
-        );
+    /// Set the randomized fraction of each delay (clamped to 0.0..=1.0)
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
 
-        let message = JSONRPCMessage::Request(initialize_request);
-        self.transport.send(&message).await?;
+    /// Set the total time after which reconnecting gives up
+    pub fn with_max_elapsed(mut self, max_elapsed: Option<Duration>) -> Self {
+        self.max_elapsed = max_elapsed;
+        self
+    }
 
-        // Wait for response with timeout if set
-        let response: JSONRPCMessage = self.receive_with_timeout().await?;
+    /// The delay before the given attempt (starting at 1), without jitter
+    pub fn base_delay(&self, attempt: u32) -> Duration {
+        backoff_delay(self.initial_delay, self.multiplier, self.max_delay, attempt)
+    }
 
-        match response {
-            JSONRPCMessage::Response(resp) => Ok(resp.result),
-            JSONRPCMessage::Error(err) => Err(MCPError::Protocol(format!(
-                "Initialization failed: {:?}",
-                err
-            ))),
-            _ => Err(MCPError::Protocol("Unexpected response type".to_string())),
-        }
+    /// The delay before the given attempt (starting at 1), with jitter applied
+    pub fn delay(&self, attempt: u32) -> Duration {
+        jittered(self.base_delay(attempt), self.jitter, self.max_delay)
     }
+}
 
-    /// List tools on a server
-    pub async fn list_tools<R: DeserializeOwned + Send + Sync>(&mut self) -> Result<R, MCPError> {
-        // Create tool call request
-        let tool_call_request =
-            JSONRPCRequest::new(self.next_request_id(), "tools/list".to_string(), None);
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        let message = JSONRPCMessage::Request(tool_call_request);
-        self.transport.send(&message).await?;
+/// `initial_delay * multiplier^(attempt - 1)`, capped at `max_delay`
+///
+/// The fields are public, so the multiplier may be anything; a delay that is
+/// negative, NaN or too long for a `Duration` becomes `max_delay`.
+fn backoff_delay(
+    initial_delay: Duration,
+    multiplier: f64,
+    max_delay: Duration,
+    attempt: u32,
+) -> Duration {
+    let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+    let delay = initial_delay.as_secs_f64() * multiplier.powi(exponent);
+    capped_secs(delay, max_delay)
+}
 
-        // Wait for response with timeout if set
-        let response: JSONRPCMessage = self.receive_with_timeout().await?;
+/// `delay` randomly spread by the fraction `jitter`, capped at `max_delay`
+fn jittered(delay: Duration, jitter: f64, max_delay: Duration) -> Duration {
+    if jitter.is_nan() || jitter <= 0.0 {
+        return delay.min(max_delay);
+    }
+    let base = delay.as_secs_f64();
+    let spread = base * jitter.min(1.0);
+    let jittered = base - spread + rand::thread_rng().gen_range(0.0..=2.0 * spread);
+    capped_secs(jittered.max(0.0), max_delay)
+}
 
-        match response {
-            JSONRPCMessage::Response(resp) => {
-                // Parse the result
-                serde_json::from_value(resp.result).map_err(MCPError::Serialization)
-            }
-            JSONRPCMessage::Error(err) => {
-                Err(MCPError::Protocol(format!("Tool list failed: {:?}", err)))
-            }
-            _ => Err(MCPError::Protocol("Unexpected response type".to_string())),
+/// `secs` as a `Duration` no longer than `max_delay`, or `max_delay` if it is no duration
+fn capped_secs(secs: f64, max_delay: Duration) -> Duration {
+    Duration::try_from_secs_f64(secs).map_or(max_delay, |delay| delay.min(max_delay))
+}
+
+/// How to retry requests that failed on the way, see [`Client::with_retry_policy`]
+///
+/// Only transport failures, timeouts and lost connections are retried, and
+/// only for requests that are safe to send twice: listings, reads, `ping`,
+/// subscriptions, and calls of tools annotated read-only or idempotent.
+/// Delays grow as for a [`ReconnectPolicy`].
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Attempts in all, including the first
+    pub max_attempts: u32,
+    /// Delay before the first retry
+    pub initial_delay: Duration,
+    /// Factor by which the delay grows after each retry
+    pub multiplier: f64,
+    /// Upper bound for the delay between attempts
+    pub max_delay: Duration,
+    /// Fraction of the delay (0.0 to 1.0) that is randomized
+    pub jitter: f64,
+}
+
+impl RetryPolicy {
+    /// Try up to 3 times, 100 milliseconds apart and growing from there
+    pub fn new() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(100),
+            multiplier: 2.0,
+            max_delay: Duration::from_secs(5),
+            jitter: 0.2,
         }
     }
 
-    /// Call a tool on the server
-    pub async fn call_tool<P: Serialize + Send + Sync, R: DeserializeOwned + Send + Sync>(
-        &mut self,
-        tool_name: &str,
-        params: &P,
-    ) -> Result<R, MCPError> {
-        // Create tool call request
-        let tool_call_request = JSONRPCRequest::new(
-            self.next_request_id(),
-            "tools/call".to_string(),
-            Some(serde_json::json!({
-                "name": tool_name,
-                "arguments": serde_json::to_value(params)?
-            })),
-        );
-
-        let message = JSONRPCMessage::Request(tool_call_request);
-        self.transport.send(&message).await?;
+    /// Set the attempts in all, including the first
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
 
-        // Wait for response with timeout if set
-        let response: JSONRPCMessage = self.receive_with_timeout().await?;
+    /// Set the delay before the first retry
+    pub fn with_initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
 
-        match response {
-            JSONRPCMessage::Response(resp) => {
-                // Parse the result
-                serde_json::from_value(resp.result).map_err(MCPError::Serialization)
-            }
-            JSONRPCMessage::Error(err) => {
-                Err(MCPError::Protocol(format!("Tool call failed: {:?}", err)))
-            }
-            _ => Err(MCPError::Protocol("Unexpected response type".to_string())),
-        }
+    /// Set the factor by which the delay grows after each retry
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
     }
 
-    /// Shutdown the client
-    pub async fn shutdown(&mut self) -> Result<(), MCPError> {
-        // Send shutdown request
-        let shutdown_request =
-            JSONRPCRequest::new(self.next_request_id(), "shutdown".to_string(), None);
+    /// Set the upper bound for the delay between attempts
+    pub fn with_max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
 
-        let message = JSONRPCMessage::Request(shutdown_request);
-        self.transport.send(&message).await?;
+    /// Set the randomized fraction of each delay (clamped to 0.0..=1.0)
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
 
-        // Wait for response with timeout if set
-        let response: JSONRPCMessage = self.receive_with_timeout().await?;
+    /// The delay after the given failed attempt (starting at 1), with jitter applied
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let base = backoff_delay(self.initial_delay, self.multiplier, self.max_delay, attempt);
+        jittered(base, self.jitter, self.max_delay)
+    }
+}
 
-        match response {
-            JSONRPCMessage::Response(_) => {
-                // Close the transport
-                self.transport.close().await?;
-                Ok(())
-            }
-            JSONRPCMessage::Error(err) => {
-                Err(MCPError::Protocol(format!("Shutdown failed: {:?}", err)))
-            }
-            _ => Err(MCPError::Protocol("Unexpected response type".to_string())),
-        }
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    /// Receive a message with optional timeout
-    async fn receive_with_timeout<R: DeserializeOwned + Send + Sync>(
-        &mut self,
-    ) -> Result<R, MCPError> {
-        if let Some(duration) = self.timeout_duration {
-            match timeout(duration, self.transport.receive::<R>()).await {
-                Ok(result) => result,
-                Err(_) => Err(MCPError::Timeout(format!(
-                    "Operation timed out after {:?}",
-                    duration
-                ))),
-            }
-        } else {
-            self.transport.receive().await
+/// Periodic pings detecting a peer that stopped answering
+///
+/// A ping is sent every `interval`, and counts as missed when no answer
+/// comes within `timeout`. After `max_missed` missed pings in a row the
+/// connection is considered dead. Used by [`Client::spawn_keep_alive`] and
+/// [`ServerConfig::with_keep_alive`](crate::server::ServerConfig::with_keep_alive).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeepAlive {
+    /// Time between pings
+    pub interval: Duration,
+    /// How long to wait for the answer to a ping
+    pub timeout: Duration,
+    /// Missed pings in a row after which the peer is considered gone
+    pub max_missed: u32,
+}
+
+impl KeepAlive {
+    /// Ping every `interval`, with the default timeout and missed pings
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            timeout: Duration::from_secs(10),
+            max_missed: 3,
         }
     }
 
-    /// Generate the next request ID
-    fn next_request_id(&mut self) -> RequestId {
-        let id = self.next_request_id;
-        self.next_request_id += 1;
-        RequestId::Number(id)
+    /// Set how long to wait for the answer to a ping
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
     }
 
-    /// Call multiple tools concurrently
-    ///
-    /// This method demonstrates the power of async by allowing multiple tool calls to be made
-    /// concurrently. Each tool call is represented as a tuple of (tool_name, parameters).
-    ///
-    /// Note that this requires cloning the transport for each concurrent call, which may not
-    /// be supported by all transport types. Use with caution.
-    pub async fn call_tools_concurrent<P, R>(
-        &self,
-        tool_calls: Vec<(String, P)>,
-    ) -> Result<Vec<Result<R, MCPError>>, MCPError>
-    where
-        P: Serialize + Clone + Send + Sync + 'static,
-        R: DeserializeOwned + Send + Sync + 'static,
-        T: Clone + Send + Sync + 'static,
-    {
-        let mut tasks = Vec::with_capacity(tool_calls.len());
+    /// Set the missed pings in a row after which the peer is considered gone
+    pub fn with_max_missed(mut self, max_missed: u32) -> Self {
+        self.max_missed = max_missed.max(1);
+        self
+    }
+}
 
-        // Create a new client for each concurrent call
-        for (idx, (tool_name, params)) in tool_calls.into_iter().enumerate() {
-            let mut client = Client {
-                transport: self.transport.clone(),
-                next_request_id: self.next_request_id + idx as i64 + 1, // Ensure unique IDs
-                timeout_duration: self.timeout_duration,
-            };
+impl Default for KeepAlive {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(30))
+    }
+}
 
-            // Spawn a task for each tool call
-            let task =
-                tokio::spawn(async move { client.call_tool::<P, R>(&tool_name, &params).await });
+/// Everything a server offers, as returned by [`Client::describe_server`]
+///
+/// Lists for capabilities the server did not advertise are empty.
+#[derive(Debug, Clone, Default)]
+pub struct ServerDescription {
+    /// Tools the server offers
+    pub tools: Vec<Tool>,
+    /// Prompts and prompt templates the server offers
+    pub prompts: Vec<Prompt>,
+    /// Resources the server can read
+    pub resources: Vec<Resource>,
+    /// Resource templates the server offers
+    pub resource_templates: Vec<ResourceTemplate>,
+}
 
-            tasks.push(task);
-        }
+/// A page of a cursor-paginated list result
+trait PaginatedResult: DeserializeOwned + Send + Sync {
+    type Item;
 
-        // Wait for all tasks to complete
-        let results = join_all(tasks).await;
+    /// Split the page into its items and the cursor for the next page
+    fn into_page(self) -> (Vec<Self::Item>, Option<Cursor>);
+}
 
-        // Process results, handling any JoinErrors from the tasks
-        let processed_results = results
-            .into_iter()
-            .map(|join_result| match join_result {
-                Ok(tool_result) => tool_result,
-                Err(e) => Err(MCPError::Transport(format!("Task join error: {}", e))),
-            })
-            .collect();
+impl PaginatedResult for ListToolsResult {
+    type Item = Tool;
 
-        Ok(processed_results)
+    fn into_page(self) -> (Vec<Tool>, Option<Cursor>) {
+        (self.tools, self.next_cursor)
     }
+}
 
-    /// Execute a complete client session in one call
-    ///
-    /// This method handles initialization, tool calls, and shutdown in a single method call.
-    /// It's especially useful for short-lived clients that need to make a few tool calls
-    /// and then shut down.
-    ///
-    /// # Example
-    /// ```ignore
-    /// use mcpr::{client::Client, transport::stdio::StdioTransport};
-    /// use serde_json::json;
-    ///
-    /// // Create a client with stdio transport
-    /// let mut client = Client::new(StdioTransport::new());
-    ///
-    /// // Execute a session with initialization and shutdown handled automatically
-    /// let result = client.execute_session(|client| {
-    ///     Box::pin(async move {
-    ///         // Call a tool and return the result
-    ///         client.call_tool("example_tool", &json!({"param": "value"})).await
-    ///     })
-    /// }).await;
-    /// ```
-    pub async fn execute_session<F, Fut, R>(&mut self, f: F) -> Result<R, MCPError>
-    where
-        F: FnOnce(&mut Self) -> Fut + Send,
-        Fut: std::future::Future<Output = Result<R, MCPError>> + Send,
-        R: Send,
-    {
-        // Initialize the client
-        self.initialize().await?;
+impl PaginatedResult for ListPromptsResult {
+    type Item = Prompt;
 
-        // Execute the user-provided function
-        let result = f(self).await;
+    fn into_page(self) -> (Vec<Prompt>, Option<Cursor>) {
+        (self.prompts, self.next_cursor)
+    }
+}
 
-        // Shutdown the client regardless of whether the function succeeded
-        let shutdown_result = self.shutdown().await;
+impl PaginatedResult for ListResourcesResult {
+    type Item = Resource;
 
-        // Return the function result if it succeeded, otherwise return the function error
-        match result {
-            Ok(r) => {
-                // If the function succeeded but shutdown failed, return the shutdown error
-                if let Err(e) = shutdown_result {
-                    Err(e)
-                } else {
-                    Ok(r)
-                }
-            }
-            Err(e) => {
-                // If both the function and shutdown failed, prefer the function error
-                Err(e)
-            }
-        }
+    fn into_page(self) -> (Vec<Resource>, Option<Cursor>) {
+        (self.resources, self.next_cursor)
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::schema::json_rpc::{JSONRPCError, JSONRPCMessage, JSONRPCResponse, RequestId};
-    use crate::transport::Transport;
-    use crate::transport::{CloseCallback, ErrorCallback, MessageCallback};
-    use async_trait::async_trait;
-    use futures::future::Future;
-    use std::collections::VecDeque;
-    use std::pin::Pin;
-    use std::sync::{Arc, Mutex};
-    use std::time::Duration;
-    use tokio::sync::Mutex as TokioMutex;
+impl PaginatedResult for ListResourceTemplatesResult {
+    type Item = ResourceTemplate;
 
-    // Mock transport for testing
-    #[derive(Clone)]
-    struct MockTransport {
-        send_queue: Arc<TokioMutex<VecDeque<String>>>,
-        receive_queue: Arc<TokioMutex<VecDeque<String>>>,
-        is_started: Arc<TokioMutex<bool>>,
-        is_closed: Arc<TokioMutex<bool>>,
-        should_fail: Arc<TokioMutex<bool>>,
-        simulate_timeout: Arc<TokioMutex<bool>>,
-        on_message: Arc<Mutex<Option<MessageCallback>>>,
-        on_error: Arc<Mutex<Option<ErrorCallback>>>,
-        on_close: Arc<Mutex<Option<CloseCallback>>>,
+    fn into_page(self) -> (Vec<ResourceTemplate>, Option<Cursor>) {
+        (self.resource_templates, self.next_cursor)
     }
+}
 
-    impl MockTransport {
-        fn new() -> Self {
-            Self {
-                send_queue: Arc::new(TokioMutex::new(VecDeque::new())),
-                receive_queue: Arc::new(TokioMutex::new(VecDeque::new())),
-                is_started: Arc::new(TokioMutex::new(false)),
-                is_closed: Arc::new(TokioMutex::new(false)),
-                should_fail: Arc::new(TokioMutex::new(false)),
-                simulate_timeout: Arc::new(TokioMutex::new(false)),
-                on_message: Arc::new(Mutex::new(None)),
-                on_error: Arc::new(Mutex::new(None)),
-                on_close: Arc::new(Mutex::new(None)),
-            }
-        }
+/// Tool call arguments built at runtime
+///
+/// Serializes as a JSON object, so it can be passed straight to
+/// [`Client::call_tool`]. Nest objects by passing another `Arguments` as a
+/// value, and arrays by passing a `Vec`.
+///
+/// ```
+/// use mcpr::client::Arguments;
+///
+/// let args = Arguments::builder()
+///     .arg("message", "hello")
+///     .arg("count", 3)
+///     .arg("tags", vec!["a", "b"])
+///     .arg("options", Arguments::builder().arg("verbose", true).build())
+///     .build();
+///
+/// assert_eq!(args.get("count"), Some(&serde_json::json!(3)));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Arguments(Map<String, Value>);
 
-        async fn queue_message(&self, message: JSONRPCMessage) {
+impl Arguments {
+    /// Start building a new set of arguments
+    pub fn builder() -> ArgumentsBuilder {
+        ArgumentsBuilder::default()
+    }
+
+    /// Get the value of an argument
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.0.get(name)
+    }
+
+    /// Check whether no arguments were set
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Convert the arguments into a JSON object value
+    pub fn into_value(self) -> Value {
+        Value::Object(self.0)
+    }
+}
+
+impl From<Arguments> for Value {
+    fn from(arguments: Arguments) -> Self {
+        arguments.into_value()
+    }
+}
+
+/// Builder for [`Arguments`]
+#[derive(Debug, Clone, Default)]
+pub struct ArgumentsBuilder {
+    arguments: Map<String, Value>,
+}
+
+impl ArgumentsBuilder {
+    /// Set an argument, replacing any earlier value with the same name
+    pub fn arg(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.arguments.insert(name.into(), value.into());
+        self
+    }
+
+    /// Set an argument from any serializable value
+    pub fn arg_serialized<V: Serialize>(
+        mut self,
+        name: impl Into<String>,
+        value: &V,
+    ) -> Result<Self, MCPError> {
+        self.arguments
+            .insert(name.into(), serde_json::to_value(value)?);
+        Ok(self)
+    }
+
+    /// Finish building the arguments
+    pub fn build(self) -> Arguments {
+        Arguments(self.arguments)
+    }
+}
+
+/// A snapshot of a request that is waiting for its response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingRequestInfo {
+    /// The id the request was sent with
+    pub id: RequestId,
+    /// The request method
+    pub method: String,
+    /// How long the request has been waiting
+    pub elapsed: Duration,
+}
+
+/// Bookkeeping for an in-flight request
+#[derive(Debug)]
+struct PendingRequest {
+    method: String,
+    started: Instant,
+    /// Hands the response over when another clone sharing the transport reads it
+    reply: Option<oneshot::Sender<JSONRPCMessage>>,
+}
+
+type PendingRequests = Arc<Mutex<HashMap<RequestId, PendingRequest>>>;
+
+/// How long the id of a cancelled request is remembered
+///
+/// Responses that arrive for the id within this window are classified as late
+/// responses rather than responses with an unknown id.
+const CANCELLED_GRACE_WINDOW: Duration = Duration::from_secs(60);
+
+/// A request that was abandoned before its response arrived
+#[derive(Debug)]
+struct CancelledRequest {
+    method: String,
+    cancelled_at: Instant,
+}
+
+type CancelledRequests = Arc<Mutex<HashMap<RequestId, CancelledRequest>>>;
+
+/// Requests in flight that identical requests can join, keyed on method and params
+///
+/// Followers receive the leader's response, or a copy of its error.
+type InFlightRequests =
+    Arc<Mutex<HashMap<String, broadcast::Sender<Result<JSONRPCMessage, Arc<MCPError>>>>>>;
+
+/// Methods that only read server state, and so can be coalesced
+const READ_ONLY_METHODS: &[&str] = &[
+    "tools/list",
+    "resources/list",
+    "resources/templates/list",
+    "resources/read",
+    "prompts/list",
+    "prompts/get",
+];
+
+/// Methods that are not read-only, but safe to send twice
+const IDEMPOTENT_METHODS: &[&str] = &[
+    "ping",
+    "resources/subscribe",
+    "resources/unsubscribe",
+    "logging/setLevel",
+    "completion/complete",
+];
+
+/// The server capability a method needs, as a dotted path into the capabilities
+fn required_capability(method: &str) -> Option<&'static str> {
+    match method {
+        "tools/list" | "tools/call" => Some("tools"),
+        "resources/subscribe" | "resources/unsubscribe" => Some("resources.subscribe"),
+        "resources/list" | "resources/templates/list" | "resources/read" => Some("resources"),
+        "prompts/list" | "prompts/get" => Some("prompts"),
+        "logging/setLevel" => Some("logging"),
+        "completion/complete" => Some("completions"),
+        _ => None,
+    }
+}
+
+/// Whether the capabilities include a capability named by [`required_capability`]
+fn has_capability(capabilities: &ServerCapabilities, capability: &str) -> bool {
+    match capability {
+        "tools" => capabilities.tools.is_some(),
+        "resources" => capabilities.resources.is_some(),
+        "resources.subscribe" => capabilities
+            .resources
+            .as_ref()
+            .is_some_and(|resources| resources.subscribe == Some(true)),
+        "prompts" => capabilities.prompts.is_some(),
+        "logging" => capabilities.logging.is_some(),
+        "completions" => capabilities.completions.is_some(),
+        _ => true,
+    }
+}
+
+/// Removes a coalesced request from the in-flight map when its leader is dropped
+///
+/// Dropping the sender wakes any followers with an error, so they never wait
+/// on a leader that was cancelled.
+struct FlightGuard {
+    in_flight: InFlightRequests,
+    key: String,
+}
+
+impl FlightGuard {
+    /// Hand the leader's result to every follower
+    fn complete(self, result: &Result<JSONRPCMessage, MCPError>) {
+        if let Some(sender) = self.in_flight.lock().unwrap().remove(&self.key) {
+            let result = match result {
+                Ok(response) => Ok(response.clone()),
+                Err(e) => Err(Arc::new(e.duplicate())),
+            };
+            let _ = sender.send(result);
+        }
+    }
+}
+
+impl Drop for FlightGuard {
+    fn drop(&mut self) {
+        self.in_flight.lock().unwrap().remove(&self.key);
+    }
+}
+
+/// Options for a single call, see [`Client::call_tool_with_options`]
+#[derive(Debug, Clone, Default)]
+pub struct CallOptions {
+    /// Longest the whole call may take, overriding [`Client::with_timeout`]
+    pub timeout: Option<Duration>,
+    /// `_meta` fields sent with the call's requests, over those of [`Client::with_request_meta`]
+    pub meta: Meta,
+}
+
+impl CallOptions {
+    /// Create options with the client's defaults
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the longest the whole call may take
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Send a `_meta` field with the call, such as a tracing id
+    pub fn with_meta(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.meta.insert(key.to_string(), value.into());
+        self
+    }
+}
+
+/// How to shut down, see [`Client::shutdown_with`]
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownOptions {
+    /// Longest to wait for requests in flight to complete before shutting down
+    pub drain_timeout: Option<Duration>,
+    /// Longest to wait for the server to answer the shutdown request
+    pub timeout: Option<Duration>,
+}
+
+impl ShutdownOptions {
+    /// Shut down right away, waiting as long as the server takes to answer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wait up to `drain_timeout` for requests in flight to complete first
+    pub fn with_drain_timeout(mut self, drain_timeout: Duration) -> Self {
+        self.drain_timeout = Some(drain_timeout);
+        self
+    }
+
+    /// Close the transport if the server has not answered within `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+/// How often [`Client::shutdown_with`] checks whether requests have drained
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Requests sent to the server in one JSON-RPC batch, see [`Client::batch`]
+pub struct Batch<'a, T: Transport + Send + Sync> {
+    client: &'a mut Client<T>,
+    requests: Vec<JSONRPCRequest>,
+}
+
+impl<T: Transport + Send + Sync> Batch<'_, T> {
+    /// Add a request to the batch
+    pub fn request(mut self, method: &str, params: Option<Value>) -> Self {
+        let id = self.client.next_request_id();
+        self.requests
+            .push(JSONRPCRequest::new(id, method.to_string(), params));
+        self
+    }
+
+    /// How many requests the batch holds
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    /// Whether the batch holds no requests
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    /// Send the requests in one frame and wait for all of their responses
+    ///
+    /// The results are in the order the requests were added. The outer error
+    /// is for a batch that could not be sent; each request fails on its own.
+    pub async fn send(self) -> Result<Vec<Result<Value, MCPError>>, MCPError> {
+        self.client.send_batch(self.requests).await
+    }
+}
+
+tokio::task_local! {
+    /// Deadline of the call in progress, set by [`Client::call_tool_with_options`]
+    static CALL_DEADLINE: Instant;
+    /// `_meta` fields of the call in progress, set by [`Client::call_tool_with_options`]
+    static CALL_META: Meta;
+}
+
+/// What to do with a response that arrives after its request was cancelled
+///
+/// A request is cancelled when its future is dropped or times out before
+/// the response arrives. The late response is never delivered to anyone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LateResponsePolicy {
+    /// Drop the response without a trace
+    #[default]
+    IgnoreSilently,
+    /// Drop the response and log it
+    Log,
+    /// Drop the response and publish a [`ClientDiagnostic::LateResponse`]
+    Error,
+}
+
+/// Events published on the client's diagnostics channel
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientDiagnostic {
+    /// A response arrived for a request that had already been cancelled
+    LateResponse {
+        /// The id of the cancelled request
+        id: RequestId,
+        /// The method of the cancelled request
+        method: String,
+        /// How long after the cancellation the response arrived
+        after_cancel: Duration,
+    },
+    /// A tool call was allowed or denied, for audit logs
+    ToolCallDecided {
+        /// The tool called
+        tool: String,
+        /// The arguments of the call
+        arguments: Value,
+        /// Whether the call went ahead
+        allowed: bool,
+        /// Whether the user was asked
+        asked: bool,
+        /// Why a denied call was denied
+        reason: Option<String>,
+    },
+    /// A response arrived for a request that was never sent or was answered already
+    UnknownResponse {
+        /// The id of the response
+        id: RequestId,
+    },
+    /// A message from the server could not be parsed, and was skipped
+    InvalidMessage {
+        /// Why it could not be parsed
+        error: String,
+    },
+}
+
+impl ClientDiagnostic {
+    /// Whether the event is the server breaking the protocol
+    pub fn is_protocol_error(&self) -> bool {
+        matches!(
+            self,
+            ClientDiagnostic::UnknownResponse { .. } | ClientDiagnostic::InvalidMessage { .. }
+        )
+    }
+}
+
+/// Lifecycle events of a client, published on [`Client::events`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientEvent {
+    /// The transport started
+    Connected,
+    /// The initialize handshake completed
+    Initialized {
+        /// The protocol version the server agreed on
+        protocol_version: Option<String>,
+        /// The name and version the server gave
+        server_info: Option<Implementation>,
+    },
+    /// The connection was lost, closed or stopped answering
+    Disconnected {
+        /// Why, for display
+        reason: String,
+    },
+    /// A notification arrived from the server
+    NotificationReceived(JSONRPCNotification),
+    /// A request failed or was answered with an error
+    RequestFailed {
+        /// The method of the request
+        method: String,
+        /// The error, for display
+        error: String,
+    },
+    /// The server process behind the transport exited
+    ChildProcessExited {
+        /// How it exited
+        status: ExitStatus,
+    },
+}
+
+/// Requests dropped while in flight, whose cancellation is yet to be sent
+///
+/// Dropping cannot send, so `notifications/cancelled` goes out just before
+/// the next message the client sends.
+type UnsentCancellations = Arc<Mutex<Vec<RequestId>>>;
+
+/// Removes a request from the pending map when it resolves, fails or is dropped
+///
+/// A request dropped before its response arrived is remembered as cancelled,
+/// and the server told so if it is still waiting on it.
+struct PendingGuard {
+    pending: PendingRequests,
+    /// Woken once no request is pending anymore
+    idle: Arc<Notify>,
+    cancelled: CancelledRequests,
+    unsent_cancellations: UnsentCancellations,
+    id: RequestId,
+    answered: bool,
+    /// Whether the server should be told when the request is dropped
+    notify_server: bool,
+}
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        let removed = {
+            let mut pending = self.pending.lock().unwrap();
+            let removed = pending.remove(&self.id);
+            if pending.is_empty() {
+                self.idle.notify_one();
+            }
+            removed
+        };
+        let Some(request) = removed else {
+            return;
+        };
+
+        if self.answered {
+            debug!(
+                "Request {:?} ('{}') resolved after {:?}",
+                self.id,
+                request.method,
+                request.started.elapsed()
+            );
+            return;
+        }
+
+        debug!(
+            "Request {:?} ('{}') cancelled after {:?}",
+            self.id,
+            request.method,
+            request.started.elapsed()
+        );
+        if self.notify_server {
+            self.unsent_cancellations
+                .lock()
+                .unwrap()
+                .push(self.id.clone());
+        }
+        let now = Instant::now();
+        let mut cancelled = self.cancelled.lock().unwrap();
+        cancelled.retain(|_, c| now.duration_since(c.cancelled_at) < CANCELLED_GRACE_WINDOW);
+        cancelled.insert(
+            self.id.clone(),
+            CancelledRequest {
+                method: request.method,
+                cancelled_at: now,
+            },
+        );
+    }
+}
+
+/// Snapshot the pending map, longest-waiting request first
+fn snapshot_pending(pending: &PendingRequests) -> Vec<PendingRequestInfo> {
+    let mut requests: Vec<_> = pending
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(id, request)| PendingRequestInfo {
+            id: id.clone(),
+            method: request.method.clone(),
+            elapsed: request.started.elapsed(),
+        })
+        .collect();
+    requests.sort_by_key(|request| std::cmp::Reverse(request.elapsed));
+    requests
+}
+
+/// JSON-RPC error code servers may use to signal rate limiting
+///
+/// Mirrors HTTP's `429 Too Many Requests`.
+pub const RATE_LIMITED: i32 = 429;
+
+/// Delay used when a rate-limited response does not say how long to wait
+const DEFAULT_RATE_LIMIT_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest wait for a rate-limited call, whatever `retryAfter` the server asks for
+pub const MAX_RATE_LIMIT_DELAY: Duration = Duration::from_secs(60);
+
+/// How often [`Client::wait_ready`] polls the server's tool list
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How many notifications the client keeps for [`Client::next_notification_where`]
+const NOTIFICATION_BUFFER_SIZE: usize = 64;
+
+/// Deserialize the result of a response, or turn an error response into an error
+fn decode_response<R: DeserializeOwned>(
+    method: &str,
+    response: JSONRPCMessage,
+) -> Result<R, MCPError> {
+    match response {
+        JSONRPCMessage::Response(resp) => {
+            serde_json::from_value(resp.result).map_err(MCPError::Serialization)
+        }
+        JSONRPCMessage::Error(err) => {
+            debug!("Request '{}' failed: {:?}", method, err);
+            Err(err.error.into())
+        }
+        _ => Err(MCPError::Protocol("Unexpected response type".to_string())),
+    }
+}
+
+/// Remember the schemas the server declared in `field` for its tools
+fn learn_schemas(response: &JSONRPCMessage, field: &str, schemas: &Mutex<HashMap<String, Value>>) {
+    let JSONRPCMessage::Response(response) = response else {
+        return;
+    };
+    let Some(tools) = response.result.get("tools").and_then(Value::as_array) else {
+        return;
+    };
+
+    let mut schemas = schemas.lock().unwrap();
+    for tool in tools {
+        let Some(name) = tool.get("name").and_then(Value::as_str) else {
+            continue;
+        };
+        match tool.get(field) {
+            Some(schema) => schemas.insert(name.to_string(), schema.clone()),
+            None => schemas.remove(name),
+        };
+    }
+}
+
+/// How a tool call ended, for its audit record
+fn audit_outcome(response: &Result<JSONRPCMessage, MCPError>) -> AuditOutcome {
+    match response {
+        Ok(JSONRPCMessage::Response(response)) if response.result["isError"] == true => {
+            AuditOutcome::ToolError
+        }
+        Ok(JSONRPCMessage::Response(_)) => AuditOutcome::Success,
+        Ok(JSONRPCMessage::Error(error)) => AuditOutcome::Error(error.error.message.clone()),
+        Ok(_) => AuditOutcome::Error("Unexpected response type".to_string()),
+        Err(MCPError::ToolCallDenied { reason, .. }) => AuditOutcome::Denied(reason.clone()),
+        Err(e @ MCPError::InvalidArguments { .. }) => AuditOutcome::Denied(e.to_string()),
+        Err(e) => AuditOutcome::Error(e.to_string()),
+    }
+}
+
+/// Check that the content items of a tool result have only the fields of their type
+fn check_tool_content(response: &JSONRPCMessage) -> Result<(), MCPError> {
+    let JSONRPCMessage::Response(response) = response else {
+        return Ok(());
+    };
+    let items = response.result.get("content").and_then(Value::as_array);
+    for (index, item) in items.into_iter().flatten().enumerate() {
+        let extraneous = ToolResultContent::extraneous_fields(item);
+        if !extraneous.is_empty() {
+            return Err(MCPError::Protocol(format!(
+                "Nonconforming tool result: content item {} has fields {:?} its type does not define",
+                index, extraneous
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Read an `initialize` result, leaving missing or malformed fields empty
+fn lenient_initialize_result(result: &Value) -> InitializeResult {
+    fn field<T: DeserializeOwned + Default>(result: &Value, name: &str) -> T {
+        result
+            .get(name)
+            .and_then(|value| serde_json::from_value(value.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    InitializeResult {
+        protocol_version: field(result, "protocolVersion"),
+        capabilities: field(result, "capabilities"),
+        server_info: field(result, "serverInfo"),
+        instructions: field(result, "instructions"),
+    }
+}
+
+/// Check that an `initialize` result has exactly the fields the specification defines
+fn check_initialize_result(result: &Value) -> Result<(), MCPError> {
+    const FIELDS: &[&str] = &[
+        "protocolVersion",
+        "capabilities",
+        "serverInfo",
+        "instructions",
+        "_meta",
+    ];
+    const CAPABILITIES: &[&str] = &["experimental", "logging", "prompts", "resources", "tools"];
+
+    let nonconforming = |message: String| {
+        Err(MCPError::Protocol(format!(
+            "Nonconforming initialize result: {}",
+            message
+        )))
+    };
+    let Some(fields) = result.as_object() else {
+        return nonconforming(format!("expected an object, got {}", result));
+    };
+    if let Some(field) = fields.keys().find(|key| !FIELDS.contains(&key.as_str())) {
+        return nonconforming(format!("unknown field '{}'", field));
+    }
+
+    match fields.get("protocolVersion") {
+        Some(Value::String(_)) => {}
+        Some(other) => return nonconforming(format!("protocolVersion is not a string: {}", other)),
+        None => return nonconforming("missing protocolVersion".to_string()),
+    }
+
+    let Some(capabilities) = fields.get("capabilities") else {
+        return nonconforming("missing capabilities".to_string());
+    };
+    let Some(capabilities) = capabilities.as_object() else {
+        return nonconforming(format!("capabilities is not an object: {}", capabilities));
+    };
+    if let Some(capability) = capabilities
+        .keys()
+        .find(|key| !CAPABILITIES.contains(&key.as_str()))
+    {
+        return nonconforming(format!("unknown capability '{}'", capability));
+    }
+    if let Err(e) =
+        serde_json::from_value::<ServerCapabilities>(Value::Object(capabilities.clone()))
+    {
+        return nonconforming(format!("invalid capabilities: {}", e));
+    }
+
+    match fields.get("serverInfo") {
+        Some(info) => {
+            if let Err(e) = serde_json::from_value::<Implementation>(info.clone()) {
+                return nonconforming(format!("invalid serverInfo: {}", e));
+            }
+        }
+        None => return nonconforming("missing serverInfo".to_string()),
+    }
+    Ok(())
+}
+
+/// Read a `retryAfter` value in seconds from an object or its `_meta`
+///
+/// Longer delays, including ones too long for a `Duration`, are capped at
+/// [`MAX_RATE_LIMIT_DELAY`].
+fn retry_after(value: &Value) -> Option<Duration> {
+    let seconds = value
+        .get("retryAfter")
+        .or_else(|| value.get("_meta").and_then(|meta| meta.get("retryAfter")))?
+        .as_f64()?;
+    if seconds.is_nan() || seconds < 0.0 {
+        return None;
+    }
+    Some(
+        Duration::try_from_secs_f64(seconds).map_or(MAX_RATE_LIMIT_DELAY, |delay| {
+            delay.min(MAX_RATE_LIMIT_DELAY)
+        }),
+    )
+}
+
+/// How long to wait before retrying, if the response signals rate limiting
+fn rate_limit_delay(response: &JSONRPCMessage) -> Option<Duration> {
+    match response {
+        JSONRPCMessage::Error(err) if err.error.code == RATE_LIMITED => Some(
+            err.error
+                .data
+                .as_ref()
+                .and_then(retry_after)
+                .unwrap_or(DEFAULT_RATE_LIMIT_DELAY),
+        ),
+        JSONRPCMessage::Response(resp)
+            if resp.result.get("isError") == Some(&Value::Bool(true)) =>
+        {
+            retry_after(&resp.result)
+        }
+        _ => None,
+    }
+}
+
+/// Split a string into pieces that each take at most `budget` bytes once
+/// escaped as a JSON string
+///
+/// A single character larger than the budget still gets a piece of its own.
+fn split_for_json(text: &str, budget: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut size = 0;
+
+    for (offset, ch) in text.char_indices() {
+        let escaped = match ch {
+            '"' | '\\' | '\n' | '\r' | '\t' | '\u{08}' | '\u{0c}' => 2,
+            c if (c as u32) < 0x20 => 6,
+            c => c.len_utf8(),
+        };
+        if size + escaped > budget && offset > start {
+            pieces.push(&text[start..offset]);
+            start = offset;
+            size = 0;
+        }
+        size += escaped;
+    }
+    if start < text.len() {
+        pieces.push(&text[start..]);
+    }
+
+    pieces
+}
+
+/// Counters describing a client session, as returned by [`Client::close`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionSummary {
+    /// Requests sent to the server, including the initialize handshake
+    pub requests_sent: u64,
+    /// Requests that failed, either with an error response or a local error
+    pub errors: u64,
+    /// Bytes the transport wrote, zero if it does not count them, see
+    /// [`Transport::traffic`]
+    pub bytes_sent: u64,
+    /// Bytes the transport read, zero if it does not count them
+    pub bytes_received: u64,
+    /// Time from the first successful initialization until the client closed
+    pub uptime: Duration,
+}
+
+/// Session counters, shared with the clones made for concurrent calls
+#[derive(Debug, Default)]
+struct SessionStats {
+    requests_sent: AtomicU64,
+    errors: AtomicU64,
+    /// Traffic of the transports replaced on reconnect
+    earlier_traffic: Mutex<Traffic>,
+    connected_at: Mutex<Option<Instant>>,
+    closed_at: Mutex<Option<Instant>>,
+}
+
+impl SessionStats {
+    /// Keep the traffic of a transport about to be replaced
+    fn add_traffic(&self, traffic: Option<Traffic>) {
+        if let Some(traffic) = traffic {
+            let mut earlier = self.earlier_traffic.lock().unwrap();
+            earlier.bytes_sent += traffic.bytes_sent;
+            earlier.bytes_received += traffic.bytes_received;
+        }
+    }
+
+    /// The counters so far, with `traffic` of the current transport
+    fn summary(&self, traffic: Option<Traffic>) -> ConnectionSummary {
+        let uptime = match *self.connected_at.lock().unwrap() {
+            Some(connected_at) => {
+                let end = self.closed_at.lock().unwrap().unwrap_or_else(Instant::now);
+                end.saturating_duration_since(connected_at)
+            }
+            None => Duration::ZERO,
+        };
+
+        let earlier = *self.earlier_traffic.lock().unwrap();
+        let traffic = traffic.unwrap_or_default();
+        ConnectionSummary {
+            requests_sent: self.requests_sent.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            bytes_sent: earlier.bytes_sent + traffic.bytes_sent,
+            bytes_received: earlier.bytes_received + traffic.bytes_received,
+            uptime,
+        }
+    }
+}
+
+/// Context passed to handlers of server-initiated requests
+pub struct RequestContext {
+    /// The id of the incoming request
+    pub id: RequestId,
+    /// The method of the incoming request
+    pub method: String,
+    /// The parameters of the incoming request
+    pub params: Option<Value>,
+    progress_token: Option<ProgressToken>,
+    outgoing: mpsc::UnboundedSender<JSONRPCMessage>,
+}
+
+impl RequestContext {
+    /// The progress token from the request's `_meta`, if the server sent one
+    pub fn progress_token(&self) -> Option<&ProgressToken> {
+        self.progress_token.as_ref()
+    }
+
+    /// The request's `_meta`, if the server sent any
+    pub fn meta(&self) -> Option<&Meta> {
+        meta_of(self.params.as_ref()?)
+    }
+
+    /// Report progress on the request to the server
+    ///
+    /// Progress is only reported when the server asked for it by sending a
+    /// progress token; otherwise this does nothing.
+    pub fn report_progress(&self, progress: f64, total: Option<f64>) -> Result<(), MCPError> {
+        let Some(progress_token) = self.progress_token.clone() else {
+            return Ok(());
+        };
+
+        let params = ProgressParams {
+            progress_token,
+            progress,
+            total,
+        };
+        let notification = JSONRPCNotification::new(
+            "notifications/progress".to_string(),
+            Some(serde_json::to_value(params)?),
+        );
+
+        self.outgoing
+            .send(JSONRPCMessage::Notification(notification))
+            .map_err(|_| MCPError::Transport("Request already completed".to_string()))
+    }
+}
+
+/// Handler function type for server-initiated requests
+/// Returns a boxed future that resolves to the result to send back, or an error
+pub type AsyncRequestHandler = Box<
+    dyn Fn(RequestContext) -> Pin<Box<dyn Future<Output = Result<Value, MCPError>> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// Handler function type for notifications from the server
+pub type NotificationHandler = Arc<dyn Fn(&JSONRPCNotification) + Send + Sync>;
+
+/// Where the progress of calls made with [`Client::call_tool_with_progress`] goes, by token
+type ProgressListeners = Arc<Mutex<HashMap<ProgressToken, mpsc::UnboundedSender<ProgressParams>>>>;
+
+/// Stops routing progress for a token when the call completes or is dropped
+struct ProgressListener {
+    listeners: ProgressListeners,
+    token: ProgressToken,
+}
+
+impl Drop for ProgressListener {
+    fn drop(&mut self) {
+        self.listeners.lock().unwrap().remove(&self.token);
+    }
+}
+
+/// Answers the server's `sampling/createMessage` requests
+///
+/// Implemented by the host application, which runs the completion with its
+/// own model, typically after the user approved the request. Install it with
+/// [`Client::with_sampling_handler`]. Return [`MCPError::custom`] to decline
+/// a request with a code of your choice; other errors reach the server as
+/// internal errors.
+#[async_trait]
+pub trait SamplingHandler: Send + Sync + 'static {
+    /// Sample the model for the server's request
+    async fn create_message(
+        &self,
+        params: CreateMessageParams,
+    ) -> Result<CreateMessageResult, MCPError>;
+}
+
+/// Answers the server's `elicitation/create` requests
+///
+/// Implemented by the host application, which shows the server's message
+/// and a form for the requested schema, then returns what the user did.
+/// Install it with [`Client::with_elicitation_handler`]; the content of an
+/// accepted elicitation is checked against the requested schema before it
+/// is sent.
+#[async_trait]
+pub trait ElicitationHandler: Send + Sync + 'static {
+    /// Ask the user for the input the server requested
+    async fn elicit(&self, params: ElicitParams) -> Result<ElicitResult, MCPError>;
+}
+
+/// What a [`ToolCallPolicy`] decided about a tool call
+pub enum ToolCallDecision {
+    /// Call the tool
+    Allow,
+    /// Refuse the call, for this reason
+    Deny(String),
+    /// Call the tool if the prompt, typically a question to the user, resolves to `true`
+    Ask(BoxFuture<'static, bool>),
+}
+
+impl fmt::Debug for ToolCallDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ToolCallDecision::Allow => write!(f, "Allow"),
+            ToolCallDecision::Deny(reason) => f.debug_tuple("Deny").field(reason).finish(),
+            ToolCallDecision::Ask(_) => write!(f, "Ask(..)"),
+        }
+    }
+}
+
+/// Decides whether the client may call a tool, before the call is sent
+///
+/// Implemented by the host application to gate dangerous tools, for example
+/// by asking the user to approve them. Install it with
+/// [`Client::with_tool_call_policy`]. Denied calls fail with
+/// [`MCPError::ToolCallDenied`] without reaching the server, and every
+/// decision is logged and published as a [`ClientDiagnostic::ToolCallDecided`].
+#[async_trait]
+pub trait ToolCallPolicy: Send + Sync + 'static {
+    /// Decide about a call of `tool_name` with `arguments`
+    async fn check(&self, tool_name: &str, arguments: &Value) -> ToolCallDecision;
+
+    /// Decide about a call knowing the tool's annotations, if the server listed any
+    ///
+    /// The client calls this; by default it leaves the decision to
+    /// [`ToolCallPolicy::check`]. Annotations are taken from the last tool
+    /// list, so they are missing for tools called before listing them.
+    async fn check_annotated(
+        &self,
+        tool_name: &str,
+        arguments: &Value,
+        annotations: Option<&ToolAnnotations>,
+    ) -> ToolCallDecision {
+        let _ = annotations;
+        self.check(tool_name, arguments).await
+    }
+}
+
+/// A [`ToolCallPolicy`] allowing the tools the server annotated as read-only
+///
+/// Calls of other tools are decided by the wrapped policy.
+pub struct ApproveReadOnly<P>(pub P);
+
+#[async_trait]
+impl<P: ToolCallPolicy> ToolCallPolicy for ApproveReadOnly<P> {
+    async fn check(&self, tool_name: &str, arguments: &Value) -> ToolCallDecision {
+        self.0.check(tool_name, arguments).await
+    }
+
+    async fn check_annotated(
+        &self,
+        tool_name: &str,
+        arguments: &Value,
+        annotations: Option<&ToolAnnotations>,
+    ) -> ToolCallDecision {
+        match annotations {
+            Some(annotations) if annotations.is_read_only() => ToolCallDecision::Allow,
+            _ => {
+                self.0
+                    .check_annotated(tool_name, arguments, annotations)
+                    .await
+            }
+        }
+    }
+}
+
+/// Roots provider function type
+///
+/// Returns a boxed future that resolves to the client's current roots.
+pub type RootsProvider = Box<
+    dyn Fn() -> Pin<Box<dyn Future<Output = Result<Vec<Root>, MCPError>> + Send>> + Send + Sync,
+>;
+
+/// Transport factory function type
+///
+/// Returns a boxed future that resolves to a new, unstarted transport.
+pub type TransportFactory<T> =
+    Arc<dyn Fn() -> Pin<Box<dyn Future<Output = Result<T, MCPError>> + Send>> + Send + Sync>;
+
+/// The roots the client answers `roots/list` with
+///
+/// Roots are cached: static roots always, and computed roots from the first
+/// `roots/list` until they are invalidated.
+struct RootsSource {
+    cache: tokio::sync::Mutex<Option<Vec<Root>>>,
+    provider: Option<RootsProvider>,
+}
+
+impl RootsSource {
+    /// A fixed set of roots
+    fn fixed(roots: Vec<Root>) -> Arc<Self> {
+        Arc::new(Self {
+            cache: tokio::sync::Mutex::new(Some(roots)),
+            provider: None,
+        })
+    }
+
+    /// The current roots, computing them if they are not cached
+    async fn list(&self) -> Result<Vec<Root>, MCPError> {
+        let mut cache = self.cache.lock().await;
+        if let Some(roots) = cache.as_ref() {
+            return Ok(roots.clone());
+        }
+
+        let provider = self
+            .provider
+            .as_ref()
+            .ok_or_else(|| MCPError::Protocol("No roots configured".to_string()))?;
+        let roots = provider().await?;
+        *cache = Some(roots.clone());
+        Ok(roots)
+    }
+}
+
+/// A summary of the negotiated connection, for bug reports
+///
+/// Built from what the client learned during initialization. The `Display`
+/// implementation renders it as plain text; it can also be serialized.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionReport {
+    /// The protocol version the server chose
+    pub protocol_version: Option<String>,
+    /// The server's name and version
+    pub server_info: Option<Implementation>,
+    /// The capabilities the server advertised
+    pub server_capabilities: Option<ServerCapabilities>,
+    /// The capabilities the client advertised
+    pub client_capabilities: Value,
+    /// The transport type in use
+    pub transport: String,
+    /// The current connection state
+    pub state: ConnectionState,
+}
+
+impl fmt::Display for ConnectionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn json<T: Serialize>(value: &T) -> String {
+            serde_json::to_string(value).unwrap_or_else(|_| "<unserializable>".to_string())
+        }
+
+        writeln!(f, "mcpr {}", crate::VERSION)?;
+        writeln!(
+            f,
+            "Protocol version:    {}",
+            self.protocol_version
+                .as_deref()
+                .unwrap_or("<not negotiated>")
+        )?;
+        match &self.server_info {
+            Some(info) => writeln!(f, "Server:              {} {}", info.name, info.version)?,
+            None => writeln!(f, "Server:              <unknown>")?,
+        }
+        match &self.server_capabilities {
+            Some(capabilities) => writeln!(f, "Server capabilities: {}", json(capabilities))?,
+            None => writeln!(f, "Server capabilities: <unknown>")?,
+        }
+        writeln!(f, "Client capabilities: {}", self.client_capabilities)?;
+        writeln!(f, "Transport:           {}", self.transport)?;
+        write!(f, "State:               {:?}", self.state)
+    }
+}
+
+/// What a request waiting for its response can receive
+enum Incoming<'a> {
+    /// Messages read from the transport, while holding the read lease
+    Payload(tokio::sync::MutexGuard<'a, ()>, JSONRPCPayload),
+    /// The response, read and handed over by another clone sharing the transport
+    HandedOver(JSONRPCMessage),
+}
+
+/// The task reading the connection while the client is idle
+///
+/// Shared by the clones of a client, and aborted once the last is dropped,
+/// so the task does not keep the connection open on its own.
+#[cfg(feature = "runtime-tokio")]
+#[derive(Default)]
+struct BackgroundReader(Mutex<Option<tokio::task::JoinHandle<()>>>);
+
+#[cfg(feature = "runtime-tokio")]
+impl BackgroundReader {
+    /// Make `reader` the running task, stopping the previous one
+    fn replace(&self, reader: tokio::task::JoinHandle<()>) {
+        if let Some(previous) = self.0.lock().unwrap().replace(reader) {
+            previous.abort();
+        }
+    }
+
+    fn stop(&self) {
+        if let Some(reader) = self.0.lock().unwrap().take() {
+            reader.abort();
+        }
+    }
+}
+
+#[cfg(feature = "runtime-tokio")]
+impl Drop for BackgroundReader {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// High-level MCP client
+pub struct Client<T: Transport + Send + Sync> {
+    transport: T,
+    transport_factory: Option<TransportFactory<T>>,
+    next_request_id: Arc<AtomicI64>,
+    timeout_duration: Option<Duration>,
+    reconnect_policy: Option<ReconnectPolicy>,
+    state: Arc<watch::Sender<ConnectionState>>,
+    server_capabilities: Option<ServerCapabilities>,
+    server_info: Option<Implementation>,
+    protocol_version: Option<String>,
+    pending: PendingRequests,
+    cancelled: CancelledRequests,
+    unsent_cancellations: UnsentCancellations,
+    late_response_policy: LateResponsePolicy,
+    diagnostics: broadcast::Sender<ClientDiagnostic>,
+    events: broadcast::Sender<ClientEvent>,
+    /// Whether the loss of the current connection was published already
+    loss_reported: Arc<AtomicBool>,
+    slow_request_threshold: Option<Duration>,
+    /// Name and version sent as `clientInfo` during initialization
+    client_info: Implementation,
+    /// Protocol version requested during initialization
+    requested_protocol_version: String,
+    /// Capabilities declared with [`Client::with_capability`]
+    declared_capabilities: Map<String, Value>,
+    jsonrpc_version: String,
+    rate_limit_retries: u32,
+    retry_policy: Option<RetryPolicy>,
+    stats: Arc<SessionStats>,
+    request_handlers: Arc<Mutex<HashMap<String, AsyncRequestHandler>>>,
+    notification_handlers: Arc<Mutex<HashMap<String, Vec<NotificationHandler>>>>,
+    progress_listeners: ProgressListeners,
+    accepted_content_types: Option<Vec<String>>,
+    chunked_uploads: bool,
+    single_flight: bool,
+    in_flight: InFlightRequests,
+    /// Answers to read-only requests kept for reuse
+    response_cache: Option<ResponseCache>,
+    /// `_meta` fields sent with every request
+    request_meta: Meta,
+    /// Annotations of the tools, from the last tool list
+    tool_annotations: Arc<Mutex<HashMap<String, ToolAnnotations>>>,
+    roots: Option<Arc<RootsSource>>,
+    /// The resource list from the last `list_resources`, kept up to date
+    resources: Arc<Mutex<Option<Vec<Resource>>>>,
+    resource_updates: broadcast::Sender<ResourceUpdatedParams>,
+    log_messages: broadcast::Sender<LoggingMessageParams>,
+    /// The level last set with [`Client::set_log_level`], restored on reconnect
+    log_level: Arc<Mutex<Option<LoggingLevel>>>,
+    /// Whether log messages from the server are passed to the `log` crate
+    forward_logs: bool,
+    /// URIs of the resources the client is subscribed to
+    subscriptions: Arc<Mutex<HashSet<String>>>,
+    /// Notifications not yet taken by [`Client::next_notification_where`]
+    notifications: Arc<Mutex<VecDeque<JSONRPCNotification>>>,
+    /// Woken whenever a notification is added to `notifications`
+    notification_arrived: Arc<Notify>,
+    /// Held by the clone currently reading the transport for all the others
+    read_lease: Arc<TokioMutex<()>>,
+    /// Woken when a request starts waiting, so an idle reader hands over the lease
+    lease_wanted: Arc<Notify>,
+    /// Woken when the last request in flight resolves, so an idle reader reads on
+    idle: Arc<Notify>,
+    /// Whether to read the connection while idle, once initialized
+    background_reading: bool,
+    #[cfg(feature = "runtime-tokio")]
+    background_reader: Arc<BackgroundReader>,
+    parse_mode: ParseMode,
+    validate_output: bool,
+    validate_arguments: bool,
+    clock: Arc<dyn Clock>,
+    /// Output schemas of the tools from the last `tools/list`, by tool name
+    output_schemas: Arc<Mutex<HashMap<String, Value>>>,
+    /// Input schemas of the tools from the last `tools/list`, by tool name
+    input_schemas: Arc<Mutex<HashMap<String, Value>>>,
+    /// Round-trip time of the last answered ping
+    ping_latency: Arc<Mutex<Option<Duration>>>,
+    tool_call_policy: Option<Arc<dyn ToolCallPolicy>>,
+    audit: Option<Auditor>,
+    /// Tools called without consulting the policy
+    allowed_tools: HashSet<String>,
+    /// Tools never called
+    denied_tools: HashSet<String>,
+}
+
+/// Clients are cheap handles to one connection
+///
+/// Requests from clones are multiplexed over the shared transport and can
+/// be awaited concurrently: whichever waiting request holds the read lease
+/// reads the transport for all of them, routing each response to its
+/// request by id, and hands the lease on when its own response arrives.
+/// The transport's clones must share its connection, as those of
+/// [`StdioTransport`](crate::transport::stdio::StdioTransport) and the HTTP
+/// transports do. Only the original client reconnects.
+impl<T: Transport + Clone + Send + Sync> Clone for Client<T> {
+    fn clone(&self) -> Self {
+        self.share()
+    }
+}
+
+impl<T: Transport + Send + Sync> Client<T> {
+    /// Create a new MCP client with the given transport
+    pub fn new(transport: T) -> Self {
+        Self {
+            transport,
+            transport_factory: None,
+            next_request_id: Arc::new(AtomicI64::new(1)),
+            timeout_duration: None,
+            reconnect_policy: None,
+            state: Arc::new(watch::channel(ConnectionState::Disconnected).0),
+            server_capabilities: None,
+            server_info: None,
+            protocol_version: None,
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            cancelled: Arc::new(Mutex::new(HashMap::new())),
+            unsent_cancellations: Arc::new(Mutex::new(Vec::new())),
+            late_response_policy: LateResponsePolicy::default(),
+            diagnostics: broadcast::channel(64).0,
+            events: broadcast::channel(64).0,
+            loss_reported: Arc::new(AtomicBool::new(false)),
+            slow_request_threshold: None,
+            client_info: Implementation {
+                name: "mcpr".to_string(),
+                version: crate::VERSION.to_string(),
+            },
+            requested_protocol_version: LATEST_PROTOCOL_VERSION.to_string(),
+            declared_capabilities: Map::new(),
+            jsonrpc_version: JSONRPC_VERSION.to_string(),
+            rate_limit_retries: 0,
+            retry_policy: None,
+            stats: Arc::new(SessionStats::default()),
+            request_handlers: Arc::new(Mutex::new(HashMap::new())),
+            notification_handlers: Arc::new(Mutex::new(HashMap::new())),
+            progress_listeners: Arc::new(Mutex::new(HashMap::new())),
+            accepted_content_types: None,
+            chunked_uploads: false,
+            single_flight: false,
+            response_cache: None,
+            request_meta: Meta::new(),
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            tool_annotations: Arc::new(Mutex::new(HashMap::new())),
+            roots: None,
+            resources: Arc::new(Mutex::new(None)),
+            resource_updates: broadcast::channel(64).0,
+            log_messages: broadcast::channel(64).0,
+            log_level: Arc::new(Mutex::new(None)),
+            forward_logs: false,
+            subscriptions: Arc::new(Mutex::new(HashSet::new())),
+            notifications: Arc::new(Mutex::new(VecDeque::new())),
+            notification_arrived: Arc::new(Notify::new()),
+            read_lease: Arc::new(TokioMutex::new(())),
+            lease_wanted: Arc::new(Notify::new()),
+            idle: Arc::new(Notify::new()),
+            background_reading: true,
+            #[cfg(feature = "runtime-tokio")]
+            background_reader: Arc::default(),
+            parse_mode: ParseMode::Lenient,
+            validate_output: false,
+            validate_arguments: false,
+            clock: Arc::new(SystemClock),
+            output_schemas: Arc::new(Mutex::new(HashMap::new())),
+            input_schemas: Arc::new(Mutex::new(HashMap::new())),
+            ping_latency: Arc::new(Mutex::new(None)),
+            tool_call_policy: None,
+            audit: None,
+            allowed_tools: HashSet::new(),
+            denied_tools: HashSet::new(),
+        }
+    }
+
+    /// Create a client whose transport is built by `factory`
+    ///
+    /// The factory is called once now, and again by [`Client::reconnect`]
+    /// for a fresh connection each time; see [`Client::with_transport_factory`].
+    pub async fn from_transport_factory<F, Fut>(factory: F) -> Result<Self, MCPError>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T, MCPError>> + Send + 'static,
+    {
+        let transport = factory().await?;
+        Ok(Self::new(transport).with_transport_factory(factory))
+    }
+
+    /// Rebuild the transport with `factory` whenever the client reconnects
+    ///
+    /// Without a factory, reconnecting restarts the existing transport, which
+    /// is not possible for transports that cannot be reopened once closed,
+    /// such as a spawned child process. A factory can also pick another
+    /// server to fail over to.
+    pub fn with_transport_factory<F, Fut>(mut self, factory: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T, MCPError>> + Send + 'static,
+    {
+        self.transport_factory = Some(Arc::new(move || {
+            Box::pin(factory()) as Pin<Box<dyn Future<Output = Result<T, MCPError>> + Send>>
+        }));
+        self
+    }
+
+    /// Set a timeout for operations
+    pub fn with_timeout(mut self, duration: Duration) -> Self {
+        self.timeout_duration = Some(duration);
+        self
+    }
+
+    /// Reconnect automatically with the given policy when the transport fails
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = Some(policy);
+        self
+    }
+
+    /// Log a warning for any request still pending after the given duration
+    pub fn with_slow_request_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_request_threshold = Some(threshold);
+        self
+    }
+
+    /// Choose what happens to responses that arrive after their request was cancelled
+    pub fn with_late_response_policy(mut self, policy: LateResponsePolicy) -> Self {
+        self.late_response_policy = policy;
+        self
+    }
+
+    /// Advertise the content types this client can render
+    ///
+    /// Entries are content block types (`"text"`, `"image"`, `"audio"`,
+    /// `"resource"`), MIME types (`"image/png"`) or wildcards (`"image/*"`).
+    /// They are sent during initialization as an experimental capability, so
+    /// servers can avoid returning content the client cannot use. When unset,
+    /// the client accepts everything.
+    pub fn with_accepted_content_types<I, S>(mut self, content_types: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.accepted_content_types = Some(content_types.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Chunk tool calls that exceed the server's advertised message size
+    ///
+    /// Servers that support the chunked-upload convention advertise a
+    /// `maxMessageSize`. With this enabled, a [`Client::call_tool`] request
+    /// larger than that is sent as a sequence of `uploads/chunk` requests and
+    /// committed with a `tools/call` that references the upload. Requests that
+    /// fit, and calls to servers without the capability, are sent unchanged.
+    /// See [`UploadChunkParams`] for the convention.
+    pub fn with_chunked_uploads(mut self) -> Self {
+        self.chunked_uploads = true;
+        self
+    }
+
+    /// Coalesce identical concurrent read-only requests into one
+    ///
+    /// When enabled, a request with the same method and params as one already
+    /// in flight (for example from another task of
+    /// [`Client::call_tools_concurrent`]) is not sent again: it waits for the
+    /// first and gets the same response. Only read-only requests are
+    /// coalesced: listings, `resources/read`, `prompts/get`, and `tools/call`
+    /// for tools the server annotated with `readOnlyHint` in its tool list.
+    pub fn with_single_flight(mut self, enabled: bool) -> Self {
+        self.single_flight = enabled;
+        self
+    }
+
+    /// Read the server's messages while no request is waiting, enabled by default
+    ///
+    /// Once initialized, the client reads through another handle on the
+    /// connection in a task of its own, as [`Client::spawn_reader`] does, if
+    /// the transport shares its connection with
+    /// [`Transport::share_connection`]. Otherwise, or when disabled, the
+    /// connection is only read while requests wait for their response.
+    pub fn with_background_reading(mut self, enabled: bool) -> Self {
+        self.background_reading = enabled;
+        self
+    }
+
+    /// Answer repeated list requests, and reads of immutable resources, from `cache`
+    ///
+    /// See the [`cache`](crate::cache) module. Keep a clone of the cache to
+    /// invalidate answers explicitly.
+    pub fn with_response_cache(mut self, cache: ResponseCache) -> Self {
+        self.response_cache = Some(cache);
+        self
+    }
+
+    /// Send a `_meta` field with every request, such as a tenant id
+    ///
+    /// Fields set for a call with [`CallOptions::with_meta`] take precedence,
+    /// and neither replaces fields the request sets itself, like the
+    /// progress token. Read `_meta` sent back with [`WithMeta`] results and
+    /// [`JSONRPCNotification::meta`].
+    ///
+    /// [`WithMeta`]: crate::schema::json_rpc::WithMeta
+    pub fn with_request_meta(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.request_meta.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Expose a fixed set of roots to the server
+    ///
+    /// The client advertises the `roots` capability and answers the server's
+    /// `roots/list` requests itself. Use [`Client::set_roots`] to change them.
+    pub fn with_roots<I>(mut self, roots: I) -> Self
+    where
+        I: IntoIterator<Item = Root>,
+    {
+        self.roots = Some(RootsSource::fixed(roots.into_iter().collect()));
+        self
+    }
+
+    /// Expose roots computed on demand to the server
+    ///
+    /// For root sets that are expensive to enumerate. The provider runs on
+    /// the first `roots/list` request and its result is reused for later
+    /// ones, until [`Client::notify_roots_changed`] discards it.
+    pub fn with_roots_provider<F, Fut>(mut self, provider: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Vec<Root>, MCPError>> + Send + 'static,
+    {
+        let provider: RootsProvider = Box::new(move || {
+            let fut = provider();
+            Box::pin(fut) as Pin<Box<dyn Future<Output = Result<Vec<Root>, MCPError>> + Send>>
+        });
+        self.roots = Some(Arc::new(RootsSource {
+            cache: tokio::sync::Mutex::new(None),
+            provider: Some(provider),
+        }));
+        self
+    }
+
+    /// Let the server sample the host's model through `handler`
+    ///
+    /// The client advertises the `sampling` capability and answers the
+    /// server's `sampling/createMessage` requests with the handler.
+    pub fn with_sampling_handler(mut self, handler: impl SamplingHandler) -> Self {
+        let handler = Arc::new(handler);
+        self.register_request_handler("sampling/createMessage", move |context| {
+            let handler = handler.clone();
+            async move {
+                let params = serde_json::from_value(context.params.unwrap_or(Value::Null))
+                    .map_err(|e| MCPError::Rpc {
+                        code: error_codes::INVALID_PARAMS,
+                        message: format!("Invalid sampling/createMessage parameters: {}", e),
+                        data: None,
+                    })?;
+                Ok(serde_json::to_value(handler.create_message(params).await?)?)
+            }
+        });
+        self
+    }
+
+    /// Let the server ask the user for input through `handler`
+    ///
+    /// The client advertises the `elicitation` capability and answers the
+    /// server's `elicitation/create` requests with the handler. Accepted
+    /// content that does not match the requested schema is not sent; the
+    /// server gets an error instead.
+    pub fn with_elicitation_handler(mut self, handler: impl ElicitationHandler) -> Self {
+        let handler = Arc::new(handler);
+        self.register_request_handler("elicitation/create", move |context| {
+            let handler = handler.clone();
+            async move {
+                let params: ElicitParams =
+                    serde_json::from_value(context.params.unwrap_or(Value::Null)).map_err(|e| {
+                        MCPError::Rpc {
+                            code: error_codes::INVALID_PARAMS,
+                            message: format!("Invalid elicitation/create parameters: {}", e),
+                            data: None,
+                        }
+                    })?;
+                let schema = params.requested_schema.clone();
+                let result = handler.elicit(params).await?;
+                if result.action == ElicitAction::Accept {
+                    let content = result.content.as_ref().unwrap_or(&Value::Null);
+                    validation::validate(&schema, content).map_err(|violation| {
+                        MCPError::Protocol(format!(
+                            "Elicited content does not match the requested schema at {}",
+                            violation
+                        ))
+                    })?;
+                }
+                Ok(serde_json::to_value(result)?)
+            }
+        });
+        self
+    }
+
+    /// Check every tool call with `policy` before sending it
+    ///
+    /// Tools listed with [`Client::with_allowed_tools`] or
+    /// [`Client::with_denied_tools`] are decided without the policy.
+    pub fn with_tool_call_policy(mut self, policy: impl ToolCallPolicy) -> Self {
+        self.tool_call_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Record every tool call with `auditor`, including denied ones
+    ///
+    /// See the [`audit`](crate::audit) module. The record's peer is the name
+    /// the server gave on initialization.
+    pub fn with_audit(mut self, auditor: Auditor) -> Self {
+        self.audit = Some(auditor);
+        self
+    }
+
+    /// Call these tools without consulting the [`ToolCallPolicy`]
+    pub fn with_allowed_tools<I, S>(mut self, tools: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowed_tools.extend(tools.into_iter().map(Into::into));
+        self
+    }
+
+    /// Never call these tools; calls fail with [`MCPError::ToolCallDenied`]
+    pub fn with_denied_tools<I, S>(mut self, tools: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.denied_tools.extend(tools.into_iter().map(Into::into));
+        self
+    }
+
+    /// Set the name sent as `clientInfo` during initialization
+    ///
+    /// Defaults to `"mcpr"`.
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.client_info.name = name.to_string();
+        self
+    }
+
+    /// Set the version sent as `clientInfo` during initialization
+    ///
+    /// Defaults to the version of this crate.
+    pub fn with_version(mut self, version: &str) -> Self {
+        self.client_info.version = version.to_string();
+        self
+    }
+
+    /// Declare a capability sent during initialization
+    ///
+    /// For capabilities the client does not derive from its setup, such as
+    /// experimental ones. A declared capability replaces a derived one of
+    /// the same name, like `roots` from [`Client::with_roots`].
+    pub fn with_capability(mut self, name: &str, value: Value) -> Self {
+        self.declared_capabilities.insert(name.to_string(), value);
+        self
+    }
+
+    /// Set the protocol version requested during initialization
+    ///
+    /// Defaults to [`LATEST_PROTOCOL_VERSION`]. The server may answer with
+    /// another version; initialization fails with
+    /// [`MCPError::UnsupportedProtocolVersion`] unless it is one of
+    /// [`SUPPORTED_PROTOCOL_VERSIONS`].
+    pub fn with_protocol_version(mut self, version: &str) -> Self {
+        self.requested_protocol_version = version.to_string();
+        self
+    }
+
+    /// Pass log messages from the server to the `log` crate
+    ///
+    /// Messages are logged with the `mcpr::server` target, prefixed with
+    /// the server's logger name if any. Syslog levels map to the nearest
+    /// `log` level: notice to info, and everything from error up to error.
+    pub fn with_log_forwarding(mut self, enabled: bool) -> Self {
+        self.forward_logs = enabled;
+        self
+    }
+
+    /// Override the JSON-RPC version string sent and expected by this client
+    ///
+    /// This is a compatibility shim for nonconforming servers that reject the
+    /// standard `"2.0"`. Outgoing requests carry the given version, and
+    /// responses are validated against it instead of `"2.0"`. Do not use it
+    /// with servers that follow the specification.
+    pub fn with_jsonrpc_version(mut self, version: &str) -> Self {
+        self.jsonrpc_version = version.to_string();
+        self
+    }
+
+    /// Reject any deviation from the specification instead of working around it
+    ///
+    /// For conformance testing of servers. A strict client turns off its
+    /// compatibility shims and fails with a protocol error that names the
+    /// deviation when:
+    ///
+    /// - the `initialize` result lacks `protocolVersion`, `capabilities` or
+    ///   `serverInfo`, has a malformed one, or has fields the specification
+    ///   does not define
+    /// - a message carries a JSON-RPC version other than `"2.0"`, even with
+    ///   [`Client::with_jsonrpc_version`]
+    /// - a request whose result must be empty, like `ping`, gets fields back
+    /// - a response arrives for an id that was never sent
+    /// - a tool result has a content item with fields its `type` does not
+    ///   define, which a lenient client ignores
+    /// - a result has fields its type does not define, see [`ParseMode`]
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.parse_mode = if strict {
+            ParseMode::Strict
+        } else {
+            ParseMode::Lenient
+        };
+        self
+    }
+
+    /// Parse messages leniently or strictly, like [`Client::with_strict`]
+    pub fn with_parse_mode(mut self, parse_mode: ParseMode) -> Self {
+        self.parse_mode = parse_mode;
+        self
+    }
+
+    /// Use `clock` for timeouts, reconnect backoff and other waits
+    ///
+    /// For tests, with a [`MockClock`](crate::clock::MockClock) that is
+    /// advanced by hand.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Validate the structured content of tool results against the tool's `outputSchema`
+    ///
+    /// The schemas are learned from `tools/list` responses, so only tools
+    /// listed since this was enabled are checked. A result that does not
+    /// match, or a successful result without structured content from a tool
+    /// that declares a schema, makes [`Client::call_tool`] return
+    /// [`MCPError::Protocol`] naming the failing path. Off by default.
+    pub fn with_validate_output(mut self, enabled: bool) -> Self {
+        self.validate_output = enabled;
+        self
+    }
+
+    /// Retry idempotent requests that fail on the way, according to `policy`
+    ///
+    /// Tool calls are only retried for tools the server annotated as
+    /// read-only or idempotent in its tool list; see [`RetryPolicy`]. Off by
+    /// default.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Validate the arguments of tool calls against the tool's `inputSchema`
+    ///
+    /// The schemas are learned from `tools/list` responses, so only tools
+    /// listed since this was enabled are checked. Arguments that do not match
+    /// make [`Client::call_tool`] return [`MCPError::InvalidArguments`] naming
+    /// the failing path, without sending the call. Off by default.
+    pub fn with_validate_arguments(mut self, enabled: bool) -> Self {
+        self.validate_arguments = enabled;
+        self
+    }
+
+    /// Retry rate-limited tool calls up to `max_retries` times
+    ///
+    /// A tool call is considered rate limited when the server answers with a
+    /// [`RATE_LIMITED`] JSON-RPC error, optionally carrying a `retryAfter` (in
+    /// seconds) in its data, or with a tool error result (`isError: true`)
+    /// carrying a `retryAfter` at the top level or in `_meta`. Errors with
+    /// other codes are returned as is, whatever their data holds. The client waits
+    /// for the indicated delay (one second if none is given, at most
+    /// [`MAX_RATE_LIMIT_DELAY`]) before retrying.
+    pub fn with_retry_on_rate_limit(mut self, max_retries: u32) -> Self {
+        self.rate_limit_retries = max_retries;
+        self
+    }
+
+    /// Check if the client is connected to the server
+    pub fn is_connected(&self) -> bool {
+        *self.state.borrow() == ConnectionState::Connected
+    }
+
+    /// Get the current connection state
+    pub fn state(&self) -> ConnectionState {
+        self.state.borrow().clone()
+    }
+
+    /// Subscribe to connection state changes, including every reconnect attempt
+    pub fn subscribe_state(&self) -> watch::Receiver<ConnectionState> {
+        self.state.subscribe()
+    }
+
+    /// Subscribe to `notifications/resources/updated` from the server
+    ///
+    /// Like other notifications, updates arrive as they are read; see
+    /// [`Client::with_background_reading`].
+    pub fn subscribe_resource_updates(&self) -> broadcast::Receiver<ResourceUpdatedParams> {
+        self.resource_updates.subscribe()
+    }
+
+    /// Subscribe to diagnostic events, such as late responses to cancelled requests
+    pub fn subscribe_diagnostics(&self) -> broadcast::Receiver<ClientDiagnostic> {
+        self.diagnostics.subscribe()
+    }
+
+    /// Responses with unknown ids and messages that failed to parse, as they are skipped
+    ///
+    /// The protocol errors among the [diagnostics](Self::subscribe_diagnostics).
+    pub fn protocol_errors(&self) -> impl Stream<Item = ClientDiagnostic> + Send + 'static {
+        stream::unfold(self.diagnostics.subscribe(), |mut diagnostics| async move {
+            loop {
+                match diagnostics.recv().await {
+                    Ok(diagnostic) if diagnostic.is_protocol_error() => {
+                        return Some((diagnostic, diagnostics))
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("Missed {} client diagnostics", missed)
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+
+    /// Lifecycle events of the connection, as they happen
+    ///
+    /// Connection, initialization, loss and failed requests are published,
+    /// so a user interface can show the state of the connection and errors
+    /// without reading logs. Events published before the call are not seen.
+    /// An idle client notices notifications and a lost connection only while
+    /// it reads in the background; see [`Client::with_background_reading`].
+    pub fn events(&self) -> impl Stream<Item = ClientEvent> + Send + 'static {
+        stream::unfold(self.events.subscribe(), |mut events| async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => return Some((event, events)),
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("Missed {} client events", missed)
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+
+    /// Publish a lifecycle event; nobody listening is fine
+    fn emit(&self, event: ClientEvent) {
+        let _ = self.events.send(event);
+    }
+
+    /// Publish the loss of the connection, once per connection
+    async fn report_lost(&self, reason: String) {
+        if self.loss_reported.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        self.emit(ClientEvent::Disconnected { reason });
+        if let Some(status) = self.transport.exit_status().await {
+            self.emit(ClientEvent::ChildProcessExited { status });
+        }
+    }
+
+    /// Summarize the negotiated connection, for pasting into bug reports
+    pub fn connection_report(&self) -> ConnectionReport {
+        ConnectionReport {
+            protocol_version: self.protocol_version.clone(),
+            server_info: self.server_info.clone(),
+            server_capabilities: self.server_capabilities.clone(),
+            client_capabilities: self.client_capabilities(),
+            transport: std::any::type_name::<T>().to_string(),
+            state: self.state(),
+        }
+    }
+
+    /// The capabilities this client advertises during initialization
+    fn client_capabilities(&self) -> Value {
+        let mut capabilities = serde_json::json!({});
+        if self.roots.is_some() {
+            capabilities["roots"] = serde_json::json!({ "listChanged": true });
+        }
+        if self
+            .request_handlers
+            .lock()
+            .unwrap()
+            .contains_key("sampling/createMessage")
+        {
+            capabilities["sampling"] = serde_json::json!({});
+        }
+        if self
+            .request_handlers
+            .lock()
+            .unwrap()
+            .contains_key("elicitation/create")
+        {
+            capabilities["elicitation"] = serde_json::json!({});
+        }
+        if let Some(content_types) = &self.accepted_content_types {
+            capabilities["experimental"] = serde_json::json!({
+                ACCEPTED_CONTENT_TYPES_CAPABILITY: content_types
+            });
+        }
+        for (name, value) in &self.declared_capabilities {
+            capabilities[name] = value.clone();
+        }
+        capabilities
+    }
+
+    /// Get the protocol version the server chose during initialization
+    ///
+    /// One of [`SUPPORTED_PROTOCOL_VERSIONS`], so callers can adapt to
+    /// features that differ between versions.
+    pub fn protocol_version(&self) -> Option<&str> {
+        self.protocol_version.as_deref()
+    }
+
+    /// Get the capabilities the server advertised during initialization
+    pub fn server_capabilities(&self) -> Option<&ServerCapabilities> {
+        self.server_capabilities.as_ref()
+    }
+
+    /// Register a handler for requests the server sends to the client
+    ///
+    /// Requests for methods without a handler are answered with a "method
+    /// not found" error. Registering a handler for the same method again
+    /// replaces the previous one.
+    pub fn register_request_handler<F, Fut>(&mut self, method: &str, handler: F)
+    where
+        F: Fn(RequestContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Value, MCPError>> + Send + 'static,
+    {
+        let async_handler: AsyncRequestHandler = Box::new(move |context| {
+            let fut = handler(context);
+            Box::pin(fut) as Pin<Box<dyn Future<Output = Result<Value, MCPError>> + Send>>
+        });
+
+        self.request_handlers
+            .lock()
+            .unwrap()
+            .insert(method.to_string(), async_handler);
+    }
+
+    /// Call `handler` for each notification the server sends with `method`
+    ///
+    /// Handlers run as notifications arrive, including progress for the
+    /// request being awaited; without background reading (see
+    /// [`Client::with_background_reading`]), notifications arriving while the
+    /// client is idle wait for the next request. Handlers add up: all handlers
+    /// registered for a method are called, in registration order, and are
+    /// shared by clones of the client. They run on the task reading the
+    /// transport and should not block.
+    pub fn on_notification<F>(&mut self, method: &str, handler: F)
+    where
+        F: Fn(&JSONRPCNotification) + Send + Sync + 'static,
+    {
+        self.notification_handlers
+            .lock()
+            .unwrap()
+            .entry(method.to_string())
+            .or_default()
+            .push(Arc::new(handler));
+    }
+
+    /// Replace the roots exposed to the server, and notify it of the change
+    ///
+    /// Roots set here are used instead of any roots provider from now on.
+    pub async fn set_roots<I>(&mut self, roots: I) -> Result<(), MCPError>
+    where
+        I: IntoIterator<Item = Root>,
+    {
+        let roots: Vec<Root> = roots.into_iter().collect();
+        self.edit_roots(move |current| {
+            *current = roots;
+            true
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Expose another root to the server, and notify it of the change
+    ///
+    /// A root with the same URI is replaced. Like [`Client::set_roots`], this
+    /// fixes the roots: a roots provider is not run again.
+    pub async fn add_root(&mut self, root: Root) -> Result<(), MCPError> {
+        self.edit_roots(move |roots| {
+            roots.retain(|existing| existing.uri != root.uri);
+            roots.push(root);
+            true
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Stop exposing the root with `uri`, notifying the server if it was exposed
+    ///
+    /// Returns whether there was such a root.
+    pub async fn remove_root(&mut self, uri: &str) -> Result<bool, MCPError> {
+        self.edit_roots(|roots| {
+            let before = roots.len();
+            roots.retain(|root| root.uri != uri);
+            roots.len() != before
+        })
+        .await
+    }
+
+    /// Apply `edit` to the current roots and notify the server if it changed them
+    ///
+    /// A fixed set of roots is edited in place, so clones see the change.
+    async fn edit_roots<F>(&mut self, edit: F) -> Result<bool, MCPError>
+    where
+        F: FnOnce(&mut Vec<Root>) -> bool,
+    {
+        let mut roots = match &self.roots {
+            Some(source) => source.list().await?,
+            None => Vec::new(),
+        };
+        if !edit(&mut roots) {
+            return Ok(false);
+        }
+
+        match &self.roots {
+            Some(source) if source.provider.is_none() => *source.cache.lock().await = Some(roots),
+            _ => self.roots = Some(RootsSource::fixed(roots)),
+        }
+        self.send_roots_list_changed().await?;
+        Ok(true)
+    }
+
+    /// Notify the server that the roots changed
+    ///
+    /// Roots computed by a provider are discarded, so the next `roots/list`
+    /// runs the provider again.
+    pub async fn notify_roots_changed(&mut self) -> Result<(), MCPError> {
+        if let Some(source) = &self.roots {
+            if source.provider.is_some() {
+                *source.cache.lock().await = None;
+            }
+        }
+        self.send_roots_list_changed().await
+    }
+
+    async fn send_roots_list_changed(&mut self) -> Result<(), MCPError> {
+        let mut notification =
+            JSONRPCNotification::new("notifications/roots/list_changed".to_string(), None);
+        notification.jsonrpc = self.jsonrpc_version().to_string();
+        self.transport
+            .send(&JSONRPCMessage::Notification(notification))
+            .await
+    }
+
+    /// Tell the server that a request is no longer wanted
+    ///
+    /// Sends `notifications/cancelled` for the request, with an optional reason
+    /// the server can log, such as `"timeout"`. The server stops working on
+    /// the request and does not answer it; a response that arrives anyway is
+    /// handled according to the [`LateResponsePolicy`].
+    ///
+    /// Requests are also cancelled without calling this: right away when they
+    /// time out, and with the next message sent when their future is dropped.
+    pub async fn cancel(&mut self, id: RequestId, reason: Option<&str>) -> Result<(), MCPError> {
+        self.send_cancelled(id.clone(), reason).await?;
+
+        let method = self
+            .pending
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|request| request.method.clone());
+        let mut cancelled = self.cancelled.lock().unwrap();
+        cancelled.entry(id).or_insert_with(|| CancelledRequest {
+            method: method.unwrap_or_default(),
+            cancelled_at: Instant::now(),
+        });
+        Ok(())
+    }
+
+    /// Send `notifications/cancelled` for a request
+    async fn send_cancelled(
+        &mut self,
+        id: RequestId,
+        reason: Option<&str>,
+    ) -> Result<(), MCPError> {
+        let params = CancelledParams {
+            request_id: id,
+            reason: reason.map(str::to_string),
+        };
+        let mut notification = JSONRPCNotification::new(
+            "notifications/cancelled".to_string(),
+            Some(serde_json::to_value(params)?),
+        );
+        notification.jsonrpc = self.jsonrpc_version().to_string();
+        self.transport
+            .send(&JSONRPCMessage::Notification(notification))
+            .await
+    }
+
+    /// Tell the server about requests dropped since the client last sent anything
+    async fn send_unsent_cancellations(&mut self) -> Result<(), MCPError> {
+        let ids = std::mem::take(&mut *self.unsent_cancellations.lock().unwrap());
+        for id in ids {
+            self.send_cancelled(id, Some("request dropped")).await?;
+        }
+        Ok(())
+    }
+
+    /// List the requests that are still waiting for a response
+    ///
+    /// Intended for diagnostics, such as finding stuck requests. The list is
+    /// ordered from the longest-waiting request to the most recent one.
+    pub fn pending_requests(&self) -> Vec<PendingRequestInfo> {
+        snapshot_pending(&self.pending)
+    }
+
+    /// Initialize the client
+    ///
+    /// Returns what the server negotiated. Fields the server left out or
+    /// sent malformed are empty; use [`Client::initialize_raw`] for the
+    /// result exactly as the server sent it.
+    pub async fn initialize(&mut self) -> Result<InitializeResult, MCPError> {
+        self.initialize_raw()
+            .await
+            .map(|result| lenient_initialize_result(&result))
+    }
+
+    /// Initialize the client, returning the server's result unparsed
+    pub async fn initialize_raw(&mut self) -> Result<Value, MCPError> {
+        if !matches!(self.state(), ConnectionState::Reconnecting { .. }) {
+            self.state.send_replace(ConnectionState::Connecting);
+        }
+
+        let result = self.handshake().await;
+        if let Err(e) = &result {
+            self.emit(ClientEvent::RequestFailed {
+                method: "initialize".to_string(),
+                error: e.to_string(),
+            });
+            if matches!(e, MCPError::Transport(_) | MCPError::ConnectionClosed) {
+                self.report_lost(e.to_string()).await;
+            }
+        }
+        if result.is_ok() {
+            self.stats
+                .connected_at
+                .lock()
+                .unwrap()
+                .get_or_insert_with(Instant::now);
+            self.state.send_replace(ConnectionState::Connected);
+            #[cfg(feature = "runtime-tokio")]
+            self.start_background_reader();
+        } else if self.state() == ConnectionState::Connecting {
+            self.state.send_replace(ConnectionState::Disconnected);
+        }
+        result
+    }
+
+    /// Re-establish the connection to the server
+    ///
+    /// The transport is closed and restarted, or replaced by a new one from
+    /// the transport factory if the client has one, and the initialize
+    /// handshake is replayed. Attempts are spaced according to the configured
+    /// [`ReconnectPolicy`] (or its defaults). Once the policy's `max_elapsed`
+    /// has passed the client gives up and transitions to
+    /// [`ConnectionState::Closed`]. Resource subscriptions and the log level
+    /// are restored on the new connection.
+    pub async fn reconnect(&mut self) -> Result<InitializeResult, MCPError> {
+        let policy = self.reconnect_policy.clone().unwrap_or_default();
+        let started = self.clock.now();
+        let elapsed = |clock: &dyn Clock| clock.now() - started;
+        let mut attempt = 0;
+
+        // The old connection may already be gone, so errors here are expected
+        #[cfg(feature = "runtime-tokio")]
+        self.background_reader.stop();
+        let _ = self.transport.close().await;
+
+        loop {
+            attempt += 1;
+            let delay = policy.delay(attempt);
+
+            if let Some(max_elapsed) = policy.max_elapsed {
+                if elapsed(&*self.clock) + delay > max_elapsed {
+                    self.mark_closed();
+                    return Err(MCPError::Transport(format!(
+                        "Reconnect gave up after {} attempts in {:?}",
+                        attempt - 1,
+                        elapsed(&*self.clock)
+                    )));
+                }
+            }
+
+            self.state
+                .send_replace(ConnectionState::Reconnecting { attempt });
+            self.clock.sleep(delay).await;
+
+            info!("Reconnect attempt {}", attempt);
+            let result = match self.transport_factory.clone() {
+                Some(factory) => match factory().await {
+                    Ok(transport) => {
+                        self.stats.add_traffic(self.transport.traffic());
+                        self.transport = transport;
+                        self.initialize().await
+                    }
+                    Err(e) => Err(e),
+                },
+                None => self.initialize().await,
+            };
+            match result {
+                Ok(result) => {
+                    self.restore_session().await;
+                    return Ok(result);
+                }
+                Err(e) => {
+                    warn!("Reconnect attempt {} failed: {}", attempt, e);
+                    let _ = self.transport.close().await;
+                }
+            }
+        }
+    }
+
+    /// Renegotiate the session over the existing connection
+    ///
+    /// Sends `initialize` again with the client's current capabilities, such
+    /// as a sampling handler or roots added since the session started, and
+    /// updates the server capabilities, server info and protocol version.
+    ///
+    /// The specification expects one `initialize` per connection, so a server
+    /// may refuse a second one. If the server answers with an error, or the
+    /// exchange fails, the client falls back to [`Client::reconnect`], which
+    /// negotiates on a fresh connection. A client that is not connected is
+    /// simply initialized.
+    pub async fn reinitialize(&mut self) -> Result<InitializeResult, MCPError> {
+        if self.state() != ConnectionState::Connected {
+            return self.initialize().await;
+        }
+
+        match self.negotiate().await {
+            Ok(result) => {
+                self.emit_initialized();
+                Ok(lenient_initialize_result(&result))
+            }
+            Err(e) => {
+                warn!(
+                    "Server did not renegotiate on the existing connection, reconnecting: {}",
+                    e
+                );
+                self.reconnect().await
+            }
+        }
+    }
+
+    /// Start the transport and perform the initialize handshake
+    async fn handshake(&mut self) -> Result<Value, MCPError> {
+        self.transport.start().await?;
+        self.loss_reported.store(false, Ordering::Relaxed);
+        self.emit(ClientEvent::Connected);
+        let result = self.negotiate().await?;
+        self.emit_initialized();
+        Ok(result)
+    }
+
+    fn emit_initialized(&self) {
+        self.emit(ClientEvent::Initialized {
+            protocol_version: self.protocol_version.clone(),
+            server_info: self.server_info.clone(),
+        });
+    }
+
+    /// Send `initialize` and store what the server negotiated
+    async fn negotiate(&mut self) -> Result<Value, MCPError> {
+        // Send initialization request; strict servers reject it without a
+        // capabilities object, so one is sent even when nothing is advertised
+        let params = serde_json::json!({
+            "protocolVersion": self.requested_protocol_version,
+            "capabilities": self.client_capabilities(),
+            "clientInfo": self.client_info
+        });
+        let initialize_request = JSONRPCRequest::new(
+            self.next_request_id(),
+            "initialize".to_string(),
+            Some(params),
+        );
+
+        let response = self.exchange(initialize_request).await?;
+
+        match response {
+            JSONRPCMessage::Response(resp) => {
+                if self.parse_mode.is_strict() {
+                    check_initialize_result(&resp.result)?;
+                    self.parse_mode.check_result("initialize", &resp.result)?;
+                }
+
+                // Parsed leniently, so an unusual field does not fail the handshake
+                let field = |name: &str| resp.result.get(name).cloned();
+                self.server_capabilities =
+                    field("capabilities").and_then(|c| serde_json::from_value(c).ok());
+                self.server_info = field("serverInfo").and_then(|i| serde_json::from_value(i).ok());
+                self.protocol_version =
+                    field("protocolVersion").and_then(|v| v.as_str().map(str::to_string));
+                if let Some(offered) = &self.protocol_version {
+                    if !SUPPORTED_PROTOCOL_VERSIONS.contains(&offered.as_str()) {
+                        return Err(MCPError::UnsupportedProtocolVersion {
+                            requested: self.requested_protocol_version.clone(),
+                            offered: offered.clone(),
+                        });
+                    }
+                }
+                Ok(resp.result)
+            }
+            JSONRPCMessage::Error(err) => {
+                debug!("Initialization failed: {:?}", err);
+                Err(err.error.into())
+            }
+            _ => Err(MCPError::Protocol("Unexpected response type".to_string())),
+        }
+    }
+
+    /// List tools on a server
+    pub async fn list_tools<R: DeserializeOwned + Send + Sync>(&mut self) -> Result<R, MCPError> {
+        self.send_request("tools/list", None).await
+    }
+
+    /// Call a tool on the server
+    pub async fn call_tool<P: Serialize + Send + Sync, R: DeserializeOwned + Send + Sync>(
+        &mut self,
+        tool_name: &str,
+        params: &P,
+    ) -> Result<R, MCPError> {
+        let params = serde_json::json!({
+            "name": tool_name,
+            "arguments": serde_json::to_value(params)?
+        });
+        self.send_tool_call(tool_name, params).await
+    }
+
+    /// Call a tool with options for this call only
+    ///
+    /// With a timeout, the call fails with [`MCPError::Timeout`] once the
+    /// timeout elapses, counting retries of rate-limited calls, and the
+    /// request in flight is cancelled on the wire. Unlike the timeout set
+    /// with [`Client::with_timeout`], which bounds the wait for each message
+    /// from the server, this bounds the whole call.
+    pub async fn call_tool_with_options<P, R>(
+        &mut self,
+        tool_name: &str,
+        params: &P,
+        options: CallOptions,
+    ) -> Result<R, MCPError>
+    where
+        P: Serialize + Send + Sync,
+        R: DeserializeOwned + Send + Sync,
+    {
+        let clock = self.clock.clone();
+        let call = CALL_META.scope(options.meta, self.call_tool(tool_name, params));
+        let Some(timeout) = options.timeout else {
+            return call.await;
+        };
+
+        let call = CALL_DEADLINE.scope(clock.now() + timeout, call);
+        clock::timeout(&*clock, timeout, call)
+            .await
+            .unwrap_or_else(|| {
+                Err(MCPError::Timeout(format!(
+                    "Tool '{}' timed out after {:?}",
+                    tool_name, timeout
+                )))
+            })
+    }
+
+    /// Call a tool, asking the server to report progress
+    ///
+    /// Returns the progress the server reports, as a stream that ends with
+    /// the call, and the call itself, which must be awaited for the request
+    /// to be sent. Like other notifications, progress is read while the
+    /// client waits for a response, so poll both together:
+    ///
+    /// ```rust,ignore
+    /// let (progress, result) = client.call_tool_with_progress("index", &args);
+    /// let (_, result) = tokio::join!(
+    ///     progress.for_each(|p| async move { println!("{}/{:?}", p.progress, p.total) }),
+    ///     result,
+    /// );
+    /// let result: CallToolResult = result?;
+    /// ```
+    pub fn call_tool_with_progress<'a, P, R>(
+        &'a mut self,
+        tool_name: &str,
+        params: &P,
+    ) -> (
+        impl Stream<Item = ProgressParams> + Send + 'static,
+        impl Future<Output = Result<R, MCPError>> + 'a,
+    )
+    where
+        P: Serialize + Send + Sync,
+        R: DeserializeOwned + Send + Sync,
+    {
+        let token = ProgressToken::String(format!("{:016x}", rand::thread_rng().gen::<u64>()));
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.progress_listeners
+            .lock()
+            .unwrap()
+            .insert(token.clone(), sender);
+        let listener = ProgressListener {
+            listeners: self.progress_listeners.clone(),
+            token: token.clone(),
+        };
+
+        let progress = stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|progress| (progress, receiver))
+        });
+        let tool_name = tool_name.to_string();
+        let arguments = serde_json::to_value(params);
+        let result = async move {
+            // Ends the progress stream once the call completes or is dropped
+            let _listener = listener;
+            let params = serde_json::json!({
+                "name": tool_name,
+                "arguments": arguments?,
+                "_meta": RequestMeta { progress_token: Some(token) }
+            });
+            self.send_tool_call(&tool_name, params).await
+        };
+        (progress, result)
+    }
+
+    /// Send a `tools/call` request with the given params, recording it if audited
+    async fn send_tool_call<R: DeserializeOwned + Send + Sync>(
+        &mut self,
+        tool_name: &str,
+        params: Value,
+    ) -> Result<R, MCPError> {
+        let audit = self.audit.as_ref().map(|auditor| {
+            let arguments = params.get("arguments").cloned().unwrap_or(Value::Null);
+            let peer = self.server_info.as_ref().map(|info| info.name.clone());
+            auditor
+                .start(AuditSide::Client, tool_name, &arguments)
+                .with_peer(None, peer)
+        });
+        let response = self.send_tool_call_raw(tool_name, params).await;
+        if let Some(audit) = audit {
+            audit.finish(audit_outcome(&response));
+        }
+        decode_response("tools/call", response?)
+    }
+
+    /// Send a `tools/call` request with the given params, retrying if rate limited
+    async fn send_tool_call_raw(
+        &mut self,
+        tool_name: &str,
+        params: Value,
+    ) -> Result<JSONRPCMessage, MCPError> {
+        if self.validate_arguments {
+            self.check_arguments(tool_name, &params)?;
+        }
+        self.check_tool_call(tool_name, &params).await?;
+
+        let mut retries = 0;
+        loop {
+            // An upload is consumed by its commit, so each attempt uploads anew
+            let request_params = self.upload_if_oversized(params.clone()).await?;
+            let response = self
+                .send_request_raw("tools/call", Some(request_params))
+                .await?;
+
+            if retries < self.rate_limit_retries {
+                if let Some(delay) = rate_limit_delay(&response) {
+                    retries += 1;
+                    warn!(
+                        "Tool '{}' was rate limited, retrying in {:?} ({}/{})",
+                        tool_name, delay, retries, self.rate_limit_retries
+                    );
+                    self.clock.sleep(delay).await;
+                    continue;
+                }
+            }
+
+            if self.parse_mode.is_strict() {
+                check_tool_content(&response)?;
+                if let JSONRPCMessage::Response(response) = &response {
+                    self.parse_mode
+                        .check_result("tools/call", &response.result)?;
+                }
+            }
+            if self.validate_output {
+                self.check_structured_content(tool_name, &response)?;
+            }
+            return Ok(response);
+        }
+    }
+
+    /// Decide whether a tool call may be sent, and record the decision
+    async fn check_tool_call(&self, tool_name: &str, params: &Value) -> Result<(), MCPError> {
+        let arguments = params.get("arguments").cloned().unwrap_or(Value::Null);
+        let decision = if self.denied_tools.contains(tool_name) {
+            ToolCallDecision::Deny("the tool is on the deny list".to_string())
+        } else if self.allowed_tools.contains(tool_name) {
+            ToolCallDecision::Allow
+        } else {
+            match &self.tool_call_policy {
+                Some(policy) => {
+                    let annotations = self.tool_annotations(tool_name);
+                    policy
+                        .check_annotated(tool_name, &arguments, annotations.as_ref())
+                        .await
+                }
+                None => return Ok(()),
+            }
+        };
+
+        let asked = matches!(decision, ToolCallDecision::Ask(_));
+        let reason = match decision {
+            ToolCallDecision::Allow => None,
+            ToolCallDecision::Deny(reason) => Some(reason),
+            ToolCallDecision::Ask(prompt) => {
+                (!prompt.await).then(|| "the user declined the call".to_string())
+            }
+        };
+        match &reason {
+            None => info!("Tool call '{}' allowed (asked: {})", tool_name, asked),
+            Some(reason) => warn!("Tool call '{}' denied: {}", tool_name, reason),
+        }
+        let _ = self.diagnostics.send(ClientDiagnostic::ToolCallDecided {
+            tool: tool_name.to_string(),
+            arguments,
+            allowed: reason.is_none(),
+            asked,
+            reason: reason.clone(),
+        });
+
+        match reason {
+            None => Ok(()),
+            Some(reason) => Err(MCPError::ToolCallDenied {
+                tool: tool_name.to_string(),
+                reason,
+            }),
+        }
+    }
+
+    /// Check the arguments of a tool call against the tool's input schema
+    fn check_arguments(&self, tool_name: &str, params: &Value) -> Result<(), MCPError> {
+        let Some(schema) = self.input_schemas.lock().unwrap().get(tool_name).cloned() else {
+            return Ok(());
+        };
+        let arguments = match params.get("arguments") {
+            Some(Value::Null) | None => Value::Object(Map::new()),
+            Some(arguments) => arguments.clone(),
+        };
+        validation::validate(&schema, &arguments).map_err(|violation| MCPError::InvalidArguments {
+            tool: tool_name.to_string(),
+            path: if violation.path.is_empty() {
+                "/".to_string()
+            } else {
+                violation.path
+            },
+            message: violation.message,
+        })
+    }
+
+    /// Check a tool result's structured content against the tool's output schema
+    fn check_structured_content(
+        &self,
+        tool_name: &str,
+        response: &JSONRPCMessage,
+    ) -> Result<(), MCPError> {
+        let JSONRPCMessage::Response(response) = response else {
+            return Ok(());
+        };
+        let Some(schema) = self.output_schemas.lock().unwrap().get(tool_name).cloned() else {
+            return Ok(());
+        };
+        if response.result.get("isError") == Some(&Value::Bool(true)) {
+            return Ok(());
+        }
+
+        let Some(content) = response.result.get("structuredContent") else {
+            return Err(MCPError::Protocol(format!(
+                "Tool '{}' declares an outputSchema but returned no structuredContent",
+                tool_name
+            )));
+        };
+        validation::validate(&schema, content).map_err(|violation| {
+            MCPError::Protocol(format!(
+                "Structured content of tool '{}' does not match its outputSchema at {}",
+                tool_name, violation
+            ))
+        })
+    }
+
+    /// Upload the arguments of an oversized `tools/call` in chunks
+    ///
+    /// Returns the parameters to send: the committing parameters when the
+    /// arguments were uploaded, and `params` unchanged otherwise.
+    async fn upload_if_oversized(&mut self, params: Value) -> Result<Value, MCPError> {
+        let max_message_size = self
+            .server_capabilities
+            .as_ref()
+            .and_then(|capabilities| capabilities.experimental.as_ref())
+            .and_then(|experimental| experimental.get(CHUNKED_UPLOAD_CAPABILITY))
+            .and_then(|capability| capability.get("maxMessageSize"))
+            .and_then(Value::as_u64)
+            .map(|size| size as usize);
+        let Some(max_message_size) = max_message_size.filter(|_| self.chunked_uploads) else {
+            return Ok(params);
+        };
+
+        // Measure with the widest id, so the real request is never larger
+        let envelope_size = |method: &str, params: Value| -> Result<usize, MCPError> {
+            let mut request = JSONRPCRequest::new(
+                RequestId::Number(i64::MIN),
+                method.to_string(),
+                Some(params),
+            );
+            request.jsonrpc = self.jsonrpc_version().to_string();
+            Ok(serde_json::to_vec(&request)?.len())
+        };
+        if envelope_size("tools/call", params.clone())? <= max_message_size {
+            return Ok(params);
+        }
+
+        let upload_id = format!("{:016x}", rand::thread_rng().gen::<u64>());
+        let arguments = serde_json::to_string(&params["arguments"])?;
+        let chunk = |index: u32, count: u32, data: &str| UploadChunkParams {
+            _meta: UploadMeta {
+                upload_id: upload_id.clone(),
+                chunk_index: Some(index),
+                chunk_count: Some(count),
+            },
+            data: data.to_string(),
+        };
+
+        // What remains of the limit once the rest of a chunk request is counted
+        let overhead = envelope_size(
+            "uploads/chunk",
+            serde_json::to_value(chunk(u32::MAX, u32::MAX, ""))?,
+        )?;
+        let budget = max_message_size
+            .checked_sub(overhead)
+            .filter(|budget| *budget > 0)
+            .ok_or_else(|| {
+                MCPError::Protocol(format!(
+                    "Server maxMessageSize of {} bytes is too small for chunked uploads",
+                    max_message_size
+                ))
+            })?;
+
+        let pieces = split_for_json(&arguments, budget);
+        debug!(
+            "Uploading {} bytes of tool arguments in {} chunks as upload {}",
+            arguments.len(),
+            pieces.len(),
+            upload_id
+        );
+        for (index, data) in pieces.iter().enumerate() {
+            let chunk = serde_json::to_value(chunk(index as u32, pieces.len() as u32, data))?;
+            self.send_empty_request("uploads/chunk", Some(chunk))
+                .await?;
+        }
+
+        let mut committed = serde_json::json!({
+            "name": params["name"],
+            "_meta": UploadMeta {
+                upload_id: upload_id.clone(),
+                chunk_index: None,
+                chunk_count: Some(pieces.len() as u32),
+            }
+        });
+        if let Some(token) = params.pointer("/_meta/progressToken") {
+            committed["_meta"]["progressToken"] = token.clone();
+        }
+        Ok(committed)
+    }
+
+    /// Call a tool and get its typed result
+    ///
+    /// A result the tool marked with `isError` is returned as
+    /// [`MCPError::ToolError`], with the text of its content as the message.
+    pub async fn call_tool_result<P: Serialize + Send + Sync>(
+        &mut self,
+        tool_name: &str,
+        params: &P,
+    ) -> Result<CallToolResult, MCPError> {
+        let result: CallToolResult = self.call_tool(tool_name, params).await?;
+        if result.is_error != Some(true) {
+            return Ok(result);
+        }
+
+        let message = result
+            .content
+            .iter()
+            .filter_map(|content| match content {
+                ToolResultContent::Text(text) => Some(text.text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        Err(MCPError::ToolError {
+            tool: tool_name.to_string(),
+            message: if message.is_empty() {
+                "the tool returned an error".to_string()
+            } else {
+                message
+            },
+            content: result.content,
+        })
+    }
+
+    /// Call a tool and get both its content and its structured output
+    ///
+    /// The structured output is deserialized into `S`, and is `None` when the
+    /// tool returned no `structuredContent`.
+    pub async fn call_tool_full<P, S>(
+        &mut self,
+        tool_name: &str,
+        params: &P,
+    ) -> Result<(CallToolResult, Option<S>), MCPError>
+    where
+        P: Serialize + Send + Sync,
+        S: DeserializeOwned,
+    {
+        let result: CallToolResult = self.call_tool(tool_name, params).await?;
+        let structured = result
+            .structured_content
+            .clone()
+            .map(serde_json::from_value)
+            .transpose()?;
+        Ok((result, structured))
+    }
+
+    /// Call a tool with typed arguments and get its structured output as `O`
+    ///
+    /// The output is the result's `structuredContent`, which is checked
+    /// against the tool's `outputSchema` if enabled with
+    /// [`Client::with_validate_output`]. A result the
+    /// tool marked with `isError` is returned as [`MCPError::ToolError`], and
+    /// one without structured content as [`MCPError::Protocol`].
+    ///
+    /// ```rust,ignore
+    /// let forecast: Forecast = client
+    ///     .call_tool_typed("forecast", &ForecastArgs { city: "Oslo".into() })
+    ///     .await?;
+    /// ```
+    pub async fn call_tool_typed<A, O>(&mut self, tool_name: &str, args: &A) -> Result<O, MCPError>
+    where
+        A: Serialize + Send + Sync,
+        O: DeserializeOwned,
+    {
+        let result = self.call_tool_result(tool_name, args).await?;
+        let structured = result.structured_content.ok_or_else(|| {
+            MCPError::Protocol(format!(
+                "Tool '{}' returned no structuredContent",
+                tool_name
+            ))
+        })?;
+        Ok(serde_json::from_value(structured)?)
+    }
+
+    /// Wait until the server's tool list satisfies a predicate
+    ///
+    /// For servers that register tools asynchronously after initialization.
+    /// The tool list is polled until `ready` returns `true` for it, which
+    /// returns the tools, or until `timeout` elapses, which returns a
+    /// [`MCPError::Timeout`]. Errors while polling are retried.
+    ///
+    /// ```ignore
+    /// client
+    ///     .wait_ready(Duration::from_secs(10), |tools| tools.iter().any(|t| t.name == "search"))
+    ///     .await?;
+    /// ```
+    pub async fn wait_ready<F>(
+        &mut self,
+        timeout: Duration,
+        ready: F,
+    ) -> Result<Vec<Tool>, MCPError>
+    where
+        F: Fn(&[Tool]) -> bool,
+    {
+        let clock = self.clock.clone();
+        let deadline = clock.now() + timeout;
+        let mut last_error = None;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(clock.now());
+            match clock::timeout(
+                &*clock,
+                remaining,
+                self.fetch_all_pages::<ListToolsResult>("tools/list"),
+            )
+            .await
+            {
+                Some(Ok(tools)) if ready(&tools) => return Ok(tools),
+                Some(Ok(tools)) => debug!("Server not ready yet ({} tools)", tools.len()),
+                Some(Err(e)) => {
+                    debug!("Server not ready yet: {}", e);
+                    last_error = Some(e);
+                }
+                None => {}
+            }
+
+            if clock.now() + READY_POLL_INTERVAL >= deadline {
+                return Err(MCPError::Timeout(match last_error {
+                    Some(e) => format!("Server not ready after {:?}: {}", timeout, e),
+                    None => format!("Server not ready after {:?}", timeout),
+                }));
+            }
+            clock.sleep(READY_POLL_INTERVAL).await;
+        }
+    }
+
+    /// List the resources on the server, walking all pages
+    ///
+    /// The list is cached, and kept up to date with the metadata servers
+    /// include in `notifications/resources/updated`; see [`Client::cached_resources`].
+    pub async fn list_resources(&mut self) -> Result<Vec<Resource>, MCPError> {
+        let resources = self
+            .fetch_all_pages::<ListResourcesResult>("resources/list")
+            .await?;
+        *self.resources.lock().unwrap() = Some(resources.clone());
+        Ok(resources)
+    }
+
+    /// The resources from the last [`Client::list_resources`], with updates applied
+    pub fn cached_resources(&self) -> Option<Vec<Resource>> {
+        self.resources.lock().unwrap().clone()
+    }
+
+    /// Ask the server to notify the client when a resource changes
+    ///
+    /// Updates are delivered through [`Client::subscribe_resource_updates`].
+    /// The subscription is renewed by [`Client::reconnect`] until it is
+    /// removed with [`Client::unsubscribe_resource`] or the client is closed.
+    pub async fn subscribe_resource(&mut self, uri: &str) -> Result<(), MCPError> {
+        self.send_empty_request(
+            "resources/subscribe",
+            Some(serde_json::json!({ "uri": uri })),
+        )
+        .await?;
+        self.subscriptions.lock().unwrap().insert(uri.to_string());
+        Ok(())
+    }
+
+    /// Updates for one resource, from `notifications/resources/updated`
+    ///
+    /// The server only sends them after [`Client::subscribe_resource`]. Like
+    /// other notifications, they arrive as they are read. The stream ends when the client and its clones are dropped.
+    pub fn resource_updates(
+        &self,
+        uri: &str,
+    ) -> impl Stream<Item = ResourceUpdatedParams> + Send + 'static {
+        let uri = uri.to_string();
+        stream::unfold(self.resource_updates.subscribe(), move |mut updates| {
+            let uri = uri.clone();
+            async move {
+                loop {
+                    match updates.recv().await {
+                        Ok(update) if update.uri == uri => return Some((update, updates)),
+                        Ok(_) => {}
+                        Err(broadcast::error::RecvError::Lagged(missed)) => {
+                            warn!("Missed {} resource updates", missed)
+                        }
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    }
+                }
+            }
+        })
+    }
+
+    /// Stop receiving updates for a resource
+    ///
+    /// Fails without contacting the server if the client is not subscribed.
+    pub async fn unsubscribe_resource(&mut self, uri: &str) -> Result<(), MCPError> {
+        if !self.subscriptions.lock().unwrap().contains(uri) {
+            return Err(MCPError::Protocol(format!(
+                "Not subscribed to resource '{}'",
+                uri
+            )));
+        }
+
+        self.send_empty_request(
+            "resources/unsubscribe",
+            Some(serde_json::json!({ "uri": uri })),
+        )
+        .await?;
+        self.subscriptions.lock().unwrap().remove(uri);
+        Ok(())
+    }
+
+    /// The URIs of the resources the client is subscribed to
+    pub fn subscriptions(&self) -> Vec<String> {
+        let mut uris: Vec<String> = self.subscriptions.lock().unwrap().iter().cloned().collect();
+        uris.sort();
+        uris
+    }
+
+    /// Set the log level again and renew every subscription on a new connection
+    ///
+    /// Requests are exchanged directly, since a failure here must not start
+    /// another reconnect.
+    async fn restore_session(&mut self) {
+        let log_level = self.log_level.lock().unwrap().clone();
+        if let Some(level) = log_level {
+            let request = JSONRPCRequest::new(
+                self.next_request_id(),
+                "logging/setLevel".to_string(),
+                Some(serde_json::json!({ "level": level })),
+            );
+            let result = self
+                .exchange(request)
+                .await
+                .and_then(|response| decode_response::<EmptyResult>("logging/setLevel", response));
+            if let Err(e) = result {
+                warn!("Failed to restore the log level: {}", e);
+            }
+        }
+
+        for uri in self.subscriptions() {
+            let request = JSONRPCRequest::new(
+                self.next_request_id(),
+                "resources/subscribe".to_string(),
+                Some(serde_json::json!({ "uri": uri })),
+            );
+            let result = self.exchange(request).await.and_then(|response| {
+                decode_response::<EmptyResult>("resources/subscribe", response)
+            });
+            if let Err(e) = result {
+                warn!("Failed to renew subscription to '{}': {}", uri, e);
+            }
+        }
+    }
+
+    /// Ask the server to send log messages of `level` and more severe ones
+    pub async fn set_log_level(&mut self, level: LoggingLevel) -> Result<(), MCPError> {
+        let params = serde_json::to_value(SetLevelParams {
+            level: level.clone(),
+        })?;
+        self.send_empty_request("logging/setLevel", Some(params))
+            .await?;
+        *self.log_level.lock().unwrap() = Some(level);
+        Ok(())
+    }
+
+    /// Log messages the server sends from now on
+    ///
+    /// Without background reading (see [`Client::with_background_reading`]),
+    /// the stream only yields while requests are in flight. Messages a slow consumer
+    /// falls behind on are skipped, with a warning.
+    pub fn log_messages(&self) -> impl Stream<Item = LoggingMessageParams> + Send + 'static {
+        stream::unfold(self.log_messages.subscribe(), |mut messages| async move {
+            loop {
+                match messages.recv().await {
+                    Ok(message) => return Some((message, messages)),
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("Missed {} log messages", missed)
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+
+    /// Wait for the first notification that matches `predicate`
+    ///
+    /// Notifications received while the client waited for earlier responses
+    /// are checked first, so one sent before the request that triggered it
+    /// returned is not missed; the last 64 are kept.
+    /// Otherwise messages are read from the server until a match arrives,
+    /// handling anything else as usual, or until `timeout` elapses.
+    pub async fn next_notification_where<F>(
+        &mut self,
+        predicate: F,
+        timeout: Duration,
+    ) -> Result<JSONRPCNotification, MCPError>
+    where
+        F: Fn(&JSONRPCNotification) -> bool,
+    {
+        let deadline = self.clock.now() + timeout;
+        let mut state = self.state.subscribe();
+        let notification_arrived = self.notification_arrived.clone();
+        let read_lease = self.read_lease.clone();
+        loop {
+            // Registered before checking, so a notification kept by a clone
+            // reading in the meantime is not missed
+            let arrived = notification_arrived.notified();
+            tokio::pin!(arrived);
+            arrived.as_mut().enable();
+
+            if let Some(notification) = self.take_notification(&predicate) {
+                return Ok(notification);
+            }
+
+            let receive = async {
+                let lease = read_lease.lock().await;
+                (lease, self.transport.receive::<JSONRPCPayload>().await)
+            };
+            let (_lease, payload) = tokio::select! {
+                (lease, result) = receive => match result {
+                    Err(MCPError::Serialization(e)) if !self.parse_mode.is_strict() => {
+                        self.skip_invalid_message(&e);
+                        continue;
+                    }
+                    result => (lease, result?),
+                },
+                _ = &mut arrived => continue,
+                _ = state.wait_for(ConnectionState::is_lost) => {
+                    return Err(MCPError::ConnectionClosed);
+                }
+                _ = self.clock.sleep_until(deadline) => {
+                    return Err(MCPError::Timeout(format!(
+                        "No matching notification within {:?}",
+                        timeout
+                    )));
+                }
+            };
+
+            for message in payload.into_messages() {
+                match message {
+                    JSONRPCMessage::Request(request) => self.handle_server_request(request).await?,
+                    JSONRPCMessage::Notification(notification) => {
+                        self.handle_notification(notification)
+                    }
+                    response => self.route_response(response)?,
+                }
+            }
+        }
+    }
+
+    /// Remove and return the oldest kept notification that matches `predicate`
+    fn take_notification<F>(&self, predicate: F) -> Option<JSONRPCNotification>
+    where
+        F: Fn(&JSONRPCNotification) -> bool,
+    {
+        let mut notifications = self.notifications.lock().unwrap();
+        let index = notifications.iter().position(predicate)?;
+        notifications.remove(index)
+    }
+
+    /// Read a resource from the server
+    ///
+    /// For resources available in several representations, `accept` lists
+    /// the MIME types wanted, most preferred first, and may use wildcards
+    /// such as `text/*`. The server returns the best match, or its default
+    /// representation when none matches, so check the returned MIME type.
+    pub async fn read_resource(
+        &mut self,
+        uri: &str,
+        accept: Option<&[&str]>,
+    ) -> Result<ReadResourceResult, MCPError> {
+        let params = ReadResourceParams {
+            uri: uri.to_string(),
+            _meta: accept.map(|accept| ReadResourceMeta {
+                accept: accept.iter().map(|t| t.to_string()).collect(),
+                ..ReadResourceMeta::default()
+            }),
+        };
+        self.send_request("resources/read", Some(serde_json::to_value(params)?))
+            .await
+    }
+
+    /// Read a resource in parts of at most `chunk_size` bytes, as the stream is consumed
+    ///
+    /// Servers serving the resource with a
+    /// [`ResourceReader`](crate::server::ResourceReader) send one part per
+    /// request; others send it whole, as the only item. Base64 blobs of the
+    /// parts can be decoded one at a time. The stream ends after the first
+    /// error.
+    pub fn read_resource_chunks<'a>(
+        &'a mut self,
+        uri: &str,
+        chunk_size: usize,
+    ) -> impl Stream<Item = Result<ResourceContent, MCPError>> + 'a {
+        let uri = uri.to_string();
+        // `None` once the last part was read
+        let start: Option<Option<Cursor>> = Some(None);
+        stream::try_unfold((self, start), move |(client, cursor)| {
+            let uri = uri.clone();
+            async move {
+                let Some(cursor) = cursor else {
+                    return Ok(None);
+                };
+                let params = ReadResourceParams {
+                    uri,
+                    _meta: Some(ReadResourceMeta {
+                        chunk_size: Some(chunk_size),
+                        cursor,
+                        ..ReadResourceMeta::default()
+                    }),
+                };
+                let result: ReadResourceResult = client
+                    .send_request("resources/read", Some(serde_json::to_value(params)?))
+                    .await?;
+                let next = result.next_cursor().cloned().map(Some);
+                Ok::<_, MCPError>(Some((result.contents, (client, next))))
+            }
+        })
+        .map_ok(|contents| stream::iter(contents.into_iter().map(Ok)))
+        .try_flatten()
+    }
+
+    /// List the resource templates on the server, walking all pages
+    ///
+    /// Build URIs from them with [`ResourceTemplate::expand`] and read them
+    /// with [`Client::read_resource`].
+    pub async fn list_resource_templates(&mut self) -> Result<Vec<ResourceTemplate>, MCPError> {
+        self.fetch_all_pages::<ListResourceTemplatesResult>("resources/templates/list")
+            .await
+    }
+
+    /// List the prompts on the server, with their arguments, walking all pages
+    pub async fn list_prompts(&mut self) -> Result<Vec<Prompt>, MCPError> {
+        self.fetch_all_pages::<ListPromptsResult>("prompts/list")
+            .await
+    }
+
+    /// Get a prompt from the server, filled in with `arguments`
+    pub async fn get_prompt(
+        &mut self,
+        name: &str,
+        arguments: Option<HashMap<String, String>>,
+    ) -> Result<GetPromptResult, MCPError> {
+        let params = GetPromptParams {
+            name: name.to_string(),
+            arguments,
+        };
+        self.send_request("prompts/get", Some(serde_json::to_value(params)?))
+            .await
+    }
+
+    /// Ask the server to complete an argument of a prompt or resource template
+    ///
+    /// `value` is what the user typed so far; use [`Reference::prompt`] or
+    /// [`Reference::resource`] to name what the argument belongs to.
+    pub async fn complete(
+        &mut self,
+        reference: Reference,
+        argument: &str,
+        value: &str,
+    ) -> Result<CompleteResult, MCPError> {
+        let params = CompleteParams {
+            ref_: reference,
+            argument: ArgumentInfo {
+                name: argument.to_string(),
+                value: value.to_string(),
+            },
+        };
+        self.send_request("completion/complete", Some(serde_json::to_value(params)?))
+            .await
+    }
+
+    /// Fetch everything the server offers in one call
+    ///
+    /// Tools, prompts, resources and resource templates are fetched
+    /// concurrently (walking all pages) for each capability the server
+    /// advertised during initialization. Capabilities the server did not
+    /// advertise, and list methods it answers with method not found, are
+    /// left empty.
+    pub async fn describe_server(&mut self) -> Result<ServerDescription, MCPError>
+    where
+        T: Clone,
+    {
+        let capabilities = self.server_capabilities.clone().ok_or_else(|| {
+            MCPError::Protocol("Server capabilities unknown; initialize the client first".into())
+        })?;
+
+        let (tools, prompts, resources, resource_templates) = futures::try_join!(
+            self.share()
+                .fetch_described::<ListToolsResult>(capabilities.tools.is_some(), "tools/list"),
+            self.share().fetch_described::<ListPromptsResult>(
+                capabilities.prompts.is_some(),
+                "prompts/list"
+            ),
+            self.share().fetch_described::<ListResourcesResult>(
+                capabilities.resources.is_some(),
+                "resources/list"
+            ),
+            self.share().fetch_described::<ListResourceTemplatesResult>(
+                capabilities.resources.is_some(),
+                "resources/templates/list"
+            ),
+        )?;
+        Ok(ServerDescription {
+            tools,
+            prompts,
+            resources,
+            resource_templates,
+        })
+    }
+
+    /// Fetch every page of a list method for [`Client::describe_server`]
+    ///
+    /// Empty if the capability was not `advertised` or the server does not
+    /// implement the method.
+    async fn fetch_described<P: PaginatedResult>(
+        mut self,
+        advertised: bool,
+        method: &str,
+    ) -> Result<Vec<P::Item>, MCPError> {
+        if !advertised {
+            return Ok(Vec::new());
+        }
+        match self.fetch_all_pages::<P>(method).await {
+            Err(e) if e.is_method_not_found() => {
+                debug!(
+                    "Server does not implement {}, describing it as empty",
+                    method
+                );
+                Ok(Vec::new())
+            }
+            result => result,
+        }
+    }
+
+    /// Fetch the page of a cursor-paginated list method starting at `cursor`
+    async fn fetch_page<P: PaginatedResult>(
+        &mut self,
+        method: &str,
+        cursor: Option<Cursor>,
+    ) -> Result<(Vec<P::Item>, Option<Cursor>), MCPError> {
+        let params = cursor.map(|c| serde_json::json!({ "cursor": c }));
+        let page: P = self.send_request(method, params).await?;
+        Ok(page.into_page())
+    }
+
+    /// Fetch every page of a cursor-paginated list method
+    ///
+    /// Fails if the server hands out a cursor it handed out before, which
+    /// would otherwise loop forever.
+    async fn fetch_all_pages<P: PaginatedResult>(
+        &mut self,
+        method: &str,
+    ) -> Result<Vec<P::Item>, MCPError> {
+        let mut items = Vec::new();
+        let mut cursor: Option<Cursor> = None;
+        let mut seen = HashSet::new();
+
+        loop {
+            let (page_items, next_cursor) = self.fetch_page::<P>(method, cursor).await?;
+            items.extend(page_items);
+
+            match next_cursor {
+                Some(next) if !seen.insert(next.clone()) => {
+                    return Err(MCPError::Protocol(format!(
+                        "Server repeated cursor {:?} while listing {}",
+                        next, method
+                    )));
+                }
+                Some(next) => cursor = Some(next),
+                None => return Ok(items),
+            }
+        }
+    }
+
+    /// Stream the items of every page of a cursor-paginated list method
+    ///
+    /// Pages are fetched as the stream is consumed. The stream ends after
+    /// the first error.
+    fn stream_pages<P: PaginatedResult>(
+        &mut self,
+        method: &'static str,
+    ) -> impl Stream<Item = Result<P::Item, MCPError>> + '_ {
+        // `None` once the last page was fetched
+        let start: Option<Option<Cursor>> = Some(None);
+        stream::try_unfold((self, start), move |(client, cursor)| async move {
+            let Some(cursor) = cursor else {
+                return Ok(None);
+            };
+            let (items, next_cursor) = client.fetch_page::<P>(method, cursor).await?;
+            Ok::<_, MCPError>(Some((items, (client, next_cursor.map(Some)))))
+        })
+        .map_ok(|items| stream::iter(items.into_iter().map(Ok)))
+        .try_flatten()
+    }
+
+    /// Fetch one page of tools, starting at `cursor`
+    ///
+    /// Returns the tools and the cursor of the next page, or `None` on the
+    /// last page. Pass `None` for the first page.
+    pub async fn list_tools_paged(
+        &mut self,
+        cursor: Option<Cursor>,
+    ) -> Result<(Vec<Tool>, Option<Cursor>), MCPError> {
+        self.fetch_page::<ListToolsResult>("tools/list", cursor)
+            .await
+    }
+
+    /// List all tools on the server, fetching every page
+    pub async fn list_all_tools(&mut self) -> Result<Vec<Tool>, MCPError> {
+        self.fetch_all_pages::<ListToolsResult>("tools/list").await
+    }
+
+    /// Stream all tools on the server, fetching pages as they are needed
+    pub fn list_tools_stream(&mut self) -> impl Stream<Item = Result<Tool, MCPError>> + '_ {
+        self.stream_pages::<ListToolsResult>("tools/list")
+    }
+
+    /// Fetch one page of resources, starting at `cursor`
+    ///
+    /// Unlike [`Client::list_resources`], this does not update the cached
+    /// resource list.
+    pub async fn list_resources_paged(
+        &mut self,
+        cursor: Option<Cursor>,
+    ) -> Result<(Vec<Resource>, Option<Cursor>), MCPError> {
+        self.fetch_page::<ListResourcesResult>("resources/list", cursor)
+            .await
+    }
+
+    /// Stream all resources on the server, fetching pages as they are needed
+    pub fn list_resources_stream(&mut self) -> impl Stream<Item = Result<Resource, MCPError>> + '_ {
+        self.stream_pages::<ListResourcesResult>("resources/list")
+    }
+
+    /// Fetch one page of prompts, starting at `cursor`
+    pub async fn list_prompts_paged(
+        &mut self,
+        cursor: Option<Cursor>,
+    ) -> Result<(Vec<Prompt>, Option<Cursor>), MCPError> {
+        self.fetch_page::<ListPromptsResult>("prompts/list", cursor)
+            .await
+    }
+
+    /// Stream all prompts on the server, fetching pages as they are needed
+    pub fn list_prompts_stream(&mut self) -> impl Stream<Item = Result<Prompt, MCPError>> + '_ {
+        self.stream_pages::<ListPromptsResult>("prompts/list")
+    }
+
+    /// The server's tools, kept up to date as the server changes them
+    ///
+    /// See [`LiveCatalog`]; tools are looked up by name.
+    pub fn tool_catalog(&self) -> LiveCatalog<T, Tool>
+    where
+        T: Clone + 'static,
+    {
+        LiveCatalog::new(self)
+    }
+
+    /// The server's prompts, kept up to date as the server changes them
+    pub fn prompt_catalog(&self) -> LiveCatalog<T, Prompt>
+    where
+        T: Clone + 'static,
+    {
+        LiveCatalog::new(self)
+    }
+
+    /// The server's resources, looked up by URI, kept up to date as the server changes them
+    pub fn resource_catalog(&self) -> LiveCatalog<T, Resource>
+    where
+        T: Clone + 'static,
+    {
+        LiveCatalog::new(self)
+    }
+
+    /// Ping the server to check that it is still alive
+    ///
+    /// The round-trip time is kept as [`Client::ping_latency`].
+    pub async fn ping(&mut self) -> Result<(), MCPError> {
+        let started = self.clock.now();
+        self.send_empty_request("ping", None).await?;
+        *self.ping_latency.lock().unwrap() = Some(self.clock.now() - started);
+        Ok(())
+    }
+
+    /// Round-trip time of the last ping the server answered
+    pub fn ping_latency(&self) -> Option<Duration> {
+        *self.ping_latency.lock().unwrap()
+    }
+
+    /// Ping the server periodically, in a task of its own, to detect a dead connection
+    ///
+    /// Pings go out while the client is connected, through a clone sharing
+    /// its connection. Once `keep_alive.max_missed` pings in a row go
+    /// unanswered, the state becomes [`ConnectionState::Unresponsive`]: the
+    /// requests waiting for a response fail, and with a reconnect policy
+    /// the next one to fail reconnects. Pinging resumes once the client is
+    /// connected again, and the task ends when the client is closed.
+    #[cfg(feature = "runtime-tokio")]
+    pub fn spawn_keep_alive(&self, keep_alive: KeepAlive) -> tokio::task::JoinHandle<()>
+    where
+        T: Clone + 'static,
+    {
+        let mut client = self.share();
+        let mut state = self.state.subscribe();
+        tokio::spawn(async move {
+            let clock = client.clock.clone();
+            let mut missed = 0;
+            loop {
+                tokio::select! {
+                    _ = clock.sleep(keep_alive.interval) => {}
+                    _ = state.wait_for(|state| *state == ConnectionState::Closed) => return,
+                }
+                if client.state() != ConnectionState::Connected {
+                    missed = 0;
+                    continue;
+                }
+
+                // An error response still shows the server is alive
+                match clock::timeout(&*clock, keep_alive.timeout, client.ping()).await {
+                    Some(Ok(())) | Some(Err(MCPError::Rpc { .. })) => missed = 0,
+                    Some(Err(e)) => {
+                        missed += 1;
+                        debug!("Keep-alive ping failed: {}", e);
+                    }
+                    None => {
+                        missed += 1;
+                        debug!("Keep-alive ping unanswered after {:?}", keep_alive.timeout);
+                    }
+                }
+                if missed >= keep_alive.max_missed {
+                    warn!(
+                        "Server missed {} pings in a row, marking the connection unresponsive",
+                        missed
+                    );
+                    let marked = client.state.send_if_modified(|state| {
+                        let connected = *state == ConnectionState::Connected;
+                        if connected {
+                            *state = ConnectionState::Unresponsive;
+                        }
+                        connected
+                    });
+                    if marked {
+                        client.emit(ClientEvent::Disconnected {
+                            reason: format!("The server missed {} pings in a row", missed),
+                        });
+                    }
+                    missed = 0;
+                }
+            }
+        })
+    }
+
+    /// Read the server's messages while no request is waiting, in a task of its own
+    ///
+    /// Without it, the transport is only read by requests waiting for their
+    /// response, so notifications, server requests and the loss of the
+    /// connection go unnoticed while the client is idle. The task reads
+    /// through a clone sharing the connection, dispatching messages as a
+    /// waiting request would, and steps aside as soon as a request starts
+    /// waiting, which then reads for itself as usual. The client starts
+    /// such a task itself once initialized if its transport implements
+    /// [`Transport::share_connection`]; otherwise spawn it once the client is
+    /// initialized. It ends when the client is closed, or when the
+    /// connection fails, which is published as [`ClientEvent::Disconnected`].
+    /// Only for transports whose clones share their connection.
+    #[cfg(feature = "runtime-tokio")]
+    pub fn spawn_reader(&self) -> tokio::task::JoinHandle<()>
+    where
+        T: Clone + 'static,
+    {
+        self.share().read_in_background()
+    }
+
+    /// Start reading through another handle on the connection, if enabled and possible
+    #[cfg(feature = "runtime-tokio")]
+    fn start_background_reader(&self) {
+        if !self.background_reading {
+            return;
+        }
+        let Some(transport) = self.transport.share_connection() else {
+            return;
+        };
+        let reader = self.share_with(transport).read_in_background();
+        self.background_reader.replace(reader);
+    }
+
+    /// Read the connection with this client while no request waits, until it is closed or lost
+    #[cfg(feature = "runtime-tokio")]
+    fn read_in_background(self) -> tokio::task::JoinHandle<()> {
+        let mut client = self;
+        // The task must not keep the client's own reader running once it is dropped
+        client.background_reader = Arc::default();
+        let mut state = client.state.subscribe();
+        tokio::spawn(async move {
+            let read_lease = client.read_lease.clone();
+            loop {
+                // Requests waiting for a response read for themselves
+                while !client.pending.lock().unwrap().is_empty() {
+                    tokio::select! {
+                        _ = client.idle.notified() => {}
+                        _ = state.wait_for(|state| *state == ConnectionState::Closed) => return,
+                    }
+                }
+
+                let lease = tokio::select! {
+                    biased;
+                    _ = state.wait_for(|state| *state == ConnectionState::Closed) => return,
+                    lease = read_lease.lock() => lease,
+                };
+                if !client.pending.lock().unwrap().is_empty() {
+                    continue;
+                }
+                let received = tokio::select! {
+                    biased;
+                    _ = client.lease_wanted.notified() => continue,
+                    _ = state.wait_for(|state| *state == ConnectionState::Closed) => return,
+                    received = client.transport.receive::<JSONRPCPayload>() => received,
+                };
+
+                let payload = match received {
+                    Ok(payload) => payload,
+                    Err(MCPError::Serialization(e)) if !client.parse_mode.is_strict() => {
+                        client.skip_invalid_message(&e);
+                        continue;
+                    }
+                    Err(e) => {
+                        if client.state() != ConnectionState::Closed {
+                            warn!("Stopped reading from the server: {}", e);
+                            client.report_lost(e.to_string()).await;
+                        }
+                        return;
+                    }
+                };
+                for message in payload.into_messages() {
+                    let handled = match message {
+                        JSONRPCMessage::Request(request) => {
+                            client.handle_server_request(request).await
+                        }
+                        JSONRPCMessage::Notification(notification) => {
+                            client.handle_notification(notification);
+                            Ok(())
+                        }
+                        response => client.route_response(response),
+                    };
+                    if let Err(e) = handled {
+                        warn!("Failed to handle a message from the server: {}", e);
+                    }
+                }
+                drop(lease);
+            }
+        })
+    }
+
+    /// Send a request for a method without a method of its own here, such as an extension
+    ///
+    /// The result is deserialized as `R`; error responses are returned as
+    /// [`MCPError::Rpc`].
+    pub async fn request<R: DeserializeOwned + Send + Sync>(
+        &mut self,
+        method: &str,
+        params: Option<Value>,
+    ) -> Result<R, MCPError> {
+        self.send_request(method, params).await
+    }
+
+    /// Send a request for a vendor-specific method, with typed params and result
+    ///
+    /// Like [`Client::request`], but `params` is serialized first; params
+    /// serializing to `null`, such as `()`, are left out.
+    ///
+    /// ```rust,no_run
+    /// # async fn run(mut client: mcpr::client::Client<mcpr::transport::stdio::StdioTransport>) -> Result<(), mcpr::error::MCPError> {
+    /// let usage: serde_json::Value = client
+    ///     .request_raw("x/usage", &serde_json::json!({ "period": "day" }))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn request_raw<P, R>(&mut self, method: &str, params: &P) -> Result<R, MCPError>
+    where
+        P: Serialize + Send + Sync,
+        R: DeserializeOwned + Send + Sync,
+    {
+        let params = serde_json::to_value(params)?;
+        self.send_request(method, (!params.is_null()).then_some(params))
+            .await
+    }
+
+    /// Send a notification for a vendor-specific method, with typed params
+    ///
+    /// Params serializing to `null`, such as `()`, are left out.
+    pub async fn notify_raw<P>(&mut self, method: &str, params: &P) -> Result<(), MCPError>
+    where
+        P: Serialize + Send + Sync,
+    {
+        let params = serde_json::to_value(params)?;
+        let mut notification =
+            JSONRPCNotification::new(method.to_string(), (!params.is_null()).then_some(params));
+        notification.jsonrpc = self.jsonrpc_version().to_string();
+        self.transport
+            .send(&JSONRPCMessage::Notification(notification))
+            .await
+    }
+
+    /// Start a batch of requests, sent to the server in one frame
+    ///
+    /// ```rust,no_run
+    /// # async fn run(mut client: mcpr::client::Client<mcpr::transport::stdio::StdioTransport>) -> Result<(), mcpr::error::MCPError> {
+    /// let results = client
+    ///     .batch()
+    ///     .request("tools/list", None)
+    ///     .request("prompts/list", None)
+    ///     .send()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn batch(&mut self) -> Batch<'_, T> {
+        Batch {
+            client: self,
+            requests: Vec::new(),
+        }
+    }
+
+    /// Shutdown the client
+    ///
+    /// Requests still waiting for a response on clients sharing the connection,
+    /// such as concurrent tool calls, fail with [`MCPError::ConnectionClosed`]
+    /// once the connection is closed, and responses arriving for them later
+    /// are discarded.
+    pub async fn shutdown(&mut self) -> Result<(), MCPError> {
+        // Send shutdown request
+        let shutdown_request =
+            JSONRPCRequest::new(self.next_request_id(), "shutdown".to_string(), None);
+
+        let response = self.exchange(shutdown_request).await?;
+
+        match response {
+            JSONRPCMessage::Response(_) => {
+                // Close the transport
+                self.transport.close().await?;
+                self.mark_closed();
+                Ok(())
+            }
+            JSONRPCMessage::Error(err) => {
+                debug!("Shutdown failed: {:?}", err);
+                Err(err.error.into())
+            }
+            _ => Err(MCPError::Protocol("Unexpected response type".to_string())),
+        }
+    }
+
+    /// Shut down gracefully
+    ///
+    /// Waits for the requests in flight, including those of clones sharing
+    /// the connection, to complete or for the drain timeout to pass, then
+    /// sends the shutdown request and closes the transport. Closing flushes
+    /// the messages still queued; a server spawned with
+    /// [`StdioTransport::spawn`](crate::transport::stdio::StdioTransport::spawn)
+    /// gets its exit grace period before it is killed. Unlike
+    /// [`Client::shutdown`], the transport is closed even if the server
+    /// rejects the shutdown or does not answer in time, and that error is
+    /// returned afterwards.
+    pub async fn shutdown_with(&mut self, options: ShutdownOptions) -> Result<(), MCPError> {
+        if let Some(drain_timeout) = options.drain_timeout {
+            let clock = self.clock.clone();
+            let pending = self.pending.clone();
+            let drained = clock::timeout(&*clock, drain_timeout, async {
+                while !pending.lock().unwrap().is_empty() {
+                    clock.sleep(DRAIN_POLL_INTERVAL).await;
+                }
+            })
+            .await;
+            if drained.is_none() {
+                let methods: Vec<_> = self
+                    .pending_requests()
+                    .into_iter()
+                    .map(|request| request.method)
+                    .collect();
+                warn!(
+                    "Shutting down with {} requests in flight: {:?}",
+                    methods.len(),
+                    methods
+                );
+            }
+        }
+
+        let shutdown_request =
+            JSONRPCRequest::new(self.next_request_id(), "shutdown".to_string(), None);
+        let clock = self.clock.clone();
+        let exchange = self.exchange(shutdown_request);
+        let response = match options.timeout {
+            Some(timeout) => clock::timeout(&*clock, timeout, exchange)
+                .await
+                .unwrap_or_else(|| {
+                    Err(MCPError::Timeout(format!(
+                        "Server did not answer the shutdown request within {:?}",
+                        timeout
+                    )))
+                }),
+            None => exchange.await,
+        };
+        let result = match response {
+            Ok(JSONRPCMessage::Response(_)) => Ok(()),
+            Ok(JSONRPCMessage::Error(err)) => {
+                debug!("Shutdown failed: {:?}", err);
+                Err(err.error.into())
+            }
+            Ok(_) => Err(MCPError::Protocol("Unexpected response type".to_string())),
+            Err(e) => Err(e),
+        };
+
+        let closed = self.transport.close().await;
+        self.mark_closed();
+        result.and(closed)
+    }
+
+    /// Close the connection and return a summary of the session
+    ///
+    /// Unlike [`Client::shutdown`], no shutdown request is sent: the transport
+    /// is closed directly, after unsubscribing from every resource if the
+    /// client is connected. Calling `close` again, or after a successful
+    /// shutdown, does not touch the transport and returns the same summary.
+    pub async fn close(&mut self) -> ConnectionSummary {
+        if self.state() == ConnectionState::Connected {
+            for uri in self.subscriptions() {
+                if let Err(e) = self.unsubscribe_resource(&uri).await {
+                    warn!("Failed to unsubscribe from '{}': {}", uri, e);
+                }
+            }
+        }
+        self.subscriptions.lock().unwrap().clear();
+
+        if self.state() != ConnectionState::Closed {
+            #[cfg(feature = "runtime-tokio")]
+            self.background_reader.stop();
+            if let Err(e) = self.transport.close().await {
+                warn!("Error closing transport: {}", e);
+            }
+            self.mark_closed();
+        }
+
+        self.summary()
+    }
+
+    /// Get a summary of the session so far
+    pub fn summary(&self) -> ConnectionSummary {
+        self.stats.summary(self.transport.traffic())
+    }
+
+    /// Transition to the closed state and freeze the session uptime
+    fn mark_closed(&self) {
+        if self.state() != ConnectionState::Closed
+            && !self.loss_reported.swap(true, Ordering::Relaxed)
+        {
+            self.emit(ClientEvent::Disconnected {
+                reason: "The connection was closed".to_string(),
+            });
+        }
+        self.stats
+            .closed_at
+            .lock()
+            .unwrap()
+            .get_or_insert_with(Instant::now);
+        self.state.send_replace(ConnectionState::Closed);
+    }
+
+    /// Create a client sharing this one's connection, for concurrent requests
+    ///
+    /// The clone shares the transport, request ids, pending requests, read
+    /// lease and connection state, but does not reconnect on its own.
+    fn share(&self) -> Self
+    where
+        T: Clone,
+    {
+        self.share_with(self.transport.clone())
+    }
+
+    /// A client on `transport`, sharing the connection state of this one
+    fn share_with(&self, transport: T) -> Self {
+        Client {
+            transport,
+            transport_factory: None,
+            next_request_id: self.next_request_id.clone(), // Shared to keep IDs unique
+            timeout_duration: self.timeout_duration,
+            reconnect_policy: None,
+            state: self.state.clone(),
+            server_capabilities: self.server_capabilities.clone(),
+            server_info: self.server_info.clone(),
+            protocol_version: self.protocol_version.clone(),
+            pending: self.pending.clone(),
+            cancelled: self.cancelled.clone(),
+            unsent_cancellations: self.unsent_cancellations.clone(),
+            late_response_policy: self.late_response_policy,
+            diagnostics: self.diagnostics.clone(),
+            events: self.events.clone(),
+            loss_reported: self.loss_reported.clone(),
+            slow_request_threshold: self.slow_request_threshold,
+            client_info: self.client_info.clone(),
+            requested_protocol_version: self.requested_protocol_version.clone(),
+            declared_capabilities: self.declared_capabilities.clone(),
+            jsonrpc_version: self.jsonrpc_version.clone(),
+            rate_limit_retries: self.rate_limit_retries,
+            retry_policy: self.retry_policy.clone(),
+            stats: self.stats.clone(),
+            request_handlers: self.request_handlers.clone(),
+            notification_handlers: self.notification_handlers.clone(),
+            progress_listeners: self.progress_listeners.clone(),
+            accepted_content_types: self.accepted_content_types.clone(),
+            chunked_uploads: self.chunked_uploads,
+            single_flight: self.single_flight,
+            response_cache: self.response_cache.clone(),
+            in_flight: self.in_flight.clone(),
+            request_meta: self.request_meta.clone(),
+            tool_annotations: self.tool_annotations.clone(),
+            roots: self.roots.clone(),
+            resources: self.resources.clone(),
+            resource_updates: self.resource_updates.clone(),
+            log_messages: self.log_messages.clone(),
+            log_level: self.log_level.clone(),
+            forward_logs: self.forward_logs,
+            subscriptions: self.subscriptions.clone(),
+            notifications: self.notifications.clone(),
+            notification_arrived: self.notification_arrived.clone(),
+            read_lease: self.read_lease.clone(),
+            lease_wanted: self.lease_wanted.clone(),
+            idle: self.idle.clone(),
+            background_reading: self.background_reading,
+            #[cfg(feature = "runtime-tokio")]
+            background_reader: self.background_reader.clone(),
+            parse_mode: self.parse_mode,
+            validate_output: self.validate_output,
+            validate_arguments: self.validate_arguments,
+            clock: self.clock.clone(),
+            output_schemas: self.output_schemas.clone(),
+            input_schemas: self.input_schemas.clone(),
+            ping_latency: self.ping_latency.clone(),
+            tool_call_policy: self.tool_call_policy.clone(),
+            audit: self.audit.clone(),
+            allowed_tools: self.allowed_tools.clone(),
+            denied_tools: self.denied_tools.clone(),
+        }
+    }
+
+    /// Send a request and deserialize the result of its response
+    async fn send_request<R: DeserializeOwned + Send + Sync>(
+        &mut self,
+        method: &str,
+        params: Option<Value>,
+    ) -> Result<R, MCPError> {
+        let response = self.send_request_raw(method, params).await?;
+        if let JSONRPCMessage::Response(response) = &response {
+            self.parse_mode.check_result(method, &response.result)?;
+        }
+        decode_response(method, response)
+    }
+
+    /// Fail if the server did not advertise the capability `method` needs
+    ///
+    /// Requests are only checked once the capabilities are known, after
+    /// [`Client::initialize`].
+    fn check_capability(&self, method: &str) -> Result<(), MCPError> {
+        let (Some(capabilities), Some(capability)) =
+            (&self.server_capabilities, required_capability(method))
+        else {
+            return Ok(());
+        };
+        if has_capability(capabilities, capability) {
+            return Ok(());
+        }
+        Err(MCPError::CapabilityNotSupported {
+            method: method.to_string(),
+            capability: capability.to_string(),
+        })
+    }
+
+    /// Send a request and return the server's reply without interpreting it
+    ///
+    /// Answers kept by the response cache are returned without sending.
+    async fn send_request_raw(
+        &mut self,
+        method: &str,
+        params: Option<Value>,
+    ) -> Result<JSONRPCMessage, MCPError> {
+        let result = self.send_request_cached(method, params).await;
+        let error = match &result {
+            Ok(JSONRPCMessage::Error(err)) => Some(err.error.message.clone()),
+            Err(e) => Some(e.to_string()),
+            Ok(_) => None,
+        };
+        if let Some(error) = error {
+            self.emit(ClientEvent::RequestFailed {
+                method: method.to_string(),
+                error,
+            });
+        }
+        result
+    }
+
+    /// Send a request, unless the response cache has its answer
+    async fn send_request_cached(
+        &mut self,
+        method: &str,
+        params: Option<Value>,
+    ) -> Result<JSONRPCMessage, MCPError> {
+        self.check_capability(method)?;
+        let cached = self.response_cache.clone().and_then(|cache| {
+            let key = cache.key(method, params.as_ref())?;
+            Some((cache, key))
+        });
+        let Some((cache, key)) = cached else {
+            return self.send_request_retried(method, params).await;
+        };
+        if let Some(response) = cache.get(&key, self.clock.now()) {
+            debug!("Answering '{}' from the response cache", method);
+            return Ok(response);
+        }
+        let response = self.send_request_retried(method, params.clone()).await?;
+        cache.insert(key, method, params.as_ref(), &response, self.clock.now());
+        Ok(response)
+    }
+
+    /// Send a request, retrying it as the retry policy allows
+    ///
+    /// With a retry policy, idempotent requests that fail on the way are sent
+    /// again; once retried, errors are wrapped in [`MCPError::RetriesExhausted`].
+    async fn send_request_retried(
+        &mut self,
+        method: &str,
+        params: Option<Value>,
+    ) -> Result<JSONRPCMessage, MCPError> {
+        let policy = match &self.retry_policy {
+            Some(policy) if self.is_idempotent(method, params.as_ref()) => policy.clone(),
+            _ => return self.send_request_coalesced(method, params).await,
+        };
+
+        let mut attempt = 1;
+        loop {
+            match self.send_request_coalesced(method, params.clone()).await {
+                Err(e)
+                    if attempt < policy.max_attempts
+                        && e.kind() == ErrorKind::Transport
+                        && e.retryable() =>
+                {
+                    let delay = policy.delay(attempt);
+                    warn!(
+                        "'{}' failed, retrying in {:?} ({}/{}): {}",
+                        method, delay, attempt, policy.max_attempts, e
+                    );
+                    self.clock.sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) if attempt > 1 => {
+                    return Err(MCPError::RetriesExhausted {
+                        attempts: attempt,
+                        source: Box::new(e),
+                    })
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Whether a request is safe to send twice
+    fn is_idempotent(&self, method: &str, params: Option<&Value>) -> bool {
+        READ_ONLY_METHODS.contains(&method)
+            || IDEMPOTENT_METHODS.contains(&method)
+            || (method == "tools/call"
+                && params
+                    .and_then(|p| p.get("name"))
+                    .and_then(Value::as_str)
+                    .and_then(|name| self.tool_annotations(name))
+                    .is_some_and(|annotations| annotations.is_idempotent()))
+    }
+
+    /// Send a request and return the server's reply, or that of an identical one
+    ///
+    /// With single-flight enabled, read-only requests join an identical
+    /// request already in flight instead of being sent.
+    async fn send_request_coalesced(
+        &mut self,
+        method: &str,
+        params: Option<Value>,
+    ) -> Result<JSONRPCMessage, MCPError> {
+        let Some(key) = self.single_flight_key(method, params.as_ref()) else {
+            return self.send_request_once(method, params).await;
+        };
+
+        let follower = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(leader) => Some(leader.subscribe()),
+                None => {
+                    in_flight.insert(key.clone(), broadcast::channel(1).0);
+                    None
+                }
+            }
+        };
+        if let Some(mut follower) = follower {
+            debug!("Joining an identical '{}' request in flight", method);
+            return match follower.recv().await {
+                Ok(result) => result.map_err(|e| e.duplicate()),
+                Err(_) => Err(MCPError::Protocol(format!(
+                    "The '{}' request this one joined was cancelled",
+                    method
+                ))),
+            };
+        }
+
+        let guard = FlightGuard {
+            in_flight: self.in_flight.clone(),
+            key,
+        };
+        let result = self.send_request_once(method, params).await;
+        guard.complete(&result);
+        result
+    }
+
+    /// The key identical requests are coalesced on, if this one may be
+    fn single_flight_key(&self, method: &str, params: Option<&Value>) -> Option<String> {
+        if !self.single_flight {
+            return None;
+        }
+
+        let read_only = READ_ONLY_METHODS.contains(&method)
+            || (method == "tools/call"
+                && params
+                    .and_then(|p| p.get("name"))
+                    .and_then(Value::as_str)
+                    .and_then(|name| self.tool_annotations(name))
+                    .is_some_and(|annotations| annotations.is_read_only()));
+        let meta = self.outgoing_meta();
+        read_only.then(|| {
+            format!(
+                "{} {} {}",
+                method,
+                params.unwrap_or(&Value::Null),
+                Value::Object(meta)
+            )
+        })
+    }
+
+    /// The `_meta` fields to send with a request: the call's, then the client's
+    fn outgoing_meta(&self) -> Meta {
+        let mut meta = CALL_META.try_with(Meta::clone).unwrap_or_default();
+        for (key, value) in &self.request_meta {
+            meta.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+        meta
+    }
+
+    /// Remember the annotations of the listed tools
+    fn learn_tool_annotations(&self, response: &JSONRPCMessage) {
+        let JSONRPCMessage::Response(response) = response else {
+            return;
+        };
+        let Some(tools) = response.result.get("tools").and_then(Value::as_array) else {
+            return;
+        };
+
+        let mut tool_annotations = self.tool_annotations.lock().unwrap();
+        for tool in tools {
+            let Some(name) = tool.get("name").and_then(Value::as_str) else {
+                continue;
+            };
+            let annotations = tool
+                .get("annotations")
+                .and_then(|annotations| serde_json::from_value(annotations.clone()).ok());
+            match annotations {
+                Some(annotations) => tool_annotations.insert(name.to_string(), annotations),
+                None => tool_annotations.remove(name),
+            };
+        }
+    }
+
+    /// The annotations of a tool, as of the last tool list
+    pub fn tool_annotations(&self, tool_name: &str) -> Option<ToolAnnotations> {
+        self.tool_annotations
+            .lock()
+            .unwrap()
+            .get(tool_name)
+            .cloned()
+    }
+
+    /// Send a request once, reconnecting if the transport fails
+    async fn send_request_once(
+        &mut self,
+        method: &str,
+        params: Option<Value>,
+    ) -> Result<JSONRPCMessage, MCPError> {
+        let request = JSONRPCRequest::new(self.next_request_id(), method.to_string(), params);
+
+        match self.exchange(request).await {
+            Ok(response) => {
+                if method == "tools/list" {
+                    self.learn_tool_annotations(&response);
+                }
+                if self.validate_output && method == "tools/list" {
+                    learn_schemas(&response, "outputSchema", &self.output_schemas);
+                }
+                if self.validate_arguments && method == "tools/list" {
+                    learn_schemas(&response, "inputSchema", &self.input_schemas);
+                }
+                Ok(response)
+            }
+            // A connection the client did not close itself was lost
+            Err(e @ (MCPError::Transport(_) | MCPError::ConnectionClosed))
+                if self.state() != ConnectionState::Closed =>
+            {
+                self.report_lost(e.to_string()).await;
+                if self.reconnect_policy.is_none() {
+                    return Err(e);
+                }
+                // Re-establish the session for subsequent requests, but do not
+                // replay this one: it may not be safe to execute twice.
+                warn!("Transport failed during '{}', reconnecting: {}", method, e);
+                self.reconnect().await?;
+                Err(e)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Send a request and wait for the next message from the server
+    ///
+    /// The request is counted in the session stats, and tracked in the
+    /// pending map until this returns or is dropped.
+    async fn exchange(&mut self, request: JSONRPCRequest) -> Result<JSONRPCMessage, MCPError> {
+        let span = RequestSpan::new("client", &request.method, &request.id);
+        let result = span.instrument(self.send_and_receive(request)).await;
+        if matches!(result, Err(_) | Ok(JSONRPCMessage::Error(_))) {
+            self.stats.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// Send a request and wait for the next message from the server
+    async fn send_and_receive(
+        &mut self,
+        mut request: JSONRPCRequest,
+    ) -> Result<JSONRPCMessage, MCPError> {
+        request.jsonrpc = self.jsonrpc_version().to_string();
+        request.params = merge_meta(request.params.take(), &self.outgoing_meta());
+        let method = request.method.clone();
+        let (mut guard, handed_over) = self.track_request(&request);
+
+        self.send_unsent_cancellations().await?;
+        let message = JSONRPCMessage::Request(request);
+        self.transport.send(&message).await?;
+        guard.notify_server = true;
+        self.stats.requests_sent.fetch_add(1, Ordering::Relaxed);
+
+        self.await_response(&mut guard, &method, handed_over).await
+    }
+
+    /// Send requests in one batch and wait for their responses, in order
+    async fn send_batch(
+        &mut self,
+        mut requests: Vec<JSONRPCRequest>,
+    ) -> Result<Vec<Result<Value, MCPError>>, MCPError> {
+        if requests.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let meta = self.outgoing_meta();
+        let mut tracked = Vec::with_capacity(requests.len());
+        for request in &mut requests {
+            request.jsonrpc = self.jsonrpc_version().to_string();
+            request.params = merge_meta(request.params.take(), &meta);
+            let (guard, handed_over) = self.track_request(request);
+            tracked.push((request.method.clone(), guard, handed_over));
+        }
+
+        self.send_unsent_cancellations().await?;
+        let batch: Vec<JSONRPCMessage> =
+            requests.into_iter().map(JSONRPCMessage::Request).collect();
+        self.transport.send(&batch).await?;
+        self.stats
+            .requests_sent
+            .fetch_add(batch.len() as u64, Ordering::Relaxed);
+        for (_, guard, _) in &mut tracked {
+            guard.notify_server = true;
+        }
+
+        // Responses may come back in any order; those read while waiting for
+        // an earlier one are handed over to their own waiter
+        let mut results = Vec::with_capacity(tracked.len());
+        for (method, mut guard, handed_over) in tracked {
+            let response = self.await_response(&mut guard, &method, handed_over).await;
+            if matches!(response, Err(_) | Ok(JSONRPCMessage::Error(_))) {
+                self.stats.errors.fetch_add(1, Ordering::Relaxed);
+            }
+            results.push(response.and_then(|response| decode_response(&method, response)));
+        }
+        Ok(results)
+    }
+
+    /// Track a request in the pending map, until the returned guard is dropped
+    fn track_request(
+        &self,
+        request: &JSONRPCRequest,
+    ) -> (PendingGuard, oneshot::Receiver<JSONRPCMessage>) {
+        let (reply, handed_over) = oneshot::channel();
+        self.pending.lock().unwrap().insert(
+            request.id.clone(),
+            PendingRequest {
+                method: request.method.clone(),
+                started: Instant::now(),
+                reply: Some(reply),
+            },
+        );
+        let guard = PendingGuard {
+            pending: self.pending.clone(),
+            idle: self.idle.clone(),
+            cancelled: self.cancelled.clone(),
+            unsent_cancellations: self.unsent_cancellations.clone(),
+            id: request.id.clone(),
+            answered: false,
+            notify_server: false,
+        };
+        (guard, handed_over)
+    }
+
+    /// Wait for the response to a request that was sent
+    async fn await_response(
+        &mut self,
+        guard: &mut PendingGuard,
+        method: &str,
+        mut handed_over: oneshot::Receiver<JSONRPCMessage>,
+    ) -> Result<JSONRPCMessage, MCPError> {
+        let id = guard.id.clone();
+        self.lease_wanted.notify_one();
+
+        // Wait for the response with timeout if set, handling anything the
+        // server sends in the meantime. Clones of the client share the
+        // transport, and one request at a time, the holder of the read lease,
+        // reads it on behalf of all of them. The response may so be read and
+        // handed over by another clone, or the connection closed by another
+        // clone.
+        let mut state = self.state.subscribe();
+        let slow_request_threshold = self.slow_request_threshold.unwrap_or_default();
+        let clock = self.clock.clone();
+        let mut slow_deadline = self.slow_request_threshold.map(|t| clock.now() + t);
+        let call_deadline = CALL_DEADLINE.try_with(|deadline| *deadline).ok();
+        let read_lease = self.read_lease.clone();
+        loop {
+            let received = {
+                let receive = async {
+                    let lease = read_lease.lock().await;
+                    let result = self.receive_with_timeout::<JSONRPCPayload>().await;
+                    result.map(|payload| Incoming::Payload(lease, payload))
+                };
+                tokio::pin!(receive);
+
+                loop {
+                    tokio::select! {
+                        // A response handed over, or the connection closing,
+                        // wins over reading on
+                        biased;
+                        Ok(response) = &mut handed_over => break Ok(Incoming::HandedOver(response)),
+                        _ = state.wait_for(ConnectionState::is_lost) => {
+                            debug!("Request {:?} ('{}') abandoned, connection lost", id, method);
+                            break Err(MCPError::ConnectionClosed);
+                        }
+                        _ = clock.sleep_until(call_deadline.unwrap_or_else(|| clock.now())),
+                            if call_deadline.is_some() =>
+                        {
+                            break Err(MCPError::Timeout(format!(
+                                "Request {:?} ('{}') passed its deadline",
+                                id, method
+                            )));
+                        }
+                        result = &mut receive => break result,
+                        _ = clock.sleep_until(slow_deadline.unwrap_or_else(|| clock.now())),
+                            if slow_deadline.is_some() =>
+                        {
+                            warn!(
+                                "Request {:?} ('{}') still pending after {:?}",
+                                id, method, slow_request_threshold
+                            );
+                            slow_deadline = None;
+                        }
+                    }
+                }
+            };
+
+            let received = match received {
+                Err(MCPError::Serialization(e)) if !self.parse_mode.is_strict() => {
+                    self.skip_invalid_message(&e);
+                    continue;
+                }
+                received => received,
+            };
+
+            if let Err(e) = &received {
+                guard.notify_server = false;
+                if matches!(e, MCPError::Timeout(_)) {
+                    // The server need not finish what nobody waits for anymore
+                    if let Err(e) = self.send_cancelled(id.clone(), Some("timeout")).await {
+                        debug!("Failed to cancel timed out request {:?}: {}", id, e);
+                    }
+                }
+            }
+
+            // The lease is kept until every message read is dispatched
+            let (_lease, payload) = match received? {
+                Incoming::HandedOver(response) => {
+                    guard.answered = true;
+                    return self.check_jsonrpc_version(response);
+                }
+                Incoming::Payload(lease, payload) => (lease, payload),
+            };
+
+            // A batch may mix requests, notifications and responses, so every
+            // element is dispatched before our own response is returned
+            let mut own_response = None;
+            for message in payload.into_messages() {
+                match message {
+                    JSONRPCMessage::Request(request) => self.handle_server_request(request).await?,
+                    JSONRPCMessage::Notification(notification) => {
+                        self.handle_notification(notification)
+                    }
+                    response if own_response.is_none() && response.id() == Some(&id) => {
+                        own_response = Some(response);
+                    }
+                    response => self.route_response(response)?,
+                }
+            }
+
+            if let Some(response) = own_response {
+                guard.answered = true;
+                return self.check_jsonrpc_version(response);
+            }
+        }
+    }
+
+    /// React to a notification received while waiting for a response
+    fn handle_notification(&self, notification: JSONRPCNotification) {
+        self.emit(ClientEvent::NotificationReceived(notification.clone()));
+        {
+            let mut notifications = self.notifications.lock().unwrap();
+            if notifications.len() == NOTIFICATION_BUFFER_SIZE {
+                notifications.pop_front();
+            }
+            notifications.push_back(notification.clone());
+        }
+        self.notification_arrived.notify_waiters();
+
+        let handlers = self
+            .notification_handlers
+            .lock()
+            .unwrap()
+            .get(&notification.method)
+            .cloned()
+            .unwrap_or_default();
+        for handler in &handlers {
+            handler(&notification);
+        }
+
+        let params = notification.params.unwrap_or(Value::Null);
+        if let Some(cache) = &self.response_cache {
+            cache.notified(&notification.method, &params);
+        }
+        match notification.method.as_str() {
+            "notifications/cancelled" => {
+                // Server requests are answered before the next message is
+                // read, so the cancelled request has already been handled
+                if let Ok(params) = serde_json::from_value::<CancelledParams>(params) {
+                    info!(
+                        "Server cancelled request {:?}: {}",
+                        params.request_id,
+                        params.reason.as_deref().unwrap_or("no reason given")
+                    );
+                }
+            }
+            "notifications/progress" => {
+                let Ok(progress) = serde_json::from_value::<ProgressParams>(params) else {
+                    warn!("Ignoring invalid progress notification");
+                    return;
+                };
+                let listener = self
+                    .progress_listeners
+                    .lock()
+                    .unwrap()
+                    .get(&progress.progress_token)
+                    .cloned();
+                match listener {
+                    Some(listener) => {
+                        // The stream may have been dropped, which is fine
+                        let _ = listener.send(progress);
+                    }
+                    None if handlers.is_empty() => debug!(
+                        "Ignoring progress for unknown token {:?}",
+                        progress.progress_token
+                    ),
+                    None => {}
+                }
+            }
+            "notifications/resources/updated" => {
+                let update = match serde_json::from_value::<ResourceUpdatedParams>(params) {
+                    Ok(update) => update,
+                    Err(e) => {
+                        warn!("Ignoring invalid resource update: {}", e);
+                        return;
+                    }
+                };
+                if let Some(resources) = self.resources.lock().unwrap().as_mut() {
+                    for resource in resources.iter_mut().filter(|r| r.uri == update.uri) {
+                        update.apply_to(resource);
+                    }
+                }
+                // Nobody listening is not an error for the client
+                let _ = self.resource_updates.send(update);
+            }
+            "notifications/message" => {
+                let message = match serde_json::from_value::<LoggingMessageParams>(params) {
+                    Ok(message) => message,
+                    Err(e) => {
+                        warn!("Ignoring invalid log message: {}", e);
+                        return;
+                    }
+                };
+                if self.forward_logs {
+                    let level = match message.level {
+                        LoggingLevel::Debug => log::Level::Debug,
+                        LoggingLevel::Info | LoggingLevel::Notice => log::Level::Info,
+                        LoggingLevel::Warning => log::Level::Warn,
+                        _ => log::Level::Error,
+                    };
+                    let data = match &message.data {
+                        Value::String(text) => text.clone(),
+                        data => data.to_string(),
+                    };
+                    match &message.logger {
+                        Some(logger) => {
+                            log::log!(target: "mcpr::server", level, "[{}] {}", logger, data)
+                        }
+                        None => log::log!(target: "mcpr::server", level, "{}", data),
+                    }
+                }
+                let _ = self.log_messages.send(message);
+            }
+            method if handlers.is_empty() => debug!(
+                "Ignoring notification '{}' received while waiting for a response",
+                method
+            ),
+            _ => {}
+        }
+    }
+
+    /// Hand a response over to the clone waiting for it, or classify it as unmatched
+    ///
+    /// A strict client fails on a response to an id it never sent.
+    fn route_response(&self, response: JSONRPCMessage) -> Result<(), MCPError> {
+        let reply = response.id().and_then(|id| {
+            self.pending
+                .lock()
+                .unwrap()
+                .get_mut(id)
+                .and_then(|request| request.reply.take())
+        });
+
+        match reply {
+            Some(reply) => {
+                // The waiter may have been dropped in the meantime
+                let _ = reply.send(response);
+                Ok(())
+            }
+            None => self.handle_unmatched_response(response),
+        }
+    }
+
+    /// Classify a response whose id does not match the request being awaited
+    fn handle_unmatched_response(&self, response: JSONRPCMessage) -> Result<(), MCPError> {
+        let Some(id) = response.id() else {
+            return Ok(());
+        };
+
+        let cancelled = self.cancelled.lock().unwrap().remove(id);
+        let Some(cancelled) = cancelled else {
+            // Nobody listening is not an error for the client
+            let _ = self
+                .diagnostics
+                .send(ClientDiagnostic::UnknownResponse { id: id.clone() });
+            if self.parse_mode.is_strict() {
+                return Err(MCPError::Protocol(format!(
+                    "Received a response with unknown id {:?}",
+                    id
+                )));
+            }
+            warn!("Ignoring response with unknown id {:?}", id);
+            return Ok(());
+        };
+
+        let after_cancel = cancelled.cancelled_at.elapsed();
+        match self.late_response_policy {
+            LateResponsePolicy::IgnoreSilently => {}
+            LateResponsePolicy::Log => info!(
+                "Ignoring late response to cancelled request {:?} ('{}'), {:?} after cancellation",
+                id, cancelled.method, after_cancel
+            ),
+            LateResponsePolicy::Error => {
+                // Nobody listening is not an error for the client
+                let _ = self.diagnostics.send(ClientDiagnostic::LateResponse {
+                    id: id.clone(),
+                    method: cancelled.method,
+                    after_cancel,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Log and publish a message from the server that could not be parsed
+    ///
+    /// The transport has consumed it, so reading goes on with the next one;
+    /// a strict client fails instead.
+    fn skip_invalid_message(&self, error: &serde_json::Error) {
+        warn!(
+            "Skipping a message from the server that failed to parse: {}",
+            error
+        );
+        let _ = self.diagnostics.send(ClientDiagnostic::InvalidMessage {
+            error: error.to_string(),
+        });
+    }
+
+    /// Run the registered handler for a server-initiated request and reply
+    ///
+    /// Progress reported by the handler is forwarded to the server while the
+    /// handler runs, ahead of the response.
+    async fn handle_server_request(&mut self, request: JSONRPCRequest) -> Result<(), MCPError> {
+        debug!("Received server request '{}'", request.method);
+
+        let progress_token = request
+            .params
+            .as_ref()
+            .and_then(|params| params.get("_meta"))
+            .and_then(|meta| serde_json::from_value::<RequestMeta>(meta.clone()).ok())
+            .and_then(|meta| meta.progress_token);
+
+        let (outgoing, mut outgoing_rx) = mpsc::unbounded_channel();
+        let context = RequestContext {
+            id: request.id.clone(),
+            method: request.method.clone(),
+            params: request.params,
+            progress_token,
+            outgoing,
+        };
+
+        let handler_future = match (&self.roots, request.method.as_str()) {
+            (_, "ping") => Some(Box::pin(async { Ok(serde_json::json!({})) })
+                as Pin<Box<dyn Future<Output = Result<Value, MCPError>> + Send>>),
+            // Roots are answered from the client's own roots, not a handler
+            (Some(roots), "roots/list") => {
+                let roots = roots.clone();
+                Some(Box::pin(
+                    async move { Ok(serde_json::json!({ "roots": roots.list().await? })) },
+                )
+                    as Pin<
+                        Box<dyn Future<Output = Result<Value, MCPError>> + Send>,
+                    >)
+            }
+            _ => {
+                let handlers = self.request_handlers.lock().unwrap();
+                handlers
+                    .get(&request.method)
+                    .map(|handler| handler(context))
+            }
+        };
+
+        let reply = match handler_future {
+            Some(handler_future) => {
+                tokio::pin!(handler_future);
+                let result = loop {
+                    tokio::select! {
+                        result = &mut handler_future => break result,
+                        Some(message) = outgoing_rx.recv() => self.transport.send(&message).await?,
+                    }
+                };
+                while let Ok(message) = outgoing_rx.try_recv() {
+                    self.transport.send(&message).await?;
+                }
+
+                match result {
+                    Ok(result) => {
+                        JSONRPCMessage::Response(JSONRPCResponse::new(request.id, result))
+                    }
+                    // Handlers choose the code of errors they mean to send
+                    Err(MCPError::Rpc {
+                        code,
+                        message,
+                        data,
+                    }) => JSONRPCMessage::Error(JSONRPCError::new_with_details(
+                        request.id, code, message, data,
+                    )),
+                    Err(e) => JSONRPCMessage::Error(JSONRPCError::new_with_details(
+                        request.id,
+                        error_codes::INTERNAL_ERROR,
+                        e.to_string(),
+                        None,
+                    )),
+                }
+            }
+            None => {
+                warn!(
+                    "No handler registered for server request '{}'",
+                    request.method
+                );
+                JSONRPCMessage::Error(JSONRPCError::new_with_details(
+                    request.id,
+                    error_codes::METHOD_NOT_FOUND,
+                    format!("Method not found: {}", request.method),
+                    None,
+                ))
+            }
+        };
+
+        self.transport.send(&reply).await
+    }
+
+    /// Reject a message whose JSON-RPC version differs from the expected one
+    fn check_jsonrpc_version(&self, message: JSONRPCMessage) -> Result<JSONRPCMessage, MCPError> {
+        if message.jsonrpc() != self.jsonrpc_version() {
+            return Err(MCPError::Protocol(format!(
+                "Unexpected JSON-RPC version '{}', expected '{}'",
+                message.jsonrpc(),
+                self.jsonrpc_version()
+            )));
+        }
+        Ok(message)
+    }
+
+    /// The JSON-RPC version sent and expected, which is always `"2.0"` when strict
+    fn jsonrpc_version(&self) -> &str {
+        if self.parse_mode.is_strict() {
+            JSONRPC_VERSION
+        } else {
+            &self.jsonrpc_version
+        }
+    }
+
+    /// Send a request whose result is expected to be an empty object
+    ///
+    /// A non-empty result is tolerated but logged, since it usually means the
+    /// server and client disagree about the method's contract; a strict client
+    /// rejects it.
+    async fn send_empty_request(
+        &mut self,
+        method: &str,
+        params: Option<Value>,
+    ) -> Result<(), MCPError> {
+        let result: EmptyResult = self.send_request(method, params).await?;
+        if self.parse_mode.is_strict() && !result.extra.is_empty() {
+            return Err(MCPError::Protocol(format!(
+                "Expected an empty result for '{}' but received fields: {:?}",
+                method,
+                result.extra.keys().collect::<Vec<_>>()
+            )));
+        }
+        if !result.extra.is_empty() {
+            warn!(
+                "Expected an empty result for '{}' but received fields: {:?}",
+                method,
+                result.extra.keys().collect::<Vec<_>>()
+            );
+        }
+        Ok(())
+    }
+
+    /// Receive a message with optional timeout
+    async fn receive_with_timeout<R: DeserializeOwned + Send + Sync>(
+        &mut self,
+    ) -> Result<R, MCPError> {
+        if let Some(duration) = self.timeout_duration {
+            let clock = self.clock.clone();
+            match clock::timeout(&*clock, duration, self.transport.receive::<R>()).await {
+                Some(result) => result,
+                None => Err(MCPError::Timeout(format!(
+                    "Operation timed out after {:?}",
+                    duration
+                ))),
+            }
+        } else {
+            self.transport.receive().await
+        }
+    }
+
+    /// Generate the next request ID
+    fn next_request_id(&mut self) -> RequestId {
+        RequestId::Number(self.next_request_id.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Call multiple tools concurrently
+    ///
+    /// This method demonstrates the power of async by allowing multiple tool calls to be made
+    /// concurrently. Each tool call is represented as a tuple of (tool_name, parameters).
+    ///
+    /// Each call is made on a clone of the client, so the transport's clones
+    /// must share its connection, as for [`Client`]'s `Clone`.
+    #[cfg(feature = "runtime-tokio")]
+    pub async fn call_tools_concurrent<P, R>(
+        &self,
+        tool_calls: Vec<(String, P)>,
+    ) -> Result<Vec<Result<R, MCPError>>, MCPError>
+    where
+        P: Serialize + Clone + Send + Sync + 'static,
+        R: DeserializeOwned + Send + Sync + 'static,
+        T: Clone + Send + Sync + 'static,
+    {
+        let mut tasks = Vec::with_capacity(tool_calls.len());
+
+        // Create a new client for each concurrent call
+        for (tool_name, params) in tool_calls {
+            let mut client = self.clone();
+
+            // Spawn a task for each tool call
+            let task =
+                tokio::spawn(async move { client.call_tool::<P, R>(&tool_name, &params).await });
+
+            tasks.push(task);
+        }
+
+        // Wait for all tasks to complete
+        let results = futures::future::join_all(tasks).await;
+
+        // Process results, handling any JoinErrors from the tasks
+        let processed_results = results
+            .into_iter()
+            .map(|join_result| match join_result {
+                Ok(tool_result) => tool_result,
+                Err(e) => Err(MCPError::Transport(format!("Task join error: {}", e))),
+            })
+            .collect();
+
+        Ok(processed_results)
+    }
+
+    /// Execute a complete client session in one call
+    ///
+    /// This method handles initialization, tool calls, and shutdown in a single method call.
+    /// It's especially useful for short-lived clients that need to make a few tool calls
+    /// and then shut down.
+    ///
+    /// # Example
+    /// ```ignore
+    /// use mcpr::{client::Client, transport::stdio::StdioTransport};
+    /// use serde_json::json;
+    ///
+    /// // Create a client with stdio transport
+    /// let mut client = Client::new(StdioTransport::new());
+    ///
+    /// // Execute a session with initialization and shutdown handled automatically
+    /// let result = client.execute_session(|client| {
+    ///     Box::pin(async move {
+    ///         // Call a tool and return the result
+    ///         client.call_tool("example_tool", &json!({"param": "value"})).await
+    ///     })
+    /// }).await;
+    /// ```
+    pub async fn execute_session<F, Fut, R>(&mut self, f: F) -> Result<R, MCPError>
+    where
+        F: FnOnce(&mut Self) -> Fut + Send,
+        Fut: std::future::Future<Output = Result<R, MCPError>> + Send,
+        R: Send,
+    {
+        // Initialize the client
+        self.initialize().await?;
+
+        // Execute the user-provided function
+        let result = f(self).await;
+
+        // Shutdown the client regardless of whether the function succeeded
+        let shutdown_result = self.shutdown().await;
+
+        // Return the function result if it succeeded, otherwise return the function error
+        match result {
+            Ok(r) => {
+                // If the function succeeded but shutdown failed, return the shutdown error
+                if let Err(e) = shutdown_result {
+                    Err(e)
+                } else {
+                    Ok(r)
+                }
+            }
+            Err(e) => {
+                // If both the function and shutdown failed, prefer the function error
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::schema::json_rpc::{JSONRPCError, JSONRPCMessage, JSONRPCResponse, RequestId};
+    use crate::schema::server::ToolCallResult;
+    use crate::transport::Transport;
+    use crate::transport::{CloseCallback, ErrorCallback, MessageCallback, TrafficCounter};
+    use async_trait::async_trait;
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::sync::Mutex as TokioMutex;
+
+    // Mock transport for testing
+    #[derive(Clone)]
+    struct MockTransport {
+        send_queue: Arc<TokioMutex<VecDeque<String>>>,
+        receive_queue: Arc<TokioMutex<VecDeque<String>>>,
+        is_started: Arc<TokioMutex<bool>>,
+        is_closed: Arc<TokioMutex<bool>>,
+        should_fail: Arc<TokioMutex<bool>>,
+        simulate_timeout: Arc<TokioMutex<bool>>,
+        on_message: Arc<Mutex<Option<MessageCallback>>>,
+        on_error: Arc<Mutex<Option<ErrorCallback>>>,
+        on_close: Arc<Mutex<Option<CloseCallback>>>,
+        traffic: TrafficCounter,
+    }
+
+    impl MockTransport {
+        fn new() -> Self {
+            Self {
+                send_queue: Arc::new(TokioMutex::new(VecDeque::new())),
+                receive_queue: Arc::new(TokioMutex::new(VecDeque::new())),
+                is_started: Arc::new(TokioMutex::new(false)),
+                is_closed: Arc::new(TokioMutex::new(false)),
+                should_fail: Arc::new(TokioMutex::new(false)),
+                simulate_timeout: Arc::new(TokioMutex::new(false)),
+                on_message: Arc::new(Mutex::new(None)),
+                on_error: Arc::new(Mutex::new(None)),
+                on_close: Arc::new(Mutex::new(None)),
+                traffic: TrafficCounter::default(),
+            }
+        }
+
+        async fn queue_message(&self, message: JSONRPCMessage) {
             let serialized = serde_json::to_string(&message).unwrap();
             let mut queue = self.receive_queue.lock().await;
             queue.push_back(serialized);
         }
 
-        async fn get_last_sent(&self) -> Option<String> {
-            let mut queue = self.send_queue.lock().await;
-            queue.pop_front()
+        async fn get_last_sent(&self) -> Option<String> {
+            let mut queue = self.send_queue.lock().await;
+            queue.pop_front()
+        }
+
+        async fn set_should_fail(&self, should_fail: bool) {
+            let mut fail = self.should_fail.lock().await;
+            *fail = should_fail;
+        }
+
+        async fn set_simulate_timeout(&self, timeout: bool) {
+            let mut t = self.simulate_timeout.lock().await;
+            *t = timeout;
+        }
+    }
+
+    #[async_trait]
+    impl Transport for MockTransport {
+        async fn start(&mut self) -> Result<(), MCPError> {
+            let should_fail = *self.should_fail.lock().await;
+            if should_fail {
+                return Err(MCPError::Transport(
+                    "Mock transport failed to start".to_string(),
+                ));
+            }
+
+            let mut started = self.is_started.lock().await;
+            *started = true;
+            Ok(())
+        }
+
+        async fn send<T: Serialize + Send + Sync>(&mut self, message: &T) -> Result<(), MCPError> {
+            let should_fail = *self.should_fail.lock().await;
+            if should_fail {
+                return Err(MCPError::Transport(
+                    "Mock transport send failure".to_string(),
+                ));
+            }
+
+            let serialized = serde_json::to_string(message).map_err(MCPError::Serialization)?;
+            self.traffic.sent(serialized.len());
+
+            let mut queue = self.send_queue.lock().await;
+            queue.push_back(serialized);
+            Ok(())
+        }
+
+        async fn receive<T: DeserializeOwned + Send + Sync>(&mut self) -> Result<T, MCPError> {
+            let should_fail = *self.should_fail.lock().await;
+            if should_fail {
+                return Err(MCPError::Transport(
+                    "Mock transport receive failure".to_string(),
+                ));
+            }
+
+            let simulate_timeout = *self.simulate_timeout.lock().await;
+            if simulate_timeout {
+                // Simulate a long operation
+                tokio::time::sleep(Duration::from_secs(2)).await;
+            }
+
+            let mut queue = self.receive_queue.lock().await;
+
+            if let Some(message) = queue.pop_front() {
+                self.traffic.received(message.len());
+
+                // If there's a message callback, call it
+                if let Some(callback) = &*self.on_message.lock().unwrap() {
+                    callback(&message);
+                }
+
+                return serde_json::from_str(&message).map_err(MCPError::Serialization);
+            }
+
+            Err(MCPError::Transport("No more messages".to_string()))
+        }
+
+        async fn close(&mut self) -> Result<(), MCPError> {
+            let should_fail = *self.should_fail.lock().await;
+            if should_fail {
+                return Err(MCPError::Transport(
+                    "Mock transport close failure".to_string(),
+                ));
+            }
+
+            let mut closed = self.is_closed.lock().await;
+            *closed = true;
+
+            // If there's a close callback, call it
+            if let Some(callback) = &*self.on_close.lock().unwrap() {
+                callback();
+            }
+
+            Ok(())
+        }
+
+        fn set_on_close(&mut self, callback: Option<CloseCallback>) {
+            let mut cb = self.on_close.lock().unwrap();
+            *cb = callback;
+        }
+
+        fn set_on_error(&mut self, callback: Option<ErrorCallback>) {
+            let mut cb = self.on_error.lock().unwrap();
+            *cb = callback;
+        }
+
+        fn set_on_message<F>(&mut self, callback: Option<F>)
+        where
+            F: Fn(&str) + Send + Sync + 'static,
+        {
+            let mut cb = self.on_message.lock().unwrap();
+            if let Some(callback) = callback {
+                *cb = Some(Box::new(callback));
+            } else {
+                *cb = None;
+            }
+        }
+
+        fn traffic(&self) -> Option<Traffic> {
+            Some(self.traffic.traffic())
+        }
+    }
+
+    // Helper function to create a server info response
+    fn create_initialize_response(id: RequestId) -> JSONRPCMessage {
+        JSONRPCMessage::Response(JSONRPCResponse {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: serde_json::json!({
+                "protocol_version": "1.0",
+                "server_info": {
+                    "name": "TestServer",
+                    "version": "1.0.0",
+                    "protocol_version": "1.0"
+                },
+                "capabilities": {
+                    "tools": true
+                }
+            }),
+        })
+    }
+
+    // Helper function to create a tools list response
+    fn create_tools_list_response(id: RequestId) -> JSONRPCMessage {
+        JSONRPCMessage::Response(JSONRPCResponse {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: serde_json::json!([
+                {
+                    "name": "hello",
+                    "description": "Say hello",
+                    "input_schema": {
+                        "type": "object",
+                        "properties": {
+                            "name": {
+                                "type": "string"
+                            }
+                        }
+                    }
+                }
+            ]),
+        })
+    }
+
+    // Helper function to create a tool call response
+    fn create_tool_call_response(id: RequestId, result: serde_json::Value) -> JSONRPCMessage {
+        JSONRPCMessage::Response(JSONRPCResponse {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: serde_json::json!({
+                "result": result
+            }),
+        })
+    }
+
+    // Basic client initialization test
+    #[tokio::test]
+    async fn test_client_initialization() {
+        // Create a mock transport
+        let mock = MockTransport::new();
+
+        // Queue the initialize response
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
+
+        // Create client with mock transport
+        let mut client = Client::new(mock.clone());
+
+        // Test initialization
+        let result = client.initialize().await;
+        assert!(result.is_ok(), "Client initialization should succeed");
+
+        // Check what was sent to the server
+        let sent = mock.get_last_sent().await.unwrap();
+        let sent_msg: JSONRPCMessage = serde_json::from_str(&sent).unwrap();
+
+        if let JSONRPCMessage::Request(req) = sent_msg {
+            assert_eq!(req.method, "initialize");
+        } else {
+            panic!("Expected request message");
+        }
+    }
+
+    // Test client error handling
+    #[tokio::test]
+    async fn test_client_error_handling() {
+        // Create a mock transport that will fail
+        let mock = MockTransport::new();
+        mock.set_should_fail(true).await;
+
+        // Create client with mock transport
+        let mut client = Client::new(mock);
+
+        // Test initialization with failing transport
+        let result = client.initialize().await;
+        assert!(
+            result.is_err(),
+            "Client initialization should fail with failing transport"
+        );
+    }
+
+    // Test tool calling
+    #[tokio::test]
+    async fn test_tool_call() {
+        // Create a mock transport
+        let mock = MockTransport::new();
+
+        // Queue the initialize response
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
+
+        // Queue the tool call response
+        mock.queue_message(create_tool_call_response(
+            RequestId::Number(2),
+            serde_json::json!("Hello, Test User!"),
+        ))
+        .await;
+
+        // Create client with mock transport
+        let mut client = Client::new(mock.clone());
+
+        // Initialize the client
+        client.initialize().await.unwrap();
+
+        // Call a tool
+        let params = serde_json::json!({
+            "name": "Test User"
+        });
+
+        let result: ToolCallResult = client.call_tool("hello", &params).await.unwrap();
+        assert_eq!(result.result, "Hello, Test User!");
+
+        // Check what was sent to the server
+        let _init_msg = mock.get_last_sent().await.unwrap();
+        let tool_msg = mock.get_last_sent().await.unwrap();
+
+        let tool_req: JSONRPCMessage = serde_json::from_str(&tool_msg).unwrap();
+        if let JSONRPCMessage::Request(req) = tool_req {
+            assert_eq!(req.method, "tools/call");
+            if let Some(params) = req.params {
+                assert_eq!(params["name"], "hello");
+            } else {
+                panic!("Expected tool call params");
+            }
+        } else {
+            panic!("Expected request message");
+        }
+    }
+
+    // Test listing tools
+    #[tokio::test]
+    async fn test_list_tools() {
+        let mock = MockTransport::new();
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
+        mock.queue_message(create_tools_list_response(RequestId::Number(2)))
+            .await;
+
+        let mut client = Client::new(mock.clone());
+        client.initialize().await.unwrap();
+
+        let tools: Value = client.list_tools().await.unwrap();
+        assert_eq!(tools[0]["name"], "hello");
+
+        let _init_msg = mock.get_last_sent().await.unwrap();
+        let list_msg = mock.get_last_sent().await.unwrap();
+        let list_req: JSONRPCMessage = serde_json::from_str(&list_msg).unwrap();
+        if let JSONRPCMessage::Request(req) = list_req {
+            assert_eq!(req.method, "tools/list");
+        } else {
+            panic!("Expected request message");
+        }
+    }
+
+    // Test ping with empty and non-empty results
+    #[tokio::test]
+    async fn test_ping() {
+        let mock = MockTransport::new();
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(2),
+            serde_json::json!({}),
+        )))
+        .await;
+        // A non-empty result is tolerated (with a warning)
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(3),
+            serde_json::json!({"unexpected": true}),
+        )))
+        .await;
+
+        let mut client = Client::new(mock.clone());
+        client.initialize().await.unwrap();
+
+        assert!(client.ping().await.is_ok());
+        assert!(client.ping().await.is_ok());
+
+        let _init_msg = mock.get_last_sent().await.unwrap();
+        let ping_msg = mock.get_last_sent().await.unwrap();
+        let ping_req: JSONRPCMessage = serde_json::from_str(&ping_msg).unwrap();
+        if let JSONRPCMessage::Request(req) = ping_req {
+            assert_eq!(req.method, "ping");
+            assert!(req.params.is_none());
+        } else {
+            panic!("Expected request message");
+        }
+    }
+
+    // Test building arguments with nested objects and arrays
+    #[test]
+    fn test_arguments_builder() {
+        let args = Arguments::builder()
+            .arg("message", "hello")
+            .arg("count", 3)
+            .arg("ratio", 0.5)
+            .arg("tags", vec!["a", "b"])
+            .arg("options", Arguments::builder().arg("verbose", true).build())
+            .arg_serialized("pair", &(1, "x"))
+            .unwrap()
+            .build();
+
+        assert_eq!(
+            serde_json::to_value(&args).unwrap(),
+            serde_json::json!({
+                "message": "hello",
+                "count": 3,
+                "ratio": 0.5,
+                "tags": ["a", "b"],
+                "options": { "verbose": true },
+                "pair": [1, "x"]
+            })
+        );
+    }
+
+    // Test advertising accepted content types during initialization
+    #[tokio::test]
+    async fn test_accepted_content_types() {
+        let mock = MockTransport::new();
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
+
+        let mut client =
+            Client::new(mock.clone()).with_accepted_content_types(["text", "image/png"]);
+        client.initialize().await.unwrap();
+
+        let sent: Value = serde_json::from_str(&mock.get_last_sent().await.unwrap()).unwrap();
+        assert_eq!(
+            sent["params"]["capabilities"]["experimental"]["acceptedContentTypes"],
+            serde_json::json!(["text", "image/png"])
+        );
+    }
+
+    // Test getting both content and structured output from a tool call
+    #[tokio::test]
+    async fn test_call_tool_full() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Weather {
+            celsius: f64,
+        }
+
+        let mock = MockTransport::new();
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(1),
+            serde_json::json!({
+                "content": [{ "type": "text", "text": "It is 21.5°C" }],
+                "structuredContent": { "celsius": 21.5 }
+            }),
+        )))
+        .await;
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(2),
+            serde_json::json!({ "content": [] }),
+        )))
+        .await;
+
+        let mut client = Client::new(mock);
+        let (result, weather) = client
+            .call_tool_full::<_, Weather>("weather", &serde_json::json!({}))
+            .await
+            .unwrap();
+        assert_eq!(result.to_transcript(), "tool: It is 21.5°C");
+        assert_eq!(weather, Some(Weather { celsius: 21.5 }));
+
+        let (_, weather) = client
+            .call_tool_full::<_, Weather>("weather", &serde_json::json!({}))
+            .await
+            .unwrap();
+        assert_eq!(weather, None);
+    }
+
+    // Test typed content blocks and tool errors from call_tool_result
+    #[tokio::test]
+    async fn test_call_tool_result() {
+        let mock = MockTransport::new();
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(1),
+            serde_json::json!({
+                "content": [
+                    { "type": "text", "text": "Recorded" },
+                    { "type": "audio", "data": "aGVsbG8=", "mimeType": "audio/wav" }
+                ]
+            }),
+        )))
+        .await;
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(2),
+            serde_json::json!({
+                "content": [{ "type": "text", "text": "Microphone unavailable" }],
+                "isError": true
+            }),
+        )))
+        .await;
+
+        let mut client = Client::new(mock);
+        let result = client
+            .call_tool_result("record", &serde_json::json!({}))
+            .await
+            .unwrap();
+        assert!(
+            matches!(&result.content[1], ToolResultContent::Audio(audio) if audio.mime_type == "audio/wav")
+        );
+        assert_eq!(
+            result.to_transcript(),
+            "tool: Recorded\ntool: [audio/wav, 5B]"
+        );
+
+        match client
+            .call_tool_result("record", &serde_json::json!({}))
+            .await
+        {
+            Err(MCPError::ToolError {
+                tool,
+                message,
+                content,
+            }) => {
+                assert_eq!(tool, "record");
+                assert_eq!(message, "Microphone unavailable");
+                assert_eq!(content.len(), 1);
+            }
+            other => panic!("Expected a tool error, got {:?}", other),
+        }
+    }
+
+    // Test that oversized tool calls are uploaded in chunks, and others are not
+    #[tokio::test]
+    async fn test_chunked_upload() {
+        let mock = MockTransport::new();
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(1),
+            serde_json::json!({
+                "capabilities": {
+                    "tools": {},
+                    "experimental": { "chunkedUpload": { "maxMessageSize": 200 } }
+                }
+            }),
+        )))
+        .await;
+        // Chunk acknowledgements and the tool result, for whichever ids are used
+        for id in 2..80 {
+            mock.queue_message(create_tool_call_response(
+                RequestId::Number(id),
+                serde_json::json!("done"),
+            ))
+            .await;
+        }
+
+        let mut client = Client::new(mock.clone()).with_chunked_uploads();
+        client.initialize().await.unwrap();
+        mock.get_last_sent().await;
+
+        // A small call is sent as is
+        let _: Value = client
+            .call_tool("echo", &serde_json::json!({ "message": "hi" }))
+            .await
+            .unwrap();
+        let sent: JSONRPCRequest =
+            serde_json::from_str(&mock.get_last_sent().await.unwrap()).unwrap();
+        assert_eq!(sent.params.unwrap()["arguments"]["message"], "hi");
+
+        // A large one is split, with every request within the limit
+        let document = "line \"quoted\"\n".repeat(40);
+        let _: Value = client
+            .call_tool("echo", &serde_json::json!({ "message": document }))
+            .await
+            .unwrap();
+
+        let mut uploaded = String::new();
+        let mut chunks = 0;
+        while let Some(sent) = mock.get_last_sent().await {
+            assert!(sent.len() <= 200, "request of {} bytes", sent.len());
+            let request: JSONRPCRequest = serde_json::from_str(&sent).unwrap();
+            let params = request.params.unwrap();
+            match request.method.as_str() {
+                "uploads/chunk" => {
+                    let chunk: UploadChunkParams = serde_json::from_value(params).unwrap();
+                    assert_eq!(chunk._meta.chunk_index, Some(chunks));
+                    assert!(chunk._meta.chunk_count.is_some());
+                    uploaded.push_str(&chunk.data);
+                    chunks += 1;
+                }
+                "tools/call" => {
+                    assert!(params.get("arguments").is_none());
+                    assert_eq!(params["_meta"]["chunkCount"], chunks);
+                }
+                method => panic!("Unexpected request: {}", method),
+            }
+        }
+        assert!(chunks > 1);
+        let arguments: Value = serde_json::from_str(&uploaded).unwrap();
+        assert_eq!(arguments["message"], document);
+    }
+
+    // Test that identical concurrent calls to a read-only tool are sent once
+    #[tokio::test(start_paused = true)]
+    async fn test_single_flight() {
+        let mock = MockTransport::new();
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(2),
+            serde_json::json!({
+                "tools": [
+                    { "name": "search", "annotations": { "readOnlyHint": true } },
+                    { "name": "delete" }
+                ]
+            }),
+        )))
+        .await;
+        mock.queue_message(create_tool_call_response(
+            RequestId::Number(3),
+            serde_json::json!("found"),
+        ))
+        .await;
+
+        let mut client = Client::new(mock.clone()).with_single_flight(true);
+        client.initialize().await.unwrap();
+        let _: Value = client.list_tools().await.unwrap();
+        while mock.get_last_sent().await.is_some() {}
+
+        // Slow responses keep the first call in flight while the second joins it
+        mock.set_simulate_timeout(true).await;
+        let query = serde_json::json!({ "query": "mcp" });
+        let results: Vec<Result<Value, MCPError>> = client
+            .call_tools_concurrent(vec![
+                ("search".to_string(), query.clone()),
+                ("search".to_string(), query),
+            ])
+            .await
+            .unwrap();
+
+        for result in results {
+            assert_eq!(result.unwrap()["result"], "found");
+        }
+        assert!(mock.get_last_sent().await.is_some());
+        assert!(mock.get_last_sent().await.is_none());
+
+        // The follower gets the leader's error as it is, not a protocol error
+        let query = serde_json::json!({ "query": "lost" });
+        let results: Vec<Result<Value, MCPError>> = client
+            .call_tools_concurrent(vec![
+                ("search".to_string(), query.clone()),
+                ("search".to_string(), query),
+            ])
+            .await
+            .unwrap();
+        for result in results {
+            assert!(matches!(result, Err(MCPError::Transport(_))));
+        }
+        assert!(mock.get_last_sent().await.is_some());
+        assert!(mock.get_last_sent().await.is_none());
+
+        // Tools without the read-only hint are never coalesced
+        assert!(client
+            .single_flight_key("tools/call", Some(&serde_json::json!({ "name": "delete" })))
+            .is_none());
+        assert!(client.single_flight_key("tools/list", None).is_some());
+    }
+
+    // Test answering repeated tool lists from the cache until the list changes
+    #[tokio::test(start_paused = true)]
+    async fn test_response_cache() {
+        let mock = MockTransport::new();
+        let tools = |id| {
+            JSONRPCMessage::Response(JSONRPCResponse::new(
+                RequestId::Number(id),
+                serde_json::json!({ "tools": [{ "name": format!("tool{}", id) }] }),
+            ))
+        };
+        mock.queue_message(tools(1)).await;
+        mock.queue_message(JSONRPCMessage::Notification(JSONRPCNotification::new(
+            "notifications/tools/list_changed".to_string(),
+            None,
+        )))
+        .await;
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(2),
+            serde_json::json!({}),
+        )))
+        .await;
+        mock.queue_message(tools(3)).await;
+
+        let cache = ResponseCache::new(Duration::from_secs(60));
+        let mut client = Client::new(mock.clone()).with_response_cache(cache.clone());
+        for _ in 0..2 {
+            let result: Value = client.list_tools().await.unwrap();
+            assert_eq!(result["tools"][0]["name"], "tool1");
+        }
+        assert!(mock.get_last_sent().await.is_some());
+        assert!(mock.get_last_sent().await.is_none());
+
+        // The notification read with the ping's response drops the cached list
+        client.ping().await.unwrap();
+        let result: Value = client.list_tools().await.unwrap();
+        assert_eq!(result["tools"][0]["name"], "tool3");
+        assert_eq!(cache.len(), 1);
+    }
+
+    // Test reading the server's messages while no request waits for a response
+    #[tokio::test]
+    async fn test_spawn_reader() -> Result<(), MCPError> {
+        use crate::transport::in_memory::InMemoryTransport;
+        use futures::StreamExt;
+
+        let (client_end, mut server_end) = InMemoryTransport::pair();
+        let mut client = Client::new(client_end).with_background_reading(false);
+        server_end.start().await?;
+        let server = tokio::spawn(async move {
+            let request: JSONRPCRequest = server_end.receive().await.unwrap();
+            let response = create_initialize_response(request.id);
+            server_end.send(&response).await.unwrap();
+            server_end
+        });
+        client.initialize().await?;
+        let mut server_end = server.await.unwrap();
+        let mut events = Box::pin(client.events());
+        let reader = client.spawn_reader();
+
+        // Notifications and server requests are handled while the client is idle
+        let notification =
+            JSONRPCNotification::new("notifications/tools/list_changed".to_string(), None);
+        server_end.send(&notification).await?;
+        let event = tokio::time::timeout(Duration::from_secs(1), events.next()).await;
+        assert_eq!(
+            event.unwrap(),
+            Some(ClientEvent::NotificationReceived(notification))
+        );
+        let ping = JSONRPCRequest::new(RequestId::Number(99), "ping".to_string(), None);
+        server_end.send(&ping).await?;
+        let response: JSONRPCResponse = server_end.receive().await?;
+        assert_eq!(response.id, RequestId::Number(99));
+
+        // A request reads its own response, the reader stepping aside
+        let mut other = client.clone();
+        let pending = tokio::spawn(async move { other.ping().await });
+        let request: JSONRPCRequest = server_end.receive().await?;
+        let response = JSONRPCResponse::new(request.id, serde_json::json!({}));
+        server_end.send(&response).await?;
+        pending.await.unwrap()?;
+
+        // The loss of the connection is noticed without a request
+        server_end.close().await?;
+        tokio::time::timeout(Duration::from_secs(1), reader)
+            .await
+            .unwrap()
+            .unwrap();
+        let event = tokio::time::timeout(Duration::from_secs(1), events.next()).await;
+        assert!(matches!(
+            event.unwrap(),
+            Some(ClientEvent::Disconnected { .. })
+        ));
+        Ok(())
+    }
+
+    // Test reading in the background by default once initialized
+    #[tokio::test]
+    async fn test_background_reading() -> Result<(), MCPError> {
+        use crate::transport::in_memory::InMemoryTransport;
+
+        let (client_end, mut server_end) = InMemoryTransport::pair();
+        let mut client = Client::new(client_end);
+        server_end.start().await?;
+        let server = tokio::spawn(async move {
+            let request: JSONRPCRequest = server_end.receive().await.unwrap();
+            let response = create_initialize_response(request.id);
+            server_end.send(&response).await.unwrap();
+            server_end
+        });
+        client.initialize().await?;
+        let mut server_end = server.await.unwrap();
+
+        // An idle client answers the server's requests
+        let ping = JSONRPCRequest::new(RequestId::Number(99), "ping".to_string(), None);
+        server_end.send(&ping).await?;
+        let response: JSONRPCResponse =
+            tokio::time::timeout(Duration::from_secs(1), server_end.receive())
+                .await
+                .unwrap()?;
+        assert_eq!(response.id, RequestId::Number(99));
+
+        // Requests still read their own responses
+        let mut other = client.clone();
+        let pending = tokio::spawn(async move { other.ping().await });
+        let request: JSONRPCRequest = server_end.receive().await?;
+        let response = JSONRPCResponse::new(request.id, serde_json::json!({}));
+        server_end.send(&response).await?;
+        pending.await.unwrap()?;
+
+        // Dropping the client stops the reader, which lets go of the connection
+        drop(client);
+        let received = tokio::time::timeout(
+            Duration::from_secs(1),
+            server_end.receive::<JSONRPCRequest>(),
+        )
+        .await;
+        assert!(matches!(received, Ok(Err(MCPError::ConnectionClosed))));
+        Ok(())
+    }
+
+    // Test the lifecycle events published over a connection that is lost
+    #[tokio::test]
+    async fn test_client_events() {
+        use futures::{FutureExt, StreamExt};
+
+        let mock = MockTransport::new();
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
+        mock.queue_message(JSONRPCMessage::Notification(JSONRPCNotification::new(
+            "notifications/tools/list_changed".to_string(),
+            None,
+        )))
+        .await;
+        mock.queue_message(JSONRPCMessage::Error(JSONRPCError::new_with_details(
+            RequestId::Number(2),
+            error_codes::METHOD_NOT_FOUND,
+            "Method not found".to_string(),
+            None,
+        )))
+        .await;
+
+        let mut client = Client::new(mock);
+        let mut events = Box::pin(client.events());
+        client.initialize().await.unwrap();
+        assert!(client
+            .request::<Value>("vendor/unknown", None)
+            .await
+            .is_err());
+        // The queue is empty, so the transport fails as a lost connection would
+        assert!(client.ping().await.is_err());
+        assert!(client.ping().await.is_err());
+        client.close().await;
+
+        let mut received = Vec::new();
+        while let Some(Some(event)) = events.next().now_or_never() {
+            received.push(event);
+        }
+        assert_eq!(received[0], ClientEvent::Connected);
+        assert!(matches!(&received[1], ClientEvent::Initialized { .. }));
+        assert!(matches!(
+            &received[2],
+            ClientEvent::NotificationReceived(n) if n.method == "notifications/tools/list_changed"
+        ));
+        assert!(matches!(
+            &received[3],
+            ClientEvent::RequestFailed { method, error } if method == "vendor/unknown" && error == "Method not found"
+        ));
+        assert!(matches!(&received[4], ClientEvent::Disconnected { .. }));
+        // Both pings fail, but the loss is reported once, and closing adds nothing
+        let failed_pings = received[5..]
+            .iter()
+            .filter(|event| matches!(event, ClientEvent::RequestFailed { method, .. } if method == "ping"))
+            .count();
+        assert_eq!(failed_pings, 2);
+        assert_eq!(received.len(), 7);
+    }
+
+    // Test polling the tool list until a tool appears, and timing out
+    #[tokio::test(start_paused = true)]
+    async fn test_wait_ready() {
+        let mock = MockTransport::new();
+        let tools = |id, tools: Value| {
+            JSONRPCMessage::Response(JSONRPCResponse::new(
+                RequestId::Number(id),
+                serde_json::json!({ "tools": tools }),
+            ))
+        };
+        mock.queue_message(tools(1, serde_json::json!([]))).await;
+        mock.queue_message(tools(
+            2,
+            serde_json::json!([{ "name": "search", "inputSchema": { "type": "object" } }]),
+        ))
+        .await;
+
+        let mut client = Client::new(mock.clone());
+        let has_search = |tools: &[Tool]| tools.iter().any(|t| t.name == "search");
+        let ready = client
+            .wait_ready(Duration::from_secs(5), has_search)
+            .await
+            .unwrap();
+        assert_eq!(ready.len(), 1);
+
+        // The tool never appears: the queue is empty, so every poll fails
+        let result = client
+            .wait_ready(Duration::from_secs(1), |tools| tools.len() > 1)
+            .await;
+        assert!(matches!(result, Err(MCPError::Timeout(_))));
+    }
+
+    // Test that initialize carries the spec's field names and a capabilities object, even an empty one
+    #[tokio::test]
+    async fn test_initialize_sends_empty_capabilities() {
+        let mock = MockTransport::new();
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(1),
+            serde_json::json!({ "capabilities": {} }),
+        )))
+        .await;
+
+        let mut client = Client::new(mock.clone());
+        client.initialize().await.unwrap();
+
+        let sent: Value = serde_json::from_str(&mock.get_last_sent().await.unwrap()).unwrap();
+        assert_eq!(sent["method"], "initialize");
+        assert_eq!(sent["params"]["capabilities"], serde_json::json!({}));
+        assert_eq!(sent["params"]["protocolVersion"], LATEST_PROTOCOL_VERSION);
+        assert_eq!(sent["params"]["clientInfo"]["name"], "mcpr");
+    }
+
+    // Test the connection report after initialization
+    #[tokio::test]
+    async fn test_connection_report() {
+        let mock = MockTransport::new();
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(1),
+            serde_json::json!({
+                "protocolVersion": "2024-11-05",
+                "serverInfo": { "name": "TestServer", "version": "1.2.3" },
+                "capabilities": { "tools": {} }
+            }),
+        )))
+        .await;
+
+        let mut client = Client::new(mock).with_accepted_content_types(["text"]);
+        client.initialize().await.unwrap();
+
+        let report = client.connection_report();
+        assert_eq!(report.protocol_version.as_deref(), Some("2024-11-05"));
+        assert_eq!(report.server_info.as_ref().unwrap().version, "1.2.3");
+        assert!(report.server_capabilities.unwrap().tools.is_some());
+        assert_eq!(
+            report.client_capabilities["experimental"]["acceptedContentTypes"],
+            serde_json::json!(["text"])
+        );
+        assert!(report.transport.ends_with("MockTransport"));
+
+        let text = client.connection_report().to_string();
+        assert!(text.contains("Server:              TestServer 1.2.3"));
+        assert!(text.contains("State:               Connected"));
+    }
+
+    // Test describing a server that offers tools (paginated), prompts and
+    // resources without templates, with the lists fetched concurrently
+    #[tokio::test]
+    async fn test_describe_server() -> Result<(), MCPError> {
+        use crate::transport::in_memory::InMemoryTransport;
+
+        let (client_end, mut server_end) = InMemoryTransport::pair();
+        server_end.start().await?;
+        let server = tokio::spawn(async move {
+            let request: JSONRPCRequest = server_end.receive().await?;
+            let mut response = create_initialize_response(request.id);
+            if let JSONRPCMessage::Response(response) = &mut response {
+                response.result["capabilities"] =
+                    serde_json::json!({ "tools": {}, "prompts": {}, "resources": {} });
+            }
+            server_end.send(&response).await?;
+
+            // Every list is requested before any is answered
+            let mut requests = Vec::new();
+            for _ in 0..4 {
+                requests.push(server_end.receive::<JSONRPCRequest>().await?);
+            }
+            requests.sort_by(|a, b| a.method.cmp(&b.method));
+            let methods: Vec<_> = requests.iter().map(|r| r.method.as_str()).collect();
+            assert_eq!(
+                methods,
+                [
+                    "prompts/list",
+                    "resources/list",
+                    "resources/templates/list",
+                    "tools/list"
+                ]
+            );
+            let results = [
+                serde_json::json!({ "prompts": [{ "name": "greeting" }] }),
+                serde_json::json!({ "resources": [] }),
+                Value::Null,
+                serde_json::json!({
+                    "tools": [{ "name": "a", "inputSchema": { "type": "object" } }],
+                    "nextCursor": "page-2"
+                }),
+            ];
+            for (request, result) in requests.into_iter().zip(results) {
+                if result.is_null() {
+                    let error = JSONRPCError::new_with_details(
+                        request.id,
+                        error_codes::METHOD_NOT_FOUND,
+                        "Method not found".to_string(),
+                        None,
+                    );
+                    server_end.send(&JSONRPCMessage::Error(error)).await?;
+                } else {
+                    server_end
+                        .send(&JSONRPCResponse::new(request.id, result))
+                        .await?;
+                }
+            }
+
+            // The second page is requested with the cursor
+            let request: JSONRPCRequest = server_end.receive().await?;
+            assert_eq!(request.method, "tools/list");
+            assert_eq!(request.params.unwrap()["cursor"], "page-2");
+            let result = serde_json::json!({
+                "tools": [{ "name": "b", "inputSchema": { "type": "object" } }]
+            });
+            server_end
+                .send(&JSONRPCResponse::new(request.id, result))
+                .await?;
+            Ok::<_, MCPError>(())
+        });
+
+        let mut client = Client::new(client_end);
+        client.initialize().await?;
+        let description = client.describe_server().await?;
+        server.await.unwrap()?;
+
+        let tool_names: Vec<_> = description.tools.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(tool_names, vec!["a", "b"]);
+        assert_eq!(description.prompts.len(), 1);
+        assert!(description.resources.is_empty());
+        assert!(description.resource_templates.is_empty());
+        Ok(())
+    }
+
+    // Test that listing fails instead of looping when a server repeats a cursor
+    #[tokio::test]
+    async fn test_repeated_cursor() {
+        let mock = MockTransport::new();
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
+        for id in 2..4 {
+            mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+                RequestId::Number(id),
+                serde_json::json!({ "tools": [], "nextCursor": "again" }),
+            )))
+            .await;
+        }
+
+        let mut client = Client::new(mock);
+        client.initialize().await.unwrap();
+        assert!(matches!(
+            client.list_all_tools().await,
+            Err(MCPError::Protocol(message)) if message.contains("again")
+        ));
+    }
+
+    // Test retrying rate-limited tool calls from error data and tool results
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_on_rate_limit() {
+        let mock = MockTransport::new();
+        mock.queue_message(JSONRPCMessage::Error(JSONRPCError::new_with_details(
+            RequestId::Number(1),
+            RATE_LIMITED,
+            "Slow down".to_string(),
+            Some(serde_json::json!({ "retryAfter": 2 })),
+        )))
+        .await;
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(2),
+            serde_json::json!({
+                "content": [],
+                "isError": true,
+                "_meta": { "retryAfter": 0.5 }
+            }),
+        )))
+        .await;
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(3),
+            serde_json::json!({ "content": [], "isError": false }),
+        )))
+        .await;
+
+        let mut client = Client::new(mock.clone()).with_retry_on_rate_limit(2);
+        let started = Instant::now();
+        let result: Value = client
+            .call_tool("search", &serde_json::json!({}))
+            .await
+            .unwrap();
+        assert_eq!(result["isError"], false);
+        assert_eq!(started.elapsed(), Duration::from_millis(2500));
+
+        // Without retries enabled the rate-limit error is returned as is
+        mock.queue_message(JSONRPCMessage::Error(JSONRPCError::new_with_details(
+            RequestId::Number(1),
+            RATE_LIMITED,
+            "Too many requests".to_string(),
+            None,
+        )))
+        .await;
+        let mut client = Client::new(mock);
+        let result: Result<Value, _> = client.call_tool("search", &serde_json::json!({})).await;
+        assert!(matches!(
+            result,
+            Err(MCPError::Rpc {
+                code: RATE_LIMITED,
+                ..
+            })
+        ));
+
+        // Whatever the server asks for, the wait is capped
+        for retry_after in [1e9, 1e300] {
+            let limited = JSONRPCMessage::Error(JSONRPCError::new_with_details(
+                RequestId::Number(1),
+                RATE_LIMITED,
+                "Too many requests".to_string(),
+                Some(serde_json::json!({ "retryAfter": retry_after })),
+            ));
+            assert_eq!(rate_limit_delay(&limited), Some(MAX_RATE_LIMIT_DELAY));
+        }
+
+        // A retryAfter hint on any other error is not a rate limit
+        let invalid = JSONRPCError::new_with_details(
+            RequestId::Number(1),
+            error_codes::INVALID_PARAMS,
+            "Bad query".to_string(),
+            Some(serde_json::json!({ "retryAfter": 2 })),
+        );
+        assert_eq!(rate_limit_delay(&JSONRPCMessage::Error(invalid)), None);
+        let invalid = MCPError::Rpc {
+            code: error_codes::INVALID_PARAMS,
+            message: "Bad query".to_string(),
+            data: Some(serde_json::json!({ "retryAfter": 2 })),
+        };
+        assert!(!invalid.retryable());
+    }
+
+    // Garbage and stray responses are skipped and reported, not failing the request
+    #[tokio::test]
+    async fn test_skip_protocol_errors() {
+        use futures::StreamExt;
+
+        let response = |id: i64| {
+            JSONRPCMessage::Response(JSONRPCResponse::new(
+                RequestId::Number(id),
+                serde_json::json!({}),
+            ))
+        };
+        let mock = MockTransport::new();
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(1),
+            serde_json::json!({
+                "protocolVersion": "2024-11-05",
+                "capabilities": {},
+                "serverInfo": { "name": "test", "version": "1.0" },
+            }),
+        )))
+        .await;
+        let mut client = Client::new(mock.clone());
+        client.initialize().await.unwrap();
+        let errors = client.protocol_errors();
+
+        mock.receive_queue
+            .lock()
+            .await
+            .extend(["not json".to_string(), r#"{"unrelated":true}"#.to_string()]);
+        mock.queue_message(response(42)).await;
+        mock.queue_message(JSONRPCMessage::Notification(JSONRPCNotification::new(
+            "notifications/message".to_string(),
+            Some(serde_json::json!({ "level": "info", "data": "working" })),
+        )))
+        .await;
+        mock.queue_message(response(2)).await;
+        mock.queue_message(response(2)).await;
+        mock.queue_message(response(3)).await;
+        client.ping().await.unwrap();
+        client.ping().await.unwrap();
+
+        let errors: Vec<ClientDiagnostic> = errors.take(4).collect().await;
+        assert!(matches!(errors[0], ClientDiagnostic::InvalidMessage { .. }));
+        assert!(matches!(errors[1], ClientDiagnostic::InvalidMessage { .. }));
+        assert_eq!(
+            errors[2..],
+            [
+                ClientDiagnostic::UnknownResponse {
+                    id: RequestId::Number(42)
+                },
+                ClientDiagnostic::UnknownResponse {
+                    id: RequestId::Number(2)
+                },
+            ]
+        );
+    }
+
+    // Test that a strict client rejects what a lenient one works around
+    #[tokio::test]
+    async fn test_strict_mode() {
+        let initialize = |result: Value| {
+            JSONRPCMessage::Response(JSONRPCResponse::new(RequestId::Number(1), result))
+        };
+        let conforming = serde_json::json!({
+            "protocolVersion": "2024-11-05",
+            "capabilities": { "tools": {} },
+            "serverInfo": { "name": "test", "version": "1.0" },
+        });
+
+        let mut missing = conforming.clone();
+        missing.as_object_mut().unwrap().remove("capabilities");
+        let mut unknown = conforming.clone();
+        unknown["vendor"] = serde_json::json!("acme");
+        for (result, problem) in [
+            (missing, "missing capabilities"),
+            (unknown, "unknown field 'vendor'"),
+        ] {
+            let mock = MockTransport::new();
+            mock.queue_message(initialize(result.clone())).await;
+            let mut lenient = Client::new(mock.clone());
+            assert!(lenient.initialize().await.is_ok());
+
+            mock.queue_message(initialize(result)).await;
+            let mut strict = Client::new(mock).with_strict(true);
+            let error = strict.initialize().await.unwrap_err().to_string();
+            assert!(error.contains(problem), "{}", error);
+        }
+
+        // Fields in an empty result and responses to unknown ids are errors
+        let mock = MockTransport::new();
+        mock.queue_message(initialize(conforming.clone())).await;
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(2),
+            serde_json::json!({ "pong": true }),
+        )))
+        .await;
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(42),
+            serde_json::json!({}),
+        )))
+        .await;
+        let mut client = Client::new(mock).with_strict(true);
+        client.initialize().await.unwrap();
+        let error = client.ping().await.unwrap_err().to_string();
+        assert!(error.contains("pong"), "{}", error);
+        let error = client.ping().await.unwrap_err().to_string();
+        assert!(error.contains("unknown id"), "{}", error);
+
+        // Content items with fields of another content type are errors
+        let mock = MockTransport::new();
+        mock.queue_message(initialize(conforming)).await;
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(2),
+            serde_json::json!({ "content": [{ "type": "text", "text": "hi", "data": "aGk=" }] }),
+        )))
+        .await;
+        let mut client = Client::new(mock).with_strict(true);
+        client.initialize().await.unwrap();
+        let error = client
+            .call_tool::<_, CallToolResult>("echo", &Value::Null)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("[\"data\"]"), "{}", error);
+    }
+
+    // Test that a request timeout follows the injected clock
+    #[tokio::test]
+    async fn test_timeout_with_mock_clock() -> Result<(), MCPError> {
+        let mock = MockTransport::new();
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
+        let clock = MockClock::new();
+        let mut client = Client::new(mock.clone())
+            .with_clock(clock.clone())
+            .with_timeout(Duration::from_secs(30));
+        client.initialize().await?;
+
+        // The server never answers the ping
+        mock.set_simulate_timeout(true).await;
+        let started = std::time::Instant::now();
+        let ping = tokio::spawn(async move { client.ping().await });
+        while clock.sleepers() == 0 {
+            tokio::task::yield_now().await;
+        }
+        clock.advance(Duration::from_secs(30));
+
+        assert!(matches!(ping.await.unwrap(), Err(MCPError::Timeout(_))));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        Ok(())
+    }
+
+    // Test the typed and raw results of initialize
+    #[tokio::test]
+    async fn test_initialize_result() -> Result<(), MCPError> {
+        let result = serde_json::json!({
+            "protocolVersion": "2024-11-05",
+            "capabilities": { "tools": { "listChanged": true } },
+            "serverInfo": { "name": "test", "version": "1.0" },
+            "instructions": "Call search first",
+            "vendorExtension": 1,
+        });
+        let mock = MockTransport::new();
+        for id in 1..=2 {
+            mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+                RequestId::Number(id),
+                result.clone(),
+            )))
+            .await;
+        }
+        let mut client = Client::new(mock.clone());
+
+        let typed = client.initialize().await?;
+        assert_eq!(typed.server_info.name, "test");
+        assert_eq!(typed.capabilities.tools.unwrap().list_changed, Some(true));
+        assert_eq!(typed.instructions.as_deref(), Some("Call search first"));
+        assert_eq!(client.initialize_raw().await?, result);
+
+        // A malformed field is left empty instead of failing the handshake
+        let lenient = lenient_initialize_result(&serde_json::json!({ "serverInfo": "test" }));
+        assert_eq!(lenient.server_info, Implementation::default());
+        Ok(())
+    }
+
+    // Test renegotiating a session, in place and by reconnecting
+    #[tokio::test(start_paused = true)]
+    async fn test_client_info() -> Result<(), MCPError> {
+        let mock = MockTransport::new();
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
+        let mut client = Client::new(mock.clone())
+            .with_name("host")
+            .with_version("1.2.3")
+            .with_protocol_version("2025-03-26")
+            .with_roots([])
+            .with_capability("roots", serde_json::json!({ "listChanged": false }))
+            .with_capability("experimental", serde_json::json!({ "x": {} }));
+        client.initialize().await?;
+
+        let sent: Value = serde_json::from_str(&mock.get_last_sent().await.unwrap())?;
+        assert_eq!(sent["params"]["protocolVersion"], "2025-03-26");
+        assert_eq!(
+            sent["params"]["clientInfo"],
+            serde_json::json!({ "name": "host", "version": "1.2.3" })
+        );
+        assert_eq!(
+            sent["params"]["capabilities"],
+            serde_json::json!({ "roots": { "listChanged": false }, "experimental": { "x": {} } })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_protocol_version_negotiation() -> Result<(), MCPError> {
+        // A server supporting the requested version agrees to it
+        let (client_end, server_end) = crate::transport::in_memory::InMemoryTransport::pair();
+        let mut server = crate::server::Server::new(crate::server::ServerConfig::new());
+        tokio::spawn(async move { server.serve(server_end).await });
+        let mut client = Client::new(client_end).with_protocol_version("2025-03-26");
+        client.initialize().await?;
+        assert_eq!(client.protocol_version(), Some("2025-03-26"));
+
+        // A version the client does not know fails initialization
+        let mock = MockTransport::new();
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse {
+            jsonrpc: "2.0".to_string(),
+            id: RequestId::Number(1),
+            result: serde_json::json!({
+                "protocolVersion": "2099-01-01",
+                "capabilities": {},
+                "serverInfo": { "name": "future", "version": "1.0" }
+            }),
+        }))
+        .await;
+        let mut client = Client::new(mock);
+        match client.initialize().await {
+            Err(MCPError::UnsupportedProtocolVersion { requested, offered }) => {
+                assert_eq!(requested, LATEST_PROTOCOL_VERSION);
+                assert_eq!(offered, "2099-01-01");
+            }
+            other => panic!("Expected UnsupportedProtocolVersion, got {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reinitialize() -> Result<(), MCPError> {
+        let mock = MockTransport::new();
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
+        let mut client = Client::new(mock.clone());
+        client.initialize().await?;
+        mock.get_last_sent().await;
+
+        // A handler registered later is advertised on renegotiation
+        client.register_request_handler("sampling/createMessage", |_| async {
+            Ok(serde_json::json!({}))
+        });
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(2),
+            serde_json::json!({
+                "protocolVersion": "2024-11-05",
+                "capabilities": { "prompts": {} },
+                "serverInfo": { "name": "test", "version": "2.0" },
+            }),
+        )))
+        .await;
+        client.reinitialize().await?;
+        let sent: Value = serde_json::from_str(&mock.get_last_sent().await.unwrap())?;
+        assert_eq!(sent["method"], "initialize");
+        assert_eq!(
+            sent["params"]["capabilities"]["sampling"],
+            serde_json::json!({})
+        );
+        assert!(client.server_capabilities().unwrap().prompts.is_some());
+        assert_eq!(client.state(), ConnectionState::Connected);
+
+        // A server that refuses a second initialize gets a fresh connection
+        mock.queue_message(JSONRPCMessage::Error(JSONRPCError::new_with_details(
+            RequestId::Number(3),
+            error_codes::INVALID_REQUEST,
+            "Already initialized".to_string(),
+            None,
+        )))
+        .await;
+        mock.queue_message(create_initialize_response(RequestId::Number(4)))
+            .await;
+        client.reinitialize().await?;
+        assert_eq!(client.state(), ConnectionState::Connected);
+        for id in [3, 4] {
+            let sent: Value = serde_json::from_str(&mock.get_last_sent().await.unwrap())?;
+            assert_eq!(
+                (sent["id"].as_i64(), &sent["method"]),
+                (Some(id), &"initialize".into())
+            );
+        }
+        Ok(())
+    }
+
+    // Test that structured tool output is checked against the tool's outputSchema
+    #[tokio::test]
+    async fn test_validate_output() -> Result<(), MCPError> {
+        let result = |id: i64, structured: Value| {
+            JSONRPCMessage::Response(JSONRPCResponse::new(
+                RequestId::Number(id),
+                serde_json::json!({ "content": [], "structuredContent": structured }),
+            ))
+        };
+
+        let mock = MockTransport::new();
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(2),
+            serde_json::json!({ "tools": [{
+                "name": "weather",
+                "inputSchema": { "type": "object" },
+                "outputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "readings": { "type": "array", "items": { "type": "number" } },
+                    },
+                    "required": ["readings"],
+                },
+            }] }),
+        )))
+        .await;
+        mock.queue_message(result(3, serde_json::json!({ "readings": [21.5, 22] })))
+            .await;
+        mock.queue_message(result(4, serde_json::json!({ "readings": [21.5, "hot"] })))
+            .await;
+        let mut client = Client::new(mock).with_validate_output(true);
+        client.initialize().await?;
+        client.list_tools::<ListToolsResult>().await?;
+
+        let output: CallToolResult = client.call_tool("weather", &Value::Null).await?;
+        assert!(output.structured_content.is_some());
+
+        match client
+            .call_tool::<_, CallToolResult>("weather", &Value::Null)
+            .await
+        {
+            Err(MCPError::Protocol(message)) => {
+                assert!(
+                    message.contains("/readings/1: expected number"),
+                    "{}",
+                    message
+                )
+            }
+            other => panic!("Expected a protocol error, got {:?}", other),
+        }
+        Ok(())
+    }
+
+    // Test notification handlers running while a request is in flight
+    #[tokio::test]
+    async fn test_on_notification() -> Result<(), MCPError> {
+        let mock = MockTransport::new();
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
+        mock.queue_message(JSONRPCMessage::Notification(JSONRPCNotification::new(
+            "notifications/tools/list_changed".to_string(),
+            None,
+        )))
+        .await;
+        mock.queue_message(JSONRPCMessage::Notification(JSONRPCNotification::new(
+            "notifications/progress".to_string(),
+            Some(serde_json::json!({ "progressToken": "t", "progress": 1 })),
+        )))
+        .await;
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(2),
+            serde_json::json!({}),
+        )))
+        .await;
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut client = Client::new(mock);
+        for method in [
+            "notifications/tools/list_changed",
+            "notifications/progress",
+            "notifications/progress",
+        ] {
+            let seen = seen.clone();
+            client.on_notification(method, move |notification| {
+                seen.lock().unwrap().push(notification.method.clone());
+            });
         }
+        client.initialize().await?;
+        client.ping().await?;
 
-        async fn set_should_fail(&self, should_fail: bool) {
-            let mut fail = self.should_fail.lock().await;
-            *fail = should_fail;
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [
+                "notifications/tools/list_changed",
+                "notifications/progress",
+                "notifications/progress"
+            ]
+        );
+        Ok(())
+    }
+
+    // Test requests from clones awaited concurrently, answered out of order
+    #[tokio::test(start_paused = true)]
+    async fn test_concurrent_clones() -> Result<(), MCPError> {
+        let mock = MockTransport::new();
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
+        let mut client = Client::new(mock.clone());
+        client.initialize().await?;
+        mock.get_last_sent().await;
+        mock.get_last_sent().await;
+
+        // Every read now takes a while, so all requests are sent before the
+        // first response is read
+        mock.set_simulate_timeout(true).await;
+        let calls: Vec<_> = (0..3)
+            .map(|n| {
+                let mut client = client.clone();
+                tokio::spawn(async move {
+                    client
+                        .call_tool::<_, Value>("echo", &serde_json::json!({ "n": n }))
+                        .await
+                })
+            })
+            .collect();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let mut requests = Vec::new();
+        while let Some(sent) = mock.get_last_sent().await {
+            requests.push(serde_json::from_str::<Value>(&sent)?);
+        }
+        assert_eq!(requests.len(), 3);
+        for request in requests.iter().rev() {
+            mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+                serde_json::from_value(request["id"].clone())?,
+                request["params"]["arguments"].clone(),
+            )))
+            .await;
         }
 
-        async fn set_simulate_timeout(&self, timeout: bool) {
-            let mut t = self.simulate_timeout.lock().await;
-            *t = timeout;
+        for (n, call) in calls.into_iter().enumerate() {
+            assert_eq!(call.await.unwrap()?, serde_json::json!({ "n": n }));
         }
+        assert!(client.pending_requests().is_empty());
+        Ok(())
     }
 
-    #[async_trait]
-    impl Transport for MockTransport {
-        async fn start(&mut self) -> Result<(), MCPError> {
-            let should_fail = *self.should_fail.lock().await;
-            if should_fail {
-                return Err(MCPError::Transport(
-                    "Mock transport failed to start".to_string(),
-                ));
+    // Test the progress of a tool call, streamed alongside its result
+    #[tokio::test(start_paused = true)]
+    async fn test_call_tool_with_progress() -> Result<(), MCPError> {
+        let mock = MockTransport::new();
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
+        let mut client = Client::new(mock.clone());
+        client.initialize().await?;
+        mock.get_last_sent().await;
+        mock.get_last_sent().await;
+
+        // Reads take a while, so the server below answers in the meantime
+        mock.set_simulate_timeout(true).await;
+        let (progress, result) =
+            client.call_tool_with_progress::<_, Value>("index", &serde_json::json!({}));
+        let server = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let request: Value = serde_json::from_str(&mock.get_last_sent().await.unwrap())?;
+            let token = request["params"]["_meta"]["progressToken"].clone();
+            for (progress, token) in [(1, token.clone()), (5, "other".into()), (2, token)] {
+                mock.queue_message(JSONRPCMessage::Notification(JSONRPCNotification::new(
+                    "notifications/progress".to_string(),
+                    Some(serde_json::json!({
+                        "progressToken": token,
+                        "progress": progress,
+                        "total": 2
+                    })),
+                )))
+                .await;
+            }
+            mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+                serde_json::from_value(request["id"].clone())?,
+                serde_json::json!({ "done": true }),
+            )))
+            .await;
+            Ok::<_, MCPError>(futures::StreamExt::collect::<Vec<_>>(progress).await)
+        };
+
+        let (result, progress) = tokio::join!(result, server);
+        assert_eq!(result?, serde_json::json!({ "done": true }));
+        let progress: Vec<_> = progress?.iter().map(|p| (p.progress, p.total)).collect();
+        assert_eq!(progress, [(1.0, Some(2.0)), (2.0, Some(2.0))]);
+        assert!(client.progress_listeners.lock().unwrap().is_empty());
+        Ok(())
+    }
+
+    // Test listing prompts and getting one with arguments
+    #[tokio::test]
+    async fn test_paginated_tools() -> Result<(), MCPError> {
+        let mock = MockTransport::new();
+        let page = |id: i64, name: &str, next: Option<&str>| {
+            JSONRPCMessage::Response(JSONRPCResponse::new(
+                RequestId::Number(id),
+                serde_json::json!({
+                    "tools": [{ "name": name, "inputSchema": { "type": "object" } }],
+                    "nextCursor": next
+                }),
+            ))
+        };
+        for (id, name, next) in [
+            (1, "a", Some("p2")),
+            (2, "a", Some("p2")),
+            (3, "b", None),
+            (4, "a", Some("p2")),
+            (5, "b", None),
+        ] {
+            mock.queue_message(page(id, name, next)).await;
+        }
+
+        let mut client = Client::new(mock.clone());
+        let (tools, next) = client.list_tools_paged(None).await?;
+        assert_eq!(tools[0].name, "a");
+        assert_eq!(next.as_deref(), Some("p2"));
+
+        let names = |tools: Vec<Tool>| tools.into_iter().map(|t| t.name).collect::<Vec<_>>();
+        assert_eq!(names(client.list_all_tools().await?), ["a", "b"]);
+        let streamed: Vec<Tool> = client.list_tools_stream().try_collect().await?;
+        assert_eq!(names(streamed), ["a", "b"]);
+
+        // The cursor of the previous page is sent for the next one
+        let mut sent = Vec::new();
+        while let Some(message) = mock.get_last_sent().await {
+            sent.push(serde_json::from_str::<Value>(&message)?);
+        }
+        assert!(sent[1].get("params").is_none());
+        assert_eq!(sent[2]["params"]["cursor"], "p2");
+        assert_eq!(sent[4]["params"]["cursor"], "p2");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_prompts() -> Result<(), MCPError> {
+        let mock = MockTransport::new();
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(2),
+            serde_json::json!({ "prompts": [{
+                "name": "review",
+                "description": "Review code",
+                "arguments": [{ "name": "code", "required": true }]
+            }]}),
+        )))
+        .await;
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(3),
+            serde_json::json!({ "messages": [{
+                "role": "user",
+                "content": { "type": "text", "text": "Review fn main() {}" }
+            }]}),
+        )))
+        .await;
+
+        let mut client = Client::new(mock.clone());
+        client.initialize().await?;
+        let prompts = client.list_prompts().await?;
+        let arguments = prompts[0].arguments.as_ref().unwrap();
+        assert_eq!(arguments[0].name, "code");
+        assert_eq!(arguments[0].required, Some(true));
+
+        let arguments = HashMap::from([("code".to_string(), "fn main() {}".to_string())]);
+        let prompt = client.get_prompt("review", Some(arguments)).await?;
+        assert_eq!(prompt.to_transcript(), "user: Review fn main() {}");
+
+        let mut sent = Vec::new();
+        while let Some(message) = mock.get_last_sent().await {
+            sent.push(serde_json::from_str::<Value>(&message)?);
+        }
+        assert_eq!(sent[2]["params"]["arguments"]["code"], "fn main() {}");
+        Ok(())
+    }
+
+    // Test reading text and blob resources, and updates for one resource
+    #[tokio::test]
+    async fn test_resources() -> Result<(), MCPError> {
+        let mock = MockTransport::new();
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
+        for uri in ["file:///b", "file:///a"] {
+            mock.queue_message(JSONRPCMessage::Notification(JSONRPCNotification::new(
+                "notifications/resources/updated".to_string(),
+                Some(serde_json::json!({ "uri": uri })),
+            )))
+            .await;
+        }
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(2),
+            serde_json::json!({ "contents": [
+                { "uri": "file:///a", "mimeType": "text/plain", "text": "hello" },
+                { "uri": "file:///a", "blob": "aGVsbG8=" }
+            ]}),
+        )))
+        .await;
+
+        let mut client = Client::new(mock);
+        let updates = client.resource_updates("file:///a");
+        tokio::pin!(updates);
+        client.initialize().await?;
+        let read = client.read_resource("file:///a", None).await?;
+
+        assert_eq!(read.text(), Some("hello"));
+        assert_eq!(read.contents[0].mime_type(), Some("text/plain"));
+        assert_eq!(read.contents[1].text(), None);
+        assert_eq!(read.contents[1].bytes()?, b"hello");
+        let update = futures::StreamExt::next(&mut updates).await.unwrap();
+        assert_eq!(update.uri, "file:///a");
+        Ok(())
+    }
+
+    // Test waiting for a specific notification
+    #[tokio::test(start_paused = true)]
+    async fn test_next_notification_where() -> Result<(), MCPError> {
+        let updated = |uri: &str| {
+            JSONRPCMessage::Notification(JSONRPCNotification::new(
+                "notifications/resources/updated".to_string(),
+                Some(serde_json::json!({ "uri": uri })),
+            ))
+        };
+        let for_uri = |uri: &'static str| {
+            move |notification: &JSONRPCNotification| {
+                notification.params.as_ref().and_then(|p| p.get("uri")) == Some(&uri.into())
+            }
+        };
+
+        // A notification that arrived with an earlier response is not missed
+        let mock = MockTransport::new();
+        mock.queue_message(updated("file:///a")).await;
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
+        mock.queue_message(updated("file:///b")).await;
+        let mut client = Client::new(mock.clone());
+        client.initialize().await?;
+
+        let timeout = Duration::from_secs(1);
+        let notification = client
+            .next_notification_where(for_uri("file:///a"), timeout)
+            .await?;
+        assert_eq!(notification.method, "notifications/resources/updated");
+
+        // Later ones are read from the server, skipping those that do not match
+        let notification = client
+            .next_notification_where(for_uri("file:///b"), timeout)
+            .await?;
+        assert_eq!(notification.params.unwrap()["uri"], "file:///b");
+
+        mock.set_simulate_timeout(true).await;
+        let result = client
+            .next_notification_where(for_uri("file:///a"), timeout)
+            .await;
+        assert!(matches!(result, Err(MCPError::Timeout(_))));
+        Ok(())
+    }
+
+    // Test that close summarizes the session and is idempotent
+    #[tokio::test(start_paused = true)]
+    async fn test_close_summary() {
+        let mock = MockTransport::new();
+        let responses = [
+            create_initialize_response(RequestId::Number(1)),
+            JSONRPCMessage::Error(JSONRPCError::new_with_details(
+                RequestId::Number(2),
+                -32601,
+                "Method not found".to_string(),
+                None,
+            )),
+        ];
+        let mut received = 0;
+        for response in responses {
+            received += serde_json::to_string(&response).unwrap().len();
+            mock.queue_message(response).await;
+        }
+
+        let mut client = Client::new(mock.clone());
+        client.initialize().await.unwrap();
+        assert!(client.ping().await.is_err());
+        tokio::time::sleep(Duration::from_secs(5)).await;
+
+        // Bytes are those the transport counted as it wrote and read them
+        let sent: usize = mock.send_queue.lock().await.iter().map(String::len).sum();
+        let summary = client.close().await;
+        assert_eq!(summary.requests_sent, 2);
+        assert_eq!(summary.errors, 1);
+        assert_eq!(summary.bytes_sent, sent as u64);
+        assert_eq!(summary.bytes_received, received as u64);
+        assert_eq!(summary.uptime, Duration::from_secs(5));
+        assert!(*mock.is_closed.lock().await);
+        assert_eq!(client.state(), ConnectionState::Closed);
+
+        // A second close returns the same summary
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert_eq!(client.close().await, summary);
+    }
+
+    // Test applying resource updates to the cached resource list
+    #[tokio::test]
+    async fn test_resource_updates() {
+        let mock = MockTransport::new();
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(1),
+            serde_json::json!({
+                "resources": [
+                    { "uri": "file:///a.txt", "name": "a.txt", "size": 10 },
+                    { "uri": "file:///b.txt", "name": "b.txt" }
+                ]
+            }),
+        )))
+        .await;
+        mock.queue_message(JSONRPCMessage::Notification(JSONRPCNotification::new(
+            "notifications/resources/updated".to_string(),
+            Some(serde_json::json!({ "uri": "file:///a.txt", "size": 42 })),
+        )))
+        .await;
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(2),
+            serde_json::json!({}),
+        )))
+        .await;
+
+        let mut client = Client::new(mock.clone());
+        let mut updates = client.subscribe_resource_updates();
+        assert_eq!(client.list_resources().await.unwrap().len(), 2);
+        client.ping().await.unwrap();
+
+        let update = updates.try_recv().unwrap();
+        assert_eq!(update.uri, "file:///a.txt");
+        assert_eq!(update.size, Some(42));
+
+        let cached = client.cached_resources().unwrap();
+        assert_eq!(cached[0].size, Some(42));
+        assert_eq!(cached[0].name, "a.txt");
+        assert_eq!(cached[1].size, None);
+    }
+
+    // Test that subscriptions are tracked and dropped on close
+    #[tokio::test]
+    async fn test_resource_subscriptions() {
+        let mock = MockTransport::new();
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
+        for id in 2..=5 {
+            mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+                RequestId::Number(id),
+                serde_json::json!({}),
+            )))
+            .await;
+        }
+
+        let mut client = Client::new(mock.clone());
+        client.initialize().await.unwrap();
+        client.subscribe_resource("file:///a.txt").await.unwrap();
+        client.subscribe_resource("file:///b.txt").await.unwrap();
+        assert!(client.unsubscribe_resource("file:///c.txt").await.is_err());
+        client.unsubscribe_resource("file:///a.txt").await.unwrap();
+        assert!(client.unsubscribe_resource("file:///a.txt").await.is_err());
+        assert_eq!(client.subscriptions(), vec!["file:///b.txt"]);
+
+        // Closing unsubscribes from what is left
+        client.close().await;
+        assert!(client.subscriptions().is_empty());
+        let mut sent = Vec::new();
+        while let Some(message) = mock.get_last_sent().await {
+            let message: Value = serde_json::from_str(&message).unwrap();
+            sent.push((message["method"].clone(), message["params"]["uri"].clone()));
+        }
+        assert_eq!(
+            sent[sent.len() - 2..],
+            [
+                ("resources/unsubscribe".into(), "file:///a.txt".into()),
+                ("resources/unsubscribe".into(), "file:///b.txt".into()),
+            ]
+        );
+    }
+
+    // Test cancelling a request with a reason
+    #[tokio::test]
+    async fn test_cancel_with_reason() {
+        let mock = MockTransport::new();
+        let mut client = Client::new(mock.clone());
+
+        client
+            .cancel(RequestId::Number(3), Some("timeout"))
+            .await
+            .unwrap();
+
+        let sent: Value = serde_json::from_str(&mock.get_last_sent().await.unwrap()).unwrap();
+        assert_eq!(sent["method"], "notifications/cancelled");
+        assert_eq!(sent["params"]["requestId"], 3);
+        assert_eq!(sent["params"]["reason"], "timeout");
+        assert!(client
+            .cancelled
+            .lock()
+            .unwrap()
+            .contains_key(&RequestId::Number(3)));
+    }
+
+    // Test that dropped and timed out requests are cancelled on the wire
+    #[tokio::test(start_paused = true)]
+    async fn test_cancel_dropped_request() -> Result<(), MCPError> {
+        let mock = MockTransport::new();
+        let mut client = Client::new(mock.clone()).with_timeout(Duration::from_secs(1));
+
+        // Dropped before the response arrived
+        mock.set_simulate_timeout(true).await;
+        let dropped = tokio::time::timeout(Duration::from_millis(10), client.ping()).await;
+        assert!(dropped.is_err());
+        mock.get_last_sent().await;
+
+        // Told with the next message, which then times out itself
+        let result = client.ping().await;
+        assert!(matches!(result, Err(MCPError::Timeout(_))));
+
+        let mut sent = Vec::new();
+        while let Some(message) = mock.get_last_sent().await {
+            sent.push(serde_json::from_str::<Value>(&message)?);
+        }
+        assert_eq!(sent.len(), 3);
+        assert_eq!(sent[0]["method"], "notifications/cancelled");
+        assert_eq!(sent[0]["params"]["requestId"], 1);
+        assert_eq!(sent[1]["method"], "ping");
+        assert_eq!(sent[2]["params"]["requestId"], 2);
+        assert_eq!(sent[2]["params"]["reason"], "timeout");
+        Ok(())
+    }
+
+    // Test bounding one call with a timeout of its own
+    #[tokio::test(start_paused = true)]
+    async fn test_call_tool_with_options() -> Result<(), MCPError> {
+        let mock = MockTransport::new();
+        let mut client = Client::new(mock.clone()).with_timeout(Duration::from_secs(60));
+
+        // The server takes 2 seconds to answer
+        mock.set_simulate_timeout(true).await;
+        let started = Instant::now();
+        let options = CallOptions::new().with_timeout(Duration::from_millis(500));
+        let result = client
+            .call_tool_with_options::<_, Value>("slow", &Value::Null, options)
+            .await;
+        assert!(matches!(result, Err(MCPError::Timeout(_))));
+        assert_eq!(started.elapsed(), Duration::from_millis(500));
+
+        mock.get_last_sent().await;
+        let sent: Value = serde_json::from_str(&mock.get_last_sent().await.unwrap())?;
+        assert_eq!(sent["method"], "notifications/cancelled");
+        assert_eq!(sent["params"]["reason"], "timeout");
+        assert!(client.pending_requests().is_empty());
+        Ok(())
+    }
+
+    // Test retrying requests that fail on the way, if they are idempotent
+    #[tokio::test]
+    async fn test_retry_policy() -> Result<(), MCPError> {
+        // The mock fails every receive, as if the connection dropped
+        let mock = MockTransport::new();
+        let policy = RetryPolicy::new()
+            .with_max_attempts(3)
+            .with_initial_delay(Duration::ZERO)
+            .with_jitter(0.0);
+        let mut client = Client::new(mock.clone()).with_retry_policy(policy);
+
+        let error = client
+            .request::<Value>("tools/list", None)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            MCPError::RetriesExhausted { attempts: 3, .. }
+        ));
+        assert_eq!(error.kind(), ErrorKind::Transport);
+
+        // Tool calls are not retried unless the tool is idempotent
+        let error = client
+            .call_tool::<_, Value>("send_email", &serde_json::json!({}))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, MCPError::Transport(_)));
+
+        let mut sent = Vec::new();
+        while let Some(message) = mock.get_last_sent().await {
+            let message: Value = serde_json::from_str(&message)?;
+            sent.push(message["method"].as_str().unwrap_or_default().to_string());
+        }
+        sent.retain(|method| method != "notifications/cancelled");
+        assert_eq!(
+            sent,
+            ["tools/list", "tools/list", "tools/list", "tools/call"]
+        );
+        Ok(())
+    }
+
+    // Test sending notifications for vendor-specific methods
+    #[tokio::test]
+    async fn test_notify_raw() -> Result<(), MCPError> {
+        let mock = MockTransport::new();
+        let mut client = Client::new(mock.clone());
+        client
+            .notify_raw("x/heartbeat", &serde_json::json!({ "load": 0.5 }))
+            .await?;
+        let sent: Value = serde_json::from_str(&mock.get_last_sent().await.unwrap())?;
+        assert_eq!(
+            sent,
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "method": "x/heartbeat",
+                "params": { "load": 0.5 }
+            })
+        );
+
+        client.notify_raw("x/reset", &()).await?;
+        let sent: Value = serde_json::from_str(&mock.get_last_sent().await.unwrap())?;
+        assert!(sent.get("params").is_none());
+        Ok(())
+    }
+
+    // Test answering a server request that reports progress while in flight
+    #[tokio::test]
+    async fn test_server_request_progress() {
+        let mock = MockTransport::new();
+        mock.queue_message(JSONRPCMessage::Request(JSONRPCRequest::new(
+            RequestId::String("srv-1".to_string()),
+            "sampling/createMessage".to_string(),
+            Some(serde_json::json!({
+                "messages": [],
+                "_meta": { "progressToken": "tok" }
+            })),
+        )))
+        .await;
+        mock.queue_message(JSONRPCMessage::Request(JSONRPCRequest::new(
+            RequestId::String("srv-2".to_string()),
+            "roots/list".to_string(),
+            None,
+        )))
+        .await;
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(1),
+            serde_json::json!({}),
+        )))
+        .await;
+
+        let mut client = Client::new(mock.clone());
+        client.register_request_handler("sampling/createMessage", |context| async move {
+            assert_eq!(
+                context.progress_token(),
+                Some(&ProgressToken::String("tok".to_string()))
+            );
+            context.report_progress(1.0, Some(2.0))?;
+            context.report_progress(2.0, Some(2.0))?;
+            Ok(serde_json::json!({ "role": "assistant" }))
+        });
+
+        client.ping().await.unwrap();
+
+        let mut sent = Vec::new();
+        while let Some(msg) = mock.get_last_sent().await {
+            sent.push(serde_json::from_str::<Value>(&msg).unwrap());
+        }
+        assert_eq!(sent.len(), 5);
+        assert_eq!(sent[0]["method"], "ping");
+        assert_eq!(sent[1]["method"], "notifications/progress");
+        assert_eq!(sent[1]["params"]["progressToken"], "tok");
+        assert_eq!(sent[1]["params"]["progress"], 1.0);
+        assert_eq!(sent[2]["params"]["progress"], 2.0);
+        assert_eq!(sent[3]["id"], "srv-1");
+        assert_eq!(sent[3]["result"]["role"], "assistant");
+        assert_eq!(sent[4]["id"], "srv-2");
+        assert_eq!(sent[4]["error"]["code"], error_codes::METHOD_NOT_FOUND);
+    }
+
+    // Test that a late response to a timed-out request is not mistaken for the next response
+    #[tokio::test(start_paused = true)]
+    async fn test_late_response_policy() {
+        let mock = MockTransport::new();
+        mock.set_simulate_timeout(true).await;
+
+        let mut client = Client::new(mock.clone())
+            .with_timeout(Duration::from_secs(1))
+            .with_late_response_policy(LateResponsePolicy::Error);
+        let mut diagnostics = client.subscribe_diagnostics();
+
+        // The first ping times out before the mock answers
+        assert!(matches!(client.ping().await, Err(MCPError::Timeout(_))));
+
+        // The late response arrives ahead of the answer to the second ping
+        mock.set_simulate_timeout(false).await;
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(1),
+            serde_json::json!({ "late": true }),
+        )))
+        .await;
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(2),
+            serde_json::json!({}),
+        )))
+        .await;
+        client.ping().await.unwrap();
+
+        match diagnostics.try_recv().unwrap() {
+            ClientDiagnostic::LateResponse { id, method, .. } => {
+                assert_eq!(id, RequestId::Number(1));
+                assert_eq!(method, "ping");
+            }
+            diagnostic => panic!("unexpected diagnostic {:?}", diagnostic),
+        }
+        assert!(diagnostics.try_recv().is_err());
+    }
+
+    // Test dispatching a batch that mixes requests, notifications and responses
+    #[tokio::test]
+    async fn test_mixed_batch() {
+        let mock = MockTransport::new();
+        let batch = serde_json::json!([
+            { "jsonrpc": "2.0", "method": "notifications/message", "params": { "data": "hi" } },
+            { "jsonrpc": "2.0", "id": 1, "result": { "echo": "pong" } },
+            { "jsonrpc": "2.0", "id": "srv-1", "method": "roots/list" }
+        ]);
+        mock.receive_queue.lock().await.push_back(batch.to_string());
+
+        let mut client = Client::new(mock.clone());
+        client.register_request_handler("roots/list", |_| async {
+            Ok(serde_json::json!({ "roots": [] }))
+        });
+
+        let result: Value = client.send_request("ping", None).await.unwrap();
+        assert_eq!(result["echo"], "pong");
+
+        // The server request in the batch was answered after the ping was sent
+        let _ping = mock.get_last_sent().await.unwrap();
+        let reply: Value = serde_json::from_str(&mock.get_last_sent().await.unwrap()).unwrap();
+        assert_eq!(reply["id"], "srv-1");
+        assert_eq!(reply["result"]["roots"], serde_json::json!([]));
+    }
+
+    // Test answering roots/list from cached, provider-computed roots
+    #[tokio::test]
+    async fn test_roots_provider() {
+        let mock = MockTransport::new();
+        let roots_list =
+            |id: &str| serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": "roots/list" });
+        let batch = serde_json::json!([
+            roots_list("srv-1"),
+            roots_list("srv-2"),
+            { "jsonrpc": "2.0", "id": 1, "result": {} }
+        ]);
+        mock.receive_queue.lock().await.push_back(batch.to_string());
+
+        let calls = Arc::new(AtomicU64::new(0));
+        let counter = calls.clone();
+        let mut client = Client::new(mock.clone()).with_roots_provider(move || {
+            let calls = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            async move {
+                Ok(vec![Root {
+                    uri: format!("file:///project/{}", calls),
+                    name: None,
+                }])
+            }
+        });
+        assert_eq!(client.client_capabilities()["roots"]["listChanged"], true);
+
+        // Both requests are answered, but the provider runs once
+        client.ping().await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let _ping = mock.get_last_sent().await.unwrap();
+        for _ in 0..2 {
+            let reply: Value = serde_json::from_str(&mock.get_last_sent().await.unwrap()).unwrap();
+            assert_eq!(reply["result"]["roots"][0]["uri"], "file:///project/1");
+        }
+
+        // After a change notification the roots are computed again
+        client.notify_roots_changed().await.unwrap();
+        let notification: Value =
+            serde_json::from_str(&mock.get_last_sent().await.unwrap()).unwrap();
+        assert_eq!(notification["method"], "notifications/roots/list_changed");
+
+        let batch =
+            serde_json::json!([roots_list("srv-3"), { "jsonrpc": "2.0", "id": 2, "result": {} }]);
+        mock.receive_queue.lock().await.push_back(batch.to_string());
+        client.ping().await.unwrap();
+        let _ping = mock.get_last_sent().await.unwrap();
+        let reply: Value = serde_json::from_str(&mock.get_last_sent().await.unwrap()).unwrap();
+        assert_eq!(reply["result"]["roots"][0]["uri"], "file:///project/2");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    // Test adding and removing roots at runtime
+    #[tokio::test]
+    async fn test_add_and_remove_roots() {
+        let mock = MockTransport::new();
+        let mut client = Client::new(mock.clone()).with_roots([]);
+        let clone = client.clone();
+
+        let root = |uri: &str, name: &str| Root {
+            uri: uri.to_string(),
+            name: Some(name.to_string()),
+        };
+        client.add_root(root("file:///a", "a")).await.unwrap();
+        client.add_root(root("file:///b", "b")).await.unwrap();
+        client.add_root(root("file:///a", "renamed")).await.unwrap();
+        assert!(client.remove_root("file:///b").await.unwrap());
+        assert!(!client.remove_root("file:///b").await.unwrap());
+
+        // Each change but the last was notified
+        for _ in 0..4 {
+            let notification: Value =
+                serde_json::from_str(&mock.get_last_sent().await.unwrap()).unwrap();
+            assert_eq!(notification["method"], "notifications/roots/list_changed");
+        }
+        assert!(mock.get_last_sent().await.is_none());
+
+        // Clones answer roots/list with the edited roots
+        let roots = clone.roots.as_ref().unwrap().list().await.unwrap();
+        assert_eq!(roots, vec![root("file:///a", "renamed")]);
+    }
+
+    // Test the JSON-RPC version compatibility override and its validation
+    #[tokio::test]
+    async fn test_jsonrpc_version_override() {
+        let mock = MockTransport::new();
+        let mut legacy_response = JSONRPCResponse::new(RequestId::Number(1), serde_json::json!({}));
+        legacy_response.jsonrpc = "2".to_string();
+        mock.queue_message(JSONRPCMessage::Response(legacy_response.clone()))
+            .await;
+
+        let mut client = Client::new(mock.clone()).with_jsonrpc_version("2");
+        client.ping().await.unwrap();
+
+        let sent: JSONRPCMessage =
+            serde_json::from_str(&mock.get_last_sent().await.unwrap()).unwrap();
+        assert_eq!(sent.jsonrpc(), "2");
+
+        // A default client rejects the nonstandard version
+        let mock = MockTransport::new();
+        mock.queue_message(JSONRPCMessage::Response(legacy_response))
+            .await;
+        let mut client = Client::new(mock);
+        assert!(matches!(client.ping().await, Err(MCPError::Protocol(_))));
+    }
+
+    // Test that in-flight requests are visible until they resolve
+    #[tokio::test(start_paused = true)]
+    async fn test_pending_requests() {
+        let mock = MockTransport::new();
+        mock.set_simulate_timeout(true).await;
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(1),
+            serde_json::json!({}),
+        )))
+        .await;
+
+        let mut client =
+            Client::new(mock.clone()).with_slow_request_threshold(Duration::from_millis(500));
+        assert!(client.pending_requests().is_empty());
+        let pending = client.pending.clone();
+
+        {
+            let ping = client.ping();
+            tokio::pin!(ping);
+            tokio::select! {
+                _ = &mut ping => panic!("ping resolved before the mock responded"),
+                _ = tokio::time::sleep(Duration::from_secs(1)) => {}
             }
 
-            let mut started = self.is_started.lock().await;
-            *started = true;
-            Ok(())
+            let outstanding = snapshot_pending(&pending);
+            assert_eq!(outstanding.len(), 1);
+            assert_eq!(outstanding[0].id, RequestId::Number(1));
+            assert_eq!(outstanding[0].method, "ping");
+            assert_eq!(outstanding[0].elapsed, Duration::from_secs(1));
+
+            ping.await.unwrap();
         }
 
-        async fn send<T: Serialize + Send + Sync>(&mut self, message: &T) -> Result<(), MCPError> {
-            let should_fail = *self.should_fail.lock().await;
-            if should_fail {
-                return Err(MCPError::Transport(
-                    "Mock transport send failure".to_string(),
-                ));
-            }
+        assert!(client.pending_requests().is_empty());
+    }
 
-            let serialized =
-                serde_json::to_string(message).map_err(|e| MCPError::Serialization(e))?;
+    // Test the reconnect backoff schedule
+    #[test]
+    fn test_reconnect_policy_delays() {
+        let policy = ReconnectPolicy::new()
+            .with_initial_delay(Duration::from_millis(100))
+            .with_multiplier(2.0)
+            .with_max_delay(Duration::from_millis(500))
+            .with_jitter(0.0);
 
-            let mut queue = self.send_queue.lock().await;
-            queue.push_back(serialized);
-            Ok(())
-        }
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+        assert_eq!(policy.delay(4), Duration::from_millis(500));
+        assert_eq!(policy.delay(50), Duration::from_millis(500));
 
-        async fn receive<T: DeserializeOwned + Send + Sync>(&mut self) -> Result<T, MCPError> {
-            let should_fail = *self.should_fail.lock().await;
-            if should_fail {
-                return Err(MCPError::Transport(
-                    "Mock transport receive failure".to_string(),
-                ));
-            }
+        let jittered = policy.with_jitter(0.5);
+        for _ in 0..100 {
+            let delay = jittered.delay(2);
+            assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(300));
+        }
 
-            let simulate_timeout = *self.simulate_timeout.lock().await;
-            if simulate_timeout {
-                // Simulate a long operation
-                tokio::time::sleep(Duration::from_secs(2)).await;
-            }
+        // Values set on the public fields directly do not panic
+        let mut unchecked = ReconnectPolicy::new()
+            .with_max_delay(Duration::MAX)
+            .with_jitter(0.0);
+        unchecked.multiplier = f64::NAN;
+        assert_eq!(unchecked.delay(2), Duration::MAX);
+        unchecked.multiplier = -3.0;
+        assert_eq!(unchecked.base_delay(2), Duration::MAX);
+        unchecked.multiplier = 1e300;
+        unchecked.jitter = f64::INFINITY;
+        assert!(unchecked.delay(3) <= Duration::MAX);
+    }
 
-            let mut queue = self.receive_queue.lock().await;
+    // Test that reconnecting gives up after the maximum elapsed time
+    #[tokio::test(start_paused = true)]
+    async fn test_reconnect_gives_up() {
+        let mock = MockTransport::new();
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
 
-            if let Some(message) = queue.pop_front() {
-                // If there's a message callback, call it
-                if let Some(callback) = &*self.on_message.lock().unwrap() {
-                    callback(&message);
-                }
+        let mut client = Client::new(mock.clone()).with_reconnect_policy(
+            ReconnectPolicy::new()
+                .with_initial_delay(Duration::from_secs(1))
+                .with_jitter(0.0)
+                .with_max_elapsed(Some(Duration::from_secs(10))),
+        );
+        client.initialize().await.unwrap();
+        assert_eq!(client.state(), ConnectionState::Connected);
 
-                return serde_json::from_str(&message).map_err(|e| MCPError::Serialization(e));
-            }
+        let mut states = client.subscribe_state();
+        mock.set_should_fail(true).await;
 
-            Err(MCPError::Transport("No more messages".to_string()))
-        }
+        // Delays of 1s, 2s and 4s fit in 10s; the fourth (8s) does not
+        let result = client.reconnect().await;
+        assert!(result.is_err());
+        assert_eq!(client.state(), ConnectionState::Closed);
+        assert!(states.has_changed().unwrap());
+        assert_eq!(*states.borrow_and_update(), ConnectionState::Closed);
+    }
 
-        async fn close(&mut self) -> Result<(), MCPError> {
-            let should_fail = *self.should_fail.lock().await;
-            if should_fail {
-                return Err(MCPError::Transport(
-                    "Mock transport close failure".to_string(),
-                ));
-            }
+    // Test that a successful reconnect replays the handshake
+    #[tokio::test(start_paused = true)]
+    async fn test_reconnect_succeeds() {
+        let mock = MockTransport::new();
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
+        mock.queue_message(create_initialize_response(RequestId::Number(2)))
+            .await;
 
-            let mut closed = self.is_closed.lock().await;
-            *closed = true;
+        let mut client = Client::new(mock.clone());
+        client.initialize().await.unwrap();
 
-            // If there's a close callback, call it
-            if let Some(callback) = &*self.on_close.lock().unwrap() {
-                callback();
-            }
+        let result = client.reconnect().await;
+        assert!(result.is_ok());
+        assert_eq!(client.state(), ConnectionState::Connected);
+        assert!(*mock.is_closed.lock().await);
+    }
 
-            Ok(())
+    // Test that a reconnect restores the log level and subscriptions
+    #[tokio::test(start_paused = true)]
+    async fn test_reconnect_restores_session() {
+        let mock = MockTransport::new();
+        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+            .await;
+        for id in 2..=3 {
+            mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+                RequestId::Number(id),
+                serde_json::json!({}),
+            )))
+            .await;
         }
-
-        fn set_on_close(&mut self, callback: Option<CloseCallback>) {
-            let mut cb = self.on_close.lock().unwrap();
-            *cb = callback;
+        mock.queue_message(create_initialize_response(RequestId::Number(4)))
+            .await;
+        for id in 5..=6 {
+            mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+                RequestId::Number(id),
+                serde_json::json!({}),
+            )))
+            .await;
         }
 
-        fn set_on_error(&mut self, callback: Option<ErrorCallback>) {
-            let mut cb = self.on_error.lock().unwrap();
-            *cb = callback;
-        }
+        let mut client = Client::new(mock.clone());
+        client.initialize().await.unwrap();
+        client.set_log_level(LoggingLevel::Warning).await.unwrap();
+        client.subscribe_resource("file:///a").await.unwrap();
+        client.reconnect().await.unwrap();
 
-        fn set_on_message<F>(&mut self, callback: Option<F>)
-        where
-            F: Fn(&str) + Send + Sync + 'static,
-        {
-            let mut cb = self.on_message.lock().unwrap();
-            if let Some(callback) = callback {
-                *cb = Some(Box::new(callback));
-            } else {
-                *cb = None;
+        let mut requests = Vec::new();
+        while let Some(message) = mock.get_last_sent().await {
+            let message: Value = serde_json::from_str(&message).unwrap();
+            if message.get("id").is_some() {
+                requests.push(message);
             }
         }
+        let methods: Vec<&str> = requests
+            .iter()
+            .map(|request| request["method"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            methods,
+            [
+                "initialize",
+                "logging/setLevel",
+                "resources/subscribe",
+                "initialize",
+                "logging/setLevel",
+                "resources/subscribe"
+            ]
+        );
+        assert_eq!(requests[4]["params"]["level"], "warning");
     }
 
-    // Helper function to create a server info response
-    fn create_initialize_response(id: RequestId) -> JSONRPCMessage {
-        JSONRPCMessage::Response(JSONRPCResponse {
-            jsonrpc: "2.0".to_string(),
-            id,
-            result: serde_json::json!({
-                "protocol_version": "1.0",
-                "server_info": {
-                    "name": "TestServer",
-                    "version": "1.0.0",
-                    "protocol_version": "1.0"
-                },
-                "capabilities": {
-                    "tools": true
-                }
-            }),
-        })
+    // Test that shutting down fails requests still waiting for a response
+    #[tokio::test(start_paused = true)]
+    async fn test_shutdown_mid_request() {
+        let mock = MockTransport::new();
+        let mut client = Client::new(mock.clone());
+
+        // A concurrent request waits for a response that never comes
+        mock.set_simulate_timeout(true).await;
+        let mut other = client.clone();
+        let pending = tokio::spawn(async move { other.ping().await });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(client.pending_requests().len(), 1);
+
+        mock.set_simulate_timeout(false).await;
+        mock.queue_message(JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::Number(2),
+            serde_json::json!({}),
+        )))
+        .await;
+        client.shutdown().await.unwrap();
+
+        let result = tokio::time::timeout(Duration::from_millis(10), pending)
+            .await
+            .expect("pending request should fail right away")
+            .unwrap();
+        assert!(matches!(result, Err(MCPError::ConnectionClosed)));
+        assert!(client.pending_requests().is_empty());
+
+        // A response arriving afterwards is discarded
+        client
+            .route_response(JSONRPCMessage::Response(JSONRPCResponse::new(
+                RequestId::Number(1),
+                serde_json::json!({}),
+            )))
+            .unwrap();
     }
 
-    // Helper function to create a tools list response
-    fn create_tools_list_response(id: RequestId) -> JSONRPCMessage {
-        JSONRPCMessage::Response(JSONRPCResponse {
-            jsonrpc: "2.0".to_string(),
-            id,
-            result: serde_json::json!([
-                {
-                    "name": "hello",
-                    "description": "Say hello",
-                    "input_schema": {
-                        "type": "object",
-                        "properties": {
-                            "name": {
-                                "type": "string"
-                            }
-                        }
+    #[tokio::test]
+    async fn test_tool_call_policy() -> Result<(), MCPError> {
+        use crate::testing::MockServer;
+
+        struct Policy;
+
+        #[async_trait]
+        impl ToolCallPolicy for Policy {
+            async fn check(&self, tool_name: &str, arguments: &Value) -> ToolCallDecision {
+                match tool_name {
+                    "write" => {
+                        let approved = arguments["path"] != "/etc/passwd";
+                        ToolCallDecision::Ask(Box::pin(async move { approved }))
                     }
+                    _ => ToolCallDecision::Deny("not approved".to_string()),
                 }
-            ]),
-        })
-    }
+            }
+        }
 
-    // Helper function to create a tool call response
-    fn create_tool_call_response(id: RequestId, result: serde_json::Value) -> JSONRPCMessage {
-        JSONRPCMessage::Response(JSONRPCResponse {
-            jsonrpc: "2.0".to_string(),
-            id,
-            result: serde_json::json!({
-                "result": result
-            }),
-        })
+        let server = MockServer::new().with_response("tools/call", serde_json::json!({}));
+        let mut client = Client::new(server.transport())
+            .with_tool_call_policy(Policy)
+            .with_allowed_tools(["read"])
+            .with_denied_tools(["delete"]);
+        let mut diagnostics = client.subscribe_diagnostics();
+        client.initialize().await?;
+
+        let _: Value = client.call_tool("read", &serde_json::json!({})).await?;
+        let _: Value = client
+            .call_tool("write", &serde_json::json!({ "path": "/tmp/a" }))
+            .await?;
+        for (tool, path) in [("write", "/etc/passwd"), ("delete", "/"), ("run", "/")] {
+            let result: Result<Value, _> = client
+                .call_tool(tool, &serde_json::json!({ "path": path }))
+                .await;
+            assert!(
+                matches!(result, Err(MCPError::ToolCallDenied { .. })),
+                "{}",
+                tool
+            );
+        }
+
+        // Denied calls never reach the server, and every decision is published
+        let calls = server
+            .requests()
+            .into_iter()
+            .filter(|request| request.method == "tools/call")
+            .count();
+        assert_eq!(calls, 2);
+        let mut decisions = Vec::new();
+        while let Ok(ClientDiagnostic::ToolCallDecided {
+            tool,
+            allowed,
+            asked,
+            ..
+        }) = diagnostics.try_recv()
+        {
+            decisions.push((tool, allowed, asked));
+        }
+        let expected = [
+            ("read", true, false),
+            ("write", true, true),
+            ("write", false, true),
+            ("delete", false, false),
+            ("run", false, false),
+        ];
+        let expected: Vec<_> = expected
+            .into_iter()
+            .map(|(tool, allowed, asked)| (tool.to_string(), allowed, asked))
+            .collect();
+        assert_eq!(decisions, expected);
+        Ok(())
     }
 
-    // Basic client initialization test
     #[tokio::test]
-    async fn test_client_initialization() {
-        // Create a mock transport
-        let mut mock = MockTransport::new();
+    async fn test_approve_read_only_tools() -> Result<(), MCPError> {
+        use crate::testing::MockServer;
 
-        // Queue the initialize response
-        mock.queue_message(create_initialize_response(RequestId::Number(1)))
-            .await;
+        struct DenyAll;
 
-        // Create client with mock transport
-        let mut client = Client::new(mock.clone());
+        #[async_trait]
+        impl ToolCallPolicy for DenyAll {
+            async fn check(&self, _tool_name: &str, _arguments: &Value) -> ToolCallDecision {
+                ToolCallDecision::Deny("not approved".to_string())
+            }
+        }
 
-        // Test initialization
-        let result = client.initialize().await;
-        assert!(result.is_ok(), "Client initialization should succeed");
+        let tools = serde_json::json!({ "tools": [
+            {
+                "name": "search",
+                "inputSchema": { "type": "object" },
+                "annotations": { "title": "Search", "readOnlyHint": true }
+            },
+            { "name": "delete", "inputSchema": { "type": "object" } },
+        ] });
+        let server = MockServer::new()
+            .with_response("tools/list", tools)
+            .with_response("tools/call", serde_json::json!({}));
+        let mut client =
+            Client::new(server.transport()).with_tool_call_policy(ApproveReadOnly(DenyAll));
+        client.initialize().await?;
 
-        // Check what was sent to the server
-        let sent = mock.get_last_sent().await.unwrap();
-        let sent_msg: JSONRPCMessage = serde_json::from_str(&sent).unwrap();
+        // Annotations are only known once the tools are listed
+        let result: Result<Value, _> = client.call_tool("search", &serde_json::json!({})).await;
+        assert!(matches!(result, Err(MCPError::ToolCallDenied { .. })));
 
-        if let JSONRPCMessage::Request(req) = sent_msg {
-            assert_eq!(req.method, "initialize");
-        } else {
-            panic!("Expected request message");
-        }
+        let tools = client.list_all_tools().await?;
+        assert_eq!(
+            tools[0].annotations,
+            Some(ToolAnnotations {
+                title: Some("Search".to_string()),
+                ..ToolAnnotations::read_only()
+            })
+        );
+        assert!(client
+            .tool_annotations("search")
+            .is_some_and(|a| !a.is_destructive()));
+        let _: Value = client.call_tool("search", &serde_json::json!({})).await?;
+        let result: Result<Value, _> = client.call_tool("delete", &serde_json::json!({})).await;
+        assert!(matches!(result, Err(MCPError::ToolCallDenied { .. })));
+        Ok(())
     }
 
-    // Test client error handling
+    // Test that requests the server did not advertise fail without being sent
     #[tokio::test]
-    async fn test_client_error_handling() {
-        // Create a mock transport that will fail
-        let mut mock = MockTransport::new();
-        mock.set_should_fail(true).await;
+    async fn test_capability_not_supported() -> Result<(), MCPError> {
+        use crate::testing::MockServer;
 
-        // Create client with mock transport
-        let mut client = Client::new(mock);
+        let server = MockServer::new().with_response("resources/list", serde_json::json!({}));
+        let mut client = Client::new(server.transport());
+        client.initialize().await?;
 
-        // Test initialization with failing transport
-        let result = client.initialize().await;
+        let error = client.subscribe_resource("file:///a").await.unwrap_err();
         assert!(
-            result.is_err(),
-            "Client initialization should fail with failing transport"
+            matches!(
+                &error,
+                MCPError::CapabilityNotSupported { method, capability }
+                    if method == "resources/subscribe" && capability == "resources.subscribe"
+            ),
+            "{:?}",
+            error
+        );
+        let error = client.list_tools::<Value>().await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Server does not support 'tools/list': it did not advertise the 'tools' capability"
         );
+        assert_eq!(error.kind(), ErrorKind::Protocol);
+
+        // Methods outside the specification are left to the server
+        let error = client.request::<Value>("x/custom", None).await.unwrap_err();
+        assert!(error.is_method_not_found());
+        let methods: Vec<_> = server.requests().into_iter().map(|r| r.method).collect();
+        assert_eq!(methods, ["initialize", "x/custom"]);
+        Ok(())
     }
 
-    // Test tool calling
-    #[tokio::test]
-    async fn test_tool_call() {
-        // Create a mock transport
-        let mut mock = MockTransport::new();
+    #[tokio::test(start_paused = true)]
+    async fn test_shutdown_with_drains_requests() -> Result<(), MCPError> {
+        use crate::testing::{Fault, MockServer};
 
-        // Queue the initialize response
-        mock.queue_message(create_initialize_response(RequestId::Number(1)))
+        let server = MockServer::new().with_response("tools/call", serde_json::json!({}));
+        let mut client = Client::new(server.transport());
+        client.initialize().await?;
+
+        server.inject("tools/call", Fault::Delay(Duration::from_secs(5)));
+        let mut other = client.clone();
+        let call = tokio::spawn(async move { other.call_tool::<_, Value>("slow", &()).await });
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        assert_eq!(client.pending_requests().len(), 1);
+
+        let start = tokio::time::Instant::now();
+        client
+            .shutdown_with(ShutdownOptions::new().with_drain_timeout(Duration::from_secs(10)))
+            .await?;
+        assert!(start.elapsed() >= Duration::from_secs(4));
+        assert!(call.await.unwrap().is_ok());
+
+        let methods: Vec<_> = server.requests().into_iter().map(|r| r.method).collect();
+        assert_eq!(methods, ["initialize", "tools/call", "shutdown"]);
+        assert!(matches!(
+            client.ping().await,
+            Err(MCPError::ConnectionClosed)
+        ));
+
+        // Without a response, the shutdown times out but still closes
+        let server = MockServer::new();
+        let mut client = Client::new(server.transport());
+        server.inject("shutdown", Fault::Delay(Duration::from_secs(60)));
+        let result = client
+            .shutdown_with(ShutdownOptions::new().with_timeout(Duration::from_secs(1)))
             .await;
+        assert!(matches!(result, Err(MCPError::Timeout(_))));
+        assert!(matches!(
+            client.ping().await,
+            Err(MCPError::ConnectionClosed)
+        ));
+        Ok(())
+    }
 
-        // Queue the tool call response
-        mock.queue_message(create_tool_call_response(
-            RequestId::Number(2),
-            serde_json::json!("Hello, Test User!"),
-        ))
-        .await;
+    // Test that a server that stops answering pings is detected
+    #[tokio::test(start_paused = true)]
+    async fn test_keep_alive() -> Result<(), MCPError> {
+        use crate::testing::{Fault, MockServer};
 
-        // Create client with mock transport
-        let mut client = Client::new(mock.clone());
+        let server = MockServer::new().with_response("tools/call", serde_json::json!({}));
+        let mut client = Client::new(server.transport());
+        client.initialize().await?;
+        client.ping().await?;
+        assert!(client.ping_latency().is_some());
 
-        // Initialize the client
-        client.initialize().await.unwrap();
+        let keep_alive = KeepAlive::new(Duration::from_secs(5))
+            .with_timeout(Duration::from_secs(1))
+            .with_max_missed(2);
+        let task = client.spawn_keep_alive(keep_alive);
+        tokio::time::sleep(Duration::from_secs(11)).await;
+        assert_eq!(client.state(), ConnectionState::Connected);
 
-        // Call a tool
-        let params = serde_json::json!({
-            "name": "Test User"
-        });
+        server.inject("tools/call", Fault::Delay(Duration::from_secs(60)));
+        server.inject("ping", Fault::Delay(Duration::from_secs(60)));
+        server.inject("ping", Fault::Delay(Duration::from_secs(60)));
+        let mut other = client.clone();
+        let call = tokio::spawn(async move { other.call_tool::<_, Value>("slow", &()).await });
 
-        let result: String = client.call_tool("hello", &params).await.unwrap();
-        assert_eq!(result, "Hello, Test User!");
+        let mut state = client.subscribe_state();
+        state
+            .wait_for(|state| *state == ConnectionState::Unresponsive)
+            .await
+            .unwrap();
+        assert!(matches!(
+            call.await.unwrap(),
+            Err(MCPError::ConnectionClosed)
+        ));
 
-        // Check what was sent to the server
-        let init_msg = mock.get_last_sent().await.unwrap();
-        let tool_msg = mock.get_last_sent().await.unwrap();
+        client.close().await;
+        task.await.unwrap();
+        Ok(())
+    }
 
-        let tool_req: JSONRPCMessage = serde_json::from_str(&tool_msg).unwrap();
-        if let JSONRPCMessage::Request(req) = tool_req {
-            assert_eq!(req.method, "tool_call");
-            if let Some(params) = req.params {
-                assert_eq!(params["name"], "hello");
-            } else {
-                panic!("Expected tool call params");
+    // Test that reconnecting builds a new transport with the factory
+    #[tokio::test(start_paused = true)]
+    async fn test_transport_factory() {
+        let built = Arc::new(Mutex::new(Vec::new()));
+        let built_clone = built.clone();
+        let factory = move || {
+            let built = built_clone.clone();
+            async move {
+                // Each connection answers the initialize request sent on it
+                let mock = MockTransport::new();
+                let id = built.lock().unwrap().len() as i64 + 1;
+                mock.queue_message(create_initialize_response(RequestId::Number(id)))
+                    .await;
+                built.lock().unwrap().push(mock.clone());
+                Ok(mock)
             }
-        } else {
-            panic!("Expected request message");
-        }
+        };
+
+        let mut client = Client::from_transport_factory(factory).await.unwrap();
+        client.initialize().await.unwrap();
+        assert_eq!(built.lock().unwrap().len(), 1);
+
+        client.reconnect().await.unwrap();
+        assert_eq!(client.state(), ConnectionState::Connected);
+
+        let built = built.lock().unwrap().clone();
+        assert_eq!(built.len(), 2);
+        assert!(*built[0].is_closed.lock().await);
+        assert!(!*built[1].is_closed.lock().await);
     }
 
     // Test timeout handling
     #[tokio::test]
     async fn test_timeout_handling() {
         // Create a mock transport that will simulate a timeout
-        let mut mock = MockTransport::new();
+        let mock = MockTransport::new();
         mock.set_simulate_timeout(true).await;
 
         // Queue the initialize response (but it won't be used due to timeout)
@@ -669,7 +7860,7 @@ mod tests {
     #[tokio::test]
     async fn test_shutdown() {
         // Create a mock transport
-        let mut mock = MockTransport::new();
+        let mock = MockTransport::new();
 
         // Queue the initialize response
         mock.queue_message(create_initialize_response(RequestId::Number(1)))
@@ -702,7 +7893,7 @@ mod tests {
     #[tokio::test]
     async fn test_error_response_handling() {
         // Create a mock transport
-        let mut mock = MockTransport::new();
+        let mock = MockTransport::new();
 
         // Queue an error response for initialization
         mock.queue_message(JSONRPCMessage::Error(JSONRPCError {
@@ -726,21 +7917,56 @@ mod tests {
             "Client initialization should fail with error response"
         );
 
-        if let Err(MCPError::Protocol(msg)) = result {
-            assert!(
-                msg.contains("Test error"),
-                "Error message should contain the error from the response"
-            );
+        if let Err(MCPError::Rpc { code, message, .. }) = result {
+            assert_eq!(code, -32000);
+            assert_eq!(message, "Test error");
         } else {
-            panic!("Expected protocol error but got: {:?}", result);
+            panic!("Expected JSON-RPC error but got: {:?}", result);
         }
     }
 
+    // Test the helpers classifying errors
+    #[tokio::test]
+    async fn test_error_helpers() {
+        let mock = MockTransport::new();
+        mock.queue_message(JSONRPCMessage::Error(JSONRPCError::new_with_details(
+            RequestId::Number(1),
+            error_codes::METHOD_NOT_FOUND,
+            "Method not found".to_string(),
+            Some(serde_json::json!({ "method": "tools/list" })),
+        )))
+        .await;
+        let mut client = Client::new(mock.clone());
+
+        let err = client.list_tools::<Value>().await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Rpc);
+        assert_eq!(err.code(), Some(error_codes::METHOD_NOT_FOUND));
+        assert_eq!(err.data().unwrap()["method"], "tools/list");
+        assert!(err.is_method_not_found());
+        assert!(!err.is_invalid_params());
+        assert!(!err.retryable());
+
+        let rate_limited = MCPError::custom(RATE_LIMITED, "Slow down", None);
+        assert!(rate_limited.retryable());
+        assert!(MCPError::Timeout("ping".to_string()).retryable());
+        let internal = MCPError::Rpc {
+            code: error_codes::INTERNAL_ERROR,
+            message: "Crashed".to_string(),
+            data: None,
+        };
+        assert!(!internal.retryable());
+        assert_eq!(MCPError::ConnectionClosed.kind(), ErrorKind::Transport);
+        let protocol = MCPError::Protocol("Unexpected response type".to_string());
+        assert_eq!(protocol.kind(), ErrorKind::Protocol);
+        assert!(!protocol.retryable());
+        assert_eq!(protocol.code(), None);
+    }
+
     // Test concurrent tool calls
     #[tokio::test]
     async fn test_concurrent_tool_calls() -> Result<(), MCPError> {
         // Create a mock transport
-        let mut mock = MockTransport::new();
+        let mock = MockTransport::new();
 
         // Queue the initialize response
         mock.queue_message(create_initialize_response(RequestId::Number(1)))
@@ -772,7 +7998,7 @@ mod tests {
         ];
 
         // Call tools concurrently
-        let results: Vec<Result<String, MCPError>> =
+        let results: Vec<Result<ToolCallResult, MCPError>> =
             client.call_tools_concurrent(tool_calls).await?;
 
         // Verify results
@@ -780,11 +8006,11 @@ mod tests {
         assert!(results[0].is_ok());
         assert!(results[1].is_ok());
 
-        assert_eq!(results[0].as_ref().unwrap(), "Result 1");
-        assert_eq!(results[1].as_ref().unwrap(), "Result 2");
+        assert_eq!(results[0].as_ref().unwrap().result, "Result 1");
+        assert_eq!(results[1].as_ref().unwrap().result, "Result 2");
 
         // Verify the requests were sent
-        let init_msg = mock.get_last_sent().await.unwrap();
+        let _init_msg = mock.get_last_sent().await.unwrap();
         let tool1_msg = mock.get_last_sent().await.unwrap();
         let tool2_msg = mock.get_last_sent().await.unwrap();
 
@@ -793,18 +8019,18 @@ mod tests {
         let tool2_req: JSONRPCMessage = serde_json::from_str(&tool2_msg).unwrap();
 
         if let JSONRPCMessage::Request(req) = tool1_req {
-            assert_eq!(req.method, "tool_call");
+            assert_eq!(req.method, "tools/call");
             if let Some(params) = req.params {
                 assert_eq!(params["name"], "tool1");
-                assert_eq!(params["parameters"]["param"], "value1");
+                assert_eq!(params["arguments"]["param"], "value1");
             }
         }
 
         if let JSONRPCMessage::Request(req) = tool2_req {
-            assert_eq!(req.method, "tool_call");
+            assert_eq!(req.method, "tools/call");
             if let Some(params) = req.params {
                 assert_eq!(params["name"], "tool2");
-                assert_eq!(params["parameters"]["param"], "value2");
+                assert_eq!(params["arguments"]["param"], "value2");
             }
         }
 
@@ -0,0 +1,381 @@
+//! Time source for timeouts, backoff and other time-based behavior
+//!
+//! The client reads time through a [`Clock`], which is [`SystemClock`]
+//! unless replaced. With the `test-util` feature, tests can install a
+//! [`MockClock`] with `Client::with_clock` and move time forward by hand,
+//! so a 30 second timeout fires without waiting 30 seconds:
+//!
+//! ```rust,ignore
+//! let clock = MockClock::new();
+//! let mut client = Client::new(transport)
+//!     .with_clock(clock.clone())
+//!     .with_timeout(Duration::from_secs(30));
+//! let call = tokio::spawn(async move { client.ping().await });
+//!
+//! while clock.sleepers() == 0 {
+//!     tokio::task::yield_now().await;
+//! }
+//! clock.advance(Duration::from_secs(30));
+//! assert!(matches!(call.await?, Err(MCPError::Timeout(_))));
+//! ```
+//!
+//! [`SystemClock`] uses tokio's timers with the `runtime-tokio` feature.
+//! Without it, timers are fired by a thread of their own, so they work on
+//! any executor. In the browser, [`SystemClock`] reads `performance.now()`
+//! and sleeps with `setTimeout`, and [`Instant`] is a point in time on that
+//! clock instead of tokio's.
+
+use futures::future::BoxFuture;
+use std::{fmt, future::Future, time::Duration};
+
+/// A point in time, as measured by a [`Clock`]
+#[cfg(not(target_arch = "wasm32"))]
+pub use tokio::time::Instant;
+#[cfg(target_arch = "wasm32")]
+pub use web::Instant;
+
+#[cfg(any(test, feature = "test-util"))]
+use std::sync::Arc;
+#[cfg(any(test, feature = "test-util"))]
+use tokio::sync::watch;
+
+/// A source of the current time and of timers
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// The current time
+    fn now(&self) -> Instant;
+
+    /// Wait until `deadline`
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()>;
+
+    /// Wait for `duration`
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        self.sleep_until(self.now() + duration)
+    }
+}
+
+/// The clock of the tokio runtime
+///
+/// Follows tokio's paused time in tests that use `tokio::time::pause`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "runtime-tokio")]
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep_until(deadline))
+    }
+}
+
+#[cfg(all(not(feature = "runtime-tokio"), target_arch = "wasm32"))]
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+        web::sleep(deadline.saturating_duration_since(Instant::now()))
+    }
+}
+
+#[cfg(all(not(feature = "runtime-tokio"), not(target_arch = "wasm32")))]
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+        timer_thread::sleep_until(deadline)
+    }
+}
+
+/// Timers for builds without a runtime, fired by a thread of their own
+#[cfg(all(not(feature = "runtime-tokio"), not(target_arch = "wasm32")))]
+mod timer_thread {
+    use super::Instant;
+    use futures::{channel::oneshot, future::BoxFuture};
+    use std::{
+        collections::BTreeMap,
+        sync::{
+            mpsc::{self, RecvTimeoutError},
+            Mutex, OnceLock,
+        },
+    };
+
+    /// A deadline, and the sleeper to wake at it
+    type Timer = (Instant, oneshot::Sender<()>);
+
+    /// Sends new timers to the timer thread, which starts on first use
+    static TIMERS: OnceLock<Mutex<mpsc::Sender<Timer>>> = OnceLock::new();
+
+    /// Resolve once `deadline` passed
+    pub(super) fn sleep_until(deadline: Instant) -> BoxFuture<'static, ()> {
+        let (sender, receiver) = oneshot::channel();
+        let timers = TIMERS.get_or_init(|| {
+            let (timers, requests) = mpsc::channel();
+            std::thread::Builder::new()
+                .name("mcpr-timer".to_string())
+                .spawn(move || run(requests))
+                .expect("failed to spawn the timer thread");
+            Mutex::new(timers)
+        });
+        let _ = timers.lock().unwrap().send((deadline, sender));
+        Box::pin(async move {
+            let _ = receiver.await;
+        })
+    }
+
+    /// Wake each sleeper at its deadline, taking new timers in between
+    fn run(requests: mpsc::Receiver<Timer>) {
+        // Keyed by deadline, then by arrival, for timers with the same deadline
+        let mut timers: BTreeMap<(Instant, u64), oneshot::Sender<()>> = BTreeMap::new();
+        let mut arrivals = 0u64;
+        loop {
+            let now = Instant::now();
+            while let Some(timer) = timers.first_entry() {
+                let (deadline, _) = *timer.key();
+                if deadline > now {
+                    break;
+                }
+                let _ = timer.remove().send(());
+            }
+
+            let next = match timers.keys().next() {
+                Some((deadline, _)) => {
+                    match requests.recv_timeout(deadline.saturating_duration_since(now)) {
+                        Ok(timer) => timer,
+                        Err(RecvTimeoutError::Timeout) => continue,
+                        Err(RecvTimeoutError::Disconnected) => return,
+                    }
+                }
+                None => match requests.recv() {
+                    Ok(timer) => timer,
+                    Err(_) => return,
+                },
+            };
+            let (deadline, sleeper) = next;
+            timers.insert((deadline, arrivals), sleeper);
+            arrivals += 1;
+        }
+    }
+}
+
+/// Time in the browser, on the page's or worker's clock
+#[cfg(target_arch = "wasm32")]
+mod web {
+    use futures::{channel::oneshot, future::BoxFuture};
+    use std::{
+        ops::{Add, AddAssign, Sub, SubAssign},
+        time::Duration,
+    };
+    use wasm_bindgen::JsCast;
+    use web_sys::{Window, WorkerGlobalScope};
+
+    /// A point in time, measured from the page or worker's time origin
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct Instant(Duration);
+
+    impl Instant {
+        /// The current time
+        pub fn now() -> Self {
+            let global = js_sys::global();
+            let performance = match global.dyn_ref::<Window>() {
+                Some(window) => window.performance(),
+                None => global
+                    .dyn_ref::<WorkerGlobalScope>()
+                    .and_then(WorkerGlobalScope::performance),
+            };
+            let millis = performance.map_or_else(js_sys::Date::now, |p| p.now());
+            Self(Duration::from_secs_f64(millis.max(0.0) / 1000.0))
+        }
+
+        /// Time elapsed from `earlier` to this instant, zero if `earlier` is later
+        pub fn duration_since(&self, earlier: Instant) -> Duration {
+            self.saturating_duration_since(earlier)
+        }
+
+        /// Time elapsed from `earlier` to this instant, zero if `earlier` is later
+        pub fn saturating_duration_since(&self, earlier: Instant) -> Duration {
+            self.0.saturating_sub(earlier.0)
+        }
+
+        /// Time elapsed from `earlier` to this instant, `None` if `earlier` is later
+        pub fn checked_duration_since(&self, earlier: Instant) -> Option<Duration> {
+            self.0.checked_sub(earlier.0)
+        }
+
+        /// Time elapsed since this instant
+        pub fn elapsed(&self) -> Duration {
+            Self::now().saturating_duration_since(*self)
+        }
+
+        /// This instant moved `duration` later, if that can be represented
+        pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+            self.0.checked_add(duration).map(Self)
+        }
+
+        /// This instant moved `duration` earlier, if that can be represented
+        pub fn checked_sub(&self, duration: Duration) -> Option<Instant> {
+            self.0.checked_sub(duration).map(Self)
+        }
+    }
+
+    impl Add<Duration> for Instant {
+        type Output = Instant;
+
+        fn add(self, duration: Duration) -> Instant {
+            Self(self.0 + duration)
+        }
+    }
+
+    impl AddAssign<Duration> for Instant {
+        fn add_assign(&mut self, duration: Duration) {
+            self.0 += duration;
+        }
+    }
+
+    impl Sub<Duration> for Instant {
+        type Output = Instant;
+
+        fn sub(self, duration: Duration) -> Instant {
+            Self(self.0.saturating_sub(duration))
+        }
+    }
+
+    impl SubAssign<Duration> for Instant {
+        fn sub_assign(&mut self, duration: Duration) {
+            self.0 = self.0.saturating_sub(duration);
+        }
+    }
+
+    impl Sub<Instant> for Instant {
+        type Output = Duration;
+
+        fn sub(self, earlier: Instant) -> Duration {
+            self.saturating_duration_since(earlier)
+        }
+    }
+
+    /// Resolve after `duration`, with `setTimeout`
+    ///
+    /// The timer runs in a local task, so the returned future is `Send`.
+    pub(super) fn sleep(duration: Duration) -> BoxFuture<'static, ()> {
+        let (sender, receiver) = oneshot::channel();
+        wasm_bindgen_futures::spawn_local(async move {
+            let millis = duration.as_millis().min(i32::MAX as u128) as i32;
+            let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+                let global = js_sys::global();
+                if let Some(window) = global.dyn_ref::<Window>() {
+                    let _ = window
+                        .set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, millis);
+                } else if let Some(worker) = global.dyn_ref::<WorkerGlobalScope>() {
+                    let _ = worker
+                        .set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, millis);
+                }
+            });
+            let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+            let _ = sender.send(());
+        });
+        Box::pin(async move {
+            let _ = receiver.await;
+        })
+    }
+}
+
+/// Run `future` until it completes or `duration` passes on `clock`
+///
+/// Returns `None` when the time ran out first.
+pub(crate) async fn timeout<F: Future>(
+    clock: &dyn Clock,
+    duration: Duration,
+    future: F,
+) -> Option<F::Output> {
+    tokio::select! {
+        biased;
+        output = future => Some(output),
+        _ = clock.sleep(duration) => None,
+    }
+}
+
+/// A clock that only moves when told to
+///
+/// Clones share the same time. It starts at the tokio clock's current time,
+/// and timers fire once [`MockClock::advance`] moves it past their deadline.
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<watch::Sender<Instant>>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl MockClock {
+    /// Create a clock stopped at the current time
+    pub fn new() -> Self {
+        Self {
+            now: Arc::new(watch::channel(Instant::now()).0),
+        }
+    }
+
+    /// Move the clock forward, firing the timers that become due
+    pub fn advance(&self, duration: Duration) {
+        self.now.send_modify(|now| *now += duration);
+    }
+
+    /// Number of timers waiting on the clock
+    ///
+    /// Useful to wait until the code under test is sleeping before advancing.
+    pub fn sleepers(&self) -> usize {
+        self.now.receiver_count()
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.borrow()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+        let mut now = self.now.subscribe();
+        Box::pin(async move {
+            // Fires immediately if every clone of the clock is gone
+            let _ = now.wait_for(|now| *now >= deadline).await;
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_clock() {
+        let clock = MockClock::new();
+        let started = clock.now();
+
+        let sleep = tokio::spawn(clock.sleep(Duration::from_secs(30)));
+        while clock.sleepers() == 0 {
+            tokio::task::yield_now().await;
+        }
+        clock.advance(Duration::from_secs(29));
+        tokio::task::yield_now().await;
+        assert!(!sleep.is_finished());
+
+        clock.advance(Duration::from_secs(1));
+        sleep.await.unwrap();
+        assert_eq!(clock.now() - started, Duration::from_secs(30));
+
+        let output = timeout(&clock, Duration::ZERO, async { 1 }).await;
+        assert_eq!(output, Some(1));
+    }
+}
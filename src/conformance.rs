@@ -0,0 +1,344 @@
+//! Checking any MCP server against the specification
+//!
+//! [`run`] initializes a client with the server, then runs a battery of
+//! checks: the shape of the initialize result, pings, the error code for an
+//! unknown method, the shape and pagination of the lists the server
+//! advertises, and that cancelling an unknown request is ignored. The
+//! [`ConformanceReport`] it returns prints as one line per check, or as
+//! JSON for tools to read; `mcpr conformance` runs it from the command line.
+//!
+//! ```rust,no_run
+//! # use mcpr::{client::Client, conformance, transport::stdio::StdioTransport};
+//! # async fn run() -> Result<(), mcpr::error::MCPError> {
+//! let transport = StdioTransport::spawn("my-server", ["--stdio"])?;
+//! let mut client = Client::new(transport);
+//! let report = conformance::run(&mut client).await;
+//! println!("{}", report);
+//! assert!(report.passed());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    client::Client,
+    error::MCPError,
+    schema::{common::Implementation, json_rpc::error_codes},
+    transport::Transport,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{collections::HashSet, fmt};
+
+/// Pages of a list followed before the list is considered endless
+const MAX_PAGES: usize = 100;
+
+/// Method sent to check that unknown methods are refused
+const UNKNOWN_METHOD: &str = "mcpr/conformance/unknown";
+
+/// What the initialize check verifies
+const INITIALIZE: &str = "initialize returns a protocol version, server info and capabilities";
+
+/// The checks run once the server is initialized, with what they verify
+const CHECKS: [(&str, &str); 6] = [
+    ("ping", "ping is answered with an empty result"),
+    (
+        "unknown-method",
+        "unknown methods are refused with -32601 (method not found)",
+    ),
+    (
+        "tools-list",
+        "tools/list pages hold named tools with object input schemas, without repeats",
+    ),
+    (
+        "resources-list",
+        "resources/list pages hold resources with a URI and name, without repeats",
+    ),
+    (
+        "prompts-list",
+        "prompts/list pages hold named prompts, without repeats",
+    ),
+    (
+        "cancellation",
+        "cancelling an unknown request is ignored and the server stays responsive",
+    ),
+];
+
+/// How a check went
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", content = "detail", rename_all = "lowercase")]
+pub enum Outcome {
+    /// The server behaved as the specification requires
+    Passed,
+    /// The server did not, for the reason given
+    Failed(String),
+    /// The check does not apply to the server, for the reason given
+    Skipped(String),
+}
+
+/// The outcome of one check
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CheckResult {
+    /// Short name of the check, such as `unknown-method`
+    pub name: String,
+    /// What the check verifies
+    pub description: String,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+/// The outcomes of every check run against a server
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConformanceReport {
+    /// Name and version the server gave on initialization
+    pub server_info: Option<Implementation>,
+    /// Protocol version the server agreed on
+    pub protocol_version: Option<String>,
+    pub checks: Vec<CheckResult>,
+}
+
+impl ConformanceReport {
+    /// Whether no check failed
+    pub fn passed(&self) -> bool {
+        !self
+            .checks
+            .iter()
+            .any(|check| matches!(check.outcome, Outcome::Failed(_)))
+    }
+
+    /// The checks that failed
+    pub fn failures(&self) -> impl Iterator<Item = &CheckResult> {
+        self.checks
+            .iter()
+            .filter(|check| matches!(check.outcome, Outcome::Failed(_)))
+    }
+
+    /// The report as JSON
+    pub fn to_json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+
+    fn record(&mut self, name: &str, description: &str, outcome: Outcome) {
+        self.checks.push(CheckResult {
+            name: name.to_string(),
+            description: description.to_string(),
+            outcome,
+        });
+    }
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(info) = &self.server_info {
+            writeln!(f, "{} {}", info.name, info.version)?;
+        }
+        for check in &self.checks {
+            match &check.outcome {
+                Outcome::Passed => writeln!(f, "PASS {}: {}", check.name, check.description)?,
+                Outcome::Failed(reason) => writeln!(f, "FAIL {}: {}", check.name, reason)?,
+                Outcome::Skipped(reason) => writeln!(f, "SKIP {}: {}", check.name, reason)?,
+            }
+        }
+        let failed = self.failures().count();
+        write!(f, "{} checks, {} failed", self.checks.len(), failed)
+    }
+}
+
+/// Run every check against the server `client` is connected to
+///
+/// The client must not be initialized yet. Checks that need an
+/// initialized session are skipped when initialization fails.
+pub async fn run<T: Transport + Send + Sync>(client: &mut Client<T>) -> ConformanceReport {
+    let mut report = ConformanceReport::default();
+
+    let initialized = match client.initialize_raw().await {
+        Ok(result) => {
+            report.server_info = serde_json::from_value(result["serverInfo"].clone()).ok();
+            report.protocol_version = result["protocolVersion"].as_str().map(str::to_string);
+            report.record("initialize", INITIALIZE, check_initialize_result(&result));
+            true
+        }
+        Err(e) => {
+            report.record(
+                "initialize",
+                INITIALIZE,
+                Outcome::Failed(format!("initialize failed: {}", e)),
+            );
+            false
+        }
+    };
+
+    if !initialized {
+        for (name, description) in CHECKS {
+            let skipped = Outcome::Skipped("the server could not be initialized".to_string());
+            report.record(name, description, skipped);
+        }
+        return report;
+    }
+
+    let outcomes = [
+        check_ping(client).await,
+        check_unknown_method(client).await,
+        check_list(client, "tools", &["name"]).await,
+        check_list(client, "resources", &["uri", "name"]).await,
+        check_list(client, "prompts", &["name"]).await,
+        check_cancellation(client).await,
+    ];
+    for ((name, description), outcome) in CHECKS.iter().zip(outcomes) {
+        report.record(name, description, outcome);
+    }
+    let _ = client.shutdown().await;
+    report
+}
+
+fn check_initialize_result(result: &Value) -> Outcome {
+    let version = result["protocolVersion"].as_str().unwrap_or_default();
+    let is_date = version.len() == 10
+        && version.char_indices().all(|(i, c)| {
+            if i == 4 || i == 7 {
+                c == '-'
+            } else {
+                c.is_ascii_digit()
+            }
+        });
+    if !is_date {
+        return Outcome::Failed(format!(
+            "protocolVersion {} is not a YYYY-MM-DD date",
+            result["protocolVersion"]
+        ));
+    }
+    for field in ["name", "version"] {
+        if !result["serverInfo"][field].is_string() {
+            return Outcome::Failed(format!("serverInfo.{} is missing", field));
+        }
+    }
+    if !result["capabilities"].is_object() {
+        return Outcome::Failed("capabilities is not an object".to_string());
+    }
+    Outcome::Passed
+}
+
+async fn check_ping<T: Transport + Send + Sync>(client: &mut Client<T>) -> Outcome {
+    match client.request::<Value>("ping", None).await {
+        Ok(Value::Object(result)) if result.is_empty() => Outcome::Passed,
+        Ok(result) => Outcome::Failed(format!("ping answered with {}", result)),
+        Err(e) => Outcome::Failed(format!("ping failed: {}", e)),
+    }
+}
+
+async fn check_unknown_method<T: Transport + Send + Sync>(client: &mut Client<T>) -> Outcome {
+    match client.request::<Value>(UNKNOWN_METHOD, None).await {
+        Err(MCPError::Rpc { code, .. }) if code == error_codes::METHOD_NOT_FOUND => Outcome::Passed,
+        Err(MCPError::Rpc { code, message, .. }) => Outcome::Failed(format!(
+            "refused with {} ({}) instead of {}",
+            code,
+            message,
+            error_codes::METHOD_NOT_FOUND
+        )),
+        Ok(result) => Outcome::Failed(format!("answered with a result: {}", result)),
+        Err(e) => Outcome::Failed(format!("request failed: {}", e)),
+    }
+}
+
+/// Fetch every page of `{kind}/list`, checking each item has `fields`
+async fn check_list<T: Transport + Send + Sync>(
+    client: &mut Client<T>,
+    kind: &str,
+    fields: &[&str],
+) -> Outcome {
+    let advertised = client
+        .server_capabilities()
+        .and_then(|capabilities| serde_json::to_value(capabilities).ok())
+        .is_some_and(|capabilities| !capabilities[kind].is_null());
+    if !advertised {
+        return Outcome::Skipped(format!("the server does not advertise {}", kind));
+    }
+
+    let method = format!("{}/list", kind);
+    let mut seen = HashSet::new();
+    let mut cursors = HashSet::new();
+    let mut cursor: Option<String> = None;
+    for _ in 0..MAX_PAGES {
+        let params = cursor.as_ref().map(|cursor| json!({ "cursor": cursor }));
+        let page = match client.request::<Value>(&method, params).await {
+            Ok(page) => page,
+            Err(e) => return Outcome::Failed(format!("{} failed: {}", method, e)),
+        };
+        let Some(items) = page[kind].as_array() else {
+            return Outcome::Failed(format!("{} result has no '{}' array", method, kind));
+        };
+        for item in items {
+            if let Some(field) = fields.iter().find(|field| !item[**field].is_string()) {
+                return Outcome::Failed(format!("an item of {} has no '{}'", method, field));
+            }
+            if kind == "tools" && item["inputSchema"]["type"] != "object" {
+                return Outcome::Failed(format!(
+                    "tool '{}' has no object inputSchema",
+                    item["name"].as_str().unwrap_or_default()
+                ));
+            }
+            let key = item[fields[0]].as_str().unwrap_or_default().to_string();
+            if !seen.insert(key.clone()) {
+                return Outcome::Failed(format!("'{}' is listed twice", key));
+            }
+        }
+        match page["nextCursor"].as_str() {
+            None => return Outcome::Passed,
+            Some(next) if !cursors.insert(next.to_string()) => {
+                return Outcome::Failed(format!("cursor '{}' is returned twice", next))
+            }
+            Some(next) => cursor = Some(next.to_string()),
+        }
+    }
+    Outcome::Failed(format!("{} did not end within {} pages", method, MAX_PAGES))
+}
+
+async fn check_cancellation<T: Transport + Send + Sync>(client: &mut Client<T>) -> Outcome {
+    let params = json!({ "requestId": "mcpr-conformance-unknown", "reason": "conformance check" });
+    if let Err(e) = client.notify_raw("notifications/cancelled", &params).await {
+        return Outcome::Failed(format!("sending the cancellation failed: {}", e));
+    }
+    match client.ping().await {
+        Ok(()) => Outcome::Passed,
+        Err(e) => Outcome::Failed(format!("ping after the cancellation failed: {}", e)),
+    }
+}
+
+#[cfg(all(test, feature = "runtime-tokio"))]
+mod tests {
+    use super::*;
+    use crate::{
+        schema::common::{Tool, ToolInputSchema},
+        server::{Server, ServerConfig},
+        transport::in_memory::InMemoryTransport,
+    };
+
+    #[tokio::test]
+    async fn test_conformance_report() {
+        let (server_transport, client_transport) = InMemoryTransport::pair();
+        let mut server = Server::new(ServerConfig::new().with_tool(Tool {
+            name: "echo".to_string(),
+            description: None,
+            input_schema: ToolInputSchema {
+                r#type: "object".to_string(),
+                properties: None,
+                required: None,
+                keywords: serde_json::Map::new(),
+            },
+            output_schema: None,
+            annotations: None,
+        }));
+        server
+            .register_tool_handler("echo", |params: Value| async move { Ok(params) })
+            .unwrap();
+        tokio::spawn(async move { server.serve(server_transport).await });
+
+        let report = run(&mut Client::new(client_transport)).await;
+        assert!(report.passed(), "{}", report);
+        assert_eq!(report.checks.len(), 7);
+        let json = report.to_json();
+        assert_eq!(json["checks"][0]["name"], "initialize");
+        assert_eq!(json["checks"][0]["status"], "passed");
+        assert_eq!(json["checks"][4]["status"], "skipped");
+    }
+}
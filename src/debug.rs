@@ -0,0 +1,420 @@
+//! Readable renderings of protocol payloads for logs and bug reports
+//!
+//! A [`DebugFormatter`] pretty-prints JSON-RPC frames and tool results with
+//! base64 blobs replaced by their type and size and long strings cut short,
+//! so the output can be pasted into an issue. [`diff_initialize`] lists how
+//! two servers' (or two versions of one server's) handshakes differ.
+//!
+//! ```rust
+//! use mcpr::debug::DebugFormatter;
+//!
+//! let frame = r#"{"jsonrpc":"2.0","id":3,"method":"tools/call","params":{"name":"echo"}}"#;
+//! let text = DebugFormatter::new().format_frame(frame);
+//! assert!(text.starts_with("request 3 tools/call"));
+//! ```
+
+use crate::{
+    redact::Redactor,
+    schema::{
+        common::{base64_decoded_len, format_size},
+        json_rpc::{JSONRPCMessage, RequestId},
+        server::{CallToolResult, InitializeResult, ToolResultContent},
+        ResourceContents,
+    },
+};
+use serde_json::{Map, Value};
+use std::fmt::{self, Write};
+
+/// Default number of characters of a string that are shown
+pub const DEFAULT_MAX_TEXT_LEN: usize = 200;
+
+/// Default size up to which base64 data is shown as is
+pub const DEFAULT_MAX_INLINE_DATA: usize = 64;
+
+/// Formats protocol payloads for humans, see the [module docs](self)
+#[derive(Debug, Clone)]
+pub struct DebugFormatter {
+    max_text_len: usize,
+    max_inline_data: usize,
+    redactor: Option<Redactor>,
+}
+
+impl Default for DebugFormatter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DebugFormatter {
+    /// Create a formatter with the default limits
+    pub fn new() -> Self {
+        Self {
+            max_text_len: DEFAULT_MAX_TEXT_LEN,
+            max_inline_data: DEFAULT_MAX_INLINE_DATA,
+            redactor: None,
+        }
+    }
+
+    /// Set how many characters of a string are shown before it is cut short
+    pub fn with_max_text_len(mut self, max_text_len: usize) -> Self {
+        self.max_text_len = max_text_len;
+        self
+    }
+
+    /// Set the length up to which base64 data is shown instead of its size
+    pub fn with_max_inline_data(mut self, max_inline_data: usize) -> Self {
+        self.max_inline_data = max_inline_data;
+        self
+    }
+
+    /// Mask secrets with `redactor` before formatting
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    /// Copy a JSON value with blobs replaced by a summary and long strings cut short
+    ///
+    /// The `data` of image and audio content and the `blob` of resource
+    /// contents become e.g. `"<base64 image/png, 12KB>"`.
+    pub fn elide(&self, value: &Value) -> Value {
+        match value {
+            Value::String(text) => Value::String(self.truncate(text)),
+            Value::Array(items) => Value::Array(items.iter().map(|v| self.elide(v)).collect()),
+            Value::Object(fields) => {
+                let mime_type = fields.get("mimeType").and_then(Value::as_str);
+                let elided = fields
+                    .iter()
+                    .map(|(key, value)| {
+                        let value = match (key.as_str(), value) {
+                            ("data" | "blob", Value::String(data))
+                                if data.len() > self.max_inline_data =>
+                            {
+                                Value::String(summarize_base64(mime_type, data))
+                            }
+                            _ => self.elide(value),
+                        };
+                        (key.clone(), value)
+                    })
+                    .collect::<Map<_, _>>();
+                Value::Object(elided)
+            }
+            _ => value.clone(),
+        }
+    }
+
+    /// Pretty-print a JSON value after [eliding](Self::elide) it
+    pub fn format_json(&self, value: &Value) -> String {
+        let elided = match &self.redactor {
+            Some(redactor) => self.elide(&redactor.redact_value(value)),
+            None => self.elide(value),
+        };
+        serde_json::to_string_pretty(&elided).unwrap_or_else(|_| elided.to_string())
+    }
+
+    /// Format a frame as read from or written to a transport
+    ///
+    /// Frames that are not JSON are shown as is, cut short. Batches list
+    /// their messages one after the other.
+    pub fn format_frame(&self, frame: &str) -> String {
+        let value: Value = match serde_json::from_str(frame) {
+            Ok(value) => value,
+            Err(e) => {
+                return format!(
+                    "invalid frame ({}): {}",
+                    e,
+                    self.truncate(&self.redact(frame))
+                )
+            }
+        };
+        match value {
+            Value::Array(items) => {
+                let mut out = format!("batch of {}", items.len());
+                for item in &items {
+                    out.push('\n');
+                    out.push_str(&self.format_value(item));
+                }
+                out
+            }
+            value => self.format_value(&value),
+        }
+    }
+
+    /// Format a message with a one-line header followed by its body
+    pub fn format_message(&self, message: &JSONRPCMessage) -> String {
+        // Mask the arguments of tool calls before their fields are formatted one by one
+        let redacted = self.redactor.as_ref().and_then(|redactor| {
+            let value = serde_json::to_value(message).ok()?;
+            serde_json::from_value::<JSONRPCMessage>(redactor.redact_message(&value)).ok()
+        });
+        match redacted.as_ref().unwrap_or(message) {
+            JSONRPCMessage::Request(request) => self.with_header(
+                format!("request {} {}", format_id(&request.id), request.method),
+                request.params.as_ref(),
+            ),
+            JSONRPCMessage::Notification(notification) => self.with_header(
+                format!("notification {}", notification.method),
+                notification.params.as_ref(),
+            ),
+            JSONRPCMessage::Response(response) => self.with_header(
+                format!("response {}", format_id(&response.id)),
+                Some(&response.result),
+            ),
+            JSONRPCMessage::Error(error) => self.with_header(
+                format!(
+                    "error {}: {} {}",
+                    format_id(&error.id),
+                    error.error.code,
+                    self.truncate(&self.redact(&error.error.message))
+                ),
+                error.error.data.as_ref(),
+            ),
+        }
+    }
+
+    /// Format the content blocks of a tool result with their type and size
+    pub fn format_tool_result(&self, result: &CallToolResult) -> String {
+        let mut out = String::new();
+        if result.is_error == Some(true) {
+            out.push_str("tool error\n");
+        }
+        for (index, block) in result.content.iter().enumerate() {
+            let _ = writeln!(out, "[{}] {}", index, self.format_block(block));
+        }
+        if let Some(structured) = &result.structured_content {
+            let _ = writeln!(out, "structured content:\n{}", self.format_json(structured));
+        }
+        out.trim_end().to_string()
+    }
+
+    fn format_block(&self, block: &ToolResultContent) -> String {
+        match block {
+            ToolResultContent::Text(text) => format!(
+                "text, {} chars: {}",
+                text.text.chars().count(),
+                self.truncate(&self.redact(&text.text))
+            ),
+            ToolResultContent::Image(image) => format!(
+                "image {}, {}",
+                image.mime_type,
+                format_size(base64_decoded_len(&image.data))
+            ),
+            ToolResultContent::Audio(audio) => format!(
+                "audio {}, {}",
+                audio.mime_type,
+                format_size(base64_decoded_len(&audio.data))
+            ),
+            ToolResultContent::Resource(embedded) => match &embedded.resource {
+                ResourceContents::Text(text) => format!(
+                    "resource {} {}, {} chars: {}",
+                    text.uri,
+                    text.mime_type.as_deref().unwrap_or("text"),
+                    text.text.chars().count(),
+                    self.truncate(&self.redact(&text.text))
+                ),
+                ResourceContents::Blob(blob) => format!(
+                    "resource {} {}, {}",
+                    blob.uri,
+                    blob.mime_type.as_deref().unwrap_or("binary"),
+                    format_size(base64_decoded_len(&blob.blob))
+                ),
+            },
+        }
+    }
+
+    /// Format a JSON value that should be a message, falling back to plain JSON
+    fn format_value(&self, value: &Value) -> String {
+        match serde_json::from_value::<JSONRPCMessage>(value.clone()) {
+            Ok(message) => self.format_message(&message),
+            Err(_) => format!("unknown message\n{}", self.format_json(value)),
+        }
+    }
+
+    fn with_header(&self, header: String, body: Option<&Value>) -> String {
+        match body {
+            Some(body) if !body.is_null() => format!("{}\n{}", header, self.format_json(body)),
+            _ => header,
+        }
+    }
+
+    fn redact<'a>(&self, text: &'a str) -> std::borrow::Cow<'a, str> {
+        match &self.redactor {
+            Some(redactor) => redactor.redact_text(text),
+            None => text.into(),
+        }
+    }
+
+    fn truncate(&self, text: &str) -> String {
+        match text.char_indices().nth(self.max_text_len) {
+            Some((end, _)) => format!(
+                "{}... ({} more chars)",
+                &text[..end],
+                text[end..].chars().count()
+            ),
+            None => text.to_string(),
+        }
+    }
+}
+
+fn summarize_base64(mime_type: Option<&str>, data: &str) -> String {
+    let size = format_size(base64_decoded_len(data));
+    match mime_type {
+        Some(mime_type) => format!("<base64 {}, {}>", mime_type, size),
+        None => format!("<base64, {}>", size),
+    }
+}
+
+fn format_id(id: &RequestId) -> String {
+    match id {
+        RequestId::String(id) => format!("{:?}", id),
+        RequestId::Number(id) => id.to_string(),
+    }
+}
+
+/// A difference between two JSON values, at a JSON Pointer path
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// Present only in the new value
+    Added { path: String, value: Value },
+    /// Present only in the old value
+    Removed { path: String, value: Value },
+    /// Present in both with different values
+    Changed {
+        path: String,
+        old: Value,
+        new: Value,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added { path, value } => write!(f, "+ {}: {}", path, value),
+            Change::Removed { path, value } => write!(f, "- {}: {}", path, value),
+            Change::Changed { path, old, new } => write!(f, "~ {}: {} -> {}", path, old, new),
+        }
+    }
+}
+
+/// List the differences between two JSON values
+///
+/// Objects are compared field by field; arrays and scalars that differ are
+/// reported as a whole.
+pub fn diff_json(old: &Value, new: &Value) -> Vec<Change> {
+    let mut changes = Vec::new();
+    diff_at(String::new(), old, new, &mut changes);
+    changes
+}
+
+fn diff_at(path: String, old: &Value, new: &Value, changes: &mut Vec<Change>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, old_value) in old {
+                let path = format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+                match new.get(key) {
+                    Some(new_value) => diff_at(path, old_value, new_value, changes),
+                    None => changes.push(Change::Removed {
+                        path,
+                        value: old_value.clone(),
+                    }),
+                }
+            }
+            for (key, new_value) in new {
+                if !old.contains_key(key) {
+                    changes.push(Change::Added {
+                        path: format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1")),
+                        value: new_value.clone(),
+                    });
+                }
+            }
+        }
+        (old, new) if old != new => changes.push(Change::Changed {
+            path,
+            old: old.clone(),
+            new: new.clone(),
+        }),
+        _ => {}
+    }
+}
+
+/// List how two initialize results differ, e.g. before and after a server upgrade
+pub fn diff_initialize(old: &InitializeResult, new: &InitializeResult) -> Vec<Change> {
+    let old = serde_json::to_value(old).unwrap_or_default();
+    let new = serde_json::to_value(new).unwrap_or_default();
+    diff_json(&old, &new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{
+        common::{ImageContent, Implementation},
+        server::{ServerCapabilities, ToolsCapability},
+        TextContent,
+    };
+    use serde_json::json;
+
+    #[test]
+    fn test_format_tool_result() {
+        let result = CallToolResult {
+            content: vec![
+                ToolResultContent::Text(TextContent {
+                    r#type: "text".to_string(),
+                    text: "x".repeat(30),
+                    annotations: None,
+                }),
+                ToolResultContent::Image(ImageContent {
+                    r#type: "image".to_string(),
+                    data: "A".repeat(4096),
+                    mime_type: "image/png".to_string(),
+                    annotations: None,
+                }),
+            ],
+            structured_content: None,
+            is_error: Some(true),
+        };
+        let formatter = DebugFormatter::new().with_max_text_len(10);
+        assert_eq!(
+            formatter.format_tool_result(&result),
+            "tool error\n[0] text, 30 chars: xxxxxxxxxx... (20 more chars)\n[1] image image/png, 3KB"
+        );
+
+        let frame = json!({ "jsonrpc": "2.0", "id": 1, "result": result }).to_string();
+        let formatted = formatter.format_frame(&frame);
+        assert!(formatted.starts_with("response 1\n"));
+        assert!(formatted.contains("\"<base64 image/png, 3KB>\""));
+        assert!(formatter.format_frame("{oops").starts_with("invalid frame"));
+    }
+
+    #[test]
+    fn test_diff_initialize() {
+        let old = InitializeResult {
+            protocol_version: "2024-11-05".to_string(),
+            capabilities: ServerCapabilities::default(),
+            server_info: Implementation {
+                name: "demo".to_string(),
+                version: "1.0.0".to_string(),
+            },
+            instructions: Some("Be nice".to_string()),
+        };
+        let mut new = old.clone();
+        new.server_info.version = "1.1.0".to_string();
+        new.capabilities.tools = Some(ToolsCapability {
+            list_changed: Some(true),
+        });
+        new.instructions = None;
+
+        let changes: Vec<String> = diff_initialize(&old, &new)
+            .iter()
+            .map(Change::to_string)
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                "+ /capabilities/tools: {\"listChanged\":true}",
+                "- /instructions: \"Be nice\"",
+                "~ /serverInfo/version: \"1.0.0\" -> \"1.1.0\"",
+            ]
+        );
+    }
+}
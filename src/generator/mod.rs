@@ -5,6 +5,7 @@ use std::io;
 use std::path::Path;
 
 mod templates;
+pub mod typed_client;
 
 // Import the crate version from the environment
 use crate::VERSION;
@@ -342,7 +342,10 @@ fn main() -> Result<(), Box<dyn Error>> {
                     }))
                 ].into_iter().collect()),
                 required: Some(vec!["name".to_string()]),
+                keywords: serde_json::Map::new(),
             },
+            output_schema: None,
+            annotations: None,
         });
     
     // Create the server
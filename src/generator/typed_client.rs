@@ -0,0 +1,319 @@
+//! Generate a typed Rust client for the tools a server offers
+//!
+//! [`generate_typed_client`] turns a server's `tools/list` into a module with
+//! one argument struct per tool, derived from its `inputSchema`, and a
+//! `ToolsClient` with one async method per tool, so calls are checked at
+//! compile time. `mcpr generate-tools` writes the module for a running
+//! server; build scripts can call the function on tools they fetched:
+//!
+//! ```rust,ignore
+//! let tools: ListToolsResult = client.list_tools().await?;
+//! let code = mcpr::generator::typed_client::generate_typed_client(&tools.tools);
+//! std::fs::write(out_dir.join("tools.rs"), code)?;
+//! ```
+//!
+//! and include it with `include!(concat!(env!("OUT_DIR"), "/tools.rs"));`.
+//!
+//! Properties map to `String`, `i64`, `f64`, `bool`, `Vec` and nested
+//! structs; anything else, such as `anyOf` or an object without properties,
+//! is kept as a `serde_json::Value`. Optional properties become `Option`s.
+
+use crate::schema::common::Tool;
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt::Write};
+
+/// Words that need a raw identifier to be used as a field or method name
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where",
+    "while", "abstract", "become", "box", "do", "final", "gen", "macro", "override", "priv", "try",
+    "typeof", "unsized", "virtual", "yield",
+];
+
+/// Generate the source of a typed client module for `tools`
+pub fn generate_typed_client(tools: &[Tool]) -> String {
+    let mut out = String::new();
+    // A plain comment, so the module can be include!d by a build script
+    out.push_str("// Typed calls to the tools of an MCP server, generated by mcpr\n\n");
+    out.push_str("use mcpr::{\n");
+    out.push_str("    client::Client, error::MCPError, schema::server::CallToolResult,\n");
+    out.push_str("    transport::Transport,\n");
+    out.push_str("};\n");
+    out.push_str("use serde::{Deserialize, Serialize};\n\n");
+
+    let mut structs = Vec::new();
+    let mut methods = String::new();
+    for tool in tools {
+        let args = format!("{}Args", pascal_case(&tool.name));
+        let schema = serde_json::to_value(&tool.input_schema).unwrap_or(Value::Null);
+        let doc = format!("Arguments of the `{}` tool", tool.name);
+        structs.push(struct_definition(&args, &doc, &schema));
+        collect_nested(&args, &schema, &mut structs);
+
+        methods.push('\n');
+        match &tool.description {
+            Some(description) => doc_comment(&mut methods, "    ", description),
+            None => {
+                let _ = writeln!(methods, "    /// Call the `{}` tool", tool.name);
+            }
+        }
+        let _ = writeln!(
+            methods,
+            "    pub async fn {}(&mut self, args: &{}) -> Result<CallToolResult, MCPError> {{",
+            snake_case(&tool.name),
+            args
+        );
+        let _ = writeln!(
+            methods,
+            "        self.client.call_tool({:?}, args).await",
+            tool.name
+        );
+        methods.push_str("    }\n");
+    }
+
+    for definition in structs {
+        out.push_str(&definition);
+        out.push('\n');
+    }
+
+    out.push_str("/// Typed calls to the server's tools\n");
+    out.push_str("pub struct ToolsClient<T: Transport + Send + Sync> {\n");
+    out.push_str("    client: Client<T>,\n");
+    out.push_str("}\n\n");
+    out.push_str("impl<T: Transport + Send + Sync> ToolsClient<T> {\n");
+    out.push_str("    /// Call tools through `client`, which is initialized\n");
+    out.push_str("    pub fn new(client: Client<T>) -> Self {\n");
+    out.push_str("        Self { client }\n");
+    out.push_str("    }\n\n");
+    out.push_str("    /// The client the calls go through\n");
+    out.push_str("    pub fn client(&mut self) -> &mut Client<T> {\n");
+    out.push_str("        &mut self.client\n");
+    out.push_str("    }\n\n");
+    out.push_str("    /// Take back the client\n");
+    out.push_str("    pub fn into_inner(self) -> Client<T> {\n");
+    out.push_str("        self.client\n");
+    out.push_str("    }\n");
+    out.push_str(&methods);
+    out.push_str("}\n");
+    out
+}
+
+/// Define the struct for an object schema, without its nested structs
+fn struct_definition(name: &str, doc: &str, schema: &Value) -> String {
+    let mut out = String::new();
+    doc_comment(&mut out, "", doc);
+    out.push_str("#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]\n");
+    let _ = writeln!(out, "pub struct {} {{", name);
+
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|names| names.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    for (property, property_schema) in properties(schema) {
+        if let Some(description) = property_schema.get("description").and_then(Value::as_str) {
+            doc_comment(&mut out, "    ", description);
+        }
+        let field = snake_case(property);
+        if field.trim_start_matches("r#") != property {
+            let _ = writeln!(out, "    #[serde(rename = {:?})]", property);
+        }
+        let mut ty = rust_type(
+            &format!("{}{}", name, pascal_case(property)),
+            property_schema,
+        );
+        if !required.contains(&property.as_str()) {
+            out.push_str("    #[serde(default, skip_serializing_if = \"Option::is_none\")]\n");
+            if !ty.starts_with("Option<") {
+                ty = format!("Option<{}>", ty);
+            }
+        }
+        let _ = writeln!(out, "    pub {}: {},", field, ty);
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Define the structs of the object properties of a schema, recursively
+fn collect_nested(name: &str, schema: &Value, structs: &mut Vec<String>) {
+    for (property, property_schema) in properties(schema) {
+        let nested = format!("{}{}", name, pascal_case(property));
+        let mut schema = property_schema;
+        // The structs of array items are named after the array
+        while schema_type(schema) == Some("array") {
+            match schema.get("items") {
+                Some(items) => schema = items,
+                None => break,
+            }
+        }
+        if schema_type(schema) == Some("object") && schema.get("properties").is_some() {
+            let doc = format!("The `{}` argument", property);
+            structs.push(struct_definition(&nested, &doc, schema));
+            collect_nested(&nested, schema, structs);
+        }
+    }
+}
+
+/// The properties of an object schema, by name
+fn properties(schema: &Value) -> BTreeMap<&String, &Value> {
+    schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .map(|properties| properties.iter().collect())
+        .unwrap_or_default()
+}
+
+/// The type a schema declares, ignoring `null` in a list of types
+fn schema_type(schema: &Value) -> Option<&str> {
+    match schema.get("type")? {
+        Value::String(name) => Some(name),
+        Value::Array(names) => {
+            let mut names = names
+                .iter()
+                .filter_map(Value::as_str)
+                .filter(|n| *n != "null");
+            let name = names.next()?;
+            names.next().is_none().then_some(name)
+        }
+        _ => None,
+    }
+}
+
+/// The Rust type of a value matching `schema`; `name` is used for structs
+fn rust_type(name: &str, schema: &Value) -> String {
+    let nullable = matches!(schema.get("type"), Some(Value::Array(names))
+        if names.iter().any(|n| n == "null"));
+    let ty = match schema_type(schema) {
+        Some("string") => "String".to_string(),
+        Some("integer") => "i64".to_string(),
+        Some("number") => "f64".to_string(),
+        Some("boolean") => "bool".to_string(),
+        Some("array") => match schema.get("items") {
+            Some(items) => format!("Vec<{}>", rust_type(name, items)),
+            None => "Vec<serde_json::Value>".to_string(),
+        },
+        Some("object") if schema.get("properties").is_some() => name.to_string(),
+        _ => "serde_json::Value".to_string(),
+    };
+    if nullable {
+        format!("Option<{}>", ty)
+    } else {
+        ty
+    }
+}
+
+/// Write `text` as a doc comment, line by line
+fn doc_comment(out: &mut String, indent: &str, text: &str) {
+    for line in text.trim().lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            let _ = writeln!(out, "{}///", indent);
+        } else {
+            let _ = writeln!(out, "{}/// {}", indent, line);
+        }
+    }
+}
+
+/// Split a name into words at separators and lower-to-upper case changes
+fn words(name: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut previous_lower = false;
+    for c in name.chars() {
+        if !c.is_ascii_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            previous_lower = false;
+            continue;
+        }
+        if c.is_ascii_uppercase() && previous_lower {
+            words.push(std::mem::take(&mut word));
+        }
+        previous_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// A field or method name for `name`, such as `get_weather` for `getWeather`
+fn snake_case(name: &str) -> String {
+    let words: Vec<String> = words(name).iter().map(|w| w.to_ascii_lowercase()).collect();
+    let mut ident = words.join("_");
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+    if KEYWORDS.contains(&ident.as_str()) {
+        ident.insert_str(0, "r#");
+    }
+    ident
+}
+
+/// A type name for `name`, such as `GetWeather` for `get-weather`
+fn pascal_case(name: &str) -> String {
+    let mut ident: String = words(name)
+        .iter()
+        .map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().map(|c| c.to_ascii_uppercase());
+            first.into_iter().chain(chars).collect::<String>()
+        })
+        .collect();
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, 'T');
+    }
+    ident
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::common::ToolInputSchema;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_generate_typed_client() {
+        let tool = Tool {
+            name: "get-weather".to_string(),
+            description: Some("Current weather in a city".to_string()),
+            input_schema: ToolInputSchema {
+                r#type: "object".to_string(),
+                properties: Some(HashMap::from([
+                    ("city".to_string(), serde_json::json!({ "type": "string" })),
+                    ("type".to_string(), serde_json::json!({ "type": "string" })),
+                    (
+                        "maxDays".to_string(),
+                        serde_json::json!({ "type": ["integer", "null"] }),
+                    ),
+                    (
+                        "location".to_string(),
+                        serde_json::json!({
+                            "type": "object",
+                            "properties": { "lat": { "type": "number" } },
+                            "required": ["lat"],
+                        }),
+                    ),
+                ])),
+                required: Some(vec!["city".to_string()]),
+                keywords: serde_json::Map::new(),
+            },
+            output_schema: None,
+            annotations: None,
+        };
+        let code = generate_typed_client(&[tool]);
+
+        assert!(code.contains("pub struct GetWeatherArgs {"));
+        assert!(code.contains("    pub city: String,"));
+        assert!(code.contains("    pub r#type: Option<String>,"));
+        assert!(code.contains("    #[serde(rename = \"maxDays\")]\n"));
+        assert!(code.contains("    pub max_days: Option<i64>,"));
+        assert!(code.contains("    pub location: Option<GetWeatherArgsLocation>,"));
+        assert!(code.contains("pub struct GetWeatherArgsLocation {\n    pub lat: f64,"));
+        assert!(code.contains("    /// Current weather in a city\n    pub async fn get_weather("));
+        assert!(code.contains("self.client.call_tool(\"get-weather\", args).await"));
+    }
+}
@@ -78,7 +78,10 @@
 //!                     }))
 //!                 ].into_iter().collect()),
 //!                 required: Some(vec!["param1".to_string(), "param2".to_string()]),
+//!                 keywords: serde_json::Map::new(),
 //!             },
+//!             output_schema: None,
+//!             annotations: None,
 //!         });
 //!
 //!     // Create the server
@@ -108,31 +111,98 @@
 //!     server.serve(transport).await
 //! }
 //! ```
+//!
+//! ## Runtimes
+//!
+//! The server, the transports over processes, sockets and HTTP,
+//! authorization, and the generator run on tokio, and come with the
+//! default `runtime-tokio` feature. The `mcpr` binary and its interactive
+//! shell, in `cli`, come with the default `cli` feature. Without it, the protocol types, the
+//! client, and the in-memory and middleware transports remain: they only
+//! use tokio's runtime-independent synchronization, so the client runs on
+//! any executor, such as async-std or smol, over a [`transport::Transport`]
+//! written for it. Timeouts then use a timer thread; see [`clock`].
+//!
+//! ## WebAssembly
+//!
+//! The client also builds for `wasm32-unknown-unknown`, for MCP hosts that
+//! run in the browser, with `default-features = false`. It connects with the
+//! transports in `transport::browser`, over `fetch` or a browser WebSocket.
 
 /// Current version of the MCPR crate
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+#[cfg(feature = "runtime-tokio")]
+pub mod aggregator;
+#[cfg(feature = "anthropic")]
+pub mod anthropic;
+pub mod audit;
+#[cfg(feature = "runtime-tokio")]
+pub mod auth;
+#[cfg(feature = "runtime-tokio")]
+pub mod blocking;
+#[cfg(feature = "runtime-tokio")]
+pub mod bridge;
+pub mod cache;
+pub mod catalog;
+#[cfg(feature = "cli")]
 pub mod cli;
 pub mod client;
+pub mod clock;
+pub mod conformance;
+pub mod debug;
+#[cfg(feature = "runtime-tokio")]
 pub mod generator;
+pub mod metrics;
+#[cfg(feature = "openai")]
+pub mod openai;
+#[cfg(feature = "runtime-tokio")]
+pub mod pool;
+#[cfg(feature = "runtime-tokio")]
+pub mod proxy;
+pub mod redact;
+mod rt;
+pub mod sampling;
 pub mod schema;
+#[cfg(feature = "runtime-tokio")]
 pub mod server;
+#[cfg(all(any(test, feature = "test-util"), feature = "runtime-tokio"))]
+pub mod testing;
+mod trace;
 pub mod transport;
 
 // Re-export commonly used types
 pub use schema::common::{Cursor, LoggingLevel, ProgressToken, Tool};
 pub use schema::json_rpc::{JSONRPCMessage, RequestId};
 
+#[cfg(feature = "macros")]
+pub use mcpr_macros::{mcp_tool, ToolInput};
+
+// Used by the code the macros generate
+#[doc(hidden)]
+pub mod __private {
+    pub use async_trait::async_trait;
+    pub use serde_json;
+}
+
 /// Protocol version constants
 pub mod constants {
     /// The latest supported MCP protocol version
     pub const LATEST_PROTOCOL_VERSION: &str = "2024-11-05";
+    /// The MCP protocol versions this crate can speak, oldest first
+    pub const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &["2024-11-05", "2025-03-26"];
     /// The JSON-RPC version used by MCP
     pub const JSONRPC_VERSION: &str = "2.0";
+    /// Experimental client capability listing the content types the client can render
+    pub const ACCEPTED_CONTENT_TYPES_CAPABILITY: &str = "acceptedContentTypes";
+    /// Experimental server capability advertising the chunked-upload convention
+    /// and the `maxMessageSize` above which requests should be chunked
+    pub const CHUNKED_UPLOAD_CAPABILITY: &str = "chunkedUpload";
 }
 
 /// Error types for the MCP implementation
 pub mod error {
+    use serde_json::Value;
     use thiserror::Error;
 
     #[derive(Error, Debug)]
@@ -151,5 +221,263 @@ pub mod error {
 
         #[error("Timeout error: {0}")]
         Timeout(String),
+
+        #[error("Connection closed")]
+        ConnectionClosed,
+
+        /// A message over the transport's size limit, which is not buffered
+        #[error("Message larger than the limit of {limit} bytes")]
+        MessageTooLarge { limit: usize },
+
+        /// The server refused the credentials, or there were none to send
+        #[error("Unauthorized: {0}")]
+        Unauthorized(String),
+
+        /// The server chose a protocol version the client does not support
+        #[error("Unsupported protocol version {offered} (requested {requested})")]
+        UnsupportedProtocolVersion { requested: String, offered: String },
+
+        /// A request for a feature the server did not advertise during initialization
+        #[error("Server does not support '{method}': it did not advertise the '{capability}' capability")]
+        CapabilityNotSupported { method: String, capability: String },
+
+        /// A tool call that the tool reported as failed, with its content
+        #[error("Tool '{tool}' failed: {message}")]
+        ToolError {
+            tool: String,
+            message: String,
+            content: Vec<crate::schema::server::ToolResultContent>,
+        },
+
+        /// A tool call the client's [`ToolCallPolicy`](crate::client::ToolCallPolicy) refused
+        #[error("Call of tool '{tool}' denied: {reason}")]
+        ToolCallDenied { tool: String, reason: String },
+
+        /// Tool arguments that do not match the tool's `inputSchema`
+        #[error("Invalid arguments for tool '{tool}' at {path}: {message}")]
+        InvalidArguments {
+            tool: String,
+            /// JSON Pointer to the failing argument, `/` for the arguments themselves
+            path: String,
+            message: String,
+        },
+
+        /// A server manifest that could not be read or does not hold together
+        #[error("Invalid manifest '{path}': {message}")]
+        InvalidManifest { path: String, message: String },
+
+        /// An error response from the other side, with its JSON-RPC code
+        #[error("JSON-RPC error {code}: {message}")]
+        Rpc {
+            code: i32,
+            message: String,
+            data: Option<Value>,
+        },
+
+        /// A request that was retried, with the error of its last attempt
+        #[error("Failed after {attempts} attempts: {source}")]
+        RetriesExhausted {
+            attempts: u32,
+            source: Box<MCPError>,
+        },
+    }
+
+    /// Broad category of an [`MCPError`], see [`MCPError::kind`]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum ErrorKind {
+        /// The connection failed, closed or did not answer in time
+        Transport,
+        /// A message could not be encoded or decoded
+        Serialization,
+        /// The other side broke the protocol or lacks a feature
+        Protocol,
+        /// The other side answered with a JSON-RPC error
+        Rpc,
+        /// A tool reported that it failed, was called with invalid arguments,
+        /// or the call was denied
+        Tool,
+        /// The local setup, such as a server manifest, is invalid
+        Config,
+    }
+
+    impl MCPError {
+        /// Lowest code of the range JSON-RPC reserves for predefined errors
+        pub const RESERVED_CODE_MIN: i32 = -32768;
+        /// Highest code of the range JSON-RPC reserves for predefined errors
+        pub const RESERVED_CODE_MAX: i32 = -32000;
+
+        /// Create an application error that is sent to the client with exactly this code
+        ///
+        /// Handlers return it to give a domain error a stable code that clients
+        /// can match on; clients receive it as [`MCPError::Rpc`]. Fails with
+        /// [`MCPError::Protocol`] for a code in the range reserved by JSON-RPC,
+        /// -32768 to -32000.
+        pub fn try_custom(
+            code: i32,
+            message: impl Into<String>,
+            data: Option<Value>,
+        ) -> Result<Self, MCPError> {
+            let message = message.into();
+            if !Self::is_application_code(code) {
+                return Err(MCPError::Protocol(format!(
+                    "JSON-RPC error code {} is reserved, so the error '{}' cannot use it",
+                    code, message
+                )));
+            }
+            Ok(MCPError::Rpc {
+                code,
+                message,
+                data,
+            })
+        }
+
+        /// [`MCPError::try_custom`], for codes known to be outside the reserved range
+        ///
+        /// For a reserved code this is the error `try_custom` fails with, so a
+        /// handler returning it fails like any other, with a message naming the code.
+        pub fn custom(code: i32, message: impl Into<String>, data: Option<Value>) -> Self {
+            Self::try_custom(code, message, data).unwrap_or_else(|e| e)
+        }
+
+        /// A copy of the error, for handing one failure to several callers
+        ///
+        /// A serialization error keeps its message, but not its category.
+        pub(crate) fn duplicate(&self) -> MCPError {
+            match self {
+                MCPError::Serialization(e) => {
+                    MCPError::Serialization(serde::de::Error::custom(e.to_string()))
+                }
+                MCPError::Transport(message) => MCPError::Transport(message.clone()),
+                MCPError::Protocol(message) => MCPError::Protocol(message.clone()),
+                MCPError::UnsupportedFeature(message) => {
+                    MCPError::UnsupportedFeature(message.clone())
+                }
+                MCPError::Timeout(message) => MCPError::Timeout(message.clone()),
+                MCPError::ConnectionClosed => MCPError::ConnectionClosed,
+                MCPError::MessageTooLarge { limit } => MCPError::MessageTooLarge { limit: *limit },
+                MCPError::Unauthorized(message) => MCPError::Unauthorized(message.clone()),
+                MCPError::UnsupportedProtocolVersion { requested, offered } => {
+                    MCPError::UnsupportedProtocolVersion {
+                        requested: requested.clone(),
+                        offered: offered.clone(),
+                    }
+                }
+                MCPError::CapabilityNotSupported { method, capability } => {
+                    MCPError::CapabilityNotSupported {
+                        method: method.clone(),
+                        capability: capability.clone(),
+                    }
+                }
+                MCPError::ToolError {
+                    tool,
+                    message,
+                    content,
+                } => MCPError::ToolError {
+                    tool: tool.clone(),
+                    message: message.clone(),
+                    content: content.clone(),
+                },
+                MCPError::ToolCallDenied { tool, reason } => MCPError::ToolCallDenied {
+                    tool: tool.clone(),
+                    reason: reason.clone(),
+                },
+                MCPError::InvalidArguments {
+                    tool,
+                    path,
+                    message,
+                } => MCPError::InvalidArguments {
+                    tool: tool.clone(),
+                    path: path.clone(),
+                    message: message.clone(),
+                },
+                MCPError::InvalidManifest { path, message } => MCPError::InvalidManifest {
+                    path: path.clone(),
+                    message: message.clone(),
+                },
+                MCPError::Rpc {
+                    code,
+                    message,
+                    data,
+                } => MCPError::Rpc {
+                    code: *code,
+                    message: message.clone(),
+                    data: data.clone(),
+                },
+                MCPError::RetriesExhausted { attempts, source } => MCPError::RetriesExhausted {
+                    attempts: *attempts,
+                    source: Box::new(source.duplicate()),
+                },
+            }
+        }
+
+        /// Whether `code` may be used for application errors
+        pub fn is_application_code(code: i32) -> bool {
+            !(Self::RESERVED_CODE_MIN..=Self::RESERVED_CODE_MAX).contains(&code)
+        }
+
+        /// Category of the error
+        pub fn kind(&self) -> ErrorKind {
+            match self {
+                MCPError::Transport(_)
+                | MCPError::Timeout(_)
+                | MCPError::ConnectionClosed
+                | MCPError::MessageTooLarge { .. }
+                | MCPError::Unauthorized(_) => ErrorKind::Transport,
+                MCPError::Serialization(_) => ErrorKind::Serialization,
+                MCPError::Protocol(_)
+                | MCPError::UnsupportedFeature(_)
+                | MCPError::CapabilityNotSupported { .. }
+                | MCPError::UnsupportedProtocolVersion { .. } => ErrorKind::Protocol,
+                MCPError::Rpc { .. } => ErrorKind::Rpc,
+                MCPError::ToolError { .. }
+                | MCPError::ToolCallDenied { .. }
+                | MCPError::InvalidArguments { .. } => ErrorKind::Tool,
+                MCPError::InvalidManifest { .. } => ErrorKind::Config,
+                MCPError::RetriesExhausted { source, .. } => source.kind(),
+            }
+        }
+
+        /// JSON-RPC code of an error response
+        pub fn code(&self) -> Option<i32> {
+            match self {
+                MCPError::Rpc { code, .. } => Some(*code),
+                MCPError::RetriesExhausted { source, .. } => source.code(),
+                _ => None,
+            }
+        }
+
+        /// `data` of an error response
+        pub fn data(&self) -> Option<&Value> {
+            match self {
+                MCPError::Rpc { data, .. } => data.as_ref(),
+                MCPError::RetriesExhausted { source, .. } => source.data(),
+                _ => None,
+            }
+        }
+
+        /// Whether the other side does not know the method
+        pub fn is_method_not_found(&self) -> bool {
+            self.code() == Some(crate::schema::json_rpc::error_codes::METHOD_NOT_FOUND)
+        }
+
+        /// Whether the other side rejected the parameters
+        pub fn is_invalid_params(&self) -> bool {
+            matches!(self, MCPError::InvalidArguments { .. })
+                || self.code() == Some(crate::schema::json_rpc::error_codes::INVALID_PARAMS)
+        }
+
+        /// Whether the same request may succeed if sent again later
+        ///
+        /// True for transport failures and timeouts, and for error responses
+        /// with the rate-limited code, whatever other errors carry in `data`. An internal error of the other side is
+        /// not, as it may well fail the same way again.
+        pub fn retryable(&self) -> bool {
+            match self {
+                MCPError::Rpc { code, .. } => *code == crate::client::RATE_LIMITED,
+                MCPError::Unauthorized(_) | MCPError::MessageTooLarge { .. } => false,
+                MCPError::RetriesExhausted { source, .. } => source.retryable(),
+                e => e.kind() == ErrorKind::Transport,
+            }
+        }
     }
 }
@@ -1,16 +1,20 @@
 //! MCP CLI tool for generating server and client stubs
 
-use clap::{Parser, Subcommand};
-use env_logger;
+use clap::{Args, Parser, Subcommand};
 use log::{error, info, warn};
 use mcpr::{
+    cli::repl::{self, Repl},
     client::Client,
+    conformance,
     error::MCPError,
+    generator::typed_client::generate_typed_client,
+    schema::{client::ListToolsResult, server::RenderOptions},
     transport::{
-        sse::SSETransport, stdio::StdioTransport, websocket::WebSocketTransport, Transport,
+        sse::SSETransport, stdio::StdioTransport, streamable_http::StreamableHttpTransport,
+        websocket::WebSocketTransport, Transport,
     },
 };
-use std::path::PathBuf;
+use std::{io::IsTerminal, path::PathBuf};
 
 /// MCP CLI tool for generating server and client stubs
 #[derive(Parser)]
@@ -59,6 +63,21 @@ enum Commands {
         transport: String,
     },
 
+    /// Generate a typed client module for the tools of a running server
+    GenerateTools {
+        /// URI of the server, or the command starting it for stdio
+        #[arg(short, long)]
+        uri: String,
+
+        /// Transport type to use (stdio, sse, websocket)
+        #[arg(short, long, default_value = "stdio")]
+        transport: String,
+
+        /// File to write the module to
+        #[arg(short, long, default_value = "tools.rs")]
+        output: String,
+    },
+
     /// Run a server
     RunServer {
         /// Port to listen on
@@ -107,6 +126,91 @@ enum Commands {
         #[arg(short, long)]
         path: String,
     },
+
+    /// Open an interactive shell on a server, with Tab completion of tool names
+    Repl {
+        #[command(flatten)]
+        server: ServerArgs,
+    },
+
+    /// List or call the tools of a server
+    Tools {
+        #[command(subcommand)]
+        command: ToolsCommand,
+    },
+
+    /// List or read the resources of a server
+    Resources {
+        #[command(subcommand)]
+        command: ResourcesCommand,
+    },
+
+    /// Check a server against the specification and report each check
+    Conformance {
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+
+        #[command(flatten)]
+        server: ServerArgs,
+    },
+}
+
+#[derive(Subcommand)]
+enum ToolsCommand {
+    /// List the tools
+    List {
+        #[command(flatten)]
+        server: ServerArgs,
+    },
+
+    /// Call a tool and print its result
+    Call {
+        /// Name of the tool
+        name: String,
+
+        /// Arguments of the call, as a JSON object
+        #[arg(short, long, default_value = "{}")]
+        args: String,
+
+        #[command(flatten)]
+        server: ServerArgs,
+    },
+}
+
+#[derive(Subcommand)]
+enum ResourcesCommand {
+    /// List the resources
+    List {
+        #[command(flatten)]
+        server: ServerArgs,
+    },
+
+    /// Read a resource and print its contents
+    Read {
+        /// URI of the resource
+        uri: String,
+
+        #[command(flatten)]
+        server: ServerArgs,
+    },
+}
+
+/// The server to connect to, exactly one of which is given
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct ServerArgs {
+    /// Command starting a server to talk to over stdio
+    #[arg(long)]
+    stdio: Option<String>,
+
+    /// URL of a server with the SSE transport
+    #[arg(long)]
+    sse: Option<String>,
+
+    /// URL of a server with the Streamable HTTP transport
+    #[arg(long)]
+    http: Option<String>,
 }
 
 /// Connect command parameters
@@ -172,6 +276,14 @@ async fn main() -> Result<(), MCPError> {
                 "Project generation not yet implemented".to_string(),
             ))
         }
+        Commands::GenerateTools {
+            uri,
+            transport,
+            output,
+        } => {
+            info!("Generating typed client for '{}' to '{}'", uri, output);
+            generate_tools(&uri, &transport, &output).await
+        }
         Commands::RunServer {
             port,
             transport,
@@ -203,9 +315,117 @@ async fn main() -> Result<(), MCPError> {
                 "Message validation not yet implemented".to_string(),
             ))
         }
+        Commands::Repl { server } => open_session(&server, None).await,
+        Commands::Tools { command } => match command {
+            ToolsCommand::List { server } => {
+                open_session(&server, Some(repl::Command::ListTools)).await
+            }
+            ToolsCommand::Call { name, args, server } => {
+                let arguments = serde_json::from_str(&args)?;
+                open_session(&server, Some(repl::Command::CallTool { name, arguments })).await
+            }
+        },
+        Commands::Resources { command } => match command {
+            ResourcesCommand::List { server } => {
+                open_session(&server, Some(repl::Command::ListResources)).await
+            }
+            ResourcesCommand::Read { uri, server } => {
+                open_session(&server, Some(repl::Command::ReadResource { uri })).await
+            }
+        },
+        Commands::Conformance { json, server } => check_conformance(&server, json).await,
+    }
+}
+
+/// Start a server from a command line, to talk to over stdio
+fn spawn_stdio(command_line: &str) -> Result<StdioTransport, MCPError> {
+    let mut parts = command_line.split_whitespace();
+    let command = parts
+        .next()
+        .ok_or_else(|| MCPError::Transport("Missing server command".to_string()))?;
+    StdioTransport::spawn(command, parts)
+}
+
+/// Connect to a server and run `command`, or the shell when there is none
+async fn open_session(server: &ServerArgs, command: Option<repl::Command>) -> Result<(), MCPError> {
+    if let Some(command_line) = &server.stdio {
+        run_session(Client::new(spawn_stdio(command_line)?), command).await
+    } else if let Some(url) = &server.sse {
+        run_session(Client::new(SSETransport::new(url)), command).await
+    } else if let Some(url) = &server.http {
+        run_session(Client::new(StreamableHttpTransport::new(url)), command).await
+    } else {
+        Err(MCPError::Transport("No server given".to_string()))
+    }
+}
+
+async fn run_session<T: Transport + Send + Sync>(
+    client: Client<T>,
+    command: Option<repl::Command>,
+) -> Result<(), MCPError> {
+    let options = RenderOptions::new().with_color(std::io::stdout().is_terminal());
+    let mut repl = Repl::connect(client).await?.with_render_options(options);
+    match command {
+        Some(command) => {
+            let result = repl.execute(command, &mut std::io::stdout()).await;
+            repl.shutdown().await?;
+            result.map(|_| ())
+        }
+        None => repl.run().await,
     }
 }
 
+/// Run the conformance checks against a server and print the report
+async fn check_conformance(server: &ServerArgs, json: bool) -> Result<(), MCPError> {
+    let report = if let Some(command_line) = &server.stdio {
+        conformance::run(&mut Client::new(spawn_stdio(command_line)?)).await
+    } else if let Some(url) = &server.sse {
+        conformance::run(&mut Client::new(SSETransport::new(url))).await
+    } else if let Some(url) = &server.http {
+        conformance::run(&mut Client::new(StreamableHttpTransport::new(url))).await
+    } else {
+        return Err(MCPError::Transport("No server given".to_string()));
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report.to_json())?);
+    } else {
+        println!("{}", report);
+    }
+    if !report.passed() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Connect to a server and write a typed client module for its tools
+async fn generate_tools(uri: &str, transport_type: &str, output: &str) -> Result<(), MCPError> {
+    match transport_type {
+        "sse" => write_typed_client(Client::new(SSETransport::new(uri)), output).await,
+        "websocket" => write_typed_client(Client::new(WebSocketTransport::new(uri)), output).await,
+        "stdio" => write_typed_client(Client::new(spawn_stdio(uri)?), output).await,
+        _ => Err(MCPError::Transport(format!(
+            "Unsupported transport type: {}",
+            transport_type
+        ))),
+    }
+}
+
+async fn write_typed_client<T: Transport + Send + Sync>(
+    mut client: Client<T>,
+    output: &str,
+) -> Result<(), MCPError> {
+    client.initialize().await?;
+    let tools: ListToolsResult = client.list_tools().await?;
+    client.shutdown().await?;
+
+    let code = generate_typed_client(&tools.tools);
+    std::fs::write(output, code)
+        .map_err(|e| MCPError::Transport(format!("Failed to write '{}': {}", output, e)))?;
+    info!("Wrote {} tools to '{}'", tools.tools.len(), output);
+    Ok(())
+}
+
 /// Run the server with the specified configuration
 async fn run_server(port: u16, transport_type: &str, debug: bool) -> Result<(), MCPError> {
     info!(
@@ -323,22 +543,15 @@ async fn handle_client_session<T: Transport + Send + Sync>(
         }
     };
 
-    // Handle requested operations
-    match cmd.operation.as_deref() {
-        Some("interactive") => {
-            info!("Starting interactive session");
-            // Interactive session logic here
-            Err(MCPError::UnsupportedFeature(
-                "Interactive session not implemented yet".to_string(),
-            ))
-        }
-        Some("repl") => {
-            info!("Starting REPL");
-            // REPL session logic here
-            Err(MCPError::UnsupportedFeature(
-                "REPL not implemented yet".to_string(),
-            ))
-        }
+    // Handle requested operations; --interactive is the interactive operation
+    let operation = match cmd.interactive {
+        true => Some("interactive"),
+        false => cmd.operation.as_deref(),
+    };
+    match operation {
+        Some("interactive") | Some("repl") => Err(MCPError::UnsupportedFeature(
+            "Use `mcpr repl` for an interactive session".to_string(),
+        )),
         Some(tool_name) => {
             info!("Calling tool: {}", tool_name);
 
@@ -366,8 +579,8 @@ async fn handle_client_session<T: Transport + Send + Sync>(
             // Default to the hello tool if no operation is specified
             info!("No operation specified, using hello tool");
 
-            // Use empty params for hello
-            let params = serde_json::Value::Null;
+            // Greet the name given with --name
+            let params = serde_json::json!({ "name": cmd.name });
 
             // Call the hello tool
             let response: serde_json::Value = client.call_tool("hello", &params).await?;
@@ -0,0 +1,338 @@
+//! Metrics for requests and traffic, for export to Prometheus and the like
+//!
+//! A [`Metrics`] implementation receives the latency and outcome of every
+//! request and the size of every message. [`MetricsLayer`] reports them for
+//! a transport: wrap the transport of a client or a server in a
+//! [`MiddlewareTransport`](crate::transport::middleware::MiddlewareTransport)
+//! with the layer, and it times the requests that side sends as well as the
+//! ones it answers. [`InMemoryMetrics`] keeps counters and latency histograms
+//! and renders them in the Prometheus text format:
+//!
+//! ```rust,no_run
+//! use mcpr::{
+//!     client::Client,
+//!     metrics::{InMemoryMetrics, MetricsLayer},
+//!     transport::{middleware::MiddlewareTransport, stdio::StdioTransport},
+//! };
+//! use std::sync::Arc;
+//!
+//! let metrics = Arc::new(InMemoryMetrics::new());
+//! let transport = MiddlewareTransport::new(StdioTransport::new())
+//!     .with_layer(MetricsLayer::new(metrics.clone()));
+//! let client = Client::new(transport);
+//! // ...
+//! println!("{}", metrics.to_prometheus());
+//! ```
+
+use crate::{
+    clock::Instant, error::MCPError, schema::json_rpc::RequestId, transport::middleware::Middleware,
+};
+use async_trait::async_trait;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Upper bounds of the latency histogram buckets, in seconds
+pub const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Which side of the connection sent a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Origin {
+    /// Sent by this side, as a client's calls are
+    Local,
+    /// Sent by the other side and answered here, as a server's are
+    Remote,
+}
+
+impl Origin {
+    fn as_str(self) -> &'static str {
+        match self {
+            Origin::Local => "local",
+            Origin::Remote => "remote",
+        }
+    }
+}
+
+/// Receives metrics; every method does nothing by default
+pub trait Metrics: Send + Sync + 'static {
+    /// A request was answered after `latency`, with an error response if `error`
+    fn request(&self, _origin: Origin, _method: &str, _latency: Duration, _error: bool) {}
+
+    /// A message of `bytes` bytes, serialized, was sent
+    fn bytes_sent(&self, _bytes: u64) {}
+
+    /// A message of `bytes` bytes, serialized, was received
+    fn bytes_received(&self, _bytes: u64) {}
+}
+
+impl<M: Metrics> Metrics for Arc<M> {
+    fn request(&self, origin: Origin, method: &str, latency: Duration, error: bool) {
+        (**self).request(origin, method, latency, error)
+    }
+
+    fn bytes_sent(&self, bytes: u64) {
+        (**self).bytes_sent(bytes)
+    }
+
+    fn bytes_received(&self, bytes: u64) {
+        (**self).bytes_received(bytes)
+    }
+}
+
+/// Transport middleware reporting the requests and messages it sees
+pub struct MetricsLayer {
+    metrics: Box<dyn Metrics>,
+    /// Method and start of the requests waiting for a response
+    pending: Mutex<HashMap<(Origin, RequestId), (String, Instant)>>,
+}
+
+impl MetricsLayer {
+    /// Report to `metrics`
+    pub fn new(metrics: impl Metrics) -> Self {
+        Self {
+            metrics: Box::new(metrics),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Time a request, or report the request a response answers
+    fn observe(&self, message: &Value, origin: Origin) {
+        if let Value::Array(batch) = message {
+            batch
+                .iter()
+                .for_each(|message| self.observe(message, origin));
+            return;
+        }
+        let Some(id) = message
+            .get("id")
+            .and_then(|id| serde_json::from_value::<RequestId>(id.clone()).ok())
+        else {
+            return;
+        };
+
+        if let Some(method) = message.get("method").and_then(Value::as_str) {
+            self.pending
+                .lock()
+                .unwrap()
+                .insert((origin, id), (method.to_string(), Instant::now()));
+            return;
+        }
+
+        // A response travels the other way from its request
+        let request_origin = match origin {
+            Origin::Local => Origin::Remote,
+            Origin::Remote => Origin::Local,
+        };
+        let started = self.pending.lock().unwrap().remove(&(request_origin, id));
+        if let Some((method, started)) = started {
+            let error = message.get("error").is_some();
+            self.metrics
+                .request(request_origin, &method, started.elapsed(), error);
+        }
+    }
+}
+
+#[async_trait]
+impl Middleware for MetricsLayer {
+    async fn on_send(&self, message: &mut Value) -> Result<(), MCPError> {
+        self.metrics.bytes_sent(message_size(message));
+        self.observe(message, Origin::Local);
+        Ok(())
+    }
+
+    async fn on_receive(&self, message: &mut Value) -> Result<(), MCPError> {
+        self.metrics.bytes_received(message_size(message));
+        self.observe(message, Origin::Remote);
+        Ok(())
+    }
+}
+
+/// Serialized size of a message, excluding transport framing
+fn message_size(message: &Value) -> u64 {
+    serde_json::to_vec(message).map_or(0, |bytes| bytes.len() as u64)
+}
+
+/// Counters and latency histogram of the requests of one method
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MethodMetrics {
+    /// Requests answered
+    pub count: u64,
+    /// Requests answered with an error response
+    pub errors: u64,
+    /// Requests per bucket of [`LATENCY_BUCKETS`], not cumulative; the last
+    /// entry counts those slower than every bound
+    pub latency_buckets: Vec<u64>,
+    /// Sum of the latencies
+    pub latency_sum: Duration,
+}
+
+/// Metrics collected by [`InMemoryMetrics`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetricsSnapshot {
+    /// Metrics of each method, by origin of the requests
+    pub methods: BTreeMap<(Origin, String), MethodMetrics>,
+    /// Serialized size of the messages sent
+    pub bytes_sent: u64,
+    /// Serialized size of the messages received
+    pub bytes_received: u64,
+}
+
+/// Keeps metrics in memory, for reading or exporting them
+#[derive(Debug, Default)]
+pub struct InMemoryMetrics {
+    snapshot: Mutex<MetricsSnapshot>,
+}
+
+impl InMemoryMetrics {
+    /// Start with empty metrics
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The metrics collected so far
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.snapshot.lock().unwrap().clone()
+    }
+
+    /// Render the metrics in the Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let snapshot = self.snapshot();
+        let mut out = String::new();
+
+        out.push_str("# TYPE mcp_requests_total counter\n");
+        for ((origin, method), metrics) in &snapshot.methods {
+            let _ = writeln!(
+                out,
+                "mcp_requests_total{{origin=\"{}\",method=\"{}\"}} {}",
+                origin.as_str(),
+                method,
+                metrics.count
+            );
+        }
+        out.push_str("# TYPE mcp_request_errors_total counter\n");
+        for ((origin, method), metrics) in &snapshot.methods {
+            let _ = writeln!(
+                out,
+                "mcp_request_errors_total{{origin=\"{}\",method=\"{}\"}} {}",
+                origin.as_str(),
+                method,
+                metrics.errors
+            );
+        }
+        out.push_str("# TYPE mcp_request_duration_seconds histogram\n");
+        for ((origin, method), metrics) in &snapshot.methods {
+            let labels = format!("origin=\"{}\",method=\"{}\"", origin.as_str(), method);
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(&metrics.latency_buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "mcp_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "mcp_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, metrics.count
+            );
+            let _ = writeln!(
+                out,
+                "mcp_request_duration_seconds_sum{{{}}} {}",
+                labels,
+                metrics.latency_sum.as_secs_f64()
+            );
+            let _ = writeln!(
+                out,
+                "mcp_request_duration_seconds_count{{{}}} {}",
+                labels, metrics.count
+            );
+        }
+        out.push_str("# TYPE mcp_bytes_sent_total counter\n");
+        let _ = writeln!(out, "mcp_bytes_sent_total {}", snapshot.bytes_sent);
+        out.push_str("# TYPE mcp_bytes_received_total counter\n");
+        let _ = writeln!(out, "mcp_bytes_received_total {}", snapshot.bytes_received);
+        out
+    }
+}
+
+impl Metrics for InMemoryMetrics {
+    fn request(&self, origin: Origin, method: &str, latency: Duration, error: bool) {
+        let mut snapshot = self.snapshot.lock().unwrap();
+        let metrics = snapshot
+            .methods
+            .entry((origin, method.to_string()))
+            .or_default();
+        metrics.count += 1;
+        metrics.errors += error as u64;
+        metrics.latency_buckets.resize(LATENCY_BUCKETS.len() + 1, 0);
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| latency.as_secs_f64() <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        metrics.latency_buckets[bucket] += 1;
+        metrics.latency_sum += latency;
+    }
+
+    fn bytes_sent(&self, bytes: u64) {
+        self.snapshot.lock().unwrap().bytes_sent += bytes;
+    }
+
+    fn bytes_received(&self, bytes: u64) {
+        self.snapshot.lock().unwrap().bytes_received += bytes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::Client,
+        testing::{Fault, MockServer},
+        transport::middleware::MiddlewareTransport,
+    };
+
+    #[tokio::test(start_paused = true)]
+    async fn test_metrics_layer() -> Result<(), MCPError> {
+        let server = MockServer::new();
+        server.inject("ping", Fault::Delay(Duration::from_millis(30)));
+        server.inject(
+            "ping",
+            Fault::Error {
+                code: -32000,
+                message: "Busy".to_string(),
+            },
+        );
+
+        let metrics = Arc::new(InMemoryMetrics::new());
+        let transport = MiddlewareTransport::new(server.transport())
+            .with_layer(MetricsLayer::new(metrics.clone()));
+        let mut client = Client::new(transport);
+        client.initialize().await?;
+        client.ping().await?;
+        assert!(client.ping().await.is_err());
+
+        let snapshot = metrics.snapshot();
+        let ping = &snapshot.methods[&(Origin::Local, "ping".to_string())];
+        assert_eq!((ping.count, ping.errors), (2, 1));
+        assert_eq!(ping.latency_buckets[LATENCY_BUCKETS.len()], 0);
+        assert!(ping.latency_sum >= Duration::from_millis(30));
+        // The delayed ping lands in the 50ms bucket
+        assert_eq!(ping.latency_buckets[3], 1);
+        assert!(snapshot.bytes_sent > 0 && snapshot.bytes_received > 0);
+
+        let text = metrics.to_prometheus();
+        assert!(text.contains("mcp_requests_total{origin=\"local\",method=\"initialize\"} 1"));
+        assert!(text.contains("mcp_request_errors_total{origin=\"local\",method=\"ping\"} 1"));
+        assert!(text.contains(
+            "mcp_request_duration_seconds_bucket{origin=\"local\",method=\"ping\",le=\"+Inf\"} 2"
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,351 @@
+//! Export MCP tools as OpenAI function-calling definitions
+//!
+//! Available with the `openai` feature. [`tool_to_openai_function`] maps a
+//! tool to a function definition, as used by the legacy `functions` parameter,
+//! and [`tools_to_openai_tools`] wraps a catalog for the `tools` parameter:
+//!
+//! ```rust,ignore
+//! use mcpr::openai::tools_to_openai_tools;
+//!
+//! let tools: ListToolsResult = client.list_tools().await?;
+//! let request = serde_json::json!({
+//!     "model": "gpt-4o",
+//!     "messages": messages,
+//!     "tools": tools_to_openai_tools(&tools.tools),
+//! });
+//! ```
+//!
+//! Tool names are passed through unchanged, so a model's function call can be
+//! forwarded with [`Client::call_tool`](crate::client::Client::call_tool) as is.
+//! OpenAI only accepts names made of letters, digits, `_` and `-`.
+//!
+//! [`OpenAiSampler`] answers the `sampling/createMessage` requests of servers
+//! with the chat completions API, or any API compatible with it:
+//!
+//! ```rust,ignore
+//! let client = Client::new(transport).with_sampling_handler(OpenAiSampler::from_env()?);
+//! ```
+
+use crate::{
+    client::SamplingHandler,
+    error::MCPError,
+    sampling::{post_json, ModelProfile, ModelSelector},
+    schema::{
+        common::{Role, TextContent, Tool},
+        server::{
+            CreateMessageParams, CreateMessageResult, KnownStopReason, MessageContent, StopReason,
+        },
+    },
+};
+use async_trait::async_trait;
+use serde_json::{json, Map, Value};
+
+/// Where [`OpenAiSampler`] sends requests unless told otherwise
+pub const DEFAULT_OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+
+/// Map a tool to an OpenAI function definition
+///
+/// The input schema becomes the function's `parameters`. OpenAI expects an
+/// object schema with `properties`, so an empty one is added if missing.
+pub fn tool_to_openai_function(tool: &Tool) -> Value {
+    let mut parameters = match serde_json::to_value(&tool.input_schema) {
+        Ok(Value::Object(schema)) => schema,
+        _ => Map::new(),
+    };
+    parameters
+        .entry("type")
+        .or_insert_with(|| Value::String("object".to_string()));
+    parameters
+        .entry("properties")
+        .or_insert_with(|| Value::Object(Map::new()));
+
+    let mut function = json!({
+        "name": tool.name,
+        "parameters": parameters,
+    });
+    if let Some(description) = &tool.description {
+        function["description"] = Value::String(description.clone());
+    }
+    function
+}
+
+/// Map a catalog of tools to the entries of OpenAI's `tools` parameter
+pub fn tools_to_openai_tools(tools: &[Tool]) -> Value {
+    tools
+        .iter()
+        .map(|tool| {
+            json!({
+                "type": "function",
+                "function": tool_to_openai_function(tool),
+            })
+        })
+        .collect()
+}
+
+/// Answers sampling requests with OpenAI's chat completions API
+///
+/// The model is chosen from the server's preferences with a
+/// [`ModelSelector`], by default between `gpt-4o` and `gpt-4o-mini`. The
+/// system prompt, temperature, token limit and stop sequences are passed on;
+/// the request's `metadata` is not, since the API gives the field another
+/// meaning, and including context from servers is left to the host.
+#[derive(Clone)]
+pub struct OpenAiSampler {
+    http: reqwest::Client,
+    api_key: String,
+    base_url: String,
+    models: ModelSelector,
+}
+
+impl OpenAiSampler {
+    /// A sampler authenticating with `api_key`
+    pub fn new(api_key: &str) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            api_key: api_key.to_string(),
+            base_url: DEFAULT_OPENAI_BASE_URL.to_string(),
+            models: ModelSelector::new([
+                ModelProfile::new("gpt-4o").with_scores(0.3, 0.5, 0.9),
+                ModelProfile::new("gpt-4o-mini").with_scores(0.9, 0.9, 0.5),
+            ]),
+        }
+    }
+
+    /// A sampler with the API key in the `OPENAI_API_KEY` environment variable
+    pub fn from_env() -> Result<Self, MCPError> {
+        let api_key = std::env::var("OPENAI_API_KEY")
+            .map_err(|_| MCPError::Unauthorized("OPENAI_API_KEY is not set".to_string()))?;
+        Ok(Self::new(&api_key))
+    }
+
+    /// Send requests to a compatible API at `base_url` instead, e.g. a local server
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Choose among these models instead of the default ones
+    pub fn with_models(mut self, models: ModelSelector) -> Self {
+        self.models = models;
+        self
+    }
+
+    /// The chat completions request for a sampling request
+    fn request_body(&self, params: &CreateMessageParams) -> Result<Value, MCPError> {
+        let model = self
+            .models
+            .select(params.model_preferences.as_ref())
+            .ok_or_else(|| MCPError::Protocol("No model to sample with".to_string()))?;
+
+        let mut messages = Vec::new();
+        if let Some(system_prompt) = &params.system_prompt {
+            messages.push(json!({ "role": "system", "content": system_prompt }));
+        }
+        for message in &params.messages {
+            let content = match &message.content {
+                MessageContent::Text(text) => json!(text.text),
+                MessageContent::Image(image) => json!([{
+                    "type": "image_url",
+                    "image_url": { "url": format!("data:{};base64,{}", image.mime_type, image.data) },
+                }]),
+            };
+            messages.push(json!({ "role": message.role.as_str(), "content": content }));
+        }
+
+        let mut body = json!({
+            "model": model,
+            "messages": messages,
+            "max_tokens": params.max_tokens,
+        });
+        if let Some(temperature) = params.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        if let Some(stop_sequences) = &params.stop_sequences {
+            body["stop"] = json!(stop_sequences);
+        }
+        Ok(body)
+    }
+}
+
+#[async_trait]
+impl SamplingHandler for OpenAiSampler {
+    async fn create_message(
+        &self,
+        params: CreateMessageParams,
+    ) -> Result<CreateMessageResult, MCPError> {
+        let body = self.request_body(&params)?;
+        let request = self
+            .http
+            .post(format!("{}/chat/completions", self.base_url))
+            .bearer_auth(&self.api_key);
+        parse_completion(post_json("OpenAI", request, &body).await?)
+    }
+}
+
+/// Read the first choice of a chat completion as a sampling result
+fn parse_completion(response: Value) -> Result<CreateMessageResult, MCPError> {
+    let choice = &response["choices"][0];
+    let message = &choice["message"];
+    let Some(text) = message["content"]
+        .as_str()
+        .or_else(|| message["refusal"].as_str())
+    else {
+        return Err(MCPError::Protocol(format!(
+            "OpenAI response without a message: {}",
+            response
+        )));
+    };
+    let stop_reason = choice["finish_reason"].as_str().map(|reason| match reason {
+        "stop" => StopReason::Known(KnownStopReason::EndTurn),
+        "length" => StopReason::Known(KnownStopReason::MaxTokens),
+        other => StopReason::Custom(other.to_string()),
+    });
+    Ok(CreateMessageResult {
+        role: Role::Assistant,
+        content: MessageContent::Text(TextContent {
+            r#type: "text".to_string(),
+            text: text.to_string(),
+            annotations: None,
+        }),
+        model: response["model"].as_str().unwrap_or_default().to_string(),
+        stop_reason,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{
+        common::{ImageContent, ToolInputSchema},
+        server::{ModelHint, ModelPreferences, SamplingMessage},
+    };
+
+    #[test]
+    fn test_openai_sampler() {
+        let params = CreateMessageParams {
+            messages: vec![
+                SamplingMessage {
+                    role: Role::User,
+                    content: MessageContent::Text(TextContent {
+                        r#type: "text".to_string(),
+                        text: "What is in this picture?".to_string(),
+                        annotations: None,
+                    }),
+                },
+                SamplingMessage {
+                    role: Role::User,
+                    content: MessageContent::Image(ImageContent {
+                        r#type: "image".to_string(),
+                        data: "iVBORw0K".to_string(),
+                        mime_type: "image/png".to_string(),
+                        annotations: None,
+                    }),
+                },
+            ],
+            model_preferences: Some(ModelPreferences {
+                hints: Some(vec![ModelHint {
+                    name: Some("mini".to_string()),
+                }]),
+                cost_priority: None,
+                speed_priority: None,
+                intelligence_priority: None,
+            }),
+            system_prompt: Some("Be brief".to_string()),
+            include_context: None,
+            temperature: Some(0.5),
+            max_tokens: 100,
+            stop_sequences: None,
+            metadata: None,
+        };
+        let body = OpenAiSampler::new("key").request_body(&params).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "model": "gpt-4o-mini",
+                "messages": [
+                    { "role": "system", "content": "Be brief" },
+                    { "role": "user", "content": "What is in this picture?" },
+                    { "role": "user", "content": [{
+                        "type": "image_url",
+                        "image_url": { "url": "data:image/png;base64,iVBORw0K" }
+                    }] },
+                ],
+                "max_tokens": 100,
+                "temperature": 0.5,
+            })
+        );
+
+        let result = parse_completion(json!({
+            "model": "gpt-4o-mini-2024-07-18",
+            "choices": [{
+                "message": { "role": "assistant", "content": "A cat." },
+                "finish_reason": "length",
+            }],
+        }))
+        .unwrap();
+        assert_eq!(result.model, "gpt-4o-mini-2024-07-18");
+        assert_eq!(
+            result.stop_reason,
+            Some(StopReason::Known(KnownStopReason::MaxTokens))
+        );
+        assert!(matches!(result.content, MessageContent::Text(text) if text.text == "A cat."));
+        assert!(parse_completion(json!({ "error": "nope" })).is_err());
+    }
+
+    #[test]
+    fn test_tools_to_openai_tools() {
+        let search = Tool {
+            name: "search".to_string(),
+            description: Some("Search the web".to_string()),
+            input_schema: ToolInputSchema {
+                r#type: "object".to_string(),
+                properties: Some(
+                    [("query".to_string(), json!({ "type": "string" }))]
+                        .into_iter()
+                        .collect(),
+                ),
+                required: Some(vec!["query".to_string()]),
+                keywords: serde_json::Map::new(),
+            },
+            output_schema: None,
+            annotations: None,
+        };
+        let now = Tool {
+            name: "now".to_string(),
+            description: None,
+            input_schema: ToolInputSchema {
+                r#type: "object".to_string(),
+                properties: None,
+                required: None,
+                keywords: serde_json::Map::new(),
+            },
+            output_schema: None,
+            annotations: None,
+        };
+
+        assert_eq!(
+            tools_to_openai_tools(&[search, now]),
+            json!([
+                {
+                    "type": "function",
+                    "function": {
+                        "name": "search",
+                        "description": "Search the web",
+                        "parameters": {
+                            "type": "object",
+                            "properties": { "query": { "type": "string" } },
+                            "required": ["query"]
+                        }
+                    }
+                },
+                {
+                    "type": "function",
+                    "function": {
+                        "name": "now",
+                        "parameters": { "type": "object", "properties": {} }
+                    }
+                }
+            ])
+        );
+    }
+}
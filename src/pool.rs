@@ -0,0 +1,478 @@
+//! Many MCP servers behind one handle
+//!
+//! A [`ClientPool`] owns a client per named server, as a host application
+//! such as an IDE or an agent needs. Servers are connected on first use,
+//! from a factory that creates a client; a server whose connection fails,
+//! such as a crashed stdio child, is connected again by the next use, up to
+//! [`ClientPool::with_max_restarts`] times. [`ClientPool::health_check`]
+//! pings the connected servers.
+//!
+//! Tools are called as `server.tool`:
+//!
+//! ```rust,no_run
+//! use mcpr::{
+//!     client::Client,
+//!     pool::ClientPool,
+//!     schema::server::CallToolResult,
+//!     transport::{spawn::SpawnOptions, websocket::WebSocketTransport},
+//! };
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), mcpr::error::MCPError> {
+//!     let pool = ClientPool::new()
+//!         .with_stdio_server("weather", "weather-server", ["--quiet"], SpawnOptions::new())
+//!         .with_server("search", || async {
+//!             Ok(Client::new(WebSocketTransport::new("ws://search.internal:8080")))
+//!         });
+//!     let forecast: CallToolResult = pool
+//!         .call("weather.forecast", &serde_json::json!({ "city": "Oslo" }))
+//!         .await?;
+//!     pool.shutdown().await;
+//!     Ok(())
+//! }
+//! ```
+
+use crate::{
+    client::Client,
+    error::MCPError,
+    transport::{spawn::SpawnOptions, stdio::StdioTransport, Transport},
+};
+use async_trait::async_trait;
+use futures::future::{join_all, BoxFuture};
+use log::{info, warn};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{sync::Mutex, task::JoinHandle};
+
+/// Separator between the server's name and the tool name in [`ClientPool::call`]
+pub const SERVER_SEPARATOR: char = '.';
+
+/// A client of a pooled server, whatever its transport
+#[async_trait]
+trait Member: Send + Sync {
+    async fn call_tool(&self, tool: &str, args: &Value) -> Result<Value, MCPError>;
+    async fn request(&self, method: &str, params: Option<Value>) -> Result<Value, MCPError>;
+    async fn ping(&self) -> Result<(), MCPError>;
+    async fn shutdown(&self) -> Result<(), MCPError>;
+}
+
+#[async_trait]
+impl<T: Transport + Clone + Send + Sync + 'static> Member for Client<T> {
+    async fn call_tool(&self, tool: &str, args: &Value) -> Result<Value, MCPError> {
+        Client::call_tool(&mut self.clone(), tool, args).await
+    }
+
+    async fn request(&self, method: &str, params: Option<Value>) -> Result<Value, MCPError> {
+        Client::request(&mut self.clone(), method, params).await
+    }
+
+    async fn ping(&self) -> Result<(), MCPError> {
+        Client::ping(&mut self.clone()).await
+    }
+
+    async fn shutdown(&self) -> Result<(), MCPError> {
+        Client::shutdown(&mut self.clone()).await
+    }
+}
+
+/// Creates and initializes the client of a server
+type Connect = Box<dyn Fn() -> BoxFuture<'static, Result<Arc<dyn Member>, MCPError>> + Send + Sync>;
+
+/// A named server of the pool
+struct Pooled {
+    connect: Connect,
+    state: Mutex<MemberState>,
+}
+
+#[derive(Default)]
+struct MemberState {
+    client: Option<Arc<dyn Member>>,
+    /// Whether the server was connected once, so connecting again is a restart
+    connected_before: bool,
+    restarts: u32,
+    last_error: Option<String>,
+}
+
+/// How a server of the pool is doing, see [`ClientPool::health_check`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerHealth {
+    /// Not connected yet, or not since its connection failed
+    Idle,
+    /// Answered a ping, in this time
+    Healthy(Duration),
+    /// Failed to answer a ping; the next use connects again
+    Unhealthy(String),
+    /// Failed more often than it may be restarted
+    Failed(String),
+}
+
+/// Clients of named servers, connected on first use, see the [module docs](self)
+pub struct ClientPool {
+    servers: BTreeMap<String, Pooled>,
+    max_restarts: Option<u32>,
+}
+
+impl Default for ClientPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientPool {
+    /// A pool without servers
+    pub fn new() -> Self {
+        Self {
+            servers: BTreeMap::new(),
+            max_restarts: None,
+        }
+    }
+
+    /// Add a server, known by `name`, connected with clients from `factory`
+    ///
+    /// The factory returns a client that is not yet initialized; the pool
+    /// initializes it. It is called on first use, and again after the
+    /// connection fails. `name` must not contain a `.`.
+    pub fn with_server<F, Fut, T>(mut self, name: &str, factory: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Client<T>, MCPError>> + Send + 'static,
+        T: Transport + Clone + Send + Sync + 'static,
+    {
+        assert!(
+            !name.contains(SERVER_SEPARATOR),
+            "server name '{}' contains '{}'",
+            name,
+            SERVER_SEPARATOR
+        );
+        let connect: Connect = Box::new(move || {
+            let client = factory();
+            Box::pin(async move {
+                let mut client = client.await?;
+                client.initialize().await?;
+                Ok(Arc::new(client) as Arc<dyn Member>)
+            })
+        });
+        self.servers.insert(
+            name.to_string(),
+            Pooled {
+                connect,
+                state: Mutex::new(MemberState::default()),
+            },
+        );
+        self
+    }
+
+    /// Add a server run as a child process, spawned again if it crashes
+    pub fn with_stdio_server<I, S>(
+        self,
+        name: &str,
+        command: impl AsRef<OsStr>,
+        args: I,
+        options: SpawnOptions,
+    ) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let command = command.as_ref().to_os_string();
+        let args: Vec<OsString> = args
+            .into_iter()
+            .map(|a| a.as_ref().to_os_string())
+            .collect();
+        self.with_server(name, move || {
+            let transport = StdioTransport::spawn_with(&command, &args, options.clone());
+            async move { Ok(Client::new(transport?)) }
+        })
+    }
+
+    /// Give up on a server once its connection failed more than `max_restarts` times
+    ///
+    /// Servers are restarted without limit by default.
+    pub fn with_max_restarts(mut self, max_restarts: u32) -> Self {
+        self.max_restarts = Some(max_restarts);
+        self
+    }
+
+    /// The names of the servers, in order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.servers.keys().map(String::as_str)
+    }
+
+    /// Call the tool named `server.tool`, on the server before the first `.`
+    ///
+    /// The result is deserialized as `R`, as by [`Client::call_tool`].
+    pub async fn call<P, R>(&self, name: &str, args: &P) -> Result<R, MCPError>
+    where
+        P: Serialize + Send + Sync,
+        R: DeserializeOwned,
+    {
+        let (server, tool) = name.split_once(SERVER_SEPARATOR).ok_or_else(|| {
+            MCPError::Protocol(format!(
+                "Tool '{}' is not qualified with a server, as 'server{}tool'",
+                name, SERVER_SEPARATOR
+            ))
+        })?;
+        let args = serde_json::to_value(args)?;
+        let result = self
+            .with_member(server, |member| async move {
+                member.call_tool(tool, &args).await
+            })
+            .await?;
+        Ok(serde_json::from_value(result)?)
+    }
+
+    /// Send a request to the server named `server`
+    pub async fn request<R: DeserializeOwned>(
+        &self,
+        server: &str,
+        method: &str,
+        params: Option<Value>,
+    ) -> Result<R, MCPError> {
+        let result = self
+            .with_member(server, |member| async move {
+                member.request(method, params).await
+            })
+            .await?;
+        Ok(serde_json::from_value(result)?)
+    }
+
+    /// Connect to the server named `server` now rather than on first use
+    pub async fn connect(&self, server: &str) -> Result<(), MCPError> {
+        self.member(self.pooled(server)?, server).await.map(drop)
+    }
+
+    /// Ping the connected servers
+    ///
+    /// Servers that fail to answer are disconnected, so their next use
+    /// connects again. Servers not connected are not connected for this.
+    pub async fn health_check(&self) -> BTreeMap<String, ServerHealth> {
+        let checks = self.servers.iter().map(|(name, pooled)| async move {
+            let (client, failed) = {
+                let state = pooled.state.lock().await;
+                let failed = self.exhausted(&state).then(|| state.last_error.clone());
+                (state.client.clone(), failed)
+            };
+            let health = match (client, failed) {
+                (_, Some(error)) => ServerHealth::Failed(error.unwrap_or_default()),
+                (None, None) => ServerHealth::Idle,
+                (Some(client), None) => {
+                    let started = Instant::now();
+                    match client.ping().await {
+                        Ok(()) => ServerHealth::Healthy(started.elapsed()),
+                        Err(e) => {
+                            warn!("Server '{}' failed its health check: {}", name, e);
+                            self.disconnect(pooled, &client, &e).await;
+                            ServerHealth::Unhealthy(e.to_string())
+                        }
+                    }
+                }
+            };
+            (name.clone(), health)
+        });
+        join_all(checks).await.into_iter().collect()
+    }
+
+    /// Run [`ClientPool::health_check`] every `interval`, until the pool is dropped
+    pub fn spawn_health_checks(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let pool = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                let Some(pool) = pool.upgrade() else {
+                    return;
+                };
+                pool.health_check().await;
+            }
+        })
+    }
+
+    /// Shut down the connected servers
+    pub async fn shutdown(&self) {
+        let shutdowns = self.servers.iter().map(|(name, pooled)| async move {
+            let client = pooled.state.lock().await.client.take();
+            if let Some(client) = client {
+                if let Err(e) = client.shutdown().await {
+                    warn!("Server '{}' did not shut down cleanly: {}", name, e);
+                }
+            }
+        });
+        join_all(shutdowns).await;
+    }
+
+    fn pooled(&self, server: &str) -> Result<&Pooled, MCPError> {
+        self.servers
+            .get(server)
+            .ok_or_else(|| MCPError::Protocol(format!("No server named '{}' in the pool", server)))
+    }
+
+    /// Run `f` with the client of `server`, disconnecting it if its connection fails
+    async fn with_member<F, Fut>(&self, server: &str, f: F) -> Result<Value, MCPError>
+    where
+        F: FnOnce(Arc<dyn Member>) -> Fut,
+        Fut: Future<Output = Result<Value, MCPError>>,
+    {
+        let pooled = self.pooled(server)?;
+        let member = self.member(pooled, server).await?;
+        let result = f(member.clone()).await;
+        if let Err(e @ (MCPError::Transport(_) | MCPError::ConnectionClosed)) = &result {
+            warn!("Connection to server '{}' failed: {}", server, e);
+            self.disconnect(pooled, &member, e).await;
+        }
+        result
+    }
+
+    /// The client of a server, connecting it if need be
+    async fn member(&self, pooled: &Pooled, server: &str) -> Result<Arc<dyn Member>, MCPError> {
+        let mut state = pooled.state.lock().await;
+        if let Some(client) = &state.client {
+            return Ok(client.clone());
+        }
+        if self.exhausted(&state) {
+            return Err(MCPError::Transport(format!(
+                "Server '{}' failed {} times: {}",
+                server,
+                state.restarts + 1,
+                state.last_error.as_deref().unwrap_or_default()
+            )));
+        }
+
+        if state.connected_before {
+            state.restarts += 1;
+            info!("Restarting server '{}' ({})", server, state.restarts);
+        }
+        state.connected_before = true;
+        match (pooled.connect)().await {
+            Ok(client) => {
+                info!("Connected to server '{}'", server);
+                state.client = Some(client.clone());
+                Ok(client)
+            }
+            Err(e) => {
+                state.last_error = Some(e.to_string());
+                Err(e)
+            }
+        }
+    }
+
+    /// Drop the client of a server whose connection failed, unless it was replaced already
+    async fn disconnect(&self, pooled: &Pooled, member: &Arc<dyn Member>, error: &MCPError) {
+        let mut state = pooled.state.lock().await;
+        if state
+            .client
+            .as_ref()
+            .is_some_and(|client| Arc::ptr_eq(client, member))
+        {
+            state.client = None;
+            state.last_error = Some(error.to_string());
+        }
+    }
+
+    /// Whether a server may not be connected again
+    fn exhausted(&self, state: &MemberState) -> bool {
+        state.client.is_none()
+            && state.connected_before
+            && self
+                .max_restarts
+                .is_some_and(|max_restarts| state.restarts >= max_restarts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        schema::common::{Tool, ToolInputSchema},
+        server::{Server, ServerConfig},
+        transport::in_memory::InMemoryTransport,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A server with an `echo` tool, on the other end of the returned transport
+    fn echo_server() -> InMemoryTransport {
+        let (client_end, server_end) = InMemoryTransport::pair();
+        let mut server = Server::new(ServerConfig::new().with_tool(Tool {
+            name: "echo".to_string(),
+            description: None,
+            input_schema: ToolInputSchema {
+                r#type: "object".to_string(),
+                properties: None,
+                required: None,
+                keywords: serde_json::Map::new(),
+            },
+            output_schema: None,
+            annotations: None,
+        }));
+        server
+            .register_tool_handler("echo", |params| async move { Ok(params) })
+            .unwrap();
+        tokio::spawn(async move { server.serve(server_end).await });
+        client_end
+    }
+
+    #[tokio::test]
+    async fn test_client_pool() -> Result<(), MCPError> {
+        let connects = Arc::new(AtomicUsize::new(0));
+        let counter = connects.clone();
+        let pool = ClientPool::new()
+            .with_server("echo", move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async { Ok(Client::new(echo_server())) }
+            })
+            .with_max_restarts(1);
+        assert_eq!(pool.names().collect::<Vec<_>>(), ["echo"]);
+
+        // Connected lazily, once
+        let health = pool.health_check().await;
+        assert_eq!(health["echo"], ServerHealth::Idle);
+        let result: crate::schema::server::CallToolResult = pool
+            .call("echo.echo", &serde_json::json!({ "text": "hi" }))
+            .await?;
+        assert_eq!(result.content.len(), 1);
+        let _: Value = pool.call("echo.echo", &serde_json::json!({})).await?;
+        assert_eq!(connects.load(Ordering::SeqCst), 1);
+        assert!(matches!(
+            pool.health_check().await["echo"],
+            ServerHealth::Healthy(_)
+        ));
+
+        assert!(pool.call::<_, Value>("echo", &()).await.is_err());
+        assert!(pool.call::<_, Value>("other.echo", &()).await.is_err());
+        pool.shutdown().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_restart_crashed_server() -> Result<(), MCPError> {
+        // The server exits as soon as it reads the initialize request
+        let pool = ClientPool::new()
+            .with_stdio_server(
+                "crashy",
+                "sh",
+                ["-c", "read line; exit 1"],
+                SpawnOptions::new(),
+            )
+            .with_max_restarts(1);
+        let first = pool.connect("crashy").await;
+        assert!(first.is_err());
+        let second = pool.connect("crashy").await;
+        assert!(second.is_err());
+
+        // Given up on after its restart
+        let error = pool.connect("crashy").await.unwrap_err();
+        assert!(error.to_string().contains("failed 2 times"));
+        assert!(matches!(
+            pool.health_check().await["crashy"],
+            ServerHealth::Failed(_)
+        ));
+        Ok(())
+    }
+}
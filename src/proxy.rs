@@ -0,0 +1,342 @@
+//! Relay between two transports, for building MCP proxies
+//!
+//! [`relay`] accepts a client on one transport (downstream) and forwards its
+//! traffic to a server on another (upstream), and the server's traffic back.
+//! Messages, including `initialize` and its result, pass through with their
+//! ids intact, so the client and server negotiate directly with each other.
+//! [`RelayHooks`] can inspect, modify, drop or answer each message on the way.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use mcpr::{
+//!     proxy::{relay, Direction, FrameAction, RelayHooks},
+//!     schema::json_rpc::{JSONRPCError, JSONRPCMessage},
+//!     transport::{stdio::StdioTransport, websocket::WebSocketTransport},
+//! };
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), mcpr::error::MCPError> {
+//!     // Refuse every tool call named "delete", forward everything else
+//!     let hooks = RelayHooks::new().on_frame(|direction, message| match &message {
+//!         JSONRPCMessage::Request(request)
+//!             if direction == Direction::ToServer
+//!                 && request.method == "tools/call"
+//!                 && request.params.as_ref().and_then(|p| p.get("name"))
+//!                     == Some(&"delete".into()) =>
+//!         {
+//!             FrameAction::Reply(JSONRPCMessage::Error(JSONRPCError::new_with_details(
+//!                 request.id.clone(),
+//!                 -32000,
+//!                 "Tool is blocked by the proxy".to_string(),
+//!                 None,
+//!             )))
+//!         }
+//!         _ => FrameAction::Forward(message),
+//!     });
+//!
+//!     let downstream = StdioTransport::new();
+//!     let upstream = WebSocketTransport::new("ws://localhost:8080");
+//!     relay(downstream, upstream, hooks).await
+//! }
+//! ```
+
+use crate::{
+    error::MCPError,
+    schema::json_rpc::{JSONRPCMessage, JSONRPCPayload, RawPayload},
+    transport::Transport,
+};
+use log::{debug, info, warn};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Which way a message is travelling through the relay
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Direction {
+    /// From the downstream client to the upstream server
+    ToServer,
+    /// From the upstream server to the downstream client
+    ToClient,
+}
+
+/// What the relay does with a message, as decided by a hook
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameAction {
+    /// Forward the message, possibly modified
+    Forward(JSONRPCMessage),
+    /// Drop the message
+    Drop,
+    /// Send a message back to the sender instead, e.g. an error for a refused request
+    Reply(JSONRPCMessage),
+}
+
+/// Frame hook function type
+///
+/// Receives the direction and the message, and decides what to do with it.
+pub type FrameHook = Box<dyn Fn(Direction, JSONRPCMessage) -> FrameAction + Send + Sync>;
+
+/// Hooks run by [`relay`] on every message it receives
+#[derive(Default)]
+pub struct RelayHooks {
+    frame_hooks: Vec<FrameHook>,
+}
+
+impl RelayHooks {
+    /// Create an empty set of hooks, which forwards everything unchanged
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a hook called for every message
+    ///
+    /// Hooks run in the order they were added. A message forwarded by one
+    /// hook is passed to the next; dropping or replying stops the chain.
+    /// Messages in a batch are passed to the hooks one at a time.
+    pub fn on_frame<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(Direction, JSONRPCMessage) -> FrameAction + Send + Sync + 'static,
+    {
+        self.frame_hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Run a message through every hook
+    fn apply(&self, direction: Direction, message: JSONRPCMessage) -> FrameAction {
+        let mut message = message;
+        for hook in &self.frame_hooks {
+            match hook(direction, message) {
+                FrameAction::Forward(forwarded) => message = forwarded,
+                action => return action,
+            }
+        }
+        FrameAction::Forward(message)
+    }
+}
+
+/// Forward messages between a downstream client and an upstream server
+///
+/// Both transports are started, then messages are pumped in both directions
+/// until either side's connection closes, at which point both transports are
+/// closed. Frames that fail to parse are logged and skipped; with no frame
+/// hooks, only the envelope of each message is parsed. Like
+/// [`Server::serve`](crate::server::Server::serve), the transports must be
+/// `Clone`, with clones sharing the connection for sending; each transport
+/// itself is used for receiving.
+pub async fn relay<D, U>(
+    mut downstream: D,
+    mut upstream: U,
+    hooks: RelayHooks,
+) -> Result<(), MCPError>
+where
+    D: Transport + Clone + 'static,
+    U: Transport + Clone + 'static,
+{
+    downstream.start().await?;
+    upstream.start().await?;
+    relay_started(downstream, upstream, hooks).await
+}
+
+/// Like [`relay`], for transports that were started already
+pub(crate) async fn relay_started<D, U>(
+    downstream: D,
+    upstream: U,
+    hooks: RelayHooks,
+) -> Result<(), MCPError>
+where
+    D: Transport + Clone + 'static,
+    U: Transport + Clone + 'static,
+{
+    info!("Relay started");
+
+    let mut downstream_sender = downstream.clone();
+    let mut upstream_sender = upstream.clone();
+
+    let hooks = Arc::new(hooks);
+    let mut to_server = tokio::spawn(pump(
+        downstream,
+        upstream_sender.clone(),
+        Direction::ToServer,
+        hooks.clone(),
+    ));
+    let mut to_client = tokio::spawn(pump(
+        upstream,
+        downstream_sender.clone(),
+        Direction::ToClient,
+        hooks,
+    ));
+
+    // Stop as soon as either direction ends
+    let result = tokio::select! {
+        result = &mut to_server => result,
+        result = &mut to_client => result,
+    };
+    to_server.abort();
+    to_client.abort();
+
+    let _ = downstream_sender.close().await;
+    let _ = upstream_sender.close().await;
+    info!("Relay stopped");
+
+    result.map_err(|e| MCPError::Protocol(format!("Relay task failed: {}", e)))?
+}
+
+/// Forward messages one way until the sending side closes
+async fn pump<S, R>(
+    mut from: S,
+    mut to: R,
+    direction: Direction,
+    hooks: Arc<RelayHooks>,
+) -> Result<(), MCPError>
+where
+    S: Transport,
+    R: Transport,
+{
+    if hooks.frame_hooks.is_empty() {
+        // Nothing looks at the messages, so their contents are not parsed
+        while let Some(payload) = next_payload::<RawPayload, _>(&mut from, direction).await {
+            to.send(&payload).await?;
+        }
+        return Ok(());
+    }
+
+    while let Some(payload) = next_payload::<JSONRPCPayload, _>(&mut from, direction).await {
+        let is_batch = matches!(payload, JSONRPCPayload::Batch(_));
+        let mut forwards = Vec::new();
+        let mut replies = Vec::new();
+        for message in payload.into_messages() {
+            match hooks.apply(direction, message) {
+                FrameAction::Forward(message) => forwards.push(message),
+                FrameAction::Reply(message) => replies.push(message),
+                FrameAction::Drop => debug!("Dropped a frame ({:?})", direction),
+            }
+        }
+
+        if let Some(payload) = into_payload(forwards, is_batch) {
+            to.send(&payload).await?;
+        }
+        if let Some(payload) = into_payload(replies, is_batch) {
+            from.send(&payload).await?;
+        }
+    }
+    Ok(())
+}
+
+/// The next payload that parses, or `None` once the connection closed
+async fn next_payload<T, S>(from: &mut S, direction: Direction) -> Option<T>
+where
+    T: DeserializeOwned + Send + Sync,
+    S: Transport,
+{
+    loop {
+        match from.receive::<T>().await {
+            Ok(payload) => return Some(payload),
+            Err(MCPError::Serialization(e)) => {
+                warn!("Skipping unparseable frame ({:?}): {}", direction, e);
+            }
+            Err(e) => {
+                info!("Connection closed ({:?}): {}", direction, e);
+                return None;
+            }
+        }
+    }
+}
+
+/// Repackage messages the way they arrived: alone, or as a batch
+fn into_payload(mut messages: Vec<JSONRPCMessage>, is_batch: bool) -> Option<JSONRPCPayload> {
+    match messages.len() {
+        0 => None,
+        1 if !is_batch => messages.pop().map(JSONRPCPayload::Single),
+        _ => Some(JSONRPCPayload::Batch(messages)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        schema::json_rpc::{JSONRPCError, JSONRPCRequest, JSONRPCResponse, RequestId},
+        transport::stdio::StdioTransport,
+    };
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream};
+
+    /// A transport for the relay, and the peer's end of its connection
+    fn connection() -> (StdioTransport, BufReader<DuplexStream>, DuplexStream) {
+        let (relay_reader, peer_writer) = tokio::io::duplex(4096);
+        let (peer_reader, relay_writer) = tokio::io::duplex(4096);
+        let transport = StdioTransport::from_async_rw(relay_reader, relay_writer);
+        (transport, BufReader::new(peer_reader), peer_writer)
+    }
+
+    async fn write(writer: &mut DuplexStream, message: &JSONRPCMessage) {
+        let line = format!("{}\n", serde_json::to_string(message).unwrap());
+        writer.write_all(line.as_bytes()).await.unwrap();
+    }
+
+    async fn read(reader: &mut BufReader<DuplexStream>) -> JSONRPCMessage {
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_relay() {
+        let (downstream, mut client_reader, mut client_writer) = connection();
+        let (upstream, mut server_reader, mut server_writer) = connection();
+
+        // Tag pings on their way up and refuse tool calls
+        let hooks = RelayHooks::new().on_frame(|direction, message| match message {
+            JSONRPCMessage::Request(mut request) if request.method == "ping" => {
+                assert_eq!(direction, Direction::ToServer);
+                request.params = Some(serde_json::json!({ "via": "proxy" }));
+                FrameAction::Forward(JSONRPCMessage::Request(request))
+            }
+            JSONRPCMessage::Request(request) if request.method == "tools/call" => {
+                FrameAction::Reply(JSONRPCMessage::Error(JSONRPCError::new_with_details(
+                    request.id,
+                    -32000,
+                    "Blocked".to_string(),
+                    None,
+                )))
+            }
+            message => FrameAction::Forward(message),
+        });
+        let relay = tokio::spawn(relay(downstream, upstream, hooks));
+
+        // A request reaches the server with its id, modified by the hook
+        let ping =
+            JSONRPCRequest::new(RequestId::String("a".to_string()), "ping".to_string(), None);
+        write(&mut client_writer, &JSONRPCMessage::Request(ping)).await;
+        match read(&mut server_reader).await {
+            JSONRPCMessage::Request(request) => {
+                assert_eq!(request.id, RequestId::String("a".to_string()));
+                assert_eq!(request.params.unwrap()["via"], "proxy");
+            }
+            other => panic!("Expected a request, got {:?}", other),
+        }
+
+        // The response comes back to the client unchanged
+        let pong = JSONRPCMessage::Response(JSONRPCResponse::new(
+            RequestId::String("a".to_string()),
+            serde_json::json!({}),
+        ));
+        write(&mut server_writer, &pong).await;
+        assert_eq!(read(&mut client_reader).await, pong);
+
+        // A refused request is answered by the relay itself
+        let call = JSONRPCRequest::new(RequestId::Number(7), "tools/call".to_string(), None);
+        write(&mut client_writer, &JSONRPCMessage::Request(call)).await;
+        match read(&mut client_reader).await {
+            JSONRPCMessage::Error(error) => assert_eq!(error.id, RequestId::Number(7)),
+            other => panic!("Expected an error, got {:?}", other),
+        }
+
+        // Closing the client side stops the relay
+        drop(client_writer);
+        tokio::time::timeout(std::time::Duration::from_secs(1), relay)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -0,0 +1,340 @@
+//! Masking secrets before messages are logged, recorded or audited
+//!
+//! A [`Redactor`] replaces sensitive values with [`REDACTED`]: strings that
+//! look like bearer tokens or API keys, fields with names such as `password`
+//! or `apiKey`, and whichever argument fields of a tool it is told to mask.
+//! It is applied by the [`Auditor`](crate::audit::Auditor), the
+//! `Recorder` of the `test-util` feature and the
+//! [`DebugFormatter`](crate::debug::DebugFormatter) they are handed to, and
+//! to the frames transports log at debug level once installed with
+//! [`set_log_redactor`]:
+//!
+//! ```rust
+//! use mcpr::redact::{set_log_redactor, Redactor};
+//! use serde_json::json;
+//!
+//! let redactor = Redactor::new().with_tool_fields("deploy", ["target_host"]);
+//! let message = json!({
+//!     "jsonrpc": "2.0",
+//!     "id": 1,
+//!     "method": "tools/call",
+//!     "params": { "name": "deploy", "arguments": { "target_host": "db1", "token": "t0p" } },
+//! });
+//! let redacted = redactor.redact_message(&message);
+//! assert_eq!(redacted["params"]["arguments"], json!({ "target_host": "[REDACTED]", "token": "[REDACTED]" }));
+//!
+//! set_log_redactor(Some(redactor));
+//! ```
+
+use serde_json::Value;
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, RwLock},
+};
+
+pub use crate::audit::REDACTED;
+
+/// Names of fields masked by default, lowercased without `_` and `-`
+const SECRET_FIELDS: &[&str] = &[
+    "accesstoken",
+    "apikey",
+    "authorization",
+    "clientsecret",
+    "password",
+    "refreshtoken",
+    "secret",
+    "token",
+];
+
+/// Prefixes of well-known API keys, each followed by at least [`MIN_KEY_LEN`] more characters
+const KEY_PREFIXES: &[&str] = &[
+    "sk-",
+    "ghp_",
+    "gho_",
+    "ghs_",
+    "github_pat_",
+    "xoxb-",
+    "xoxp-",
+    "AKIA",
+    "AIza",
+    "glpat-",
+];
+
+/// Shortest secret after a key prefix or `Bearer`
+const MIN_KEY_LEN: usize = 8;
+
+/// Masks secrets in messages, see the [module docs](self)
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    builtin: bool,
+    fields: BTreeSet<String>,
+    tool_fields: HashMap<String, BTreeSet<String>>,
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Redactor {
+    /// Mask bearer tokens, API keys and the fields usually holding secrets
+    pub fn new() -> Self {
+        Self {
+            builtin: true,
+            fields: BTreeSet::new(),
+            tool_fields: HashMap::new(),
+        }
+    }
+
+    /// Mask only the fields given with [`Redactor::with_field`] and
+    /// [`Redactor::with_tool_fields`], and no built-in patterns
+    pub fn without_builtin(mut self) -> Self {
+        self.builtin = false;
+        self
+    }
+
+    /// Also mask the field `name` at any depth of any message
+    pub fn with_field(mut self, name: &str) -> Self {
+        self.fields.insert(name.to_string());
+        self
+    }
+
+    /// Mask these fields, at any depth, of the arguments of calls of `tool`
+    pub fn with_tool_fields<'a>(
+        mut self,
+        tool: &str,
+        fields: impl IntoIterator<Item = &'a str>,
+    ) -> Self {
+        self.tool_fields
+            .entry(tool.to_string())
+            .or_default()
+            .extend(fields.into_iter().map(str::to_string));
+        self
+    }
+
+    /// Copy a message with its secrets masked, including the arguments of a `tools/call`
+    pub fn redact_message(&self, message: &Value) -> Value {
+        match message {
+            Value::Array(batch) => {
+                Value::Array(batch.iter().map(|item| self.redact_message(item)).collect())
+            }
+            message if message["method"] == "tools/call" => {
+                let mut message = message.clone();
+                let tool = message["params"]["name"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string();
+                if let Some(arguments) = message
+                    .get_mut("params")
+                    .and_then(|params| params.get_mut("arguments"))
+                {
+                    if let Some(fields) = self.tool_fields.get(&tool) {
+                        *arguments = mask_fields(arguments, fields);
+                    }
+                }
+                self.redact_value(&message)
+            }
+            message => self.redact_value(message),
+        }
+    }
+
+    /// Copy the arguments of a call of `tool` with its masked fields and other secrets masked
+    pub fn redact_arguments(&self, tool: &str, arguments: &Value) -> Value {
+        match self.tool_fields.get(tool) {
+            Some(fields) => self.redact_value(&mask_fields(arguments, fields)),
+            None => self.redact_value(arguments),
+        }
+    }
+
+    /// Copy a JSON value with secret fields and strings that look like secrets masked
+    pub fn redact_value(&self, value: &Value) -> Value {
+        match value {
+            Value::String(text) => Value::String(self.redact_text(text).into_owned()),
+            Value::Array(items) => {
+                Value::Array(items.iter().map(|item| self.redact_value(item)).collect())
+            }
+            Value::Object(fields) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(key, value)| {
+                        let value = if self.is_secret_field(key) && !value.is_null() {
+                            Value::String(REDACTED.to_string())
+                        } else {
+                            self.redact_value(value)
+                        };
+                        (key.clone(), value)
+                    })
+                    .collect(),
+            ),
+            value => value.clone(),
+        }
+    }
+
+    /// Mask the bearer tokens and API keys in a text
+    ///
+    /// Borrows the text when there is nothing to mask.
+    pub fn redact_text<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if !self.builtin {
+            return Cow::Borrowed(text);
+        }
+        let mut out = String::new();
+        let mut copied = 0;
+        let mut after_bearer = false;
+        for (start, word) in words(text) {
+            let secret = if after_bearer {
+                word.len() >= MIN_KEY_LEN
+            } else {
+                KEY_PREFIXES.iter().any(|prefix| {
+                    word.strip_prefix(prefix)
+                        .is_some_and(|rest| rest.len() >= MIN_KEY_LEN)
+                })
+            };
+            after_bearer = word.eq_ignore_ascii_case("bearer");
+            if secret {
+                out.push_str(&text[copied..start]);
+                out.push_str(REDACTED);
+                copied = start + word.len();
+            }
+        }
+        if copied == 0 {
+            Cow::Borrowed(text)
+        } else {
+            out.push_str(&text[copied..]);
+            Cow::Owned(out)
+        }
+    }
+
+    /// Mask the secrets of a frame, as JSON if it is JSON and as text otherwise
+    pub fn redact_frame<'a>(&self, frame: &'a str) -> Cow<'a, str> {
+        match serde_json::from_str::<Value>(frame) {
+            Ok(message) => Cow::Owned(self.redact_message(&message).to_string()),
+            Err(_) => self.redact_text(frame),
+        }
+    }
+
+    fn is_secret_field(&self, name: &str) -> bool {
+        if self.fields.contains(name) {
+            return true;
+        }
+        if !self.builtin {
+            return false;
+        }
+        let normalized: String = name
+            .chars()
+            .filter(|c| *c != '_' && *c != '-')
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        SECRET_FIELDS.contains(&normalized.as_str())
+    }
+}
+
+/// Replace the values of `fields` in `value`, at any depth
+fn mask_fields(value: &Value, fields: &BTreeSet<String>) -> Value {
+    match value {
+        Value::Object(object) => Value::Object(
+            object
+                .iter()
+                .map(|(key, value)| {
+                    let value = if fields.contains(key) {
+                        Value::String(REDACTED.to_string())
+                    } else {
+                        mask_fields(value, fields)
+                    };
+                    (key.clone(), value)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => {
+            Value::Array(items.iter().map(|item| mask_fields(item, fields)).collect())
+        }
+        value => value.clone(),
+    }
+}
+
+/// The runs of token characters in a text, with their byte offsets
+fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    let is_token =
+        |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '~' | '+' | '/' | '=');
+    let mut rest = text.char_indices().peekable();
+    std::iter::from_fn(move || {
+        while rest.next_if(|(_, c)| !is_token(*c)).is_some() {}
+        let (start, _) = *rest.peek()?;
+        let mut end = start;
+        while let Some((index, c)) = rest.next_if(|(_, c)| is_token(*c)) {
+            end = index + c.len_utf8();
+        }
+        Some((start, &text[start..end]))
+    })
+}
+
+/// The redactor applied to frames before transports log them
+static LOG_REDACTOR: RwLock<Option<Arc<Redactor>>> = RwLock::new(None);
+
+/// Mask the frames transports log from now on with `redactor`, or stop with `None`
+///
+/// Frames are only logged at debug level, so this costs nothing otherwise.
+pub fn set_log_redactor(redactor: Option<Redactor>) {
+    *LOG_REDACTOR.write().unwrap() = redactor.map(Arc::new);
+}
+
+/// A frame as it may be logged
+pub(crate) fn for_log(frame: &str) -> Cow<'_, str> {
+    let redactor = LOG_REDACTOR.read().unwrap().clone();
+    match redactor {
+        Some(redactor) => Cow::Owned(redactor.redact_frame(frame).into_owned()),
+        None => Cow::Borrowed(frame),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_redactor() {
+        let redactor = Redactor::new()
+            .with_field("ssn")
+            .with_tool_fields("query", ["sql"]);
+        let message = json!({
+            "jsonrpc": "2.0",
+            "id": 7,
+            "method": "tools/call",
+            "params": {
+                "name": "query",
+                "arguments": {
+                    "sql": "select 1",
+                    "rows": [{ "ssn": "078-05-1120", "api_key": "abc" }],
+                    "note": "use Bearer eyJhbGciOi.x.y or sk-proj-Abcdefgh12345 please",
+                },
+                "_meta": { "progressToken": 3 },
+            },
+        });
+        let redacted = redactor.redact_message(&message);
+        assert_eq!(
+            redacted["params"]["arguments"],
+            json!({
+                "sql": REDACTED,
+                "rows": [{ "ssn": REDACTED, "api_key": REDACTED }],
+                "note": format!("use Bearer {} or {} please", REDACTED, REDACTED),
+            })
+        );
+        assert_eq!(redacted["params"]["_meta"]["progressToken"], 3);
+
+        // Other tools keep the field, and short words after "bearer" are not tokens
+        let other = json!({ "sql": "select 1", "text": "bearer of bad news" });
+        assert_eq!(redactor.redact_arguments("other", &other), other);
+        assert!(matches!(
+            redactor.redact_text("nothing to see"),
+            Cow::Borrowed(_)
+        ));
+        assert_eq!(
+            Redactor::new()
+                .without_builtin()
+                .redact_frame(r#"{"password":"x"}"#),
+            r#"{"password":"x"}"#
+        );
+    }
+}
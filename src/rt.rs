@@ -0,0 +1,34 @@
+//! Background tasks, on the tokio runtime, in the browser's event loop, or
+//! on a thread of their own when there is no runtime
+
+use std::future::Future;
+
+/// Run `future` in the background, without waiting for it
+#[cfg(feature = "runtime-tokio")]
+pub(crate) fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(future);
+}
+
+/// Run `future` in the background, without waiting for it
+#[cfg(all(not(feature = "runtime-tokio"), target_arch = "wasm32"))]
+pub(crate) fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + 'static,
+{
+    wasm_bindgen_futures::spawn_local(future);
+}
+
+/// Run `future` in the background, without waiting for it
+///
+/// Without a runtime to spawn on, the future runs to completion on a thread
+/// of its own.
+#[cfg(all(not(feature = "runtime-tokio"), not(target_arch = "wasm32")))]
+pub(crate) fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    std::thread::spawn(move || futures::executor::block_on(future));
+}
@@ -0,0 +1,187 @@
+//! Choosing the model for a server's `sampling/createMessage` request
+//!
+//! Servers state which model they would like through
+//! [`ModelPreferences`]: name hints, and how much cost, speed and
+//! intelligence matter. A [`ModelSelector`] maps those preferences onto the
+//! models a host actually offers, described by a [`ModelProfile`] each:
+//!
+//! ```rust
+//! use mcpr::sampling::{ModelProfile, ModelSelector};
+//!
+//! let models = ModelSelector::new([
+//!     ModelProfile::new("gpt-4o").with_scores(0.2, 0.5, 0.9),
+//!     ModelProfile::new("gpt-4o-mini").with_scores(0.9, 0.9, 0.5),
+//! ]);
+//! assert_eq!(models.select(None), Some("gpt-4o"));
+//! ```
+//!
+//! The adapters behind the `openai` and `anthropic` features use it to
+//! answer sampling requests with a hosted model, see
+//! `mcpr::openai::OpenAiSampler` and `mcpr::anthropic::AnthropicSampler`.
+
+use crate::schema::server::ModelPreferences;
+
+/// A model a host can sample, scored from 0 to 1 on each preference
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelProfile {
+    /// The name the model provider knows the model by
+    pub name: String,
+    /// How cheap the model is
+    pub cost: f32,
+    /// How fast the model is
+    pub speed: f32,
+    /// How capable the model is
+    pub intelligence: f32,
+}
+
+impl ModelProfile {
+    /// A model scored 0.5 on everything
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            cost: 0.5,
+            speed: 0.5,
+            intelligence: 0.5,
+        }
+    }
+
+    /// Set how cheap, fast and capable the model is, each from 0 to 1
+    pub fn with_scores(mut self, cost: f32, speed: f32, intelligence: f32) -> Self {
+        self.cost = cost;
+        self.speed = speed;
+        self.intelligence = intelligence;
+        self
+    }
+}
+
+/// Picks one of a host's models for a server's preferences
+#[derive(Debug, Clone, Default)]
+pub struct ModelSelector {
+    models: Vec<ModelProfile>,
+}
+
+impl ModelSelector {
+    /// Choose among `models`; the first is the default
+    pub fn new(models: impl IntoIterator<Item = ModelProfile>) -> Self {
+        Self {
+            models: models.into_iter().collect(),
+        }
+    }
+
+    /// The models to choose from
+    pub fn models(&self) -> &[ModelProfile] {
+        &self.models
+    }
+
+    /// The name of the model that fits `preferences` best
+    ///
+    /// The first hint that is part of a model's name picks that model, as
+    /// hints are substrings like `"sonnet"` or `"gpt-4o"`. Without a matching
+    /// hint, the model with the highest sum of its scores weighted by the
+    /// priorities wins, and the first model on a tie or without priorities.
+    /// `None` only if there are no models.
+    pub fn select(&self, preferences: Option<&ModelPreferences>) -> Option<&str> {
+        let Some(preferences) = preferences else {
+            return self.models.first().map(|model| model.name.as_str());
+        };
+
+        let hints = preferences.hints.iter().flatten();
+        for hint in hints.filter_map(|hint| hint.name.as_deref()) {
+            if let Some(model) = self.models.iter().find(|model| model.name.contains(hint)) {
+                return Some(&model.name);
+            }
+        }
+
+        let score = |model: &ModelProfile| {
+            preferences.cost_priority.unwrap_or(0.0) * model.cost
+                + preferences.speed_priority.unwrap_or(0.0) * model.speed
+                + preferences.intelligence_priority.unwrap_or(0.0) * model.intelligence
+        };
+        let mut best: Option<(&ModelProfile, f32)> = None;
+        for model in &self.models {
+            let score = score(model);
+            match best {
+                Some((_, best)) if score <= best => {}
+                _ => best = Some((model, score)),
+            }
+        }
+        best.map(|(model, _)| model.name.as_str())
+    }
+}
+
+/// Send a request to a model provider and read its JSON response
+#[cfg(any(feature = "openai", feature = "anthropic"))]
+pub(crate) async fn post_json(
+    provider: &str,
+    request: reqwest::RequestBuilder,
+    body: &serde_json::Value,
+) -> Result<serde_json::Value, crate::error::MCPError> {
+    use crate::error::MCPError;
+
+    let response = request
+        .json(body)
+        .send()
+        .await
+        .map_err(|e| MCPError::Transport(format!("{} request failed: {}", provider, e)))?;
+    let status = response.status();
+    if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+        return Err(MCPError::Unauthorized(format!(
+            "{} rejected the API key: HTTP {}",
+            provider, status
+        )));
+    }
+    if !status.is_success() {
+        let text = response.text().await.unwrap_or_default();
+        return Err(MCPError::Transport(format!(
+            "{} request failed: HTTP {}: {}",
+            provider, status, text
+        )));
+    }
+    response
+        .json()
+        .await
+        .map_err(|e| MCPError::Protocol(format!("Invalid {} response: {}", provider, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::server::ModelHint;
+
+    #[test]
+    fn test_select_model() {
+        let models = ModelSelector::new([
+            ModelProfile::new("claude-sonnet-4").with_scores(0.5, 0.5, 0.8),
+            ModelProfile::new("claude-haiku-3-5").with_scores(0.9, 0.9, 0.4),
+            ModelProfile::new("claude-opus-4").with_scores(0.1, 0.2, 1.0),
+        ]);
+        let preferences = |hints: &[&str], cost, speed, intelligence| ModelPreferences {
+            hints: Some(
+                hints
+                    .iter()
+                    .map(|name| ModelHint {
+                        name: Some(name.to_string()),
+                    })
+                    .collect(),
+            ),
+            cost_priority: Some(cost),
+            speed_priority: Some(speed),
+            intelligence_priority: Some(intelligence),
+        };
+
+        assert_eq!(models.select(None), Some("claude-sonnet-4"));
+        assert_eq!(
+            models.select(Some(&preferences(&["gpt-4o", "haiku"], 0.0, 0.0, 1.0))),
+            Some("claude-haiku-3-5")
+        );
+        assert_eq!(
+            models.select(Some(&preferences(&["gpt-4o"], 0.0, 0.0, 1.0))),
+            Some("claude-opus-4")
+        );
+        assert_eq!(
+            models.select(Some(&preferences(&[], 0.8, 0.5, 0.2))),
+            Some("claude-haiku-3-5")
+        );
+        assert_eq!(ModelSelector::default().select(None), None);
+    }
+}
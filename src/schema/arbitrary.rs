@@ -0,0 +1,404 @@
+//! Property-test strategies for the protocol types
+//!
+//! Available with the `test-util` feature. Every type here implements
+//! [`proptest::arbitrary::Arbitrary`], so `any::<Tool>()` generates tools,
+//! which makes it easy to check that a type round-trips through JSON:
+//!
+//! ```ignore
+//! use mcpr::Tool;
+//! use proptest::prelude::*;
+//!
+//! proptest! {
+//!     #[test]
+//!     fn tool_round_trips(tool in any::<Tool>()) {
+//!         let json = serde_json::to_value(&tool).unwrap();
+//!         prop_assert_eq!(serde_json::from_value::<Tool>(json).unwrap(), tool);
+//!     }
+//! }
+//! ```
+
+use super::{
+    client::GetPromptResult,
+    common::{
+        Annotations, AudioContent, BlobResourceContents, EmbeddedResource, ImageContent,
+        Implementation, Prompt, PromptArgument, PromptMessage, PromptMessageContent, Resource,
+        ResourceContents, Role, TextContent, TextResourceContents, Tool, ToolAnnotations,
+        ToolInputSchema,
+    },
+    server::{
+        CallToolResult, InitializeResult, PromptsCapability, ResourcesCapability,
+        ServerCapabilities, ToolResultContent, ToolsCapability,
+    },
+};
+use proptest::{
+    arbitrary::Arbitrary,
+    collection::{hash_map, vec},
+    option,
+    prelude::*,
+    strategy::BoxedStrategy,
+};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Short printable strings, which keep failures readable
+fn text() -> impl Strategy<Value = String> {
+    "[a-zA-Z0-9 _./-]{0,16}"
+}
+
+/// Arbitrary JSON values, nested a few levels deep
+pub fn json_value() -> impl Strategy<Value = Value> {
+    let leaf = prop_oneof![
+        Just(Value::Null),
+        any::<bool>().prop_map(Value::Bool),
+        any::<i64>().prop_map(Value::from),
+        text().prop_map(Value::String),
+    ];
+    leaf.prop_recursive(3, 16, 4, |inner| {
+        prop_oneof![
+            vec(inner.clone(), 0..4).prop_map(Value::Array),
+            hash_map(text(), inner, 0..4).prop_map(|map| Value::Object(map.into_iter().collect())),
+        ]
+    })
+}
+
+/// JSON objects, for fields where `null` would not survive a round trip
+fn json_object() -> impl Strategy<Value = Value> {
+    hash_map(text(), json_value(), 0..4).prop_map(|map| Value::Object(map.into_iter().collect()))
+}
+
+fn json_map() -> impl Strategy<Value = HashMap<String, Value>> {
+    hash_map(text(), json_value(), 0..4)
+}
+
+/// Implement `Arbitrary` for a type from a strategy expression
+macro_rules! arbitrary {
+    ($ty:ty, $strategy:expr) => {
+        impl Arbitrary for $ty {
+            type Parameters = ();
+            type Strategy = BoxedStrategy<Self>;
+
+            fn arbitrary_with(_: Self::Parameters) -> Self::Strategy {
+                $strategy.boxed()
+            }
+        }
+    };
+}
+
+arbitrary!(Role, prop_oneof![Just(Role::User), Just(Role::Assistant)]);
+
+arbitrary!(
+    Annotations,
+    (
+        option::of(vec(any::<Role>(), 0..3)),
+        option::of(0.0f32..=1.0)
+    )
+        .prop_map(|(audience, priority)| Annotations { audience, priority })
+);
+
+arbitrary!(
+    Implementation,
+    (text(), text()).prop_map(|(name, version)| Implementation { name, version })
+);
+
+arbitrary!(
+    TextContent,
+    (text(), option::of(any::<Annotations>())).prop_map(|(text, annotations)| TextContent {
+        r#type: "text".to_string(),
+        text,
+        annotations,
+    })
+);
+
+arbitrary!(
+    ImageContent,
+    (text(), text(), option::of(any::<Annotations>())).prop_map(
+        |(data, mime_type, annotations)| ImageContent {
+            r#type: "image".to_string(),
+            data,
+            mime_type,
+            annotations,
+        }
+    )
+);
+
+arbitrary!(
+    AudioContent,
+    (text(), text(), option::of(any::<Annotations>())).prop_map(
+        |(data, mime_type, annotations)| AudioContent {
+            r#type: "audio".to_string(),
+            data,
+            mime_type,
+            annotations,
+        }
+    )
+);
+
+arbitrary!(
+    ResourceContents,
+    prop_oneof![
+        (text(), option::of(text()), text()).prop_map(|(uri, mime_type, text)| {
+            ResourceContents::Text(TextResourceContents {
+                uri,
+                mime_type,
+                text,
+            })
+        }),
+        (text(), option::of(text()), text()).prop_map(|(uri, mime_type, blob)| {
+            ResourceContents::Blob(BlobResourceContents {
+                uri,
+                mime_type,
+                blob,
+            })
+        }),
+    ]
+);
+
+arbitrary!(
+    EmbeddedResource,
+    (any::<ResourceContents>(), option::of(any::<Annotations>())).prop_map(
+        |(resource, annotations)| EmbeddedResource {
+            r#type: "resource".to_string(),
+            resource,
+            annotations,
+        }
+    )
+);
+
+arbitrary!(
+    ToolResultContent,
+    prop_oneof![
+        any::<TextContent>().prop_map(ToolResultContent::Text),
+        any::<ImageContent>().prop_map(ToolResultContent::Image),
+        any::<AudioContent>().prop_map(ToolResultContent::Audio),
+        any::<EmbeddedResource>().prop_map(ToolResultContent::Resource),
+    ]
+);
+
+arbitrary!(
+    PromptMessageContent,
+    prop_oneof![
+        any::<TextContent>().prop_map(PromptMessageContent::Text),
+        any::<ImageContent>().prop_map(PromptMessageContent::Image),
+        any::<EmbeddedResource>().prop_map(PromptMessageContent::Resource),
+    ]
+);
+
+arbitrary!(
+    CallToolResult,
+    (
+        vec(any::<ToolResultContent>(), 0..4),
+        option::of(json_object()),
+        option::of(any::<bool>())
+    )
+        .prop_map(|(content, structured_content, is_error)| CallToolResult {
+            content,
+            structured_content,
+            is_error,
+        })
+);
+
+arbitrary!(
+    ToolInputSchema,
+    (option::of(json_map()), option::of(vec(text(), 0..4))).prop_map(|(properties, required)| {
+        ToolInputSchema {
+            r#type: "object".to_string(),
+            properties,
+            required,
+            keywords: serde_json::Map::new(),
+        }
+    })
+);
+
+arbitrary!(
+    ToolAnnotations,
+    (
+        option::of(text()),
+        option::of(any::<bool>()),
+        option::of(any::<bool>()),
+        option::of(any::<bool>()),
+        option::of(any::<bool>()),
+    )
+        .prop_map(|(title, read_only, destructive, idempotent, open_world)| {
+            ToolAnnotations {
+                title,
+                read_only_hint: read_only,
+                destructive_hint: destructive,
+                idempotent_hint: idempotent,
+                open_world_hint: open_world,
+            }
+        })
+);
+
+arbitrary!(
+    Tool,
+    (
+        text(),
+        option::of(text()),
+        any::<ToolInputSchema>(),
+        option::of(json_object()),
+        option::of(any::<ToolAnnotations>()),
+    )
+        .prop_map(
+            |(name, description, input_schema, output_schema, annotations)| Tool {
+                name,
+                description,
+                input_schema,
+                output_schema,
+                annotations,
+            }
+        )
+);
+
+arbitrary!(
+    Resource,
+    (
+        text(),
+        text(),
+        option::of(text()),
+        option::of(text()),
+        option::of(any::<u64>()),
+        option::of(any::<Annotations>()),
+    )
+        .prop_map(
+            |(uri, name, description, mime_type, size, annotations)| Resource {
+                uri,
+                name,
+                description,
+                mime_type,
+                size,
+                annotations,
+            }
+        )
+);
+
+arbitrary!(
+    PromptArgument,
+    (text(), option::of(text()), option::of(any::<bool>())).prop_map(
+        |(name, description, required)| PromptArgument {
+            name,
+            description,
+            required,
+        }
+    )
+);
+
+arbitrary!(
+    Prompt,
+    (
+        text(),
+        option::of(text()),
+        option::of(vec(any::<PromptArgument>(), 0..3)),
+    )
+        .prop_map(|(name, description, arguments)| Prompt {
+            name,
+            description,
+            arguments,
+        })
+);
+
+arbitrary!(
+    PromptMessage,
+    (any::<Role>(), any::<PromptMessageContent>())
+        .prop_map(|(role, content)| PromptMessage { role, content })
+);
+
+arbitrary!(
+    GetPromptResult,
+    (option::of(text()), vec(any::<PromptMessage>(), 0..4)).prop_map(|(description, messages)| {
+        GetPromptResult {
+            description,
+            messages,
+        }
+    })
+);
+
+arbitrary!(
+    ServerCapabilities,
+    (
+        option::of(json_map()),
+        option::of(json_object()),
+        option::of(option::of(any::<bool>())),
+        option::of((option::of(any::<bool>()), option::of(any::<bool>()))),
+        option::of(option::of(any::<bool>())),
+        option::of(json_object()),
+    )
+        .prop_map(
+            |(experimental, logging, prompts, resources, tools, completions)| ServerCapabilities {
+                experimental,
+                logging,
+                prompts: prompts.map(|list_changed| PromptsCapability { list_changed }),
+                resources: resources.map(|(subscribe, list_changed)| ResourcesCapability {
+                    subscribe,
+                    list_changed,
+                }),
+                tools: tools.map(|list_changed| ToolsCapability { list_changed }),
+                completions,
+            }
+        )
+);
+
+arbitrary!(
+    InitializeResult,
+    (
+        text(),
+        any::<ServerCapabilities>(),
+        any::<Implementation>(),
+        option::of(text()),
+    )
+        .prop_map(
+            |(protocol_version, capabilities, server_info, instructions)| InitializeResult {
+                protocol_version,
+                capabilities,
+                server_info,
+                instructions,
+            }
+        )
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{de::DeserializeOwned, Serialize};
+
+    fn round_trip<T: Serialize + DeserializeOwned>(value: &T) -> T {
+        let json = serde_json::to_string(value).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    proptest! {
+        #[test]
+        fn tool_round_trips(value in any::<Tool>()) {
+            prop_assert_eq!(round_trip(&value), value);
+        }
+
+        #[test]
+        fn tool_result_content_round_trips(value in any::<ToolResultContent>()) {
+            prop_assert_eq!(round_trip(&value), value);
+        }
+
+        #[test]
+        fn call_tool_result_round_trips(value in any::<CallToolResult>()) {
+            prop_assert_eq!(round_trip(&value), value);
+        }
+
+        #[test]
+        fn initialize_result_round_trips(value in any::<InitializeResult>()) {
+            prop_assert_eq!(round_trip(&value), value);
+        }
+
+        #[test]
+        fn resource_round_trips(value in any::<Resource>()) {
+            prop_assert_eq!(round_trip(&value), value);
+        }
+
+        #[test]
+        fn prompt_round_trips(value in any::<Prompt>()) {
+            prop_assert_eq!(round_trip(&value), value);
+        }
+
+        #[test]
+        fn get_prompt_result_round_trips(value in any::<GetPromptResult>()) {
+            prop_assert_eq!(round_trip(&value), value);
+        }
+    }
+}
@@ -5,88 +5,102 @@ use serde_json::Value;
 use std::collections::HashMap;
 
 use super::common::{
-    BlobResourceContents, Cursor, Implementation, LoggingLevel, ProgressToken, Prompt,
-    PromptMessage, Resource, ResourceTemplate, Root, TextResourceContents, Tool,
+    Cursor, Implementation, LoggingLevel, ProgressToken, Prompt, PromptMessage, Resource,
+    ResourceContents, ResourceTemplate, Root, Tool,
 };
 use super::json_rpc::RequestId;
 
 /// Client capabilities
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct ClientCapabilities {
     /// Experimental, non-standard capabilities that the client supports.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub experimental: Option<HashMap<String, Value>>,
 
     /// Present if the client supports listing roots.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub roots: Option<RootsCapability>,
 
     /// Present if the client supports sampling from an LLM.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub sampling: Option<Value>,
+
+    /// Present if the client can ask its user for input on the server's behalf.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub elicitation: Option<Value>,
 }
 
 /// Roots capability
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct RootsCapability {
     /// Whether the client supports notifications for changes to the roots list.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub list_changed: Option<bool>,
 }
 
 /// This request is sent from the client to the server when it first connects.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct InitializeRequest {
     pub method: String,
     pub params: InitializeParams,
 }
 
 /// Parameters for initialize request
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Deserialization is lenient: `protocol_version` is accepted as an alias of
+/// `protocolVersion`, and missing capabilities or client info default to empty.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct InitializeParams {
     /// The latest version of the Model Context Protocol that the client supports.
+    #[serde(alias = "protocol_version")]
     pub protocol_version: String,
 
     /// Client capabilities
+    #[serde(default)]
     pub capabilities: ClientCapabilities,
 
     /// Client information
+    #[serde(default)]
     pub client_info: Implementation,
 }
 
 /// This notification is sent from the client to the server after initialization has finished.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct InitializedNotification {
     pub method: String,
 }
 
 /// A notification which can be sent by either side to indicate that it is cancelling a previously-issued request.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CancelledNotification {
     pub method: String,
     pub params: CancelledParams,
 }
 
 /// Parameters for cancelled notification
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct CancelledParams {
     /// The ID of the request to cancel.
     pub request_id: RequestId,
 
     /// An optional string describing the reason for the cancellation.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub reason: Option<String>,
 }
 
 /// An out-of-band notification used to inform the receiver of a progress update for a long-running request.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ProgressNotification {
     pub method: String,
     pub params: ProgressParams,
 }
 
 /// Parameters for progress notification
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ProgressParams {
     /// The progress token which was given in the initial request.
     pub progress_token: ProgressToken,
@@ -95,37 +109,39 @@ pub struct ProgressParams {
     pub progress: f64,
 
     /// Total number of items to process (or total progress required), if known.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub total: Option<f64>,
 }
 
 /// A ping, issued by either the server or the client, to check that the other party is still alive.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PingRequest {
     pub method: String,
 }
 
 /// Sent from the client to request a list of resources the server has.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ListResourcesRequest {
     pub method: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub params: Option<PaginatedParams>,
 }
 
 /// Parameters for paginated requests
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct PaginatedParams {
     /// An opaque token representing the current pagination position.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub cursor: Option<Cursor>,
 }
 
 /// The server's response to a resources/list request from the client.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ListResourcesResult {
     /// An opaque token representing the pagination position after the last returned result.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub next_cursor: Option<Cursor>,
 
     /// The list of resources
@@ -133,18 +149,19 @@ pub struct ListResourcesResult {
 }
 
 /// Sent from the client to request a list of resource templates the server has.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ListResourceTemplatesRequest {
     pub method: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub params: Option<PaginatedParams>,
 }
 
 /// The server's response to a resources/templates/list request from the client.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ListResourceTemplatesResult {
     /// An opaque token representing the pagination position after the last returned result.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub next_cursor: Option<Cursor>,
 
     /// The list of resource templates
@@ -152,74 +169,170 @@ pub struct ListResourceTemplatesResult {
 }
 
 /// Sent from the client to the server, to read a specific resource URI.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ReadResourceRequest {
     pub method: String,
     pub params: ReadResourceParams,
 }
 
 /// Parameters for read resource request
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ReadResourceParams {
     /// The URI of the resource to read.
     pub uri: String,
+
+    /// Content negotiation for resources with several representations.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub _meta: Option<ReadResourceMeta>,
+}
+
+impl ReadResourceParams {
+    /// The MIME types the client accepts, most preferred first
+    ///
+    /// Empty when the client did not state a preference.
+    pub fn accept(&self) -> &[String] {
+        self._meta.as_ref().map_or(&[], |meta| &meta.accept)
+    }
+
+    /// Choose which of the available MIME types to serve
+    ///
+    /// The client's accepted types are tried in order, and the first available
+    /// type matching one of them wins. Accepted types may be wildcards such as
+    /// `text/*` or `*/*`. When the client stated no preference, or nothing
+    /// matches, the first available type is the default.
+    pub fn negotiate<'a>(&self, available: &[&'a str]) -> Option<&'a str> {
+        self.accept()
+            .iter()
+            .find_map(|accepted| {
+                available
+                    .iter()
+                    .copied()
+                    .find(|mime_type| mime_type_matches(accepted, mime_type))
+            })
+            .or_else(|| available.first().copied())
+    }
+}
+
+/// Whether a MIME type matches an accepted type, which may be a wildcard
+fn mime_type_matches(accepted: &str, mime_type: &str) -> bool {
+    let essence = |t: &str| {
+        t.split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase()
+    };
+    let (accepted, mime_type) = (essence(accepted), essence(mime_type));
+
+    match accepted.split_once('/') {
+        _ if accepted == "*/*" || accepted == mime_type => true,
+        Some((kind, "*")) => mime_type
+            .split_once('/')
+            .is_some_and(|(mime_kind, _)| mime_kind == kind),
+        _ => false,
+    }
+}
+
+/// `_meta` fields of a resources/read request
+///
+/// Listing MIME types in `accept` asks the server for a particular
+/// representation of the resource, like the HTTP `Accept` header but without
+/// quality values: the order of the list is the order of preference. Servers
+/// that offer a single representation ignore it.
+///
+/// `chunkSize` asks for the resource in parts of at most that many bytes,
+/// and `cursor` for the part after the one that returned it. Servers that
+/// do not stream the resource ignore both and return it whole.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadResourceMeta {
+    /// Acceptable MIME types, most preferred first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub accept: Vec<String>,
+    /// Largest part of the resource to return, in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_size: Option<usize>,
+    /// Continue a chunked read where the previous part ended
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<Cursor>,
 }
 
 /// The server's response to a resources/read request from the client.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ReadResourceResult {
     pub contents: Vec<ResourceContent>,
+
+    /// Where a chunked read continues, unless this was the last part
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub _meta: Option<ReadResourceResultMeta>,
 }
 
-/// Resource content
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(untagged)]
-pub enum ResourceContent {
-    Text(TextResourceContents),
-    Blob(BlobResourceContents),
+/// `_meta` fields of a resources/read result
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadResourceResultMeta {
+    /// Cursor of the next part of a chunked read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<Cursor>,
+}
+
+impl ReadResourceResult {
+    /// The text of the first text contents, if any
+    pub fn text(&self) -> Option<&str> {
+        self.contents.iter().find_map(ResourceContents::text)
+    }
+
+    /// The cursor of the next part of a chunked read, if there is one
+    pub fn next_cursor(&self) -> Option<&Cursor> {
+        self._meta.as_ref()?.next_cursor.as_ref()
+    }
 }
 
+/// Resource content, the same as [`ResourceContents`]
+pub type ResourceContent = ResourceContents;
+
 /// Sent from the client to request resources/updated notifications from the server.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SubscribeRequest {
     pub method: String,
     pub params: SubscribeParams,
 }
 
 /// Parameters for subscribe request
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SubscribeParams {
     /// The URI of the resource to subscribe to.
     pub uri: String,
 }
 
 /// Sent from the client to request cancellation of resources/updated notifications from the server.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct UnsubscribeRequest {
     pub method: String,
     pub params: UnsubscribeParams,
 }
 
 /// Parameters for unsubscribe request
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct UnsubscribeParams {
     /// The URI of the resource to unsubscribe from.
     pub uri: String,
 }
 
 /// Sent from the client to request a list of prompts and prompt templates the server has.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ListPromptsRequest {
     pub method: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub params: Option<PaginatedParams>,
 }
 
 /// The server's response to a prompts/list request from the client.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ListPromptsResult {
     /// An opaque token representing the pagination position after the last returned result.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub next_cursor: Option<Cursor>,
 
     /// The list of prompts
@@ -227,47 +340,59 @@ pub struct ListPromptsResult {
 }
 
 /// Used by the client to get a prompt provided by the server.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GetPromptRequest {
     pub method: String,
     pub params: GetPromptParams,
 }
 
 /// Parameters for get prompt request
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GetPromptParams {
     /// The name of the prompt or prompt template.
     pub name: String,
 
     /// Arguments to use for templating the prompt.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub arguments: Option<HashMap<String, String>>,
 }
 
 /// The server's response to a prompts/get request from the client.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GetPromptResult {
     /// An optional description for the prompt.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
 
     /// The prompt messages
     pub messages: Vec<PromptMessage>,
 }
 
+impl GetPromptResult {
+    /// Render the prompt as a text transcript, one role-marked entry per message
+    pub fn to_transcript(&self) -> String {
+        self.messages
+            .iter()
+            .map(PromptMessage::to_transcript)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
 /// Sent from the client to request a list of tools the server has.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ListToolsRequest {
     pub method: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub params: Option<PaginatedParams>,
 }
 
 /// The server's response to a tools/list request from the client.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ListToolsResult {
     /// An opaque token representing the pagination position after the last returned result.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub next_cursor: Option<Cursor>,
 
     /// The list of tools
@@ -275,48 +400,83 @@ pub struct ListToolsResult {
 }
 
 /// Used by the client to invoke a tool provided by the server.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CallToolRequest {
     pub method: String,
     pub params: CallToolParams,
 }
 
 /// Parameters for call tool request
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CallToolParams {
     /// The name of the tool to call
     pub name: String,
 
     /// Arguments for the tool
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub arguments: Option<HashMap<String, Value>>,
 }
 
+/// Parameters for an `uploads/chunk` request
+///
+/// Part of the chunked-upload convention, used when a `tools/call` request
+/// would exceed the `maxMessageSize` a server advertises under the
+/// experimental `chunkedUpload` capability. The client serializes the tool
+/// arguments to a JSON string and sends it in order as a sequence of
+/// `uploads/chunk` requests that share an `uploadId`, numbering them from 0
+/// in `chunkIndex` and giving their total in `chunkCount`. It then commits
+/// the upload with a `tools/call` request that carries no `arguments`, only
+/// `_meta` with the `uploadId` and the `chunkCount`. The server concatenates
+/// the chunks and parses the result as the arguments. Servers bound the size,
+/// chunks and number of uploads, and drop uploads left uncommitted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploadChunkParams {
+    pub _meta: UploadMeta,
+    /// A piece of the serialized arguments
+    pub data: String,
+}
+
+/// `_meta` fields of the chunked-upload convention, see [`UploadChunkParams`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadMeta {
+    /// Identifies the upload the chunk or commit belongs to
+    pub upload_id: String,
+    /// Position of the chunk, set on `uploads/chunk` requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_index: Option<u32>,
+    /// Number of chunks of the upload, set on the committing `tools/call`
+    /// request and optionally on each chunk
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_count: Option<u32>,
+}
+
 /// A request from the client to the server, to enable or adjust logging.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SetLevelRequest {
     pub method: String,
     pub params: SetLevelParams,
 }
 
 /// Parameters for set level request
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SetLevelParams {
     /// The level of logging that the client wants to receive from the server.
     pub level: LoggingLevel,
 }
 
 /// A request from the client to the server, to ask for completion options.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CompleteRequest {
     pub method: String,
     pub params: CompleteParams,
 }
 
 /// Parameters for complete request
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CompleteParams {
     /// Reference to a prompt or resource
+    #[serde(rename = "ref")]
     pub ref_: Reference,
 
     /// The argument's information
@@ -324,15 +484,33 @@ pub struct CompleteParams {
 }
 
 /// Reference to a prompt or resource
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Reference {
     Prompt(PromptReference),
     Resource(ResourceReference),
 }
 
+impl Reference {
+    /// Reference the prompt named `name`
+    pub fn prompt(name: &str) -> Self {
+        Reference::Prompt(PromptReference {
+            r#type: "ref/prompt".to_string(),
+            name: name.to_string(),
+        })
+    }
+
+    /// Reference the resource or resource template at `uri`
+    pub fn resource(uri: &str) -> Self {
+        Reference::Resource(ResourceReference {
+            r#type: "ref/resource".to_string(),
+            uri: uri.to_string(),
+        })
+    }
+}
+
 /// Identifies a prompt.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PromptReference {
     pub r#type: String,
 
@@ -341,7 +519,7 @@ pub struct PromptReference {
 }
 
 /// A reference to a resource or resource template definition.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ResourceReference {
     pub r#type: String,
 
@@ -350,7 +528,7 @@ pub struct ResourceReference {
 }
 
 /// Argument information for completion
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ArgumentInfo {
     /// The name of the argument
     pub name: String,
@@ -360,13 +538,13 @@ pub struct ArgumentInfo {
 }
 
 /// The client's response to a roots/list request from the server.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ListRootsResult {
     pub roots: Vec<Root>,
 }
 
 /// A notification from the client to the server, informing it that the list of roots has changed.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RootsListChangedNotification {
     pub method: String,
 }
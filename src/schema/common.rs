@@ -1,5 +1,8 @@
 //! Common types used throughout the MCP schema
 
+use super::uri_template::UriTemplate;
+use crate::error::MCPError;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
@@ -19,7 +22,9 @@ pub type Cursor = String;
 ///
 /// These map to syslog message severities, as specified in RFC-5424:
 /// https://datatracker.ietf.org/doc/html/rfc5424#section-6.2.1
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+///
+/// Levels are ordered from the least to the most severe.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum LoggingLevel {
     Debug,
@@ -40,32 +45,42 @@ pub enum Role {
     Assistant,
 }
 
+impl Role {
+    /// The role's wire name, used as the marker in transcripts
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
+}
+
 /// Base for objects that include optional annotations for the client.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Annotated {
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub annotations: Option<Annotations>,
 }
 
 /// Annotations for objects
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Annotations {
     /// Describes who the intended customer of this object or data is.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub audience: Option<Vec<Role>>,
 
     /// Describes how important this data is for operating the server.
     /// A value of 1 means "most important," and indicates that the data is
     /// effectively required, while 0 means "least important," and indicates that
     /// the data is entirely optional.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub priority: Option<f32>,
 }
 
 /// Describes an implementation of MCP.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Implementation {
     pub name: String,
@@ -73,49 +88,113 @@ pub struct Implementation {
 }
 
 /// Text provided to or from an LLM.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TextContent {
     pub r#type: String,
     pub text: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub annotations: Option<Annotations>,
 }
 
 /// An image provided to or from an LLM.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ImageContent {
     pub r#type: String,
     pub data: String,
     pub mime_type: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<Annotations>,
+}
+
+/// Audio provided to or from an LLM.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioContent {
+    pub r#type: String,
+    pub data: String,
+    pub mime_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub annotations: Option<Annotations>,
 }
 
 /// The contents of a resource, embedded into a prompt or tool call result.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct EmbeddedResource {
     pub r#type: String,
     pub resource: ResourceContents,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub annotations: Option<Annotations>,
 }
 
 /// The contents of a specific resource or sub-resource.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum ResourceContents {
     Text(TextResourceContents),
     Blob(BlobResourceContents),
 }
 
+impl ResourceContents {
+    /// The URI of the resource
+    pub fn uri(&self) -> &str {
+        match self {
+            ResourceContents::Text(text) => &text.uri,
+            ResourceContents::Blob(blob) => &blob.uri,
+        }
+    }
+
+    /// The MIME type of the resource, if known
+    pub fn mime_type(&self) -> Option<&str> {
+        match self {
+            ResourceContents::Text(text) => text.mime_type.as_deref(),
+            ResourceContents::Blob(blob) => blob.mime_type.as_deref(),
+        }
+    }
+
+    /// The text, for text contents
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            ResourceContents::Text(text) => Some(&text.text),
+            ResourceContents::Blob(_) => None,
+        }
+    }
+
+    /// The raw bytes: the UTF-8 text, or the decoded blob
+    pub fn bytes(&self) -> Result<Vec<u8>, MCPError> {
+        match self {
+            ResourceContents::Text(text) => Ok(text.text.clone().into_bytes()),
+            ResourceContents::Blob(blob) => BASE64
+                .decode(blob.blob.trim_end())
+                .map_err(|e| MCPError::Protocol(format!("Invalid blob for '{}': {}", blob.uri, e))),
+        }
+    }
+
+    /// Render the contents for a transcript, like [`EmbeddedResource::to_transcript`]
+    pub fn to_transcript(&self) -> String {
+        match self {
+            ResourceContents::Text(text) => format!("[resource {}]\n{}", text.uri, text.text),
+            ResourceContents::Blob(blob) => format!(
+                "[resource {}, {}, {}]",
+                blob.uri,
+                blob.mime_type
+                    .as_deref()
+                    .unwrap_or("application/octet-stream"),
+                format_size(base64_decoded_len(&blob.blob))
+            ),
+        }
+    }
+}
+
 /// Text resource contents
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct TextResourceContents {
     /// The URI of this resource.
     pub uri: String,
 
     /// The MIME type of this resource, if known.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub mime_type: Option<String>,
 
     /// The text of the item.
@@ -123,13 +202,14 @@ pub struct TextResourceContents {
 }
 
 /// Binary resource contents
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct BlobResourceContents {
     /// The URI of this resource.
     pub uri: String,
 
     /// The MIME type of this resource, if known.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub mime_type: Option<String>,
 
     /// A base64-encoded string representing the binary data of the item.
@@ -137,7 +217,8 @@ pub struct BlobResourceContents {
 }
 
 /// A known resource that the server is capable of reading.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Resource {
     /// The URI of this resource.
     pub uri: String,
@@ -146,23 +227,24 @@ pub struct Resource {
     pub name: String,
 
     /// A description of what this resource represents.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
 
     /// The MIME type of this resource, if known.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub mime_type: Option<String>,
 
     /// The size of the raw resource content, in bytes.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub size: Option<u64>,
 
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub annotations: Option<Annotations>,
 }
 
 /// A template description for resources available on the server.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ResourceTemplate {
     /// A URI template (according to RFC 6570) that can be used to construct resource URIs.
     pub uri_template: String,
@@ -171,57 +253,56 @@ pub struct ResourceTemplate {
     pub name: String,
 
     /// A description of what this template is for.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
 
     /// The MIME type for all resources that match this template.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub mime_type: Option<String>,
 
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub annotations: Option<Annotations>,
 }
 
 /// A prompt or prompt template that the server offers.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Prompt {
     /// The name of the prompt or prompt template.
     pub name: String,
 
     /// An optional description of what this prompt provides
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
 
     /// A list of arguments to use for templating the prompt.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub arguments: Option<Vec<PromptArgument>>,
 }
 
 /// Describes an argument that a prompt can accept.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PromptArgument {
     /// The name of the argument.
     pub name: String,
 
     /// A human-readable description of the argument.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
 
     /// Whether this argument must be provided.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub required: Option<bool>,
 }
 
 /// Describes a message returned as part of a prompt.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PromptMessage {
     pub role: Role,
-    #[serde(flatten)]
     pub content: PromptMessageContent,
 }
 
 /// Content of a prompt message
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum PromptMessageContent {
     Text(TextContent),
@@ -229,41 +310,190 @@ pub enum PromptMessageContent {
     Resource(EmbeddedResource),
 }
 
+impl PromptMessage {
+    /// Render the message as a transcript line, e.g. `user: hello`
+    pub fn to_transcript(&self) -> String {
+        let content = match &self.content {
+            PromptMessageContent::Text(text) => text.text.clone(),
+            PromptMessageContent::Image(image) => image.transcript_placeholder(),
+            PromptMessageContent::Resource(resource) => resource.to_transcript(),
+        };
+        format!("{}: {}", self.role.as_str(), content)
+    }
+}
+
+impl ImageContent {
+    /// A placeholder such as `[image/png, 12KB]` standing in for the image data
+    pub fn transcript_placeholder(&self) -> String {
+        format!(
+            "[{}, {}]",
+            self.mime_type,
+            format_size(base64_decoded_len(&self.data))
+        )
+    }
+}
+
+impl ResourceTemplate {
+    /// Build the URI of a resource from values for the template's variables
+    ///
+    /// Fails if the template is invalid or a variable has no value.
+    pub fn expand(&self, values: &HashMap<String, String>) -> Result<String, MCPError> {
+        UriTemplate::parse(&self.uri_template)?.expand(values)
+    }
+
+    /// The values of the template's variables if `uri` matches it
+    pub fn match_uri(&self, uri: &str) -> Option<HashMap<String, String>> {
+        UriTemplate::parse(&self.uri_template).ok()?.match_uri(uri)
+    }
+}
+
+impl AudioContent {
+    /// A placeholder such as `[audio/wav, 12KB]` standing in for the audio data
+    pub fn transcript_placeholder(&self) -> String {
+        format!(
+            "[{}, {}]",
+            self.mime_type,
+            format_size(base64_decoded_len(&self.data))
+        )
+    }
+}
+
+impl EmbeddedResource {
+    /// Render the resource for a transcript
+    ///
+    /// Text resources are inlined after a `[resource <uri>]` marker, while
+    /// binary resources are replaced by a placeholder with their type and size.
+    pub fn to_transcript(&self) -> String {
+        self.resource.to_transcript()
+    }
+}
+
+/// The number of bytes a base64 string decodes to
+pub(crate) fn base64_decoded_len(data: &str) -> usize {
+    let data = data.trim_end();
+    let padding = data.chars().rev().take_while(|&c| c == '=').count();
+    (data.len() * 3 / 4).saturating_sub(padding)
+}
+
+/// Format a byte count for humans, e.g. `512B` or `12KB`
+pub(crate) fn format_size(bytes: usize) -> String {
+    const KB: usize = 1024;
+    const MB: usize = 1024 * KB;
+    if bytes >= MB {
+        format!("{}MB", bytes.div_ceil(MB))
+    } else if bytes >= KB {
+        format!("{}KB", bytes.div_ceil(KB))
+    } else {
+        format!("{}B", bytes)
+    }
+}
+
 /// Definition for a tool the client can call.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Tool {
     /// The name of the tool.
     pub name: String,
 
     /// A human-readable description of the tool.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
 
     /// A JSON Schema object defining the expected parameters for the tool.
     pub input_schema: ToolInputSchema,
+
+    /// A JSON Schema object the tool's `structuredContent` conforms to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_schema: Option<Value>,
+
+    /// Hints about the tool's behavior, such as whether it only reads.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<ToolAnnotations>,
+}
+
+/// Hints about what a tool does, for clients deciding how to present and gate it
+///
+/// Hints are not guaranteed to be accurate; clients should only trust them
+/// from servers they trust. Unset hints take the defaults of the spec, see
+/// the accessors.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolAnnotations {
+    /// A human-readable title for the tool.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+
+    /// If true, the tool does not modify its environment.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_only_hint: Option<bool>,
+
+    /// If true, the tool may perform destructive updates, rather than only additive ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destructive_hint: Option<bool>,
+
+    /// If true, calling the tool again with the same arguments has no further effect.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotent_hint: Option<bool>,
+
+    /// If true, the tool interacts with external entities, like the web.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_world_hint: Option<bool>,
+}
+
+impl ToolAnnotations {
+    /// Annotations of a tool that only reads
+    pub fn read_only() -> Self {
+        Self {
+            read_only_hint: Some(true),
+            ..Self::default()
+        }
+    }
+
+    /// Whether the tool does not modify its environment, `false` by default
+    pub fn is_read_only(&self) -> bool {
+        self.read_only_hint.unwrap_or(false)
+    }
+
+    /// Whether the tool may destroy data, `true` by default for tools that are not read-only
+    pub fn is_destructive(&self) -> bool {
+        !self.is_read_only() && self.destructive_hint.unwrap_or(true)
+    }
+
+    /// Whether repeated calls with the same arguments have no further effect, `false` by default
+    pub fn is_idempotent(&self) -> bool {
+        self.is_read_only() || self.idempotent_hint.unwrap_or(false)
+    }
+
+    /// Whether the tool reaches beyond the server, `true` by default
+    pub fn is_open_world(&self) -> bool {
+        self.open_world_hint.unwrap_or(true)
+    }
 }
 
 /// JSON Schema for tool input
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ToolInputSchema {
     pub r#type: String,
 
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub properties: Option<HashMap<String, Value>>,
 
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub required: Option<Vec<String>>,
+
+    /// Other keywords of the schema, such as `additionalProperties` or `description`
+    #[serde(flatten)]
+    pub keywords: serde_json::Map<String, Value>,
 }
 
 /// Represents a root directory or file that the server can operate on.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Root {
     /// The URI identifying the root. This must start with file:// for now.
     pub uri: String,
 
     /// An optional name for the root.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
 }
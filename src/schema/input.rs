@@ -0,0 +1,214 @@
+//! JSON Schemas for tool arguments derived from Rust types
+//!
+//! [`SchemaType`] gives the schema of a value of a type, and [`ToolInput`]
+//! the input schema of a tool whose arguments are a struct. Both are
+//! implemented by `#[derive(ToolInput)]` from the `macros` feature:
+//!
+//! ```rust,ignore
+//! #[derive(Deserialize, ToolInput)]
+//! struct SearchArgs {
+//!     /// What to look for
+//!     query: String,
+//!     limit: Option<u32>,
+//! }
+//!
+//! let schema = SearchArgs::input_schema();
+//! assert_eq!(schema.required, Some(vec!["query".to_string()]));
+//! ```
+//!
+//! With the `schemars` feature, [`ToolArguments`] also takes the schema of
+//! a type deriving `schemars::JsonSchema`, descriptions included.
+
+use super::common::ToolInputSchema;
+use crate::error::MCPError;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+
+/// A type with a JSON Schema
+pub trait SchemaType {
+    /// The schema of values of this type
+    fn json_schema() -> Value;
+
+    /// Whether a tool argument of this type may be left out
+    fn optional() -> bool {
+        false
+    }
+}
+
+/// Arguments of a tool, described by an object schema
+pub trait ToolInput: SchemaType {
+    /// The input schema for a tool taking these arguments
+    fn input_schema() -> ToolInputSchema;
+}
+
+macro_rules! schema_type {
+    ($schema:literal: $($ty:ty),+) => {
+        $(impl SchemaType for $ty {
+            fn json_schema() -> Value {
+                json!({ "type": $schema })
+            }
+        })+
+    };
+}
+
+schema_type!("string": String, str, char);
+schema_type!("boolean": bool);
+schema_type!("integer": i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+schema_type!("number": f32, f64);
+
+impl SchemaType for Value {
+    fn json_schema() -> Value {
+        json!({})
+    }
+}
+
+impl<T: SchemaType + ?Sized> SchemaType for &T {
+    fn json_schema() -> Value {
+        T::json_schema()
+    }
+}
+
+impl<T: SchemaType> SchemaType for Option<T> {
+    fn json_schema() -> Value {
+        T::json_schema()
+    }
+
+    fn optional() -> bool {
+        true
+    }
+}
+
+impl<T: SchemaType> SchemaType for Vec<T> {
+    fn json_schema() -> Value {
+        json!({ "type": "array", "items": T::json_schema() })
+    }
+}
+
+impl<T: SchemaType> SchemaType for [T] {
+    fn json_schema() -> Value {
+        Vec::<T>::json_schema()
+    }
+}
+
+impl<T: SchemaType> SchemaType for HashMap<String, T> {
+    fn json_schema() -> Value {
+        json!({ "type": "object", "additionalProperties": T::json_schema() })
+    }
+}
+
+impl<T: SchemaType> SchemaType for BTreeMap<String, T> {
+    fn json_schema() -> Value {
+        HashMap::<String, T>::json_schema()
+    }
+}
+
+impl<T: SchemaType + ?Sized> SchemaType for Box<T> {
+    fn json_schema() -> Value {
+        T::json_schema()
+    }
+
+    fn optional() -> bool {
+        T::optional()
+    }
+}
+
+/// Arguments of a tool, with the input schema from [`ToolInput`] or `JsonSchema`
+///
+/// Implemented for every [`ToolInput`] type and, with the `schemars`
+/// feature, for every `schemars::JsonSchema` type. `M` only tells the two
+/// apart and is inferred; name it for a type implementing both.
+pub trait ToolArguments<M> {
+    /// The input schema for a tool taking these arguments
+    ///
+    /// Fails for types whose values are not JSON objects, which tool
+    /// arguments always are.
+    fn arguments_schema() -> Result<ToolInputSchema, MCPError>;
+}
+
+/// Marks arguments whose schema comes from [`ToolInput`]
+pub enum FromToolInput {}
+
+impl<T: ToolInput> ToolArguments<FromToolInput> for T {
+    fn arguments_schema() -> Result<ToolInputSchema, MCPError> {
+        Ok(T::input_schema())
+    }
+}
+
+/// Marks arguments whose schema comes from `schemars::JsonSchema`
+#[cfg(feature = "schemars")]
+pub enum FromJsonSchema {}
+
+#[cfg(feature = "schemars")]
+impl<T: schemars::JsonSchema> ToolArguments<FromJsonSchema> for T {
+    fn arguments_schema() -> Result<ToolInputSchema, MCPError> {
+        // Inlined, since an input schema has no definitions to refer to
+        let generator = schemars::gen::SchemaSettings::draft07()
+            .with(|settings| {
+                settings.inline_subschemas = true;
+                settings.meta_schema = None;
+            })
+            .into_generator();
+        let Value::Object(mut keywords) =
+            serde_json::to_value(generator.into_root_schema_for::<T>())?
+        else {
+            return Err(not_an_object::<T>());
+        };
+
+        // An object, or a choice between objects such as an enum with fields
+        let is_object = |schema: &Value| schema["type"] == "object";
+        let objects = match keywords.remove("type") {
+            Some(ty) => ty == "object",
+            None => ["oneOf", "anyOf"].iter().any(|keyword| {
+                keywords
+                    .get(*keyword)
+                    .and_then(Value::as_array)
+                    .is_some_and(|schemas| schemas.iter().all(is_object))
+            }),
+        };
+        if !objects {
+            return Err(not_an_object::<T>());
+        }
+
+        let properties = keywords
+            .remove("properties")
+            .map(serde_json::from_value)
+            .transpose()?;
+        let required = keywords
+            .remove("required")
+            .map(serde_json::from_value)
+            .transpose()?;
+        Ok(ToolInputSchema {
+            r#type: "object".to_string(),
+            properties,
+            required,
+            keywords,
+        })
+    }
+}
+
+/// The error for tool arguments of a type whose values are not objects
+#[cfg(feature = "schemars")]
+fn not_an_object<T>() -> MCPError {
+    MCPError::Protocol(format!(
+        "Tool arguments must be a JSON object, but {} is not one",
+        std::any::type_name::<T>()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_types() {
+        assert_eq!(
+            Vec::<Option<u8>>::json_schema(),
+            json!({ "type": "array", "items": { "type": "integer" } })
+        );
+        assert_eq!(
+            HashMap::<String, f64>::json_schema(),
+            json!({ "type": "object", "additionalProperties": { "type": "number" } })
+        );
+        assert!(Option::<String>::optional() && !String::optional());
+    }
+}
@@ -1,10 +1,10 @@
 //! JSON-RPC message types for MCP
 
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{value::RawValue, Map, Value};
 use std::collections::HashMap;
 
-use crate::constants::JSONRPC_VERSION;
+use crate::{constants::JSONRPC_VERSION, error::MCPError};
 
 /// A uniquely identifying ID for a request in JSON-RPC.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -15,7 +15,7 @@ pub enum RequestId {
 }
 
 /// JSON-RPC message types
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum JSONRPCMessage {
     Request(JSONRPCRequest),
@@ -25,28 +25,28 @@ pub enum JSONRPCMessage {
 }
 
 /// A request that expects a response.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct JSONRPCRequest {
     pub jsonrpc: String,
     pub id: RequestId,
     pub method: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub params: Option<Value>,
 }
 
 /// A notification which does not expect a response.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct JSONRPCNotification {
     pub jsonrpc: String,
     pub method: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub params: Option<Value>,
 }
 
 /// A successful (non-error) response to a request.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct JSONRPCResponse {
     pub jsonrpc: String,
@@ -55,7 +55,7 @@ pub struct JSONRPCResponse {
 }
 
 /// A response to a request that indicates an error occurred.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct JSONRPCError {
     pub jsonrpc: String,
@@ -64,7 +64,7 @@ pub struct JSONRPCError {
 }
 
 /// Error object in a JSON-RPC error response
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct JSONRPCErrorObject {
     /// The error type that occurred.
@@ -74,7 +74,7 @@ pub struct JSONRPCErrorObject {
     pub message: String,
 
     /// Additional information about the error.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub data: Option<Value>,
 }
 
@@ -85,53 +85,57 @@ pub mod error_codes {
     pub const METHOD_NOT_FOUND: i32 = -32601;
     pub const INVALID_PARAMS: i32 = -32602;
     pub const INTERNAL_ERROR: i32 = -32603;
+
+    /// MCP-specific: the requested resource does not exist or may not be read
+    pub const RESOURCE_NOT_FOUND: i32 = -32002;
 }
 
 /// Base request interface
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Request {
     pub method: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub params: Option<RequestParams>,
 }
 
 /// Request parameters
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RequestParams {
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub _meta: Option<RequestMeta>,
     #[serde(flatten)]
     pub extra: HashMap<String, Value>,
 }
 
 /// Request metadata
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct RequestMeta {
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub progress_token: Option<super::common::ProgressToken>,
 }
 
 /// Base notification interface
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Notification {
     pub method: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub params: Option<NotificationParams>,
 }
 
 /// Notification parameters
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct NotificationParams {
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub _meta: Option<HashMap<String, Value>>,
     #[serde(flatten)]
     pub extra: HashMap<String, Value>,
 }
 
 /// Base result interface
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Result {
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub _meta: Option<HashMap<String, Value>>,
     #[serde(flatten)]
     pub extra: HashMap<String, Value>,
@@ -140,6 +144,90 @@ pub struct Result {
 /// A response that indicates success but carries no data.
 pub type EmptyResult = Result;
 
+/// The `_meta` object of request params, notification params or a result
+///
+/// Holds fields outside the typed structs, such as tracing ids, tenant info
+/// or the progress token.
+pub type Meta = Map<String, Value>;
+
+/// The `_meta` object of `params` or a result, if it has one
+pub fn meta_of(value: &Value) -> Option<&Meta> {
+    value.get("_meta")?.as_object()
+}
+
+/// Add `meta` to the `_meta` of `params`, creating either if needed
+///
+/// Fields already in `_meta` are kept, so the progress token and other
+/// fields set for the request win over defaults. Params that are not an
+/// object, such as positional params, are left alone.
+pub fn merge_meta(params: Option<Value>, meta: &Meta) -> Option<Value> {
+    if meta.is_empty() {
+        return params;
+    }
+    let mut params = params.unwrap_or_else(|| Value::Object(Map::new()));
+    if let Some(object) = params.as_object_mut() {
+        let target = object
+            .entry("_meta")
+            .or_insert_with(|| Value::Object(Map::new()));
+        if let Some(target) = target.as_object_mut() {
+            for (key, value) in meta {
+                target.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+        }
+    }
+    Some(params)
+}
+
+/// A typed result along with the `_meta` it was sent with
+///
+/// Deserializing reads `_meta` next to the fields of `R`, so any typed call
+/// can return it, e.g. `client.call_tool::<_, WithMeta<CallToolResult>>(..)`.
+/// Serializing adds `meta` as `_meta` to the result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WithMeta<R> {
+    pub meta: Option<Meta>,
+    pub inner: R,
+}
+
+impl<R> WithMeta<R> {
+    /// Wrap `inner` without any `_meta`
+    pub fn new(inner: R) -> Self {
+        Self { meta: None, inner }
+    }
+
+    /// Set a `_meta` field
+    pub fn with_meta(mut self, key: &str, value: impl Into<Value>) -> Self {
+        self.meta
+            .get_or_insert_with(Map::new)
+            .insert(key.to_string(), value.into());
+        self
+    }
+
+    /// The `_meta` field `key`, if it was sent
+    pub fn meta(&self, key: &str) -> Option<&Value> {
+        self.meta.as_ref()?.get(key)
+    }
+}
+
+impl<'de, R: serde::de::DeserializeOwned> Deserialize<'de> for WithMeta<R> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        let meta = meta_of(&value).cloned();
+        let inner = R::deserialize(value).map_err(serde::de::Error::custom)?;
+        Ok(Self { meta, inner })
+    }
+}
+
+impl<R: Serialize> Serialize for WithMeta<R> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut value = serde_json::to_value(&self.inner).map_err(serde::ser::Error::custom)?;
+        if let (Some(meta), Some(object)) = (&self.meta, value.as_object_mut()) {
+            object.insert("_meta".to_string(), Value::Object(meta.clone()));
+        }
+        value.serialize(serializer)
+    }
+}
+
 // Helper functions for creating JSON-RPC messages
 impl JSONRPCRequest {
     /// Create a new JSON-RPC request
@@ -151,6 +239,11 @@ impl JSONRPCRequest {
             params,
         }
     }
+
+    /// The `_meta` of the request's params, if it has any
+    pub fn meta(&self) -> Option<&Meta> {
+        meta_of(self.params.as_ref()?)
+    }
 }
 
 impl JSONRPCNotification {
@@ -162,6 +255,11 @@ impl JSONRPCNotification {
             params,
         }
     }
+
+    /// The `_meta` of the notification's params, if it has any
+    pub fn meta(&self) -> Option<&Meta> {
+        meta_of(self.params.as_ref()?)
+    }
 }
 
 impl JSONRPCResponse {
@@ -173,6 +271,11 @@ impl JSONRPCResponse {
             result,
         }
     }
+
+    /// The `_meta` of the result, if it has any
+    pub fn meta(&self) -> Option<&Meta> {
+        meta_of(&self.result)
+    }
 }
 
 impl JSONRPCError {
@@ -202,3 +305,114 @@ impl JSONRPCError {
         )
     }
 }
+
+impl From<JSONRPCErrorObject> for MCPError {
+    fn from(error: JSONRPCErrorObject) -> Self {
+        MCPError::Rpc {
+            code: error.code,
+            message: error.message,
+            data: error.data,
+        }
+    }
+}
+
+impl JSONRPCMessage {
+    /// The id of a request or response, or `None` for a notification
+    pub fn id(&self) -> Option<&RequestId> {
+        match self {
+            JSONRPCMessage::Request(req) => Some(&req.id),
+            JSONRPCMessage::Notification(_) => None,
+            JSONRPCMessage::Response(resp) => Some(&resp.id),
+            JSONRPCMessage::Error(err) => Some(&err.id),
+        }
+    }
+
+    /// The JSON-RPC version string the message was sent with
+    pub fn jsonrpc(&self) -> &str {
+        match self {
+            JSONRPCMessage::Request(req) => &req.jsonrpc,
+            JSONRPCMessage::Notification(notif) => &notif.jsonrpc,
+            JSONRPCMessage::Response(resp) => &resp.jsonrpc,
+            JSONRPCMessage::Error(err) => &err.jsonrpc,
+        }
+    }
+}
+
+/// A single JSON-RPC message, or a batch of messages sent as one array
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum JSONRPCPayload {
+    Single(JSONRPCMessage),
+    Batch(Vec<JSONRPCMessage>),
+}
+
+impl JSONRPCPayload {
+    /// The messages in the payload, in the order they were sent
+    pub fn into_messages(self) -> Vec<JSONRPCMessage> {
+        match self {
+            JSONRPCPayload::Single(message) => vec![message],
+            JSONRPCPayload::Batch(messages) => messages,
+        }
+    }
+}
+
+/// A message with its params, result or error left as raw JSON
+///
+/// For passing messages through without parsing their contents: only the
+/// envelope is read, and the rest is written back byte for byte.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawMessage {
+    pub jsonrpc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<RequestId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<Box<RawValue>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Box<RawValue>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<Box<RawValue>>,
+}
+
+/// A [`RawMessage`], or a batch of them sent as one array
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum RawPayload {
+    Single(RawMessage),
+    Batch(Vec<RawMessage>),
+}
+
+// Not derived, since an untagged enum buffers its input, which raw values
+// cannot be read back from
+impl<'de> Deserialize<'de> for RawPayload {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct PayloadVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for PayloadVisitor {
+            type Value = RawPayload;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a JSON-RPC message or batch")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                map: A,
+            ) -> std::result::Result<RawPayload, A::Error> {
+                RawMessage::deserialize(serde::de::value::MapAccessDeserializer::new(map))
+                    .map(RawPayload::Single)
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                seq: A,
+            ) -> std::result::Result<RawPayload, A::Error> {
+                Vec::deserialize(serde::de::value::SeqAccessDeserializer::new(seq))
+                    .map(RawPayload::Batch)
+            }
+        }
+
+        deserializer.deserialize_any(PayloadVisitor)
+    }
+}
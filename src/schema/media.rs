@@ -0,0 +1,324 @@
+//! Image and audio content built from and decoded to raw bytes
+//!
+//! [`ImageContent`] and [`AudioContent`] carry their data base64-encoded.
+//! The helpers here encode bytes or files into them, checking the size and
+//! sniffing the MIME type when none is given, and decode received content
+//! back into bytes:
+//!
+//! ```
+//! use mcpr::schema::server::ToolResultContent;
+//!
+//! let png = b"\x89PNG\r\n\x1a\n...";
+//! let content = ToolResultContent::image_from_bytes(png, None).unwrap();
+//! let ToolResultContent::Image(image) = &content else { unreachable!() };
+//! assert_eq!(image.mime_type, "image/png");
+//! assert_eq!(image.decode().unwrap(), png);
+//! ```
+
+use super::{
+    common::{AudioContent, ImageContent},
+    server::{CallToolResult, ToolResultContent},
+};
+use crate::error::MCPError;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use std::path::Path;
+
+/// Largest image or audio the helpers encode, before base64
+pub const MAX_MEDIA_SIZE: usize = 10 * 1024 * 1024;
+
+/// Kind of binary content, which decides the MIME types it accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MediaKind {
+    Image,
+    Audio,
+}
+
+impl MediaKind {
+    fn prefix(self) -> &'static str {
+        match self {
+            MediaKind::Image => "image/",
+            MediaKind::Audio => "audio/",
+        }
+    }
+
+    /// Check the size and settle the MIME type, then encode `bytes`
+    fn encode(
+        self,
+        bytes: &[u8],
+        mime_type: Option<&str>,
+        limit: usize,
+    ) -> Result<(String, String), MCPError> {
+        if bytes.len() > limit {
+            return Err(MCPError::MessageTooLarge { limit });
+        }
+        let mime_type = match mime_type {
+            Some(mime_type) => mime_type.trim().to_ascii_lowercase(),
+            None => sniff_mime_type(bytes)
+                .ok_or_else(|| {
+                    MCPError::Protocol(format!("Cannot tell the {}type of the data", self.prefix()))
+                })?
+                .to_string(),
+        };
+        if !mime_type.starts_with(self.prefix()) {
+            return Err(MCPError::Protocol(format!(
+                "Expected an {}* MIME type, got '{}'",
+                self.prefix(),
+                mime_type
+            )));
+        }
+        Ok((mime_type, BASE64.encode(bytes)))
+    }
+
+    /// Read a file, taking its MIME type from its contents or else its extension
+    fn encode_file(self, path: &Path) -> Result<(String, String), MCPError> {
+        let bytes = std::fs::read(path)
+            .map_err(|e| MCPError::Protocol(format!("Failed to read {}: {}", path.display(), e)))?;
+        let mime_type = sniff_mime_type(&bytes).or_else(|| mime_type_from_extension(path));
+        self.encode(&bytes, mime_type, MAX_MEDIA_SIZE)
+    }
+}
+
+/// The MIME type of image or audio data, from its leading bytes
+///
+/// Recognizes PNG, JPEG, GIF, WebP, BMP, WAV, MP3, OGG and FLAC.
+pub fn sniff_mime_type(bytes: &[u8]) -> Option<&'static str> {
+    let riff = |format: &[u8]| bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(format);
+    Some(match bytes {
+        [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, ..] => "image/png",
+        [0xff, 0xd8, 0xff, ..] => "image/jpeg",
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => "image/gif",
+        _ if riff(b"WEBP") => "image/webp",
+        [b'B', b'M', ..] => "image/bmp",
+        _ if riff(b"WAVE") => "audio/wav",
+        [b'I', b'D', b'3', ..] | [0xff, 0xe0..=0xff, ..] => "audio/mpeg",
+        [b'O', b'g', b'g', b'S', ..] => "audio/ogg",
+        [b'f', b'L', b'a', b'C', ..] => "audio/flac",
+        _ => return None,
+    })
+}
+
+/// The MIME type of an image or audio file from its extension
+fn mime_type_from_extension(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    Some(match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "svg" => "image/svg+xml",
+        "wav" => "audio/wav",
+        "mp3" => "audio/mpeg",
+        "ogg" | "oga" => "audio/ogg",
+        "flac" => "audio/flac",
+        "m4a" => "audio/mp4",
+        _ => return None,
+    })
+}
+
+fn decode(data: &str, mime_type: &str) -> Result<Vec<u8>, MCPError> {
+    let data: String = data.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+    BASE64
+        .decode(data)
+        .map_err(|e| MCPError::Protocol(format!("Invalid base64 {} data: {}", mime_type, e)))
+}
+
+impl ImageContent {
+    /// Encode an image, sniffing its MIME type from `bytes` when `mime_type` is `None`
+    ///
+    /// Fails if the image is larger than [`MAX_MEDIA_SIZE`], or its type is
+    /// unknown or not `image/*`.
+    pub fn from_bytes(bytes: &[u8], mime_type: Option<&str>) -> Result<Self, MCPError> {
+        Self::from_bytes_with_limit(bytes, mime_type, MAX_MEDIA_SIZE)
+    }
+
+    /// Encode an image of at most `limit` bytes, see [`ImageContent::from_bytes`]
+    pub fn from_bytes_with_limit(
+        bytes: &[u8],
+        mime_type: Option<&str>,
+        limit: usize,
+    ) -> Result<Self, MCPError> {
+        let (mime_type, data) = MediaKind::Image.encode(bytes, mime_type, limit)?;
+        Ok(Self::encoded(data, mime_type))
+    }
+
+    /// Read and encode an image file, typed by its contents or extension
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, MCPError> {
+        let (mime_type, data) = MediaKind::Image.encode_file(path.as_ref())?;
+        Ok(Self::encoded(data, mime_type))
+    }
+
+    fn encoded(data: String, mime_type: String) -> Self {
+        Self {
+            r#type: "image".to_string(),
+            data,
+            mime_type,
+            annotations: None,
+        }
+    }
+
+    /// The raw bytes of the image
+    pub fn decode(&self) -> Result<Vec<u8>, MCPError> {
+        decode(&self.data, &self.mime_type)
+    }
+}
+
+impl AudioContent {
+    /// Encode audio, sniffing its MIME type from `bytes` when `mime_type` is `None`
+    ///
+    /// Fails if the audio is larger than [`MAX_MEDIA_SIZE`], or its type is
+    /// unknown or not `audio/*`.
+    pub fn from_bytes(bytes: &[u8], mime_type: Option<&str>) -> Result<Self, MCPError> {
+        Self::from_bytes_with_limit(bytes, mime_type, MAX_MEDIA_SIZE)
+    }
+
+    /// Encode audio of at most `limit` bytes, see [`AudioContent::from_bytes`]
+    pub fn from_bytes_with_limit(
+        bytes: &[u8],
+        mime_type: Option<&str>,
+        limit: usize,
+    ) -> Result<Self, MCPError> {
+        let (mime_type, data) = MediaKind::Audio.encode(bytes, mime_type, limit)?;
+        Ok(Self::encoded(data, mime_type))
+    }
+
+    /// Read and encode an audio file, typed by its contents or extension
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, MCPError> {
+        let (mime_type, data) = MediaKind::Audio.encode_file(path.as_ref())?;
+        Ok(Self::encoded(data, mime_type))
+    }
+
+    fn encoded(data: String, mime_type: String) -> Self {
+        Self {
+            r#type: "audio".to_string(),
+            data,
+            mime_type,
+            annotations: None,
+        }
+    }
+
+    /// The raw bytes of the audio
+    pub fn decode(&self) -> Result<Vec<u8>, MCPError> {
+        decode(&self.data, &self.mime_type)
+    }
+}
+
+impl ToolResultContent {
+    /// Image content from bytes, see [`ImageContent::from_bytes`]
+    pub fn image_from_bytes(bytes: &[u8], mime_type: Option<&str>) -> Result<Self, MCPError> {
+        ImageContent::from_bytes(bytes, mime_type).map(Self::Image)
+    }
+
+    /// Image content from a file, see [`ImageContent::from_path`]
+    pub fn image_from_path(path: impl AsRef<Path>) -> Result<Self, MCPError> {
+        ImageContent::from_path(path).map(Self::Image)
+    }
+
+    /// Audio content from bytes, see [`AudioContent::from_bytes`]
+    pub fn audio_from_bytes(bytes: &[u8], mime_type: Option<&str>) -> Result<Self, MCPError> {
+        AudioContent::from_bytes(bytes, mime_type).map(Self::Audio)
+    }
+
+    /// Audio content from a file, see [`AudioContent::from_path`]
+    pub fn audio_from_path(path: impl AsRef<Path>) -> Result<Self, MCPError> {
+        AudioContent::from_path(path).map(Self::Audio)
+    }
+}
+
+/// Image or audio received in content, decoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedMedia {
+    /// MIME type the sender gave
+    pub mime_type: String,
+    /// The raw bytes
+    pub bytes: Vec<u8>,
+}
+
+impl CallToolResult {
+    /// The images of the result, decoded, in order
+    pub fn images(&self) -> Result<Vec<DecodedMedia>, MCPError> {
+        self.decode_media(|content| match content {
+            ToolResultContent::Image(image) => Some((&image.data, &image.mime_type)),
+            _ => None,
+        })
+    }
+
+    /// The audio clips of the result, decoded, in order
+    pub fn audio(&self) -> Result<Vec<DecodedMedia>, MCPError> {
+        self.decode_media(|content| match content {
+            ToolResultContent::Audio(audio) => Some((&audio.data, &audio.mime_type)),
+            _ => None,
+        })
+    }
+
+    fn decode_media<'a>(
+        &'a self,
+        select: impl Fn(&'a ToolResultContent) -> Option<(&'a String, &'a String)>,
+    ) -> Result<Vec<DecodedMedia>, MCPError> {
+        self.content
+            .iter()
+            .filter_map(select)
+            .map(|(data, mime_type)| {
+                Ok(DecodedMedia {
+                    mime_type: mime_type.clone(),
+                    bytes: decode(data, mime_type)?,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_and_decode_media() {
+        let png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR".to_vec();
+        let wav = b"RIFF\x24\x00\x00\x00WAVEfmt ".to_vec();
+        assert_eq!(sniff_mime_type(&png), Some("image/png"));
+        assert_eq!(sniff_mime_type(&wav), Some("audio/wav"));
+        assert_eq!(sniff_mime_type(b"hello"), None);
+
+        let result = CallToolResult {
+            content: vec![
+                ToolResultContent::image_from_bytes(&png, None).unwrap(),
+                ToolResultContent::audio_from_bytes(&wav, Some("Audio/WAV")).unwrap(),
+            ],
+            structured_content: None,
+            is_error: None,
+        };
+        let images = result.images().unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].bytes, png);
+        assert_eq!(result.audio().unwrap()[0].mime_type, "audio/wav");
+
+        assert!(ImageContent::from_bytes(b"hello", None).is_err());
+        assert!(ImageContent::from_bytes(&wav, Some("audio/wav")).is_err());
+        assert!(matches!(
+            AudioContent::from_bytes_with_limit(&wav, None, 4),
+            Err(MCPError::MessageTooLarge { limit: 4 })
+        ));
+
+        let mut image = ImageContent::from_bytes(&png, None).unwrap();
+        image.data.insert(4, '\n');
+        assert_eq!(image.decode().unwrap(), png);
+        image.data = "not base64!".to_string();
+        assert!(image.decode().is_err());
+    }
+
+    #[test]
+    fn test_media_from_path() {
+        let dir = std::env::temp_dir().join(format!("mcpr-media-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("icon.svg");
+        std::fs::write(&path, "<svg/>").unwrap();
+
+        let image = ImageContent::from_path(&path).unwrap();
+        assert_eq!(image.mime_type, "image/svg+xml");
+        assert_eq!(image.decode().unwrap(), b"<svg/>");
+        assert!(AudioContent::from_path(&path).is_err());
+        assert!(AudioContent::from_path(dir.join("missing.wav")).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
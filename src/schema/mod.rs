@@ -2,11 +2,191 @@
 
 pub mod client;
 pub mod common;
+pub mod input;
 pub mod json_rpc;
+pub mod media;
+pub mod parse;
 pub mod server;
+pub mod uri_template;
+pub mod validation;
+
+#[cfg(any(test, feature = "test-util"))]
+pub mod arbitrary;
 
 // Re-export all schema types
 pub use client::*;
 pub use common::*;
 pub use json_rpc::*;
+pub use media::*;
 pub use server::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::de::DeserializeOwned;
+    use serde_json::json;
+
+    fn parse<T: DeserializeOwned>(value: serde_json::Value) -> T {
+        serde_json::from_value(value).unwrap()
+    }
+
+    // Every result should accept the smallest payload the spec allows
+    #[test]
+    fn test_minimal_results() {
+        let init: InitializeResult = parse(json!({
+            "protocolVersion": "2024-11-05",
+            "capabilities": {},
+            "serverInfo": { "name": "server", "version": "1.0" }
+        }));
+        assert!(init.capabilities.tools.is_none() && init.instructions.is_none());
+
+        let tools: ListToolsResult = parse(json!({
+            "tools": [{ "name": "echo", "inputSchema": { "type": "object" } }]
+        }));
+        assert!(tools.next_cursor.is_none() && tools.tools[0].description.is_none());
+
+        let call: CallToolResult = parse(json!({ "content": [] }));
+        assert!(call.is_error.is_none() && call.structured_content.is_none());
+
+        let resources: ListResourcesResult = parse(json!({
+            "resources": [{ "uri": "file:///a.txt", "name": "a.txt" }]
+        }));
+        assert!(resources.resources[0].mime_type.is_none());
+
+        let templates: ListResourceTemplatesResult = parse(json!({
+            "resourceTemplates": [{ "uriTemplate": "file:///{path}", "name": "files" }]
+        }));
+        assert!(templates.resource_templates[0].description.is_none());
+
+        let read: ReadResourceResult = parse(json!({
+            "contents": [{ "uri": "file:///a.txt", "text": "hello" }]
+        }));
+        assert!(matches!(&read.contents[0], ResourceContent::Text(t) if t.mime_type.is_none()));
+
+        let prompts: ListPromptsResult = parse(json!({ "prompts": [{ "name": "greet" }] }));
+        assert!(prompts.prompts[0].arguments.is_none());
+
+        let prompt: GetPromptResult = parse(json!({
+            "messages": [{ "role": "user", "content": { "type": "text", "text": "hi" } }]
+        }));
+        assert!(prompt.description.is_none());
+
+        let complete: CompleteResult = parse(json!({ "completion": { "values": [] } }));
+        assert!(complete.completion.total.is_none() && complete.completion.has_more.is_none());
+
+        let message: CreateMessageResult = parse(json!({
+            "role": "assistant",
+            "content": { "type": "text", "text": "hi" },
+            "model": "model"
+        }));
+        assert!(message.stop_reason.is_none());
+
+        let roots: ListRootsResult = parse(json!({ "roots": [{ "uri": "file:///" }] }));
+        assert!(roots.roots[0].name.is_none());
+
+        let empty: EmptyResult = parse(json!({}));
+        assert!(empty._meta.is_none() && empty.extra.is_empty());
+    }
+
+    // Missing required fields should be reported by name
+    #[test]
+    fn test_missing_required_field() {
+        let error = serde_json::from_value::<ListToolsResult>(json!({})).unwrap_err();
+        assert!(error.to_string().contains("missing field `tools`"));
+
+        let error = serde_json::from_value::<ListToolsResult>(json!({
+            "tools": [{ "name": "echo" }]
+        }))
+        .unwrap_err();
+        assert!(error.to_string().contains("missing field `inputSchema`"));
+
+        let error = serde_json::from_value::<InitializeResult>(json!({
+            "protocolVersion": "2024-11-05",
+            "capabilities": {}
+        }))
+        .unwrap_err();
+        assert!(error.to_string().contains("missing field `serverInfo`"));
+    }
+
+    // Resource reads pick the most preferred available representation
+    #[test]
+    fn test_read_resource_negotiation() {
+        let params: ReadResourceParams = parse(json!({
+            "uri": "docs://guide",
+            "_meta": { "accept": ["text/html; charset=utf-8", "text/*"] }
+        }));
+        assert_eq!(params.accept().len(), 2);
+        assert_eq!(
+            params.negotiate(&["text/markdown", "text/html"]),
+            Some("text/html")
+        );
+        assert_eq!(
+            params.negotiate(&["application/json", "text/plain"]),
+            Some("text/plain")
+        );
+        assert_eq!(
+            params.negotiate(&["application/json", "image/png"]),
+            Some("application/json")
+        );
+
+        let plain: ReadResourceParams = parse(json!({ "uri": "docs://guide" }));
+        assert!(plain.accept().is_empty());
+        assert_eq!(plain.negotiate(&["text/markdown"]), Some("text/markdown"));
+        assert_eq!(plain.negotiate(&[]), None);
+    }
+
+    // Multi-word fields use the spec's camelCase names
+    #[test]
+    fn test_camel_case_fields() {
+        let params: CreateMessageParams = parse(json!({
+            "messages": [],
+            "maxTokens": 100,
+            "systemPrompt": "Be brief",
+            "modelPreferences": { "costPriority": 0.5 }
+        }));
+        assert_eq!(params.max_tokens, 100);
+        assert_eq!(params.model_preferences.unwrap().cost_priority, Some(0.5));
+
+        let cancelled: CancelledParams = parse(json!({ "requestId": 1 }));
+        assert_eq!(cancelled.request_id, RequestId::Number(1));
+
+        let complete: CompleteParams = parse(json!({
+            "ref": { "type": "ref/prompt", "name": "greet" },
+            "argument": { "name": "name", "value": "Al" }
+        }));
+        assert!(matches!(complete.ref_, Reference::Prompt(_)));
+    }
+
+    // Merged `_meta` fields should not replace those the request sets itself
+    #[test]
+    fn test_merge_meta() {
+        let meta: Meta = parse(json!({ "traceId": "abc", "progressToken": "default" }));
+        let params = merge_meta(Some(json!({ "_meta": { "progressToken": 1 } })), &meta);
+        assert_eq!(
+            params,
+            Some(json!({ "_meta": { "progressToken": 1, "traceId": "abc" } }))
+        );
+        assert_eq!(merge_meta(None, &Meta::new()), None);
+        assert_eq!(meta_of(&merge_meta(None, &meta).unwrap()), Some(&meta));
+        assert_eq!(merge_meta(Some(json!([1])), &meta), Some(json!([1])));
+    }
+
+    // Raw payloads are written back as they were read, from text or a value
+    #[test]
+    fn test_raw_payload() {
+        let text = r#"{"jsonrpc":"2.0","id":7,"result":{"b":1.50,"a":[ 1, 2 ]}}"#;
+        let payload: RawPayload = serde_json::from_str(text).unwrap();
+        assert_eq!(serde_json::to_string(&payload).unwrap(), text);
+
+        let batch = json!([
+            { "jsonrpc": "2.0", "id": "x", "method": "ping" },
+            { "jsonrpc": "2.0", "method": "notifications/initialized", "params": {} }
+        ]);
+        let payload: RawPayload = serde_json::from_value(batch.clone()).unwrap();
+        let RawPayload::Batch(messages) = &payload else {
+            panic!("expected a batch");
+        };
+        assert_eq!(messages[0].id, Some(RequestId::String("x".to_string())));
+        assert_eq!(serde_json::to_value(&payload).unwrap(), batch);
+    }
+}
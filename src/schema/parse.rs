@@ -0,0 +1,198 @@
+//! How strictly messages are held to the specification when parsed
+//!
+//! Servers and clients in the wild send extra fields or leave out optional
+//! ones, so by default both are accepted: unknown fields are ignored and
+//! missing optional fields take their defaults. [`ParseMode::Strict`] is for
+//! conformance testing and rejects a message with fields its type does not
+//! define, including deprecated aliases such as `protocol_version`.
+//!
+//! Unknown fields are found by parsing a value into its type, serializing it
+//! back and looking for the fields that did not survive. `_meta` is open to
+//! extensions and never checked, and a dropped field whose value is empty
+//! (`null`, `[]`, `{}`, `""`, `false` or `0`) is taken for an optional field
+//! left at its default.
+
+use super::{
+    client::{
+        CallToolParams, CompleteParams, GetPromptParams, GetPromptResult, InitializeParams,
+        ListPromptsResult, ListResourceTemplatesResult, ListResourcesResult, ListToolsResult,
+        PaginatedParams, ReadResourceParams, ReadResourceResult, SetLevelParams, SubscribeParams,
+        UnsubscribeParams,
+    },
+    server::{CallToolResult, CompleteResult, InitializeResult},
+};
+use crate::error::MCPError;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+/// How strictly messages are parsed, see the [module docs](self)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Ignore unknown fields and default missing optional fields
+    #[default]
+    Lenient,
+    /// Reject unknown fields and any other deviation from the specification
+    Strict,
+}
+
+impl ParseMode {
+    /// Whether this is [`ParseMode::Strict`]
+    pub fn is_strict(self) -> bool {
+        self == ParseMode::Strict
+    }
+
+    /// Parse a value into `T`, rejecting unknown fields when strict
+    pub fn from_value<T: DeserializeOwned + Serialize>(self, value: Value) -> Result<T, MCPError> {
+        let parsed: T = serde_json::from_value(value.clone())?;
+        if self.is_strict() {
+            check_unknown_fields(&value, &parsed)?;
+        }
+        Ok(parsed)
+    }
+
+    /// Check the params of a request to `method`, which passes unless strict
+    ///
+    /// Requests to methods without a known params type pass.
+    pub fn check_params(self, method: &str, params: Option<&Value>) -> Result<(), MCPError> {
+        if !self.is_strict() {
+            return Ok(());
+        }
+        let params = params
+            .cloned()
+            .unwrap_or_else(|| Value::Object(Default::default()));
+        match method {
+            "initialize" => self.check::<InitializeParams>(params),
+            "tools/call" => self.check::<CallToolParams>(params),
+            "resources/read" => self.check::<ReadResourceParams>(params),
+            "resources/subscribe" => self.check::<SubscribeParams>(params),
+            "resources/unsubscribe" => self.check::<UnsubscribeParams>(params),
+            "prompts/get" => self.check::<GetPromptParams>(params),
+            "logging/setLevel" => self.check::<SetLevelParams>(params),
+            "completion/complete" => self.check::<CompleteParams>(params),
+            "tools/list" | "resources/list" | "resources/templates/list" | "prompts/list" => {
+                self.check::<PaginatedParams>(params)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the result of a request to `method`, which passes unless strict
+    ///
+    /// Results of methods without a known result type pass.
+    pub fn check_result(self, method: &str, result: &Value) -> Result<(), MCPError> {
+        if !self.is_strict() {
+            return Ok(());
+        }
+        let result = result.clone();
+        match method {
+            "initialize" => self.check::<InitializeResult>(result),
+            "tools/list" => self.check::<ListToolsResult>(result),
+            "tools/call" => self.check::<CallToolResult>(result),
+            "resources/list" => self.check::<ListResourcesResult>(result),
+            "resources/templates/list" => self.check::<ListResourceTemplatesResult>(result),
+            "resources/read" => self.check::<ReadResourceResult>(result),
+            "prompts/list" => self.check::<ListPromptsResult>(result),
+            "prompts/get" => self.check::<GetPromptResult>(result),
+            "completion/complete" => self.check::<CompleteResult>(result),
+            _ => Ok(()),
+        }
+    }
+
+    fn check<T: DeserializeOwned + Serialize>(self, value: Value) -> Result<(), MCPError> {
+        self.from_value::<T>(value).map(drop)
+    }
+}
+
+fn check_unknown_fields<T: Serialize>(input: &Value, parsed: &T) -> Result<(), MCPError> {
+    let unknown = unknown_fields(input, &serde_json::to_value(parsed)?);
+    if unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(MCPError::Protocol(format!(
+            "Unknown fields: {}",
+            unknown.join(", ")
+        )))
+    }
+}
+
+/// JSON Pointers to the fields of `input` that are missing from `parsed`
+pub fn unknown_fields(input: &Value, parsed: &Value) -> Vec<String> {
+    let mut unknown = Vec::new();
+    collect_unknown(input, parsed, String::new(), &mut unknown);
+    unknown
+}
+
+fn collect_unknown(input: &Value, parsed: &Value, path: String, unknown: &mut Vec<String>) {
+    match (input, parsed) {
+        (Value::Object(input), Value::Object(parsed)) => {
+            for (key, value) in input {
+                if key == "_meta" {
+                    continue;
+                }
+                let path = format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+                match parsed.get(key) {
+                    Some(parsed) => collect_unknown(value, parsed, path, unknown),
+                    None if is_empty(value) => {}
+                    None => unknown.push(path),
+                }
+            }
+        }
+        (Value::Array(input), Value::Array(parsed)) => {
+            for (index, (value, parsed)) in input.iter().zip(parsed).enumerate() {
+                collect_unknown(value, parsed, format!("{}/{}", path, index), unknown);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Bool(b) => !b,
+        Value::Number(n) => n.as_f64() == Some(0.0),
+        Value::String(s) => s.is_empty(),
+        Value::Array(items) => items.is_empty(),
+        Value::Object(fields) => fields.is_empty(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_mode() {
+        let params = json!({
+            "protocol_version": "2024-11-05",
+            "clientInfo": { "name": "test", "version": "1.0", "vendor": "acme" },
+            "_meta": { "anything": true },
+        });
+        let lenient: InitializeParams = ParseMode::Lenient.from_value(params.clone()).unwrap();
+        assert_eq!(lenient.protocol_version, "2024-11-05");
+
+        let error = ParseMode::Strict
+            .from_value::<InitializeParams>(params)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            MCPError::Protocol("Unknown fields: /clientInfo/vendor, /protocol_version".to_string())
+                .to_string()
+        );
+
+        let result = json!({ "content": [{ "type": "text", "text": "hi", "mimeType": "x" }] });
+        assert!(ParseMode::Lenient
+            .check_result("tools/call", &result)
+            .is_ok());
+        assert!(ParseMode::Strict
+            .check_result("tools/call", &result)
+            .is_err());
+        assert!(ParseMode::Strict
+            .check_params("tools/list", Some(&json!({ "cursor": "2" })))
+            .is_ok());
+        assert!(ParseMode::Strict
+            .check_params("custom/method", Some(&json!({ "x": 1 })))
+            .is_ok());
+    }
+}
@@ -5,67 +5,72 @@ use serde_json::Value;
 use std::collections::HashMap;
 
 use super::common::{
-    EmbeddedResource, ImageContent, Implementation, LoggingLevel, Role, TextContent,
+    AudioContent, EmbeddedResource, ImageContent, Implementation, LoggingLevel, Resource, Role,
+    TextContent,
 };
 
 /// Server capabilities
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ServerCapabilities {
     /// Experimental, non-standard capabilities that the server supports.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub experimental: Option<HashMap<String, Value>>,
 
     /// Present if the server supports sending log messages to the client.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub logging: Option<Value>,
 
     /// Present if the server offers any prompt templates.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub prompts: Option<PromptsCapability>,
 
     /// Present if the server offers any resources to read.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub resources: Option<ResourcesCapability>,
 
     /// Present if the server offers any tools to call.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub tools: Option<ToolsCapability>,
+
+    /// Present if the server completes prompt and resource template arguments.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completions: Option<Value>,
 }
 
 /// Prompts capability
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PromptsCapability {
     /// Whether this server supports notifications for changes to the prompt list.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub list_changed: Option<bool>,
 }
 
 /// Resources capability
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ResourcesCapability {
     /// Whether this server supports subscribing to resource updates.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub subscribe: Option<bool>,
 
     /// Whether this server supports notifications for changes to the resource list.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub list_changed: Option<bool>,
 }
 
 /// Tools capability
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ToolsCapability {
     /// Whether this server supports notifications for changes to the tool list.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub list_changed: Option<bool>,
 }
 
 /// After receiving an initialize request from the client, the server sends this response.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct InitializeResult {
     /// The version of the Model Context Protocol that the server wants to use.
@@ -78,57 +83,106 @@ pub struct InitializeResult {
     pub server_info: Implementation,
 
     /// Instructions describing how to use the server and its features.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub instructions: Option<String>,
 }
 
 /// A notification from the server to the client, informing it that a resource has changed.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ResourceUpdatedNotification {
     pub method: String,
     pub params: ResourceUpdatedParams,
 }
 
 /// Parameters for resource updated notification
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Besides the URI, servers may include the resource's metadata that changed
+/// with the update, using the field names of [`Resource`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ResourceUpdatedParams {
     /// The URI of the resource that has been updated.
     pub uri: String,
+
+    /// The new name of the resource, if it changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// The new description of the resource, if it changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// The new MIME type of the resource, if it changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+
+    /// The new size of the resource in bytes, if it changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+}
+
+impl ResourceUpdatedParams {
+    /// An update of the resource's contents, without metadata changes
+    pub fn new(uri: impl Into<String>) -> Self {
+        Self {
+            uri: uri.into(),
+            name: None,
+            description: None,
+            mime_type: None,
+            size: None,
+        }
+    }
+
+    /// Apply the changed metadata to a resource, leaving other fields alone
+    pub fn apply_to(&self, resource: &mut Resource) {
+        if let Some(name) = &self.name {
+            resource.name = name.clone();
+        }
+        if let Some(description) = &self.description {
+            resource.description = Some(description.clone());
+        }
+        if let Some(mime_type) = &self.mime_type {
+            resource.mime_type = Some(mime_type.clone());
+        }
+        if let Some(size) = self.size {
+            resource.size = Some(size);
+        }
+    }
 }
 
 /// An optional notification from the server to the client, informing it that the list of resources it can read from has changed.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ResourceListChangedNotification {
     pub method: String,
 }
 
 /// An optional notification from the server to the client, informing it that the list of prompts it offers has changed.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PromptListChangedNotification {
     pub method: String,
 }
 
 /// An optional notification from the server to the client, informing it that the list of tools it offers has changed.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ToolListChangedNotification {
     pub method: String,
 }
 
 /// Notification of a log message passed from server to client.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LoggingMessageNotification {
     pub method: String,
     pub params: LoggingMessageParams,
 }
 
 /// Parameters for logging message notification
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct LoggingMessageParams {
     /// The severity of this log message.
     pub level: LoggingLevel,
 
     /// An optional name of the logger issuing this message.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub logger: Option<String>,
 
     /// The data to be logged, such as a string message or an object.
@@ -136,48 +190,103 @@ pub struct LoggingMessageParams {
 }
 
 /// A request from the server to sample an LLM via the client.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CreateMessageRequest {
     pub method: String,
     pub params: CreateMessageParams,
 }
 
 /// Parameters for create message request
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct CreateMessageParams {
     /// The messages to sample from
     pub messages: Vec<SamplingMessage>,
 
     /// The server's preferences for which model to select.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub model_preferences: Option<ModelPreferences>,
 
     /// An optional system prompt the server wants to use for sampling.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub system_prompt: Option<String>,
 
     /// A request to include context from one or more MCP servers.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub include_context: Option<IncludeContext>,
 
     /// Temperature for sampling
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
 
     /// The maximum number of tokens to sample.
     pub max_tokens: u32,
 
     /// Stop sequences for sampling
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub stop_sequences: Option<Vec<String>>,
 
     /// Optional metadata to pass through to the LLM provider.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Value>,
+}
+
+impl CreateMessageParams {
+    /// The generation parameters of the request
+    pub fn sampling_options(&self) -> SamplingOptions {
+        SamplingOptions {
+            temperature: self.temperature,
+            max_tokens: self.max_tokens,
+            stop_sequences: self.stop_sequences.clone(),
+            metadata: self.metadata.clone(),
+        }
+    }
+}
+
+/// Generation parameters of a sampling request
+///
+/// Options the server left out are `None`, meaning the model provider's
+/// default applies. Since unknown fields are ignored, this also deserializes
+/// straight from the raw `sampling/createMessage` params.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SamplingOptions {
+    /// Temperature for sampling
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+
+    /// The maximum number of tokens to sample.
+    pub max_tokens: u32,
+
+    /// Stop sequences for sampling
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+
+    /// Metadata to pass through to the LLM provider unchanged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub metadata: Option<Value>,
 }
 
+impl SamplingOptions {
+    /// Options with only the required token limit set
+    pub fn new(max_tokens: u32) -> Self {
+        Self {
+            temperature: None,
+            max_tokens,
+            stop_sequences: None,
+            metadata: None,
+        }
+    }
+}
+
+impl From<&CreateMessageParams> for SamplingOptions {
+    fn from(params: &CreateMessageParams) -> Self {
+        params.sampling_options()
+    }
+}
+
 /// Include context options
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum IncludeContext {
     None,
@@ -186,25 +295,25 @@ pub enum IncludeContext {
 }
 
 /// The client's response to a sampling/create_message request from the server.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct CreateMessageResult {
     /// The role of the message
     pub role: Role,
 
     /// The content of the message
-    #[serde(flatten)]
     pub content: MessageContent,
 
     /// The name of the model that generated the message.
     pub model: String,
 
     /// The reason why sampling stopped, if known.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub stop_reason: Option<StopReason>,
 }
 
 /// Message content
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum MessageContent {
     Text(TextContent),
@@ -212,7 +321,7 @@ pub enum MessageContent {
 }
 
 /// Stop reason
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum StopReason {
     /// Known stop reasons
@@ -223,7 +332,7 @@ pub enum StopReason {
 }
 
 /// Known stop reasons
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum KnownStopReason {
     EndTurn,
@@ -232,91 +341,458 @@ pub enum KnownStopReason {
 }
 
 /// Describes a message issued to or received from an LLM API.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SamplingMessage {
     pub role: Role,
-    #[serde(flatten)]
     pub content: MessageContent,
 }
 
 /// The server's preferences for model selection, requested of the client during sampling.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ModelPreferences {
     /// Optional hints to use for model selection.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub hints: Option<Vec<ModelHint>>,
 
     /// How much to prioritize cost when selecting a model.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub cost_priority: Option<f32>,
 
     /// How much to prioritize sampling speed (latency) when selecting a model.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub speed_priority: Option<f32>,
 
     /// How much to prioritize intelligence and capabilities when selecting a model.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub intelligence_priority: Option<f32>,
 }
 
 /// Hints to use for model selection.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ModelHint {
     /// A hint for a model name.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
 }
 
+/// Parameters of an `elicitation/create` request, asking the user for input
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ElicitParams {
+    /// What to ask the user
+    pub message: String,
+
+    /// The JSON Schema of the answer: an object with properties of
+    /// primitive types
+    pub requested_schema: Value,
+}
+
+/// How the user answered an elicitation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ElicitAction {
+    /// The user submitted the requested content
+    Accept,
+    /// The user explicitly refused
+    Decline,
+    /// The user dismissed the request without choosing
+    Cancel,
+}
+
+/// The client's response to an `elicitation/create` request
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ElicitResult {
+    pub action: ElicitAction,
+
+    /// The submitted content, when the user accepted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<Value>,
+}
+
+impl ElicitResult {
+    /// The user submitted `content`
+    pub fn accept(content: Value) -> Self {
+        Self {
+            action: ElicitAction::Accept,
+            content: Some(content),
+        }
+    }
+
+    /// The user refused
+    pub fn decline() -> Self {
+        Self {
+            action: ElicitAction::Decline,
+            content: None,
+        }
+    }
+
+    /// The user dismissed the request
+    pub fn cancel() -> Self {
+        Self {
+            action: ElicitAction::Cancel,
+            content: None,
+        }
+    }
+}
+
 /// The server's response to a completion/complete request
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CompleteResult {
     pub completion: CompletionInfo,
 }
 
 /// Completion information
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct CompletionInfo {
     /// An array of completion values.
     pub values: Vec<String>,
 
     /// The total number of completion options available.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub total: Option<u32>,
 
     /// Indicates whether there are additional completion options beyond those provided.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub has_more: Option<bool>,
 }
 
 /// Sent from the server to request a list of root URIs from the client.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ListRootsRequest {
     pub method: String,
 }
 
 /// The server's response to a tool call.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct CallToolResult {
     pub content: Vec<ToolResultContent>,
 
+    /// Machine-readable output of the tool, alongside the human-readable content.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub structured_content: Option<Value>,
+
     /// Whether the tool call ended in an error.
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub is_error: Option<bool>,
 }
 
+impl CallToolResult {
+    /// Render the result as a text transcript
+    ///
+    /// Each content block becomes one entry marked `tool:` (or `tool error:`
+    /// for failed calls). Text is included verbatim and non-text content is
+    /// replaced by a placeholder such as `[image/png, 12KB]`.
+    pub fn to_transcript(&self) -> String {
+        let marker = if self.is_error == Some(true) {
+            "tool error"
+        } else {
+            "tool"
+        };
+
+        self.content
+            .iter()
+            .map(|content| {
+                let text = match content {
+                    ToolResultContent::Text(text) => text.text.clone(),
+                    ToolResultContent::Image(image) => image.transcript_placeholder(),
+                    ToolResultContent::Audio(audio) => audio.transcript_placeholder(),
+                    ToolResultContent::Resource(resource) => resource.to_transcript(),
+                };
+                format!("{}: {}", marker, text)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Options for [`CallToolResult::render_cli`]
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptions {
+    /// Style labels and errors with ANSI colors; needs the `color` feature
+    pub color: bool,
+    /// Truncate each text block to this many characters
+    pub max_text_len: Option<usize>,
+}
+
+impl RenderOptions {
+    /// Plain, untruncated rendering
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enable or disable colors, which are only used with the `color` feature
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Truncate each text block to `max_text_len` characters
+    pub fn with_max_text_len(mut self, max_text_len: usize) -> Self {
+        self.max_text_len = Some(max_text_len);
+        self
+    }
+
+    /// Wrap text in an ANSI style, if colors are enabled and compiled in
+    fn style(&self, text: &str, _ansi: &str) -> String {
+        #[cfg(feature = "color")]
+        if self.color {
+            return format!("\x1b[{}m{}\x1b[0m", _ansi, text);
+        }
+        text.to_string()
+    }
+
+    fn truncate(&self, text: &str) -> String {
+        match self.max_text_len {
+            Some(max) if text.chars().count() > max => {
+                format!("{}…", text.chars().take(max).collect::<String>())
+            }
+            _ => text.to_string(),
+        }
+    }
+}
+
+impl CallToolResult {
+    /// Render the result for display in a terminal
+    ///
+    /// Text content is printed as is, images and resources are labelled with
+    /// their type and size, and failed calls are headed by an error line.
+    /// When there is no content, structured output is printed as JSON.
+    pub fn render_cli(&self, options: &RenderOptions) -> String {
+        const LABEL: &str = "36";
+        const ERROR: &str = "1;31";
+
+        let mut lines = Vec::new();
+        if self.is_error == Some(true) {
+            lines.push(options.style("Error:", ERROR));
+        }
+
+        for content in &self.content {
+            lines.push(match content {
+                ToolResultContent::Text(text) => options.truncate(&text.text),
+                ToolResultContent::Image(image) => {
+                    options.style(&image.transcript_placeholder(), LABEL)
+                }
+                ToolResultContent::Audio(audio) => {
+                    options.style(&audio.transcript_placeholder(), LABEL)
+                }
+                ToolResultContent::Resource(resource) => {
+                    let rendered = resource.to_transcript();
+                    match rendered.split_once('\n') {
+                        Some((label, text)) => {
+                            format!(
+                                "{}\n{}",
+                                options.style(label, LABEL),
+                                options.truncate(text)
+                            )
+                        }
+                        None => options.style(&rendered, LABEL),
+                    }
+                }
+            });
+        }
+
+        if self.content.is_empty() {
+            if let Some(structured) = &self.structured_content {
+                lines.push(
+                    serde_json::to_string_pretty(structured)
+                        .unwrap_or_else(|_| structured.to_string()),
+                );
+            }
+        }
+
+        lines.join("\n")
+    }
+}
+
 /// Tool result content
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Deserialized by its `type`, so an item carrying fields of another
+/// content type, like an image with a stray `text`, is still read as what
+/// its `type` says. Such extraneous fields are ignored with a warning.
+#[derive(Debug, Clone, PartialEq, Serialize)]
 #[serde(untagged)]
 pub enum ToolResultContent {
     Text(TextContent),
     Image(ImageContent),
+    Audio(AudioContent),
     Resource(EmbeddedResource),
 }
 
+impl ToolResultContent {
+    /// Fields of a raw content item that its `type` does not define
+    ///
+    /// Items of an unknown type have none, as their fields are not known.
+    pub fn extraneous_fields(item: &Value) -> Vec<String> {
+        let known: &[&str] = match item.get("type").and_then(Value::as_str) {
+            Some("text") => &["type", "text", "annotations", "_meta"],
+            Some("image" | "audio") => &["type", "data", "mimeType", "annotations", "_meta"],
+            Some("resource") => &["type", "resource", "annotations", "_meta"],
+            _ => return Vec::new(),
+        };
+        item.as_object()
+            .into_iter()
+            .flat_map(|fields| fields.keys())
+            .filter(|field| !known.contains(&field.as_str()))
+            .cloned()
+            .collect()
+    }
+}
+
+impl<'de> Deserialize<'de> for ToolResultContent {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let item = Value::deserialize(deserializer)?;
+        let extraneous = Self::extraneous_fields(&item);
+        if !extraneous.is_empty() {
+            log::warn!(
+                "Ignoring fields {:?} of {} content",
+                extraneous,
+                item["type"].as_str().unwrap_or_default()
+            );
+        }
+
+        let content = match item.get("type").and_then(Value::as_str) {
+            Some("text") => serde_json::from_value(item).map(Self::Text),
+            Some("image") => serde_json::from_value(item).map(Self::Image),
+            Some("audio") => serde_json::from_value(item).map(Self::Audio),
+            Some("resource") => serde_json::from_value(item).map(Self::Resource),
+            // Unknown types are read by their shape, as before
+            _ => serde_json::from_value(item.clone())
+                .map(Self::Text)
+                .or_else(|_| serde_json::from_value(item.clone()).map(Self::Image))
+                .or_else(|_| serde_json::from_value(item).map(Self::Resource)),
+        };
+        content.map_err(D::Error::custom)
+    }
+}
+
 /// Result of a tool call
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ToolCallResult {
     /// The result of the tool call
     pub result: Value,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test that content is read by its type, ignoring fields of other types
+    #[test]
+    fn test_content_with_extraneous_fields() {
+        let item = serde_json::json!({
+            "type": "image",
+            "text": "stray",
+            "data": "aGVsbG8=",
+            "mimeType": "image/png",
+        });
+        assert_eq!(ToolResultContent::extraneous_fields(&item), vec!["text"]);
+
+        let content: ToolResultContent = serde_json::from_value(item).unwrap();
+        assert!(matches!(content, ToolResultContent::Image(ref image) if image.data == "aGVsbG8="));
+
+        let text: ToolResultContent = serde_json::from_value(
+            serde_json::json!({ "type": "text", "text": "hi", "data": "x" }),
+        )
+        .unwrap();
+        assert!(matches!(text, ToolResultContent::Text(ref text) if text.text == "hi"));
+
+        // A missing canonical field is still an error
+        assert!(serde_json::from_value::<ToolResultContent>(
+            serde_json::json!({ "type": "image", "text": "no data" })
+        )
+        .is_err());
+    }
+
+    // Test extracting the generation parameters of a sampling request
+    #[test]
+    fn test_sampling_options() {
+        let raw = serde_json::json!({
+            "messages": [],
+            "maxTokens": 256,
+            "temperature": 0.5,
+            "stopSequences": ["\n\n"],
+            "metadata": { "user": "u-1", "tags": ["a"] }
+        });
+
+        let params: CreateMessageParams = serde_json::from_value(raw.clone()).unwrap();
+        let options = params.sampling_options();
+        assert_eq!(options.max_tokens, 256);
+        assert_eq!(options.temperature, Some(0.5));
+        assert_eq!(options.stop_sequences, Some(vec!["\n\n".to_string()]));
+        assert_eq!(options.metadata, Some(raw["metadata"].clone()));
+        assert_eq!(
+            serde_json::from_value::<SamplingOptions>(raw).unwrap(),
+            options
+        );
+
+        // Absent options stay absent, leaving the provider defaults in place
+        let minimal: SamplingOptions =
+            serde_json::from_value(serde_json::json!({ "maxTokens": 10 })).unwrap();
+        assert_eq!(minimal, SamplingOptions::new(10));
+        assert_eq!(
+            serde_json::to_value(&minimal).unwrap(),
+            serde_json::json!({ "maxTokens": 10 })
+        );
+    }
+
+    // Test rendering a tool result for the terminal
+    #[test]
+    fn test_render_cli() {
+        let result: CallToolResult = serde_json::from_value(serde_json::json!({
+            "content": [
+                { "type": "text", "text": "Something went wrong" },
+                { "type": "image", "data": "AAAA", "mimeType": "image/png" },
+                {
+                    "type": "resource",
+                    "resource": { "uri": "file:///log.txt", "text": "line 1" }
+                }
+            ],
+            "isError": true
+        }))
+        .unwrap();
+
+        let options = RenderOptions::new().with_max_text_len(9);
+        assert_eq!(
+            result.render_cli(&options),
+            "Error:\nSomething…\n[image/png, 3B]\n[resource file:///log.txt]\nline 1"
+        );
+
+        let structured: CallToolResult = serde_json::from_value(serde_json::json!({
+            "content": [],
+            "structuredContent": { "count": 2 }
+        }))
+        .unwrap();
+        assert_eq!(
+            structured.render_cli(&RenderOptions::new()),
+            "{\n  \"count\": 2\n}"
+        );
+    }
+
+    // Test rendering a tool result with text, image and resource content
+    #[test]
+    fn test_call_tool_result_transcript() {
+        let result: CallToolResult = serde_json::from_value(serde_json::json!({
+            "content": [
+                { "type": "text", "text": "Found it" },
+                { "type": "image", "data": "A".repeat(16384), "mimeType": "image/png" },
+                {
+                    "type": "resource",
+                    "resource": { "uri": "file:///notes.txt", "text": "hello" }
+                }
+            ],
+            "isError": false
+        }))
+        .unwrap();
+
+        assert_eq!(
+            result.to_transcript(),
+            "tool: Found it\ntool: [image/png, 12KB]\ntool: [resource file:///notes.txt]\nhello"
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! URI templates (RFC 6570) for resource templates
+//!
+//! Covers simple string expansion, `{var}`, and reserved expansion,
+//! `{+var}`, which are what resource templates use in practice, such as
+//! `db://{table}/{id}` or `file:///{+path}`. Templates can be expanded into
+//! URIs and URIs matched against templates to recover the variables.
+
+use crate::error::MCPError;
+use std::collections::HashMap;
+
+/// Characters reserved expansion leaves as is, besides unreserved ones
+const RESERVED: &str = ":/?#[]@!$&'()*+,;=";
+
+/// A parsed URI template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UriTemplate {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Variable { name: String, reserved: bool },
+}
+
+impl UriTemplate {
+    /// Parse a template, failing on unclosed braces or unsupported operators
+    pub fn parse(template: &str) -> Result<Self, MCPError> {
+        let invalid = |reason: &str| {
+            MCPError::Protocol(format!("Invalid URI template '{}': {}", template, reason))
+        };
+
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| invalid("unclosed '{'"))?
+                + start;
+            let expression = &rest[start + 1..end];
+            let (name, reserved) = match expression.strip_prefix('+') {
+                Some(name) => (name, true),
+                None => (expression, false),
+            };
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+            {
+                return Err(invalid(&format!(
+                    "unsupported expression '{{{}}}'",
+                    expression
+                )));
+            }
+            parts.push(Part::Variable {
+                name: name.to_string(),
+                reserved,
+            });
+            rest = &rest[end + 1..];
+        }
+        if rest.contains('}') {
+            return Err(invalid("unmatched '}'"));
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        Ok(Self { parts })
+    }
+
+    /// The names of the template's variables, in order
+    pub fn variables(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Variable { name, .. } => Some(name.as_str()),
+            Part::Literal(_) => None,
+        })
+    }
+
+    /// Build a URI from the template, percent-encoding the values
+    ///
+    /// Every variable must have a value.
+    pub fn expand(&self, values: &HashMap<String, String>) -> Result<String, MCPError> {
+        let mut uri = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => uri.push_str(literal),
+                Part::Variable { name, reserved } => {
+                    let value = values.get(name).ok_or_else(|| {
+                        MCPError::Protocol(format!("Missing URI template variable '{}'", name))
+                    })?;
+                    uri.push_str(&encode(value, *reserved));
+                }
+            }
+        }
+        Ok(uri)
+    }
+
+    /// The values of the variables if `uri` matches the template
+    ///
+    /// A `{var}` matches a non-empty value without reserved characters, like
+    /// `/`, and a `{+var}` any non-empty value. Values are percent-decoded.
+    pub fn match_uri(&self, uri: &str) -> Option<HashMap<String, String>> {
+        let mut values = HashMap::new();
+        match_parts(&self.parts, uri, &mut values).then_some(values)
+    }
+}
+
+/// Match `uri` against `parts`, trying shorter values first
+fn match_parts(parts: &[Part], uri: &str, values: &mut HashMap<String, String>) -> bool {
+    let Some((part, rest)) = parts.split_first() else {
+        return uri.is_empty();
+    };
+    match part {
+        Part::Literal(literal) => uri
+            .strip_prefix(literal.as_str())
+            .is_some_and(|uri| match_parts(rest, uri, values)),
+        Part::Variable { name, reserved } => {
+            for (end, c) in uri.char_indices().map(|(i, c)| (i + c.len_utf8(), c)) {
+                if !reserved && RESERVED.contains(c) {
+                    break;
+                }
+                let Some(value) = decode(&uri[..end]) else {
+                    continue;
+                };
+                if match_parts(rest, &uri[end..], values) {
+                    values.insert(name.clone(), value);
+                    return true;
+                }
+            }
+            false
+        }
+    }
+}
+
+/// Percent-encode everything but unreserved characters, and reserved ones if allowed
+fn encode(value: &str, reserved: bool) -> String {
+    let mut encoded = String::new();
+    for c in value.chars() {
+        if c.is_ascii_alphanumeric() || "-._~".contains(c) || (reserved && RESERVED.contains(c)) {
+            encoded.push(c);
+        } else {
+            let mut bytes = [0; 4];
+            for byte in c.encode_utf8(&mut bytes).bytes() {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        }
+    }
+    encoded
+}
+
+/// Percent-decode a value, or `None` if it is not valid UTF-8 once decoded
+fn decode(value: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let hex = tail.get(..2).and_then(|hex| std::str::from_utf8(hex).ok());
+        match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(decoded) if byte == b'%' => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_and_match() {
+        let template = UriTemplate::parse("db://{table}/{id}").unwrap();
+        assert_eq!(template.variables().collect::<Vec<_>>(), ["table", "id"]);
+
+        let values: HashMap<String, String> = [("table", "users"), ("id", "a b/c")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let uri = template.expand(&values).unwrap();
+        assert_eq!(uri, "db://users/a%20b%2Fc");
+        assert_eq!(template.match_uri(&uri), Some(values));
+        assert_eq!(template.match_uri("db://users/1/2"), None);
+        assert_eq!(template.match_uri("db://users/"), None);
+        assert!(template.expand(&HashMap::new()).is_err());
+
+        // Reserved expansion keeps and matches slashes
+        let files = UriTemplate::parse("file:///{+path}").unwrap();
+        let values = files.match_uri("file:///src/main.rs").unwrap();
+        assert_eq!(values["path"], "src/main.rs");
+        assert_eq!(files.expand(&values).unwrap(), "file:///src/main.rs");
+
+        assert!(UriTemplate::parse("db://{table").is_err());
+        assert!(UriTemplate::parse("db://{?query}").is_err());
+    }
+}
@@ -0,0 +1,299 @@
+//! Validation of JSON values against the JSON Schemas tools declare
+//!
+//! Covers the keywords tool schemas use in practice: `type`, `enum`,
+//! `const`, `properties`, `required`, `additionalProperties`, `items`,
+//! `allOf`, `anyOf`, `oneOf`, and the length and range bounds. Other
+//! keywords, such as `$ref`, `pattern` and `format`, are ignored, so a value
+//! may pass here that a full validator rejects.
+
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// The first place a value fails its schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaViolation {
+    /// JSON Pointer to the failing part of the value, empty for the value itself
+    pub path: String,
+    /// What is wrong there
+    pub message: String,
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = if self.path.is_empty() {
+            "/"
+        } else {
+            &self.path
+        };
+        write!(f, "{}: {}", path, self.message)
+    }
+}
+
+/// Check a value against a JSON Schema
+pub fn validate(schema: &Value, value: &Value) -> Result<(), SchemaViolation> {
+    check(schema, value, "")
+}
+
+fn violation(path: &str, message: String) -> Result<(), SchemaViolation> {
+    Err(SchemaViolation {
+        path: path.to_string(),
+        message,
+    })
+}
+
+fn check(schema: &Value, value: &Value, path: &str) -> Result<(), SchemaViolation> {
+    let schema = match schema {
+        Value::Bool(true) => return Ok(()),
+        Value::Bool(false) => return violation(path, "no value is allowed here".to_string()),
+        Value::Object(schema) => schema,
+        _ => return Ok(()),
+    };
+
+    if let Some(types) = schema.get("type") {
+        let allowed: Vec<&str> = match types {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|name| has_type(value, name)) {
+            return violation(
+                path,
+                format!(
+                    "expected {}, got {}",
+                    allowed.join(" or "),
+                    type_name(value)
+                ),
+            );
+        }
+    }
+
+    if let Some(Value::Array(options)) = schema.get("enum") {
+        if !options.contains(value) {
+            return violation(
+                path,
+                format!("{} is not one of {}", value, Value::from(options.clone())),
+            );
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            return violation(path, format!("expected {}, got {}", expected, value));
+        }
+    }
+
+    check_combinators(schema, value, path)?;
+
+    match value {
+        Value::Object(object) => check_object(schema, object, path),
+        Value::Array(items) => check_array(schema, items, path),
+        Value::String(text) => {
+            let length = text.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                if length < min {
+                    return violation(path, format!("shorter than {} characters", min));
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+                if length > max {
+                    return violation(path, format!("longer than {} characters", max));
+                }
+            }
+            Ok(())
+        }
+        Value::Number(number) => {
+            let number = number.as_f64().unwrap_or_default();
+            let bound = |keyword: &str| schema.get(keyword).and_then(Value::as_f64);
+            if let Some(min) = bound("minimum").filter(|&min| number < min) {
+                return violation(path, format!("{} is less than {}", number, min));
+            }
+            if let Some(max) = bound("maximum").filter(|&max| number > max) {
+                return violation(path, format!("{} is greater than {}", number, max));
+            }
+            if let Some(min) = bound("exclusiveMinimum").filter(|&min| number <= min) {
+                return violation(path, format!("{} is not greater than {}", number, min));
+            }
+            if let Some(max) = bound("exclusiveMaximum").filter(|&max| number >= max) {
+                return violation(path, format!("{} is not less than {}", number, max));
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_combinators(
+    schema: &Map<String, Value>,
+    value: &Value,
+    path: &str,
+) -> Result<(), SchemaViolation> {
+    let subschemas = |keyword: &str| {
+        schema
+            .get(keyword)
+            .and_then(Value::as_array)
+            .map(|schemas| schemas.as_slice())
+    };
+
+    for subschema in subschemas("allOf").unwrap_or_default() {
+        check(subschema, value, path)?;
+    }
+    if let Some(options) = subschemas("anyOf") {
+        if !options
+            .iter()
+            .any(|option| check(option, value, path).is_ok())
+        {
+            return violation(path, "does not match any allowed schema".to_string());
+        }
+    }
+    if let Some(options) = subschemas("oneOf") {
+        let matches = options
+            .iter()
+            .filter(|option| check(option, value, path).is_ok())
+            .count();
+        if matches != 1 {
+            return violation(
+                path,
+                format!("matches {} schemas where exactly one is allowed", matches),
+            );
+        }
+    }
+    Ok(())
+}
+
+fn check_object(
+    schema: &Map<String, Value>,
+    object: &Map<String, Value>,
+    path: &str,
+) -> Result<(), SchemaViolation> {
+    for name in schema
+        .get("required")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+    {
+        if !object.contains_key(name) {
+            return violation(path, format!("missing required property '{}'", name));
+        }
+    }
+
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for (name, value) in object {
+        let property_path = format!("{}/{}", path, escape_pointer(name));
+        match properties.and_then(|properties| properties.get(name)) {
+            Some(property) => check(property, value, &property_path)?,
+            None => match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => {
+                    return violation(path, format!("unexpected property '{}'", name));
+                }
+                Some(additional) => check(additional, value, &property_path)?,
+                None => {}
+            },
+        }
+    }
+    Ok(())
+}
+
+fn check_array(
+    schema: &Map<String, Value>,
+    items: &[Value],
+    path: &str,
+) -> Result<(), SchemaViolation> {
+    let length = items.len() as u64;
+    if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+        if length < min {
+            return violation(path, format!("fewer than {} items", min));
+        }
+    }
+    if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+        if length > max {
+            return violation(path, format!("more than {} items", max));
+        }
+    }
+
+    if let Some(item_schema) = schema.get("items") {
+        for (index, item) in items.iter().enumerate() {
+            check(item_schema, item, &format!("{}/{}", path, index))?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether a value is of a JSON Schema type
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "integer" => match value {
+            Value::Number(number) => {
+                number.is_i64()
+                    || number.is_u64()
+                    || number.as_f64().is_some_and(|n| n.fract() == 0.0)
+            }
+            _ => false,
+        },
+        "number" => value.is_number(),
+        name => type_name(value) == name,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Escape a property name for use in a JSON Pointer
+fn escape_pointer(name: &str) -> String {
+    name.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_validate() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "count": { "type": "integer", "minimum": 0 },
+                "items": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": { "kind": { "enum": ["a", "b"] } },
+                        "required": ["kind"],
+                    },
+                },
+            },
+            "required": ["count"],
+            "additionalProperties": false,
+        });
+
+        assert!(validate(&schema, &json!({ "count": 2, "items": [{ "kind": "a" }] })).is_ok());
+
+        let failure = |value: Value| validate(&schema, &value).unwrap_err();
+        assert_eq!(
+            failure(json!({})).to_string(),
+            "/: missing required property 'count'"
+        );
+        assert_eq!(failure(json!({ "count": 1.5 })).path, "/count");
+        assert_eq!(failure(json!({ "count": -1 })).path, "/count");
+        assert_eq!(
+            failure(json!({ "count": 0, "items": [{ "kind": "c" }] })).path,
+            "/items/0/kind"
+        );
+        assert_eq!(
+            failure(json!({ "count": 0, "extra": true })).message,
+            "unexpected property 'extra'"
+        );
+        assert_eq!(failure(json!([])).message, "expected object, got array");
+
+        // Unsupported keywords are ignored
+        assert!(validate(&json!({ "type": "string", "format": "email" }), &json!("x")).is_ok());
+    }
+}
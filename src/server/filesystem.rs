@@ -0,0 +1,335 @@
+//! Built-in filesystem resource provider
+//!
+//! Serves the files under a root directory as `file://` resources. Paths are
+//! canonicalized before every access, so neither `..` segments nor symlinks
+//! can reach outside the root.
+
+use super::ResourceProvider;
+use crate::{
+    error::MCPError,
+    schema::{
+        client::ResourceContent,
+        common::{BlobResourceContents, Resource, TextResourceContents},
+    },
+};
+use async_trait::async_trait;
+use base64::Engine;
+use log::debug;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tokio::sync::Mutex;
+use url::Url;
+
+/// Serves the files under a root directory as `file://` resources
+///
+/// MIME types are guessed from file extensions. Files are read as text if
+/// they are valid UTF-8 and as base64 blobs otherwise. Subscribed files are
+/// watched by comparing their modification time on every poll, so a file
+/// that is deleted or recreated counts as changed.
+pub struct FsResourceProvider {
+    root: PathBuf,
+    /// Subscribed URIs and the modification time last seen for each
+    subscriptions: Mutex<HashMap<String, Option<SystemTime>>>,
+}
+
+impl FsResourceProvider {
+    /// Create a provider for the given root directory
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            subscriptions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Map a `file://` URI to a path, rejecting anything outside the root
+    async fn resolve(&self, uri: &str) -> Result<PathBuf, MCPError> {
+        let path = Url::parse(uri)
+            .ok()
+            .filter(|url| url.scheme() == "file")
+            .and_then(|url| url.to_file_path().ok())
+            .ok_or_else(|| MCPError::Protocol(format!("Invalid file URI: {}", uri)))?;
+
+        let root = self.canonical_root().await?;
+        let path = tokio::fs::canonicalize(&path)
+            .await
+            .map_err(|_| MCPError::Protocol(format!("Resource not found: {}", uri)))?;
+
+        if !path.starts_with(&root) || !path.is_file() {
+            return Err(MCPError::Protocol(format!("Resource not found: {}", uri)));
+        }
+
+        Ok(path)
+    }
+
+    async fn canonical_root(&self) -> Result<PathBuf, MCPError> {
+        tokio::fs::canonicalize(&self.root)
+            .await
+            .map_err(|e| io_error(&self.root, e))
+    }
+}
+
+#[async_trait]
+impl ResourceProvider for FsResourceProvider {
+    /// List every file under the root, recursively, sorted by URI
+    ///
+    /// Directories and files reached through several symlinks are listed
+    /// once, and symlinks back to a directory above them are not followed.
+    async fn list(&self) -> Result<Vec<Resource>, MCPError> {
+        let root = self.canonical_root().await?;
+        let mut resources = Vec::new();
+        let mut directories = vec![root.clone()];
+        // Canonical paths of the directories and files already seen
+        let mut seen = HashSet::from([root.clone()]);
+
+        while let Some(directory) = directories.pop() {
+            let mut entries = tokio::fs::read_dir(&directory)
+                .await
+                .map_err(|e| io_error(&directory, e))?;
+
+            while let Some(entry) = entries
+                .next_entry()
+                .await
+                .map_err(|e| io_error(&directory, e))?
+            {
+                // Resolve symlinks, and skip anything that leads outside the root
+                let Ok(path) = tokio::fs::canonicalize(entry.path()).await else {
+                    continue;
+                };
+                if !path.starts_with(&root) || !seen.insert(path.clone()) {
+                    continue;
+                }
+
+                let metadata = match tokio::fs::metadata(&path).await {
+                    Ok(metadata) => metadata,
+                    Err(_) => continue,
+                };
+                if metadata.is_dir() {
+                    directories.push(path);
+                    continue;
+                }
+
+                let Ok(uri) = Url::from_file_path(&path) else {
+                    continue;
+                };
+                let name = path
+                    .strip_prefix(&root)
+                    .unwrap_or(&path)
+                    .to_string_lossy()
+                    .into_owned();
+
+                resources.push(Resource {
+                    uri: uri.to_string(),
+                    name,
+                    description: None,
+                    mime_type: Some(guess_mime_type(&path).to_string()),
+                    size: Some(metadata.len()),
+                    annotations: None,
+                });
+            }
+        }
+
+        resources.sort_by(|a, b| a.uri.cmp(&b.uri));
+        Ok(resources)
+    }
+
+    /// Read a file, as text if it is valid UTF-8 and as a base64 blob otherwise
+    async fn read(&self, uri: &str) -> Result<ResourceContent, MCPError> {
+        let path = self.resolve(uri).await?;
+        let bytes = tokio::fs::read(&path)
+            .await
+            .map_err(|e| io_error(&path, e))?;
+        let mime_type = Some(guess_mime_type(&path).to_string());
+
+        Ok(match String::from_utf8(bytes) {
+            Ok(text) => ResourceContent::Text(TextResourceContents {
+                uri: uri.to_string(),
+                mime_type,
+                text,
+            }),
+            Err(e) => ResourceContent::Blob(BlobResourceContents {
+                uri: uri.to_string(),
+                mime_type,
+                blob: base64::engine::general_purpose::STANDARD.encode(e.into_bytes()),
+            }),
+        })
+    }
+
+    fn supports_subscriptions(&self) -> bool {
+        true
+    }
+
+    /// Start watching a file for changes
+    async fn subscribe(&self, uri: &str) -> Result<(), MCPError> {
+        let path = self.resolve(uri).await?;
+        let modified = modified_time(&path).await;
+        self.subscriptions
+            .lock()
+            .await
+            .insert(uri.to_string(), modified);
+        Ok(())
+    }
+
+    /// Stop watching a file for changes
+    async fn unsubscribe(&self, uri: &str) {
+        self.subscriptions.lock().await.remove(uri);
+    }
+
+    /// Check subscribed files and return the URIs of those that changed
+    ///
+    /// A file that is deleted or recreated counts as changed.
+    async fn poll_changes(&self) -> Vec<String> {
+        let mut subscriptions = self.subscriptions.lock().await;
+        let mut changed = Vec::new();
+
+        for (uri, last_modified) in subscriptions.iter_mut() {
+            let modified = match self.resolve(uri).await {
+                Ok(path) => modified_time(&path).await,
+                Err(_) => None,
+            };
+            if modified != *last_modified {
+                debug!("Resource {} changed", uri);
+                *last_modified = modified;
+                changed.push(uri.clone());
+            }
+        }
+
+        changed
+    }
+}
+
+async fn modified_time(path: &Path) -> Option<SystemTime> {
+    tokio::fs::metadata(path).await.ok()?.modified().ok()
+}
+
+fn io_error(path: &Path, error: std::io::Error) -> MCPError {
+    MCPError::Protocol(format!("Failed to access {}: {}", path.display(), error))
+}
+
+/// Guess a file's MIME type from its extension
+fn guess_mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("txt") | Some("log") => "text/plain",
+        Some("md") => "text/markdown",
+        Some("html") | Some("htm") => "text/html",
+        Some("css") => "text/css",
+        Some("csv") => "text/csv",
+        Some("js") => "text/javascript",
+        Some("json") => "application/json",
+        Some("toml") => "application/toml",
+        Some("yaml") | Some("yml") => "application/yaml",
+        Some("xml") => "application/xml",
+        Some("rs") => "text/x-rust",
+        Some("py") => "text/x-python",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("pdf") => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("mcpr-fs-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        std::fs::write(root.join("docs/readme.md"), "# Hello").unwrap();
+        std::fs::write(root.join("data.bin"), [0xff, 0xfe, 0x00]).unwrap();
+        root
+    }
+
+    fn uri(path: &Path) -> String {
+        Url::from_file_path(path).unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_list_and_read() {
+        let root = temp_root("list");
+        let provider = FsResourceProvider::new(root.clone());
+
+        let resources = provider.list().await.unwrap();
+        let names: Vec<_> = resources.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["data.bin", "docs/readme.md"]);
+
+        let readme = uri(&root.canonicalize().unwrap().join("docs/readme.md"));
+        match provider.read(&readme).await.unwrap() {
+            ResourceContent::Text(text) => {
+                assert_eq!(text.text, "# Hello");
+                assert_eq!(text.mime_type.as_deref(), Some("text/markdown"));
+            }
+            _ => panic!("Expected text contents"),
+        }
+
+        let data = uri(&root.canonicalize().unwrap().join("data.bin"));
+        assert!(matches!(
+            provider.read(&data).await.unwrap(),
+            ResourceContent::Blob(_)
+        ));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    // Symlinks to an ancestor or to a file already listed add nothing
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_list_symlink_cycles() {
+        let root = temp_root("cycles");
+        std::os::unix::fs::symlink(&root, root.join("docs/up")).unwrap();
+        std::os::unix::fs::symlink(root.join("docs"), root.join("again")).unwrap();
+        std::os::unix::fs::symlink(root.join("data.bin"), root.join("docs/data.bin")).unwrap();
+        let provider = FsResourceProvider::new(root.clone());
+
+        let resources = tokio::time::timeout(std::time::Duration::from_secs(5), provider.list())
+            .await
+            .expect("Listing follows a symlink cycle")
+            .unwrap();
+        assert_eq!(resources.len(), 2);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rejects_paths_outside_root() {
+        let root = temp_root("traversal");
+        let provider = FsResourceProvider::new(root.join("docs"));
+
+        // Both a `..` escape and an absolute path outside the root are refused
+        let escape = format!("{}/../data.bin", uri(&root.join("docs")));
+        assert!(provider.read(&escape).await.is_err());
+        assert!(provider.read(&uri(&root.join("data.bin"))).await.is_err());
+        assert!(provider.read("http://example.com/data.bin").await.is_err());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_poll_changes() {
+        let root = temp_root("poll");
+        let provider = FsResourceProvider::new(root.clone());
+        let readme = uri(&root.canonicalize().unwrap().join("docs/readme.md"));
+
+        provider.subscribe(&readme).await.unwrap();
+        assert!(provider.poll_changes().await.is_empty());
+
+        std::fs::remove_file(root.join("docs/readme.md")).unwrap();
+        assert_eq!(provider.poll_changes().await, vec![readme.clone()]);
+
+        provider.unsubscribe(&readme).await;
+        std::fs::write(root.join("docs/readme.md"), "# Again").unwrap();
+        assert!(provider.poll_changes().await.is_empty());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,293 @@
+//! Limits guarding the server against clients sending too many requests
+//!
+//! A [`RateLimit`] caps how often each session may call a method; requests
+//! over the limit are refused with a [`Rejection`] telling the client when
+//! to retry. [`UploadLimits`] bound the chunked uploads a session keeps.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// How often a session may call a method
+///
+/// A token bucket holding up to `requests` tokens, refilled at `requests`
+/// per `period`. Each request takes a token, so a burst of `requests`
+/// calls is allowed, after which calls are spaced out evenly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    requests: u32,
+    period: Duration,
+}
+
+impl RateLimit {
+    /// Allow at most `requests` requests per `period`
+    pub fn new(requests: u32, period: Duration) -> Self {
+        Self {
+            requests: requests.max(1),
+            period,
+        }
+    }
+
+    /// Allow at most `requests` requests per second
+    pub fn per_second(requests: u32) -> Self {
+        Self::new(requests, Duration::from_secs(1))
+    }
+
+    /// Allow at most `requests` requests per minute
+    pub fn per_minute(requests: u32) -> Self {
+        Self::new(requests, Duration::from_secs(60))
+    }
+}
+
+/// The error refused requests are answered with
+///
+/// Its `data` always holds `retryAfter`, in seconds, which clients set up
+/// with [`Client::with_retry_on_rate_limit`](crate::client::Client::with_retry_on_rate_limit)
+/// wait for before retrying.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rejection {
+    /// JSON-RPC error code
+    pub code: i32,
+    /// Error message
+    pub message: String,
+}
+
+impl Rejection {
+    /// Answer refused requests with `code` and `message`
+    pub fn new(code: i32, message: &str) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Bounds on the chunked uploads of a session
+///
+/// Chunks are buffered until the upload is committed, so without bounds a
+/// client could grow the server's memory one chunk at a time. Chunks past a
+/// bound are refused, and the upload they belong to is dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadLimits {
+    /// Largest upload, as a multiple of the advertised `maxMessageSize`
+    pub size_factor: usize,
+    /// Most chunks of one upload
+    pub max_chunks: u32,
+    /// Most uploads in progress at once
+    pub max_uploads: usize,
+    /// How long an upload is kept without new chunks before it is dropped
+    pub expiry: Duration,
+}
+
+impl UploadLimits {
+    /// The default limits: 16 times `maxMessageSize` in up to 1024 chunks,
+    /// 4 uploads at once, dropped after a minute without chunks
+    pub fn new() -> Self {
+        Self {
+            size_factor: 16,
+            max_chunks: 1024,
+            max_uploads: 4,
+            expiry: Duration::from_secs(60),
+        }
+    }
+
+    /// Set the largest upload, as a multiple of the advertised `maxMessageSize`
+    pub fn with_size_factor(mut self, size_factor: usize) -> Self {
+        self.size_factor = size_factor.max(1);
+        self
+    }
+
+    /// Set the most chunks of one upload
+    pub fn with_max_chunks(mut self, max_chunks: u32) -> Self {
+        self.max_chunks = max_chunks.max(1);
+        self
+    }
+
+    /// Set the most uploads in progress at once
+    pub fn with_max_uploads(mut self, max_uploads: usize) -> Self {
+        self.max_uploads = max_uploads.max(1);
+        self
+    }
+
+    /// Set how long an upload is kept without new chunks
+    pub fn with_expiry(mut self, expiry: Duration) -> Self {
+        self.expiry = expiry;
+        self
+    }
+}
+
+impl Default for UploadLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The chunks of an upload received so far
+#[derive(Debug, Clone)]
+pub(crate) struct Upload {
+    chunks: BTreeMap<u32, String>,
+    size: usize,
+    /// The number of chunks the client declared, if it did
+    chunk_count: Option<u32>,
+    updated: Instant,
+}
+
+impl Upload {
+    /// An upload without chunks
+    pub(crate) fn new() -> Self {
+        Self {
+            chunks: BTreeMap::new(),
+            size: 0,
+            chunk_count: None,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Whether no chunk arrived for longer than `expiry`
+    pub(crate) fn expired(&self, expiry: Duration) -> bool {
+        self.updated.elapsed() > expiry
+    }
+
+    /// Store a chunk, or tell why it is refused
+    ///
+    /// `max_size` is the most bytes the whole upload may hold.
+    pub(crate) fn add(
+        &mut self,
+        index: u32,
+        chunk_count: Option<u32>,
+        data: String,
+        limits: &UploadLimits,
+        max_size: usize,
+    ) -> Result<(), String> {
+        if let Some(count) = chunk_count {
+            if count > limits.max_chunks {
+                return Err(format!(
+                    "chunkCount {} exceeds the limit of {} chunks",
+                    count, limits.max_chunks
+                ));
+            }
+            if self.chunk_count.is_some_and(|declared| declared != count) {
+                return Err(format!(
+                    "chunkCount {} differs from the {} declared before",
+                    count,
+                    self.chunk_count.unwrap_or_default()
+                ));
+            }
+            self.chunk_count = Some(count);
+        }
+        let bound = self.chunk_count.unwrap_or(limits.max_chunks);
+        if index >= bound {
+            return Err(format!(
+                "chunkIndex {} is out of range for {} chunks",
+                index, bound
+            ));
+        }
+
+        let replaced = self.chunks.get(&index).map_or(0, String::len);
+        let size = self.size - replaced + data.len();
+        if size > max_size {
+            return Err(format!("Upload exceeds the limit of {} bytes", max_size));
+        }
+        self.size = size;
+        self.chunks.insert(index, data);
+        self.updated = Instant::now();
+        Ok(())
+    }
+
+    /// The number of chunks the client declared, if it did
+    pub(crate) fn chunk_count(&self) -> Option<u32> {
+        self.chunk_count
+    }
+
+    /// The chunks received, by index
+    pub(crate) fn into_chunks(self) -> BTreeMap<u32, String> {
+        self.chunks
+    }
+}
+
+/// Tokens left of a [`RateLimit`] in one session
+#[derive(Debug, Clone)]
+pub(crate) struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket
+    pub(crate) fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            tokens: limit.requests as f64,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Take a token, or return how long until one is available
+    pub(crate) fn take(&mut self) -> Result<(), Duration> {
+        let now = Instant::now();
+        let capacity = self.limit.requests as f64;
+        let refill = capacity / self.limit.period.as_secs_f64().max(f64::EPSILON);
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * refill).min(capacity);
+        self.updated = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            // Saturates for periods too long for the wait to fit a Duration
+            Err(Duration::try_from_secs_f64((1.0 - self.tokens) / refill).unwrap_or(Duration::MAX))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_token_bucket() {
+        let mut bucket = TokenBucket::new(RateLimit::per_second(2));
+        assert!(bucket.take().is_ok());
+        assert!(bucket.take().is_ok());
+        let wait = bucket.take().unwrap_err();
+        assert!(wait > Duration::from_millis(490) && wait <= Duration::from_millis(500));
+
+        tokio::time::advance(Duration::from_millis(500)).await;
+        assert!(bucket.take().is_ok());
+        assert!(bucket.take().is_err());
+
+        let mut bucket = TokenBucket::new(RateLimit::new(1, Duration::MAX));
+        assert!(bucket.take().is_ok());
+        assert_eq!(bucket.take(), Err(Duration::MAX));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_upload_limits() {
+        let limits = UploadLimits::new().with_max_chunks(4);
+        let mut upload = Upload::new();
+        assert!(upload.add(0, None, "abcd".to_string(), &limits, 10).is_ok());
+        // Resending a chunk replaces it
+        assert!(upload.add(0, None, "abcd".to_string(), &limits, 10).is_ok());
+        assert!(upload
+            .add(1, None, "efghijk".to_string(), &limits, 10)
+            .is_err());
+        assert!(upload.add(4, None, "e".to_string(), &limits, 10).is_err());
+
+        // The declared count bounds the indexes and cannot change
+        assert!(upload
+            .add(5, Some(5), "e".to_string(), &limits, 10)
+            .is_err());
+        assert!(upload.add(1, Some(2), "e".to_string(), &limits, 10).is_ok());
+        assert!(upload.add(2, None, "f".to_string(), &limits, 10).is_err());
+        assert!(upload
+            .add(1, Some(3), "f".to_string(), &limits, 10)
+            .is_err());
+        assert_eq!(upload.chunk_count(), Some(2));
+
+        assert!(!upload.expired(limits.expiry));
+        tokio::time::advance(limits.expiry + Duration::from_secs(1)).await;
+        assert!(upload.expired(limits.expiry));
+    }
+}
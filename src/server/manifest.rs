@@ -0,0 +1,358 @@
+//! Servers described by a manifest file
+//!
+//! A [`Manifest`] holds what a simple server needs without code: its name
+//! and version, capabilities, static resources and prompt templates. It is
+//! written in JSON, or in TOML with the `toml` feature:
+//!
+//! ```toml
+//! name = "notes"
+//! version = "1.0.0"
+//!
+//! [[resources]]
+//! uri = "notes://readme"
+//! name = "Readme"
+//! mimeType = "text/markdown"
+//! path = "README.md"
+//!
+//! [[prompts]]
+//! name = "summarize"
+//! description = "Summarize a topic"
+//! template = "Summarize what the notes say about {{topic}}."
+//! arguments = [{ name = "topic", required = true }]
+//! ```
+//!
+//! Resources are given inline as `text`, or as the `path` of a file,
+//! relative to the manifest. Templates refer to their arguments as
+//! `{{name}}` and embed resources as `{{resource:<uri>}}`, see
+//! [`PromptTemplate`].
+
+use super::prompts::{segments, PromptTemplate, Segment};
+use crate::{
+    error::MCPError,
+    schema::{
+        client::ResourceContent,
+        common::{BlobResourceContents, Resource, TextResourceContents},
+        server::ServerCapabilities,
+    },
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::Deserialize;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+/// A server described by a manifest, see the [module docs](self)
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Manifest {
+    /// Server name
+    pub name: String,
+    /// Server version
+    pub version: String,
+    /// Capabilities advertised instead of the ones derived from the server
+    #[serde(default)]
+    pub capabilities: ServerCapabilities,
+    /// Resources with fixed contents
+    #[serde(default)]
+    pub resources: Vec<StaticResource>,
+    /// Prompts rendered from templates
+    #[serde(default)]
+    pub prompts: Vec<PromptTemplate>,
+}
+
+/// A resource with fixed contents, given inline or read from a file
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct StaticResource {
+    /// URI of the resource
+    pub uri: String,
+    /// Human-readable name
+    pub name: String,
+    /// What the resource holds
+    #[serde(default)]
+    pub description: Option<String>,
+    /// MIME type of the contents
+    #[serde(default)]
+    pub mime_type: Option<String>,
+    /// The contents, as text
+    #[serde(default)]
+    pub text: Option<String>,
+    /// File holding the contents, relative to the manifest
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+}
+
+impl StaticResource {
+    /// The resource as listed by `resources/list`
+    pub fn resource(&self) -> Resource {
+        Resource {
+            uri: self.uri.clone(),
+            name: self.name.clone(),
+            description: self.description.clone(),
+            mime_type: self.mime_type.clone(),
+            size: None,
+            annotations: None,
+        }
+    }
+
+    /// The contents, reading the file if there is one
+    ///
+    /// Files that are not UTF-8 are served as base64 blobs.
+    pub fn contents(&self) -> Result<ResourceContent, MCPError> {
+        let bytes = match (&self.text, &self.path) {
+            (Some(text), None) => text.clone().into_bytes(),
+            (None, Some(path)) => std::fs::read(path)
+                .map_err(|e| self.error(format!("cannot read '{}': {}", path.display(), e)))?,
+            _ => return Err(self.error("needs either `text` or `path`".to_string())),
+        };
+        Ok(match String::from_utf8(bytes) {
+            Ok(text) => ResourceContent::Text(TextResourceContents {
+                uri: self.uri.clone(),
+                mime_type: self.mime_type.clone(),
+                text,
+            }),
+            Err(e) => ResourceContent::Blob(BlobResourceContents {
+                uri: self.uri.clone(),
+                mime_type: self.mime_type.clone(),
+                blob: BASE64.encode(e.into_bytes()),
+            }),
+        })
+    }
+
+    fn error(&self, message: String) -> MCPError {
+        MCPError::Protocol(format!("resource '{}' {}", self.uri, message))
+    }
+}
+
+impl Manifest {
+    /// Read a manifest, as TOML if the file ends in `.toml` and JSON otherwise
+    ///
+    /// Relative resource paths are resolved against the manifest's
+    /// directory, and the manifest is [validated](Manifest::validate).
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, MCPError> {
+        let path = path.as_ref();
+        let invalid = |e: MCPError| MCPError::InvalidManifest {
+            path: path.display().to_string(),
+            message: match e {
+                MCPError::Protocol(message) => message,
+                e => e.to_string(),
+            },
+        };
+
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| invalid(MCPError::Protocol(e.to_string())))?;
+        let mut manifest = if path.extension().is_some_and(|ext| ext == "toml") {
+            Self::from_toml_str(&source)
+        } else {
+            Self::from_json_str(&source)
+        }
+        .map_err(invalid)?;
+
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        for resource in &mut manifest.resources {
+            if let Some(file) = resource.path.as_mut() {
+                *file = base.join(&*file);
+            }
+        }
+        manifest.validate().map_err(invalid)?;
+        Ok(manifest)
+    }
+
+    /// Parse a JSON manifest
+    pub fn from_json_str(source: &str) -> Result<Self, MCPError> {
+        Ok(serde_json::from_str(source)?)
+    }
+
+    /// Parse a TOML manifest
+    #[cfg(feature = "toml")]
+    pub fn from_toml_str(source: &str) -> Result<Self, MCPError> {
+        let document: toml_edit::DocumentMut = source
+            .parse()
+            .map_err(|e: toml_edit::TomlError| MCPError::Protocol(e.to_string()))?;
+        Ok(serde_json::from_value(toml::table_to_json(
+            document.as_table(),
+        ))?)
+    }
+
+    /// Parse a TOML manifest, which needs the `toml` feature
+    #[cfg(not(feature = "toml"))]
+    pub fn from_toml_str(_source: &str) -> Result<Self, MCPError> {
+        Err(MCPError::UnsupportedFeature(
+            "TOML manifests need the `toml` feature".to_string(),
+        ))
+    }
+
+    /// Check that the manifest holds together
+    ///
+    /// Resource URIs and prompt names must be unique, resources need their
+    /// contents, which must be readable, and templates may only refer to
+    /// their declared arguments.
+    pub fn validate(&self) -> Result<(), MCPError> {
+        if self.name.trim().is_empty() {
+            return Err(MCPError::Protocol("the server name is empty".to_string()));
+        }
+
+        let mut uris = HashSet::new();
+        for resource in &self.resources {
+            if !uris.insert(&resource.uri) {
+                return Err(resource.error("is listed twice".to_string()));
+            }
+            resource.contents()?;
+        }
+
+        let mut names = HashSet::new();
+        for prompt in &self.prompts {
+            if !names.insert(&prompt.name) {
+                return Err(MCPError::Protocol(format!(
+                    "prompt '{}' is listed twice",
+                    prompt.name
+                )));
+            }
+            for segment in segments(&prompt.template)? {
+                match segment {
+                    Segment::Argument(name) if !prompt.arguments.iter().any(|a| a.name == name) => {
+                        return Err(MCPError::Protocol(format!(
+                            "prompt '{}' uses undeclared argument '{}'",
+                            prompt.name, name
+                        )))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// TOML documents as the JSON the manifest is deserialized from
+#[cfg(feature = "toml")]
+mod toml {
+    use serde_json::{Map, Number, Value};
+    use toml_edit::{Item, Table};
+
+    pub(super) fn table_to_json(table: &Table) -> Value {
+        Value::Object(
+            table
+                .iter()
+                .filter_map(|(key, item)| Some((key.to_string(), item_to_json(item)?)))
+                .collect::<Map<_, _>>(),
+        )
+    }
+
+    fn item_to_json(item: &Item) -> Option<Value> {
+        match item {
+            Item::None => None,
+            Item::Value(value) => Some(value_to_json(value)),
+            Item::Table(table) => Some(table_to_json(table)),
+            Item::ArrayOfTables(tables) => {
+                Some(Value::Array(tables.iter().map(table_to_json).collect()))
+            }
+        }
+    }
+
+    fn value_to_json(value: &toml_edit::Value) -> Value {
+        match value {
+            toml_edit::Value::String(s) => Value::String(s.value().clone()),
+            toml_edit::Value::Integer(i) => Value::from(*i.value()),
+            toml_edit::Value::Float(f) => {
+                Number::from_f64(*f.value()).map_or(Value::Null, Value::Number)
+            }
+            toml_edit::Value::Boolean(b) => Value::Bool(*b.value()),
+            toml_edit::Value::Datetime(d) => Value::String(d.value().to_string()),
+            toml_edit::Value::Array(array) => {
+                Value::Array(array.iter().map(value_to_json).collect())
+            }
+            toml_edit::Value::InlineTable(table) => Value::Object(
+                table
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value_to_json(value)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_manifest_validation() {
+        let manifest = Manifest::from_json_str(
+            r#"{
+                "name": "notes",
+                "version": "1.0.0",
+                "prompts": [{
+                    "name": "summarize",
+                    "template": "Summarize {{ topic }}{{style}}.",
+                    "arguments": [{ "name": "topic", "required": true }, { "name": "style" }]
+                }]
+            }"#,
+        )
+        .unwrap();
+        manifest.validate().unwrap();
+
+        let prompt = &manifest.prompts[0];
+        let arguments = HashMap::from([("topic".to_string(), "the week".to_string())]);
+        let resources = HashMap::new();
+        assert_eq!(
+            prompt.render(&arguments, &resources).unwrap(),
+            "Summarize the week."
+        );
+
+        let mut broken = manifest.clone();
+        broken.prompts[0].template = "{{missing}}".to_string();
+        assert!(broken.validate().is_err());
+
+        let mut broken = manifest.clone();
+        broken.resources.push(StaticResource {
+            uri: "notes://none".to_string(),
+            name: "None".to_string(),
+            description: None,
+            mime_type: None,
+            text: None,
+            path: None,
+        });
+        assert!(broken.validate().is_err());
+
+        assert!(Manifest::from_json_str(r#"{ "name": "x", "version": "1", "tool": [] }"#).is_err());
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_toml_manifest() {
+        let manifest = Manifest::from_toml_str(
+            r#"
+            name = "notes"
+            version = "1.0.0"
+
+            [capabilities.resources]
+            subscribe = true
+
+            [[resources]]
+            uri = "notes://readme"
+            name = "Readme"
+            mimeType = "text/markdown"
+            text = "Notes"
+
+            [[prompts]]
+            name = "summarize"
+            template = "Summarize {{topic}}."
+            arguments = [{ name = "topic", required = true }]
+            "#,
+        )
+        .unwrap();
+        manifest.validate().unwrap();
+        assert_eq!(
+            manifest.resources[0].mime_type.as_deref(),
+            Some("text/markdown")
+        );
+        assert_eq!(manifest.prompts[0].arguments[0].required, Some(true));
+        assert_eq!(
+            manifest.capabilities.resources.unwrap().subscribe,
+            Some(true)
+        );
+    }
+}
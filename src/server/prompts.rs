@@ -0,0 +1,251 @@
+//! Prompts rendered from templates
+//!
+//! A [`PromptTemplate`] is a prompt with named arguments whose single user
+//! message is rendered from a template. `{{name}}` is replaced by the
+//! argument `name`, and `{{resource:<uri>}}` by the contents of the
+//! server's resource at `<uri>`. Templates are defined with
+//! [`Server::prompt`] or in a [`Manifest`](super::Manifest), and answer
+//! `prompts/list` and `prompts/get`.
+
+use super::Server;
+use crate::{
+    error::MCPError,
+    schema::{
+        client::GetPromptResult,
+        common::{Prompt, PromptArgument, PromptMessage, PromptMessageContent, Role, TextContent},
+    },
+    transport::Transport,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Prefix of placeholders embedding a resource
+const RESOURCE_PREFIX: &str = "resource:";
+
+/// A prompt whose single user message is rendered from a template
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct PromptTemplate {
+    /// Name of the prompt
+    pub name: String,
+    /// What the prompt is for
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Arguments the template refers to as `{{name}}`
+    #[serde(default)]
+    pub arguments: Vec<PromptArgument>,
+    /// Text of the message
+    pub template: String,
+}
+
+/// A part of a template
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Segment<'a> {
+    Text(&'a str),
+    Argument(&'a str),
+    Resource(&'a str),
+}
+
+/// Split a template into text and placeholders
+pub(crate) fn segments(template: &str) -> Result<Vec<Segment<'_>>, MCPError> {
+    let mut segments = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..]
+            .find("}}")
+            .map(|end| start + end)
+            .ok_or_else(|| MCPError::Protocol(format!("Unclosed '{{{{' in '{}'", template)))?;
+        if start > 0 {
+            segments.push(Segment::Text(&rest[..start]));
+        }
+        let placeholder = rest[start + 2..end].trim();
+        segments.push(match placeholder.strip_prefix(RESOURCE_PREFIX) {
+            Some(uri) => Segment::Resource(uri.trim()),
+            None => Segment::Argument(placeholder),
+        });
+        rest = &rest[end + 2..];
+    }
+    if !rest.is_empty() {
+        segments.push(Segment::Text(rest));
+    }
+    Ok(segments)
+}
+
+impl PromptTemplate {
+    /// The prompt as listed by `prompts/list`
+    pub fn prompt(&self) -> Prompt {
+        Prompt {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            arguments: (!self.arguments.is_empty()).then(|| self.arguments.clone()),
+        }
+    }
+
+    /// URIs of the resources the template embeds
+    pub fn resources(&self) -> Result<Vec<&str>, MCPError> {
+        Ok(segments(&self.template)?
+            .into_iter()
+            .filter_map(|segment| match segment {
+                Segment::Resource(uri) => Some(uri),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Substitute `arguments`, and the text of the embedded `resources` by URI
+    ///
+    /// Fails if a required argument or an embedded resource is missing;
+    /// optional arguments default to an empty string.
+    pub fn render(
+        &self,
+        arguments: &HashMap<String, String>,
+        resources: &HashMap<String, String>,
+    ) -> Result<String, MCPError> {
+        for argument in &self.arguments {
+            if argument.required == Some(true) && !arguments.contains_key(&argument.name) {
+                return Err(MCPError::Protocol(format!(
+                    "Missing required argument '{}' of prompt '{}'",
+                    argument.name, self.name
+                )));
+            }
+        }
+
+        let mut rendered = String::new();
+        for segment in segments(&self.template)? {
+            match segment {
+                Segment::Text(text) => rendered.push_str(text),
+                Segment::Argument(name) => {
+                    rendered.push_str(arguments.get(name).map_or("", String::as_str))
+                }
+                Segment::Resource(uri) => {
+                    rendered.push_str(resources.get(uri).ok_or_else(|| {
+                        MCPError::Protocol(format!(
+                            "Resource '{}' of prompt '{}' not found",
+                            uri, self.name
+                        ))
+                    })?)
+                }
+            }
+        }
+        Ok(rendered)
+    }
+
+    /// The answer to `prompts/get` for this prompt, see [`PromptTemplate::render`]
+    pub fn get(
+        &self,
+        arguments: &HashMap<String, String>,
+        resources: &HashMap<String, String>,
+    ) -> Result<GetPromptResult, MCPError> {
+        Ok(GetPromptResult {
+            description: self.description.clone(),
+            messages: vec![PromptMessage {
+                role: Role::User,
+                content: PromptMessageContent::Text(TextContent {
+                    r#type: "text".to_string(),
+                    text: self.render(arguments, resources)?,
+                    annotations: None,
+                }),
+            }],
+        })
+    }
+}
+
+/// Defines a prompt on a server, see [`Server::prompt`]
+#[must_use = "the prompt is only registered by `template`"]
+pub struct PromptBuilder<'a, T: Transport> {
+    server: &'a mut Server<T>,
+    prompt: PromptTemplate,
+}
+
+impl<'a, T: Transport + Send + Sync + Clone + 'static> PromptBuilder<'a, T> {
+    pub(crate) fn new(server: &'a mut Server<T>, name: &str) -> Self {
+        Self {
+            server,
+            prompt: PromptTemplate {
+                name: name.to_string(),
+                description: None,
+                arguments: Vec::new(),
+                template: String::new(),
+            },
+        }
+    }
+
+    /// Describe what the prompt is for
+    pub fn description(mut self, description: &str) -> Self {
+        self.prompt.description = Some(description.to_string());
+        self
+    }
+
+    /// Declare a required argument
+    pub fn argument(self, name: &str, description: &str) -> Self {
+        self.declare(name, description, true)
+    }
+
+    /// Declare an optional argument, which renders as an empty string when missing
+    pub fn optional_argument(self, name: &str, description: &str) -> Self {
+        self.declare(name, description, false)
+    }
+
+    fn declare(mut self, name: &str, description: &str, required: bool) -> Self {
+        self.prompt
+            .arguments
+            .retain(|argument| argument.name != name);
+        self.prompt.arguments.push(PromptArgument {
+            name: name.to_string(),
+            description: Some(description.to_string()),
+            required: Some(required),
+        });
+        self
+    }
+
+    /// Set the template of the message and register the prompt
+    ///
+    /// Placeholders for arguments that were not declared declare required
+    /// arguments. Fails if a `{{` is not closed.
+    pub fn template(mut self, template: &str) -> Result<(), MCPError> {
+        for segment in segments(template)? {
+            if let Segment::Argument(name) = segment {
+                if !self.prompt.arguments.iter().any(|a| a.name == name) {
+                    self.prompt.arguments.push(PromptArgument {
+                        name: name.to_string(),
+                        description: None,
+                        required: Some(true),
+                    });
+                }
+            }
+        }
+        self.prompt.template = template.to_string();
+        self.server.register_prompt(self.prompt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_template() {
+        let prompt = PromptTemplate {
+            name: "review".to_string(),
+            description: None,
+            arguments: vec![PromptArgument {
+                name: "focus".to_string(),
+                description: None,
+                required: Some(true),
+            }],
+            template: "Review {{ focus }} in:\n{{resource: file:///main.rs}}".to_string(),
+        };
+        assert_eq!(prompt.resources().unwrap(), ["file:///main.rs"]);
+
+        let arguments = HashMap::from([("focus".to_string(), "naming".to_string())]);
+        let resources =
+            HashMap::from([("file:///main.rs".to_string(), "fn main() {}".to_string())]);
+        assert_eq!(
+            prompt.render(&arguments, &resources).unwrap(),
+            "Review naming in:\nfn main() {}"
+        );
+        assert!(prompt.render(&HashMap::new(), &resources).is_err());
+        assert!(prompt.render(&arguments, &HashMap::new()).is_err());
+        assert!(segments("{{unclosed").is_err());
+    }
+}
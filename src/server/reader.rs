@@ -0,0 +1,160 @@
+//! Resources read from byte streams, whole or in chunks
+//!
+//! A [`ResourceReader`] serves a resource from any [`AsyncRead`], such as a
+//! large file, without holding all of it in memory. Clients that ask for
+//! chunks with `_meta.chunkSize` get one part per `resources/read`, with a
+//! cursor for the next; others get the resource whole.
+
+use crate::{
+    error::MCPError,
+    schema::{
+        client::ResourceContent,
+        common::{BlobResourceContents, TextResourceContents},
+    },
+};
+use base64::Engine;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// A resource's contents, read from a byte stream as they are served
+///
+/// Blob chunks are a multiple of three bytes, except the last, so their
+/// base64 strings concatenate into the base64 of the whole resource. Text
+/// chunks end on character boundaries.
+pub struct ResourceReader {
+    reader: Box<dyn AsyncRead + Send + Unpin>,
+    mime_type: Option<String>,
+    text: bool,
+    /// Bytes of a character split by the previous text chunk
+    pending: Vec<u8>,
+}
+
+impl ResourceReader {
+    /// Serve the bytes of `reader` as a base64 blob
+    pub fn blob(reader: impl AsyncRead + Send + Unpin + 'static) -> Self {
+        Self {
+            reader: Box::new(reader),
+            mime_type: None,
+            text: false,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Serve the bytes of `reader` as text, which must be UTF-8
+    pub fn text(reader: impl AsyncRead + Send + Unpin + 'static) -> Self {
+        Self {
+            text: true,
+            ..Self::blob(reader)
+        }
+    }
+
+    /// Set the MIME type of the contents
+    pub fn with_mime_type(mut self, mime_type: &str) -> Self {
+        self.mime_type = Some(mime_type.to_string());
+        self
+    }
+
+    /// Read the next chunk of at most `max_size` bytes
+    ///
+    /// Returns the chunk, and whether the stream ended with it.
+    pub(crate) async fn read_chunk(
+        &mut self,
+        uri: &str,
+        max_size: usize,
+    ) -> Result<(ResourceContent, bool), MCPError> {
+        let max_size = if self.text {
+            max_size.max(4)
+        } else {
+            (max_size - max_size % 3).max(3)
+        };
+        let mut bytes = std::mem::take(&mut self.pending);
+        let mut ended = false;
+        while bytes.len() < max_size {
+            let mut chunk = vec![0; max_size - bytes.len()];
+            let read = self
+                .reader
+                .read(&mut chunk)
+                .await
+                .map_err(|e| MCPError::Transport(format!("Failed to read {}: {}", uri, e)))?;
+            if read == 0 {
+                ended = true;
+                break;
+            }
+            bytes.extend_from_slice(&chunk[..read]);
+        }
+        Ok((self.content(uri, bytes, ended)?, ended))
+    }
+
+    /// Read everything that is left
+    pub(crate) async fn read_all(&mut self, uri: &str) -> Result<ResourceContent, MCPError> {
+        let mut bytes = std::mem::take(&mut self.pending);
+        self.reader
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(|e| MCPError::Transport(format!("Failed to read {}: {}", uri, e)))?;
+        self.content(uri, bytes, true)
+    }
+
+    /// The contents of `bytes`, read from the stream
+    fn content(
+        &mut self,
+        uri: &str,
+        mut bytes: Vec<u8>,
+        ended: bool,
+    ) -> Result<ResourceContent, MCPError> {
+        Ok(if self.text {
+            // Keep the start of a split character for the next chunk
+            if let Err(e) = std::str::from_utf8(&bytes) {
+                if e.error_len().is_none() && !ended {
+                    self.pending = bytes.split_off(e.valid_up_to());
+                }
+            }
+            let text = String::from_utf8(bytes)
+                .map_err(|_| MCPError::Protocol(format!("Resource {} is not UTF-8", uri)))?;
+            ResourceContent::Text(TextResourceContents {
+                uri: uri.to_string(),
+                mime_type: self.mime_type.clone(),
+                text,
+            })
+        } else {
+            ResourceContent::Blob(BlobResourceContents {
+                uri: uri.to_string(),
+                mime_type: self.mime_type.clone(),
+                blob: base64::engine::general_purpose::STANDARD.encode(&bytes),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resource_reader() -> Result<(), MCPError> {
+        let data: Vec<u8> = (0..=255).collect();
+        let mut reader = ResourceReader::blob(std::io::Cursor::new(data.clone()));
+        let mut blob = String::new();
+        loop {
+            let (chunk, ended) = reader.read_chunk("mem://data", 100).await?;
+            let ResourceContent::Blob(chunk) = chunk else {
+                panic!("expected a blob");
+            };
+            blob.push_str(&chunk.blob);
+            if ended {
+                break;
+            }
+        }
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(blob)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        // "é" is two bytes, so a chunk of five bytes would split the third
+        let mut reader = ResourceReader::text(std::io::Cursor::new("ééé".as_bytes().to_vec()));
+        let (first, ended) = reader.read_chunk("mem://text", 5).await?;
+        assert_eq!((first.text(), ended), (Some("éé"), false));
+        let rest = reader.read_all("mem://text").await?;
+        assert_eq!(rest.text(), Some("é"));
+        Ok(())
+    }
+}
@@ -0,0 +1,263 @@
+//! Serving many clients from one server
+//!
+//! A [`SessionManager`] gives every connection its own session of a server:
+//! the handlers are shared, while what each client negotiated, its log level
+//! and its resource subscriptions are kept apart, and resource updates only
+//! reach the sessions subscribed to them.
+//!
+//! ```rust,no_run
+//! # use mcpr::{server::{Server, ServerConfig, SessionManager}, transport::ipc::{IpcListener, IpcTransport}};
+//! # async fn run() -> Result<(), mcpr::error::MCPError> {
+//! let server: Server<IpcTransport> = Server::new(ServerConfig::new());
+//! let mut sessions = SessionManager::new(server);
+//! sessions.on_session_end(|session| log::info!("Session {} ended", session.id))?;
+//!
+//! let listener = IpcListener::bind("/tmp/mcpr.sock")?;
+//! loop {
+//!     sessions.spawn(listener.accept().await?);
+//! }
+//! # }
+//! ```
+
+use super::Server;
+use crate::{
+    error::MCPError,
+    schema::{
+        common::{Implementation, LoggingLevel},
+        json_rpc::{JSONRPCMessage, JSONRPCNotification},
+    },
+    transport::Transport,
+};
+use serde_json::Value;
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
+use tokio::{sync::Mutex, task::JoinHandle};
+
+/// State of a client's session, as seen by the server
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    /// Id of the session among the server's connections
+    pub id: u64,
+    /// Protocol version agreed on initialization
+    pub protocol_version: Option<String>,
+    /// Name and version the client gave on initialization
+    pub client_info: Option<Implementation>,
+    /// Level the client set with `logging/setLevel`
+    pub log_level: Option<LoggingLevel>,
+    /// URIs of the resources the client subscribed to
+    pub subscriptions: BTreeSet<String>,
+    /// Round-trip time of the last keep-alive ping the client answered
+    pub ping_latency: Option<Duration>,
+}
+
+/// Called when a session starts or ends
+type SessionHook = Arc<dyn Fn(&Session) + Send + Sync>;
+
+/// Serves each connection in a session of its own, see the [module docs](self)
+#[derive(Clone)]
+pub struct SessionManager<T: Transport + Send + Sync> {
+    server: Server<T>,
+    on_start: Arc<Mutex<Option<SessionHook>>>,
+    on_end: Arc<Mutex<Option<SessionHook>>>,
+}
+
+impl<T: Transport + Send + Sync + Clone + 'static> SessionManager<T> {
+    /// Serve sessions of `server`, which has its handlers registered
+    pub fn new(server: Server<T>) -> Self {
+        Self {
+            server,
+            on_start: Arc::new(Mutex::new(None)),
+            on_end: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Register a hook called when a client connects, before it initializes
+    pub fn on_session_start<F>(&mut self, hook: F) -> Result<(), MCPError>
+    where
+        F: Fn(&Session) + Send + Sync + 'static,
+    {
+        let mut on_start = self.on_start.try_lock().map_err(|_| {
+            MCPError::Protocol("Failed to acquire lock on session start hook".to_string())
+        })?;
+        *on_start = Some(Arc::new(hook));
+        Ok(())
+    }
+
+    /// Register a hook called with the final state of a session once its client is gone
+    pub fn on_session_end<F>(&mut self, hook: F) -> Result<(), MCPError>
+    where
+        F: Fn(&Session) + Send + Sync + 'static,
+    {
+        let mut on_end = self.on_end.try_lock().map_err(|_| {
+            MCPError::Protocol("Failed to acquire lock on session end hook".to_string())
+        })?;
+        *on_end = Some(Arc::new(hook));
+        Ok(())
+    }
+
+    /// Serve a connection in a new session until the client leaves
+    pub async fn serve(&self, transport: T) -> Result<(), MCPError> {
+        let mut session = self.server.new_session();
+        if let Some(hook) = self.on_start.lock().await.clone() {
+            hook(&session.session().await);
+        }
+        let result = session.serve(transport).await;
+        if let Some(hook) = self.on_end.lock().await.clone() {
+            hook(&session.session().await);
+        }
+        result
+    }
+
+    /// Serve a connection in a new session, in a task of its own
+    pub fn spawn(&self, transport: T) -> JoinHandle<Result<(), MCPError>> {
+        let manager = self.clone();
+        tokio::spawn(async move { manager.serve(transport).await })
+    }
+
+    /// The sessions of the connected clients
+    pub async fn sessions(&self) -> Vec<Session> {
+        self.server.sessions().await
+    }
+
+    /// Send a notification to every connected client
+    pub async fn notify_all(&self, method: &str, params: Option<Value>) -> Result<(), MCPError> {
+        let notification =
+            JSONRPCMessage::Notification(JSONRPCNotification::new(method.to_string(), params));
+        for mut transport in self.server.connected_transports().await {
+            transport.send(&notification).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        schema::common::{Resource, Tool, ToolInputSchema},
+        server::ServerConfig,
+        transport::in_memory::InMemoryTransport,
+    };
+    use std::sync::Mutex as StdMutex;
+
+    async fn request(
+        client: &mut InMemoryTransport,
+        id: i64,
+        method: &str,
+        params: Value,
+    ) -> Value {
+        client
+            .send(&serde_json::json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+            .await
+            .unwrap();
+        client.receive().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_session_manager() -> Result<(), MCPError> {
+        let tool = Tool {
+            name: "echo".to_string(),
+            description: None,
+            input_schema: ToolInputSchema {
+                r#type: "object".to_string(),
+                properties: None,
+                required: None,
+                keywords: serde_json::Map::new(),
+            },
+            output_schema: None,
+            annotations: None,
+        };
+        let mut server = Server::new(ServerConfig::new().with_tool(tool));
+        server.register_tool_handler("echo", |params| async move { Ok(params) })?;
+        let resources = server.resources_handle();
+
+        let events = Arc::new(StdMutex::new(Vec::new()));
+        let mut manager = SessionManager::new(server);
+        let started = events.clone();
+        manager
+            .on_session_start(move |session| started.lock().unwrap().push(("start", session.id)))?;
+        let ended = events.clone();
+        manager.on_session_end(move |session| ended.lock().unwrap().push(("end", session.id)))?;
+
+        let mut clients = Vec::new();
+        let mut tasks = Vec::new();
+        for _ in 0..2 {
+            let (mut client, server_end) = InMemoryTransport::pair();
+            client.start().await?;
+            tasks.push(manager.spawn(server_end));
+            clients.push(client);
+        }
+
+        let init = |version: &str, name: &str| {
+            serde_json::json!({
+                "protocolVersion": version,
+                "capabilities": {},
+                "clientInfo": { "name": name, "version": "1.0" },
+            })
+        };
+        let response = request(&mut clients[0], 1, "initialize", init("2024-11-05", "a")).await;
+        assert_eq!(response["result"]["protocolVersion"], "2024-11-05");
+        request(&mut clients[1], 1, "initialize", init("2025-03-26", "b")).await;
+        request(
+            &mut clients[0],
+            2,
+            "logging/setLevel",
+            serde_json::json!({ "level": "debug" }),
+        )
+        .await;
+
+        let sessions = manager.sessions().await;
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0].protocol_version.as_deref(), Some("2024-11-05"));
+        assert_eq!(sessions[0].client_info.as_ref().unwrap().name, "a");
+        assert_eq!(sessions[0].log_level, Some(LoggingLevel::Debug));
+        assert_eq!(sessions[1].protocol_version.as_deref(), Some("2025-03-26"));
+        assert_eq!(sessions[1].log_level, None);
+
+        // Both sessions share the tools
+        let response = request(
+            &mut clients[1],
+            2,
+            "tools/call",
+            serde_json::json!({ "name": "echo", "arguments": { "x": 1 } }),
+        )
+        .await;
+        assert_eq!(response["id"], 2);
+        assert!(response.get("result").is_some());
+
+        // List changes and broadcasts reach every session
+        let resource = Resource {
+            uri: "memo://1".to_string(),
+            name: "memo".to_string(),
+            description: None,
+            mime_type: None,
+            size: None,
+            annotations: None,
+        };
+        resources
+            .add(resource, |_| async {
+                Err(MCPError::Protocol("unused".to_string()))
+            })
+            .await?;
+        manager
+            .notify_all("notifications/tools/list_changed", None)
+            .await?;
+        for client in clients.iter_mut() {
+            let changed: Value = client.receive().await?;
+            assert_eq!(changed["method"], "notifications/resources/list_changed");
+            let changed: Value = client.receive().await?;
+            assert_eq!(changed["method"], "notifications/tools/list_changed");
+        }
+
+        for client in clients.iter_mut() {
+            request(client, 3, "shutdown", Value::Null).await;
+        }
+        for task in tasks {
+            task.await.unwrap()?;
+        }
+        assert!(manager.sessions().await.is_empty());
+        let mut events = events.lock().unwrap().clone();
+        events.sort();
+        assert_eq!(events, [("end", 1), ("end", 2), ("start", 1), ("start", 2)]);
+        Ok(())
+    }
+}
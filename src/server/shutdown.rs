@@ -0,0 +1,118 @@
+//! Ending a server gracefully when the process is asked to stop
+//!
+//! Once shutdown begins, by a signal caught by [`Server::run_until_shutdown`]
+//! or a call of [`Server::begin_shutdown`], every session of the server
+//! refuses new requests while the ones in progress get until the
+//! [`GracefulShutdown::deadline`] to finish. Requests still running then are
+//! cancelled and answered with an error, and the connections are closed.
+//!
+//! ```rust,no_run
+//! # use mcpr::{server::{GracefulShutdown, Server, ServerConfig}, transport::stdio::StdioTransport};
+//! # use std::time::Duration;
+//! # async fn run() -> Result<(), mcpr::error::MCPError> {
+//! let config = ServerConfig::new()
+//!     .with_graceful_shutdown(GracefulShutdown::new().with_deadline(Duration::from_secs(5)));
+//! let mut server: Server<StdioTransport> = Server::new(config);
+//! server.run_until_shutdown(StdioTransport::new()).await
+//! # }
+//! ```
+//!
+//! [`Server::run_until_shutdown`]: super::Server::run_until_shutdown
+//! [`Server::begin_shutdown`]: super::Server::begin_shutdown
+
+use log::{info, warn};
+use std::{future::pending, sync::Arc, time::Duration};
+use tokio::{sync::watch, task::JoinHandle};
+
+/// How long requests in progress get to finish once shutdown began, by default
+pub const DEFAULT_SHUTDOWN_DEADLINE: Duration = Duration::from_secs(10);
+
+/// Which signals shut a server down, and how long it takes at most
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GracefulShutdown {
+    /// Whether Ctrl-C (`SIGINT`) shuts the server down
+    pub ctrl_c: bool,
+    /// Whether `SIGTERM` shuts the server down; ignored outside Unix
+    pub sigterm: bool,
+    /// How long requests in progress get to finish before they are cancelled
+    pub deadline: Duration,
+}
+
+impl Default for GracefulShutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GracefulShutdown {
+    /// Shut down on Ctrl-C or `SIGTERM`, within [`DEFAULT_SHUTDOWN_DEADLINE`]
+    pub fn new() -> Self {
+        Self {
+            ctrl_c: true,
+            sigterm: true,
+            deadline: DEFAULT_SHUTDOWN_DEADLINE,
+        }
+    }
+
+    /// Set whether Ctrl-C and `SIGTERM` shut the server down
+    ///
+    /// With neither, only [`Server::begin_shutdown`](super::Server::begin_shutdown) does.
+    pub fn with_signals(mut self, ctrl_c: bool, sigterm: bool) -> Self {
+        self.ctrl_c = ctrl_c;
+        self.sigterm = sigterm;
+        self
+    }
+
+    /// Set how long requests in progress get to finish
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Wait for the configured signals, then send `true` on `trigger`
+    pub(super) fn listen(&self, trigger: Arc<watch::Sender<bool>>) -> JoinHandle<()> {
+        let ctrl_c = self.ctrl_c;
+        let sigterm = self.sigterm;
+        tokio::spawn(async move {
+            let ctrl_c = async {
+                if !ctrl_c {
+                    return pending().await;
+                }
+                if let Err(e) = tokio::signal::ctrl_c().await {
+                    warn!("Failed to listen for Ctrl-C: {}", e);
+                    pending::<()>().await;
+                }
+            };
+            tokio::select! {
+                _ = ctrl_c => info!("Ctrl-C received, shutting down"),
+                _ = terminate(sigterm) => info!("SIGTERM received, shutting down"),
+            }
+            trigger.send_replace(true);
+        })
+    }
+}
+
+/// Resolve on `SIGTERM`, if `enabled`
+#[cfg(unix)]
+async fn terminate(enabled: bool) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    if !enabled {
+        return pending().await;
+    }
+    match signal(SignalKind::terminate()) {
+        Ok(mut signal) => {
+            signal.recv().await;
+        }
+        Err(e) => {
+            warn!("Failed to listen for SIGTERM: {}", e);
+            pending::<()>().await;
+        }
+    }
+}
+
+/// There is no `SIGTERM` to wait for outside Unix
+#[cfg(not(unix))]
+async fn terminate(_enabled: bool) {
+    pending().await
+}
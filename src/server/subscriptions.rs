@@ -0,0 +1,263 @@
+//! Which sessions subscribed to which resources, and telling them of updates
+//!
+//! A [`SubscriptionManager`] is shared by every session of a server. It
+//! records the `resources/subscribe` requests of each session, forgets them
+//! when the session ends, and sends `notifications/resources/updated` to the
+//! sessions subscribed to a resource. Updates to the same resource that come
+//! close together are coalesced into one notification.
+//!
+//! ```rust,ignore
+//! let subscriptions = server.subscriptions();
+//! tokio::spawn(async move {
+//!     while let Some(uri) = changes.recv().await {
+//!         subscriptions.notify_resource_updated(&uri);
+//!     }
+//! });
+//! ```
+
+use super::Connections;
+use crate::{
+    schema::{
+        json_rpc::{JSONRPCMessage, JSONRPCNotification},
+        server::ResourceUpdatedParams,
+    },
+    transport::Transport,
+};
+use log::{error, warn};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// How long updates to a resource are gathered before subscribers are notified
+pub const DEFAULT_UPDATE_DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// Tracks resource subscriptions and notifies subscribers, see the [module docs](self)
+///
+/// Obtained from [`Server::subscriptions`](super::Server::subscriptions).
+/// Clones share the subscriptions.
+pub struct SubscriptionManager<T: Transport + Send + Sync> {
+    /// Ids of the sessions subscribed to each URI
+    subscribers: Arc<Mutex<HashMap<String, BTreeSet<u64>>>>,
+    /// URIs with a notification waiting out the debounce
+    pending: Arc<Mutex<HashSet<String>>>,
+    debounce: Duration,
+    connections: Connections<T>,
+}
+
+impl<T: Transport + Send + Sync> Clone for SubscriptionManager<T> {
+    fn clone(&self) -> Self {
+        Self {
+            subscribers: self.subscribers.clone(),
+            pending: self.pending.clone(),
+            debounce: self.debounce,
+            connections: self.connections.clone(),
+        }
+    }
+}
+
+impl<T: Transport + Send + Sync + Clone + 'static> SubscriptionManager<T> {
+    pub(super) fn new(connections: Connections<T>, debounce: Duration) -> Self {
+        Self {
+            subscribers: Arc::new(Mutex::new(HashMap::new())),
+            pending: Arc::new(Mutex::new(HashSet::new())),
+            debounce,
+            connections,
+        }
+    }
+
+    /// Ids of the sessions subscribed to `uri`
+    pub fn subscribers(&self, uri: &str) -> BTreeSet<u64> {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .get(uri)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// URIs of the resources a session subscribed to
+    pub fn subscriptions(&self, session_id: u64) -> BTreeSet<String> {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, sessions)| sessions.contains(&session_id))
+            .map(|(uri, _)| uri.clone())
+            .collect()
+    }
+
+    /// Tell the sessions subscribed to `uri` that the resource changed
+    ///
+    /// The notification is sent once the debounce set with
+    /// [`ServerConfig::with_update_debounce`](super::ServerConfig::with_update_debounce)
+    /// has passed, to the sessions subscribed by then; further updates to
+    /// the resource in the meantime are sent with it.
+    pub fn notify_resource_updated(&self, uri: &str) {
+        if !self.pending.lock().unwrap().insert(uri.to_string()) {
+            return;
+        }
+        let manager = self.clone();
+        let uri = uri.to_string();
+        tokio::spawn(async move {
+            if !manager.debounce.is_zero() {
+                tokio::time::sleep(manager.debounce).await;
+            }
+            manager.pending.lock().unwrap().remove(&uri);
+            manager.send_update(uri).await;
+        });
+    }
+
+    /// Send `notifications/resources/updated` to the subscribers of `uri`
+    async fn send_update(&self, uri: String) {
+        let sessions = self.subscribers(&uri);
+        if sessions.is_empty() {
+            return;
+        }
+        let params = match serde_json::to_value(ResourceUpdatedParams::new(uri)) {
+            Ok(params) => params,
+            Err(e) => {
+                error!("Failed to serialize resource update: {}", e);
+                return;
+            }
+        };
+        let notification = JSONRPCMessage::Notification(JSONRPCNotification::new(
+            "notifications/resources/updated".to_string(),
+            Some(params),
+        ));
+
+        let transports: Vec<T> = self
+            .connections
+            .lock()
+            .await
+            .iter()
+            .filter(|(id, _)| sessions.contains(id))
+            .map(|(_, connection)| connection.transport.clone())
+            .collect();
+        for mut transport in transports {
+            if let Err(e) = transport.send(&notification).await {
+                warn!("Failed to send resource update: {}", e);
+            }
+        }
+    }
+
+    /// Record that a session subscribed to `uri`
+    pub(super) fn subscribe(&self, session_id: u64, uri: &str) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .entry(uri.to_string())
+            .or_default()
+            .insert(session_id);
+    }
+
+    /// Forget a session's subscription, returning whether nobody is subscribed anymore
+    pub(super) fn unsubscribe(&self, session_id: u64, uri: &str) -> bool {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let Some(sessions) = subscribers.get_mut(uri) else {
+            return true;
+        };
+        sessions.remove(&session_id);
+        if !sessions.is_empty() {
+            return false;
+        }
+        subscribers.remove(uri);
+        true
+    }
+
+    /// Forget the subscriptions of a session that ended
+    ///
+    /// Returns the URIs nobody is subscribed to anymore.
+    pub(super) fn remove_session(&self, session_id: u64) -> Vec<String> {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        let mut unsubscribed = Vec::new();
+        subscribers.retain(|uri, sessions| {
+            if sessions.remove(&session_id) && sessions.is_empty() {
+                unsubscribed.push(uri.clone());
+            }
+            !sessions.is_empty()
+        });
+        unsubscribed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::MCPError,
+        schema::server::{ResourcesCapability, ServerCapabilities},
+        server::{Server, ServerConfig, SessionManager},
+        transport::{in_memory::InMemoryTransport, Transport},
+    };
+    use serde_json::{json, Value};
+    use std::{collections::BTreeSet, time::Duration};
+
+    async fn request(
+        client: &mut InMemoryTransport,
+        id: i64,
+        method: &str,
+        params: Value,
+    ) -> Value {
+        client
+            .send(&json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+            .await
+            .unwrap();
+        client.receive().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_subscription_manager() -> Result<(), MCPError> {
+        let capabilities = ServerCapabilities {
+            resources: Some(ResourcesCapability {
+                subscribe: Some(true),
+                list_changed: None,
+            }),
+            ..Default::default()
+        };
+        let config = ServerConfig::new()
+            .with_capabilities(capabilities)
+            .with_update_debounce(Duration::from_millis(20));
+        let server: Server<InMemoryTransport> = Server::new(config);
+        let _resources = server.resources_handle();
+        let subscriptions = server.subscriptions();
+        let manager = SessionManager::new(server);
+
+        let mut clients = Vec::new();
+        let mut tasks = Vec::new();
+        for uri in ["memo://1", "memo://2"] {
+            let (mut client, server_end) = InMemoryTransport::pair();
+            client.start().await?;
+            tasks.push(manager.spawn(server_end));
+            let response =
+                request(&mut client, 1, "resources/subscribe", json!({ "uri": uri })).await;
+            assert_eq!(response["result"], json!({}));
+            clients.push(client);
+        }
+        assert_eq!(subscriptions.subscribers("memo://1"), BTreeSet::from([1]));
+        assert_eq!(
+            subscriptions.subscriptions(2),
+            BTreeSet::from(["memo://2".to_string()])
+        );
+
+        // Updates close together are sent once, to the subscribers only
+        for _ in 0..3 {
+            subscriptions.notify_resource_updated("memo://1");
+        }
+        subscriptions.notify_resource_updated("memo://2");
+        for (client, uri) in clients.iter_mut().zip(["memo://1", "memo://2"]) {
+            let update: Value = client.receive().await?;
+            assert_eq!(update["method"], "notifications/resources/updated");
+            assert_eq!(update["params"]["uri"], uri);
+            let response = request(client, 2, "ping", Value::Null).await;
+            assert_eq!(response["id"], 2);
+        }
+
+        // Subscriptions end with the session
+        request(&mut clients[0], 3, "shutdown", Value::Null).await;
+        tasks.remove(0).await.unwrap()?;
+        assert!(subscriptions.subscribers("memo://1").is_empty());
+        assert_eq!(subscriptions.subscribers("memo://2"), BTreeSet::from([2]));
+        Ok(())
+    }
+}
@@ -0,0 +1,1030 @@
+//! Test harnesses: golden-file replays and a scripted mock server
+//!
+//! Available with the `test-util` feature.
+//!
+//! ## Golden files
+//!
+//! A [`Recorder`] wraps the transport
+//! of a client or server and captures every frame it sends and receives,
+//! with the time it passed, optionally writing them to a JSON lines file as
+//! they pass so a session that crashed can still be inspected. A
+//! [`Replay`] feeds such a recording back: it stands in for the server in
+//! front of a [`Client`](crate::client::Client), or for the client in front of
+//! a [`Server`](crate::server::Server), and checks that every frame sent
+//! matches the recording. This catches unintended protocol changes, and
+//! reproduces protocol bugs seen in the field from a recording of them.
+//!
+//! ```rust,no_run
+//! use mcpr::{client::Client, testing::Replay};
+//! use serde_json::Value;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), mcpr::error::MCPError> {
+//!     let recording = std::fs::read_to_string("tests/golden/echo.jsonl").unwrap();
+//!     let replay = Replay::from_jsonl(&recording)?;
+//!     let transport = replay.client_transport();
+//!     let mut client = Client::new(transport.clone());
+//!     client.initialize().await?;
+//!     let _: Value = client.call_tool("echo", &serde_json::json!({ "message": "hi" })).await?;
+//!     transport.finish().await
+//! }
+//! ```
+//!
+//! ## Mock server
+//!
+//! A [`MockServer`] answers a client with canned tools, resources, prompts
+//! and responses, records every request it receives, and can be told to
+//! misbehave: send malformed JSON, answer late, fail, or drop the connection.
+//!
+//! ```rust,no_run
+//! use mcpr::{
+//!     client::Client,
+//!     schema::server::CallToolResult,
+//!     testing::{Fault, MockServer},
+//!     Tool,
+//! };
+//! use std::time::Duration;
+//! # async fn run(tool: Tool, result: CallToolResult) -> Result<(), mcpr::error::MCPError> {
+//! let server = MockServer::new().with_tool(tool, result);
+//! let mut client = Client::new(server.transport()).with_timeout(Duration::from_secs(1));
+//! client.initialize().await?;
+//!
+//! // The client skips the malformed answer, and gives up waiting for another
+//! server.inject("tools/call", Fault::Malformed);
+//! assert!(client.call_tool_result("search", &()).await.is_err());
+//! assert_eq!(server.requests().len(), 2);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    constants::{LATEST_PROTOCOL_VERSION, SUPPORTED_PROTOCOL_VERSIONS},
+    error::MCPError,
+    proxy::Direction,
+    redact::Redactor,
+    schema::{
+        client::{GetPromptResult, ResourceContent},
+        common::{Prompt, Resource, Tool},
+        json_rpc::{error_codes, JSONRPCRequest},
+        server::CallToolResult,
+    },
+    server::Server,
+    transport::{CloseCallback, ErrorCallback, Traffic, Transport},
+};
+use async_trait::async_trait;
+use log::warn;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::Write,
+    path::Path,
+    process::ExitStatus,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::{mpsc, watch, Mutex as TokioMutex};
+
+/// How long a replay waits for the code under test by default
+const DEFAULT_REPLAY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A message captured on the wire, and which way it was travelling
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    pub direction: Direction,
+    pub message: Value,
+    /// When the frame passed, in milliseconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+}
+
+impl Frame {
+    /// Create a frame
+    pub fn new(direction: Direction, message: Value) -> Self {
+        Self {
+            direction,
+            message,
+            timestamp: None,
+        }
+    }
+
+    /// Set when the frame passed, in milliseconds since the Unix epoch
+    pub fn with_timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+}
+
+/// Write frames as JSON lines, one frame per line
+pub fn frames_to_jsonl(frames: &[Frame]) -> Result<String, MCPError> {
+    let mut jsonl = String::new();
+    for frame in frames {
+        jsonl.push_str(&serde_json::to_string(frame)?);
+        jsonl.push('\n');
+    }
+    Ok(jsonl)
+}
+
+/// A transport that records every frame passing through another transport
+///
+/// Frames are timestamped as they pass. Clones share the recording, like the
+/// connection of the inner transport.
+pub struct Recorder<T: Transport> {
+    inner: T,
+    /// Which way sent frames travel
+    outgoing: Direction,
+    frames: Arc<Mutex<Vec<Frame>>>,
+    /// File every frame is appended to as it is recorded
+    file: Option<Arc<Mutex<File>>>,
+    redactor: Option<Redactor>,
+}
+
+impl<T: Transport> Recorder<T> {
+    /// Record the transport of a client
+    pub fn client(inner: T) -> Self {
+        Self::new(inner, Direction::ToServer)
+    }
+
+    /// Record the transport of a server
+    pub fn server(inner: T) -> Self {
+        Self::new(inner, Direction::ToClient)
+    }
+
+    fn new(inner: T, outgoing: Direction) -> Self {
+        Self {
+            inner,
+            outgoing,
+            frames: Arc::new(Mutex::new(Vec::new())),
+            file: None,
+            redactor: None,
+        }
+    }
+
+    /// Also write every frame to the file at `path`, as a JSON line, as it is recorded
+    ///
+    /// The file is created, or truncated if it exists, and can be played
+    /// back with [`Replay::from_file`].
+    pub fn with_file(mut self, path: impl AsRef<Path>) -> Result<Self, MCPError> {
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|e| {
+            MCPError::Transport(format!("Failed to create {}: {}", path.display(), e))
+        })?;
+        self.file = Some(Arc::new(Mutex::new(file)));
+        Ok(self)
+    }
+
+    /// Mask secrets in frames with `redactor` before recording them
+    ///
+    /// A redacted recording may no longer replay, if the code under test
+    /// checks the masked values.
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = Some(redactor);
+        self
+    }
+
+    /// The frames recorded so far
+    pub fn frames(&self) -> Vec<Frame> {
+        self.frames.lock().unwrap().clone()
+    }
+
+    /// The frames recorded so far, as JSON lines
+    pub fn to_jsonl(&self) -> Result<String, MCPError> {
+        frames_to_jsonl(&self.frames())
+    }
+
+    fn record(&self, direction: Direction, message: Value) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        let message = match &self.redactor {
+            Some(redactor) => redactor.redact_message(&message),
+            None => message,
+        };
+        let frame = Frame::new(direction, message).with_timestamp(timestamp);
+        if let Some(file) = &self.file {
+            let written = serde_json::to_string(&frame)
+                .map_err(|e| e.to_string())
+                .and_then(|line| {
+                    writeln!(file.lock().unwrap(), "{}", line).map_err(|e| e.to_string())
+                });
+            if let Err(e) = written {
+                warn!("Failed to write a recorded frame: {}", e);
+            }
+        }
+        self.frames.lock().unwrap().push(frame);
+    }
+}
+
+impl<T: Transport + Clone> Clone for Recorder<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            outgoing: self.outgoing,
+            frames: self.frames.clone(),
+            file: self.file.clone(),
+            redactor: self.redactor.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl<T: Transport> Transport for Recorder<T> {
+    async fn start(&mut self) -> Result<(), MCPError> {
+        self.inner.start().await
+    }
+
+    async fn send<M: Serialize + Send + Sync>(&mut self, message: &M) -> Result<(), MCPError> {
+        let value = serde_json::to_value(message)?;
+        self.inner.send(&value).await?;
+        self.record(self.outgoing, value);
+        Ok(())
+    }
+
+    async fn receive<M: DeserializeOwned + Send + Sync>(&mut self) -> Result<M, MCPError> {
+        let value: Value = self.inner.receive().await?;
+        let incoming = match self.outgoing {
+            Direction::ToServer => Direction::ToClient,
+            Direction::ToClient => Direction::ToServer,
+        };
+        self.record(incoming, value.clone());
+        serde_json::from_value(value).map_err(MCPError::Serialization)
+    }
+
+    async fn close(&mut self) -> Result<(), MCPError> {
+        self.inner.close().await
+    }
+
+    fn set_on_close(&mut self, callback: Option<CloseCallback>) {
+        self.inner.set_on_close(callback);
+    }
+
+    fn set_on_error(&mut self, callback: Option<ErrorCallback>) {
+        self.inner.set_on_error(callback);
+    }
+
+    fn set_on_message<F>(&mut self, callback: Option<F>)
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.inner.set_on_message(callback);
+    }
+
+    async fn exit_status(&self) -> Option<ExitStatus> {
+        self.inner.exit_status().await
+    }
+
+    fn share_connection(&self) -> Option<Self> {
+        Some(Self {
+            inner: self.inner.share_connection()?,
+            outgoing: self.outgoing,
+            frames: self.frames.clone(),
+            file: self.file.clone(),
+            redactor: self.redactor.clone(),
+        })
+    }
+
+    fn traffic(&self) -> Option<Traffic> {
+        self.inner.traffic()
+    }
+}
+
+/// A recorded conversation, to be played back against a client or a server
+#[derive(Debug, Clone)]
+pub struct Replay {
+    frames: Arc<Vec<Frame>>,
+    timeout: Duration,
+    /// Whether received frames keep the gaps between them in the recording
+    timing: bool,
+}
+
+impl Replay {
+    /// Replay these frames
+    pub fn new(frames: Vec<Frame>) -> Self {
+        Self {
+            frames: Arc::new(frames),
+            timeout: DEFAULT_REPLAY_TIMEOUT,
+            timing: false,
+        }
+    }
+
+    /// Replay the JSON lines file at `path`, as written by [`Recorder::with_file`]
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, MCPError> {
+        let path = path.as_ref();
+        let jsonl = std::fs::read_to_string(path).map_err(|e| {
+            MCPError::Transport(format!("Failed to read {}: {}", path.display(), e))
+        })?;
+        Self::from_jsonl(&jsonl)
+    }
+
+    /// Replay frames stored as JSON lines, as written by [`Recorder::to_jsonl`]
+    ///
+    /// Blank lines are skipped.
+    pub fn from_jsonl(jsonl: &str) -> Result<Self, MCPError> {
+        let frames = jsonl
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<Frame>, _>>()?;
+        Ok(Self::new(frames))
+    }
+
+    /// Set how long to wait for the code under test to send an expected frame
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Deliver received frames no sooner after the previous frame than they were recorded
+    ///
+    /// Reproduces bugs that depend on timing. Frames without a timestamp are
+    /// delivered at once.
+    pub fn with_timing(mut self, timing: bool) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// A transport for a client, which plays the recorded server
+    pub fn client_transport(&self) -> ReplayTransport {
+        ReplayTransport::new(self, Direction::ToClient)
+    }
+
+    /// A transport for a server, which plays the recorded client
+    pub fn server_transport(&self) -> ReplayTransport {
+        ReplayTransport::new(self, Direction::ToServer)
+    }
+
+    /// Serve the recorded requests with `server` and check its responses
+    pub async fn check_server(&self, mut server: Server<ReplayTransport>) -> Result<(), MCPError> {
+        let transport = self.server_transport();
+        let serve_transport = transport.clone();
+        let serve = tokio::spawn(async move { server.serve(serve_transport).await });
+        let result = transport.finish().await;
+        serve.abort();
+        result
+    }
+}
+
+/// A transport that plays one side of a [`Replay`]
+///
+/// Received frames are the recorded frames travelling towards the code under
+/// test, delivered once everything recorded before them has been sent. Sent
+/// frames are compared with the recording; differences are reported by
+/// [`ReplayTransport::finish`]. Clones share the position in the recording.
+#[derive(Clone)]
+pub struct ReplayTransport {
+    frames: Arc<Vec<Frame>>,
+    timeout: Duration,
+    timing: bool,
+    /// Which way received frames travel
+    incoming: Direction,
+    /// Index of the next frame to send or receive
+    cursor: Arc<watch::Sender<usize>>,
+    mismatches: Arc<Mutex<Vec<String>>>,
+    closed: Arc<watch::Sender<bool>>,
+}
+
+impl ReplayTransport {
+    fn new(replay: &Replay, incoming: Direction) -> Self {
+        Self {
+            frames: replay.frames.clone(),
+            timeout: replay.timeout,
+            timing: replay.timing,
+            incoming,
+            cursor: Arc::new(watch::channel(0).0),
+            mismatches: Arc::new(Mutex::new(Vec::new())),
+            closed: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    /// Wait until the whole recording has been played, then report any differences
+    ///
+    /// Fails with a protocol error listing every frame that did not match,
+    /// or with a timeout error if the code under test stopped short of the
+    /// end of the recording.
+    pub async fn finish(&self) -> Result<(), MCPError> {
+        let len = self.frames.len();
+        let mut cursor = self.cursor.subscribe();
+        let finished = tokio::time::timeout(self.timeout, cursor.wait_for(|&c| c >= len)).await;
+
+        let mismatches = self.mismatches.lock().unwrap().clone();
+        if !mismatches.is_empty() {
+            return Err(MCPError::Protocol(format!(
+                "Replay did not match the recording:\n{}",
+                mismatches.join("\n")
+            )));
+        }
+        if finished.is_err() {
+            return Err(MCPError::Timeout(format!(
+                "Replay stopped at frame {} of {}",
+                *self.cursor.borrow(),
+                len
+            )));
+        }
+        Ok(())
+    }
+
+    fn mismatch(&self, description: String) {
+        self.mismatches.lock().unwrap().push(description);
+    }
+}
+
+#[async_trait]
+impl Transport for ReplayTransport {
+    async fn start(&mut self) -> Result<(), MCPError> {
+        Ok(())
+    }
+
+    async fn send<M: Serialize + Send + Sync>(&mut self, message: &M) -> Result<(), MCPError> {
+        let message = serde_json::to_value(message)?;
+        let index = *self.cursor.borrow();
+        match self.frames.get(index) {
+            Some(frame) if frame.direction != self.incoming => {
+                if frame.message != message {
+                    self.mismatch(format!(
+                        "frame {}: expected {}, got {}",
+                        index, frame.message, message
+                    ));
+                }
+                self.cursor.send_modify(|c| *c += 1);
+            }
+            _ => self.mismatch(format!("frame {}: unexpected {}", index, message)),
+        }
+        Ok(())
+    }
+
+    async fn receive<M: DeserializeOwned + Send + Sync>(&mut self) -> Result<M, MCPError> {
+        let len = self.frames.len();
+        let incoming = self.incoming;
+        let frames = self.frames.clone();
+        let mut cursor = self.cursor.subscribe();
+
+        // Wait until everything recorded before the next incoming frame was sent
+        let ready = cursor.wait_for(|&c| c >= len || frames[c].direction == incoming);
+        let index = match tokio::time::timeout(self.timeout, ready).await {
+            Ok(Ok(index)) => *index,
+            Ok(Err(_)) => return Err(MCPError::ConnectionClosed),
+            Err(_) => {
+                let index = *self.cursor.borrow();
+                self.mismatch(format!(
+                    "frame {}: expected {}, nothing was sent",
+                    index, self.frames[index].message
+                ));
+                return Err(MCPError::Timeout("Expected frame was not sent".to_string()));
+            }
+        };
+
+        // At the end of the recording the other side stays silent until closed
+        if index >= len {
+            let _ = self.closed.subscribe().wait_for(|&closed| closed).await;
+            return Err(MCPError::ConnectionClosed);
+        }
+
+        if self.timing && index > 0 {
+            if let (Some(previous), Some(current)) = (
+                self.frames[index - 1].timestamp,
+                self.frames[index].timestamp,
+            ) {
+                let gap = Duration::from_millis(current.saturating_sub(previous));
+                tokio::time::sleep(gap).await;
+            }
+        }
+
+        self.cursor.send_modify(|c| *c += 1);
+        serde_json::from_value(self.frames[index].message.clone()).map_err(MCPError::Serialization)
+    }
+
+    async fn close(&mut self) -> Result<(), MCPError> {
+        self.closed.send_replace(true);
+        Ok(())
+    }
+
+    fn set_on_close(&mut self, _callback: Option<CloseCallback>) {}
+
+    fn set_on_error(&mut self, _callback: Option<ErrorCallback>) {}
+
+    fn set_on_message<F>(&mut self, _callback: Option<F>)
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+    }
+}
+
+/// Misbehavior a [`MockServer`] shows in answer to a request
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    /// Answer with a truncated message that is not valid JSON
+    ///
+    /// Clients skip it, so the request goes unanswered.
+    Malformed,
+    /// Answer as usual, but only after this long
+    Delay(Duration),
+    /// Answer with this JSON-RPC error
+    Error { code: i32, message: String },
+    /// Close the connection without answering
+    Disconnect,
+}
+
+/// What a [`MockServer`] serves
+#[derive(Default)]
+struct MockState {
+    tools: Vec<(Tool, CallToolResult)>,
+    resources: Vec<(Resource, ResourceContent)>,
+    prompts: Vec<(Prompt, GetPromptResult)>,
+    /// Results for methods, taking precedence over the built-in answers
+    responses: HashMap<String, Value>,
+    /// Faults for the next requests of each method, in order
+    faults: HashMap<String, VecDeque<Fault>>,
+    requests: Vec<JSONRPCRequest>,
+}
+
+/// A scripted server for testing clients
+///
+/// Answers `initialize`, `ping`, `shutdown`, and the list, call, read and
+/// get methods of the tools, resources and prompts it was given. Other
+/// methods get the result set with [`MockServer::with_response`], or a
+/// "method not found" error. The capabilities it advertises cover both.
+/// Clones share the script and the recorded requests.
+#[derive(Clone, Default)]
+pub struct MockServer {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockServer {
+    /// A server with nothing to offer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Offer a tool that returns `result` whenever it is called
+    pub fn with_tool(self, tool: Tool, result: CallToolResult) -> Self {
+        self.state.lock().unwrap().tools.push((tool, result));
+        self
+    }
+
+    /// Offer a resource that reads as `contents`
+    pub fn with_resource(self, resource: Resource, contents: ResourceContent) -> Self {
+        self.state
+            .lock()
+            .unwrap()
+            .resources
+            .push((resource, contents));
+        self
+    }
+
+    /// Offer a prompt that gets `result` whatever the arguments
+    pub fn with_prompt(self, prompt: Prompt, result: GetPromptResult) -> Self {
+        self.state.lock().unwrap().prompts.push((prompt, result));
+        self
+    }
+
+    /// Answer every request for `method` with `result`
+    pub fn with_response(self, method: &str, result: Value) -> Self {
+        self.state
+            .lock()
+            .unwrap()
+            .responses
+            .insert(method.to_string(), result);
+        self
+    }
+
+    /// Misbehave in answer to the next request for `method`
+    ///
+    /// Faults injected for the same method apply to its requests in turn.
+    pub fn inject(&self, method: &str, fault: Fault) {
+        self.state
+            .lock()
+            .unwrap()
+            .faults
+            .entry(method.to_string())
+            .or_default()
+            .push_back(fault);
+    }
+
+    /// The requests received so far, on every connection
+    pub fn requests(&self) -> Vec<JSONRPCRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// A new connection to the server, for a client
+    ///
+    /// Each call opens a separate connection, so a client that reconnects
+    /// after a [`Fault::Disconnect`] can be given a fresh one.
+    pub fn transport(&self) -> MockServerTransport {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        MockServerTransport {
+            server: self.clone(),
+            sender,
+            receiver: Arc::new(TokioMutex::new(receiver)),
+            closed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Record a request and take the fault to show for it, if any
+    fn receive(&self, request: &JSONRPCRequest) -> Option<Fault> {
+        let mut state = self.state.lock().unwrap();
+        state.requests.push(request.clone());
+        state
+            .faults
+            .get_mut(&request.method)
+            .and_then(VecDeque::pop_front)
+    }
+
+    /// The result of a request, or the code and message of its error
+    fn answer(&self, request: &JSONRPCRequest) -> Result<Value, (i32, String)> {
+        let state = self.state.lock().unwrap();
+        if let Some(result) = state.responses.get(&request.method) {
+            return Ok(result.clone());
+        }
+
+        let params = request.params.clone().unwrap_or(Value::Null);
+        let param = |name: &str| params[name].as_str().unwrap_or_default().to_string();
+        let not_found = |what: &str, name: String| {
+            Err((
+                error_codes::INVALID_PARAMS,
+                format!("Unknown {}: {}", what, name),
+            ))
+        };
+        fn to_value<T: Serialize>(value: &T) -> Value {
+            serde_json::to_value(value).unwrap_or_default()
+        }
+
+        match request.method.as_str() {
+            "initialize" => {
+                let requested = param("protocolVersion");
+                let version = if SUPPORTED_PROTOCOL_VERSIONS.contains(&requested.as_str()) {
+                    requested
+                } else {
+                    LATEST_PROTOCOL_VERSION.to_string()
+                };
+                let scripted = |prefix: &str| {
+                    state
+                        .responses
+                        .keys()
+                        .any(|method| method.starts_with(prefix))
+                };
+                let mut capabilities = serde_json::json!({});
+                for (name, offered) in [
+                    ("tools", !state.tools.is_empty() || scripted("tools/")),
+                    (
+                        "resources",
+                        !state.resources.is_empty() || scripted("resources/"),
+                    ),
+                    ("prompts", !state.prompts.is_empty() || scripted("prompts/")),
+                    ("logging", scripted("logging/")),
+                    ("completions", scripted("completion/")),
+                ] {
+                    if offered {
+                        capabilities[name] = serde_json::json!({});
+                    }
+                }
+                if scripted("resources/subscribe") {
+                    capabilities["resources"]["subscribe"] = Value::Bool(true);
+                }
+                Ok(serde_json::json!({
+                    "protocolVersion": version,
+                    "capabilities": capabilities,
+                    "serverInfo": { "name": "mock", "version": crate::VERSION }
+                }))
+            }
+            "ping" | "shutdown" => Ok(serde_json::json!({})),
+            "tools/list" => {
+                let tools: Vec<_> = state.tools.iter().map(|(tool, _)| to_value(tool)).collect();
+                Ok(serde_json::json!({ "tools": tools }))
+            }
+            "tools/call" => match state
+                .tools
+                .iter()
+                .find(|(tool, _)| tool.name == param("name"))
+            {
+                Some((_, result)) => Ok(to_value(result)),
+                None => not_found("tool", param("name")),
+            },
+            "resources/list" => {
+                let resources: Vec<_> = state
+                    .resources
+                    .iter()
+                    .map(|(resource, _)| to_value(resource))
+                    .collect();
+                Ok(serde_json::json!({ "resources": resources }))
+            }
+            "resources/read" => {
+                let uri = param("uri");
+                match state
+                    .resources
+                    .iter()
+                    .find(|(resource, _)| resource.uri == uri)
+                {
+                    Some((_, contents)) => {
+                        Ok(serde_json::json!({ "contents": [to_value(contents)] }))
+                    }
+                    None => not_found("resource", uri),
+                }
+            }
+            "prompts/list" => {
+                let prompts: Vec<_> = state
+                    .prompts
+                    .iter()
+                    .map(|(prompt, _)| to_value(prompt))
+                    .collect();
+                Ok(serde_json::json!({ "prompts": prompts }))
+            }
+            "prompts/get" => {
+                let name = param("name");
+                match state.prompts.iter().find(|(prompt, _)| prompt.name == name) {
+                    Some((_, result)) => Ok(to_value(result)),
+                    None => not_found("prompt", name),
+                }
+            }
+            method => Err((
+                error_codes::METHOD_NOT_FOUND,
+                format!("Method not found: {}", method),
+            )),
+        }
+    }
+}
+
+/// A client's connection to a [`MockServer`]
+///
+/// Requests are answered as they are sent. Clones share the connection.
+#[derive(Clone)]
+pub struct MockServerTransport {
+    server: MockServer,
+    /// Messages for the client; `None` once the server dropped the connection
+    sender: mpsc::UnboundedSender<Option<String>>,
+    receiver: Arc<TokioMutex<mpsc::UnboundedReceiver<Option<String>>>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl MockServerTransport {
+    /// Answer one message from the client
+    fn handle(&self, message: Value) {
+        // Notifications and responses to the server need no answer
+        let Ok(request) = serde_json::from_value::<JSONRPCRequest>(message) else {
+            return;
+        };
+
+        let fault = self.server.receive(&request);
+        if fault == Some(Fault::Disconnect) {
+            self.closed.store(true, Ordering::SeqCst);
+            let _ = self.sender.send(None);
+            return;
+        }
+
+        let result = match &fault {
+            Some(Fault::Error { code, message }) => Err((*code, message.clone())),
+            _ => self.server.answer(&request),
+        };
+        let answer = match result {
+            Ok(result) => serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": request.id,
+                "result": result
+            }),
+            Err((code, message)) => serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": request.id,
+                "error": { "code": code, "message": message }
+            }),
+        };
+        let mut answer = answer.to_string();
+        if fault == Some(Fault::Malformed) {
+            answer.truncate(answer.len() / 2);
+        }
+
+        match fault {
+            Some(Fault::Delay(delay)) => {
+                let sender = self.sender.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    let _ = sender.send(Some(answer));
+                });
+            }
+            _ => {
+                let _ = self.sender.send(Some(answer));
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for MockServerTransport {
+    async fn start(&mut self) -> Result<(), MCPError> {
+        Ok(())
+    }
+
+    async fn send<M: Serialize + Send + Sync>(&mut self, message: &M) -> Result<(), MCPError> {
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(MCPError::ConnectionClosed);
+        }
+        match serde_json::to_value(message)? {
+            Value::Array(batch) => batch.into_iter().for_each(|message| self.handle(message)),
+            message => self.handle(message),
+        }
+        Ok(())
+    }
+
+    async fn receive<M: DeserializeOwned + Send + Sync>(&mut self) -> Result<M, MCPError> {
+        let mut receiver = self.receiver.lock().await;
+        // Once closed, only what was already sent is delivered
+        let message = if self.closed.load(Ordering::SeqCst) {
+            receiver.try_recv().ok().flatten()
+        } else {
+            receiver.recv().await.flatten()
+        };
+        match message {
+            Some(message) => Ok(serde_json::from_str(&message)?),
+            None => Err(MCPError::ConnectionClosed),
+        }
+    }
+
+    async fn close(&mut self) -> Result<(), MCPError> {
+        self.closed.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn set_on_close(&mut self, _callback: Option<CloseCallback>) {}
+
+    fn set_on_error(&mut self, _callback: Option<ErrorCallback>) {}
+
+    fn set_on_message<F>(&mut self, _callback: Option<F>)
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+    }
+
+    fn share_connection(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        client::{Client, ClientDiagnostic},
+        schema::common::{Tool, ToolInputSchema},
+        server::ServerConfig,
+        transport::stdio::StdioTransport,
+    };
+
+    fn echo_server<T: Transport + Clone + 'static>() -> Server<T> {
+        let config = ServerConfig::new().with_tool(Tool {
+            name: "echo".to_string(),
+            description: None,
+            input_schema: ToolInputSchema {
+                r#type: "object".to_string(),
+                properties: None,
+                required: None,
+                keywords: serde_json::Map::new(),
+            },
+            output_schema: None,
+            annotations: None,
+        });
+        let mut server = Server::new(config);
+        server
+            .register_tool_handler("echo", |params: Value| async move { Ok(params) })
+            .unwrap();
+        server
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_mock_server() -> Result<(), MCPError> {
+        use crate::schema::common::TextContent;
+        use crate::schema::server::ToolResultContent;
+        use futures::StreamExt;
+
+        let tool = Tool {
+            name: "search".to_string(),
+            description: None,
+            input_schema: ToolInputSchema {
+                r#type: "object".to_string(),
+                properties: None,
+                required: None,
+                keywords: serde_json::Map::new(),
+            },
+            output_schema: None,
+            annotations: None,
+        };
+        let result = CallToolResult {
+            content: vec![ToolResultContent::Text(TextContent {
+                r#type: "text".to_string(),
+                text: "found".to_string(),
+                annotations: None,
+            })],
+            structured_content: None,
+            is_error: None,
+        };
+        let server = MockServer::new().with_tool(tool.clone(), result.clone());
+        let mut client = Client::new(server.transport()).with_timeout(Duration::from_secs(60));
+        let mut protocol_errors = Box::pin(client.protocol_errors());
+        let init = client.initialize().await?;
+        assert!(init.capabilities.tools.is_some());
+        assert_eq!(client.list_all_tools().await?, vec![tool]);
+        assert_eq!(client.call_tool_result("search", &()).await?, result);
+
+        // Faults apply to the next request of their method, in turn
+        server.inject("tools/call", Fault::Delay(Duration::from_secs(30)));
+        server.inject("tools/call", Fault::Malformed);
+        let start = tokio::time::Instant::now();
+        assert_eq!(client.call_tool_result("search", &()).await?, result);
+        assert!(start.elapsed() >= Duration::from_secs(30));
+        assert!(matches!(
+            client.call_tool_result("search", &()).await,
+            Err(MCPError::Timeout(_))
+        ));
+        let skipped = protocol_errors.next().await;
+        assert!(matches!(
+            skipped,
+            Some(ClientDiagnostic::InvalidMessage { .. })
+        ));
+
+        server.inject("ping", Fault::Disconnect);
+        assert!(client.ping().await.is_err());
+
+        let methods: Vec<_> = server.requests().into_iter().map(|r| r.method).collect();
+        assert_eq!(
+            methods,
+            [
+                "initialize",
+                "tools/list",
+                "tools/call",
+                "tools/call",
+                "tools/call",
+                "ping"
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() -> Result<(), MCPError> {
+        // Record a client talking to a real server over an in-memory connection
+        let (client_reader, server_writer) = tokio::io::duplex(4096);
+        let (server_reader, client_writer) = tokio::io::duplex(4096);
+        let server_transport = StdioTransport::with_reader_and_writer(
+            Box::new(server_reader),
+            Box::new(server_writer),
+        );
+        let client_transport = StdioTransport::with_reader_and_writer(
+            Box::new(client_reader),
+            Box::new(client_writer),
+        );
+
+        // The client keeps the original transport, which owns the reading end
+        let path =
+            std::env::temp_dir().join(format!("mcpr-recording-{}.jsonl", std::process::id()));
+        let recorder = Recorder::client(client_transport).with_file(&path)?;
+        let recording = recorder.clone();
+        let mut server = echo_server::<StdioTransport>();
+        tokio::spawn(async move { server.serve(server_transport).await });
+
+        let mut client = Client::new(recorder);
+        client.initialize().await?;
+        let _: Value = client
+            .call_tool("echo", &serde_json::json!({ "message": "hi" }))
+            .await?;
+        let jsonl = recording.to_jsonl()?;
+        assert_eq!(recording.frames().len(), 4);
+        assert!(recording
+            .frames()
+            .iter()
+            .all(|frame| frame.timestamp.is_some()));
+
+        // The file holds the same frames, written as they passed
+        let from_file = Replay::from_file(&path)?;
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(*from_file.frames, recording.frames());
+
+        // The same client code replays against the recorded server
+        let replay = Replay::from_jsonl(&jsonl)?;
+        let transport = replay.client_transport();
+        let mut client = Client::new(transport.clone());
+        client.initialize().await?;
+        let result: Value = client
+            .call_tool("echo", &serde_json::json!({ "message": "hi" }))
+            .await?;
+        assert!(result["content"][0]["text"]
+            .as_str()
+            .unwrap()
+            .contains("hi"));
+        transport.finish().await?;
+
+        // The server answers the recorded requests as before
+        replay.check_server(echo_server()).await?;
+
+        // A changed request is reported
+        let transport = replay.client_transport();
+        let mut client = Client::new(transport.clone());
+        client.initialize().await?;
+        let _: Value = client
+            .call_tool("echo", &serde_json::json!({ "message": "bye" }))
+            .await?;
+        let error = transport.finish().await.unwrap_err().to_string();
+        assert!(error.contains("frame 2"), "{}", error);
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+//! Spans and events for the `tracing` crate
+//!
+//! With the `tracing` feature, every request the client sends or the server
+//! handles runs in an `mcp.request` span with its side, method and id, and
+//! transports emit events when they connect and disconnect. Tool handlers
+//! run inside the span of their call, so their own spans and events nest
+//! under it. Without the feature, these are no-ops.
+
+use crate::schema::json_rpc::RequestId;
+use std::future::Future;
+
+/// Span of a request, entered while its future runs
+#[derive(Clone)]
+pub(crate) struct RequestSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    #[cfg(feature = "tracing")]
+    started: crate::clock::Instant,
+}
+
+impl RequestSpan {
+    /// Span of a request sent by the client or handled by the server
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn new(side: &'static str, method: &str, id: &RequestId) -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!("mcp.request", side, method, id = ?id),
+            #[cfg(feature = "tracing")]
+            started: crate::clock::Instant::now(),
+        }
+    }
+
+    /// Run `future` in the span, then record how long the request took
+    pub(crate) async fn instrument<F: Future>(&self, future: F) -> F::Output {
+        #[cfg(feature = "tracing")]
+        {
+            use tracing::Instrument;
+            let output = future.instrument(self.span.clone()).await;
+            let duration_ms = self.started.elapsed().as_millis() as u64;
+            self.span
+                .in_scope(|| tracing::debug!(duration_ms, "request completed"));
+            output
+        }
+        #[cfg(not(feature = "tracing"))]
+        future.await
+    }
+}
+
+/// Record that a transport connected
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn connected(transport: &'static str) {
+    #[cfg(feature = "tracing")]
+    tracing::info!(transport, "mcp transport connected");
+}
+
+/// Record that a transport disconnected
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn disconnected(transport: &'static str) {
+    #[cfg(feature = "tracing")]
+    tracing::info!(transport, "mcp transport disconnected");
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fmt::Debug;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    /// A subscriber writing down spans and events as lines of text
+    #[derive(Clone, Default)]
+    struct Capture {
+        lines: Arc<Mutex<Vec<String>>>,
+        spans: Arc<Mutex<HashMap<u64, &'static str>>>,
+        entered: Arc<Mutex<Vec<u64>>>,
+    }
+
+    /// Fields as ` name=value`, strings unquoted
+    #[derive(Default)]
+    struct Fields(String);
+
+    impl Visit for Fields {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.push_str(&format!(" {}={}", field.name(), value));
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0.push_str(&format!(" {}={:?}", field.name(), value));
+        }
+    }
+
+    impl Subscriber for Capture {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut spans = self.spans.lock().unwrap();
+            let id = spans.len() as u64 + 1;
+            spans.insert(id, span.metadata().name());
+            let mut fields = Fields::default();
+            span.record(&mut fields);
+            let line = format!("span {}{}", span.metadata().name(), fields.0);
+            self.lines.lock().unwrap().push(line);
+            Id::from_u64(id)
+        }
+
+        fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = Fields::default();
+            event.record(&mut fields);
+            let line = match self.entered.lock().unwrap().last() {
+                Some(id) => format!("event in {}:{}", self.spans.lock().unwrap()[id], fields.0),
+                None => format!("event:{}", fields.0),
+            };
+            self.lines.lock().unwrap().push(line);
+        }
+
+        fn enter(&self, span: &Id) {
+            self.entered.lock().unwrap().push(span.into_u64());
+        }
+
+        fn exit(&self, _span: &Id) {
+            self.entered.lock().unwrap().pop();
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_spans_and_events() {
+        let capture = Capture::default();
+        let _default = tracing::subscriber::set_default(capture.clone());
+
+        let span = RequestSpan::new("server", "tools/call", &RequestId::Number(7));
+        let output = span
+            .instrument(async {
+                tokio::time::sleep(Duration::from_millis(250)).await;
+                tracing::info!("handler ran");
+                42
+            })
+            .await;
+        assert_eq!(output, 42);
+        connected("stdio");
+        disconnected("stdio");
+
+        // The handler's event and the duration nest under the request span
+        assert_eq!(
+            *capture.lines.lock().unwrap(),
+            [
+                "span mcp.request side=server method=tools/call id=Number(7)",
+                "event in mcp.request: message=handler ran",
+                "event in mcp.request: message=request completed duration_ms=250",
+                "event: message=mcp transport connected transport=stdio",
+                "event: message=mcp transport disconnected transport=stdio",
+            ]
+        );
+    }
+}
@@ -0,0 +1,486 @@
+//! Transports for clients running in the browser, on `wasm32-unknown-unknown`
+//!
+//! [`FetchTransport`] posts each message with `fetch`, following the
+//! Streamable HTTP conventions: responses come back as JSON or as an event
+//! stream, and the session id the server returns in `Mcp-Session-Id` is sent
+//! with later requests. [`BrowserWebSocketTransport`] exchanges text frames
+//! over the browser's `WebSocket`.
+//!
+//! ```rust,ignore
+//! use mcpr::{client::Client, transport::browser::FetchTransport};
+//!
+//! wasm_bindgen_futures::spawn_local(async {
+//!     let mut client = Client::new(FetchTransport::new("https://example.com/mcp"));
+//!     client.initialize().await.unwrap();
+//! });
+//! ```
+//!
+//! Browser APIs are driven by the page's event loop, so requests and frames
+//! are handled in local tasks and their results queued for
+//! [`Transport::receive`]. Errors of a request sent with `fetch` are
+//! therefore reported by the next `receive`, not by `send`.
+
+use crate::error::MCPError;
+use crate::redact;
+use crate::trace;
+use crate::transport::{CloseCallback, ErrorCallback, MessageCallback, Transport};
+use async_trait::async_trait;
+use futures::channel::oneshot;
+use log::debug;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, Mutex as TokioMutex};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use web_sys::{CloseEvent, Event, MessageEvent, WebSocket};
+
+/// Header carrying the session id of a Streamable HTTP server
+const SESSION_ID_HEADER: &str = "Mcp-Session-Id";
+
+/// Messages, or the errors that stood in for them, waiting to be received
+type Incoming = mpsc::UnboundedSender<Result<Value, MCPError>>;
+
+/// A transport posting messages to an MCP endpoint with `fetch`
+///
+/// Clones share the session and the queue of received messages.
+pub struct FetchTransport {
+    url: String,
+    headers: Vec<(String, String)>,
+    session_id: Arc<Mutex<Option<String>>>,
+    sender: Incoming,
+    receiver: Arc<TokioMutex<mpsc::UnboundedReceiver<Result<Value, MCPError>>>>,
+    is_connected: bool,
+    on_close: Option<CloseCallback>,
+    on_error: Option<ErrorCallback>,
+    on_message: Option<MessageCallback>,
+}
+
+impl FetchTransport {
+    /// Create a transport for the endpoint at `url`
+    pub fn new(url: &str) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self {
+            url: url.to_string(),
+            headers: Vec::new(),
+            session_id: Arc::new(Mutex::new(None)),
+            sender,
+            receiver: Arc::new(TokioMutex::new(receiver)),
+            is_connected: false,
+            on_close: None,
+            on_error: None,
+            on_message: None,
+        }
+    }
+
+    /// Send a header with every request, such as `Authorization`
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// The session id the server assigned, once it did
+    pub fn session_id(&self) -> Option<String> {
+        self.session_id.lock().unwrap().clone()
+    }
+
+    /// Handle an error by calling the error callback if set
+    fn fail(&self, error: MCPError) -> MCPError {
+        if let Some(callback) = &self.on_error {
+            callback(&error);
+        }
+        error
+    }
+}
+
+impl Clone for FetchTransport {
+    fn clone(&self) -> Self {
+        Self {
+            url: self.url.clone(),
+            headers: self.headers.clone(),
+            session_id: self.session_id.clone(),
+            sender: self.sender.clone(),
+            receiver: self.receiver.clone(),
+            is_connected: self.is_connected,
+            on_close: None, // Callbacks cannot be cloned
+            on_error: None,
+            on_message: None,
+        }
+    }
+}
+
+/// Post `body` and queue the messages of the response
+async fn post(
+    url: String,
+    headers: Vec<(String, String)>,
+    session_id: Arc<Mutex<Option<String>>>,
+    body: String,
+    incoming: Incoming,
+) {
+    let mut request = reqwest::Client::new()
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .header("Accept", "application/json, text/event-stream")
+        .body(body);
+    for (name, value) in &headers {
+        request = request.header(name, value);
+    }
+    let session = session_id.lock().unwrap().clone();
+    if let Some(session) = session {
+        request = request.header(SESSION_ID_HEADER, session);
+    }
+
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(e) => {
+            let _ = incoming.send(Err(MCPError::Transport(format!("Request failed: {}", e))));
+            return;
+        }
+    };
+    if let Some(session) = response
+        .headers()
+        .get(SESSION_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+    {
+        *session_id.lock().unwrap() = Some(session.to_string());
+    }
+    let status = response.status();
+    if !status.is_success() {
+        let _ = incoming.send(Err(MCPError::Transport(format!(
+            "Server answered with status {}",
+            status
+        ))));
+        return;
+    }
+    let event_stream = response
+        .headers()
+        .get("Content-Type")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("text/event-stream"));
+    let text = match response.text().await {
+        Ok(text) => text,
+        Err(e) => {
+            let _ = incoming.send(Err(MCPError::Transport(format!(
+                "Failed to read the response: {}",
+                e
+            ))));
+            return;
+        }
+    };
+
+    // Notifications and responses are accepted with an empty body
+    let messages = if event_stream {
+        event_data(&text)
+    } else if text.trim().is_empty() {
+        Vec::new()
+    } else {
+        vec![text]
+    };
+    for message in messages {
+        let _ = incoming.send(serde_json::from_str(&message).map_err(MCPError::from));
+    }
+}
+
+/// The `data` of each event in an event stream
+fn event_data(text: &str) -> Vec<String> {
+    let mut events = Vec::new();
+    let mut data: Vec<&str> = Vec::new();
+    for line in text.lines().chain([""]) {
+        if line.is_empty() {
+            if !data.is_empty() {
+                events.push(data.join("\n"));
+                data.clear();
+            }
+        } else if let Some(value) = line.strip_prefix("data:") {
+            data.push(value.strip_prefix(' ').unwrap_or(value));
+        }
+    }
+    events
+}
+
+#[async_trait]
+impl Transport for FetchTransport {
+    async fn start(&mut self) -> Result<(), MCPError> {
+        self.is_connected = true;
+        trace::connected("fetch");
+        Ok(())
+    }
+
+    async fn send<T: Serialize + Send + Sync>(&mut self, message: &T) -> Result<(), MCPError> {
+        if !self.is_connected {
+            return Err(self.fail(MCPError::Transport("Transport not connected".to_string())));
+        }
+
+        let body = serde_json::to_string(message).map_err(|e| self.fail(e.into()))?;
+        debug!("Posting message: {}", redact::for_log(&body));
+        crate::rt::spawn(post(
+            self.url.clone(),
+            self.headers.clone(),
+            self.session_id.clone(),
+            body,
+            self.sender.clone(),
+        ));
+        Ok(())
+    }
+
+    async fn receive<T: DeserializeOwned + Send + Sync>(&mut self) -> Result<T, MCPError> {
+        if !self.is_connected {
+            return Err(self.fail(MCPError::Transport("Transport not connected".to_string())));
+        }
+
+        let message = self.receiver.lock().await.recv().await;
+        let message = message
+            .unwrap_or(Err(MCPError::ConnectionClosed))
+            .map_err(|e| self.fail(e))?;
+        if let Some(callback) = &self.on_message {
+            callback(&message.to_string());
+        }
+        serde_json::from_value(message).map_err(|e| self.fail(e.into()))
+    }
+
+    async fn close(&mut self) -> Result<(), MCPError> {
+        if !self.is_connected {
+            return Ok(());
+        }
+
+        self.is_connected = false;
+        trace::disconnected("fetch");
+        if let Some(callback) = &self.on_close {
+            callback();
+        }
+        Ok(())
+    }
+
+    fn set_on_close(&mut self, callback: Option<CloseCallback>) {
+        self.on_close = callback;
+    }
+
+    fn set_on_error(&mut self, callback: Option<ErrorCallback>) {
+        self.on_error = callback;
+    }
+
+    fn set_on_message<F>(&mut self, callback: Option<F>)
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.on_message = callback.map(|f| Box::new(f) as MessageCallback);
+    }
+}
+
+/// A browser `WebSocket` with the handlers feeding it into the queue
+struct Socket {
+    socket: WebSocket,
+    _on_open: Closure<dyn FnMut(Event)>,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    _on_error: Closure<dyn FnMut(Event)>,
+    _on_close: Closure<dyn FnMut(CloseEvent)>,
+}
+
+// SAFETY: wasm32-unknown-unknown runs on a single thread, so the socket and
+// its handlers are never used from another one
+unsafe impl Send for Socket {}
+unsafe impl Sync for Socket {}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        self.socket.set_onopen(None);
+        self.socket.set_onmessage(None);
+        self.socket.set_onerror(None);
+        self.socket.set_onclose(None);
+        let _ = self.socket.close();
+    }
+}
+
+/// Describe a JavaScript error
+fn js_error(context: &str, error: JsValue) -> MCPError {
+    MCPError::Transport(format!("{}: {:?}", context, error))
+}
+
+impl Socket {
+    /// Open a socket to `url`, queueing what it receives on `incoming`
+    ///
+    /// The receiver resolves once the socket is open, or failed to open.
+    fn open(
+        url: &str,
+        incoming: Incoming,
+    ) -> Result<(Self, oneshot::Receiver<Result<(), MCPError>>), MCPError> {
+        let socket =
+            WebSocket::new(url).map_err(|e| js_error("Failed to open the WebSocket", e))?;
+        let (opened, opening) = oneshot::channel();
+        let opened = Arc::new(Mutex::new(Some(opened)));
+
+        let on_open = {
+            let opened = opened.clone();
+            Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+                if let Some(opened) = opened.lock().unwrap().take() {
+                    let _ = opened.send(Ok(()));
+                }
+            })
+        };
+        let on_message = {
+            let incoming = incoming.clone();
+            Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+                let message = match event.data().as_string() {
+                    Some(text) => serde_json::from_str(&text).map_err(MCPError::from),
+                    None => Err(MCPError::Protocol(
+                        "Received a binary WebSocket frame".to_string(),
+                    )),
+                };
+                let _ = incoming.send(message);
+            })
+        };
+        let on_error = {
+            let opened = opened.clone();
+            Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+                let error = MCPError::Transport("WebSocket error".to_string());
+                if let Some(opened) = opened.lock().unwrap().take() {
+                    let _ = opened.send(Err(error));
+                }
+            })
+        };
+        let on_close = Closure::<dyn FnMut(CloseEvent)>::new(move |event: CloseEvent| {
+            debug!("WebSocket closed with code {}", event.code());
+            if let Some(opened) = opened.lock().unwrap().take() {
+                let _ = opened.send(Err(MCPError::ConnectionClosed));
+            }
+            let _ = incoming.send(Err(MCPError::ConnectionClosed));
+        });
+
+        socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        socket.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+        socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+        let socket = Self {
+            socket,
+            _on_open: on_open,
+            _on_message: on_message,
+            _on_error: on_error,
+            _on_close: on_close,
+        };
+        Ok((socket, opening))
+    }
+}
+
+/// A transport over the browser's `WebSocket`, sending text frames
+///
+/// Clones share the socket.
+pub struct BrowserWebSocketTransport {
+    url: String,
+    socket: Option<Arc<Socket>>,
+    receiver: Option<Arc<TokioMutex<mpsc::UnboundedReceiver<Result<Value, MCPError>>>>>,
+    on_close: Option<CloseCallback>,
+    on_error: Option<ErrorCallback>,
+    on_message: Option<MessageCallback>,
+}
+
+impl BrowserWebSocketTransport {
+    /// Create a transport connecting to `url`, a `ws://` or `wss://` URL
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            socket: None,
+            receiver: None,
+            on_close: None,
+            on_error: None,
+            on_message: None,
+        }
+    }
+
+    /// Handle an error by calling the error callback if set
+    fn fail(&self, error: MCPError) -> MCPError {
+        if let Some(callback) = &self.on_error {
+            callback(&error);
+        }
+        error
+    }
+}
+
+impl Clone for BrowserWebSocketTransport {
+    fn clone(&self) -> Self {
+        Self {
+            url: self.url.clone(),
+            socket: self.socket.clone(),
+            receiver: self.receiver.clone(),
+            on_close: None, // Callbacks cannot be cloned
+            on_error: None,
+            on_message: None,
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for BrowserWebSocketTransport {
+    async fn start(&mut self) -> Result<(), MCPError> {
+        if self.socket.is_some() {
+            return Ok(());
+        }
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let (socket, opening) = Socket::open(&self.url, sender).map_err(|e| self.fail(e))?;
+        let socket = Arc::new(socket);
+        opening
+            .await
+            .unwrap_or(Err(MCPError::ConnectionClosed))
+            .map_err(|e| self.fail(e))?;
+
+        self.socket = Some(socket);
+        self.receiver = Some(Arc::new(TokioMutex::new(receiver)));
+        trace::connected("browser-websocket");
+        Ok(())
+    }
+
+    async fn send<T: Serialize + Send + Sync>(&mut self, message: &T) -> Result<(), MCPError> {
+        let Some(socket) = &self.socket else {
+            return Err(self.fail(MCPError::Transport("Transport not connected".to_string())));
+        };
+
+        let text = serde_json::to_string(message).map_err(|e| self.fail(e.into()))?;
+        debug!("Sending WebSocket message: {}", redact::for_log(&text));
+        socket
+            .socket
+            .send_with_str(&text)
+            .map_err(|e| self.fail(js_error("Failed to send", e)))
+    }
+
+    async fn receive<T: DeserializeOwned + Send + Sync>(&mut self) -> Result<T, MCPError> {
+        let Some(receiver) = self.receiver.clone() else {
+            return Err(self.fail(MCPError::Transport("Transport not connected".to_string())));
+        };
+
+        let message = receiver.lock().await.recv().await;
+        let message = message
+            .unwrap_or(Err(MCPError::ConnectionClosed))
+            .map_err(|e| self.fail(e))?;
+        if let Some(callback) = &self.on_message {
+            callback(&message.to_string());
+        }
+        serde_json::from_value(message).map_err(|e| self.fail(e.into()))
+    }
+
+    async fn close(&mut self) -> Result<(), MCPError> {
+        // The socket closes once every clone dropped it
+        if self.socket.take().is_none() {
+            return Ok(());
+        }
+
+        trace::disconnected("browser-websocket");
+        if let Some(callback) = &self.on_close {
+            callback();
+        }
+        Ok(())
+    }
+
+    fn set_on_close(&mut self, callback: Option<CloseCallback>) {
+        self.on_close = callback;
+    }
+
+    fn set_on_error(&mut self, callback: Option<ErrorCallback>) {
+        self.on_error = callback;
+    }
+
+    fn set_on_message<F>(&mut self, callback: Option<F>)
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.on_message = callback.map(|f| Box::new(f) as MessageCallback);
+    }
+}
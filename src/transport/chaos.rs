@@ -0,0 +1,343 @@
+//! Fault-injecting transport wrapper for resilience testing
+//!
+//! [`ChaosTransport`] wraps another transport and, according to a
+//! [`ChaosConfig`], delays messages, drops them, delivers received messages
+//! out of order, or fails operations with transport errors. It implements
+//! [`Transport`], so it can stand in for the real transport in tests of retry,
+//! reconnect and timeout handling.
+//!
+//! ```rust,no_run
+//! use mcpr::{
+//!     client::Client,
+//!     transport::{
+//!         chaos::{ChaosConfig, ChaosTransport},
+//!         stdio::StdioTransport,
+//!     },
+//! };
+//! use std::time::Duration;
+//!
+//! let config = ChaosConfig::new()
+//!     .with_latency(Duration::from_millis(10), Duration::from_millis(200))
+//!     .with_drop_rate(0.05)
+//!     .with_seed(42);
+//! let client = Client::new(ChaosTransport::new(StdioTransport::new(), config));
+//! ```
+
+use crate::error::MCPError;
+use crate::transport::{CloseCallback, ErrorCallback, Traffic, Transport};
+use async_trait::async_trait;
+use log::debug;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{
+    process::ExitStatus,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Which faults a [`ChaosTransport`] injects, and how often
+///
+/// Rates are probabilities between 0.0 (never) and 1.0 (always), and apply
+/// to every message independently. Everything is off by default.
+#[derive(Debug, Clone, Default)]
+pub struct ChaosConfig {
+    /// Range of delay added before every send and receive
+    pub latency: Option<(Duration, Duration)>,
+    /// Share of messages silently dropped, in either direction
+    pub drop_rate: f64,
+    /// Share of received messages held back and delivered after the next one
+    pub reorder_rate: f64,
+    /// Share of sends and receives that fail with a transport error
+    pub error_rate: f64,
+    /// Seed for the random choices, for reproducible runs
+    pub seed: Option<u64>,
+}
+
+impl ChaosConfig {
+    /// Create a configuration that injects no faults
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Delay every send and receive by a random duration between `min` and `max`
+    pub fn with_latency(mut self, min: Duration, max: Duration) -> Self {
+        self.latency = Some((min, max.max(min)));
+        self
+    }
+
+    /// Drop this share of messages
+    pub fn with_drop_rate(mut self, rate: f64) -> Self {
+        self.drop_rate = rate;
+        self
+    }
+
+    /// Deliver this share of received messages after the message that follows them
+    pub fn with_reorder_rate(mut self, rate: f64) -> Self {
+        self.reorder_rate = rate;
+        self
+    }
+
+    /// Fail this share of sends and receives with a transport error
+    pub fn with_error_rate(mut self, rate: f64) -> Self {
+        self.error_rate = rate;
+        self
+    }
+
+    /// Make the injected faults reproducible
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+}
+
+/// A transport that injects faults into another transport
+///
+/// Clones share the random number generator and the held-back message, like
+/// the connection of the inner transport.
+pub struct ChaosTransport<T: Transport> {
+    inner: T,
+    config: ChaosConfig,
+    rng: Arc<Mutex<StdRng>>,
+    /// A received message held back to be delivered out of order
+    held: Arc<Mutex<Option<Value>>>,
+}
+
+impl<T: Transport> ChaosTransport<T> {
+    /// Wrap a transport, injecting the faults described by `config`
+    pub fn new(inner: T, config: ChaosConfig) -> Self {
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        Self {
+            inner,
+            config,
+            rng: Arc::new(Mutex::new(rng)),
+            held: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// The wrapped transport
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Decide whether a fault with the given rate happens this time
+    fn roll(&self, rate: f64) -> bool {
+        rate > 0.0 && self.rng.lock().unwrap().gen_bool(rate.min(1.0))
+    }
+
+    /// Wait for the configured latency, if any
+    async fn delay(&self) {
+        if let Some((min, max)) = self.config.latency {
+            let delay = self.rng.lock().unwrap().gen_range(min..=max);
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Fail with a transport error at the configured rate
+    fn maybe_fail(&self, operation: &str) -> Result<(), MCPError> {
+        if self.roll(self.config.error_rate) {
+            debug!("Chaos: injecting a {} error", operation);
+            return Err(MCPError::Transport(format!(
+                "Injected {} failure",
+                operation
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl<T: Transport + Clone> Clone for ChaosTransport<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            config: self.config.clone(),
+            rng: self.rng.clone(),
+            held: self.held.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl<T: Transport> Transport for ChaosTransport<T> {
+    async fn start(&mut self) -> Result<(), MCPError> {
+        self.inner.start().await
+    }
+
+    async fn send<M: Serialize + Send + Sync>(&mut self, message: &M) -> Result<(), MCPError> {
+        self.delay().await;
+        self.maybe_fail("send")?;
+
+        if self.roll(self.config.drop_rate) {
+            debug!("Chaos: dropping an outgoing message");
+            return Ok(());
+        }
+        self.inner.send(message).await
+    }
+
+    async fn receive<M: DeserializeOwned + Send + Sync>(&mut self) -> Result<M, MCPError> {
+        self.delay().await;
+        self.maybe_fail("receive")?;
+
+        loop {
+            // A held-back message is delivered once another has overtaken it
+            let held = self.held.lock().unwrap().take();
+            let message = match held {
+                Some(message) => message,
+                None => {
+                    let message: Value = self.inner.receive().await?;
+                    if self.roll(self.config.drop_rate) {
+                        debug!("Chaos: dropping an incoming message");
+                        continue;
+                    }
+                    if self.roll(self.config.reorder_rate) {
+                        debug!("Chaos: holding back an incoming message");
+                        *self.held.lock().unwrap() = Some(message);
+                        self.inner.receive::<Value>().await?
+                    } else {
+                        message
+                    }
+                }
+            };
+
+            return serde_json::from_value(message).map_err(MCPError::Serialization);
+        }
+    }
+
+    async fn close(&mut self) -> Result<(), MCPError> {
+        self.inner.close().await
+    }
+
+    fn set_on_close(&mut self, callback: Option<CloseCallback>) {
+        self.inner.set_on_close(callback);
+    }
+
+    fn set_on_error(&mut self, callback: Option<ErrorCallback>) {
+        self.inner.set_on_error(callback);
+    }
+
+    fn set_on_message<F>(&mut self, callback: Option<F>)
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.inner.set_on_message(callback);
+    }
+
+    async fn exit_status(&self) -> Option<ExitStatus> {
+        self.inner.exit_status().await
+    }
+
+    fn share_connection(&self) -> Option<Self> {
+        Some(Self {
+            inner: self.inner.share_connection()?,
+            config: self.config.clone(),
+            rng: self.rng.clone(),
+            held: self.held.clone(),
+        })
+    }
+
+    fn traffic(&self) -> Option<Traffic> {
+        self.inner.traffic()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    // Loopback transport: whatever is sent can be received
+    #[derive(Clone, Default)]
+    struct Loopback {
+        queue: Arc<Mutex<VecDeque<String>>>,
+    }
+
+    #[async_trait]
+    impl Transport for Loopback {
+        async fn start(&mut self) -> Result<(), MCPError> {
+            Ok(())
+        }
+
+        async fn send<M: Serialize + Send + Sync>(&mut self, message: &M) -> Result<(), MCPError> {
+            let json = serde_json::to_string(message)?;
+            self.queue.lock().unwrap().push_back(json);
+            Ok(())
+        }
+
+        async fn receive<M: DeserializeOwned + Send + Sync>(&mut self) -> Result<M, MCPError> {
+            let json = self.queue.lock().unwrap().pop_front();
+            let json = json.ok_or_else(|| MCPError::Transport("Empty".to_string()))?;
+            Ok(serde_json::from_str(&json)?)
+        }
+
+        async fn close(&mut self) -> Result<(), MCPError> {
+            Ok(())
+        }
+
+        fn set_on_close(&mut self, _callback: Option<CloseCallback>) {}
+
+        fn set_on_error(&mut self, _callback: Option<ErrorCallback>) {}
+
+        fn set_on_message<F>(&mut self, _callback: Option<F>)
+        where
+            F: Fn(&str) + Send + Sync + 'static,
+        {
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_faults_by_default() {
+        let mut transport = ChaosTransport::new(Loopback::default(), ChaosConfig::new());
+        for i in 0..10 {
+            transport.send(&i).await.unwrap();
+        }
+        for i in 0..10 {
+            assert_eq!(transport.receive::<i32>().await.unwrap(), i);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reorder_and_errors() {
+        // Every received message is overtaken by the next one
+        let config = ChaosConfig::new().with_reorder_rate(1.0);
+        let mut transport = ChaosTransport::new(Loopback::default(), config);
+        for i in 0..4 {
+            transport.send(&i).await.unwrap();
+        }
+        let mut received = Vec::new();
+        for _ in 0..4 {
+            received.push(transport.receive::<i32>().await.unwrap());
+        }
+        assert_eq!(received, vec![1, 0, 3, 2]);
+
+        let config = ChaosConfig::new().with_error_rate(1.0);
+        let mut transport = ChaosTransport::new(Loopback::default(), config);
+        assert!(matches!(
+            transport.send(&1).await,
+            Err(MCPError::Transport(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_drop_rate_is_reproducible() {
+        async fn survivors(seed: u64) -> Vec<i32> {
+            let config = ChaosConfig::new().with_drop_rate(0.5).with_seed(seed);
+            let mut transport = ChaosTransport::new(Loopback::default(), config);
+            for i in 0..50 {
+                transport.send(&i).await.unwrap();
+            }
+            let mut received = Vec::new();
+            while let Ok(i) = transport.inner.receive::<i32>().await {
+                received.push(i);
+            }
+            received
+        }
+
+        let first = survivors(7).await;
+        assert!(!first.is_empty() && first.len() < 50);
+        assert_eq!(first, survivors(7).await);
+    }
+}
@@ -0,0 +1,88 @@
+//! Wire encodings for binary-capable transports
+//!
+//! Messages are JSON by default. With the `msgpack` feature,
+//! [`Codec::MessagePack`] encodes the same message types as MessagePack,
+//! which is smaller and faster to parse; both sides of the connection must
+//! use the same codec.
+
+use crate::error::MCPError;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// How messages are encoded on the wire
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Codec {
+    /// JSON text, as required by the MCP specification
+    #[default]
+    Json,
+    /// MessagePack, with struct fields encoded by name
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+}
+
+impl Codec {
+    /// Whether encoded messages are text rather than binary
+    pub fn is_text(&self) -> bool {
+        matches!(self, Codec::Json)
+    }
+
+    /// Encode a message
+    pub fn encode<T: Serialize>(&self, message: &T) -> Result<Vec<u8>, MCPError> {
+        match self {
+            Codec::Json => Ok(serde_json::to_vec(message)?),
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack => rmp_serde::to_vec_named(message).map_err(|e| {
+                MCPError::Protocol(format!("Failed to encode MessagePack message: {}", e))
+            }),
+        }
+    }
+
+    /// Decode a message
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, MCPError> {
+        match self {
+            Codec::Json => Ok(serde_json::from_slice(bytes)?),
+            #[cfg(feature = "msgpack")]
+            Codec::MessagePack => rmp_serde::from_slice(bytes).map_err(|e| {
+                MCPError::Protocol(format!("Failed to decode MessagePack message: {}", e))
+            }),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "msgpack"))]
+mod tests {
+    use super::*;
+    use crate::schema::{
+        json_rpc::{JSONRPCMessage, JSONRPCPayload, JSONRPCRequest, RequestId},
+        server::{CallToolResult, ToolResultContent},
+        TextContent,
+    };
+
+    #[test]
+    fn test_message_pack_round_trip() {
+        let result = CallToolResult {
+            content: vec![ToolResultContent::Text(TextContent {
+                r#type: "text".to_string(),
+                text: "hello".to_string(),
+                annotations: None,
+            })],
+            structured_content: Some(serde_json::json!({ "count": 3, "ok": true })),
+            is_error: None,
+        };
+        let request = JSONRPCPayload::Batch(vec![JSONRPCMessage::Request(JSONRPCRequest::new(
+            RequestId::Number(1),
+            "tools/call".to_string(),
+            Some(serde_json::to_value(&result).unwrap()),
+        ))]);
+
+        let json = Codec::Json.encode(&request).unwrap();
+        let packed = Codec::MessagePack.encode(&request).unwrap();
+        assert!(packed.len() < json.len());
+
+        let decoded: JSONRPCPayload = Codec::MessagePack.decode(&packed).unwrap();
+        assert_eq!(decoded, request);
+        assert!(matches!(
+            Codec::MessagePack.decode::<JSONRPCPayload>(&json),
+            Err(MCPError::Protocol(_))
+        ));
+    }
+}
@@ -0,0 +1,237 @@
+//! Compression of messages for the network transports, with gzip or zstd
+//!
+//! Large resource payloads dominate the traffic of remote connections. A
+//! [`Compression`] given to a transport's `with_compression` compresses
+//! every message at least as large as its threshold:
+//!
+//! ```rust,ignore
+//! let compression = Compression::zstd().with_threshold(4096);
+//! let transport = StreamableHttpTransport::new("https://example.com/mcp")
+//!     .with_compression(compression);
+//! ```
+//!
+//! The Streamable HTTP transport sends compressed request bodies with a
+//! `Content-Encoding` header, and stops compressing them once the server
+//! answers `415 Unsupported Media Type`. It asks for compressed responses
+//! with `Accept-Encoding` and decompresses them as they arrive, with or
+//! without a `Compression`.
+//!
+//! The WebSocket library has no `permessage-deflate`, so the WebSocket
+//! transport compresses whole messages into binary frames instead. Each side
+//! lists the encodings it decompresses in an [`ACCEPT_ENCODING_HEADER`]
+//! during the handshake, and messages are only compressed with an encoding
+//! the other side listed. Binary frames starting with the gzip or zstd magic
+//! bytes are decompressed on receive, so only the sending side has to turn
+//! compression on.
+
+use crate::error::MCPError;
+use std::io::{Read, Write};
+
+/// Messages smaller than this are sent as they are, unless configured otherwise, 1 KiB
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 1024;
+
+/// The WebSocket handshake header listing the encodings a side decompresses
+pub const ACCEPT_ENCODING_HEADER: &str = "Mcp-Accept-Encoding";
+
+/// The encodings decompressed here, as the value of [`ACCEPT_ENCODING_HEADER`]
+pub(crate) const ACCEPTED_ENCODINGS: &str = "gzip, zstd";
+
+/// A compression algorithm for messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// gzip, which every HTTP server understands
+    Gzip,
+    /// zstd, faster and smaller than gzip
+    Zstd,
+}
+
+impl Encoding {
+    /// The name of the encoding in `Content-Encoding` headers
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Zstd => "zstd",
+        }
+    }
+
+    /// The encoding a `Content-Encoding` header names, if it is one of these
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("gzip") || name.eq_ignore_ascii_case("x-gzip") {
+            Some(Encoding::Gzip)
+        } else if name.eq_ignore_ascii_case("zstd") {
+            Some(Encoding::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// Whether a list of encodings, such as an `Accept-Encoding` header, names this one
+    pub(crate) fn listed_in(&self, names: &str) -> bool {
+        names
+            .split(',')
+            .filter_map(|name| Self::from_name(name.split(';').next().unwrap_or_default()))
+            .any(|encoding| encoding == *self)
+    }
+
+    /// The encoding of compressed bytes, from the magic bytes they start with
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(Encoding::Gzip)
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Encoding::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// Compress `bytes` at the default level
+    pub fn compress(&self, bytes: &[u8]) -> Result<Vec<u8>, MCPError> {
+        let compressed = match self {
+            Encoding::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(bytes).and_then(|_| encoder.finish())
+            }
+            Encoding::Zstd => zstd::encode_all(bytes, 0),
+        };
+        compressed.map_err(|e| {
+            MCPError::Transport(format!(
+                "Failed to compress message with {}: {}",
+                self.name(),
+                e
+            ))
+        })
+    }
+
+    /// Decompress `bytes`
+    ///
+    /// Fails with [`MCPError::MessageTooLarge`] once the output exceeds
+    /// `max_size`, without decompressing the rest, so a small message cannot
+    /// expand into more memory than the transport would buffer otherwise.
+    pub fn decompress(&self, bytes: &[u8], max_size: usize) -> Result<Vec<u8>, MCPError> {
+        let failed = |e: std::io::Error| {
+            MCPError::Transport(format!(
+                "Failed to decompress {} message: {}",
+                self.name(),
+                e
+            ))
+        };
+        let decoder: Box<dyn Read + '_> = match self {
+            Encoding::Gzip => Box::new(flate2::read::GzDecoder::new(bytes)),
+            Encoding::Zstd => Box::new(zstd::stream::read::Decoder::new(bytes).map_err(failed)?),
+        };
+        let mut out = Vec::new();
+        decoder
+            .take(max_size as u64 + 1)
+            .read_to_end(&mut out)
+            .map_err(failed)?;
+        if out.len() > max_size {
+            return Err(MCPError::MessageTooLarge { limit: max_size });
+        }
+        Ok(out)
+    }
+}
+
+/// Which encoding to compress messages with, and from what size on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    encoding: Encoding,
+    threshold: usize,
+}
+
+impl Compression {
+    /// Compress messages of [`DEFAULT_COMPRESSION_THRESHOLD`] bytes or more with `encoding`
+    pub fn new(encoding: Encoding) -> Self {
+        Self {
+            encoding,
+            threshold: DEFAULT_COMPRESSION_THRESHOLD,
+        }
+    }
+
+    /// Compress with gzip
+    pub fn gzip() -> Self {
+        Self::new(Encoding::Gzip)
+    }
+
+    /// Compress with zstd
+    pub fn zstd() -> Self {
+        Self::new(Encoding::Zstd)
+    }
+
+    /// Send messages smaller than `threshold` bytes as they are
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// The encoding messages are compressed with
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// The size from which messages are compressed
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Compress `bytes` if they reach the threshold and shrink by it
+    pub(crate) fn apply(&self, bytes: &[u8]) -> Result<Option<Vec<u8>>, MCPError> {
+        if bytes.len() < self.threshold {
+            return Ok(None);
+        }
+        let compressed = self.encoding.compress(bytes)?;
+        Ok((compressed.len() < bytes.len()).then_some(compressed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compression() {
+        let message = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": { "contents": [{ "uri": "file:///log", "text": "line\n".repeat(2000) }] },
+        })
+        .to_string();
+        for compression in [Compression::gzip(), Compression::zstd()] {
+            let encoding = compression.encoding();
+            let compressed = compression.apply(message.as_bytes()).unwrap().unwrap();
+            assert!(compressed.len() < message.len() / 10);
+            assert_eq!(Encoding::detect(&compressed), Some(encoding));
+            assert_eq!(Encoding::from_name(encoding.name()), Some(encoding));
+            assert_eq!(
+                encoding.decompress(&compressed, message.len()).unwrap(),
+                message.as_bytes()
+            );
+
+            // Expanding past the limit stops there
+            assert!(matches!(
+                encoding.decompress(&compressed, 1024),
+                Err(MCPError::MessageTooLarge { limit: 1024 })
+            ));
+            assert!(matches!(
+                encoding.decompress(&compressed[..compressed.len() / 2], message.len()),
+                Err(MCPError::Transport(_))
+            ));
+        }
+
+        // Small messages, and messages that do not shrink, are left alone
+        let small = br#"{"jsonrpc":"2.0","id":1,"result":{}}"#;
+        assert_eq!(Compression::gzip().apply(small).unwrap(), None);
+        assert_eq!(
+            Compression::zstd().with_threshold(0).apply(b"{}").unwrap(),
+            None
+        );
+        assert_eq!(Encoding::detect(small), None);
+        assert_eq!(Encoding::from_name("br"), None);
+
+        assert!(Encoding::Zstd.listed_in(ACCEPTED_ENCODINGS));
+        assert!(Encoding::Gzip.listed_in("br, gzip;q=0.5"));
+        assert!(!Encoding::Gzip.listed_in("zstd"));
+        assert!(!Encoding::Gzip.listed_in(""));
+    }
+}
@@ -0,0 +1,919 @@
+//! Transports over byte streams, split into messages by a framer
+//!
+//! A [`FramedTransport`] turns any reader and writer pair into a transport:
+//! a [`Framer`] decides where each message starts and ends, so a new
+//! transport only has to provide the byte I/O and pick one of
+//!
+//! - [`NewlineDelimited`]: one JSON message per line, as over stdio
+//! - [`ContentLength`]: a `Content-Length` header before each message, as
+//!   in the Language Server Protocol
+//! - [`LengthPrefixed`]: the length of each message as 4 big-endian bytes
+//! - [`SseEvents`]: one server-sent event per message, carried in `data:` lines
+//!
+//! With the `simd-json` feature, frames are parsed with simd-json instead of
+//! serde_json; see [`FrameParser`]. Whether that is faster depends on the
+//! messages, so measure with `cargo bench --bench parse --features simd-json`.
+//!
+//! ```rust,no_run
+//! # use mcpr::{client::Client, transport::framed::{ContentLength, FramedTransport}};
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let stream = tokio::net::TcpStream::connect("127.0.0.1:9000").await?;
+//! let (reader, writer) = stream.into_split();
+//! let transport = FramedTransport::new(reader, writer, ContentLength);
+//! let mut client = Client::new(transport);
+//! client.initialize().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::error::MCPError;
+use crate::trace;
+use crate::transport::{
+    CloseCallback, ErrorCallback, MessageCallback, Traffic, TrafficCounter, Transport,
+    DEFAULT_MAX_MESSAGE_SIZE,
+};
+use async_trait::async_trait;
+use bytes::{BufMut, BytesMut};
+use log::debug;
+use serde::{de::DeserializeOwned, Serialize};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::Mutex as TokioMutex;
+
+/// Bytes read from the transport at a time
+const READ_CHUNK: usize = 8192;
+
+/// Largest write buffer kept for the next message; a larger one is freed
+const RETAINED_WRITE_BUFFER: usize = 64 * 1024;
+
+/// Largest parse buffers kept for the next frame; larger ones are freed
+#[cfg(feature = "simd-json")]
+const RETAINED_PARSE_BUFFER: usize = 1024 * 1024;
+
+/// Splits a byte stream into messages, and writes messages into one
+pub trait Framer: Clone + Send + Sync + 'static {
+    /// Append `message` to `out` as one frame
+    fn encode(&self, message: &str, out: &mut Vec<u8>);
+
+    /// Serialize `message` and append it to `out` as one frame
+    ///
+    /// Returns the length of the JSON. The default serializes to a string
+    /// and calls [`encode`](Self::encode); framers that can take the JSON
+    /// as it is written skip that copy.
+    fn encode_json<T: Serialize + ?Sized>(
+        &self,
+        message: &T,
+        out: &mut BytesMut,
+    ) -> Result<usize, MCPError> {
+        let json = serde_json::to_string(message)?;
+        let mut frame = Vec::with_capacity(json.len() + 32);
+        self.encode(&json, &mut frame);
+        out.extend_from_slice(&frame);
+        Ok(json.len())
+    }
+
+    /// Take the first complete frame off the front of `buffer`
+    ///
+    /// Returns `None`, leaving `buffer` as it is, until the frame is complete.
+    /// The first `scanned` bytes were already searched by the call that
+    /// returned `None` before more were read, so framers looking for a
+    /// delimiter only search the rest, and large frames arriving in many
+    /// reads are not searched from the start every time.
+    /// Framers that learn a frame's length up front fail with
+    /// [`MCPError::MessageTooLarge`] once it exceeds `max_size`, without
+    /// waiting for the rest of the frame.
+    fn decode(
+        &self,
+        buffer: &mut Vec<u8>,
+        scanned: usize,
+        max_size: usize,
+    ) -> Result<Option<String>, MCPError>;
+}
+
+/// One message per line; blank lines are skipped
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NewlineDelimited;
+
+impl Framer for NewlineDelimited {
+    fn encode(&self, message: &str, out: &mut Vec<u8>) {
+        out.extend_from_slice(message.as_bytes());
+        out.push(b'\n');
+    }
+
+    fn encode_json<T: Serialize + ?Sized>(
+        &self,
+        message: &T,
+        out: &mut BytesMut,
+    ) -> Result<usize, MCPError> {
+        let length = write_json(message, out)?;
+        out.put_u8(b'\n');
+        Ok(length)
+    }
+
+    fn decode(
+        &self,
+        buffer: &mut Vec<u8>,
+        scanned: usize,
+        _max_size: usize,
+    ) -> Result<Option<String>, MCPError> {
+        let mut from = scanned.min(buffer.len());
+        while let Some(end) = buffer[from..].iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=from + end).collect();
+            from = 0;
+            let line = frame_text(line)?;
+            let line = line.trim_end_matches(['\n', '\r']);
+            if !line.trim().is_empty() {
+                return Ok(Some(line.to_string()));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// A `Content-Length` header, a blank line, then that many bytes of message
+///
+/// Other headers, such as `Content-Type`, are ignored.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ContentLength;
+
+impl Framer for ContentLength {
+    fn encode(&self, message: &str, out: &mut Vec<u8>) {
+        out.extend_from_slice(format!("Content-Length: {}\r\n\r\n", message.len()).as_bytes());
+        out.extend_from_slice(message.as_bytes());
+    }
+
+    fn encode_json<T: Serialize + ?Sized>(
+        &self,
+        message: &T,
+        out: &mut BytesMut,
+    ) -> Result<usize, MCPError> {
+        // The header needs the length, so the JSON is moved up after it
+        let start = out.len();
+        let length = write_json(message, out)?;
+        let header = format!("Content-Length: {}\r\n\r\n", length);
+        out.put_bytes(0, header.len());
+        out.copy_within(start..start + length, start + header.len());
+        out[start..start + header.len()].copy_from_slice(header.as_bytes());
+        Ok(length)
+    }
+
+    fn decode(
+        &self,
+        buffer: &mut Vec<u8>,
+        _scanned: usize,
+        max_size: usize,
+    ) -> Result<Option<String>, MCPError> {
+        let Some(header_end) = buffer.windows(4).position(|w| w == b"\r\n\r\n") else {
+            return Ok(None);
+        };
+        let headers = String::from_utf8_lossy(&buffer[..header_end]);
+        let length = headers
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+            .ok_or_else(|| MCPError::Transport("Frame without a Content-Length".to_string()))?
+            .1
+            .trim()
+            .parse::<usize>()
+            .map_err(|e| MCPError::Transport(format!("Invalid Content-Length: {}", e)))?;
+        if length > max_size {
+            return Err(MCPError::MessageTooLarge { limit: max_size });
+        }
+
+        let start = header_end + 4;
+        let end = start
+            .checked_add(length)
+            .ok_or_else(|| MCPError::Transport(format!("Invalid Content-Length: {}", length)))?;
+        if buffer.len() < end {
+            return Ok(None);
+        }
+        let frame: Vec<u8> = buffer.drain(..end).skip(start).collect();
+        frame_text(frame).map(Some)
+    }
+}
+
+/// The length of the message as a 4-byte big-endian integer, then the message
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LengthPrefixed;
+
+impl Framer for LengthPrefixed {
+    fn encode(&self, message: &str, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        out.extend_from_slice(message.as_bytes());
+    }
+
+    fn encode_json<T: Serialize + ?Sized>(
+        &self,
+        message: &T,
+        out: &mut BytesMut,
+    ) -> Result<usize, MCPError> {
+        // Leave room for the length, and fill it in once the JSON is written
+        let start = out.len();
+        out.put_u32(0);
+        let length = match write_json(message, out) {
+            Ok(length) => length,
+            Err(e) => {
+                out.truncate(start);
+                return Err(e);
+            }
+        };
+        let prefix = u32::try_from(length).map_err(|_| {
+            out.truncate(start);
+            MCPError::MessageTooLarge {
+                limit: u32::MAX as usize,
+            }
+        })?;
+        out[start..start + 4].copy_from_slice(&prefix.to_be_bytes());
+        Ok(length)
+    }
+
+    fn decode(
+        &self,
+        buffer: &mut Vec<u8>,
+        _scanned: usize,
+        max_size: usize,
+    ) -> Result<Option<String>, MCPError> {
+        let Some(prefix) = buffer.get(..4) else {
+            return Ok(None);
+        };
+        let length = u32::from_be_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]) as usize;
+        if length > max_size {
+            return Err(MCPError::MessageTooLarge { limit: max_size });
+        }
+        let end = length
+            .checked_add(4)
+            .ok_or_else(|| MCPError::Transport(format!("Invalid length prefix: {}", length)))?;
+        if buffer.len() < end {
+            return Ok(None);
+        }
+        let frame: Vec<u8> = buffer.drain(..end).skip(4).collect();
+        frame_text(frame).map(Some)
+    }
+}
+
+/// One server-sent event per message, in its `data:` lines
+///
+/// Events without data, such as comments used as keep-alives, are skipped.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SseEvents;
+
+impl Framer for SseEvents {
+    fn encode(&self, message: &str, out: &mut Vec<u8>) {
+        out.extend_from_slice(b"event: message\n");
+        for line in message.lines() {
+            out.extend_from_slice(b"data: ");
+            out.extend_from_slice(line.as_bytes());
+            out.push(b'\n');
+        }
+        out.push(b'\n');
+    }
+
+    fn decode(
+        &self,
+        buffer: &mut Vec<u8>,
+        scanned: usize,
+        _max_size: usize,
+    ) -> Result<Option<String>, MCPError> {
+        // The blank line may have started in the bytes already searched
+        let mut from = scanned.saturating_sub(3);
+        loop {
+            let Some((end, separator)) = event_end(buffer, from) else {
+                return Ok(None);
+            };
+            from = 0;
+            let event: Vec<u8> = buffer.drain(..end + separator).collect();
+            let event = frame_text(event)?;
+            let data: Vec<&str> = event
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                .collect();
+            if !data.is_empty() {
+                return Ok(Some(data.join("\n")));
+            }
+        }
+    }
+}
+
+/// Where the first event in `buffer` ends, and the length of the blank line
+/// after it, searching from `from`
+fn event_end(buffer: &[u8], from: usize) -> Option<(usize, usize)> {
+    let rest = buffer.get(from..)?;
+    let lf = rest
+        .windows(2)
+        .position(|w| w == b"\n\n")
+        .map(|i| (from + i, 2));
+    let crlf = rest
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|i| (from + i, 4));
+    match (lf, crlf) {
+        (Some(lf), Some(crlf)) => Some(if lf.0 <= crlf.0 { lf } else { crlf }),
+        (lf, crlf) => lf.or(crlf),
+    }
+}
+
+/// Serialize `message` onto the end of `out`, returning its length
+fn write_json<T: Serialize + ?Sized>(message: &T, out: &mut BytesMut) -> Result<usize, MCPError> {
+    let start = out.len();
+    if let Err(e) = serde_json::to_writer(BufferWriter(out), message) {
+        out.truncate(start);
+        return Err(e.into());
+    }
+    Ok(out.len() - start)
+}
+
+/// Writes into a buffer, as `Vec<u8>` does; faster for the many small writes
+/// of a serializer than the buffer's own [`Writer`](bytes::buf::Writer)
+pub(crate) struct BufferWriter<'a>(pub(crate) &'a mut BytesMut);
+
+impl std::io::Write for BufferWriter<'_> {
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        self.0.extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    #[inline]
+    fn write_all(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.0.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Parses messages out of frames, with simd-json when the feature is on
+///
+/// simd-json parses in place, so frames are copied into scratch space kept
+/// for the next frame, along with simd-json's own buffers. Frames simd-json
+/// fails on are parsed again with serde_json, so the types and errors are
+/// the same with or without the feature; types it cannot decode, such as
+/// [`RawMessage`](crate::schema::json_rpc::RawMessage) and others with
+/// [`RawValue`](serde_json::value::RawValue) fields, are parsed twice.
+#[derive(Default)]
+pub struct FrameParser {
+    #[cfg(feature = "simd-json")]
+    scratch: Vec<u8>,
+    #[cfg(feature = "simd-json")]
+    buffers: simd_json::Buffers,
+}
+
+impl FrameParser {
+    /// A parser with empty buffers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a message out of `frame`
+    pub fn parse<T: DeserializeOwned>(&mut self, frame: &str) -> Result<T, serde_json::Error> {
+        #[cfg(feature = "simd-json")]
+        {
+            self.scratch.clear();
+            self.scratch.extend_from_slice(frame.as_bytes());
+            let parsed =
+                simd_json::serde::from_slice_with_buffers(&mut self.scratch, &mut self.buffers);
+            if self.scratch.capacity() > RETAINED_PARSE_BUFFER {
+                self.scratch = Vec::new();
+                self.buffers = simd_json::Buffers::default();
+            }
+            if let Ok(message) = parsed {
+                return Ok(message);
+            }
+        }
+        serde_json::from_str(frame)
+    }
+}
+
+fn frame_text(frame: Vec<u8>) -> Result<String, MCPError> {
+    String::from_utf8(frame)
+        .map_err(|e| MCPError::Transport(format!("Frame is not valid UTF-8: {}", e)))
+}
+
+/// Reading half of a framed transport, with the bytes read past the last frame
+pub(crate) struct FrameReader {
+    reader: Box<dyn AsyncRead + Send + Sync + Unpin>,
+    pub(crate) buffer: Vec<u8>,
+    /// Bytes at the front of the buffer the framer searched without finding a frame
+    scanned: usize,
+    /// Set once a frame was too large, since the next one cannot be found
+    abandoned: bool,
+    /// Counts every byte read, framing included
+    traffic: TrafficCounter,
+}
+
+impl FrameReader {
+    pub(crate) fn new(
+        reader: Box<dyn AsyncRead + Send + Sync + Unpin>,
+        traffic: TrafficCounter,
+    ) -> Self {
+        Self {
+            reader,
+            buffer: Vec::new(),
+            scanned: 0,
+            abandoned: false,
+            traffic,
+        }
+    }
+
+    /// Read the next chunk into the buffer; the end of the stream is an error
+    pub(crate) async fn read_more(&mut self) -> Result<(), MCPError> {
+        let mut chunk = [0; READ_CHUNK];
+        let read = self
+            .reader
+            .read(&mut chunk)
+            .await
+            .map_err(|e| MCPError::Transport(format!("Failed to read: {}", e)))?;
+        if read == 0 {
+            return Err(MCPError::ConnectionClosed);
+        }
+        self.traffic.received(read);
+        self.buffer.extend_from_slice(&chunk[..read]);
+        Ok(())
+    }
+
+    /// Read until `framer` finds a whole frame, and take it
+    ///
+    /// A larger frame read whole is skipped; once more than `max_size` bytes
+    /// are buffered without a whole frame, or a frame declares a larger
+    /// length, they are dropped and the stream is abandoned, since the next
+    /// frame cannot be found.
+    pub(crate) async fn read_frame(
+        &mut self,
+        framer: &impl Framer,
+        max_size: usize,
+    ) -> Result<String, MCPError> {
+        if self.abandoned {
+            return Err(MCPError::Transport(
+                "Stream abandoned after a message over the size limit".to_string(),
+            ));
+        }
+        loop {
+            let decoded = match framer.decode(&mut self.buffer, self.scanned, max_size) {
+                Err(e @ MCPError::MessageTooLarge { .. }) => {
+                    self.buffer = Vec::new();
+                    self.abandoned = true;
+                    return Err(e);
+                }
+                decoded => decoded?,
+            };
+            self.scanned = if decoded.is_some() {
+                0
+            } else {
+                self.buffer.len()
+            };
+            if let Some(frame) = decoded {
+                if frame.len() > max_size {
+                    return Err(MCPError::MessageTooLarge { limit: max_size });
+                }
+                return Ok(frame);
+            }
+            if self.buffer.len() > max_size {
+                self.buffer = Vec::new();
+                self.abandoned = true;
+                return Err(MCPError::MessageTooLarge { limit: max_size });
+            }
+            self.read_more().await?;
+        }
+    }
+}
+
+/// Writing half of a framed transport, with the buffer frames are built in
+struct FrameWriter {
+    writer: Box<dyn AsyncWrite + Send + Sync + Unpin>,
+    buffer: BytesMut,
+}
+
+/// A transport over a reader and a writer, framed by `F`
+///
+/// Clones share the connection, so requests can be awaited concurrently.
+pub struct FramedTransport<F: Framer> {
+    reader: Arc<TokioMutex<FrameReader>>,
+    writer: Arc<TokioMutex<FrameWriter>>,
+    framer: F,
+    parser: FrameParser,
+    max_message_size: usize,
+    traffic: TrafficCounter,
+    is_connected: bool,
+    on_close: Option<CloseCallback>,
+    on_error: Option<ErrorCallback>,
+    on_message: Option<MessageCallback>,
+}
+
+impl<F: Framer> FramedTransport<F> {
+    /// Send and receive messages framed by `framer` over `reader` and `writer`
+    pub fn new<R, W>(reader: R, writer: W, framer: F) -> Self
+    where
+        R: AsyncRead + Send + Sync + Unpin + 'static,
+        W: AsyncWrite + Send + Sync + Unpin + 'static,
+    {
+        let traffic = TrafficCounter::default();
+        Self {
+            reader: Arc::new(TokioMutex::new(FrameReader::new(
+                Box::new(reader),
+                traffic.clone(),
+            ))),
+            writer: Arc::new(TokioMutex::new(FrameWriter {
+                writer: Box::new(writer),
+                buffer: BytesMut::new(),
+            })),
+            framer,
+            parser: FrameParser::new(),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            traffic,
+            is_connected: false,
+            on_close: None,
+            on_error: None,
+            on_message: None,
+        }
+    }
+
+    /// Refuse to read or write messages larger than `max_message_size` bytes
+    ///
+    /// Reading a larger message fails with [`MCPError::MessageTooLarge`].
+    /// Unless it arrived in one read, the bytes past the limit are not
+    /// buffered, so the stream is given up and later reads fail too.
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// Handle an error by calling the error callback if set
+    fn fail<T>(&self, error: MCPError) -> Result<T, MCPError> {
+        if let Some(callback) = &self.on_error {
+            callback(&error);
+        }
+        Err(error)
+    }
+
+    /// Read until a whole frame is buffered, and take it
+    async fn read_frame(&self) -> Result<String, MCPError> {
+        let mut reader = self.reader.lock().await;
+        reader.read_frame(&self.framer, self.max_message_size).await
+    }
+}
+
+impl<F: Framer> Clone for FramedTransport<F> {
+    fn clone(&self) -> Self {
+        Self {
+            reader: self.reader.clone(),
+            writer: self.writer.clone(),
+            framer: self.framer.clone(),
+            parser: FrameParser::new(),
+            max_message_size: self.max_message_size,
+            traffic: self.traffic.clone(),
+            is_connected: self.is_connected,
+            // Callbacks cannot be cloned
+            on_close: None,
+            on_error: None,
+            on_message: None,
+        }
+    }
+}
+
+#[async_trait]
+impl<F: Framer> Transport for FramedTransport<F> {
+    async fn start(&mut self) -> Result<(), MCPError> {
+        if !self.is_connected {
+            self.is_connected = true;
+            trace::connected("framed");
+        }
+        Ok(())
+    }
+
+    async fn send<T: Serialize + Send + Sync>(&mut self, message: &T) -> Result<(), MCPError> {
+        if !self.is_connected {
+            return self.fail(MCPError::Transport("Transport not connected".to_string()));
+        }
+        // Serialized straight into the buffer kept from the last message
+        let mut guard = self.writer.lock().await;
+        let FrameWriter { writer, buffer } = &mut *guard;
+        buffer.clear();
+        let length = match self.framer.encode_json(message, buffer) {
+            Ok(length) => length,
+            Err(e) => return self.fail(e),
+        };
+        if length > self.max_message_size {
+            buffer.clear();
+            return self.fail(MCPError::MessageTooLarge {
+                limit: self.max_message_size,
+            });
+        }
+
+        let written = async {
+            writer.write_all(buffer).await?;
+            writer.flush().await
+        }
+        .await;
+        if written.is_ok() {
+            self.traffic.sent(buffer.len());
+        }
+        if buffer.capacity() > RETAINED_WRITE_BUFFER {
+            *buffer = BytesMut::new();
+        }
+        match written {
+            Ok(()) => Ok(()),
+            Err(e) => self.fail(MCPError::Transport(format!("Failed to write: {}", e))),
+        }
+    }
+
+    async fn receive<T: DeserializeOwned + Send + Sync>(&mut self) -> Result<T, MCPError> {
+        if !self.is_connected {
+            return self.fail(MCPError::Transport("Transport not connected".to_string()));
+        }
+        let frame = match self.read_frame().await {
+            Ok(frame) => frame,
+            Err(e) => return self.fail(e),
+        };
+        if let Some(callback) = &self.on_message {
+            callback(&frame);
+        }
+        match self.parser.parse(&frame) {
+            Ok(message) => Ok(message),
+            Err(e) => self.fail(MCPError::Serialization(e)),
+        }
+    }
+
+    async fn close(&mut self) -> Result<(), MCPError> {
+        if !self.is_connected {
+            return Ok(());
+        }
+        self.is_connected = false;
+        trace::disconnected("framed");
+
+        // The other end reads the end of the stream
+        if let Err(e) = self.writer.lock().await.writer.shutdown().await {
+            debug!("Failed to shut down the writer: {}", e);
+        }
+        if let Some(callback) = &self.on_close {
+            callback();
+        }
+        Ok(())
+    }
+
+    fn set_on_close(&mut self, callback: Option<CloseCallback>) {
+        self.on_close = callback;
+    }
+
+    fn set_on_error(&mut self, callback: Option<ErrorCallback>) {
+        self.on_error = callback;
+    }
+
+    fn set_on_message<C>(&mut self, callback: Option<C>)
+    where
+        C: Fn(&str) + Send + Sync + 'static,
+    {
+        self.on_message = callback.map(|f| Box::new(f) as MessageCallback);
+    }
+
+    fn share_connection(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+
+    fn traffic(&self) -> Option<Traffic> {
+        Some(self.traffic.traffic())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::json_rpc::JSONRPCMessage;
+    use crate::transport::{message_sink, message_stream};
+    use futures::{SinkExt, StreamExt};
+    use serde_json::{json, Value};
+
+    fn decode_all<F: Framer>(framer: &F, bytes: &[u8]) -> Vec<String> {
+        // Fed a byte at a time, so every frame is seen incomplete first
+        let mut buffer = Vec::new();
+        let mut frames = Vec::new();
+        for &byte in bytes {
+            let scanned = buffer.len();
+            buffer.push(byte);
+            if let Some(frame) = framer
+                .decode(&mut buffer, scanned, DEFAULT_MAX_MESSAGE_SIZE)
+                .unwrap()
+            {
+                frames.push(frame);
+                while let Some(frame) = framer
+                    .decode(&mut buffer, 0, DEFAULT_MAX_MESSAGE_SIZE)
+                    .unwrap()
+                {
+                    frames.push(frame);
+                }
+            }
+        }
+        assert!(buffer.iter().all(u8::is_ascii_whitespace));
+        frames
+    }
+
+    #[test]
+    fn test_framers() {
+        let messages = [r#"{"id":1}"#, r#"{"text":"ünïcode"}"#];
+        let mut lines = Vec::new();
+        let mut headers = Vec::new();
+        let mut events = Vec::new();
+        let mut prefixed = Vec::new();
+        for message in messages {
+            NewlineDelimited.encode(message, &mut lines);
+            ContentLength.encode(message, &mut headers);
+            SseEvents.encode(message, &mut events);
+            LengthPrefixed.encode(message, &mut prefixed);
+        }
+        assert_eq!(decode_all(&NewlineDelimited, &lines), messages);
+        assert_eq!(decode_all(&ContentLength, &headers), messages);
+        assert_eq!(decode_all(&SseEvents, &events), messages);
+        assert_eq!(decode_all(&LengthPrefixed, &prefixed), messages);
+        assert_eq!(&prefixed[..4], &[0, 0, 0, 8]);
+
+        assert_eq!(
+            decode_all(&NewlineDelimited, b"\r\n{\"a\":1}\r\n\n"),
+            [r#"{"a":1}"#]
+        );
+        let headers = b"Content-Type: application/json\r\ncontent-length: 2\r\n\r\n{}";
+        assert_eq!(decode_all(&ContentLength, headers), ["{}"]);
+        let events = b": keep-alive\r\n\r\ndata: {\"a\":\r\ndata: 1}\r\n\r\n";
+        assert_eq!(decode_all(&SseEvents, events), ["{\"a\":\n1}"]);
+        assert!(ContentLength
+            .decode(&mut b"Content-Type: json\r\n\r\n{}".to_vec(), 0, 1024)
+            .is_err());
+
+        // Declared lengths over the limit fail before the body arrives
+        let huge = format!("Content-Length: {}\r\n\r\n{{}}", usize::MAX);
+        assert!(matches!(
+            ContentLength.decode(&mut huge.into_bytes(), 0, 1024),
+            Err(MCPError::MessageTooLarge { limit: 1024 })
+        ));
+        assert!(matches!(
+            LengthPrefixed.decode(&mut vec![0xff, 0xff, 0xff, 0xff, b'{'], 0, 1024),
+            Err(MCPError::MessageTooLarge { limit: 1024 })
+        ));
+    }
+
+    // Serializing into the frame gives the same bytes as framing the JSON
+    #[test]
+    fn test_encode_json() {
+        fn check<F: Framer>(framer: F, buffer: &mut BytesMut) {
+            let message = json!({ "id": 1, "text": "ünïcode\n" });
+            let json = message.to_string();
+            let mut expected = Vec::new();
+            framer.encode(&json, &mut expected);
+            buffer.clear();
+            buffer.extend_from_slice(b"kept");
+            assert_eq!(framer.encode_json(&message, buffer).unwrap(), json.len());
+            assert_eq!(&buffer[..4], b"kept");
+            assert_eq!(&buffer[4..], &expected[..]);
+        }
+        let mut buffer = BytesMut::new();
+        check(NewlineDelimited, &mut buffer);
+        check(ContentLength, &mut buffer);
+        check(SseEvents, &mut buffer);
+        check(LengthPrefixed, &mut buffer);
+    }
+
+    // Whichever parser is on, raw values and errors come out as from serde_json
+    #[test]
+    fn test_parse_frame() {
+        #[derive(serde::Deserialize)]
+        struct Relayed {
+            id: u64,
+            params: Box<serde_json::value::RawValue>,
+        }
+        let frame = r#"{"id":7,"params":{"text":"a\nb","list":[1,2.5]}}"#;
+        let mut parser = FrameParser::new();
+        for _ in 0..2 {
+            let value: Value = parser.parse(frame).unwrap();
+            assert_eq!(value["params"]["text"], "a\nb");
+            let relayed: Relayed = parser.parse(frame).unwrap();
+            assert_eq!(relayed.id, 7);
+            assert_eq!(relayed.params.get(), r#"{"text":"a\nb","list":[1,2.5]}"#);
+            assert!(parser.parse::<JSONRPCMessage>(r#"{"id":"#).is_err());
+        }
+
+        // A frame that fails to parse does not affect the next ones
+        assert!(parser.parse::<JSONRPCMessage>(r#"{"id":1}"#).is_err());
+        let message: JSONRPCMessage = parser
+            .parse(r#"{"jsonrpc":"2.0","id":1,"result":{}}"#)
+            .unwrap();
+        assert!(matches!(message, JSONRPCMessage::Response(_)));
+    }
+
+    #[tokio::test]
+    async fn test_framed_transport() -> Result<(), MCPError> {
+        let (client_io, server_io) = tokio::io::duplex(64);
+        let (reader, writer) = tokio::io::split(client_io);
+        let mut client = FramedTransport::new(reader, writer, ContentLength);
+        let (reader, writer) = tokio::io::split(server_io);
+        let mut server = FramedTransport::new(reader, writer, ContentLength);
+        client.start().await?;
+        server.start().await?;
+
+        // Messages larger than the pipe arrive whole
+        let large =
+            json!({ "jsonrpc": "2.0", "method": "log", "params": { "text": "x".repeat(500) } });
+        let ping = json!({ "jsonrpc": "2.0", "id": 1, "method": "ping" });
+        let json_length = large.to_string().len() + ping.to_string().len();
+        let sending = tokio::spawn(async move {
+            client.send(&large).await?;
+            client.send(&ping).await?;
+            client.close().await?;
+            Ok::<_, MCPError>(client.traffic().unwrap())
+        });
+
+        let mut messages = Box::pin(message_stream(server.clone()));
+        let first = messages.next().await.unwrap()?;
+        assert!(matches!(first, JSONRPCMessage::Notification(n) if n.method == "log"));
+        let second = messages.next().await.unwrap()?;
+        assert!(matches!(second, JSONRPCMessage::Request(r) if r.method == "ping"));
+        assert!(messages.next().await.unwrap().is_err());
+        assert!(messages.next().await.is_none());
+
+        // Both ends count the frames, headers included
+        let sent = sending.await.unwrap()?;
+        assert_eq!(sent.bytes_sent, server.traffic().unwrap().bytes_received);
+        assert!(sent.bytes_sent as usize > json_length);
+
+        let (mut sink_end, mut peer) = crate::transport::in_memory::InMemoryTransport::pair();
+        sink_end.start().await?;
+        peer.start().await?;
+        let mut sink = Box::pin(message_sink(sink_end));
+        sink.send(JSONRPCMessage::Notification(
+            crate::schema::json_rpc::JSONRPCNotification::new("done".to_string(), None),
+        ))
+        .await?;
+        let received: Value = peer.receive().await?;
+        assert_eq!(received["method"], "done");
+        Ok(())
+    }
+
+    // A frame arriving in thousands of reads is searched once, not once per read
+    #[tokio::test]
+    async fn test_large_frame_in_chunks() -> Result<(), MCPError> {
+        async fn check<F: Framer>(framer: F) -> Result<(), MCPError> {
+            let (near, far) = tokio::io::duplex(READ_CHUNK);
+            let (reader, writer) = tokio::io::split(near);
+            let mut transport = FramedTransport::new(reader, writer, framer.clone());
+            transport.start().await?;
+
+            let large = json!({ "text": "x".repeat(16 * 1024 * 1024) });
+            let mut frame = Vec::new();
+            framer.encode(&large.to_string(), &mut frame);
+            let (_, mut far_writer) = tokio::io::split(far);
+            tokio::spawn(async move { far_writer.write_all(&frame).await });
+
+            let received: Value =
+                tokio::time::timeout(std::time::Duration::from_secs(30), transport.receive())
+                    .await
+                    .expect("Reading the frame took quadratic time")?;
+            assert_eq!(received, large);
+            Ok(())
+        }
+        check(NewlineDelimited).await?;
+        check(SseEvents).await
+    }
+
+    #[tokio::test]
+    async fn test_max_message_size() -> Result<(), MCPError> {
+        let (near, far) = tokio::io::duplex(4096);
+        let (reader, writer) = tokio::io::split(near);
+        let mut transport =
+            FramedTransport::new(reader, writer, NewlineDelimited).with_max_message_size(100);
+        transport.start().await?;
+
+        let large = json!({ "text": "x".repeat(200) });
+        assert!(matches!(
+            transport.send(&large).await,
+            Err(MCPError::MessageTooLarge { limit: 100 })
+        ));
+
+        // A whole oversized line is skipped
+        let (_, mut far_writer) = tokio::io::split(far);
+        far_writer
+            .write_all(format!("{}\n{{}}\n", large).as_bytes())
+            .await
+            .unwrap();
+        let received: Result<Value, _> = transport.receive().await;
+        assert!(matches!(
+            received,
+            Err(MCPError::MessageTooLarge { limit: 100 })
+        ));
+        let received: Value = transport.receive().await?;
+        assert_eq!(received, json!({}));
+
+        // A partial one is dropped rather than buffered, and the stream given up
+        far_writer
+            .write_all(large.to_string().as_bytes())
+            .await
+            .unwrap();
+        let received: Result<Value, _> = transport.receive().await;
+        assert!(matches!(
+            received,
+            Err(MCPError::MessageTooLarge { limit: 100 })
+        ));
+        let received: Result<Value, _> = transport.receive().await;
+        assert!(matches!(received, Err(MCPError::Transport(_))));
+        Ok(())
+    }
+}
@@ -0,0 +1,40 @@
+//! The HTTP client of the SSE and Streamable HTTP transports
+
+use crate::error::MCPError;
+use crate::transport::proxy::ProxySetting;
+use reqwest::Client;
+
+/// What the HTTP client of a transport is built from
+#[derive(Clone, Default)]
+pub(crate) struct HttpClientSettings {
+    /// The server's URL as given, before the TLS server name replaces its host
+    pub(crate) url: String,
+    pub(crate) proxy: ProxySetting,
+    #[cfg(feature = "rustls")]
+    pub(crate) tls: Option<crate::transport::tls::TlsConfig>,
+}
+
+impl HttpClientSettings {
+    pub(crate) fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Build the client, returning it with the URL to request
+    pub(crate) fn build(&self) -> Result<(Client, String), MCPError> {
+        let builder = self.proxy.configure_http(Client::builder())?;
+        #[cfg(feature = "rustls")]
+        let (builder, url) = match &self.tls {
+            Some(tls) => tls.configure_http(builder, &self.url)?,
+            None => (builder, self.url.clone()),
+        };
+        #[cfg(not(feature = "rustls"))]
+        let url = self.url.clone();
+        let client = builder
+            .build()
+            .map_err(|e| MCPError::Transport(format!("Failed to create HTTP client: {}", e)))?;
+        Ok((client, url))
+    }
+}
@@ -0,0 +1,205 @@
+//! In-process transport connecting a client and a server directly
+//!
+//! [`InMemoryTransport::pair`] returns two connected ends, so a
+//! [`Client`](crate::client::Client) and a [`Server`](crate::server::Server)
+//! can run in the same process, for tests or to embed a server, without
+//! spawning processes or opening sockets.
+//!
+//! ```rust,no_run
+//! # use mcpr::{client::Client, server::{Server, ServerConfig}, transport::in_memory::InMemoryTransport};
+//! # async fn run() -> Result<(), mcpr::error::MCPError> {
+//! let (client_end, server_end) = InMemoryTransport::pair();
+//! let mut server = Server::new(ServerConfig::new());
+//! tokio::spawn(async move { server.serve(server_end).await });
+//!
+//! let mut client = Client::new(client_end);
+//! client.initialize().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::error::MCPError;
+use crate::redact;
+use crate::trace;
+use crate::transport::{CloseCallback, ErrorCallback, MessageCallback, Transport};
+use async_trait::async_trait;
+use log::debug;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex as TokioMutex};
+
+/// One end of an in-process connection
+///
+/// Messages go through serde as on any other transport, so both ends see
+/// exactly what they would over the wire. Clones share the end, like
+/// clones of [`StdioTransport`](crate::transport::stdio::StdioTransport).
+pub struct InMemoryTransport {
+    sender: mpsc::UnboundedSender<Value>,
+    /// Shared by clones, so they read the same stream
+    receiver: Arc<TokioMutex<mpsc::UnboundedReceiver<Value>>>,
+    is_connected: bool,
+    on_close: Option<CloseCallback>,
+    on_error: Option<ErrorCallback>,
+    on_message: Option<MessageCallback>,
+}
+
+impl InMemoryTransport {
+    /// Create two connected transports
+    ///
+    /// What one end sends, the other receives. Closing an end, with every
+    /// clone of it, ends the other's stream.
+    pub fn pair() -> (Self, Self) {
+        let (first_tx, first_rx) = mpsc::unbounded_channel();
+        let (second_tx, second_rx) = mpsc::unbounded_channel();
+        (
+            Self::new(first_tx, second_rx),
+            Self::new(second_tx, first_rx),
+        )
+    }
+
+    fn new(sender: mpsc::UnboundedSender<Value>, receiver: mpsc::UnboundedReceiver<Value>) -> Self {
+        Self {
+            sender,
+            receiver: Arc::new(TokioMutex::new(receiver)),
+            is_connected: false,
+            on_close: None,
+            on_error: None,
+            on_message: None,
+        }
+    }
+
+    /// Handle an error by calling the error callback if set
+    fn fail(&self, error: MCPError) -> MCPError {
+        if let Some(callback) = &self.on_error {
+            callback(&error);
+        }
+        error
+    }
+}
+
+impl Clone for InMemoryTransport {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            receiver: self.receiver.clone(),
+            is_connected: self.is_connected,
+            on_close: None, // Callbacks cannot be cloned
+            on_error: None,
+            on_message: None,
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for InMemoryTransport {
+    async fn start(&mut self) -> Result<(), MCPError> {
+        self.is_connected = true;
+        trace::connected("in-memory");
+        Ok(())
+    }
+
+    async fn send<T: Serialize + Send + Sync>(&mut self, message: &T) -> Result<(), MCPError> {
+        if !self.is_connected {
+            return Err(self.fail(MCPError::Transport("Transport not connected".to_string())));
+        }
+
+        let message = serde_json::to_value(message).map_err(|e| self.fail(e.into()))?;
+        debug!(
+            "Sending in-memory message: {}",
+            redact::for_log(&message.to_string())
+        );
+        self.sender
+            .send(message)
+            .map_err(|_| self.fail(MCPError::ConnectionClosed))
+    }
+
+    async fn receive<T: DeserializeOwned + Send + Sync>(&mut self) -> Result<T, MCPError> {
+        if !self.is_connected {
+            return Err(self.fail(MCPError::Transport("Transport not connected".to_string())));
+        }
+
+        let message = self.receiver.lock().await.recv().await;
+        let message = message.ok_or_else(|| self.fail(MCPError::ConnectionClosed))?;
+        if let Some(callback) = &self.on_message {
+            callback(&message.to_string());
+        }
+        serde_json::from_value(message).map_err(|e| self.fail(e.into()))
+    }
+
+    async fn close(&mut self) -> Result<(), MCPError> {
+        if !self.is_connected {
+            return Ok(());
+        }
+
+        self.is_connected = false;
+        trace::disconnected("in-memory");
+
+        // The other end's stream ends once every clone dropped its sender
+        let (closed_tx, _) = mpsc::unbounded_channel();
+        self.sender = closed_tx;
+
+        if let Some(callback) = &self.on_close {
+            callback();
+        }
+
+        Ok(())
+    }
+
+    fn set_on_close(&mut self, callback: Option<CloseCallback>) {
+        self.on_close = callback;
+    }
+
+    fn set_on_error(&mut self, callback: Option<ErrorCallback>) {
+        self.on_error = callback;
+    }
+
+    fn set_on_message<F>(&mut self, callback: Option<F>)
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.on_message = callback.map(|f| Box::new(f) as MessageCallback);
+    }
+
+    fn share_connection(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::schema::common::{Tool, ToolInputSchema};
+    use crate::server::{Server, ServerConfig};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_pair() -> Result<(), MCPError> {
+        let (client_end, server_end) = InMemoryTransport::pair();
+        let mut server = Server::new(ServerConfig::new().with_tool(Tool {
+            name: "echo".to_string(),
+            description: None,
+            input_schema: ToolInputSchema {
+                r#type: "object".to_string(),
+                properties: None,
+                required: None,
+                keywords: serde_json::Map::new(),
+            },
+            output_schema: None,
+            annotations: None,
+        }));
+        server.register_tool_handler("echo", |params: Value| async move { Ok(params) })?;
+        let serving = tokio::spawn(async move { server.serve(server_end).await });
+
+        let mut client = Client::new(client_end);
+        client.initialize().await?;
+        let result: Value = client.call_tool("echo", &json!({ "n": 1 })).await?;
+        assert_eq!(result["content"][0]["text"], json!("{\n  \"n\": 1\n}"));
+
+        // Closing the client ends the server's stream
+        client.shutdown().await?;
+        let _ = serving.await;
+        Ok(())
+    }
+}
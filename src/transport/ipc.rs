@@ -0,0 +1,279 @@
+//! Local IPC transport over Unix domain sockets or Windows named pipes
+//!
+//! Messages are newline-delimited JSON, as with
+//! [`StdioTransport`](crate::transport::stdio::StdioTransport), so a
+//! daemon can offer the same protocol on a socket that it offers on stdio.
+//! On Unix the endpoint is a socket path; on Windows it is a pipe name such
+//! as `\\.\pipe\mcpr`.
+//!
+//! ```rust,no_run
+//! # use mcpr::{server::{Server, ServerConfig}, transport::ipc::{IpcListener, IpcTransport}};
+//! # async fn run() -> Result<(), mcpr::error::MCPError> {
+//! let listener = IpcListener::bind("/tmp/mcpr.sock")?;
+//! loop {
+//!     let transport = listener.accept().await?;
+//!     // Each connection gets its own server
+//!     let mut server: Server<IpcTransport> = Server::new(ServerConfig::new());
+//!     tokio::spawn(async move { server.serve(transport).await });
+//! }
+//! # }
+//! ```
+
+use crate::error::MCPError;
+use crate::transport::{
+    framed::{FramedTransport, NewlineDelimited},
+    CloseCallback, ErrorCallback, Traffic, Transport,
+};
+use async_trait::async_trait;
+use log::{debug, info};
+use serde::{de::DeserializeOwned, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Transport over a Unix domain socket or a Windows named pipe
+///
+/// Clones share the connection, like clones of a [`FramedTransport`].
+#[derive(Clone)]
+pub struct IpcTransport {
+    endpoint: PathBuf,
+    inner: FramedTransport<NewlineDelimited>,
+}
+
+impl IpcTransport {
+    /// Connect to the socket or named pipe at `endpoint`
+    pub async fn connect(endpoint: impl AsRef<Path>) -> Result<Self, MCPError> {
+        let endpoint = endpoint.as_ref().to_path_buf();
+        debug!("Connecting to IPC endpoint {}", endpoint.display());
+
+        #[cfg(unix)]
+        let inner = {
+            let stream = tokio::net::UnixStream::connect(&endpoint)
+                .await
+                .map_err(|e| connect_error(&endpoint, e))?;
+            let (reader, writer) = stream.into_split();
+            FramedTransport::new(reader, writer, NewlineDelimited)
+        };
+
+        #[cfg(windows)]
+        let inner = {
+            let pipe = tokio::net::windows::named_pipe::ClientOptions::new()
+                .open(&endpoint)
+                .map_err(|e| connect_error(&endpoint, e))?;
+            let (reader, writer) = tokio::io::split(pipe);
+            FramedTransport::new(reader, writer, NewlineDelimited)
+        };
+
+        info!("Connected to IPC endpoint {}", endpoint.display());
+        Ok(Self { endpoint, inner })
+    }
+
+    /// Refuse to read or write messages larger than `max_message_size` bytes,
+    /// see [`FramedTransport::with_max_message_size`]
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.inner = self.inner.with_max_message_size(max_message_size);
+        self
+    }
+
+    /// The socket path or pipe name of the connection
+    pub fn endpoint(&self) -> &Path {
+        &self.endpoint
+    }
+}
+
+fn connect_error(endpoint: &Path, e: std::io::Error) -> MCPError {
+    MCPError::Transport(format!(
+        "Failed to connect to {}: {}",
+        endpoint.display(),
+        e
+    ))
+}
+
+#[async_trait]
+impl Transport for IpcTransport {
+    async fn start(&mut self) -> Result<(), MCPError> {
+        self.inner.start().await
+    }
+
+    async fn send<T: Serialize + Send + Sync>(&mut self, message: &T) -> Result<(), MCPError> {
+        self.inner.send(message).await
+    }
+
+    async fn receive<T: DeserializeOwned + Send + Sync>(&mut self) -> Result<T, MCPError> {
+        self.inner.receive().await
+    }
+
+    async fn close(&mut self) -> Result<(), MCPError> {
+        self.inner.close().await
+    }
+
+    fn set_on_close(&mut self, callback: Option<CloseCallback>) {
+        self.inner.set_on_close(callback);
+    }
+
+    fn set_on_error(&mut self, callback: Option<ErrorCallback>) {
+        self.inner.set_on_error(callback);
+    }
+
+    fn set_on_message<F>(&mut self, callback: Option<F>)
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.inner.set_on_message(callback);
+    }
+
+    fn share_connection(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+
+    fn traffic(&self) -> Option<Traffic> {
+        self.inner.traffic()
+    }
+}
+
+/// Listener accepting [`IpcTransport`] connections
+///
+/// Any number of clients may be connected at once; each accepted transport
+/// is independent of the others. On Unix the socket file is removed when
+/// the listener is dropped.
+pub struct IpcListener {
+    endpoint: PathBuf,
+    #[cfg(unix)]
+    listener: tokio::net::UnixListener,
+    /// The pipe instance the next client connects to
+    #[cfg(windows)]
+    next: tokio::sync::Mutex<tokio::net::windows::named_pipe::NamedPipeServer>,
+}
+
+impl IpcListener {
+    /// Listen on the socket path or pipe name `endpoint`
+    ///
+    /// On Unix a stale socket file left at the path is replaced.
+    pub fn bind(endpoint: impl AsRef<Path>) -> Result<Self, MCPError> {
+        let endpoint = endpoint.as_ref().to_path_buf();
+        let bind_error = |e: std::io::Error| {
+            MCPError::Transport(format!("Failed to bind to {}: {}", endpoint.display(), e))
+        };
+
+        #[cfg(unix)]
+        let listener = {
+            if std::os::unix::net::UnixStream::connect(&endpoint).is_err() {
+                let _ = std::fs::remove_file(&endpoint);
+            }
+            let listener = tokio::net::UnixListener::bind(&endpoint).map_err(bind_error)?;
+            info!("IPC server listening on {}", endpoint.display());
+            Self { endpoint, listener }
+        };
+
+        #[cfg(windows)]
+        let listener = {
+            let next = tokio::net::windows::named_pipe::ServerOptions::new()
+                .first_pipe_instance(true)
+                .create(&endpoint)
+                .map_err(bind_error)?;
+            info!("IPC server listening on {}", endpoint.display());
+            Self {
+                endpoint,
+                next: tokio::sync::Mutex::new(next),
+            }
+        };
+
+        Ok(listener)
+    }
+
+    /// Wait for the next client and return a transport connected to it
+    pub async fn accept(&self) -> Result<IpcTransport, MCPError> {
+        let accept_error =
+            |e: std::io::Error| MCPError::Transport(format!("Failed to accept connection: {}", e));
+
+        #[cfg(unix)]
+        let inner = {
+            let (stream, _) = self.listener.accept().await.map_err(accept_error)?;
+            let (reader, writer) = stream.into_split();
+            FramedTransport::new(reader, writer, NewlineDelimited)
+        };
+
+        #[cfg(windows)]
+        let inner = {
+            let mut next = self.next.lock().await;
+            next.connect().await.map_err(accept_error)?;
+            // Open the instance for the next client before handing this one out
+            let pipe = std::mem::replace(
+                &mut *next,
+                tokio::net::windows::named_pipe::ServerOptions::new()
+                    .create(&self.endpoint)
+                    .map_err(accept_error)?,
+            );
+            let (reader, writer) = tokio::io::split(pipe);
+            FramedTransport::new(reader, writer, NewlineDelimited)
+        };
+
+        info!("IPC connection accepted on {}", self.endpoint.display());
+        Ok(IpcTransport {
+            endpoint: self.endpoint.clone(),
+            inner,
+        })
+    }
+
+    /// The socket path or pipe name listened on
+    pub fn endpoint(&self) -> &Path {
+        &self.endpoint
+    }
+}
+
+#[cfg(unix)]
+impl Drop for IpcListener {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.endpoint);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::schema::common::{Tool, ToolInputSchema};
+    use crate::server::{Server, ServerConfig};
+    use serde_json::{json, Value};
+
+    #[tokio::test]
+    async fn test_concurrent_clients() -> Result<(), MCPError> {
+        let path = std::env::temp_dir().join(format!("mcpr-ipc-{}.sock", std::process::id()));
+        let listener = IpcListener::bind(&path)?;
+        tokio::spawn(async move {
+            while let Ok(transport) = listener.accept().await {
+                let mut server = Server::new(ServerConfig::new().with_tool(Tool {
+                    name: "echo".to_string(),
+                    description: None,
+                    input_schema: ToolInputSchema {
+                        r#type: "object".to_string(),
+                        properties: None,
+                        required: None,
+                        keywords: serde_json::Map::new(),
+                    },
+                    output_schema: None,
+                    annotations: None,
+                }));
+                server.register_tool_handler("echo", |params: Value| async move { Ok(params) })?;
+                tokio::spawn(async move { server.serve(transport).await });
+            }
+            Ok::<_, MCPError>(())
+        });
+
+        let mut first = Client::new(IpcTransport::connect(&path).await?);
+        let mut second = Client::new(IpcTransport::connect(&path).await?);
+        first.initialize().await?;
+        second.initialize().await?;
+
+        let (one, two) = (json!({ "client": 1 }), json!({ "client": 2 }));
+        let (a, b) = tokio::join!(
+            first.call_tool::<_, Value>("echo", &one),
+            second.call_tool::<_, Value>("echo", &two),
+        );
+        let text = |result: Value| result["content"][0]["text"].as_str().unwrap().to_string();
+        assert_eq!(serde_json::from_str::<Value>(&text(a?))?, one);
+        assert_eq!(serde_json::from_str::<Value>(&text(b?))?, two);
+
+        first.shutdown().await?;
+        second.shutdown().await?;
+        Ok(())
+    }
+}
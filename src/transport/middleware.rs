@@ -0,0 +1,207 @@
+//! Middleware for the messages of any transport
+//!
+//! [`MiddlewareTransport`] wraps a transport and passes every message through
+//! a stack of [`Middleware`] layers, for cross-cutting concerns such as
+//! logging, metrics, injecting `_meta` fields or rewriting messages. Since
+//! clients and servers both take a transport, the same layers work on
+//! either side.
+//!
+//! Outgoing messages go through the layers in the order they were added and
+//! incoming messages in reverse order, so the first layer is the outermost,
+//! as in tower.
+//!
+//! ```rust,no_run
+//! use mcpr::{
+//!     client::Client,
+//!     error::MCPError,
+//!     transport::{
+//!         middleware::{Middleware, MiddlewareTransport},
+//!         stdio::StdioTransport,
+//!     },
+//! };
+//! use serde_json::Value;
+//!
+//! struct LogMethods;
+//!
+//! #[async_trait::async_trait]
+//! impl Middleware for LogMethods {
+//!     async fn on_send(&self, message: &mut Value) -> Result<(), MCPError> {
+//!         if let Some(method) = message.get("method") {
+//!             log::info!("-> {}", method);
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let transport = MiddlewareTransport::new(StdioTransport::new()).with_layer(LogMethods);
+//! let client = Client::new(transport);
+//! ```
+
+use crate::error::MCPError;
+use crate::transport::{CloseCallback, ErrorCallback, Traffic, Transport};
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{process::ExitStatus, sync::Arc};
+
+/// A layer that sees, and may change, every message of a transport
+///
+/// Messages are raw JSON, so batches and messages the protocol types do not
+/// know pass through too. Returning an error fails the send or receive with
+/// it. Both hooks do nothing by default.
+#[async_trait]
+pub trait Middleware: Send + Sync + 'static {
+    /// Called with every message before it is sent
+    async fn on_send(&self, _message: &mut Value) -> Result<(), MCPError> {
+        Ok(())
+    }
+
+    /// Called with every message received, before it is decoded
+    async fn on_receive(&self, _message: &mut Value) -> Result<(), MCPError> {
+        Ok(())
+    }
+}
+
+/// Transport running every message through [`Middleware`] layers
+///
+/// Clones share the layers.
+#[derive(Clone)]
+pub struct MiddlewareTransport<T: Transport> {
+    inner: T,
+    layers: Arc<Vec<Arc<dyn Middleware>>>,
+}
+
+impl<T: Transport> MiddlewareTransport<T> {
+    /// Wrap `inner` without any layers
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            layers: Arc::new(Vec::new()),
+        }
+    }
+
+    /// Add a layer inside the ones added before
+    pub fn with_layer(mut self, layer: impl Middleware) -> Self {
+        let mut layers: Vec<_> = self.layers.iter().cloned().collect();
+        layers.push(Arc::new(layer));
+        self.layers = Arc::new(layers);
+        self
+    }
+
+    /// The wrapped transport
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+#[async_trait]
+impl<T: Transport> Transport for MiddlewareTransport<T> {
+    async fn start(&mut self) -> Result<(), MCPError> {
+        self.inner.start().await
+    }
+
+    async fn send<M: Serialize + Send + Sync>(&mut self, message: &M) -> Result<(), MCPError> {
+        let mut message = serde_json::to_value(message)?;
+        for layer in self.layers.iter() {
+            layer.on_send(&mut message).await?;
+        }
+        self.inner.send(&message).await
+    }
+
+    async fn receive<M: DeserializeOwned + Send + Sync>(&mut self) -> Result<M, MCPError> {
+        let mut message: Value = self.inner.receive().await?;
+        for layer in self.layers.iter().rev() {
+            layer.on_receive(&mut message).await?;
+        }
+        Ok(serde_json::from_value(message)?)
+    }
+
+    async fn close(&mut self) -> Result<(), MCPError> {
+        self.inner.close().await
+    }
+
+    fn set_on_close(&mut self, callback: Option<CloseCallback>) {
+        self.inner.set_on_close(callback);
+    }
+
+    fn set_on_error(&mut self, callback: Option<ErrorCallback>) {
+        self.inner.set_on_error(callback);
+    }
+
+    fn set_on_message<F>(&mut self, callback: Option<F>)
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.inner.set_on_message(callback);
+    }
+
+    async fn exit_status(&self) -> Option<ExitStatus> {
+        self.inner.exit_status().await
+    }
+
+    fn share_connection(&self) -> Option<Self> {
+        Some(Self {
+            inner: self.inner.share_connection()?,
+            layers: self.layers.clone(),
+        })
+    }
+
+    fn traffic(&self) -> Option<Traffic> {
+        self.inner.traffic()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::in_memory::InMemoryTransport;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    /// Records the order in which layers see messages, by name
+    struct Trace(&'static str, Arc<Mutex<Vec<String>>>);
+
+    #[async_trait]
+    impl Middleware for Trace {
+        async fn on_send(&self, message: &mut Value) -> Result<(), MCPError> {
+            self.1.lock().unwrap().push(format!("send {}", self.0));
+            message["_meta"][self.0] = json!(true);
+            Ok(())
+        }
+
+        async fn on_receive(&self, message: &mut Value) -> Result<(), MCPError> {
+            self.1.lock().unwrap().push(format!("receive {}", self.0));
+            if message["method"] == "secret" {
+                return Err(MCPError::Protocol("Rejected".to_string()));
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_layers() -> Result<(), MCPError> {
+        let (near, mut far) = InMemoryTransport::pair();
+        let trace = Arc::new(Mutex::new(Vec::new()));
+        let mut transport = MiddlewareTransport::new(near)
+            .with_layer(Trace("outer", trace.clone()))
+            .with_layer(Trace("inner", trace.clone()));
+        transport.start().await?;
+        far.start().await?;
+
+        transport.send(&json!({ "method": "ping" })).await?;
+        let sent: Value = far.receive().await?;
+        assert_eq!(sent["_meta"], json!({ "outer": true, "inner": true }));
+
+        far.send(&json!({ "method": "pong" })).await?;
+        let _: Value = transport.receive().await?;
+        assert_eq!(
+            *trace.lock().unwrap(),
+            ["send outer", "send inner", "receive inner", "receive outer"]
+        );
+
+        // A layer can reject a message
+        far.send(&json!({ "method": "secret" })).await?;
+        assert!(transport.receive::<Value>().await.is_err());
+        Ok(())
+    }
+}
@@ -5,14 +5,67 @@
 //!
 //! The following transport types are supported:
 //! - Stdio: Standard input/output for local processes
+//!   ([`stdio::StdioTransport::spawn`] starts the server process and owns it, within
+//!   the limits of [`spawn::SpawnOptions`])
 //! - SSE: Server-Sent Events for server-to-client messages with HTTP POST for client-to-server
+//!   ([`sse::SseTransport`] connects to servers speaking the MCP HTTP+SSE transport)
+//! - Streamable HTTP: HTTP POST per message, with responses as JSON or an
+//!   event stream, as in the 2025-03-26 specification
 //! - WebSocket: Bidirectional communication over WebSockets
+//! - IPC: Unix domain sockets or Windows named pipes for local daemons, with
+//!   [`ipc::IpcListener`] accepting any number of clients
+//!
+//! [`in_memory::InMemoryTransport::pair`] connects a client and a server in
+//! the same process.
+//! [`middleware::MiddlewareTransport`] runs the messages of any of them
+//! through middleware layers, for clients and servers alike.
+//! [`chaos::ChaosTransport`] wraps any of them to inject faults for testing.
+//! In the browser, clients connect with the `browser` transports, over
+//! `fetch` or a WebSocket; of the others, only the in-memory transport,
+//! middleware and codecs are available there.
+//! The WebSocket transport can also send MessagePack instead of JSON; see
+//! [`codec::Codec`].
+//! With the `rustls` feature, the SSE, Streamable HTTP and WebSocket
+//! transports take a [`tls::TlsConfig`] for private CAs, client
+//! certificates and SNI. They honor the `HTTP_PROXY`, `HTTPS_PROXY`,
+//! `ALL_PROXY` and `NO_PROXY` variables, or a [`proxy::ProxyConfig`].
+//! With the `compression` feature, the Streamable HTTP and WebSocket
+//! transports compress large messages with gzip or zstd; see
+//! [`compression::Compression`].
+//!
+//! New transports over a byte stream only provide the reader and writer:
+//! [`framed::FramedTransport`] splits it into messages with a
+//! [`framed::Framer`], for newline-delimited JSON, `Content-Length` headers
+//! or server-sent events. [`message_stream`] and [`message_sink`] adapt any
+//! transport to a [`Stream`] of messages and a [`Sink`] for them.
+//!
+//! The stdio, framed, IPC and WebSocket transports refuse messages larger
+//! than [`DEFAULT_MAX_MESSAGE_SIZE`], or the limit set with their
+//! `with_max_message_size`, with [`MCPError::MessageTooLarge`] instead of
+//! buffering them, and queue at most [`DEFAULT_QUEUE_CAPACITY`] messages
+//! before waiting for the reader or writer to catch up.
 //!
 //! The transport implementations are now fully async, using tokio for async I/O.
 
 use crate::error::MCPError;
+use crate::schema::json_rpc::{JSONRPCMessage, JSONRPCPayload};
 use async_trait::async_trait;
+use futures::{sink, stream, Sink, Stream};
 use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::VecDeque,
+    process::ExitStatus,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// Largest message a transport reads or writes unless configured otherwise, 64 MiB
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Messages a transport queues before senders or its read loop wait
+pub const DEFAULT_QUEUE_CAPACITY: usize = 32;
 
 /// Type alias for a closure that is called when an error occurs
 pub type ErrorCallback = Box<dyn Fn(&MCPError) + Send + Sync>;
@@ -23,9 +76,51 @@ pub type MessageCallback = Box<dyn Fn(&str) + Send + Sync>;
 /// Type alias for a closure that is called when the connection is closed
 pub type CloseCallback = Box<dyn Fn() + Send + Sync>;
 
+/// Bytes a transport wrote and read, as counted by [`Transport::traffic`]
+///
+/// Transports over byte streams count their frames, headers included; the
+/// HTTP and WebSocket transports count the message bodies.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Traffic {
+    /// Bytes written
+    pub bytes_sent: u64,
+    /// Bytes read
+    pub bytes_received: u64,
+}
+
+/// Running [`Traffic`] of a connection, shared by the clones of a transport
+///
+/// Transports add the length of each frame as they write or read it, so
+/// nothing is serialized again for counting.
+#[derive(Debug, Clone, Default)]
+pub struct TrafficCounter(Arc<[AtomicU64; 2]>);
+
+impl TrafficCounter {
+    /// Count `bytes` written
+    pub fn sent(&self, bytes: usize) {
+        self.0[0].fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Count `bytes` read
+    pub fn received(&self, bytes: usize) {
+        self.0[1].fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// The bytes counted so far
+    pub fn traffic(&self) -> Traffic {
+        Traffic {
+            bytes_sent: self.0[0].load(Ordering::Relaxed),
+            bytes_received: self.0[1].load(Ordering::Relaxed),
+        }
+    }
+}
+
 /// Transport trait for MCP communication
+///
+/// Transports own their connection, so clients can read it from a task of
+/// their own.
 #[async_trait]
-pub trait Transport: Send + Sync {
+pub trait Transport: Send + Sync + 'static {
     /// Start processing messages
     async fn start(&mut self) -> Result<(), MCPError>;
 
@@ -48,13 +143,139 @@ pub trait Transport: Send + Sync {
     fn set_on_message<F>(&mut self, callback: Option<F>)
     where
         F: Fn(&str) + Send + Sync + 'static;
+
+    /// How the server process behind the transport exited, once it has
+    ///
+    /// Only transports that spawned the server know; others return `None`.
+    async fn exit_status(&self) -> Option<ExitStatus> {
+        None
+    }
+
+    /// Another handle on the same connection, for reading in the background
+    ///
+    /// Clients read through it while no request waits, so the server's
+    /// requests and notifications are handled as they arrive. Transports
+    /// whose clones open connections of their own return `None`, the
+    /// default, and are only read by requests waiting for their response.
+    fn share_connection(&self) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
+
+    /// Bytes written and read on the connection so far
+    ///
+    /// Counted from the frames the transport writes and reads, so clients
+    /// report them without encoding messages again. Transports that do not
+    /// count, such as the in-memory one, return `None`, the default.
+    fn traffic(&self) -> Option<Traffic> {
+        None
+    }
+}
+
+/// The messages `transport` receives, as a stream
+///
+/// Batches are split into their messages. The transport must be started;
+/// the stream ends after the first error, such as the connection closing.
+pub fn message_stream<T>(
+    transport: T,
+) -> impl Stream<Item = Result<JSONRPCMessage, MCPError>> + Send
+where
+    T: Transport + 'static,
+{
+    let state = Some((transport, VecDeque::new()));
+    stream::unfold(state, |state| async move {
+        let (mut transport, mut queued) = state?;
+        loop {
+            if let Some(message) = queued.pop_front() {
+                return Some((Ok(message), Some((transport, queued))));
+            }
+            match transport.receive::<JSONRPCPayload>().await {
+                Ok(payload) => queued.extend(payload.into_messages()),
+                Err(e) => return Some((Err(e), None)),
+            }
+        }
+    })
+}
+
+/// A sink sending messages over `transport`, which must be started
+pub fn message_sink<T>(transport: T) -> impl Sink<JSONRPCMessage, Error = MCPError> + Send
+where
+    T: Transport + 'static,
+{
+    sink::unfold(
+        transport,
+        |mut transport, message: JSONRPCMessage| async move {
+            transport.send(&message).await?;
+            Ok(transport)
+        },
+    )
 }
 
 /// Standard IO transport
+#[cfg(feature = "runtime-tokio")]
 pub mod stdio;
 
+/// Working directory, environment and limits of spawned servers
+#[cfg(feature = "runtime-tokio")]
+pub mod spawn;
+
 /// Server-Sent Events (SSE) transport
+#[cfg(feature = "runtime-tokio")]
 pub mod sse;
 
+/// Streamable HTTP transport
+#[cfg(feature = "runtime-tokio")]
+pub mod streamable_http;
+
 /// WebSocket transport
+#[cfg(feature = "runtime-tokio")]
 pub mod websocket;
+
+/// Unix domain socket and named pipe transport
+#[cfg(feature = "runtime-tokio")]
+pub mod ipc;
+
+/// Plain TCP transport
+#[cfg(feature = "runtime-tokio")]
+pub mod tcp;
+
+/// Proxies for the network transports
+#[cfg(feature = "runtime-tokio")]
+pub mod proxy;
+
+#[cfg(feature = "runtime-tokio")]
+pub(crate) mod http_client;
+
+#[cfg(all(test, feature = "runtime-tokio"))]
+pub(crate) mod test_http;
+
+/// TLS settings for the network transports
+#[cfg(feature = "rustls")]
+pub mod tls;
+
+/// Compression of messages for the network transports
+#[cfg(feature = "compression")]
+pub mod compression;
+
+/// In-process transport pair
+pub mod in_memory;
+
+/// Fault-injecting transport wrapper
+#[cfg(feature = "runtime-tokio")]
+pub mod chaos;
+
+/// Message middleware for any transport
+pub mod middleware;
+
+/// Wire encodings for binary-capable transports
+pub mod codec;
+
+/// Transports over byte streams, framed by a pluggable framer
+#[cfg(feature = "runtime-tokio")]
+pub mod framed;
+
+/// Transports for clients running in the browser
+#[cfg(target_arch = "wasm32")]
+pub mod browser;
@@ -0,0 +1,472 @@
+//! Options for server processes spawned by [`StdioTransport::spawn_with`]
+//!
+//! A spawned server runs with the client's privileges, so it may be worth
+//! limiting what it sees and uses: its working directory, which environment
+//! variables it inherits, and how much memory, CPU time and processes it may
+//! take. Limits are set with `setrlimit` and `setpriority` on Unix and with a
+//! job object on Windows.
+//!
+//! ```rust,no_run
+//! # fn run() -> Result<(), mcpr::error::MCPError> {
+//! use mcpr::transport::{spawn::SpawnOptions, stdio::StdioTransport};
+//!
+//! let options = SpawnOptions::new()
+//!     .with_current_dir("/srv/weather")
+//!     .with_env_allowlist(["PATH", "LANG"])
+//!     .with_env("WEATHER_API_KEY", "...")
+//!     .with_max_memory(512 * 1024 * 1024)
+//!     .with_nice(10);
+//! let transport = StdioTransport::spawn_with("weather-server", ["--quiet"], options)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`StdioTransport::spawn_with`]: crate::transport::stdio::StdioTransport::spawn_with
+
+use crate::error::MCPError;
+use std::ffi::{OsStr, OsString};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::process::{Child, Command};
+
+/// How to spawn a server process, see [`StdioTransport::spawn_with`]
+///
+/// By default the server inherits the client's working directory and
+/// environment, runs without limits, and is killed when the transport is
+/// dropped.
+///
+/// [`StdioTransport::spawn_with`]: crate::transport::stdio::StdioTransport::spawn_with
+#[derive(Debug, Clone)]
+pub struct SpawnOptions {
+    current_dir: Option<PathBuf>,
+    /// The inherited variables, or all of them when `None`
+    env_allowlist: Option<Vec<OsString>>,
+    /// Variables to set, or to remove when `None`, in order
+    env: Vec<(OsString, Option<OsString>)>,
+    limits: Limits,
+    kill_on_drop: bool,
+}
+
+/// Resource limits of the server process
+#[derive(Debug, Clone, Copy, Default)]
+struct Limits {
+    nice: Option<i32>,
+    max_memory: Option<u64>,
+    max_cpu_time: Option<Duration>,
+    max_open_files: Option<u64>,
+    max_processes: Option<u64>,
+}
+
+impl Default for SpawnOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpawnOptions {
+    /// Spawn with the client's working directory and environment, without limits
+    pub fn new() -> Self {
+        Self {
+            current_dir: None,
+            env_allowlist: None,
+            env: Vec::new(),
+            limits: Limits::default(),
+            kill_on_drop: true,
+        }
+    }
+
+    /// Run the server in `dir`
+    pub fn with_current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    /// Only pass on the client's environment variables named in `names`
+    ///
+    /// Variables set with [`SpawnOptions::with_env`] are passed on as well.
+    /// Without `PATH` the server cannot find programs it runs by name.
+    pub fn with_env_allowlist<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.env_allowlist = Some(
+            names
+                .into_iter()
+                .map(|name| name.as_ref().to_os_string())
+                .collect(),
+        );
+        self
+    }
+
+    /// Set the environment variable `key` for the server
+    pub fn with_env(mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> Self {
+        self.env.push((
+            key.as_ref().to_os_string(),
+            Some(value.as_ref().to_os_string()),
+        ));
+        self
+    }
+
+    /// Do not pass the environment variable `key` on to the server
+    pub fn without_env(mut self, key: impl AsRef<OsStr>) -> Self {
+        self.env.push((key.as_ref().to_os_string(), None));
+        self
+    }
+
+    /// Run the server at a lower (positive) or higher (negative) scheduling priority
+    ///
+    /// Takes a Unix niceness, from -20 to 19; raising the priority usually
+    /// needs privileges. On Windows it picks the nearest priority class.
+    pub fn with_nice(mut self, nice: i32) -> Self {
+        self.limits.nice = Some(nice.clamp(-20, 19));
+        self
+    }
+
+    /// Limit the memory the server may allocate, in bytes
+    ///
+    /// The address space on Unix, the committed memory on Windows.
+    pub fn with_max_memory(mut self, bytes: u64) -> Self {
+        self.limits.max_memory = Some(bytes);
+        self
+    }
+
+    /// Limit the CPU time the server may use, rounded up to whole seconds on Unix
+    pub fn with_max_cpu_time(mut self, time: Duration) -> Self {
+        self.limits.max_cpu_time = Some(time);
+        self
+    }
+
+    /// Limit the files the server may have open at once, on Unix only
+    pub fn with_max_open_files(mut self, files: u64) -> Self {
+        self.limits.max_open_files = Some(files);
+        self
+    }
+
+    /// Limit the processes the server may run
+    ///
+    /// On Unix the limit counts all processes of the user, as `RLIMIT_NPROC`
+    /// does; on Windows, the processes in the server's job object.
+    pub fn with_max_processes(mut self, processes: u64) -> Self {
+        self.limits.max_processes = Some(processes);
+        self
+    }
+
+    /// Whether dropping the last clone of the transport kills the server, which it does by default
+    ///
+    /// On Linux the server is also killed if the client process dies, and on
+    /// Windows along with the processes it started.
+    pub fn with_kill_on_drop(mut self, kill_on_drop: bool) -> Self {
+        self.kill_on_drop = kill_on_drop;
+        self
+    }
+
+    /// Apply the options to `command`, before it is spawned
+    pub(crate) fn configure(&self, command: &mut Command) {
+        if let Some(dir) = &self.current_dir {
+            command.current_dir(dir);
+        }
+        if let Some(allowlist) = &self.env_allowlist {
+            command.env_clear();
+            for name in allowlist {
+                if let Some(value) = std::env::var_os(name) {
+                    command.env(name, value);
+                }
+            }
+        }
+        for (key, value) in &self.env {
+            match value {
+                Some(value) => command.env(key, value),
+                None => command.env_remove(key),
+            };
+        }
+        command.kill_on_drop(self.kill_on_drop);
+
+        #[cfg(unix)]
+        {
+            let limits = self.limits;
+            let kill_on_drop = self.kill_on_drop;
+            // SAFETY: the closure only makes async-signal-safe system calls
+            unsafe {
+                command.pre_exec(move || unix::apply(&limits, kill_on_drop));
+            }
+        }
+    }
+
+    /// Apply the options that need the process, once it is spawned
+    ///
+    /// The returned guard must live as long as the process: on Windows it
+    /// holds the job object.
+    pub(crate) fn attach(&self, child: &Child) -> Result<ProcessGuard, MCPError> {
+        #[cfg(windows)]
+        {
+            let job = windows::JobObject::new(&self.limits, self.kill_on_drop)?;
+            if let Some(handle) = child.raw_handle() {
+                job.assign(handle)?;
+            }
+            Ok(ProcessGuard { _job: Some(job) })
+        }
+        #[cfg(not(windows))]
+        {
+            let _ = child;
+            Ok(ProcessGuard {})
+        }
+    }
+}
+
+/// Keeps what limits a spawned server alive along with it
+#[derive(Debug)]
+pub(crate) struct ProcessGuard {
+    #[cfg(windows)]
+    _job: Option<windows::JobObject>,
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::Limits;
+    use std::io;
+
+    /// Set the limits of the calling process, between fork and exec
+    pub(super) fn apply(limits: &Limits, kill_on_drop: bool) -> io::Result<()> {
+        let check = |result: libc::c_int| match result {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        };
+        let set_limit = |resource, value: u64| {
+            let limit = libc::rlimit {
+                rlim_cur: value as libc::rlim_t,
+                rlim_max: value as libc::rlim_t,
+            };
+            // SAFETY: `limit` is a valid rlimit
+            check(unsafe { libc::setrlimit(resource, &limit) })
+        };
+
+        if let Some(bytes) = limits.max_memory {
+            set_limit(libc::RLIMIT_AS, bytes)?;
+        }
+        if let Some(time) = limits.max_cpu_time {
+            let seconds = time.as_secs() + u64::from(time.subsec_nanos() > 0);
+            set_limit(libc::RLIMIT_CPU, seconds.max(1))?;
+        }
+        if let Some(files) = limits.max_open_files {
+            set_limit(libc::RLIMIT_NOFILE, files)?;
+        }
+        if let Some(processes) = limits.max_processes {
+            set_limit(libc::RLIMIT_NPROC, processes)?;
+        }
+        if let Some(nice) = limits.nice {
+            // SAFETY: plain system call on the calling process
+            check(unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) })?;
+        }
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if kill_on_drop {
+            // SAFETY: plain system call on the calling process
+            check(unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) })?;
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        let _ = kill_on_drop;
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    //! Just the job object API, declared here rather than pulling in a
+    //! Windows bindings crate
+    #![allow(non_snake_case, non_camel_case_types)]
+
+    use super::Limits;
+    use crate::error::MCPError;
+    use std::ffi::c_void;
+    use std::os::windows::io::RawHandle;
+
+    type HANDLE = *mut c_void;
+    type BOOL = i32;
+
+    const JOB_OBJECT_EXTENDED_LIMIT_INFORMATION_CLASS: i32 = 9;
+    const JOB_OBJECT_LIMIT_PROCESS_TIME: u32 = 0x0000_0002;
+    const JOB_OBJECT_LIMIT_ACTIVE_PROCESS: u32 = 0x0000_0008;
+    const JOB_OBJECT_LIMIT_PRIORITY_CLASS: u32 = 0x0000_0020;
+    const JOB_OBJECT_LIMIT_PROCESS_MEMORY: u32 = 0x0000_0100;
+    const JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE: u32 = 0x0000_2000;
+
+    const IDLE_PRIORITY_CLASS: u32 = 0x0000_0040;
+    const BELOW_NORMAL_PRIORITY_CLASS: u32 = 0x0000_4000;
+    const NORMAL_PRIORITY_CLASS: u32 = 0x0000_0020;
+    const ABOVE_NORMAL_PRIORITY_CLASS: u32 = 0x0000_8000;
+    const HIGH_PRIORITY_CLASS: u32 = 0x0000_0080;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct JOBOBJECT_BASIC_LIMIT_INFORMATION {
+        PerProcessUserTimeLimit: i64,
+        PerJobUserTimeLimit: i64,
+        LimitFlags: u32,
+        MinimumWorkingSetSize: usize,
+        MaximumWorkingSetSize: usize,
+        ActiveProcessLimit: u32,
+        Affinity: usize,
+        PriorityClass: u32,
+        SchedulingClass: u32,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct IO_COUNTERS {
+        ReadOperationCount: u64,
+        WriteOperationCount: u64,
+        OtherOperationCount: u64,
+        ReadTransferCount: u64,
+        WriteTransferCount: u64,
+        OtherTransferCount: u64,
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct JOBOBJECT_EXTENDED_LIMIT_INFORMATION {
+        BasicLimitInformation: JOBOBJECT_BASIC_LIMIT_INFORMATION,
+        IoInfo: IO_COUNTERS,
+        ProcessMemoryLimit: usize,
+        JobMemoryLimit: usize,
+        PeakProcessMemoryUsed: usize,
+        PeakJobMemoryUsed: usize,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn CreateJobObjectW(attributes: *mut c_void, name: *const u16) -> HANDLE;
+        fn SetInformationJobObject(
+            job: HANDLE,
+            class: i32,
+            information: *mut c_void,
+            length: u32,
+        ) -> BOOL;
+        fn AssignProcessToJobObject(job: HANDLE, process: HANDLE) -> BOOL;
+        fn CloseHandle(handle: HANDLE) -> BOOL;
+    }
+
+    fn last_error(action: &str) -> MCPError {
+        MCPError::Transport(format!(
+            "Failed to {}: {}",
+            action,
+            std::io::Error::last_os_error()
+        ))
+    }
+
+    /// A job object holding the server process
+    ///
+    /// With kill-on-drop, closing its handle kills the processes in it.
+    #[derive(Debug)]
+    pub(super) struct JobObject(HANDLE);
+
+    // SAFETY: job object handles may be used and closed from any thread
+    unsafe impl Send for JobObject {}
+    unsafe impl Sync for JobObject {}
+
+    impl JobObject {
+        pub(super) fn new(limits: &Limits, kill_on_drop: bool) -> Result<Self, MCPError> {
+            // SAFETY: an anonymous job object with default security
+            let handle = unsafe { CreateJobObjectW(std::ptr::null_mut(), std::ptr::null()) };
+            if handle.is_null() {
+                return Err(last_error("create a job object"));
+            }
+            let job = Self(handle);
+
+            let mut info = JOBOBJECT_EXTENDED_LIMIT_INFORMATION::default();
+            if let Some(bytes) = limits.max_memory {
+                info.BasicLimitInformation.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_MEMORY;
+                info.ProcessMemoryLimit = usize::try_from(bytes).unwrap_or(usize::MAX);
+            }
+            let basic = &mut info.BasicLimitInformation;
+            if kill_on_drop {
+                basic.LimitFlags |= JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+            }
+            if let Some(time) = limits.max_cpu_time {
+                // In units of 100 nanoseconds
+                basic.LimitFlags |= JOB_OBJECT_LIMIT_PROCESS_TIME;
+                basic.PerProcessUserTimeLimit =
+                    i64::try_from(time.as_nanos() / 100).unwrap_or(i64::MAX);
+            }
+            if let Some(processes) = limits.max_processes {
+                basic.LimitFlags |= JOB_OBJECT_LIMIT_ACTIVE_PROCESS;
+                basic.ActiveProcessLimit = u32::try_from(processes).unwrap_or(u32::MAX);
+            }
+            if let Some(nice) = limits.nice {
+                basic.LimitFlags |= JOB_OBJECT_LIMIT_PRIORITY_CLASS;
+                basic.PriorityClass = match nice {
+                    15.. => IDLE_PRIORITY_CLASS,
+                    1..=14 => BELOW_NORMAL_PRIORITY_CLASS,
+                    0 => NORMAL_PRIORITY_CLASS,
+                    -10..=-1 => ABOVE_NORMAL_PRIORITY_CLASS,
+                    _ => HIGH_PRIORITY_CLASS,
+                };
+            }
+
+            // SAFETY: `info` is the structure of the information class, of the given size
+            let set = unsafe {
+                SetInformationJobObject(
+                    job.0,
+                    JOB_OBJECT_EXTENDED_LIMIT_INFORMATION_CLASS,
+                    &mut info as *mut _ as *mut c_void,
+                    std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+                )
+            };
+            if set == 0 {
+                return Err(last_error("set the job object limits"));
+            }
+            Ok(job)
+        }
+
+        /// Put the process in the job, and so under its limits
+        pub(super) fn assign(&self, process: RawHandle) -> Result<(), MCPError> {
+            // SAFETY: both handles are open
+            if unsafe { AssignProcessToJobObject(self.0, process as HANDLE) } == 0 {
+                return Err(last_error("assign the server to its job object"));
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for JobObject {
+        fn drop(&mut self) {
+            // SAFETY: the handle is open and owned
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::transport::{stdio::StdioTransport, Transport};
+
+    #[tokio::test]
+    async fn test_spawn_options() {
+        std::env::set_var("MCPR_SPAWN_SECRET", "leaked");
+        let dir = std::env::temp_dir();
+        let options = SpawnOptions::new()
+            .with_current_dir(&dir)
+            .with_env_allowlist(["PATH"])
+            .with_env("MCPR_TOKEN", "t0k3n")
+            .with_max_open_files(64)
+            .with_nice(5);
+        let script = r#"printf '{"cwd":"%s","secret":"%s","token":"%s","files":"%s","nice":"%s"}\n' "$(pwd -P)" "$MCPR_SPAWN_SECRET" "$MCPR_TOKEN" "$(ulimit -n)" "$(nice)""#;
+        let mut transport = StdioTransport::spawn_with("sh", ["-c", script], options).unwrap();
+        transport.start().await.unwrap();
+
+        let report: serde_json::Value = transport.receive().await.unwrap();
+        assert_eq!(
+            report,
+            serde_json::json!({
+                "cwd": dir.canonicalize().unwrap().to_str().unwrap(),
+                "secret": "",
+                "token": "t0k3n",
+                "files": "64",
+                "nice": "5",
+            })
+        );
+
+        let options = SpawnOptions::new().with_current_dir(dir.join("mcpr-missing-dir"));
+        assert!(StdioTransport::spawn_with("true", [] as [&str; 0], options).is_err());
+    }
+}
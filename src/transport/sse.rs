@@ -1,15 +1,25 @@
+use crate::auth::{self, StaticToken, TokenProvider};
 use crate::error::MCPError;
-use crate::transport::{CloseCallback, ErrorCallback, MessageCallback, Transport};
+use crate::redact;
+use crate::trace;
+use crate::transport::http_client::HttpClientSettings;
+use crate::transport::proxy::{ProxyConfig, ProxySetting};
+use crate::transport::{
+    CloseCallback, ErrorCallback, MessageCallback, Traffic, TrafficCounter, Transport,
+};
 use async_trait::async_trait;
+use futures::StreamExt;
 use log::{debug, error, info, warn};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use reqwest::Client;
 use serde::{de::DeserializeOwned, Serialize};
 use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use tiny_http::{Method, Request, Response as HttpResponse, Server};
-use tokio::sync::{mpsc, Mutex as TokioMutex, Notify};
+use tokio::sync::{mpsc, watch, Mutex as TokioMutex, Notify};
 use tokio::time::sleep;
+use url::Url;
 
 /// Client connection information
 struct ClientConnection {
@@ -28,6 +38,7 @@ pub struct SSETransport {
     on_message: Option<MessageCallback>,
     // HTTP client for making requests
     client: Client,
+    http_settings: HttpClientSettings,
     // Queue for incoming messages
     message_queue: Arc<TokioMutex<VecDeque<String>>>,
     // For server mode: active client connections
@@ -42,6 +53,8 @@ pub struct SSETransport {
     stop_signal: Arc<Notify>,
     // Polling task handle
     polling_task: Option<tokio::task::JoinHandle<()>>,
+    // Bytes of the messages sent and received
+    traffic: TrafficCounter,
 }
 
 impl SSETransport {
@@ -56,6 +69,7 @@ impl SSETransport {
             on_error: None,
             on_message: None,
             client: Client::new(),
+            http_settings: HttpClientSettings::new(uri),
             message_queue: Arc::new(TokioMutex::new(VecDeque::new())),
             active_clients: Arc::new(Mutex::new(HashMap::new())),
             client_messages: Arc::new(Mutex::new(HashMap::new())),
@@ -63,6 +77,7 @@ impl SSETransport {
             server: None,
             stop_signal: Arc::new(Notify::new()),
             polling_task: None,
+            traffic: TrafficCounter::default(),
         }
     }
 
@@ -73,6 +88,32 @@ impl SSETransport {
         transport.is_server = true;
         transport
     }
+
+    /// Connect to the server with these TLS settings instead of the system's trust store
+    #[cfg(feature = "rustls")]
+    pub fn with_tls(mut self, tls: &crate::transport::tls::TlsConfig) -> Result<Self, MCPError> {
+        self.http_settings.tls = Some(tls.clone());
+        self.rebuild_http()
+    }
+
+    /// Go through this proxy instead of the one the environment names
+    ///
+    /// Fails for SOCKS5 proxies, which only the WebSocket transport supports.
+    pub fn with_proxy(mut self, proxy: &ProxyConfig) -> Result<Self, MCPError> {
+        self.http_settings.proxy = ProxySetting::Explicit(proxy.clone());
+        self.rebuild_http()
+    }
+
+    /// Connect directly, ignoring the proxy environment variables
+    pub fn without_proxy(mut self) -> Result<Self, MCPError> {
+        self.http_settings.proxy = ProxySetting::Direct;
+        self.rebuild_http()
+    }
+
+    fn rebuild_http(mut self) -> Result<Self, MCPError> {
+        (self.client, self.uri) = self.http_settings.build()?;
+        Ok(self)
+    }
 }
 
 #[async_trait]
@@ -160,31 +201,29 @@ impl Transport for SSETransport {
                     .await;
 
                     // Process the request if we got one
-                    if let Ok(result) = request_result {
-                        if let Ok(Some(request)) = result {
-                            // Extract method and URL from the request
-                            let method = request.method().clone();
-                            let url = request.url().to_string();
-
-                            debug!("Server received {} request for {}", method, url);
-
-                            // Process request in a separate task to not block the main loop
-                            let sender_task = sender_clone.clone();
-                            let active_clients_task = Arc::clone(&active_clients_clone);
-                            let client_messages_task = Arc::clone(&client_messages_clone);
-
-                            tokio::spawn(async move {
-                                process_request(
-                                    request,
-                                    &method,
-                                    &url,
-                                    &sender_task,
-                                    &active_clients_task,
-                                    &client_messages_task,
-                                )
-                                .await;
-                            });
-                        }
+                    if let Ok(Ok(Some(request))) = request_result {
+                        // Extract method and URL from the request
+                        let method = request.method().clone();
+                        let url = request.url().to_string();
+
+                        debug!("Server received {} request for {}", method, url);
+
+                        // Process request in a separate task to not block the main loop
+                        let sender_task = sender_clone.clone();
+                        let active_clients_task = Arc::clone(&active_clients_clone);
+                        let client_messages_task = Arc::clone(&client_messages_clone);
+
+                        tokio::spawn(async move {
+                            process_request(
+                                request,
+                                &method,
+                                &url,
+                                &sender_task,
+                                &active_clients_task,
+                                &client_messages_task,
+                            )
+                            .await;
+                        });
                     }
                 }
                 debug!("Server HTTP handler task exited");
@@ -285,7 +324,10 @@ impl Transport for SSETransport {
                                 match response.text().await {
                                     Ok(text) => {
                                         if !text.is_empty() && text != "no_messages" {
-                                            debug!("Client received message from poll: {}", text);
+                                            debug!(
+                                                "Client received message from poll: {}",
+                                                redact::for_log(&text)
+                                            );
 
                                             // Try to parse as JSON to validate
                                             match serde_json::from_str::<serde_json::Value>(&text) {
@@ -299,7 +341,7 @@ impl Transport for SSETransport {
                                                     // The main thread will handle callbacks when messages are processed
                                                 }
                                                 Err(e) => {
-                                                    error!("Client received invalid JSON from server: {} - {}", e, text);
+                                                    error!("Client received invalid JSON from server: {} - {}", e, redact::for_log(&text));
                                                 }
                                             }
                                         } else {
@@ -338,6 +380,7 @@ impl Transport for SSETransport {
         }
 
         self.is_connected = true;
+        trace::connected("sse");
         info!("SSE transport started successfully");
         Ok(())
     }
@@ -358,7 +401,7 @@ impl Transport for SSETransport {
                 return Err(MCPError::Serialization(e));
             }
         };
-        debug!("Sending message: {}", serialized_message);
+        debug!("Sending message: {}", redact::for_log(&serialized_message));
 
         if self.is_server {
             // Server mode - add the message to the client message queue
@@ -381,6 +424,7 @@ impl Transport for SSETransport {
                     }
                 }
                 debug!("Server successfully added message to client queues");
+                self.traffic.sent(serialized_message.len());
                 Ok(())
             } else {
                 error!("Failed to lock active clients");
@@ -390,7 +434,10 @@ impl Transport for SSETransport {
             }
         } else {
             // Client mode - send a POST request to the server
-            debug!("Client sending message to server: {}", serialized_message);
+            debug!(
+                "Client sending message to server: {}",
+                redact::for_log(&serialized_message)
+            );
 
             match self
                 .client
@@ -403,6 +450,7 @@ impl Transport for SSETransport {
                 Ok(response) => {
                     if response.status().is_success() {
                         debug!("Client successfully sent message to server");
+                        self.traffic.sent(serialized_message.len());
                         Ok(())
                     } else {
                         let error_msg = format!(
@@ -442,7 +490,8 @@ impl Transport for SSETransport {
             };
 
             if let Some(message) = queue_msg {
-                debug!("Received message: {}", message);
+                debug!("Received message: {}", redact::for_log(&message));
+                self.traffic.received(message.len());
                 break message;
             }
 
@@ -485,6 +534,7 @@ impl Transport for SSETransport {
 
         // Set the connection flag
         self.is_connected = false;
+        trace::disconnected("sse");
 
         // Signal the polling task to stop
         self.stop_signal.notify_waiters();
@@ -498,9 +548,8 @@ impl Transport for SSETransport {
 
         // Join the polling task if it exists
         if let Some(task) = self.polling_task.take() {
-            match task.abort() {
-                _ => debug!("Aborted polling task"),
-            }
+            task.abort();
+            debug!("Aborted polling task");
         }
 
         // Call the close callback if set
@@ -529,6 +578,10 @@ impl Transport for SSETransport {
         debug!("Setting on_message callback for SSE transport");
         self.on_message = callback.map(|f| Box::new(f) as Box<dyn Fn(&str) + Send + Sync>);
     }
+
+    fn traffic(&self) -> Option<Traffic> {
+        Some(self.traffic.traffic())
+    }
 }
 
 // Helper function to process HTTP requests
@@ -692,3 +745,506 @@ async fn process_request(
         }
     }
 }
+
+/// How [`SseTransport`] reopens a dropped event stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct SseReconnect {
+    /// Reopen attempts in a row before giving up; 0 disables reconnecting
+    pub max_attempts: u32,
+    /// Delay before each attempt
+    pub delay: Duration,
+}
+
+impl Default for SseReconnect {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            delay: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Time to wait for the server's `endpoint` event after opening the stream
+const SSE_ENDPOINT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Client transport for the MCP HTTP+SSE transport
+///
+/// Unlike [`SSETransport`], which polls an mcpr server, this speaks the
+/// protocol hosted MCP servers implement: the client opens an event stream
+/// with a GET to the base URL, the server names the URL to POST messages to
+/// in an `endpoint` event, and sends its own messages as `message` events.
+///
+/// ```rust,ignore
+/// let transport = SseTransport::new("https://example.com/sse")
+///     .with_header("Authorization", "Bearer token")?;
+/// let mut client = Client::new(transport);
+///
+/// // Or, already connected
+/// let mut client = Client::new(SseTransport::connect("https://example.com/sse").await?);
+/// ```
+///
+/// When the event stream drops, it is reopened according to the
+/// [`SseReconnect`] policy, sending the last event id so the server can
+/// resume. Clones share the connection. A [`TokenProvider`] set with
+/// [`SseTransport::with_token_provider`] authorizes the requests.
+#[derive(Clone)]
+pub struct SseTransport {
+    url: String,
+    headers: HeaderMap,
+    token_provider: Option<Arc<dyn TokenProvider>>,
+    reconnect: SseReconnect,
+    http: Client,
+    http_settings: HttpClientSettings,
+    /// URL to POST messages to, from the server's `endpoint` event
+    endpoint: Arc<watch::Sender<Option<String>>>,
+    /// Message events, or the error that ended the stream
+    incoming: Arc<TokioMutex<mpsc::UnboundedReceiver<Result<String, MCPError>>>>,
+    incoming_tx: mpsc::UnboundedSender<Result<String, MCPError>>,
+    stream_task: Arc<Mutex<Option<tokio::task::JoinHandle<()>>>>,
+    /// Bytes of the messages posted and of the message events read
+    traffic: TrafficCounter,
+}
+
+impl SseTransport {
+    /// Create a transport for the event stream at `url`, without connecting
+    pub fn new(url: &str) -> Self {
+        let (incoming_tx, incoming) = mpsc::unbounded_channel();
+        Self {
+            url: url.to_string(),
+            headers: HeaderMap::new(),
+            token_provider: None,
+            reconnect: SseReconnect::default(),
+            http: Client::new(),
+            http_settings: HttpClientSettings::new(url),
+            endpoint: Arc::new(watch::channel(None).0),
+            incoming: Arc::new(TokioMutex::new(incoming)),
+            incoming_tx,
+            stream_task: Arc::new(Mutex::new(None)),
+            traffic: TrafficCounter::default(),
+        }
+    }
+
+    /// Create a transport and open the event stream
+    pub async fn connect(url: &str) -> Result<Self, MCPError> {
+        let mut transport = Self::new(url);
+        transport.start().await?;
+        Ok(transport)
+    }
+
+    /// Send a header, such as `Authorization`, with every request
+    pub fn with_header(mut self, name: &str, value: &str) -> Result<Self, MCPError> {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| MCPError::Transport(format!("Invalid header name '{}': {}", name, e)))?;
+        let value = HeaderValue::from_str(value).map_err(|e| {
+            MCPError::Transport(format!("Invalid value for header '{}': {}", name, e))
+        })?;
+        self.headers.insert(name, value);
+        Ok(self)
+    }
+
+    /// Authorize requests with the provider's bearer tokens
+    ///
+    /// When the server answers 401, the provider is asked for a new token
+    /// and the request is sent once more.
+    pub fn with_token_provider(mut self, provider: impl TokenProvider) -> Self {
+        self.token_provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// Authorize requests with a fixed bearer token
+    pub fn with_bearer_token(self, token: &str) -> Self {
+        self.with_token_provider(StaticToken::new(token))
+    }
+
+    /// Set how a dropped event stream is reopened
+    pub fn with_reconnect(mut self, reconnect: SseReconnect) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// Connect with these TLS settings instead of the system's trust store
+    #[cfg(feature = "rustls")]
+    pub fn with_tls(mut self, tls: &crate::transport::tls::TlsConfig) -> Result<Self, MCPError> {
+        self.http_settings.tls = Some(tls.clone());
+        self.rebuild_http()
+    }
+
+    /// Go through this proxy instead of the one the environment names
+    ///
+    /// Fails for SOCKS5 proxies, which only the WebSocket transport supports.
+    pub fn with_proxy(mut self, proxy: &ProxyConfig) -> Result<Self, MCPError> {
+        self.http_settings.proxy = ProxySetting::Explicit(proxy.clone());
+        self.rebuild_http()
+    }
+
+    /// Connect directly, ignoring the proxy environment variables
+    pub fn without_proxy(mut self) -> Result<Self, MCPError> {
+        self.http_settings.proxy = ProxySetting::Direct;
+        self.rebuild_http()
+    }
+
+    fn rebuild_http(mut self) -> Result<Self, MCPError> {
+        (self.http, self.url) = self.http_settings.build()?;
+        Ok(self)
+    }
+
+    /// Send the request `build` makes with the configured headers and the
+    /// provider's token, once more with a new token if the server answers 401
+    async fn send_authorized(
+        &self,
+        action: &str,
+        build: impl Fn(HeaderMap) -> reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, MCPError> {
+        let mut retried = false;
+        loop {
+            let mut headers = self.headers.clone();
+            auth::authorize(self.token_provider.as_ref(), &mut headers).await?;
+            let response = build(headers)
+                .send()
+                .await
+                .map_err(|e| MCPError::Transport(format!("Failed to {}: {}", action, e)))?;
+            if response.status() != reqwest::StatusCode::UNAUTHORIZED {
+                return Ok(response);
+            }
+            if retried || !auth::reauthorize(self.token_provider.as_ref()).await? {
+                return Err(MCPError::Unauthorized(format!(
+                    "Failed to {}: HTTP 401",
+                    action
+                )));
+            }
+            retried = true;
+        }
+    }
+
+    /// Open the event stream, resuming after `last_event_id` if given
+    async fn open_stream(
+        &self,
+        last_event_id: Option<&str>,
+    ) -> Result<reqwest::Response, MCPError> {
+        let response = self
+            .send_authorized("open event stream", |headers| {
+                let request = self
+                    .http
+                    .get(&self.url)
+                    .headers(headers)
+                    .header(reqwest::header::ACCEPT, "text/event-stream");
+                match last_event_id {
+                    Some(id) => request.header("Last-Event-ID", id),
+                    None => request,
+                }
+            })
+            .await?;
+        if !response.status().is_success() {
+            return Err(MCPError::Transport(format!(
+                "Failed to open event stream: HTTP {}",
+                response.status()
+            )));
+        }
+        Ok(response)
+    }
+
+    /// Read events from the stream, reopening it as the policy allows
+    async fn read_events(self, mut response: reqwest::Response) {
+        let base = Url::parse(&self.url).ok();
+        let mut last_event_id = None;
+        let mut attempts = 0;
+
+        loop {
+            let mut parser = SseParser::default();
+            let mut stream = response.bytes_stream();
+            while let Some(chunk) = stream.next().await {
+                let chunk = match chunk {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        warn!("SSE stream failed: {}", e);
+                        break;
+                    }
+                };
+                attempts = 0;
+                for event in parser.push(&chunk) {
+                    if event.id.is_some() {
+                        last_event_id = event.id.clone();
+                    }
+                    match event.event.as_str() {
+                        "endpoint" => {
+                            let endpoint = match &base {
+                                Some(base) => base
+                                    .join(&event.data)
+                                    .map(String::from)
+                                    .unwrap_or(event.data),
+                                None => event.data,
+                            };
+                            debug!("SSE endpoint: {}", endpoint);
+                            self.endpoint.send_replace(Some(endpoint));
+                        }
+                        "message" => {
+                            if self.incoming_tx.send(Ok(event.data)).is_err() {
+                                return;
+                            }
+                        }
+                        other => debug!("Ignoring SSE event '{}'", other),
+                    }
+                }
+            }
+
+            response = loop {
+                attempts += 1;
+                if attempts > self.reconnect.max_attempts {
+                    let _ = self
+                        .incoming_tx
+                        .send(Err(MCPError::Transport("SSE stream closed".to_string())));
+                    return;
+                }
+                info!(
+                    "Reopening SSE stream (attempt {}/{})",
+                    attempts, self.reconnect.max_attempts
+                );
+                sleep(self.reconnect.delay).await;
+                match self.open_stream(last_event_id.as_deref()).await {
+                    Ok(response) => break response,
+                    Err(e) => warn!("{}", e),
+                }
+            };
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for SseTransport {
+    async fn start(&mut self) -> Result<(), MCPError> {
+        if self.stream_task.lock().unwrap().is_some() {
+            debug!("SSE transport already connected");
+            return Ok(());
+        }
+
+        info!("Opening SSE stream: {}", self.url);
+        let response = self.open_stream(None).await?;
+        let task = tokio::spawn(self.clone().read_events(response));
+        *self.stream_task.lock().unwrap() = Some(task);
+
+        let mut endpoint = self.endpoint.subscribe();
+        let has_endpoint =
+            tokio::time::timeout(SSE_ENDPOINT_TIMEOUT, endpoint.wait_for(Option::is_some))
+                .await
+                .is_ok_and(|endpoint| endpoint.is_ok());
+        if !has_endpoint {
+            self.close().await?;
+            return Err(MCPError::Transport(format!(
+                "Server sent no endpoint event within {:?}",
+                SSE_ENDPOINT_TIMEOUT
+            )));
+        }
+        trace::connected("sse");
+        Ok(())
+    }
+
+    async fn send<T: Serialize + Send + Sync>(&mut self, message: &T) -> Result<(), MCPError> {
+        let Some(endpoint) = self.endpoint.borrow().clone() else {
+            return Err(MCPError::Transport(
+                "SSE transport not connected".to_string(),
+            ));
+        };
+
+        let body = bytes::Bytes::from(serde_json::to_vec(message)?);
+        let response = self
+            .send_authorized("send message", |headers| {
+                self.http
+                    .post(&endpoint)
+                    .headers(headers)
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(body.clone())
+            })
+            .await?;
+        if !response.status().is_success() {
+            return Err(MCPError::Transport(format!(
+                "Failed to send message: HTTP {}",
+                response.status()
+            )));
+        }
+        self.traffic.sent(body.len());
+        Ok(())
+    }
+
+    async fn receive<T: DeserializeOwned + Send + Sync>(&mut self) -> Result<T, MCPError> {
+        let message = self.incoming.lock().await.recv().await;
+        match message {
+            Some(Ok(data)) => {
+                self.traffic.received(data.len());
+                serde_json::from_str(&data).map_err(MCPError::Serialization)
+            }
+            Some(Err(e)) => Err(e),
+            None => Err(MCPError::Transport("SSE transport closed".to_string())),
+        }
+    }
+
+    async fn close(&mut self) -> Result<(), MCPError> {
+        if let Some(task) = self.stream_task.lock().unwrap().take() {
+            task.abort();
+            trace::disconnected("sse");
+        }
+        self.endpoint.send_replace(None);
+        Ok(())
+    }
+
+    fn set_on_close(&mut self, _callback: Option<CloseCallback>) {}
+
+    fn set_on_error(&mut self, _callback: Option<ErrorCallback>) {}
+
+    fn set_on_message<F>(&mut self, _callback: Option<F>)
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+    }
+
+    fn share_connection(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+
+    fn traffic(&self) -> Option<Traffic> {
+        Some(self.traffic.traffic())
+    }
+}
+
+/// An event read from an event stream
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SseEvent {
+    pub(crate) event: String,
+    pub(crate) data: String,
+    pub(crate) id: Option<String>,
+}
+
+/// Incremental parser for `text/event-stream` bodies
+#[derive(Debug, Default)]
+pub(crate) struct SseParser {
+    buffer: Vec<u8>,
+}
+
+impl SseParser {
+    /// Add a chunk of the body and return the events it completes
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer
+            .extend(chunk.iter().filter(|&&byte| byte != b'\r'));
+
+        let mut events = Vec::new();
+        while let Some(end) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let block: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            let block = String::from_utf8_lossy(&block);
+
+            let mut event = SseEvent {
+                event: "message".to_string(),
+                data: String::new(),
+                id: None,
+            };
+            let mut data = Vec::new();
+            for line in block.lines() {
+                if line.starts_with(':') {
+                    continue;
+                }
+                let (field, value) = line.split_once(':').unwrap_or((line, ""));
+                let value = value.strip_prefix(' ').unwrap_or(value);
+                match field {
+                    "event" => event.event = value.to_string(),
+                    "data" => data.push(value),
+                    "id" => event.id = Some(value.to_string()),
+                    _ => {}
+                }
+            }
+            if data.is_empty() {
+                continue;
+            }
+            event.data = data.join("\n");
+            events.push(event);
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::json_rpc::{JSONRPCMessage, JSONRPCRequest, RequestId};
+    use crate::transport::test_http::read_request;
+    use serde_json::Value;
+    use tokio::io::AsyncWriteExt;
+
+    #[test]
+    fn test_sse_parser() {
+        let mut parser = SseParser::default();
+        assert!(parser
+            .push(b"event: endpoint\r\ndata: /messages")
+            .is_empty());
+
+        let events =
+            parser.push(b"?session=1\r\n\r\n: keepalive\n\nid: 7\ndata: {\"a\":\ndata: 1}\n\n");
+        assert_eq!(
+            events,
+            vec![
+                SseEvent {
+                    event: "endpoint".to_string(),
+                    data: "/messages?session=1".to_string(),
+                    id: None,
+                },
+                SseEvent {
+                    event: "message".to_string(),
+                    data: "{\"a\":\n1}".to_string(),
+                    id: Some("7".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sse_transport() -> Result<(), MCPError> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/sse", listener.local_addr().unwrap());
+
+        let server = tokio::spawn(async move {
+            // The event stream names the endpoint to post to
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let (head, _) = read_request(&mut stream).await;
+            assert!(head.starts_with("GET /sse") && head.contains("x-api-key: secret"));
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\r\nevent: endpoint\ndata: /messages?session=1\n\n")
+                .await
+                .unwrap();
+
+            // A posted request is answered on the event stream
+            let (mut post, _) = listener.accept().await.unwrap();
+            let (head, body) = read_request(&mut post).await;
+            assert!(head.starts_with("POST /messages?session=1"), "{}", head);
+            post.write_all(b"HTTP/1.1 202 Accepted\r\nContent-Length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            let request: Value = serde_json::from_str(&body).unwrap();
+            let response =
+                serde_json::json!({ "jsonrpc": "2.0", "id": request["id"], "result": {} });
+            stream
+                .write_all(format!("event: message\ndata: {}\n\n", response).as_bytes())
+                .await
+                .unwrap();
+            stream
+        });
+
+        let mut transport = SseTransport::new(&url)
+            .with_header("X-Api-Key", "secret")?
+            .with_reconnect(SseReconnect {
+                max_attempts: 0,
+                delay: Duration::ZERO,
+            });
+        transport.start().await?;
+        transport
+            .send(&JSONRPCMessage::Request(JSONRPCRequest::new(
+                RequestId::Number(1),
+                "ping".to_string(),
+                None,
+            )))
+            .await?;
+        let response: JSONRPCMessage = transport.receive().await?;
+        assert!(matches!(response, JSONRPCMessage::Response(r) if r.id == RequestId::Number(1)));
+
+        // Without reconnects, the end of the stream ends the transport
+        drop(server.await.unwrap());
+        assert!(transport.receive::<Value>().await.is_err());
+        Ok(())
+    }
+}
@@ -1,18 +1,115 @@
 use crate::error::MCPError;
-use crate::transport::{CloseCallback, ErrorCallback, MessageCallback, Transport};
+use crate::trace;
+use crate::transport::framed::{
+    BufferWriter, ContentLength, FrameParser, FrameReader, NewlineDelimited,
+};
+use crate::transport::spawn::{ProcessGuard, SpawnOptions};
+use crate::transport::{
+    CloseCallback, ErrorCallback, MessageCallback, Traffic, TrafficCounter, Transport,
+    DEFAULT_MAX_MESSAGE_SIZE, DEFAULT_QUEUE_CAPACITY,
+};
 use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use futures::{stream, Stream, StreamExt};
+use log::{debug, info, warn};
 use serde::{de::DeserializeOwned, Serialize};
+use std::collections::VecDeque;
+use std::ffi::OsStr;
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::sync::mpsc;
+use tokio::process::{Child, Command};
+use tokio::sync::{broadcast, mpsc, Mutex as TokioMutex};
+
+/// Time a spawned server gets to exit after its stdin is closed, before it is killed
+const CHILD_EXIT_GRACE: Duration = Duration::from_secs(2);
+
+/// Lines of a spawned server's stderr kept for listeners that come later
+const STDERR_BACKLOG: usize = 100;
+
+/// Put the process's stdin and stdout in binary mode, on Windows
+///
+/// The C runtime would otherwise translate line ends for code linked into
+/// the process that writes through it. Elsewhere there is nothing to do.
+pub(crate) fn set_binary_mode() {
+    #[cfg(windows)]
+    {
+        extern "C" {
+            fn _setmode(fd: i32, mode: i32) -> i32;
+        }
+        const O_BINARY: i32 = 0x8000;
+        // SAFETY: descriptors 0 and 1 are stdin and stdout, which the C runtime always has
+        unsafe {
+            _setmode(0, O_BINARY);
+            _setmode(1, O_BINARY);
+        }
+    }
+}
+
+/// How messages are delimited on the pipes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StdioFraming {
+    /// One JSON message per line, as the MCP specification defines for stdio
+    #[default]
+    NdJson,
+    /// A `Content-Length` header before each message, as in the Language Server Protocol
+    ContentLength,
+    /// Whichever of the two the other end uses, detected from the first
+    /// message it sends; messages sent before that are newline-delimited
+    AutoDetect,
+}
+
+impl StdioFraming {
+    /// Detect the framing from the start of the input, once it is not blank
+    fn detect(buffer: &[u8]) -> Option<Self> {
+        let first = buffer.iter().find(|b| !b.is_ascii_whitespace())?;
+        Some(match first {
+            b'{' | b'[' => StdioFraming::NdJson,
+            _ => StdioFraming::ContentLength,
+        })
+    }
+}
+
+/// What a spawned server writes to stderr, and where it goes
+struct StderrLines {
+    /// The most recent lines, replayed to new listeners
+    recent: VecDeque<String>,
+    /// Live lines, until the server closes its stderr
+    lines: Option<broadcast::Sender<String>>,
+    /// Target of the log records lines are forwarded as, unless not forwarded
+    log_target: Option<String>,
+}
 
 /// Standard IO transport
 pub struct StdioTransport {
-    reader: BufReader<Box<dyn tokio::io::AsyncRead + Send + Sync + Unpin>>,
-    writer_tx: mpsc::Sender<String>,
+    /// Shared by clones, so they read the same stream
+    reader: Arc<TokioMutex<FrameReader>>,
+    /// Serialized messages, framed by the writer task once the framing is known
+    writer_tx: mpsc::Sender<Bytes>,
+    /// Where messages are serialized; its memory is reused once the writer
+    /// task is done with a message
+    write_buffer: BytesMut,
+    /// Per clone, like the write buffer
+    parser: FrameParser,
+    /// Shared by clones and the writer task, and settled once auto-detected
+    framing: Arc<Mutex<StdioFraming>>,
+    max_message_size: usize,
+    /// Shared by clones, the reader and the writer task
+    traffic: TrafficCounter,
     is_connected: bool,
     on_close: Option<CloseCallback>,
     on_error: Option<ErrorCallback>,
     on_message: Option<MessageCallback>,
+    /// The server process, when the transport spawned it; killed once the
+    /// last clone is dropped
+    child: Option<Arc<TokioMutex<Child>>>,
+    /// Time the spawned server gets to exit on close before it is killed
+    exit_grace: Duration,
+    /// The spawned server's stderr
+    stderr: Option<Arc<Mutex<StderrLines>>>,
+    /// What limits the spawned server, kept alive along with it
+    guard: Option<Arc<ProcessGuard>>,
 }
 
 impl Default for StdioTransport {
@@ -29,17 +126,34 @@ impl StdioTransport {
 
     pub fn with_writer(writer: Box<dyn tokio::io::AsyncWrite + Send + Sync + Unpin>) -> Self {
         // Create a channel for synchronized writing
-        let (writer_tx, mut writer_rx) = mpsc::channel::<String>(32);
+        let (writer_tx, mut writer_rx) = mpsc::channel::<Bytes>(DEFAULT_QUEUE_CAPACITY);
+        let framing = Arc::new(Mutex::new(StdioFraming::default()));
+        let traffic = TrafficCounter::default();
 
         // Spawn a dedicated writer task that processes one message at a time
+        let writer_framing = framing.clone();
+        let writer_traffic = traffic.clone();
         tokio::spawn(async move {
             let mut writer = tokio::io::BufWriter::new(writer);
             while let Some(message) = writer_rx.recv().await {
-                if let Err(e) = writer.write_all(message.as_bytes()).await {
-                    eprintln!("Error writing to stdout: {}", e);
-                }
-                if let Err(e) = writer.write_all(b"\n").await {
-                    eprintln!("Error writing newline to stdout: {}", e);
+                let framing = *writer_framing.lock().unwrap();
+                let written = async {
+                    let mut length = message.len();
+                    if framing == StdioFraming::ContentLength {
+                        let header = format!("Content-Length: {}\r\n\r\n", message.len());
+                        writer.write_all(header.as_bytes()).await?;
+                        length += header.len();
+                    }
+                    writer.write_all(&message).await?;
+                    if framing != StdioFraming::ContentLength {
+                        writer.write_all(b"\n").await?;
+                        length += 1;
+                    }
+                    Ok::<_, std::io::Error>(length)
+                };
+                match written.await {
+                    Ok(length) => writer_traffic.sent(length),
+                    Err(e) => eprintln!("Error writing to stdout: {}", e),
                 }
                 if let Err(e) = writer.flush().await {
                     eprintln!("Error flushing stdout: {}", e);
@@ -48,19 +162,190 @@ impl StdioTransport {
         });
 
         Self {
-            reader: BufReader::new(Box::new(tokio::io::stdin())),
+            reader: Arc::new(TokioMutex::new(FrameReader::new(
+                Box::new(tokio::io::stdin()),
+                traffic.clone(),
+            ))),
             writer_tx,
+            write_buffer: BytesMut::new(),
+            parser: FrameParser::new(),
+            framing,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            traffic,
             is_connected: false,
             on_close: None,
             on_error: None,
             on_message: None,
+            child: None,
+            exit_grace: CHILD_EXIT_GRACE,
+            stderr: None,
+            guard: None,
+        }
+    }
+
+    /// Spawn a server process and talk to it over its stdin and stdout
+    ///
+    /// The server's stderr is forwarded to the log, line by line, see
+    /// [`StdioTransport::with_stderr_log_target`], and can be read with
+    /// [`StdioTransport::stderr`]. There is no need to wait for the server before initializing: messages queue
+    /// in the pipe until it reads them, and [`Transport::start`] fails if
+    /// the process already exited. [`Transport::close`] closes the server's
+    /// stdin, gives it two seconds to exit, or the time set with
+    /// [`StdioTransport::with_exit_grace`], and then kills it; dropping the
+    /// last clone of the transport kills it too.
+    pub fn spawn<I, S>(command: impl AsRef<OsStr>, args: I) -> Result<Self, MCPError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        Self::spawn_with(command, args, SpawnOptions::new())
+    }
+
+    /// Spawn a server process with a working directory, environment and limits of its own
+    ///
+    /// Otherwise the same as [`StdioTransport::spawn`]. Limits the system
+    /// refuses to set fail the spawn.
+    pub fn spawn_with<I, S>(
+        command: impl AsRef<OsStr>,
+        args: I,
+        options: SpawnOptions,
+    ) -> Result<Self, MCPError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let name = command.as_ref().to_string_lossy().to_string();
+        let mut command = Command::new(command);
+        command
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        options.configure(&mut command);
+        let mut child = command
+            .spawn()
+            .map_err(|e| MCPError::Transport(format!("Failed to start '{}': {}", name, e)))?;
+        let guard = options.attach(&child)?;
+        info!("Spawned server '{}' (pid {:?})", name, child.id());
+
+        let missing =
+            |stream: &str| MCPError::Transport(format!("No {} pipe to server '{}'", stream, name));
+        let stdin = child.stdin.take().ok_or_else(|| missing("stdin"))?;
+        let stdout = child.stdout.take().ok_or_else(|| missing("stdout"))?;
+        let stderr = child.stderr.take().ok_or_else(|| missing("stderr"))?;
+
+        let lines = Arc::new(Mutex::new(StderrLines {
+            recent: VecDeque::new(),
+            lines: Some(broadcast::channel(STDERR_BACKLOG).0),
+            log_target: Some(module_path!().to_string()),
+        }));
+        let forwarded = lines.clone();
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = reader.next_line().await {
+                let mut stderr = forwarded.lock().unwrap();
+                if let Some(target) = &stderr.log_target {
+                    info!(target: target, "[{}] {}", name, line);
+                }
+                if stderr.recent.len() == STDERR_BACKLOG {
+                    stderr.recent.pop_front();
+                }
+                stderr.recent.push_back(line.clone());
+                if let Some(lines) = &stderr.lines {
+                    let _ = lines.send(line);
+                }
+            }
+            // Ends the streams of the listeners
+            forwarded.lock().unwrap().lines = None;
+        });
+
+        let mut transport = Self::with_reader_and_writer(Box::new(stdout), Box::new(stdin));
+        transport.child = Some(Arc::new(TokioMutex::new(child)));
+        transport.stderr = Some(lines);
+        transport.guard = Some(Arc::new(guard));
+        Ok(transport)
+    }
+
+    /// Forward the spawned server's stderr to the log under `target`, or not at all with `None`
+    ///
+    /// Lines are logged at info level, prefixed with the command, under this
+    /// module's target by default.
+    pub fn with_stderr_log_target(self, target: Option<&str>) -> Self {
+        if let Some(stderr) = &self.stderr {
+            stderr.lock().unwrap().log_target = target.map(str::to_string);
+        }
+        self
+    }
+
+    /// Lines the spawned server writes to stderr, such as diagnostics to show users
+    ///
+    /// Starts with the last 100 lines written so far, and ends when the server
+    /// closes its stderr. Empty for transports that did not spawn a server.
+    pub fn stderr(&self) -> impl Stream<Item = String> + Send + 'static {
+        let (backlog, live) = match &self.stderr {
+            Some(stderr) => {
+                let stderr = stderr.lock().unwrap();
+                let live = stderr.lines.as_ref().map(broadcast::Sender::subscribe);
+                (stderr.recent.clone(), live)
+            }
+            None => (VecDeque::new(), None),
+        };
+        stream::iter(backlog).chain(stream::unfold(live, |live| async move {
+            let mut live = live?;
+            loop {
+                match live.recv().await {
+                    Ok(line) => return Some((line, Some(live))),
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("Missed {} lines of server stderr", missed)
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        }))
+    }
+
+    /// Delimit messages with `framing` instead of newlines
+    pub fn with_framing(self, framing: StdioFraming) -> Self {
+        *self.framing.lock().unwrap() = framing;
+        self
+    }
+
+    /// The framing in use, which is no longer `AutoDetect` once detected
+    pub fn framing(&self) -> StdioFraming {
+        *self.framing.lock().unwrap()
+    }
+
+    /// Refuse to read or write messages larger than `max_message_size` bytes
+    ///
+    /// Reading a larger message fails with [`MCPError::MessageTooLarge`].
+    /// Unless it arrived in one read, the bytes past the limit are not
+    /// buffered, so the stream is given up and later reads fail too.
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// Time a spawned server gets to exit on close before it is killed
+    pub fn with_exit_grace(mut self, exit_grace: Duration) -> Self {
+        self.exit_grace = exit_grace;
+        self
+    }
+
+    /// Process id of the spawned server, while it runs
+    pub async fn child_id(&self) -> Option<u32> {
+        match &self.child {
+            Some(child) => child.lock().await.id(),
+            None => None,
         }
     }
 
     /// Create a new stdio transport with custom reader and writer
     pub fn with_reader(reader: Box<dyn tokio::io::AsyncRead + Send + Sync + Unpin>) -> Self {
         let mut transport = Self::new();
-        transport.reader = BufReader::new(reader);
+        transport.reader = Arc::new(TokioMutex::new(FrameReader::new(
+            reader,
+            transport.traffic.clone(),
+        )));
         transport
     }
 
@@ -69,10 +354,62 @@ impl StdioTransport {
         writer: Box<dyn tokio::io::AsyncWrite + Send + Sync + Unpin>,
     ) -> Self {
         let mut transport = Self::with_writer(writer);
-        transport.reader = BufReader::new(reader);
+        transport.reader = Arc::new(TokioMutex::new(FrameReader::new(
+            reader,
+            transport.traffic.clone(),
+        )));
         transport
     }
 
+    /// Create a stdio transport over any reader and writer, such as a pipe or tunnel
+    pub fn from_async_rw<R, W>(reader: R, writer: W) -> Self
+    where
+        R: tokio::io::AsyncRead + Send + Sync + Unpin + 'static,
+        W: tokio::io::AsyncWrite + Send + Sync + Unpin + 'static,
+    {
+        Self::with_reader_and_writer(Box::new(reader), Box::new(writer))
+    }
+
+    /// Create a stdio transport over one bidirectional stream
+    ///
+    /// Either end of [`tokio::io::duplex`] connects a client and a server in
+    /// the same process; sockets and SSH channels work the same way.
+    pub fn from_duplex<S>(stream: S) -> Self
+    where
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Send + Sync + Unpin + 'static,
+    {
+        let (reader, writer) = tokio::io::split(stream);
+        Self::from_async_rw(reader, writer)
+    }
+
+    /// Read the next message, detecting the framing first if need be
+    async fn read_frame(&self) -> Result<String, MCPError> {
+        let mut reader = self.reader.lock().await;
+        let mut framing = self.framing();
+        while framing == StdioFraming::AutoDetect {
+            match StdioFraming::detect(&reader.buffer) {
+                Some(detected) => {
+                    debug!("Detected {:?} framing on stdio", detected);
+                    *self.framing.lock().unwrap() = detected;
+                    framing = detected;
+                }
+                None => reader.read_more().await?,
+            }
+        }
+        match framing {
+            StdioFraming::ContentLength => {
+                reader
+                    .read_frame(&ContentLength, self.max_message_size)
+                    .await
+            }
+            _ => {
+                reader
+                    .read_frame(&NewlineDelimited, self.max_message_size)
+                    .await
+            }
+        }
+    }
+
     /// Handle an error by calling the error callback if set
     fn handle_error(&self, error: &MCPError) {
         if let Some(callback) = &self.on_error {
@@ -84,14 +421,23 @@ impl StdioTransport {
 // Implement Clone for StdioTransport
 impl Clone for StdioTransport {
     fn clone(&self) -> Self {
-        // Create a new instance with its own reader but sharing the same writer channel
+        // Clones share the reader and the writer channel
         Self {
-            reader: BufReader::new(Box::new(tokio::io::stdin())),
+            reader: self.reader.clone(),
             writer_tx: self.writer_tx.clone(),
+            write_buffer: BytesMut::new(),
+            parser: FrameParser::new(),
+            framing: self.framing.clone(),
+            max_message_size: self.max_message_size,
+            traffic: self.traffic.clone(),
             is_connected: self.is_connected,
             on_close: None, // Callbacks cannot be cloned, create new ones when needed
             on_error: None,
             on_message: None,
+            child: self.child.clone(),
+            exit_grace: self.exit_grace,
+            stderr: self.stderr.clone(),
+            guard: self.guard.clone(),
         }
     }
 }
@@ -103,7 +449,22 @@ impl Transport for StdioTransport {
             return Ok(());
         }
 
+        if let Some(child) = &self.child {
+            let status = child
+                .lock()
+                .await
+                .try_wait()
+                .map_err(|e| MCPError::Transport(format!("Failed to check server: {}", e)))?;
+            if let Some(status) = status {
+                return Err(MCPError::Transport(format!(
+                    "Server exited before the session started: {}",
+                    status
+                )));
+            }
+        }
+
         self.is_connected = true;
+        trace::connected("stdio");
         Ok(())
     }
 
@@ -114,16 +475,23 @@ impl Transport for StdioTransport {
             return Err(error);
         }
 
-        let json = match serde_json::to_string(message) {
-            Ok(json) => json,
-            Err(e) => {
-                let error = MCPError::Serialization(e);
-                self.handle_error(&error);
-                return Err(error);
-            }
-        };
+        if let Err(e) = serde_json::to_writer(BufferWriter(&mut self.write_buffer), message) {
+            self.write_buffer.clear();
+            let error = MCPError::Serialization(e);
+            self.handle_error(&error);
+            return Err(error);
+        }
+        let json = self.write_buffer.split().freeze();
 
-        // Send via channel to the dedicated writer task
+        if json.len() > self.max_message_size {
+            let error = MCPError::MessageTooLarge {
+                limit: self.max_message_size,
+            };
+            self.handle_error(&error);
+            return Err(error);
+        }
+
+        // Send via channel to the dedicated writer task, waiting while its queue is full
         match self.writer_tx.send(json).await {
             Ok(_) => Ok(()),
             Err(e) => {
@@ -141,14 +509,13 @@ impl Transport for StdioTransport {
             return Err(error);
         }
 
-        let mut line = String::new();
-        match self.reader.read_line(&mut line).await {
-            Ok(_) => {
+        match self.read_frame().await {
+            Ok(line) => {
                 if let Some(callback) = &self.on_message {
                     callback(&line);
                 }
 
-                match serde_json::from_str(&line) {
+                match self.parser.parse(&line) {
                     Ok(parsed) => Ok(parsed),
                     Err(e) => {
                         let error = MCPError::Serialization(e);
@@ -157,8 +524,7 @@ impl Transport for StdioTransport {
                     }
                 }
             }
-            Err(e) => {
-                let error = MCPError::Transport(format!("Failed to read: {}", e));
+            Err(error) => {
                 self.handle_error(&error);
                 Err(error)
             }
@@ -171,6 +537,29 @@ impl Transport for StdioTransport {
         }
 
         self.is_connected = false;
+        trace::disconnected("stdio");
+
+        // Dropping the sender ends the writer task once every clone closed,
+        // which closes the other end's input; a spawned server takes it as
+        // the request to exit
+        let (closed_tx, _) = mpsc::channel(1);
+        self.writer_tx = closed_tx;
+
+        if let Some(child) = &self.child {
+            let mut child = child.lock().await;
+            match tokio::time::timeout(self.exit_grace, child.wait()).await {
+                Ok(Ok(status)) => debug!("Server exited: {}", status),
+                _ => {
+                    warn!(
+                        "Server did not exit within {:?}, killing it",
+                        self.exit_grace
+                    );
+                    child.kill().await.map_err(|e| {
+                        MCPError::Transport(format!("Failed to kill server: {}", e))
+                    })?;
+                }
+            }
+        }
 
         if let Some(callback) = &self.on_close {
             callback();
@@ -193,6 +582,19 @@ impl Transport for StdioTransport {
     {
         self.on_message = callback.map(|f| Box::new(f) as Box<dyn Fn(&str) + Send + Sync>);
     }
+
+    async fn exit_status(&self) -> Option<ExitStatus> {
+        let child = self.child.as_ref()?;
+        child.lock().await.try_wait().ok().flatten()
+    }
+
+    fn share_connection(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+
+    fn traffic(&self) -> Option<Traffic> {
+        Some(self.traffic.traffic())
+    }
 }
 
 #[cfg(test)]
@@ -201,7 +603,7 @@ mod tests {
     use std::pin::Pin;
     use std::sync::{Arc, Mutex};
     use std::task::{Context, Poll};
-    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+    use tokio::io::{AsyncRead, ReadBuf};
     use tokio::sync::Mutex as TokioMutex;
 
     // Simple implementation of AsyncRead for testing
@@ -249,53 +651,6 @@ mod tests {
         }
     }
 
-    // Simple implementation of AsyncWrite for testing
-    struct MockAsyncWrite {
-        written: Arc<TokioMutex<Vec<String>>>,
-    }
-
-    impl MockAsyncWrite {
-        fn new() -> Self {
-            Self {
-                written: Arc::new(TokioMutex::new(Vec::new())),
-            }
-        }
-
-        async fn get_written(&self) -> Vec<String> {
-            let written = self.written.lock().await;
-            written.clone()
-        }
-    }
-
-    impl AsyncWrite for MockAsyncWrite {
-        fn poll_write(
-            self: Pin<&mut Self>,
-            cx: &mut Context<'_>,
-            buf: &[u8],
-        ) -> Poll<std::io::Result<usize>> {
-            let this = self.get_mut();
-
-            // Use poll_fn to convert TokioMutex::lock() to poll-based API
-            let mut future = Box::pin(async {
-                let mut written = this.written.lock().await;
-                let s = String::from_utf8_lossy(buf).to_string();
-                written.push(s);
-                Ok(buf.len())
-            });
-
-            // Convert the future to a poll
-            futures::future::FutureExt::poll_unpin(&mut future, cx)
-        }
-
-        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
-            Poll::Ready(Ok(()))
-        }
-
-        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
-            Poll::Ready(Ok(()))
-        }
-    }
-
     // Basic test for StdioTransport
     #[tokio::test]
     async fn test_send_receive() {
@@ -437,4 +792,96 @@ mod tests {
         assert_eq!(result2.id, 2);
         assert_eq!(result2.method, "test2");
     }
+
+    #[tokio::test]
+    async fn test_spawn() {
+        let mut transport =
+            StdioTransport::spawn("sh", ["-c", "echo starting >&2; exec cat"]).unwrap();
+        transport.start().await.unwrap();
+        let pid = transport.child_id().await;
+        assert!(pid.is_some());
+
+        let message = serde_json::json!({"jsonrpc": "2.0", "method": "ping", "id": 1});
+        transport.send(&message).await.unwrap();
+        let echoed: serde_json::Value = transport.receive().await.unwrap();
+        assert_eq!(echoed, message);
+
+        // cat exits once its stdin closes, so close does not need to kill it
+        transport.close().await.unwrap();
+        assert_eq!(transport.child_id().await, None);
+        assert!(transport.send(&message).await.is_err());
+
+        let mut exited = StdioTransport::spawn("sh", ["-c", "exit 3"]).unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(exited.start().await.is_err());
+        let status = exited.exit_status().await;
+        assert_eq!(status.and_then(|status| status.code()), Some(3));
+        assert_eq!(StdioTransport::new().exit_status().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_from_duplex() {
+        let (client_end, server_end) = tokio::io::duplex(4096);
+        let mut client = StdioTransport::from_duplex(client_end);
+        let mut server = StdioTransport::from_duplex(server_end);
+        client.start().await.unwrap();
+        server.start().await.unwrap();
+
+        let message = serde_json::json!({"jsonrpc": "2.0", "method": "ping", "id": 1});
+        client.send(&message).await.unwrap();
+        let received: serde_json::Value = server.receive().await.unwrap();
+        assert_eq!(received, message);
+        server.send(&message).await.unwrap();
+        let received: serde_json::Value = client.receive().await.unwrap();
+        assert_eq!(received, message);
+    }
+
+    #[tokio::test]
+    async fn test_stderr() {
+        let mut transport =
+            StdioTransport::spawn("sh", ["-c", "echo one >&2; sleep 0.2; echo two >&2"])
+                .unwrap()
+                .with_stderr_log_target(None);
+        transport.start().await.unwrap();
+
+        // Lines written before listening are replayed
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let lines: Vec<String> = transport.stderr().collect().await;
+        assert_eq!(lines, ["one", "two"]);
+        assert_eq!(StdioTransport::new().stderr().count().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_framing() {
+        let message = serde_json::json!({"jsonrpc": "2.0", "method": "ping", "id": 1});
+        let body = message.to_string();
+
+        for (input, detected) in [
+            (format!("{}\n", body), StdioFraming::NdJson),
+            (
+                format!("Content-Length: {}\r\n\r\n{}", body.len(), body),
+                StdioFraming::ContentLength,
+            ),
+        ] {
+            let (reader, mut input_end) = tokio::io::duplex(1024);
+            let (writer, mut output_end) = tokio::io::duplex(1024);
+            let mut transport =
+                StdioTransport::with_reader_and_writer(Box::new(reader), Box::new(writer))
+                    .with_framing(StdioFraming::AutoDetect);
+            transport.start().await.unwrap();
+
+            input_end.write_all(input.as_bytes()).await.unwrap();
+            let received: serde_json::Value = transport.receive().await.unwrap();
+            assert_eq!(received, message);
+            assert_eq!(transport.framing(), detected);
+
+            // Replies use the detected framing
+            transport.send(&message).await.unwrap();
+            let mut output = vec![0; input.len()];
+            tokio::io::AsyncReadExt::read_exact(&mut output_end, &mut output)
+                .await
+                .unwrap();
+            assert_eq!(String::from_utf8(output).unwrap(), input);
+        }
+    }
 }
@@ -0,0 +1,626 @@
+//! Client side of the Streamable HTTP transport
+//!
+//! Each message is POSTed to the server's MCP endpoint. The server answers a
+//! request with either a JSON body or an event stream that carries the
+//! response, possibly preceded by requests and notifications of its own.
+//! A session id the server returns in the `Mcp-Session-Id` header is sent
+//! with every later request, and event streams that drop are resumed from
+//! the last event id. A [`TokenProvider`] set with
+//! [`StreamableHttpTransport::with_token_provider`] authorizes the requests.
+//! With the `compression` feature, responses are decompressed as they
+//! arrive, and large request bodies are compressed once a
+//! [`Compression`](crate::transport::compression::Compression) is set.
+//!
+//! ```rust,ignore
+//! let transport = StreamableHttpTransport::new("https://example.com/mcp")
+//!     .with_header("Authorization", "Bearer token")?;
+//! let mut client = Client::new(transport);
+//! client.initialize().await?;
+//! ```
+
+use crate::auth::{self, StaticToken, TokenProvider};
+use crate::error::MCPError;
+use crate::trace;
+use crate::transport::http_client::HttpClientSettings;
+use crate::transport::proxy::{ProxyConfig, ProxySetting};
+use crate::transport::sse::SseParser;
+use crate::transport::{CloseCallback, ErrorCallback, Traffic, TrafficCounter, Transport};
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::StreamExt;
+use log::{debug, info, warn};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, ACCEPT, CONTENT_ENCODING, CONTENT_TYPE};
+use reqwest::{Client, Response, StatusCode};
+use serde::{de::DeserializeOwned, Serialize};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex as TokioMutex};
+
+/// Header carrying the session id
+pub const SESSION_ID_HEADER: &str = "Mcp-Session-Id";
+
+/// Client transport for the Streamable HTTP transport of the 2025-03-26 specification
+///
+/// Clones share the session and the incoming messages, so clients made for
+/// concurrent calls work unchanged.
+#[derive(Clone)]
+pub struct StreamableHttpTransport {
+    url: String,
+    headers: HeaderMap,
+    token_provider: Option<Arc<dyn TokenProvider>>,
+    http: Client,
+    http_settings: HttpClientSettings,
+    /// Attempts to resume a dropped event stream before giving up
+    resume_attempts: u32,
+    /// Whether to open a stream for messages the server sends on its own
+    server_stream: bool,
+    #[cfg(feature = "compression")]
+    compression: Option<crate::transport::compression::Compression>,
+    /// Set once the server refused a compressed request body
+    #[cfg(feature = "compression")]
+    compression_refused: Arc<AtomicBool>,
+    session_id: Arc<Mutex<Option<String>>>,
+    /// Messages from the server, or the error that ended a stream
+    incoming: Arc<TokioMutex<mpsc::UnboundedReceiver<Result<String, MCPError>>>>,
+    incoming_tx: mpsc::UnboundedSender<Result<String, MCPError>>,
+    server_stream_opened: Arc<AtomicBool>,
+    streams: Arc<Mutex<Vec<tokio::task::JoinHandle<()>>>>,
+    /// Bytes of the request bodies posted and of the messages read
+    traffic: TrafficCounter,
+}
+
+impl StreamableHttpTransport {
+    /// Create a transport for the MCP endpoint at `url`
+    pub fn new(url: &str) -> Self {
+        let (incoming_tx, incoming) = mpsc::unbounded_channel();
+        Self {
+            url: url.to_string(),
+            headers: HeaderMap::new(),
+            token_provider: None,
+            http: Client::new(),
+            http_settings: HttpClientSettings::new(url),
+            resume_attempts: 3,
+            server_stream: true,
+            #[cfg(feature = "compression")]
+            compression: None,
+            #[cfg(feature = "compression")]
+            compression_refused: Arc::new(AtomicBool::new(false)),
+            session_id: Arc::new(Mutex::new(None)),
+            incoming: Arc::new(TokioMutex::new(incoming)),
+            incoming_tx,
+            server_stream_opened: Arc::new(AtomicBool::new(false)),
+            streams: Arc::new(Mutex::new(Vec::new())),
+            traffic: TrafficCounter::default(),
+        }
+    }
+
+    /// Send a header, such as `Authorization`, with every request
+    pub fn with_header(mut self, name: &str, value: &str) -> Result<Self, MCPError> {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| MCPError::Transport(format!("Invalid header name '{}': {}", name, e)))?;
+        let value = HeaderValue::from_str(value).map_err(|e| {
+            MCPError::Transport(format!("Invalid value for header '{}': {}", name, e))
+        })?;
+        self.headers.insert(name, value);
+        Ok(self)
+    }
+
+    /// Authorize requests with the provider's bearer tokens
+    ///
+    /// When the server answers 401, the provider is asked for a new token
+    /// and the request is sent once more.
+    pub fn with_token_provider(mut self, provider: impl TokenProvider) -> Self {
+        self.token_provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// Authorize requests with a fixed bearer token
+    pub fn with_bearer_token(self, token: &str) -> Self {
+        self.with_token_provider(StaticToken::new(token))
+    }
+
+    /// Set how many times a dropped event stream is resumed; 0 disables resumption
+    pub fn with_resume_attempts(mut self, attempts: u32) -> Self {
+        self.resume_attempts = attempts;
+        self
+    }
+
+    /// Whether to open a GET stream for requests and notifications the
+    /// server sends outside of a response; on by default
+    pub fn with_server_stream(mut self, enabled: bool) -> Self {
+        self.server_stream = enabled;
+        self
+    }
+
+    /// Compress request bodies as large as the threshold of `compression`
+    ///
+    /// Once the server answers a compressed request with `415 Unsupported
+    /// Media Type`, the request is sent again, and later ones too, as they are.
+    #[cfg(feature = "compression")]
+    pub fn with_compression(
+        mut self,
+        compression: crate::transport::compression::Compression,
+    ) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Connect with these TLS settings instead of the system's trust store
+    #[cfg(feature = "rustls")]
+    pub fn with_tls(mut self, tls: &crate::transport::tls::TlsConfig) -> Result<Self, MCPError> {
+        self.http_settings.tls = Some(tls.clone());
+        self.rebuild_http()
+    }
+
+    /// Go through this proxy instead of the one the environment names
+    ///
+    /// Fails for SOCKS5 proxies, which only the WebSocket transport supports.
+    pub fn with_proxy(mut self, proxy: &ProxyConfig) -> Result<Self, MCPError> {
+        self.http_settings.proxy = ProxySetting::Explicit(proxy.clone());
+        self.rebuild_http()
+    }
+
+    /// Connect directly, ignoring the proxy environment variables
+    pub fn without_proxy(mut self) -> Result<Self, MCPError> {
+        self.http_settings.proxy = ProxySetting::Direct;
+        self.rebuild_http()
+    }
+
+    fn rebuild_http(mut self) -> Result<Self, MCPError> {
+        (self.http, self.url) = self.http_settings.build()?;
+        Ok(self)
+    }
+
+    /// The session id the server assigned, if any
+    pub fn session_id(&self) -> Option<String> {
+        self.session_id.lock().unwrap().clone()
+    }
+
+    /// The configured headers, plus the session id once there is one and
+    /// the token of the provider
+    async fn request_headers(&self) -> Result<HeaderMap, MCPError> {
+        let mut headers = self.headers.clone();
+        if let Some(session_id) = self.session_id() {
+            if let Ok(value) = HeaderValue::from_str(&session_id) {
+                headers.insert(SESSION_ID_HEADER, value);
+            }
+        }
+        auth::authorize(self.token_provider.as_ref(), &mut headers).await?;
+        Ok(headers)
+    }
+
+    /// Send the request `build` makes with the request headers, once more
+    /// with a new token if the server answers 401
+    async fn send_authorized(
+        &self,
+        action: &str,
+        build: impl Fn(HeaderMap) -> reqwest::RequestBuilder,
+    ) -> Result<Response, MCPError> {
+        let mut retried = false;
+        loop {
+            let response = build(self.request_headers().await?)
+                .send()
+                .await
+                .map_err(|e| MCPError::Transport(format!("Failed to {}: {}", action, e)))?;
+            if response.status() != StatusCode::UNAUTHORIZED {
+                return Ok(response);
+            }
+            if retried || !auth::reauthorize(self.token_provider.as_ref()).await? {
+                return Err(MCPError::Unauthorized(format!(
+                    "Failed to {}: HTTP 401",
+                    action
+                )));
+            }
+            retried = true;
+        }
+    }
+
+    /// POST a message, compressed if it is large enough and the server has
+    /// not refused compressed bodies
+    async fn post_message(&self, body: Bytes) -> Result<Response, MCPError> {
+        #[cfg(feature = "compression")]
+        if let Some(compression) = self
+            .compression
+            .filter(|_| !self.compression_refused.load(Ordering::SeqCst))
+        {
+            if let Some(compressed) = compression.apply(&body)? {
+                let encoding = compression.encoding().name();
+                let response = self.post(compressed.into(), Some(encoding)).await?;
+                if response.status() != StatusCode::UNSUPPORTED_MEDIA_TYPE {
+                    return Ok(response);
+                }
+                debug!(
+                    "Server refused a {} request body; sending them uncompressed",
+                    encoding
+                );
+                self.compression_refused.store(true, Ordering::SeqCst);
+            }
+        }
+        self.post(body, None).await
+    }
+
+    /// POST a JSON body, with the `Content-Encoding` it is compressed with
+    async fn post(&self, body: Bytes, encoding: Option<&str>) -> Result<Response, MCPError> {
+        let length = body.len();
+        let response = self
+            .send_authorized("send message", |headers| {
+                let request = self
+                    .http
+                    .post(&self.url)
+                    .headers(headers)
+                    .header(ACCEPT, "application/json, text/event-stream")
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(body.clone());
+                match encoding {
+                    Some(encoding) => request.header(CONTENT_ENCODING, encoding),
+                    None => request,
+                }
+            })
+            .await?;
+        self.traffic.sent(length);
+        Ok(response)
+    }
+
+    /// Open a GET event stream, resuming after `last_event_id` if given
+    ///
+    /// Returns `None` when the server does not offer one.
+    async fn open_stream(&self, last_event_id: Option<&str>) -> Result<Option<Response>, MCPError> {
+        let response = self
+            .send_authorized("open event stream", |headers| {
+                let request = self
+                    .http
+                    .get(&self.url)
+                    .headers(headers)
+                    .header(ACCEPT, "text/event-stream");
+                match last_event_id {
+                    Some(id) => request.header("Last-Event-ID", id),
+                    None => request,
+                }
+            })
+            .await?;
+        match response.status() {
+            StatusCode::METHOD_NOT_ALLOWED => Ok(None),
+            status if status.is_success() => Ok(Some(response)),
+            status => Err(MCPError::Transport(format!(
+                "Failed to open event stream: HTTP {}",
+                status
+            ))),
+        }
+    }
+
+    /// Read an event stream in the background
+    ///
+    /// A stream carrying a response ends once the server has sent it; a
+    /// stream that fails first is resumed with a GET from the last event id.
+    fn spawn_stream(&self, response: Response) {
+        let transport = self.clone();
+        let task = tokio::spawn(async move {
+            let mut response = response;
+            let mut last_event_id = None;
+            let mut attempts = 0;
+
+            loop {
+                let mut parser = SseParser::default();
+                let mut stream = response.bytes_stream();
+                let mut failed = false;
+                while let Some(chunk) = stream.next().await {
+                    let chunk = match chunk {
+                        Ok(chunk) => chunk,
+                        Err(e) => {
+                            warn!("Event stream failed: {}", e);
+                            failed = true;
+                            break;
+                        }
+                    };
+                    attempts = 0;
+                    for event in parser.push(&chunk) {
+                        if event.id.is_some() {
+                            last_event_id = event.id;
+                        }
+                        if event.event == "message"
+                            && transport.incoming_tx.send(Ok(event.data)).is_err()
+                        {
+                            return;
+                        }
+                    }
+                }
+
+                // Without an event id there is nothing to resume from
+                if !failed || last_event_id.is_none() {
+                    debug!("Event stream ended");
+                    return;
+                }
+                response = loop {
+                    attempts += 1;
+                    if attempts > transport.resume_attempts {
+                        let _ = transport.incoming_tx.send(Err(MCPError::Transport(
+                            "Event stream dropped and could not be resumed".to_string(),
+                        )));
+                        return;
+                    }
+                    info!(
+                        "Resuming event stream after event {:?} (attempt {}/{})",
+                        last_event_id, attempts, transport.resume_attempts
+                    );
+                    tokio::time::sleep(Duration::from_millis(500) * attempts).await;
+                    match transport.open_stream(last_event_id.as_deref()).await {
+                        Ok(Some(response)) => break response,
+                        Ok(None) => return,
+                        Err(e) => warn!("{}", e),
+                    }
+                };
+            }
+        });
+
+        let mut streams = self.streams.lock().unwrap();
+        streams.retain(|stream| !stream.is_finished());
+        streams.push(task);
+    }
+
+    /// Open the stream for server-initiated messages, once
+    async fn open_server_stream(&self) {
+        if !self.server_stream || self.server_stream_opened.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        match self.open_stream(None).await {
+            Ok(Some(response)) => self.spawn_stream(response),
+            Ok(None) => debug!("Server offers no stream for its own messages"),
+            Err(e) => warn!("{}", e),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for StreamableHttpTransport {
+    async fn start(&mut self) -> Result<(), MCPError> {
+        // Each message is its own HTTP request, so there is nothing to open
+        // until the server has answered the first one
+        trace::connected("streamable-http");
+        Ok(())
+    }
+
+    async fn send<T: Serialize + Send + Sync>(&mut self, message: &T) -> Result<(), MCPError> {
+        let had_session = self.session_id().is_some();
+        let body = Bytes::from(serde_json::to_vec(message)?);
+        let response = self.post_message(body).await?;
+
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND && had_session {
+            // The session expired; a new one starts with the next initialize
+            *self.session_id.lock().unwrap() = None;
+            self.server_stream_opened.store(false, Ordering::SeqCst);
+            return Err(MCPError::Transport("Session expired".to_string()));
+        }
+        if !status.is_success() {
+            return Err(MCPError::Transport(format!(
+                "Failed to send message: HTTP {}",
+                status
+            )));
+        }
+
+        if let Some(session_id) = response
+            .headers()
+            .get(SESSION_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+        {
+            *self.session_id.lock().unwrap() = Some(session_id.to_string());
+        }
+
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        if status != StatusCode::ACCEPTED {
+            if content_type.starts_with("text/event-stream") {
+                self.spawn_stream(response);
+            } else if content_type.starts_with("application/json") {
+                let body = response
+                    .text()
+                    .await
+                    .map_err(|e| MCPError::Transport(format!("Failed to read response: {}", e)))?;
+                let _ = self.incoming_tx.send(Ok(body));
+            }
+        }
+
+        self.open_server_stream().await;
+        Ok(())
+    }
+
+    async fn receive<T: DeserializeOwned + Send + Sync>(&mut self) -> Result<T, MCPError> {
+        let message = self.incoming.lock().await.recv().await;
+        match message {
+            Some(Ok(data)) => {
+                self.traffic.received(data.len());
+                serde_json::from_str(&data).map_err(MCPError::Serialization)
+            }
+            Some(Err(e)) => Err(e),
+            None => Err(MCPError::Transport(
+                "Streamable HTTP transport closed".to_string(),
+            )),
+        }
+    }
+
+    async fn close(&mut self) -> Result<(), MCPError> {
+        for stream in self.streams.lock().unwrap().drain(..) {
+            stream.abort();
+        }
+        self.server_stream_opened.store(false, Ordering::SeqCst);
+
+        // Tell the server the session is over; servers may not allow it
+        if self.session_id().is_some() {
+            let result = self
+                .send_authorized("end session", |headers| {
+                    self.http.delete(&self.url).headers(headers)
+                })
+                .await;
+            if let Err(e) = result {
+                debug!("Failed to end session: {}", e);
+            }
+            *self.session_id.lock().unwrap() = None;
+        }
+        trace::disconnected("streamable-http");
+        Ok(())
+    }
+
+    fn set_on_close(&mut self, _callback: Option<CloseCallback>) {}
+
+    fn set_on_error(&mut self, _callback: Option<ErrorCallback>) {}
+
+    fn set_on_message<F>(&mut self, _callback: Option<F>)
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+    }
+
+    fn share_connection(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+
+    fn traffic(&self) -> Option<Traffic> {
+        Some(self.traffic.traffic())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::transport::test_http::read_request;
+    use serde_json::Value;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_streamable_http_transport() -> Result<(), MCPError> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+
+        let server = tokio::spawn(async move {
+            let mut heads = Vec::new();
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let (head, body) = read_request(&mut socket).await;
+                heads.push(head.clone());
+                let message: Value = serde_json::from_str(&body).unwrap_or_default();
+                let result = |result: Value| serde_json::json!({ "jsonrpc": "2.0", "id": message["id"], "result": result });
+
+                let reply = if head.starts_with("GET") {
+                    "HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\n\r\n".to_string()
+                } else if head.starts_with("DELETE") {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_string()
+                } else if message["method"] == "initialize" {
+                    // Answered with JSON, starting a session
+                    let body = result(serde_json::json!({
+                        "protocolVersion": "2025-03-26",
+                        "capabilities": {},
+                        "serverInfo": { "name": "test", "version": "1.0" },
+                    }))
+                    .to_string();
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nMcp-Session-Id: s-1\r\nContent-Length: {}\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                } else {
+                    // Answered on an event stream, after a notification
+                    let notification = serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "method": "notifications/message",
+                        "params": { "level": "info", "data": "pinged" },
+                    });
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\nid: 1\ndata: {}\n\nid: 2\ndata: {}\n\n",
+                        notification,
+                        result(serde_json::json!({}))
+                    )
+                };
+                socket.write_all(reply.as_bytes()).await.unwrap();
+                if head.starts_with("DELETE") {
+                    return heads;
+                }
+            }
+        });
+
+        let transport = StreamableHttpTransport::new(&url);
+        let mut client = Client::new(transport.clone());
+        client.initialize().await?;
+        assert_eq!(transport.session_id().as_deref(), Some("s-1"));
+        client.ping().await?;
+        client.shutdown().await.ok();
+        let mut transport = transport;
+        transport.close().await?;
+
+        // Every request after initialize carries the session id
+        let heads = server.await.unwrap();
+        assert!(!heads[0].to_lowercase().contains("mcp-session-id"));
+        assert!(heads[1..]
+            .iter()
+            .all(|head| head.to_lowercase().contains("mcp-session-id: s-1")));
+        assert!(heads.last().unwrap().starts_with("DELETE"));
+        Ok(())
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn test_compression() -> Result<(), MCPError> {
+        use crate::transport::compression::{Compression, Encoding};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+
+        let server = tokio::spawn(async move {
+            let mut heads = Vec::new();
+            for _ in 0..3 {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let (head, _) = read_request(&mut socket).await;
+                heads.push(head.to_lowercase());
+                let reply = if heads.last().unwrap().contains("content-encoding: gzip") {
+                    b"HTTP/1.1 415 Unsupported Media Type\r\nConnection: close\r\nContent-Length: 0\r\n\r\n".to_vec()
+                } else if heads.len() == 2 {
+                    // The response is compressed whatever the request was
+                    let body = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "result": { "text": "y".repeat(4000) } });
+                    let body = Encoding::Gzip
+                        .compress(body.to_string().as_bytes())
+                        .unwrap();
+                    let mut reply = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Encoding: gzip\r\nConnection: close\r\nContent-Length: {}\r\n\r\n",
+                        body.len()
+                    )
+                    .into_bytes();
+                    reply.extend_from_slice(&body);
+                    reply
+                } else {
+                    b"HTTP/1.1 202 Accepted\r\nConnection: close\r\nContent-Length: 0\r\n\r\n"
+                        .to_vec()
+                };
+                socket.write_all(&reply).await.unwrap();
+            }
+            heads
+        });
+
+        let mut transport = StreamableHttpTransport::new(&url)
+            .with_server_stream(false)
+            .with_compression(Compression::gzip());
+        transport.start().await?;
+        let large = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/call", "params": { "text": "x".repeat(4000) } });
+        transport.send(&large).await?;
+        let response: Value = transport.receive().await?;
+        assert_eq!(response["result"]["text"], "y".repeat(4000));
+        transport.send(&large).await?;
+
+        // Refused once, the body is sent again and from then on uncompressed
+        let heads = server.await.unwrap();
+        assert!(heads[0].contains("content-encoding: gzip"));
+        assert!(heads[1..]
+            .iter()
+            .all(|head| !head.contains("content-encoding")));
+        assert!(heads[1]
+            .lines()
+            .any(|line| line.starts_with("accept-encoding:") && line.contains("gzip")));
+        Ok(())
+    }
+}
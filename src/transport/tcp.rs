@@ -0,0 +1,309 @@
+//! Plain TCP transport, for networks where HTTP is more than is needed
+//!
+//! Messages are newline-delimited JSON by default, as over stdio, or
+//! prefixed with their length as 4 big-endian bytes, which needs no
+//! scanning for line ends. Both sides must use the same [`TcpFraming`].
+//!
+//! ```rust,no_run
+//! # use mcpr::{server::{Server, ServerConfig}, transport::tcp::{TcpFraming, TcpListener, TcpOptions, TcpTransport}};
+//! # use std::time::Duration;
+//! # async fn run() -> Result<(), mcpr::error::MCPError> {
+//! let options = TcpOptions::new()
+//!     .with_framing(TcpFraming::LengthPrefixed)
+//!     .with_keepalive(Duration::from_secs(30));
+//! let listener = TcpListener::bind("0.0.0.0:7070", options).await?;
+//! loop {
+//!     let transport = listener.accept().await?;
+//!     // Each connection gets its own server
+//!     let mut server: Server<TcpTransport> = Server::new(ServerConfig::new());
+//!     tokio::spawn(async move { server.serve(transport).await });
+//! }
+//! # }
+//! ```
+
+use crate::error::MCPError;
+use crate::transport::{
+    framed::{FramedTransport, Framer, LengthPrefixed, NewlineDelimited},
+    CloseCallback, ErrorCallback, Traffic, Transport,
+};
+use async_trait::async_trait;
+use bytes::BytesMut;
+use log::{debug, info};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{net::SocketAddr, time::Duration};
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+/// How messages are delimited on a TCP connection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TcpFraming {
+    /// One JSON message per line, see [`NewlineDelimited`]
+    #[default]
+    NewlineDelimited,
+    /// The length of each message before it, see [`LengthPrefixed`]
+    LengthPrefixed,
+}
+
+impl Framer for TcpFraming {
+    fn encode(&self, message: &str, out: &mut Vec<u8>) {
+        match self {
+            TcpFraming::NewlineDelimited => NewlineDelimited.encode(message, out),
+            TcpFraming::LengthPrefixed => LengthPrefixed.encode(message, out),
+        }
+    }
+
+    fn encode_json<T: Serialize + ?Sized>(
+        &self,
+        message: &T,
+        out: &mut BytesMut,
+    ) -> Result<usize, MCPError> {
+        match self {
+            TcpFraming::NewlineDelimited => NewlineDelimited.encode_json(message, out),
+            TcpFraming::LengthPrefixed => LengthPrefixed.encode_json(message, out),
+        }
+    }
+
+    fn decode(
+        &self,
+        buffer: &mut Vec<u8>,
+        scanned: usize,
+        max_size: usize,
+    ) -> Result<Option<String>, MCPError> {
+        match self {
+            TcpFraming::NewlineDelimited => NewlineDelimited.decode(buffer, scanned, max_size),
+            TcpFraming::LengthPrefixed => LengthPrefixed.decode(buffer, scanned, max_size),
+        }
+    }
+}
+
+/// Framing and socket options of TCP connections
+#[derive(Debug, Clone)]
+pub struct TcpOptions {
+    pub framing: TcpFraming,
+    /// Whether small messages are sent at once instead of being coalesced
+    pub nodelay: bool,
+    /// Idle time after which the OS probes whether the peer is still there
+    pub keepalive: Option<Duration>,
+}
+
+impl Default for TcpOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TcpOptions {
+    /// Newline-delimited messages, sent without delay, and no keepalive probes
+    pub fn new() -> Self {
+        Self {
+            framing: TcpFraming::default(),
+            nodelay: true,
+            keepalive: None,
+        }
+    }
+
+    /// Set how messages are delimited
+    pub fn with_framing(mut self, framing: TcpFraming) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Set whether small messages are sent at once (`TCP_NODELAY`)
+    pub fn with_nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Probe idle connections after `idle`, so a vanished peer is noticed
+    pub fn with_keepalive(mut self, idle: Duration) -> Self {
+        self.keepalive = Some(idle);
+        self
+    }
+
+    /// Apply the socket options to a connected stream
+    fn apply(&self, stream: &TcpStream) -> std::io::Result<()> {
+        stream.set_nodelay(self.nodelay)?;
+        if let Some(idle) = self.keepalive {
+            let keepalive = socket2::TcpKeepalive::new().with_time(idle);
+            socket2::SockRef::from(stream).set_tcp_keepalive(&keepalive)?;
+        }
+        Ok(())
+    }
+}
+
+/// Transport over a TCP connection
+///
+/// Clones share the connection, like clones of a [`FramedTransport`].
+#[derive(Clone)]
+pub struct TcpTransport {
+    peer: SocketAddr,
+    inner: FramedTransport<TcpFraming>,
+}
+
+impl TcpTransport {
+    /// Connect to `addr`, e.g. `"10.0.0.5:7070"`
+    pub async fn connect(addr: impl ToSocketAddrs, options: TcpOptions) -> Result<Self, MCPError> {
+        let stream = TcpStream::connect(addr)
+            .await
+            .map_err(|e| MCPError::Transport(format!("Failed to connect: {}", e)))?;
+        let transport = Self::from_stream(stream, &options)?;
+        info!("Connected to {} over TCP", transport.peer);
+        Ok(transport)
+    }
+
+    fn from_stream(stream: TcpStream, options: &TcpOptions) -> Result<Self, MCPError> {
+        let socket_error =
+            |e: std::io::Error| MCPError::Transport(format!("Failed to set up socket: {}", e));
+        options.apply(&stream).map_err(socket_error)?;
+        let peer = stream.peer_addr().map_err(socket_error)?;
+        let (reader, writer) = stream.into_split();
+        Ok(Self {
+            peer,
+            inner: FramedTransport::new(reader, writer, options.framing),
+        })
+    }
+
+    /// Refuse to read or write messages larger than `max_message_size` bytes,
+    /// see [`FramedTransport::with_max_message_size`]
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.inner = self.inner.with_max_message_size(max_message_size);
+        self
+    }
+
+    /// The address of the other end of the connection
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+}
+
+#[async_trait]
+impl Transport for TcpTransport {
+    async fn start(&mut self) -> Result<(), MCPError> {
+        self.inner.start().await
+    }
+
+    async fn send<T: Serialize + Send + Sync>(&mut self, message: &T) -> Result<(), MCPError> {
+        self.inner.send(message).await
+    }
+
+    async fn receive<T: DeserializeOwned + Send + Sync>(&mut self) -> Result<T, MCPError> {
+        self.inner.receive().await
+    }
+
+    async fn close(&mut self) -> Result<(), MCPError> {
+        self.inner.close().await
+    }
+
+    fn set_on_close(&mut self, callback: Option<CloseCallback>) {
+        self.inner.set_on_close(callback);
+    }
+
+    fn set_on_error(&mut self, callback: Option<ErrorCallback>) {
+        self.inner.set_on_error(callback);
+    }
+
+    fn set_on_message<F>(&mut self, callback: Option<F>)
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.inner.set_on_message(callback);
+    }
+
+    fn share_connection(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+
+    fn traffic(&self) -> Option<Traffic> {
+        self.inner.traffic()
+    }
+}
+
+/// Listener accepting [`TcpTransport`] connections
+///
+/// Any number of clients may be connected at once; each accepted transport
+/// is independent of the others and gets the listener's options.
+pub struct TcpListener {
+    listener: tokio::net::TcpListener,
+    options: TcpOptions,
+}
+
+impl TcpListener {
+    /// Listen on `addr`, e.g. `"0.0.0.0:7070"`, or port 0 for any free port
+    pub async fn bind(addr: impl ToSocketAddrs, options: TcpOptions) -> Result<Self, MCPError> {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(|e| MCPError::Transport(format!("Failed to bind: {}", e)))?;
+        if let Ok(addr) = listener.local_addr() {
+            info!("TCP server listening on {}", addr);
+        }
+        Ok(Self { listener, options })
+    }
+
+    /// Wait for the next client and return a transport connected to it
+    pub async fn accept(&self) -> Result<TcpTransport, MCPError> {
+        let (stream, peer) = self
+            .listener
+            .accept()
+            .await
+            .map_err(|e| MCPError::Transport(format!("Failed to accept connection: {}", e)))?;
+        debug!("TCP connection accepted from {}", peer);
+        TcpTransport::from_stream(stream, &self.options)
+    }
+
+    /// The address listened on, with the port chosen if bound to port 0
+    pub fn local_addr(&self) -> Result<SocketAddr, MCPError> {
+        self.listener
+            .local_addr()
+            .map_err(|e| MCPError::Transport(format!("Failed to get local address: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::schema::common::{Tool, ToolInputSchema};
+    use crate::server::{Server, ServerConfig};
+    use serde_json::{json, Value};
+
+    #[tokio::test]
+    async fn test_tcp_transport() -> Result<(), MCPError> {
+        for framing in [TcpFraming::NewlineDelimited, TcpFraming::LengthPrefixed] {
+            let options = TcpOptions::new()
+                .with_framing(framing)
+                .with_keepalive(Duration::from_secs(60));
+            let listener = TcpListener::bind("127.0.0.1:0", options.clone()).await?;
+            let addr = listener.local_addr()?;
+            tokio::spawn(async move {
+                let transport = listener.accept().await?;
+                let mut server = Server::new(ServerConfig::new().with_tool(Tool {
+                    name: "echo".to_string(),
+                    description: None,
+                    input_schema: ToolInputSchema {
+                        r#type: "object".to_string(),
+                        properties: None,
+                        required: None,
+                        keywords: serde_json::Map::new(),
+                    },
+                    output_schema: None,
+                    annotations: None,
+                }));
+                server.register_tool_handler("echo", |params: Value| async move { Ok(params) })?;
+                server.serve(transport).await
+            });
+
+            let transport = TcpTransport::connect(addr, options).await?;
+            assert_eq!(transport.peer_addr(), addr);
+            let mut client = Client::new(transport);
+            client.initialize().await?;
+            let result: Value = client
+                .call_tool("echo", &json!({ "text": "line\nbreak" }))
+                .await?;
+            let text = result["content"][0]["text"].as_str().unwrap_or_default();
+            assert_eq!(
+                serde_json::from_str::<Value>(text)?,
+                json!({ "text": "line\nbreak" })
+            );
+        }
+        Ok(())
+    }
+}
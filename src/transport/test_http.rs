@@ -0,0 +1,35 @@
+//! Helpers shared by the tests of the HTTP transports
+
+use tokio::{io::AsyncReadExt, net::TcpStream};
+
+/// Read one HTTP request from a socket, returning its head and body
+///
+/// Panics if the peer closes the socket before the whole request arrived.
+pub(crate) async fn read_request(socket: &mut TcpStream) -> (String, String) {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    loop {
+        let n = socket.read(&mut buf).await.unwrap();
+        assert!(
+            n > 0,
+            "Connection closed after {} bytes of an incomplete request",
+            request.len()
+        );
+        request.extend_from_slice(&buf[..n]);
+        let text = String::from_utf8_lossy(&request).to_string();
+        if let Some((head, body)) = text.split_once("\r\n\r\n") {
+            let length = head
+                .lines()
+                .find_map(|line| {
+                    line.to_lowercase()
+                        .strip_prefix("content-length: ")
+                        .map(str::to_string)
+                })
+                .and_then(|length| length.parse().ok())
+                .unwrap_or(0);
+            if body.len() >= length {
+                return (head.to_string(), body.to_string());
+            }
+        }
+    }
+}
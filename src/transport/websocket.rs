@@ -1,17 +1,46 @@
 use crate::error::MCPError;
-use crate::transport::{CloseCallback, ErrorCallback, MessageCallback, Transport};
+use crate::redact;
+use crate::trace;
+use crate::transport::proxy::{ProxyConfig, ProxySetting};
+use crate::transport::{
+    codec::Codec, CloseCallback, ErrorCallback, MessageCallback, Traffic, TrafficCounter,
+    Transport, DEFAULT_MAX_MESSAGE_SIZE, DEFAULT_QUEUE_CAPACITY,
+};
 use async_trait::async_trait;
-use futures::{SinkExt, StreamExt};
+use futures::{Sink, SinkExt, Stream, StreamExt};
 use log::{debug, error, info, warn};
 use serde::{de::DeserializeOwned, Serialize};
-use std::{collections::VecDeque, sync::Arc, time::Duration};
-use tokio::{
-    sync::{Mutex as TokioMutex, Notify},
-    time::sleep,
+use std::{borrow::Cow, time::Duration};
+use tokio::sync::mpsc;
+use tokio_tungstenite::{
+    client_async_with_config,
+    tungstenite::{
+        client::IntoClientRequest, error::UrlError, handshake::client::Response,
+        protocol::WebSocketConfig, Error as WsError, Message,
+    },
 };
-use tokio_tungstenite::{connect_async, tungstenite::Message};
 use url::Url;
 
+#[cfg(feature = "compression")]
+use crate::transport::compression::{ACCEPTED_ENCODINGS, ACCEPT_ENCODING_HEADER};
+#[cfg(feature = "compression")]
+use tokio_tungstenite::tungstenite::{
+    handshake::client::Request,
+    handshake::server::{Callback, ErrorResponse, Response as ServerResponse},
+    http::HeaderValue,
+};
+
+/// A client connection, over TLS or not
+trait Connection:
+    Stream<Item = Result<Message, WsError>> + Sink<Message, Error = WsError> + Send + Unpin
+{
+}
+
+impl<T> Connection for T where
+    T: Stream<Item = Result<Message, WsError>> + Sink<Message, Error = WsError> + Send + Unpin
+{
+}
+
 /// WebSocket transport implementation for MCP
 pub struct WebSocketTransport {
     uri: String,
@@ -20,12 +49,17 @@ pub struct WebSocketTransport {
     on_close: Option<CloseCallback>,
     on_error: Option<ErrorCallback>,
     on_message: Option<MessageCallback>,
-
-    // Queue for incoming messages
-    message_queue: Arc<TokioMutex<VecDeque<String>>>,
-
-    // Signal to stop background tasks
-    stop_signal: Arc<Notify>,
+    codec: Codec,
+    max_message_size: usize,
+    traffic: TrafficCounter,
+    proxy: ProxySetting,
+    #[cfg(feature = "rustls")]
+    tls: Option<crate::transport::tls::TlsConfig>,
+    #[cfg(feature = "compression")]
+    compression: Option<crate::transport::compression::Compression>,
+
+    // Queue for incoming text and binary messages
+    incoming: Option<mpsc::Receiver<Message>>,
 
     // Background task handle
     message_task: Option<tokio::task::JoinHandle<()>>,
@@ -41,8 +75,15 @@ impl Clone for WebSocketTransport {
             on_close: None, // Callbacks cannot be cloned
             on_error: None,
             on_message: None,
-            message_queue: Arc::new(TokioMutex::new(VecDeque::new())),
-            stop_signal: Arc::new(Notify::new()),
+            codec: self.codec,
+            max_message_size: self.max_message_size,
+            traffic: self.traffic.clone(),
+            proxy: self.proxy.clone(),
+            #[cfg(feature = "rustls")]
+            tls: self.tls.clone(),
+            #[cfg(feature = "compression")]
+            compression: self.compression,
+            incoming: None,
             message_task: None, // Each clone should create its own task
         }
     }
@@ -59,8 +100,15 @@ impl WebSocketTransport {
             on_close: None,
             on_error: None,
             on_message: None,
-            message_queue: Arc::new(TokioMutex::new(VecDeque::new())),
-            stop_signal: Arc::new(Notify::new()),
+            codec: Codec::default(),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            traffic: TrafficCounter::default(),
+            proxy: ProxySetting::default(),
+            #[cfg(feature = "rustls")]
+            tls: None,
+            #[cfg(feature = "compression")]
+            compression: None,
+            incoming: None,
             message_task: None,
         }
     }
@@ -73,18 +121,119 @@ impl WebSocketTransport {
         transport
     }
 
+    /// Set the encoding of outgoing messages
+    ///
+    /// JSON is sent as text frames and other codecs as binary frames. Binary
+    /// frames received are decoded with this codec; text frames are always
+    /// read as JSON. Both ends of the connection must use the same codec.
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Refuse to read or write messages larger than `max_message_size` bytes
+    ///
+    /// The connection is closed when the other side sends a larger message.
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// Compress encoded messages as large as the threshold of `compression`
+    ///
+    /// They are sent as binary frames, and only to a server that listed the
+    /// encoding in its handshake, as servers built with the `compression`
+    /// feature do; others get the messages uncompressed. Compressed frames
+    /// received are decompressed whether this is set or not.
+    #[cfg(feature = "compression")]
+    pub fn with_compression(
+        mut self,
+        compression: crate::transport::compression::Compression,
+    ) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// `message` compressed, if it should be and the server accepts the encoding
+    #[cfg(feature = "compression")]
+    fn compress_for(
+        &self,
+        response: &Response,
+        message: &[u8],
+    ) -> Result<Option<Vec<u8>>, MCPError> {
+        let Some(compression) = &self.compression else {
+            return Ok(None);
+        };
+        let accepted = response
+            .headers()
+            .get(ACCEPT_ENCODING_HEADER)
+            .and_then(|names| names.to_str().ok())
+            .unwrap_or_default();
+        if !compression.encoding().listed_in(accepted) {
+            debug!(
+                "Not compressing, the server does not accept {}",
+                compression.encoding().name()
+            );
+            return Ok(None);
+        }
+        compression.apply(message)
+    }
+
+    #[cfg(not(feature = "compression"))]
+    fn compress_for(
+        &self,
+        _response: &Response,
+        _message: &[u8],
+    ) -> Result<Option<Vec<u8>>, MCPError> {
+        Ok(None)
+    }
+
+    /// The encoded message a binary frame carries, decompressed if it was compressed
+    fn payload<'a>(&self, data: &'a [u8]) -> Result<Cow<'a, [u8]>, MCPError> {
+        #[cfg(feature = "compression")]
+        if let Some(encoding) = crate::transport::compression::Encoding::detect(data) {
+            return encoding
+                .decompress(data, self.max_message_size)
+                .map(Cow::Owned);
+        }
+        Ok(Cow::Borrowed(data))
+    }
+
+    /// Connect through this proxy, HTTP or SOCKS5, instead of the one the environment names
+    pub fn with_proxy(mut self, proxy: &ProxyConfig) -> Self {
+        self.proxy = ProxySetting::Explicit(proxy.clone());
+        self
+    }
+
+    /// Connect directly, ignoring the proxy environment variables
+    pub fn without_proxy(mut self) -> Self {
+        self.proxy = ProxySetting::Direct;
+        self
+    }
+
+    /// Connect to `wss` URLs with these TLS settings instead of the system's trust store
+    #[cfg(feature = "rustls")]
+    pub fn with_tls(mut self, tls: &crate::transport::tls::TlsConfig) -> Result<Self, MCPError> {
+        tls.client_config()?;
+        self.tls = Some(tls.clone());
+        Ok(self)
+    }
+
+    /// WebSocket settings enforcing the message size limit
+    fn config(&self) -> WebSocketConfig {
+        WebSocketConfig {
+            max_message_size: Some(self.max_message_size),
+            max_frame_size: Some(self.max_message_size),
+            ..WebSocketConfig::default()
+        }
+    }
+
     /// Start client connection to a WebSocket server
     async fn connect_as_client(&mut self) -> Result<(), MCPError> {
         debug!("Connecting to WebSocket server: {}", self.uri);
 
-        // Parse URL
-        let url = Url::parse(&self.uri)
-            .map_err(|e| MCPError::Transport(format!("Invalid WebSocket URL: {}", e)))?;
-
         // Connect to server
-        let (ws_stream, _) = connect_async(url).await.map_err(|e| {
-            MCPError::Transport(format!("Failed to connect to WebSocket server: {}", e))
-        })?;
+        let (ws_stream, _) = self.open("connect to WebSocket server").await?;
 
         info!("Connected to WebSocket server: {}", self.uri);
 
@@ -113,9 +262,18 @@ impl WebSocketTransport {
 
         info!("WebSocket connection accepted from {}", addr);
 
-        // Upgrade to WebSocket
-        let ws_stream = tokio_tungstenite::accept_async(socket)
-            .await
+        // Upgrade to WebSocket, telling the client which encodings it may compress with
+        #[cfg(feature = "compression")]
+        let accepted = tokio_tungstenite::accept_hdr_async_with_config(
+            socket,
+            ListAcceptedEncodings,
+            Some(self.config()),
+        )
+        .await;
+        #[cfg(not(feature = "compression"))]
+        let accepted =
+            tokio_tungstenite::accept_async_with_config(socket, Some(self.config())).await;
+        let ws_stream = accepted
             .map_err(|e| MCPError::Transport(format!("Error during WebSocket handshake: {}", e)))?;
 
         // Start message processing
@@ -131,9 +289,10 @@ impl WebSocketTransport {
             + Send
             + 'static,
     {
-        // Clone resources for the task
-        let message_queue = Arc::clone(&self.message_queue);
-        let stop_signal = Arc::clone(&self.stop_signal);
+        // Stop reading while the queue is full, so the other side waits
+        // instead of filling memory
+        let (incoming_tx, incoming_rx) = mpsc::channel(DEFAULT_QUEUE_CAPACITY);
+        self.incoming = Some(incoming_rx);
 
         // Start a task to process incoming messages
         self.message_task = Some(tokio::spawn(async move {
@@ -141,39 +300,32 @@ impl WebSocketTransport {
 
             tokio::pin!(ws_stream);
 
-            // Process messages until the stream ends or we're signaled to stop
+            // Process messages until the stream ends or the transport is closed
             loop {
-                tokio::select! {
-                    // Process incoming message
-                    msg = ws_stream.next() => match msg {
-                        Some(Ok(msg)) => {
-                            if let Message::Text(text) = msg {
-                                debug!("Received WebSocket text message: {}", text);
-
-                                // Add to message queue
-                                let mut queue = message_queue.lock().await;
-                                queue.push_back(text);
-                            } else if let Message::Binary(data) = msg {
-                                debug!("Received WebSocket binary message of {} bytes", data.len());
-                                // We don't handle binary messages currently
-                            } else if let Message::Close(_) = msg {
-                                debug!("Received WebSocket close message");
-                                break;
-                            }
-                        },
-                        Some(Err(e)) => {
-                            error!("WebSocket error: {}", e);
-                            break;
-                        },
-                        None => {
-                            debug!("WebSocket stream ended");
+                match ws_stream.next().await {
+                    Some(Ok(msg)) => {
+                        if let Message::Text(text) = &msg {
+                            debug!("Received WebSocket text message: {}", redact::for_log(text));
+                        } else if let Message::Binary(data) = &msg {
+                            debug!("Received WebSocket binary message of {} bytes", data.len());
+                        } else if let Message::Close(_) = msg {
+                            debug!("Received WebSocket close message");
                             break;
+                        } else {
+                            continue;
                         }
-                    },
 
-                    // Check for stop signal
-                    _ = stop_signal.notified() => {
-                        debug!("Received stop signal, exiting message processing task");
+                        // Add to message queue
+                        if incoming_tx.send(msg).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Err(e)) => {
+                        error!("WebSocket error: {}", e);
+                        break;
+                    }
+                    None => {
+                        debug!("WebSocket stream ended");
                         break;
                     }
                 }
@@ -185,16 +337,54 @@ impl WebSocketTransport {
         Ok(())
     }
 
-    /// Create a new connection for sending messages
-    async fn create_send_connection(&self) -> Result<impl SinkExt<Message>, MCPError> {
+    /// Create a new connection for sending messages, with the server's handshake response
+    async fn create_send_connection(&self) -> Result<(impl SinkExt<Message>, Response), MCPError> {
+        self.open("create send connection").await
+    }
+
+    /// Open a client connection, through the proxy and with the TLS settings
+    /// for `wss` URLs if there are any
+    async fn open(&self, action: &str) -> Result<(Box<dyn Connection>, Response), MCPError> {
         let url = Url::parse(&self.uri)
             .map_err(|e| MCPError::Transport(format!("Invalid WebSocket URL: {}", e)))?;
+        let failed =
+            |e: &dyn std::fmt::Display| MCPError::Transport(format!("Failed to {}: {}", action, e));
+        let request = url.as_str().into_client_request().map_err(|e| failed(&e))?;
+        #[cfg(feature = "compression")]
+        let request = offer_encodings(request);
+
+        let host = url
+            .host_str()
+            .ok_or_else(|| MCPError::Transport("WebSocket URL has no host".to_string()))?
+            .to_string();
+        let port = url.port_or_known_default().unwrap_or(80);
+        let tcp = match self.proxy.for_url(&url) {
+            Some(proxy) => proxy.connect(&host, port).await?,
+            None => tokio::net::TcpStream::connect((host.trim_matches(['[', ']']), port))
+                .await
+                .map_err(|e| failed(&e))?,
+        };
 
-        let (stream, _) = connect_async(url)
-            .await
-            .map_err(|e| MCPError::Transport(format!("Failed to create send connection: {}", e)))?;
+        #[cfg(feature = "rustls")]
+        if let (Some(tls), "wss") = (&self.tls, url.scheme()) {
+            let stream = tokio_rustls::TlsConnector::from(tls.client_config()?)
+                .connect(tls.server_name_for(&host)?, tcp)
+                .await
+                .map_err(|e| failed(&e))?;
+            let (ws_stream, response) =
+                client_async_with_config(request, stream, Some(self.config()))
+                    .await
+                    .map_err(|e| failed(&e))?;
+            return Ok((Box::new(ws_stream), response));
+        }
 
-        Ok(stream)
+        if url.scheme() == "wss" {
+            return Err(failed(&WsError::Url(UrlError::TlsFeatureNotEnabled)));
+        }
+        let (ws_stream, response) = client_async_with_config(request, tcp, Some(self.config()))
+            .await
+            .map_err(|e| failed(&e))?;
+        Ok((Box::new(ws_stream), response))
     }
 }
 
@@ -216,6 +406,7 @@ impl Transport for WebSocketTransport {
         }
 
         self.is_connected = true;
+        trace::connected("websocket");
         info!("WebSocket transport started successfully");
         Ok(())
     }
@@ -228,22 +419,47 @@ impl Transport for WebSocketTransport {
         }
 
         // Serialize the message
-        let serialized_message = serde_json::to_string(message).map_err(|e| {
-            let msg = format!("Failed to serialize message: {}", e);
-            error!("{}", msg);
-            MCPError::Serialization(e)
+        let serialized_message = self.codec.encode(message).map_err(|e| {
+            error!("Failed to serialize message: {}", e);
+            e
         })?;
+        if serialized_message.len() > self.max_message_size {
+            return Err(MCPError::MessageTooLarge {
+                limit: self.max_message_size,
+            });
+        }
 
-        debug!("Sending WebSocket message: {}", serialized_message);
+        // Create a new connection for sending, which tells whether to compress
+        let (mut send_stream, response) = self.create_send_connection().await?;
 
-        // Create a new connection for sending if none exists
-        let mut send_stream = self.create_send_connection().await?;
+        let serialized_message =
+            if let Some(compressed) = self.compress_for(&response, &serialized_message)? {
+                debug!(
+                    "Sending WebSocket message of {} bytes compressed to {}",
+                    serialized_message.len(),
+                    compressed.len()
+                );
+                Message::Binary(compressed)
+            } else if self.codec.is_text() {
+                let text = String::from_utf8(serialized_message)
+                    .map_err(|e| MCPError::Protocol(format!("Invalid UTF-8 in message: {}", e)))?;
+                debug!("Sending WebSocket message: {}", redact::for_log(&text));
+                Message::Text(text)
+            } else {
+                debug!(
+                    "Sending WebSocket binary message of {} bytes",
+                    serialized_message.len()
+                );
+                Message::Binary(serialized_message)
+            };
 
         // Send the message
+        let length = serialized_message.len();
         send_stream
-            .send(Message::Text(serialized_message))
+            .send(serialized_message)
             .await
             .map_err(|_| MCPError::Transport("Error sending WebSocket message".to_string()))?;
+        self.traffic.sent(length);
 
         debug!("WebSocket message sent successfully");
         Ok(())
@@ -256,53 +472,52 @@ impl Transport for WebSocketTransport {
             ));
         }
 
-        // Use a timeout of 30 seconds
-        let timeout_duration = Duration::from_secs(30);
-        let start = std::time::Instant::now();
-
-        // Wait for a message
-        let message = loop {
-            // Check for timeout
-            if start.elapsed() >= timeout_duration {
-                return Err(MCPError::Transport(
-                    "Timeout waiting for message".to_string(),
-                ));
-            }
-
-            // Try to get a message from the queue
-            let queue_msg = {
-                let mut queue = self.message_queue.lock().await;
-                queue.pop_front()
-            };
-
-            if let Some(message) = queue_msg {
-                debug!("Received message from queue: {}", message);
+        let incoming = self
+            .incoming
+            .as_mut()
+            .ok_or_else(|| MCPError::Transport("WebSocket transport not connected".to_string()))?;
 
-                // Execute callback if set
-                if let Some(callback) = &self.on_message {
-                    callback(&message);
+        // Wait for a message, with a timeout of 30 seconds
+        let message = tokio::time::timeout(Duration::from_secs(30), incoming.recv())
+            .await
+            .map_err(|_| MCPError::Transport("Timeout waiting for message".to_string()))?
+            .ok_or(MCPError::ConnectionClosed)?;
+        debug!("Received message from queue: {}", message);
+        self.traffic.received(message.len());
+
+        // Execute callback if set, with binary messages shown as JSON
+        if let Some(callback) = &self.on_message {
+            match &message {
+                Message::Binary(data) => {
+                    let value = self
+                        .payload(data)
+                        .and_then(|data| self.codec.decode::<serde_json::Value>(&data));
+                    if let Ok(value) = value {
+                        callback(&value.to_string());
+                    }
                 }
-
-                break message;
+                message => callback(message.to_text().unwrap_or_default()),
             }
-
-            // Wait before checking again
-            sleep(Duration::from_millis(100)).await;
-        };
+        }
 
         // Parse the message
-        match serde_json::from_str::<T>(&message) {
+        let parsed = match &message {
+            Message::Binary(data) => self
+                .payload(data)
+                .and_then(|data| self.codec.decode::<T>(&data)),
+            message => Codec::Json.decode::<T>(message.to_text().unwrap_or_default().as_bytes()),
+        };
+        match parsed {
             Ok(parsed) => {
                 debug!("Successfully parsed WebSocket message");
                 Ok(parsed)
             }
             Err(e) => {
-                let error_msg = format!(
+                error!(
                     "Failed to deserialize WebSocket message: {} - Content: {}",
                     e, message
                 );
-                error!("{}", error_msg);
-                Err(MCPError::Serialization(e))
+                Err(e)
             }
         }
     }
@@ -316,25 +531,23 @@ impl Transport for WebSocketTransport {
         info!("Closing WebSocket transport: {}", self.uri);
 
         // Create a send connection to send the close frame
-        let mut send_stream = self.create_send_connection().await?;
+        let (mut send_stream, _) = self.create_send_connection().await?;
 
         // Send close frame
         debug!("Sending WebSocket close frame");
-        if let Err(_) = send_stream.send(Message::Close(None)).await {
+        if send_stream.send(Message::Close(None)).await.is_err() {
             warn!("Error sending WebSocket close frame");
         }
 
-        // Signal tasks to stop
-        self.stop_signal.notify_waiters();
-
-        // Wait for tasks to finish
+        // Stop reading
         if let Some(task) = self.message_task.take() {
-            debug!("Waiting for WebSocket message task to finish");
-            let _ = tokio::time::timeout(Duration::from_secs(5), task).await;
+            task.abort();
         }
+        self.incoming = None;
 
         // Update state
         self.is_connected = false;
+        trace::disconnected("websocket");
 
         // Call close callback
         if let Some(callback) = &self.on_close {
@@ -362,14 +575,129 @@ impl Transport for WebSocketTransport {
         debug!("Setting on_message callback for WebSocket transport");
         self.on_message = callback.map(|f| Box::new(f) as MessageCallback);
     }
+
+    fn traffic(&self) -> Option<Traffic> {
+        Some(self.traffic.traffic())
+    }
+}
+
+/// Tell the server which encodings compressed messages to this client may use
+#[cfg(feature = "compression")]
+fn offer_encodings(mut request: Request) -> Request {
+    request.headers_mut().insert(
+        ACCEPT_ENCODING_HEADER,
+        HeaderValue::from_static(ACCEPTED_ENCODINGS),
+    );
+    request
+}
+
+/// Tells the client which encodings compressed messages to this server may use
+#[cfg(feature = "compression")]
+struct ListAcceptedEncodings;
+
+#[cfg(feature = "compression")]
+impl Callback for ListAcceptedEncodings {
+    fn on_request(
+        self,
+        _request: &Request,
+        mut response: ServerResponse,
+    ) -> Result<ServerResponse, ErrorResponse> {
+        response.headers_mut().insert(
+            ACCEPT_ENCODING_HEADER,
+            HeaderValue::from_static(ACCEPTED_ENCODINGS),
+        );
+        Ok(response)
+    }
 }
 
 impl Drop for WebSocketTransport {
     fn drop(&mut self) {
-        if self.is_connected {
-            debug!("WebSocketTransport dropped while still connected, attempting to close");
-            self.stop_signal.notify_waiters();
+        if let Some(task) = self.message_task.take() {
+            debug!("WebSocketTransport dropped while still connected, stopping the reader");
+            task.abort();
         }
         debug!("WebSocketTransport dropped");
     }
 }
+
+#[cfg(all(test, feature = "compression"))]
+mod tests {
+    use super::*;
+    use crate::transport::compression::{Compression, Encoding};
+
+    /// Records the encodings a client offers, and lists `advertised` back if any
+    struct Advertise {
+        offers: mpsc::UnboundedSender<Option<HeaderValue>>,
+        advertised: Option<&'static str>,
+    }
+
+    impl Callback for Advertise {
+        fn on_request(
+            self,
+            request: &Request,
+            mut response: ServerResponse,
+        ) -> Result<ServerResponse, ErrorResponse> {
+            let offer = request.headers().get(ACCEPT_ENCODING_HEADER);
+            let _ = self.offers.send(offer.cloned());
+            if let Some(advertised) = self.advertised {
+                response
+                    .headers_mut()
+                    .insert(ACCEPT_ENCODING_HEADER, HeaderValue::from_static(advertised));
+            }
+            Ok(response)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compression_negotiation() -> Result<(), MCPError> {
+        let message = serde_json::json!({ "text": "line\n".repeat(2000) });
+        for advertised in [Some("gzip"), Some("zstd"), None] {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let uri = format!("ws://{}", listener.local_addr().unwrap());
+            let (offers_tx, mut offers) = mpsc::unbounded_channel();
+            let (frames_tx, mut frames) = mpsc::unbounded_channel();
+            tokio::spawn(async move {
+                while let Ok((socket, _)) = listener.accept().await {
+                    let callback = Advertise {
+                        offers: offers_tx.clone(),
+                        advertised,
+                    };
+                    let mut ws = tokio_tungstenite::accept_hdr_async(socket, callback)
+                        .await
+                        .unwrap();
+                    let frames_tx = frames_tx.clone();
+                    tokio::spawn(async move {
+                        while let Some(Ok(frame)) = ws.next().await {
+                            let _ = frames_tx.send(frame);
+                        }
+                    });
+                }
+            });
+
+            let mut transport = WebSocketTransport::new(&uri)
+                .without_proxy()
+                .with_compression(Compression::gzip());
+            transport.start().await?;
+            transport.send(&message).await?;
+
+            // Every connection offers the encodings decompressed here
+            let offer = offers.recv().await.unwrap().unwrap();
+            assert_eq!(offer.to_str().unwrap(), ACCEPTED_ENCODINGS);
+
+            let frame = frames.recv().await.unwrap();
+            match (advertised, frame) {
+                (Some("gzip"), Message::Binary(data)) => {
+                    assert_eq!(Encoding::detect(&data), Some(Encoding::Gzip));
+                }
+                (_, Message::Text(text)) if advertised != Some("gzip") => {
+                    assert_eq!(serde_json::from_str::<serde_json::Value>(&text)?, message);
+                }
+                (advertised, frame) => {
+                    panic!("{:?} sent to a server accepting {:?}", frame, advertised)
+                }
+            }
+            transport.close().await?;
+        }
+        Ok(())
+    }
+}